@@ -0,0 +1,54 @@
+pragma solidity >=0.4.21 <0.7.0;
+
+// A minimal ERC-20, minting its whole supply to a holder chosen at deployment.
+contract ExampleToken {
+	event Transfer(address indexed from, address indexed to, uint256 value);
+	event Approval(address indexed owner, address indexed spender, uint256 value);
+
+	string public constant name = "Example";
+	string public constant symbol = "EXT";
+	uint8 public constant decimals = 18;
+
+	uint256 public totalSupply;
+	mapping (address => uint256) balances;
+	mapping (address => mapping (address => uint256)) allowances;
+
+	constructor(address holder, uint256 supply) public {
+		totalSupply = supply;
+		balances[holder] = supply;
+		emit Transfer(address(0), holder, supply);
+	}
+
+	function balanceOf(address owner) public view returns (uint256) {
+		return balances[owner];
+	}
+
+	function allowance(address owner, address spender) public view returns (uint256) {
+		return allowances[owner][spender];
+	}
+
+	function transfer(address to, uint256 value) public returns (bool) {
+		move(msg.sender, to, value);
+		return true;
+	}
+
+	function approve(address spender, uint256 value) public returns (bool) {
+		allowances[msg.sender][spender] = value;
+		emit Approval(msg.sender, spender, value);
+		return true;
+	}
+
+	function transferFrom(address from, address to, uint256 value) public returns (bool) {
+		require(value <= allowances[from][msg.sender], "allowance exceeded");
+		allowances[from][msg.sender] -= value;
+		move(from, to, value);
+		return true;
+	}
+
+	function move(address from, address to, uint256 value) internal {
+		require(value <= balances[from], "balance exceeded");
+		balances[from] -= value;
+		balances[to] += value;
+		emit Transfer(from, to, value);
+	}
+}
@@ -35,6 +35,50 @@ pub struct Attributes {
     #[serde(skip)]
     /// Whether generated instructions should be printed to the console.
     pub codegen_print: bool,
+    #[serde(default)]
+    /// Whether the node is a coverage probe, which marks that its line was reached.
+    pub probe: bool,
+    #[serde(skip)]
+    /// Whether arithmetic may wrap around even when compiling with overflow checks.
+    pub wrapping: bool,
+    #[serde(skip)]
+    /// Whether the func is inlined into its callers no matter its size.
+    pub inline: bool,
+    #[serde(skip)]
+    /// Whether the func is a unit test, which `mini test` runs as a program of its own.
+    pub test: bool,
+    #[serde(skip)]
+    /// Whether the func takes the place of the func of its name in another module when linked.
+    pub overrides: bool,
+    #[serde(skip)]
+    /// Whether the func initializes globals before the rest of the program runs.
+    pub init: bool,
+}
+
+impl Attributes {
+    /// Reads the attributes written on a statement or global, reporting those it doesn't know.
+    pub fn from_names(names: Vec<NamedAttribute>, errors: &mut Vec<CompileError>) -> Self {
+        let mut attribs = Attributes::default();
+        for attribute in names {
+            match attribute.name.as_ref() {
+                "breakpoint" => attribs.breakpoint = true,
+                "print" => attribs.codegen_print = true,
+                unrecognized => errors.push(CompileError::new(
+                    String::from("Lexer error"),
+                    format!("Unrecognized attribute {}", Color::red(unrecognized)),
+                    attribute.location.into_iter().collect(),
+                )),
+            }
+        }
+        attribs
+    }
+}
+
+/// An attribute as written between `#[` and `]`, before it's known what it means.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamedAttribute {
+    pub name: String,
+    pub location: Option<Location>,
 }
 
 impl DebugInfo {
@@ -75,18 +119,147 @@ pub enum TopLevelDecl {
     FuncDecl(Func),
     VarDecl(GlobalVar),
     UseDecl(Import),
-    ConstDecl,
+    ConstDecl(ConstDecl),
+    InterfaceDecl(InterfaceDecl),
+    ImplDecl(ImplDecl),
+    StaticAssert(StaticAssert),
 }
 
 /// Type Declaration, contains the StringId corresponding to the type name, and the underlying Type.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TypeDecl {
     pub name: StringId,
     pub tipe: Type,
+    /// The names of this type's generic parameters, in order.
+    pub generics: Vec<StringId>,
+    /// The text of the doc comment preceding this declaration, if any.
+    pub docs: Option<String>,
 }
 
-pub fn new_type_decl(name: StringId, tipe: Type) -> TypeDecl {
-    TypeDecl { name, tipe }
+pub fn new_type_decl(name: StringId, tipe: Type, generics: Vec<StringId>) -> TypeDecl {
+    TypeDecl {
+        name,
+        tipe,
+        generics,
+        docs: None,
+    }
+}
+
+/// Constant Declaration, whose value is substituted wherever the name appears while parsing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstDecl {
+    pub name: String,
+    pub value: Uint256,
+    /// The text of the doc comment preceding this declaration, if any.
+    pub docs: Option<String>,
+    pub debug_info: DebugInfo,
+}
+
+/// Static Assert, a condition that must evaluate to true at compile time for the program to
+/// compile, along with the message to fail with when it doesn't.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticAssert {
+    pub condition: Expr,
+    pub message: String,
+    pub debug_info: DebugInfo,
+}
+
+/// Gets the text of a doc comment, stripping each line's `///` marker along with the space after it.
+pub fn doc_comment_text(comment: &str) -> String {
+    comment
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let text = line.trim_start_matches("///");
+            text.strip_prefix(' ').unwrap_or(text)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end()
+        .to_string()
+}
+
+/// Interface Declaration, a named set of func signatures that types can implement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceDecl {
+    pub name: StringId,
+    pub methods: Vec<InterfaceMethod>,
+    pub debug_info: DebugInfo,
+}
+
+/// A func signature required by an interface. The receiver is left out of the signature's type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceMethod {
+    pub name: String,
+    pub tipe: Type,
+    pub debug_info: DebugInfo,
+}
+
+/// Impl Declaration, which implements methods for a named type, either those of an interface or
+/// any the type needs. Each method is an ordinary func named `Type::method` whose first arg is the
+/// receiver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImplDecl {
+    pub interface: Option<StringId>,
+    pub tipe: StringId,
+    pub funcs: Vec<Func>,
+    pub debug_info: DebugInfo,
+}
+
+impl ImplDecl {
+    /// Creates an impl of interface's methods for tipe, or of tipe's own methods if there's no
+    /// interface, naming each func after the method it implements.
+    pub fn new(
+        interface: Option<StringId>,
+        tipe: StringId,
+        funcs: Vec<Func>,
+        string_table: &mut StringTable,
+        debug_info: DebugInfo,
+    ) -> Self {
+        let type_name = string_table.name_from_id(tipe).clone();
+        let funcs = funcs
+            .into_iter()
+            .map(|mut func| {
+                func.name = format!("{}::{}", type_name, func.name);
+                func.id = string_table.get(func.name.clone());
+                func.interface = interface;
+                func.receiver = Some(tipe);
+                func
+            })
+            .collect();
+        ImplDecl {
+            interface,
+            tipe,
+            funcs,
+            debug_info,
+        }
+    }
+}
+
+/// A method an impl provides for a type, once the impl has been checked against its interface.
+#[derive(Debug, Clone)]
+pub struct ImplMethod {
+    pub receiver: Type,
+    pub name: String,
+    /// The func implementing the method
+    pub func: Import,
+    pub tipe: Type,
+    /// Whether modules besides the one implementing the method can call it, which they can if
+    /// it's marked `public` or implements an interface
+    pub public: bool,
+}
+
+/// The name under which the method implemented by func `name` in the module at `path` is entered
+/// into the func table of every module.
+pub fn impl_method_key(path: &[String], name: &str) -> String {
+    format!("{}::{}", path.join("::"), name)
+}
+
+/// The name under which an interface's method signature is entered into the func table of every
+/// module that can see the interface.
+pub fn interface_method_key(interface: &str, method: &str) -> String {
+    format!("{}.{}", interface, method)
 }
 
 /// A type in the mini language.
@@ -99,10 +272,15 @@ pub enum Type {
     Bytes32,
     EthAddress,
     Buffer,
+    /// A string of bytes, represented as a tuple of its length and a buffer holding its bytes.
+    /// Bytes of the buffer past the length are zero.
+    String,
     Tuple(Vec<Type>),
     Array(Box<Type>),
     FixedArray(Box<Type>, usize),
     Struct(Vec<StructField>),
+    /// A tagged union, represented as a tuple of the variant's index and a tuple of its fields.
+    Enum(Vec<EnumVariant>),
     Func(FuncProperties, Vec<Type>, Box<Type>),
     Map(Box<Type>, Box<Type>),
     Any,
@@ -124,6 +302,7 @@ impl AbstractSyntaxTree for Type {
             | Type::Bytes32
             | Type::EthAddress
             | Type::Buffer
+            | Type::String
             | Type::Any
             | Type::Every
             | Type::GenericSlot(..)
@@ -139,6 +318,11 @@ impl AbstractSyntaxTree for Type {
                 .iter_mut()
                 .map(|field| TypeCheckedNode::Type(&mut field.tipe))
                 .collect(),
+            Type::Enum(variants) => variants
+                .iter_mut()
+                .flat_map(|variant| variant.fields.iter_mut())
+                .map(TypeCheckedNode::Type)
+                .collect(),
             Type::Func(_, args, ret) => {
                 let mut nodes = vec![TypeCheckedNode::Type(ret)];
                 nodes.extend(args.iter_mut().map(|t| TypeCheckedNode::Type(t)));
@@ -210,6 +394,15 @@ impl Type {
                 }
                 tipes
             }
+            Type::Enum(variants) => {
+                let mut tipes = vec![];
+                for variant in variants {
+                    for field in &variant.fields {
+                        tipes.extend(field.find_nominals());
+                    }
+                }
+                tipes
+            }
 
             Type::Map(domain_tipe, codomain_tipe) => {
                 let mut tipes = domain_tipe.find_nominals();
@@ -250,6 +443,11 @@ impl Type {
             Self::Struct(fields) => {
                 fields.iter().for_each(|field| find!(field.tipe));
             }
+            Self::Enum(variants) => {
+                variants
+                    .iter()
+                    .for_each(|variant| variant.fields.iter().for_each(|val| find!(val)));
+            }
             _ => {}
         }
         if take(self) {
@@ -284,6 +482,11 @@ impl Type {
             Self::Struct(ref mut fields) => {
                 fields.iter_mut().for_each(|field| field.tipe.replace(via));
             }
+            Self::Enum(ref mut variants) => {
+                variants
+                    .iter_mut()
+                    .for_each(|variant| variant.fields.iter_mut().for_each(|val| val.replace(via)));
+            }
             _ => {}
         }
         via(self);
@@ -350,6 +553,40 @@ impl Type {
         tipe
     }
 
+    /// Replaces nominals naming a func's generic parameters with the parameters themselves, so that
+    /// the type can later be specialized without the func's type tree.
+    pub fn resolve_generics(&self, type_tree: &TypeTree) -> Self {
+        let mut tipe = self.clone();
+        tipe.replace(&mut |tipe| {
+            if let Type::Nominal(path, id, _) = tipe {
+                if let Some((generic @ Type::Generic(_), _)) = type_tree.get(&(path.clone(), *id)) {
+                    *tipe = generic.clone();
+                }
+            }
+        });
+        tipe
+    }
+
+    /// Replaces committed generics with the types in the specialization.
+    pub fn specialize_generics(&self, specialization: &[Type]) -> Self {
+        let mut tipe = self.clone();
+        tipe.replace(&mut |tipe| {
+            if let Type::Generic(slot) = tipe {
+                if let Some(specific) = specialization.get(*slot) {
+                    *tipe = specific.clone();
+                }
+            }
+        });
+        tipe
+    }
+
+    /// Whether this type contains generics that have yet to be specialized.
+    pub fn is_generic(&self) -> bool {
+        !self
+            .find(&|tipe| matches!(tipe, Type::Generic(_) | Type::GenericSlot(_)))
+            .is_empty()
+    }
+
     /// Converts all slots to immutable generics. This ensures they are never changed again at call sites.
     pub fn commit_generic_slots(&self) -> Self {
         let mut tipe = self.clone();
@@ -361,6 +598,11 @@ impl Type {
         tipe
     }
 
+    /// The types of the fields of the tuple a string is made of.
+    pub fn string_fields() -> Vec<Type> {
+        vec![Type::Uint, Type::Buffer]
+    }
+
     pub fn castable(
         &self,
         rhs: &Self,
@@ -385,8 +627,15 @@ impl Type {
                 _ => false,
             },
             Type::Buffer | Type::Void | Type::Every => rhs == self,
+            Type::String => match rhs.rep(type_tree) {
+                Ok(Type::String) => true,
+                Ok(Type::Tuple(tvec)) => tvec == Type::string_fields(),
+                _ => false,
+            },
             Type::Tuple(tvec) => {
-                if let Ok(Type::Tuple(tvec2)) = rhs.rep(type_tree) {
+                if let Ok(Type::String) = rhs.rep(type_tree) {
+                    *tvec == Type::string_fields()
+                } else if let Ok(Type::Tuple(tvec2)) = rhs.rep(type_tree) {
                     type_vectors_castable(tvec, &tvec2, type_tree, seen)
                 } else {
                     false
@@ -413,6 +662,16 @@ impl Type {
                     false
                 }
             }
+            Type::Enum(variants) => {
+                if let Ok(Type::Enum(variants2)) = rhs.rep(type_tree) {
+                    variants.len() == variants2.len()
+                        && variants.iter().zip(&variants2).all(|(v1, v2)| {
+                            type_vectors_castable(&v1.fields, &v2.fields, type_tree, seen.clone())
+                        })
+                } else {
+                    false
+                }
+            }
             Type::Nominal(_, _, _) => {
                 if let (Ok(left), Ok(right)) = (self.rep(type_tree), rhs.rep(type_tree)) {
                     if seen.insert((left.clone(), right.clone())) {
@@ -492,8 +751,16 @@ impl Type {
                 Ok(right) => right == *self,
                 Err(_) => false,
             },
+            // strings and the tuples they're made of can be used in place of each other
+            Type::String => match rhs.rep(type_tree) {
+                Ok(Type::String) => true,
+                Ok(Type::Tuple(tvec)) => tvec == Type::string_fields(),
+                _ => false,
+            },
             Type::Tuple(tvec) => {
-                if let Ok(Type::Tuple(tvec2)) = rhs.rep(type_tree) {
+                if let Ok(Type::String) = rhs.rep(type_tree) {
+                    *tvec == Type::string_fields()
+                } else if let Ok(Type::Tuple(tvec2)) = rhs.rep(type_tree) {
                     type_vectors_assignable(tvec, &tvec2, type_tree, seen)
                 } else {
                     false
@@ -520,6 +787,22 @@ impl Type {
                     false
                 }
             }
+            Type::Enum(variants) => {
+                if let Ok(Type::Enum(variants2)) = rhs.rep(type_tree) {
+                    variants.len() == variants2.len()
+                        && variants.iter().zip(&variants2).all(|(v1, v2)| {
+                            v1.name == v2.name
+                                && type_vectors_assignable(
+                                    &v1.fields,
+                                    &v2.fields,
+                                    type_tree,
+                                    seen.clone(),
+                                )
+                        })
+                } else {
+                    false
+                }
+            }
             Type::Nominal(_, _, _) => {
                 if let (Ok(left), Ok(right)) = (self.rep(type_tree), rhs.rep(type_tree)) {
                     if seen.insert((left.clone(), right.clone())) {
@@ -619,6 +902,11 @@ impl Type {
                     Some(TypeMismatch::Type(self.clone(), rhs.clone()))
                 }
             }
+            Type::String => match rhs.rep(type_tree) {
+                Ok(Type::String) => None,
+                Ok(Type::Tuple(tvec)) if tvec == Type::string_fields() => None,
+                _ => Some(TypeMismatch::Type(self.clone(), rhs.clone())),
+            },
             Type::GenericSlot(slot) => match rhs.rep(type_tree) {
                 Ok(Type::GenericSlot(slot2)) if *slot == slot2 => None,
                 _ => Some(TypeMismatch::Type(self.clone(), rhs.clone())),
@@ -628,7 +916,12 @@ impl Type {
                 _ => Some(TypeMismatch::Type(self.clone(), rhs.clone())),
             },
             Type::Tuple(tvec) => {
-                if let Ok(Type::Tuple(tvec2)) = rhs.rep(type_tree) {
+                if let Ok(Type::String) = rhs.rep(type_tree) {
+                    match *tvec == Type::string_fields() {
+                        true => None,
+                        false => Some(TypeMismatch::Type(self.clone(), rhs.clone())),
+                    }
+                } else if let Ok(Type::Tuple(tvec2)) = rhs.rep(type_tree) {
                     for (index, (left, right)) in tvec.iter().zip(tvec2.iter()).enumerate() {
                         if let Some(inner) = left.first_mismatch(right, type_tree, seen.clone()) {
                             return Some(TypeMismatch::Tuple(index, Box::new(inner)));
@@ -670,6 +963,13 @@ impl Type {
                     Some(TypeMismatch::Type(self.clone(), rhs.clone()))
                 }
             }
+            Type::Enum(variants) => {
+                if let Ok(Type::Enum(variants2)) = rhs.rep(type_tree) {
+                    variant_vectors_mismatch(variants, &variants2, type_tree, seen)
+                } else {
+                    Some(TypeMismatch::Type(self.clone(), rhs.clone()))
+                }
+            }
             Type::Nominal(_, _, _) => match (self.rep(type_tree), rhs.rep(type_tree)) {
                 (Ok(left), Ok(right)) => {
                     if seen.insert((self.clone(), rhs.clone())) {
@@ -773,6 +1073,7 @@ impl Type {
                             | Type::Bytes32
                             | Type::EthAddress
                             | Type::Buffer
+                            | Type::String
                             | Type::Every => String::new(),
                             _ => match right {
                                 Type::Any
@@ -783,6 +1084,7 @@ impl Type {
                                 | Type::Bytes32
                                 | Type::EthAddress
                                 | Type::Buffer
+                                | Type::String
                                 | Type::Every => String::new(),
                                 _ => format!(
                                     "\nleft: {}\nright: {}\nFirst mismatch: ",
@@ -802,6 +1104,7 @@ impl Type {
         match self {
             Type::Any => Value::none(),
             Type::Buffer => Value::new_buffer(vec![]),
+            Type::String => Value::new_tuple(vec![Value::from(0), Value::new_buffer(vec![])]),
             Type::Uint | Type::Int | Type::Bytes32 | Type::EthAddress | Type::Bool => {
                 Value::from(0)
             }
@@ -819,6 +1122,18 @@ impl Type {
                     .collect();
                 TupleTree::fold_into_tuple(field_values)
             }
+            Type::Enum(variants) => {
+                // the first variant, with its fields defaulted
+                let field_values = variants[0]
+                    .fields
+                    .iter()
+                    .map(|t| t.default_value(type_tree))
+                    .collect();
+                Value::new_tuple(vec![
+                    Value::from(0),
+                    TupleTree::fold_into_tuple(field_values),
+                ])
+            }
             Type::Func(..) => {
                 // the error codepoint
                 Value::CodePoint(CodePt::Null)
@@ -837,20 +1152,14 @@ impl Type {
                 ])
             }
             Type::FixedArray(t, size) => {
-                // emulate array.mini builtin_arrayNew()
-                fn emulated_builtin(size: usize, mut base: Value) -> Value {
-                    let mut chunk = 1;
-                    while (8 * chunk < size) {
-                        chunk = 8 * chunk;
-                        base = Value::new_tuple(vec![base; 8]);
-                    }
-                    Value::new_tuple(vec![
-                        Value::from(size),  // size
-                        Value::from(chunk), // topstep
-                        Value::new_tuple(vec![base; 8]),
-                    ])
+                // the nested tuples of 8 that newfixedarray builds, with no header
+                let mut value = Value::new_tuple(vec![t.default_value(type_tree); 8]);
+                let mut capacity = 8;
+                while capacity < *size {
+                    value = Value::new_tuple(vec![value; 8]);
+                    capacity *= 8;
                 }
-                emulated_builtin(*size, t.default_value(type_tree))
+                value
             }
             Type::Nominal(..) => {
                 let tipe = self.rep(type_tree).unwrap_or(Type::Any);
@@ -905,6 +1214,7 @@ impl Type {
             Type::Bytes32 => ("bytes32".to_string(), type_set),
             Type::EthAddress => ("address".to_string(), type_set),
             Type::Buffer => ("buffer".to_string(), type_set),
+            Type::String => ("string".to_string(), type_set),
             Type::GenericSlot(id) => (format!("generic' {}", id), type_set),
             Type::Generic(id) => (format!("generic {}", id), type_set),
             Type::Tuple(subtypes) => {
@@ -967,6 +1277,28 @@ impl Type {
                 out.push('}');
                 (out, type_set)
             }
+            Type::Enum(variants) => {
+                let mut shown = vec![];
+                for variant in variants {
+                    let mut fields = vec![];
+                    for field in &variant.fields {
+                        let (displayed, subtypes) = field.display_indented(
+                            indent_level,
+                            separator,
+                            prefix,
+                            include_pathname,
+                            type_tree,
+                        );
+                        fields.push(displayed);
+                        type_set.extend(subtypes);
+                    }
+                    shown.push(match variant.fields.is_empty() {
+                        true => variant.name.clone(),
+                        false => format!("{}({})", variant.name, fields.join(", ")),
+                    });
+                }
+                (format!("enum {{ {} }}", shown.join(", ")), type_set)
+            }
             Type::Nominal(path, id, spec) => {
                 let out = format!(
                     "{}{}{}{}",
@@ -1129,6 +1461,22 @@ pub fn check_generic_parameters(
     Ok(params.into_iter().map(|(name, _)| name).collect())
 }
 
+/// Makes the type of fixed-size arrays of size elements, which must number at least one.
+pub fn new_fixed_array(
+    tipe: Type,
+    size: Uint256,
+    locs: Vec<Location>,
+) -> Result<Type, CompileError> {
+    match size.to_usize() {
+        Some(size) if size > 0 => Ok(Type::FixedArray(Box::new(tipe), size)),
+        _ => Err(CompileError::new(
+            "Parser error",
+            format!("Fixed-size arrays can't have {} elements", Color::red(size)),
+            locs,
+        )),
+    }
+}
+
 pub fn type_vectors_castable(
     tvec1: &[Type],
     tvec2: &[Type],
@@ -1206,6 +1554,30 @@ pub fn field_vectors_mismatch(
 
 /// Identical to `type_vectors_assignable` but using StructField slices as inputs and comparing their
 /// inner types.
+fn variant_vectors_mismatch(
+    vvec1: &[EnumVariant],
+    vvec2: &[EnumVariant],
+    type_tree: &TypeTree,
+    seen: HashSet<(Type, Type)>,
+) -> Option<TypeMismatch> {
+    for (v1, v2) in vvec1.iter().zip(vvec2.iter()) {
+        if v1.name != v2.name {
+            return Some(TypeMismatch::VariantName(v1.name.clone(), v2.name.clone()));
+        }
+        let (t1, t2) = (
+            Type::Tuple(v1.fields.clone()),
+            Type::Tuple(v2.fields.clone()),
+        );
+        if let Some(mismatch) = t1.first_mismatch(&t2, type_tree, seen.clone()) {
+            return Some(TypeMismatch::Variant(v1.name.clone(), Box::new(mismatch)));
+        }
+    }
+    if vvec1.len() != vvec2.len() {
+        return Some(TypeMismatch::VariantCount(vvec1.len(), vvec2.len()));
+    }
+    None
+}
+
 fn field_vectors_assignable(
     tvec1: &[StructField],
     tvec2: &[StructField],
@@ -1229,11 +1601,13 @@ impl PartialEq for Type {
             | (Type::EthAddress, Type::EthAddress)
             | (Type::Any, Type::Any)
             | (Type::Buffer, Type::Buffer)
+            | (Type::String, Type::String)
             | (Type::Every, Type::Every) => true,
             (Type::Tuple(v1), Type::Tuple(v2)) => type_vectors_equal(&v1, &v2),
             (Type::Array(a1), Type::Array(a2)) => *a1 == *a2,
             (Type::FixedArray(a1, s1), Type::FixedArray(a2, s2)) => (s1 == s2) && (*a1 == *a2),
             (Type::Struct(f1), Type::Struct(f2)) => struct_field_vectors_equal(&f1, &f2),
+            (Type::Enum(v1), Type::Enum(v2)) => v1 == v2,
             (Type::Map(k1, v1), Type::Map(k2, v2)) => (*k1 == *k2) && (*v1 == *v2),
             (Type::Func(p1, a1, r1), Type::Func(p2, a2, r2)) => {
                 (p1 == p2) && type_vectors_equal(&a1, &a2) && (*r1 == *r2)
@@ -1265,6 +1639,9 @@ pub enum TypeMismatch {
     Type(Type, Type),
     FieldName(String, String),
     FieldType(String, Box<TypeMismatch>),
+    VariantName(String, String),
+    Variant(String, Box<TypeMismatch>),
+    VariantCount(usize, usize),
     UnresolvedRight(Type),
     UnresolvedLeft(Type),
     UnresolvedBoth(Type, Type),
@@ -1305,6 +1682,23 @@ impl TypeMismatch {
                 Color::red(left),
                 Color::red(right)
             ),
+            TypeMismatch::VariantName(left, right) => format!(
+                "expected variant {}, got {}",
+                Color::red(left),
+                Color::red(right)
+            ),
+            TypeMismatch::Variant(name, problem) => {
+                format!(
+                    "in variant {}: {}",
+                    Color::red(name),
+                    problem.print(type_tree)
+                )
+            }
+            TypeMismatch::VariantCount(left, right) => format!(
+                "enums with different variants: expected {} variants got {}",
+                Color::red(left),
+                Color::red(right)
+            ),
             TypeMismatch::UnresolvedRight(tipe) => format!(
                 "could not resolve right-hand type {}",
                 Color::red(tipe.print(type_tree))
@@ -1415,6 +1809,27 @@ impl StructField {
     }
 }
 
+/// Variant of an enum, contains the variant's name and the types of its fields.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct EnumVariant {
+    pub name: String,
+    pub fields: Vec<Type>,
+}
+
+impl EnumVariant {
+    pub fn new(name: String, fields: Vec<Type>) -> EnumVariant {
+        EnumVariant { name, fields }
+    }
+
+    /// Finds the variant named name, along with its tag.
+    pub fn lookup<'a>(variants: &'a [EnumVariant], name: &str) -> Option<(usize, &'a Self)> {
+        variants
+            .iter()
+            .enumerate()
+            .find(|(_, variant)| variant.name == name)
+    }
+}
+
 /// Argument to a function, contains field name and underlying type.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct FuncArg {
@@ -1457,7 +1872,7 @@ impl GlobalVar {
 
 /// Represents a top level function declaration.  The view, write, args, and ret_type fields are
 /// assumed to be derived from tipe, and this must be upheld by the user of this type.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Func<T = Statement> {
     pub name: String,
     pub id: StringId,
@@ -1469,10 +1884,21 @@ pub struct Func<T = Statement> {
     pub captures: BTreeSet<StringId>,
     /// The names of this func's generic types. The order specifies which goes where.
     pub generics: Vec<StringId>,
+    /// The interface each generic type must implement, if any.
+    pub generic_bounds: Vec<Option<StringId>>,
+    /// The interface this func implements a method of, if it's part of an impl of one.
+    pub interface: Option<StringId>,
+    /// The type this func is a method of, if it's part of an impl.
+    pub receiver: Option<StringId>,
     /// A global id unique to this function used for building jump labels
     pub unique_id: Option<LabelId>,
     /// Additional properties like viewness that this func has
     pub properties: FuncProperties,
+    /// The text of the doc comment preceding this func, if any.
+    pub docs: Option<String>,
+    /// The attributes written on this func, which are given meaning by `AttributeHooks`.
+    #[serde(default)]
+    pub attributes: Vec<NamedAttribute>,
     pub debug_info: DebugInfo,
 }
 
@@ -1510,9 +1936,14 @@ impl Func {
             tipe: Type::Func(prop, arg_types, Box::new(ret_type)),
             public,
             captures,
+            generic_bounds: vec![None; generics.len()],
             generics,
+            interface: None,
+            receiver: None,
             unique_id: None,
             properties: prop,
+            docs: None,
+            attributes: vec![],
             debug_info,
         }
     }
@@ -1688,7 +2119,11 @@ pub enum ExprKind {
     FunctionCall(Box<Expr>, Vec<Expr>),
     CodeBlock(CodeBlock),
     ArrayOrMapRef(Box<Expr>, Box<Expr>),
+    /// A slice of a string, from the start index up to but not including the end index.
+    Slice(Box<Expr>, Box<Expr>, Box<Expr>),
     StructInitializer(Vec<FieldInitializer>),
+    /// The variant of an enum type with the given name, built from the values of its fields.
+    Variant(Type, String, Vec<Expr>),
     Tuple(Vec<Expr>),
     NewArray(Box<Expr>, Type),
     NewFixedArray(usize, Box<Expr>),
@@ -1705,6 +2140,7 @@ pub enum ExprKind {
     Try(Box<Expr>),
     If(Box<Expr>, CodeBlock, Option<CodeBlock>),
     IfLet(StringId, Box<Expr>, CodeBlock, Option<CodeBlock>),
+    Match(Box<Expr>, Vec<MatchArm>),
     Loop(CodeBlock, Type),
     UnionCast(Box<Expr>, Type),
     NewBuffer,
@@ -1712,6 +2148,54 @@ pub enum ExprKind {
     Closure(Func),
 }
 
+/// A pattern in a match expression, which a value must fit for its arm to be taken.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Pattern {
+    /// `_`, which matches anything
+    Wildcard,
+    /// A name that matches anything, binding it to the value
+    Bind(StringId),
+    /// A `uint`, `int`, or `bool` the value must equal
+    Const(Constant),
+    Some(Box<Pattern>),
+    None,
+    Tuple(Vec<Pattern>),
+    /// The variant of an enum type with the given name, whose fields fit the patterns
+    Variant(Type, String, Vec<Pattern>),
+}
+
+impl Pattern {
+    /// Gets the names this pattern binds, in the order they appear.
+    pub fn bindings(&self) -> Vec<StringId> {
+        match self {
+            Pattern::Bind(id) => vec![*id],
+            Pattern::Some(inner) => inner.bindings(),
+            Pattern::Tuple(fields) | Pattern::Variant(_, _, fields) => {
+                fields.iter().flat_map(|field| field.bindings()).collect()
+            }
+            Pattern::Wildcard | Pattern::Const(_) | Pattern::None => vec![],
+        }
+    }
+}
+
+/// An arm of a match expression, whose body is evaluated when its pattern is the first to match.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchArm<T = Expr> {
+    pub pattern: Pattern,
+    pub body: T,
+    pub debug_info: DebugInfo,
+}
+
+impl<T> MatchArm<T> {
+    pub fn new(pattern: Pattern, body: T, debug_info: DebugInfo) -> Self {
+        MatchArm {
+            pattern,
+            body,
+            debug_info,
+        }
+    }
+}
+
 impl Expr {
     /// Returns an expression that applies unary operator op to e.
     pub fn new_unary(op: UnaryOp, e: Expr, lines: &Lines, lno: usize, file: u64) -> Self {
@@ -1753,6 +2237,18 @@ impl Expr {
         )
     }
 
+    /// Returns an expression that calls callable with args. Calling a variant of an enum instead
+    /// gives it args as its fields.
+    pub fn new_call(callable: Expr, args: Vec<Expr>, lines: &Lines, lno: usize, file: u64) -> Self {
+        let kind = match callable.kind {
+            ExprKind::Variant(tipe, name, fields) if fields.is_empty() => {
+                ExprKind::Variant(tipe, name, args)
+            }
+            kind => ExprKind::FunctionCall(Box::new(Expr::new(kind, callable.debug_info)), args),
+        };
+        Self::lno(kind, lines, lno, file)
+    }
+
     /// Creates an expression whose DebugInfo is populated in-place at the parsing site
     pub fn lno(kind: ExprKind, lines: &Lines, lno: usize, file: u64) -> Self {
         Self::new(kind, DebugInfo::here(lines, lno, file))
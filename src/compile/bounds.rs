@@ -0,0 +1,262 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides a range analysis that proves fixed array indices are in bounds, so that codegen can
+//! leave out their bounds checks.
+
+use super::ast::{BinaryOp, Type};
+use super::typecheck::{
+    AbstractSyntaxTree, TypeCheckedCodeBlock, TypeCheckedExpr, TypeCheckedExprKind,
+    TypeCheckedFunc, TypeCheckedNode, TypeCheckedStatement, TypeCheckedStatementKind,
+};
+use crate::mavm::Value;
+use crate::stringtable::StringId;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The least and greatest values a uint may have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Range {
+    lo: u64,
+    hi: u64,
+}
+
+/// What's known of the values of the uint locals at a point in a func. Locals without an entry
+/// could have any value.
+type Ranges = BTreeMap<StringId, Range>;
+
+/// Marks the fixed array accesses of a func whose indices are always in bounds.
+///
+/// Statements are walked in order, tracking a range for each uint local from the constants and
+/// arithmetic it's assigned, and narrowing it within the bodies of the `while` loops and `if`s
+/// whose conditions compare it to a bound. Anything a loop, branch, or nested block might assign
+/// is forgotten on entering it, so a range only ever describes values the local can really hold.
+pub fn elide_bounds_checks(func: &mut TypeCheckedFunc) {
+    let mut ranges = Ranges::new();
+    for statement in &mut func.code {
+        statement_ranges(statement, &mut ranges);
+    }
+}
+
+fn statement_ranges(statement: &mut TypeCheckedStatement, ranges: &mut Ranges) {
+    if let TypeCheckedStatementKind::While(..) = statement.kind {
+        forget(&mut TypeCheckedNode::Statement(statement), ranges);
+    }
+    match &mut statement.kind {
+        TypeCheckedStatementKind::SetLocals(assigned, expr) => {
+            expr_ranges(expr, ranges);
+            let range = match assigned.len() {
+                1 => range_of(expr, ranges),
+                _ => None,
+            };
+            for assign in assigned.iter() {
+                ranges.remove(&assign.id);
+            }
+            if let Some(range) = range {
+                ranges.insert(assigned[0].id, range);
+            }
+        }
+        TypeCheckedStatementKind::While(cond, body) => {
+            expr_ranges(cond, &mut ranges.clone());
+            let mut inner = ranges.clone();
+            narrow(cond, &mut inner);
+            block_ranges(body, &mut inner);
+        }
+        _ => {
+            for mut node in statement.child_nodes() {
+                node_ranges(&mut node, ranges);
+            }
+        }
+    }
+}
+
+fn block_ranges(block: &mut TypeCheckedCodeBlock, ranges: &mut Ranges) {
+    for statement in &mut block.body {
+        statement_ranges(statement, ranges);
+    }
+    if let Some(expr) = &mut block.ret_expr {
+        expr_ranges(expr, ranges);
+    }
+}
+
+fn node_ranges(node: &mut TypeCheckedNode, ranges: &mut Ranges) {
+    match node {
+        TypeCheckedNode::Statement(statement) => statement_ranges(statement, ranges),
+        TypeCheckedNode::Expression(expr) => expr_ranges(expr, ranges),
+        TypeCheckedNode::Type(_) => {}
+    }
+}
+
+/// Marks the accesses within expr that are in bounds. Any locals expr might assign are forgotten.
+fn expr_ranges(expr: &mut TypeCheckedExpr, ranges: &mut Ranges) {
+    forget(&mut TypeCheckedNode::Expression(expr), ranges);
+    match &mut expr.kind {
+        TypeCheckedExprKind::FixedArrayRef(array, index, size, in_bounds, _) => {
+            expr_ranges(array, &mut ranges.clone());
+            expr_ranges(index, &mut ranges.clone());
+            *in_bounds = below(index, *size, ranges);
+        }
+        TypeCheckedExprKind::FixedArrayMod(array, index, value, size, in_bounds, _) => {
+            expr_ranges(array, &mut ranges.clone());
+            expr_ranges(index, &mut ranges.clone());
+            expr_ranges(value, &mut ranges.clone());
+            *in_bounds = below(index, *size, ranges);
+        }
+        TypeCheckedExprKind::If(cond, block, else_block, _) => {
+            expr_ranges(cond, &mut ranges.clone());
+            let mut inner = ranges.clone();
+            narrow(cond, &mut inner);
+            block_ranges(block, &mut inner);
+            if let Some(else_block) = else_block {
+                block_ranges(else_block, &mut ranges.clone());
+            }
+        }
+        TypeCheckedExprKind::ShortcutAnd(left, right) => {
+            expr_ranges(left, &mut ranges.clone());
+            let mut inner = ranges.clone();
+            narrow(left, &mut inner);
+            expr_ranges(right, &mut inner);
+        }
+        TypeCheckedExprKind::CodeBlock(block) | TypeCheckedExprKind::Loop(block, _) => {
+            block_ranges(block, &mut ranges.clone());
+        }
+        _ => {
+            // siblings are walked separately, so a local one binds isn't confused with another's
+            for mut node in expr.child_nodes() {
+                node_ranges(&mut node, &mut ranges.clone());
+            }
+        }
+    }
+}
+
+/// Forgets the ranges of the locals that node binds or assigns anywhere within it.
+fn forget(node: &mut TypeCheckedNode, ranges: &mut Ranges) {
+    if ranges.is_empty() {
+        return;
+    }
+    let mut bound = BTreeSet::new();
+    bound_locals(node, &mut bound);
+    for id in bound {
+        ranges.remove(&id);
+    }
+}
+
+fn bound_locals(node: &mut TypeCheckedNode, bound: &mut BTreeSet<StringId>) {
+    match node {
+        TypeCheckedNode::Statement(statement) => {
+            if let TypeCheckedStatementKind::SetLocals(assigned, _) = &statement.kind {
+                bound.extend(assigned.iter().map(|assign| assign.id));
+            }
+        }
+        TypeCheckedNode::Expression(expr) => match &expr.kind {
+            TypeCheckedExprKind::IfLet(id, ..) => drop(bound.insert(*id)),
+            TypeCheckedExprKind::Match(_, arms, ..) => {
+                bound.extend(arms.iter().flat_map(|arm| arm.pattern.bindings()));
+            }
+            _ => {}
+        },
+        TypeCheckedNode::Type(_) => {}
+    }
+    for mut child in node.child_nodes() {
+        bound_locals(&mut child, bound);
+    }
+}
+
+/// Whether the value of index is always less than size.
+fn below(index: &TypeCheckedExpr, size: usize, ranges: &Ranges) -> bool {
+    match range_of(index, ranges) {
+        Some(range) => (range.hi as u128) < size as u128,
+        None => false,
+    }
+}
+
+/// Finds the range of values a uint expression may have, if it can be bounded.
+fn range_of(expr: &TypeCheckedExpr, ranges: &Ranges) -> Option<Range> {
+    match &expr.kind {
+        TypeCheckedExprKind::Const(Value::Int(value), Type::Uint) => {
+            let value = value.to_u64()?;
+            Some(Range {
+                lo: value,
+                hi: value,
+            })
+        }
+        TypeCheckedExprKind::LocalVariableRef(id, Type::Uint) => ranges.get(id).copied(),
+        TypeCheckedExprKind::Binary(op, left, right, Type::Uint) => {
+            let (left, right) = (range_of(left, ranges), range_of(right, ranges));
+            match (op, left, right) {
+                (BinaryOp::Plus, Some(left), Some(right)) => Some(Range {
+                    lo: left.lo.checked_add(right.lo)?,
+                    hi: left.hi.checked_add(right.hi)?,
+                }),
+                (BinaryOp::Minus, Some(left), Some(right)) if left.lo >= right.hi => Some(Range {
+                    lo: left.lo - right.hi,
+                    hi: left.hi - right.lo,
+                }),
+                (BinaryOp::Times, Some(left), Some(right)) => Some(Range {
+                    lo: left.lo.checked_mul(right.lo)?,
+                    hi: left.hi.checked_mul(right.hi)?,
+                }),
+                (BinaryOp::Div, Some(left), Some(right)) if right.lo > 0 => Some(Range {
+                    lo: left.lo / right.hi,
+                    hi: left.hi / right.lo,
+                }),
+                (BinaryOp::Mod, left, Some(right)) if right.lo > 0 => Some(Range {
+                    lo: 0,
+                    hi: match left {
+                        Some(left) => left.hi.min(right.hi - 1),
+                        None => right.hi - 1,
+                    },
+                }),
+                (BinaryOp::BitwiseAnd, Some(one), other)
+                | (BinaryOp::BitwiseAnd, other, Some(one)) => Some(Range {
+                    lo: 0,
+                    hi: match other {
+                        Some(other) => one.hi.min(other.hi),
+                        None => one.hi,
+                    },
+                }),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Narrows the ranges of locals to those that satisfy cond.
+fn narrow(cond: &TypeCheckedExpr, ranges: &mut Ranges) {
+    let (op, left, right) = match &cond.kind {
+        TypeCheckedExprKind::ShortcutAnd(left, right) => {
+            narrow(left, ranges);
+            narrow(right, ranges);
+            return;
+        }
+        TypeCheckedExprKind::Binary(op, left, right, _) => (op, left, right),
+        _ => return,
+    };
+    // write the comparison as local < bound or local <= bound
+    let (local, bound, strict) = match op {
+        BinaryOp::LessThan => (left, right, true),
+        BinaryOp::LessEq => (left, right, false),
+        BinaryOp::GreaterThan => (right, left, true),
+        BinaryOp::GreaterEq => (right, left, false),
+        _ => return,
+    };
+    let id = match &local.kind {
+        TypeCheckedExprKind::LocalVariableRef(id, Type::Uint) => *id,
+        _ => return,
+    };
+    let bound = match range_of(bound, ranges) {
+        Some(bound) => bound.hi,
+        None => return,
+    };
+    let hi = match strict {
+        true => match bound.checked_sub(1) {
+            Some(hi) => hi,
+            None => return,
+        },
+        false => bound,
+    };
+    let range = ranges.entry(id).or_insert(Range { lo: 0, hi });
+    range.hi = range.hi.min(hi);
+    range.lo = range.lo.min(range.hi);
+}
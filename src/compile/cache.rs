@@ -0,0 +1,223 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides an on-disk cache of typechecked modules, so that unchanged modules aren't typechecked
+//! on every compile.
+
+use super::{module_file_id, CompileError, FileInfo, Module, Type, TypeCheckedModule, TypeTree};
+use crate::mavm::StableHasher;
+use crate::stringtable::{StringId, StringTable};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The folder, next to a program's sources, that its cache is kept in.
+pub const CACHE_DIR: &str = ".mini-cache";
+
+/// Changes whenever the layout of cache entries does, so that older entries are ignored.
+const CACHE_FORMAT: u64 = 2;
+
+/// A typechecked module along with the issues typechecking it raised.
+pub type CacheEntry = (TypeCheckedModule, Vec<CompileError>);
+
+/// Distinguishes the temporary files of concurrent writers within a process.
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+/// The typechecked modules of earlier compiles of a folder.
+pub struct ModuleCache {
+    dir: PathBuf,
+    /// Covers everything besides the sources that typechecking depends on. It and the keys are
+    /// hashed with the stable hasher, so that they don't change with the Rust release the compiler
+    /// is built with
+    salt: u64,
+}
+
+impl ModuleCache {
+    /// Opens the cache of the program in folder. Since a cached module is only valid for the
+    /// compiler that produced it, there's no cache if the running compiler can't be identified.
+    pub fn new(
+        folder: &Path,
+        constants_path: Option<&Path>,
+        features: &BTreeSet<String>,
+        builtins: bool,
+        warnings_are_errors: bool,
+    ) -> Option<Self> {
+        let exe = std::env::current_exe().ok()?;
+        let meta = fs::metadata(&exe).ok()?;
+
+        let mut hasher = StableHasher::default();
+        CACHE_FORMAT.hash(&mut hasher);
+        exe.hash(&mut hasher);
+        meta.len().hash(&mut hasher);
+        meta.modified().ok()?.hash(&mut hasher);
+        constants_path
+            .map(|path| fs::read(path).unwrap_or_default())
+            .hash(&mut hasher);
+        features.hash(&mut hasher);
+        builtins.hash(&mut hasher);
+        warnings_are_errors.hash(&mut hasher);
+
+        Some(ModuleCache {
+            dir: folder.join(CACHE_DIR),
+            salt: hasher.finish(),
+        })
+    }
+
+    /// Finds the key each module is cached under, or `None` for those that can't be cached.
+    ///
+    /// A module's key covers everything typechecking it reads: its source, the types, funcs, and
+    /// interfaces imports and impls have brought into it, and the parts of the type tree those
+    /// refer to. Changes to the bodies of funcs elsewhere don't affect it. Modules using the print attribute aren't cached, as the
+    /// attribute isn't kept in cache entries.
+    pub fn keys(
+        &self,
+        modules: &[Module],
+        type_tree: &TypeTree,
+        file_info_chart: &BTreeMap<u64, FileInfo>,
+    ) -> Vec<Option<u64>> {
+        modules
+            .iter()
+            .map(|module| {
+                let source = &file_info_chart.get(&module_file_id(&module.path))?.contents;
+                let printing = source.iter().any(|line| {
+                    let line = line.trim_start();
+                    (line.starts_with("#[") || line.starts_with("#![")) && line.contains("print")
+                });
+                if printing {
+                    return None;
+                }
+
+                let name = |id: &StringId| module.string_table.name_from_id(*id);
+                let mut interfaces: Vec<_> = module
+                    .interfaces
+                    .iter()
+                    .map(|(id, interface)| {
+                        let methods: Vec<_> = interface
+                            .methods
+                            .iter()
+                            .map(|method| (&method.name, &method.tipe))
+                            .collect();
+                        (*id, name(id), methods)
+                    })
+                    .collect();
+                interfaces.sort_by_key(|entry| entry.0);
+
+                let mut roots: Vec<&Type> = module.func_table.values().collect();
+                roots.extend(module.named_types.values());
+                roots.extend(module.global_vars.iter().map(|global| &global.tipe));
+                for interface in module.interfaces.values() {
+                    roots.extend(interface.methods.iter().map(|method| &method.tipe));
+                }
+
+                let mut hasher = StableHasher::default();
+                self.salt.hash(&mut hasher);
+                reachable_types(roots, type_tree).hash(&mut hasher);
+                module.path.hash(&mut hasher);
+                source.hash(&mut hasher);
+                sorted_types(&module.func_table, &module.string_table).hash(&mut hasher);
+                sorted_types(&module.named_types, &module.string_table).hash(&mut hasher);
+                interfaces.hash(&mut hasher);
+                Some(hasher.finish())
+            })
+            .collect()
+    }
+
+    /// Gets the entry cached under key, if there's a readable one.
+    pub fn load(&self, key: u64) -> Option<CacheEntry> {
+        let bytes = fs::read(self.entry_path(key)).ok()?;
+        bincode::deserialize(&bytes).ok()
+    }
+
+    /// Caches an entry under key. Failing to is harmless, since the module will just be
+    /// typechecked again next time.
+    pub fn store(&self, key: u64, entry: &CacheEntry) {
+        let bytes = match bincode::serialize(entry) {
+            Ok(bytes) => bytes,
+            Err(_) => return,
+        };
+        // written elsewhere first so that readers never see part of an entry
+        let temp = self.dir.join(format!(
+            "{:016x}.{}.{}.tmp",
+            key,
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&temp, bytes))
+            .and_then(|_| fs::rename(&temp, self.entry_path(key)));
+        if written.is_err() {
+            drop(fs::remove_file(&temp));
+        }
+    }
+
+    fn entry_path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.bin", key))
+    }
+}
+
+/// Lists the types in a table by id, along with the names they're known by, so they hash the same
+/// in every run.
+fn sorted_types<'a>(
+    table: &'a HashMap<StringId, Type>,
+    string_table: &'a StringTable,
+) -> Vec<(StringId, &'a String, &'a Type)> {
+    let mut entries: Vec<_> = table
+        .iter()
+        .map(|(id, tipe)| (*id, string_table.name_from_id(*id), tipe))
+        .collect();
+    entries.sort_by_key(|entry| entry.0);
+    entries
+}
+
+/// Gets the entries of the type tree reachable from a set of types, in order.
+pub(super) fn reachable_types<'a>(
+    roots: Vec<&'a Type>,
+    type_tree: &'a TypeTree,
+) -> BTreeMap<&'a (Vec<String>, StringId), &'a (Type, String)> {
+    let mut reached = BTreeMap::new();
+    let mut queue = roots;
+    while let Some(tipe) = queue.pop() {
+        match tipe {
+            Type::Nominal(path, id, args) => {
+                queue.extend(args);
+                if let Some((key, entry)) = type_tree.get_key_value(&(path.clone(), *id)) {
+                    if reached.insert(key, entry).is_none() {
+                        queue.push(&entry.0);
+                    }
+                }
+            }
+            Type::Tuple(types) | Type::Union(types) => queue.extend(types),
+            Type::Array(inner) | Type::FixedArray(inner, _) | Type::Option(inner) => {
+                queue.push(inner)
+            }
+            Type::Struct(fields) => queue.extend(fields.iter().map(|field| &field.tipe)),
+            Type::Enum(variants) => {
+                queue.extend(variants.iter().flat_map(|variant| &variant.fields))
+            }
+            Type::Func(_, args, ret) => {
+                queue.extend(args);
+                queue.push(ret);
+            }
+            Type::Map(key, value) => {
+                queue.push(key);
+                queue.push(value);
+            }
+            Type::Void
+            | Type::Uint
+            | Type::Int
+            | Type::Bool
+            | Type::Bytes32
+            | Type::EthAddress
+            | Type::Buffer
+            | Type::String
+            | Type::Any
+            | Type::Every
+            | Type::GenericSlot(_)
+            | Type::Generic(_) => {}
+        }
+    }
+    reached
+}
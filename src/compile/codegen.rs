@@ -4,10 +4,11 @@
 
 //! Contains utilities for generating instructions from AST structures.
 
-use super::ast::{BinaryOp, DebugInfo, GlobalVar, TrinaryOp, Type, UnaryOp};
+use super::ast::{BinaryOp, Constant, DebugInfo, GlobalVar, Pattern, TrinaryOp, Type, UnaryOp};
+use super::monomorph;
 use super::typecheck::{TypeCheckedFunc, TypeCheckedNode};
 use crate::compile::typecheck::{
-    AbstractSyntaxTree, TypeCheckedExprKind, TypeCheckedStatementKind,
+    AbstractSyntaxTree, TypeCheckedExpr, TypeCheckedExprKind, TypeCheckedStatementKind,
 };
 use crate::compile::CompileError;
 use crate::console::Color;
@@ -35,6 +36,12 @@ struct Codegen<'a> {
     globals: &'a HashMap<StringId, GlobalVar>,
     /// Whether to elide debug-only constructs like assert().
     release_build: bool,
+    /// The func's own label and where its body begins, when tail calls should become jumps.
+    tail_calls: Option<(Label, Label)>,
+    /// Whether each statement should begin with a coverage probe.
+    coverage: bool,
+    /// Whether `+`, `-`, and `*` on uints and ints should raise an error when they wrap.
+    overflow_checks: bool,
     /// The open set of scopes
     scopes: Vec<Scope>,
     /// The next slot available for assignment
@@ -88,6 +95,32 @@ impl Codegen<'_> {
         last.locals.insert(local, slot);
     }
 
+    /// Get the label of the func a func ref refers to.
+    fn func_label(&self, id: &StringId, spec: &[Type]) -> Option<Label> {
+        match self.func_labels.get(id) {
+            Some(Label::Func(unique_id)) if monomorph::is_concrete(spec) => {
+                Some(Label::Func(monomorph::specialized_id(*unique_id, spec)))
+            }
+            label => label.cloned(),
+        }
+    }
+
+    /// Get the label of the func a return statement tail calls, if any.
+    fn tail_callee(&self, expr: &TypeCheckedExpr) -> Option<Label> {
+        self.tail_calls?;
+        match &expr.kind {
+            TypeCheckedExprKind::FunctionCall(fexpr, _, _, prop) if prop.returns => {
+                match &fexpr.kind {
+                    TypeCheckedExprKind::FuncRef(id, spec, _) => self
+                        .func_label(id, spec)
+                        .filter(|label| matches!(label, Label::Func(_))),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
     /// Get the currently accessible slot assignment for a variable in scope.
     fn get_local(&mut self, local: &StringId) -> Option<SlotNum> {
         let last = self.scopes.last_mut().expect("no scope");
@@ -142,6 +175,9 @@ pub fn mavm_codegen_func(
     globals: &HashMap<StringId, GlobalVar>,
     func_labels: &HashMap<StringId, Label>,
     release_build: bool,
+    tail_calls: bool,
+    coverage: bool,
+    overflow_checks: bool,
 ) -> Result<(Vec<Instruction>, LabelGenerator, u32), CompileError> {
     let mut code = vec![];
     let debug = func.debug_info;
@@ -172,6 +208,8 @@ pub fn mavm_codegen_func(
 
     code.push(opcode!(@Label(label)));
 
+    let mut label_gen = LabelGenerator::new(unique_id + 1);
+
     // Codegen the initial function frame
 
     let prop = match &func.tipe {
@@ -188,6 +226,16 @@ pub fn mavm_codegen_func(
     code.push(opcode!(@MakeFrame(0, prebuilt)));
     code.push(opcode!(AuxPush));
 
+    // a tail call hands its return address to the callee, so only funcs that return can make them
+    let tail_calls = match tail_calls && prop.returns {
+        true => {
+            let body_label = label_gen.next();
+            code.push(opcode!(@Label(body_label)));
+            Some((label, body_label))
+        }
+        false => None,
+    };
+
     let nargs = func.args.len();
     for i in 0..nargs {
         code.push(opcode!(@SetLocal(i as SlotNum)));
@@ -197,8 +245,6 @@ pub fn mavm_codegen_func(
         code.push(opcode!(@ReserveCapture(slot as SlotNum, *id)));
     }
 
-    let mut label_gen = LabelGenerator::new(unique_id + 1);
-
     let mut cgen = Codegen {
         code: &mut code,
         label_gen: &mut label_gen,
@@ -206,6 +252,9 @@ pub fn mavm_codegen_func(
         func_labels,
         globals,
         release_build,
+        tail_calls,
+        coverage,
+        overflow_checks: overflow_checks && !debug.attributes.wrapping,
         scopes: vec![Scope::default()],
         next_assignable_slot: 0,
     };
@@ -319,12 +368,34 @@ fn codegen(
 
         match node {
             TypeCheckedNode::Statement(stat) => {
+                if cgen.coverage && debug.location.is_some() {
+                    let mut probe = opcode!(Noop);
+                    probe.debug_info.attributes.probe = true;
+                    cgen.code.push(probe);
+                }
                 match &mut stat.kind {
                     TypeCheckedStatementKind::SetLocals(assigned, expr) => {
-                        expr!(expr);
                         let count = assigned.len();
-                        for _ in 0..(count - 1) {
-                            cgen.code.push(opcode!(Dup0));
+
+                        // a tuple being built is unpacked as it's built, so it's never made
+                        let unpacked = match &expr.kind {
+                            TypeCheckedExprKind::Tuple(fields, _) => {
+                                count > 1 && fields.len() == count
+                            }
+                            _ => false,
+                        };
+                        match &mut expr.kind {
+                            TypeCheckedExprKind::Tuple(fields, _) if unpacked => {
+                                for i in 0..count {
+                                    expr!(&mut fields[count - 1 - i], i);
+                                }
+                            }
+                            _ => {
+                                expr!(expr);
+                                for _ in 0..(count - 1) {
+                                    cgen.code.push(opcode!(Dup0));
+                                }
+                            }
                         }
 
                         for (index, local) in assigned.into_iter().enumerate() {
@@ -335,7 +406,7 @@ fn codegen(
                                 false => cgen.set_local(local.id, slot),
                             }
 
-                            if count > 1 {
+                            if count > 1 && !unpacked {
                                 cgen.code.push(opcode!(@TupleGet(index, count)));
                             }
 
@@ -354,8 +425,32 @@ fn codegen(
                     }
                     TypeCheckedStatementKind::Return(expr) => {
                         clear_stack!();
-                        expr!(expr);
-                        cgen.code.push(opcode!(@Return));
+                        match (cgen.tail_callee(expr), cgen.tail_calls) {
+                            (Some(callee), Some((label, body_label))) => {
+                                if let TypeCheckedExprKind::FunctionCall(_, args, ..) =
+                                    &mut expr.kind
+                                {
+                                    let nargs = args.len();
+                                    for i in 0..nargs {
+                                        expr!(&mut args[nargs - 1 - i], i);
+                                    }
+                                }
+                                if callee == label {
+                                    // reuse the frame, jumping back to where the args are set
+                                    cgen.code.push(opcode!(Jump, Value::Label(body_label)));
+                                } else {
+                                    // drop the frame, leaving our return address for the callee
+                                    cgen.code.push(opcode!(AuxPop));
+                                    cgen.code.push(opcode!(Pop));
+                                    cgen.code.push(opcode!(AuxPop));
+                                    cgen.code.push(opcode!(Jump, Value::Label(callee)));
+                                }
+                            }
+                            _ => {
+                                expr!(expr);
+                                cgen.code.push(opcode!(@Return));
+                            }
+                        }
                     }
                     TypeCheckedStatementKind::Expression(expr) => {
                         expr!(expr);
@@ -456,6 +551,93 @@ fn codegen(
                         }
                         cgen.code.push(opcode!(@Label(end_label)));
                     }
+                    TypeCheckedExprKind::Match(scrutinee, arms, ..) => {
+                        expr!(scrutinee);
+                        let scrutinee_slot = cgen.next_slot();
+                        cgen.code.push(opcode!(@SetLocal(scrutinee_slot)));
+                        let end_label = cgen.label_gen.next();
+
+                        for arm in arms {
+                            let next_label = cgen.label_gen.next();
+
+                            // Each test loads the part of the scrutinee at its path, jumping to
+                            // the next arm when it doesn't fit the pattern.
+                            let mut tests = vec![(&arm.pattern, vec![])];
+                            let mut bindings = vec![];
+                            while let Some((pattern, path)) = tests.pop() {
+                                macro_rules! load {
+                                    () => {
+                                        cgen.code.push(opcode!(@GetLocal(scrutinee_slot)));
+                                        cgen.code.extend(path.iter().cloned());
+                                    };
+                                }
+                                match pattern {
+                                    Pattern::Wildcard => {}
+                                    Pattern::Bind(id) => bindings.push((*id, path)),
+                                    Pattern::Const(Constant::Bool(value)) => {
+                                        load!();
+                                        if *value {
+                                            cgen.code.push(opcode!(IsZero));
+                                        }
+                                        cgen.code.push(opcode!(Cjump, Value::Label(next_label)));
+                                    }
+                                    Pattern::Const(constant) => {
+                                        load!();
+                                        cgen.code.push(opcode!(Equal, constant.value()));
+                                        cgen.code.push(opcode!(IsZero));
+                                        cgen.code.push(opcode!(Cjump, Value::Label(next_label)));
+                                    }
+                                    Pattern::None => {
+                                        load!();
+                                        cgen.code.push(opcode!(Tget, Value::from(0)));
+                                        cgen.code.push(opcode!(Cjump, Value::Label(next_label)));
+                                    }
+                                    Pattern::Some(inner) => {
+                                        load!();
+                                        cgen.code.push(opcode!(Tget, Value::from(0)));
+                                        cgen.code.push(opcode!(IsZero));
+                                        cgen.code.push(opcode!(Cjump, Value::Label(next_label)));
+                                        let mut path = path.clone();
+                                        path.push(opcode!(Tget, Value::from(1)));
+                                        tests.push((inner, path));
+                                    }
+                                    Pattern::Tuple(fields) => {
+                                        for (index, field) in fields.iter().enumerate() {
+                                            let mut path = path.clone();
+                                            path.push(opcode!(@TupleGet(index, fields.len())));
+                                            tests.push((field, path));
+                                        }
+                                    }
+                                    Pattern::Variant(..) => {
+                                        panic!("variant patterns are lowered when typechecked")
+                                    }
+                                }
+                            }
+
+                            // like if-let, the bindings go in the slots the arm's scope will
+                            // assign them upon opening
+                            let mut declare = vec![];
+                            for (offset, (id, path)) in bindings.into_iter().enumerate() {
+                                cgen.code.push(opcode!(@GetLocal(scrutinee_slot)));
+                                cgen.code.extend(path);
+                                let slot = cgen.next_assignable_slot + offset as SlotNum;
+                                cgen.code.push(opcode!(@SetLocal(slot)));
+                                declare.push(id);
+                            }
+                            codegen(
+                                vec![TypeCheckedNode::Expression(&mut arm.body)],
+                                cgen,
+                                stack_items,
+                                declare,
+                            )?;
+                            cgen.code.push(opcode!(Jump, Value::Label(end_label)));
+                            cgen.code.push(opcode!(@Label(next_label)));
+                        }
+
+                        // the typechecker ensures some arm always matches
+                        cgen.code.push(opcode!(Error));
+                        cgen.code.push(opcode!(@Label(end_label)));
+                    }
                     TypeCheckedExprKind::Loop(body, _) => {
                         let loop_slot = cgen.next_slot();
                         let top_label = cgen.label_gen.next();
@@ -490,15 +672,19 @@ fn codegen(
                         let slot = local!(id);
                         cgen.code.push(opcode!(@GetLocal(slot)));
                     }
-                    TypeCheckedExprKind::FuncRef(id, _) => {
-                        let func_label = match cgen.func_labels.get(id) {
-                            Some(label) => *label,
+                    TypeCheckedExprKind::FuncRef(id, spec, _) => {
+                        let func_label = match cgen.func_label(id, spec) {
+                            Some(label) => label,
                             None => {
                                 error!("No label for func ref {}", id)
                             }
                         };
                         cgen.code.push(opcode!(Noop, Value::Label(func_label)));
                     }
+                    TypeCheckedExprKind::MethodRef(..) => {
+                        // only unspecialized generic funcs can't resolve their methods
+                        cgen.code.push(opcode!(Error));
+                    }
                     TypeCheckedExprKind::GlobalVariableRef(id, _) => {
                         let offset = match cgen.globals.get(id) {
                             Some(global) => global.offset.unwrap(),
@@ -556,10 +742,10 @@ fn codegen(
                             tuple_size *= 8;
                         }
                     }
-                    TypeCheckedExprKind::FixedArrayRef(expr1, expr2, size, _) => {
+                    TypeCheckedExprKind::FixedArrayRef(expr1, expr2, size, in_bounds, _) => {
                         expr!(expr1, 0);
                         expr!(expr2, 1);
-                        if *size != 8 {
+                        if *size != 8 && !*in_bounds {
                             //TODO: also skip check if size is larger power of 8
                             let cont_label = cgen.label_gen.next();
                             cgen.code.push(opcode!(Dup0));
@@ -570,12 +756,12 @@ fn codegen(
                         }
                         cgen.code.push(opcode!(@UncheckedFixedArrayGet(*size)));
                     }
-                    TypeCheckedExprKind::FixedArrayMod(arr, key, val, size, _) => {
+                    TypeCheckedExprKind::FixedArrayMod(arr, key, val, size, in_bounds, _) => {
                         expr!(val, 0);
                         expr!(arr, 1);
                         expr!(key, 2);
 
-                        if *size != 8 {
+                        if *size != 8 && !*in_bounds {
                             // TODO: safe for if-condition to say size does not equal any power of 8
                             let ok_label = cgen.label_gen.next();
                             cgen.code.push(opcode!(Dup0));
@@ -673,9 +859,12 @@ fn codegen(
                             UnaryOp::ToUint | UnaryOp::ToInt | UnaryOp::ToBytes32 => opcode!(Noop),
                         });
                     }
-                    TypeCheckedExprKind::Binary(op, expr1, expr2, _) => {
+                    TypeCheckedExprKind::Binary(op, expr1, expr2, tipe) => {
                         expr!(expr2, 0);
                         expr!(expr1, 1);
+                        if cgen.overflow_checks && checked_arithmetic(*op, tipe, cgen, debug) {
+                            continue;
+                        }
                         let opcode = Opcode::AVMOpcode(match op {
                             BinaryOp::GetBuffer8 => AVMOpcode::GetBuffer8,
                             BinaryOp::GetBuffer64 => AVMOpcode::GetBuffer64,
@@ -854,3 +1043,114 @@ fn codegen(
 
     Ok(())
 }
+
+/// Generates a `+`, `-`, or `*` of two uints or ints that raises an error instead of wrapping,
+/// returning whether op is one of these. The left operand is atop the right, and is replaced by
+/// the result.
+fn checked_arithmetic(op: BinaryOp, tipe: &Type, cgen: &mut Codegen, debug: DebugInfo) -> bool {
+    macro_rules! opcode {
+        ($opcode:ident) => {
+            Instruction::from_opcode(Opcode::AVMOpcode(AVMOpcode::$opcode), debug)
+        };
+        ($opcode:ident, $immediate:expr) => {
+            Instruction::from_opcode_imm(Opcode::AVMOpcode(AVMOpcode::$opcode), $immediate, debug)
+        };
+        (@$($opcode:tt)+) => {
+            Instruction::from_opcode(Opcode::$($opcode)+, debug)
+        };
+    }
+
+    // each check leaves whether the op is safe atop the stack
+    macro_rules! check {
+        ($($opcode:ident $(($immediate:expr))?),* $(,)?) => {{
+            let ok_label = cgen.label_gen.next();
+            $(cgen.code.push(opcode!($opcode $(, $immediate)?));)*
+            cgen.code.push(opcode!(Cjump, Value::Label(ok_label)));
+            cgen.code.push(opcode!(Error));
+            cgen.code.push(opcode!(@Label(ok_label)));
+        }};
+    }
+
+    let signed = match tipe {
+        Type::Uint => false,
+        Type::Int => true,
+        _ => return false,
+    };
+
+    // stack: left right
+    match (op, signed) {
+        (BinaryOp::Plus, false) => {
+            // the sum wrapped if it's less than either operand
+            cgen.code.push(opcode!(Dup0));
+            cgen.code.push(opcode!(Swap2));
+            cgen.code.push(opcode!(Add));
+            cgen.code.push(opcode!(Dup0));
+            cgen.code.push(opcode!(Swap2));
+            check!(GreaterThan, IsZero);
+        }
+        (BinaryOp::Minus, false) => {
+            // the difference wrapped if the right operand is greater
+            check!(Dup1, Dup1, LessThan, IsZero);
+            cgen.code.push(opcode!(Sub));
+        }
+        (BinaryOp::Times, false) => {
+            // the product wrapped if dividing it by a nonzero left operand doesn't give the right
+            let mul_label = cgen.label_gen.next();
+            cgen.code.push(opcode!(Dup0));
+            cgen.code.push(opcode!(IsZero));
+            cgen.code.push(opcode!(Cjump, Value::Label(mul_label)));
+            check!(Dup1, Dup1, Dup1, Dup1, Mul, Div, Equal);
+            cgen.code.push(opcode!(@Label(mul_label)));
+            cgen.code.push(opcode!(Mul));
+        }
+        (BinaryOp::Plus, true) => {
+            // the sum wrapped if its sign differs from that of both operands
+            cgen.code.push(opcode!(Dup1));
+            cgen.code.push(opcode!(Dup1));
+            cgen.code.push(opcode!(Add));
+            cgen.code.push(opcode!(Dup0));
+            cgen.code.push(opcode!(Swap2));
+            cgen.code.push(opcode!(BitwiseXor));
+            cgen.code.push(opcode!(Swap2));
+            cgen.code.push(opcode!(Dup1));
+            cgen.code.push(opcode!(BitwiseXor));
+            cgen.code.push(opcode!(Swap1));
+            cgen.code.push(opcode!(Swap2));
+            check!(BitwiseAnd, SGreaterThan(Value::from(0)), IsZero);
+        }
+        (BinaryOp::Minus, true) => {
+            // the difference wrapped if the operands' signs differ and its sign isn't the left's
+            cgen.code.push(opcode!(Dup1));
+            cgen.code.push(opcode!(Dup1));
+            cgen.code.push(opcode!(Sub));
+            cgen.code.push(opcode!(Swap2));
+            cgen.code.push(opcode!(Dup1));
+            cgen.code.push(opcode!(BitwiseXor));
+            cgen.code.push(opcode!(Swap1));
+            cgen.code.push(opcode!(Dup2));
+            cgen.code.push(opcode!(BitwiseXor));
+            check!(BitwiseAnd, SGreaterThan(Value::from(0)), IsZero);
+        }
+        (BinaryOp::Times, true) => {
+            // like uints, except that -1 times the most negative int wraps without dividing wrong
+            let mul_label = cgen.label_gen.next();
+            let divide_label = cgen.label_gen.next();
+            let minus_one = Value::Int(Uint256::max_uint());
+            cgen.code.push(opcode!(Dup0));
+            cgen.code.push(opcode!(IsZero));
+            cgen.code.push(opcode!(Cjump, Value::Label(mul_label)));
+            cgen.code.push(opcode!(Dup0));
+            cgen.code.push(opcode!(Equal, minus_one));
+            cgen.code.push(opcode!(IsZero));
+            cgen.code.push(opcode!(Cjump, Value::Label(divide_label)));
+            check!(Dup1, Equal(Value::Int(Uint256::max_neg_int())), IsZero);
+            cgen.code.push(opcode!(Jump, Value::Label(mul_label)));
+            cgen.code.push(opcode!(@Label(divide_label)));
+            check!(Dup1, Dup1, Dup1, Dup1, Mul, Sdiv, Equal);
+            cgen.code.push(opcode!(@Label(mul_label)));
+            cgen.code.push(opcode!(Mul));
+        }
+        _ => return false,
+    }
+    true
+}
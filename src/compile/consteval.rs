@@ -0,0 +1,157 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides compile-time evaluation of constant expressions, which folds them into single values.
+
+use super::ast::{Type, TypeTree};
+use super::typecheck::{
+    typecheck_binary_op, typecheck_static_assert, typecheck_unary_op, AbstractSyntaxTree,
+    TypeCheckedCodeBlock, TypeCheckedExpr, TypeCheckedExprKind, TypeCheckedFunc, TypeCheckedNode,
+};
+use super::{CompileError, Module};
+use crate::console::Color;
+use crate::link::TupleTree;
+use crate::mavm::Value;
+
+/// Replaces the expressions of a func whose values are known at compile time with constants.
+///
+/// Constants from `const` declarations and the constants table are substituted while parsing,
+/// and typechecking folds operators whose operands are literals. This pass finishes the job,
+/// folding what typechecking can't see is constant: casts, tuples and structs, field accesses,
+/// options, short-circuiting logic, and `if` expressions with constant conditions, along with any
+/// operators these make constant in turn.
+pub fn fold_constants(func: &mut TypeCheckedFunc, type_tree: &TypeTree) {
+    fold_nodes(func.child_nodes(), type_tree);
+}
+
+fn fold_nodes(mut nodes: Vec<TypeCheckedNode>, type_tree: &TypeTree) {
+    for node in &mut nodes {
+        // fold from the bottom up so that parents see their children's constants
+        fold_nodes(node.child_nodes(), type_tree);
+
+        if let TypeCheckedNode::Expression(expr) = node {
+            if let Some(kind) = fold_expr(expr, type_tree) {
+                expr.kind = kind;
+            }
+        }
+    }
+}
+
+/// Folds expr as far as it will go, returning its value if that's known at compile time.
+pub fn evaluate(mut expr: TypeCheckedExpr, type_tree: &TypeTree) -> Option<Value> {
+    fold_nodes(vec![TypeCheckedNode::Expression(&mut expr)], type_tree);
+    constant(&expr).cloned()
+}
+
+/// Checks a module's static asserts, reporting those whose conditions are false or can't be
+/// evaluated at compile time.
+pub(super) fn check_static_asserts(module: &Module, type_tree: &TypeTree) -> Vec<CompileError> {
+    let mut errors = vec![];
+    for assert in &module.static_asserts {
+        let condition = match typecheck_static_assert(assert, &module.string_table, type_tree) {
+            Ok(condition) => condition,
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+        let description = match evaluate(condition, type_tree) {
+            Some(Value::Int(value)) if !value.is_zero() => continue,
+            Some(_) => format!("Static assert failed: {}", Color::red(&assert.message)),
+            None => String::from("static_assert condition can't be evaluated at compile time"),
+        };
+        errors.push(CompileError::new(
+            "Static assert error",
+            description,
+            assert.debug_info.locs(),
+        ));
+    }
+    errors
+}
+
+/// Gets the constant an expression evaluates to, if any.
+fn constant(expr: &TypeCheckedExpr) -> Option<&Value> {
+    match &expr.kind {
+        TypeCheckedExprKind::Const(value, _) => Some(value),
+        _ => None,
+    }
+}
+
+/// Gets the constant a boolean expression evaluates to, if any.
+fn constant_bool(expr: &TypeCheckedExpr) -> Option<bool> {
+    match constant(expr) {
+        Some(Value::Int(value)) => Some(!value.is_zero()),
+        _ => None,
+    }
+}
+
+/// Finds a simpler expression with the same effect as expr, whose children have already been folded.
+fn fold_expr(expr: &TypeCheckedExpr, type_tree: &TypeTree) -> Option<TypeCheckedExprKind> {
+    let loc = expr.debug_info.location;
+    match &expr.kind {
+        // operators that can't be evaluated, like division by zero, are left for runtime
+        TypeCheckedExprKind::UnaryOp(op, sub, _) if constant(sub).is_some() => {
+            typecheck_unary_op(*op, (**sub).clone(), loc, type_tree).ok()
+        }
+        TypeCheckedExprKind::Binary(op, left, right, _)
+            if matches!(constant(left), Some(Value::Int(_)))
+                && matches!(constant(right), Some(Value::Int(_))) =>
+        {
+            typecheck_binary_op(*op, (**left).clone(), (**right).clone(), type_tree, loc).ok()
+        }
+        TypeCheckedExprKind::ShortcutOr(left, right) => {
+            match (constant_bool(left), constant_bool(right)) {
+                (Some(true), _) | (None, Some(false)) => Some(left.kind.clone()),
+                (Some(false), _) => Some(right.kind.clone()),
+                _ => None,
+            }
+        }
+        TypeCheckedExprKind::ShortcutAnd(left, right) => {
+            match (constant_bool(left), constant_bool(right)) {
+                (Some(false), _) | (None, Some(true)) => Some(left.kind.clone()),
+                (Some(true), _) => Some(right.kind.clone()),
+                _ => None,
+            }
+        }
+        TypeCheckedExprKind::Cast(inner, tipe) => {
+            constant(inner).map(|value| TypeCheckedExprKind::Const(value.clone(), tipe.clone()))
+        }
+        TypeCheckedExprKind::Variant(inner) => constant(inner).map(|value| {
+            TypeCheckedExprKind::Const(
+                Value::new_tuple(vec![Value::from(1), value.clone()]),
+                Type::Option(Box::new(inner.get_type())),
+            )
+        }),
+        // tuples are laid out the same way the linker lays out those built at runtime
+        TypeCheckedExprKind::Tuple(fields, tipe) => fields
+            .iter()
+            .map(|field| constant(field).cloned())
+            .collect::<Option<Vec<_>>>()
+            .map(|values| {
+                TypeCheckedExprKind::Const(TupleTree::fold_into_tuple(values), tipe.clone())
+            }),
+        TypeCheckedExprKind::TupleRef(inner, offset, width, tipe) => {
+            let mut value = constant(inner)?;
+            for slot in TupleTree::new(*width, false).slot_path(*offset)? {
+                value = match value {
+                    Value::Tuple(fields) => fields.get(slot)?,
+                    _ => return None,
+                };
+            }
+            Some(TypeCheckedExprKind::Const(value.clone(), tipe.clone()))
+        }
+        TypeCheckedExprKind::If(cond, block, else_block, _) => {
+            constant_bool(cond).map(|cond| match (cond, else_block) {
+                (true, _) => TypeCheckedExprKind::CodeBlock(block.clone()),
+                (false, Some(else_block)) => TypeCheckedExprKind::CodeBlock(else_block.clone()),
+                (false, None) => TypeCheckedExprKind::CodeBlock(TypeCheckedCodeBlock {
+                    body: vec![],
+                    ret_expr: None,
+                    scope: None,
+                }),
+            })
+        }
+        _ => None,
+    }
+}
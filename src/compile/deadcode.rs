@@ -0,0 +1,98 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides dead code elimination, which drops the private funcs and globals nothing can reach.
+
+use super::typecheck::{
+    AbstractSyntaxTree, TypeCheckedExprKind, TypeCheckedNode, TypeCheckedStatementKind,
+};
+use super::{CompileError, ErrorSystem, Lint, TypeCheckedModule};
+use crate::console::Color;
+use crate::stringtable::StringId;
+use std::collections::HashSet;
+
+/// Drops the funcs of a module that can't be reached from its entry points, along with the
+/// globals none of the remaining funcs refer to. Entry points are the funcs other modules might
+/// call: main, public funcs, and the methods of impls, along with the unit tests `mini test` runs
+/// and the overrides the linker sends other modules' calls to.
+/// Funcs and globals whose names start with an underscore are marked as intentionally unused and
+/// so are always kept.
+///
+/// Since dropped funcs never make it to the linker, the warnings it would give for them being
+/// unreachable are given here instead.
+pub fn eliminate_dead_code(module: &mut TypeCheckedModule, error_system: &mut ErrorSystem) {
+    let mut queue: Vec<StringId> = module
+        .checked_funcs
+        .iter()
+        .filter(|(_, func)| {
+            func.public
+                || func.receiver.is_some()
+                || func.name == "main"
+                || func.name.starts_with('_')
+                || func
+                    .attributes
+                    .iter()
+                    .any(|attribute| ["test", "override"].contains(&attribute.name.as_str()))
+        })
+        .map(|(id, _)| *id)
+        .collect();
+
+    let mut reached = HashSet::new();
+    let mut globals = HashSet::new();
+    while let Some(id) = queue.pop() {
+        if !reached.insert(id) {
+            continue;
+        }
+        // references to funcs of other modules aren't in this one and so aren't followed
+        if let Some(func) = module.checked_funcs.get_mut(&id) {
+            find_references(func.child_nodes(), &mut queue, &mut globals);
+        }
+    }
+
+    let library = ["core", "std", "std2"].contains(&module.path[0].as_str());
+    for (id, func) in &module.checked_funcs {
+        if !reached.contains(id) && !func.properties.closure && !library {
+            error_system.warnings.push(CompileError::new_warning(
+                Lint::DeadCode,
+                String::from("Compile warning"),
+                format!(
+                    "func {} is unreachable",
+                    Color::color(error_system.warn_color, &func.name)
+                ),
+                func.debug_info.locs(),
+            ));
+        }
+    }
+
+    module.checked_funcs.retain(|id, _| reached.contains(id));
+    module
+        .global_vars
+        .retain(|global| globals.contains(&global.id) || global.name.starts_with('_'));
+}
+
+/// Finds the funcs, closures, and globals referred to within nodes.
+fn find_references(
+    mut nodes: Vec<TypeCheckedNode>,
+    funcs: &mut Vec<StringId>,
+    globals: &mut HashSet<StringId>,
+) {
+    for node in &mut nodes {
+        match node {
+            TypeCheckedNode::Expression(expr) => match &expr.kind {
+                TypeCheckedExprKind::FuncRef(id, ..) | TypeCheckedExprKind::ClosureLoad(id, ..) => {
+                    funcs.push(*id)
+                }
+                TypeCheckedExprKind::GlobalVariableRef(id, _) => drop(globals.insert(*id)),
+                _ => {}
+            },
+            TypeCheckedNode::Statement(stat) => {
+                if let TypeCheckedStatementKind::AssignGlobal(id, _) = &stat.kind {
+                    globals.insert(*id);
+                }
+            }
+            TypeCheckedNode::Type(_) => {}
+        }
+        find_references(node.child_nodes(), funcs, globals);
+    }
+}
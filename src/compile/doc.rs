@@ -0,0 +1,334 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides API documentation for the constants, types, and funcs of mini programs.
+
+use super::ast::{Type, TypeTree};
+use super::{
+    create_program_tree, create_type_tree, locate_main, resolve_imports, CompileError, ErrorSystem,
+    FileInfo, Manifest, Module,
+};
+use crate::console::Color;
+use crate::stringtable::StringId;
+use clap::Clap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Command line options for doc subcommand.
+///
+/// Documents the constants, types, and public funcs of each module in the input's folder as
+/// markdown or, with `--format html`, as a single html page. With `--all`, the core, std, and std2
+/// modules the program uses are documented too.
+#[derive(Clap, Debug, Default)]
+pub struct DocStruct {
+    pub input: String,
+    #[clap(short, long)]
+    pub output: Option<String>,
+    #[clap(short, long)]
+    pub format: Option<String>,
+    #[clap(short, long)]
+    pub consts_file: Option<String>,
+    #[clap(short, long)]
+    pub all: bool,
+}
+
+/// A documented declaration.
+struct Item {
+    name: String,
+    /// How the item is declared, written as mini source
+    signature: String,
+    docs: Option<String>,
+}
+
+/// The documented declarations of a module, grouped by kind.
+struct ModuleDocs {
+    name: String,
+    sections: Vec<(&'static str, Vec<Item>)>,
+}
+
+impl DocStruct {
+    /// Generates the documentation, or the errors encountered parsing the program.
+    pub fn invoke(&self) -> Result<String, ErrorSystem> {
+        let mut error_system = ErrorSystem {
+            errors: vec![],
+            warnings: vec![],
+            warnings_are_errors: false,
+            warn_color: Color::YELLOW,
+            file_info_chart: BTreeMap::new(),
+            lint_levels: BTreeMap::new(),
+        };
+        let mut file_info_chart = BTreeMap::new();
+
+        let docs = self.document(&mut file_info_chart, &mut error_system);
+        error_system.file_info_chart = file_info_chart;
+        match docs {
+            Ok(docs) if error_system.errors.is_empty() => Ok(docs),
+            Ok(_) => Err(error_system),
+            Err(err) => {
+                error_system.errors.push(err);
+                Err(error_system)
+            }
+        }
+    }
+
+    fn document(
+        &self,
+        file_info_chart: &mut BTreeMap<u64, FileInfo>,
+        error_system: &mut ErrorSystem,
+    ) -> Result<String, CompileError> {
+        let render = match self.format.as_deref() {
+            None | Some("markdown") => markdown,
+            Some("html") => html,
+            Some(other) => {
+                return Err(CompileError::new(
+                    "Doc error",
+                    format!(
+                        "Unknown format {}, expected markdown or html",
+                        Color::red(other)
+                    ),
+                    vec![],
+                ))
+            }
+        };
+
+        let (folder, library, main) = locate_main(Path::new(&self.input))?;
+        let constants_default = folder.join("constants.json");
+        let constants_path = match &self.consts_file {
+            Some(path) => Some(Path::new(path)),
+            None => match constants_default.exists() {
+                true => Some(constants_default.as_path()),
+                false => None,
+            },
+        };
+
+        let (mut programs, mut import_map) = create_program_tree(
+            folder,
+            library,
+            main,
+            file_info_chart,
+            constants_path,
+            &BTreeSet::new(),
+            error_system,
+            true,
+            &Manifest::load(folder)?,
+        )?;
+        resolve_imports(&mut programs, &mut import_map, error_system)?;
+        let mut type_tree = create_type_tree(&programs);
+
+        // modules of the input's folder have paths without a library prefix, unless the folder
+        // is itself a library
+        let mut modules: Vec<_> = programs
+            .values()
+            .filter(|module| {
+                self.all || module.path.len() == 1 || library == Some(module.path[0].as_str())
+            })
+            .collect();
+        modules.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let docs: Vec<_> = modules
+            .into_iter()
+            .map(|module| document_module(module, &mut type_tree))
+            .filter(|docs| !docs.sections.is_empty())
+            .collect();
+        Ok(render(&docs))
+    }
+}
+
+fn document_module(module: &Module, type_tree: &mut TypeTree) -> ModuleDocs {
+    let names = &module.string_table;
+
+    let constants: Vec<_> = module
+        .const_decls
+        .iter()
+        .map(|constant| Item {
+            name: constant.name.clone(),
+            signature: format!("const {} = {};", constant.name, constant.value),
+            docs: constant.docs.clone(),
+        })
+        .collect();
+
+    let types: Vec<_> = module
+        .type_decls
+        .iter()
+        .map(|decl| {
+            let tipe = name_generics(&decl.tipe, &decl.generics, module, type_tree);
+            let generics: Vec<_> = decl
+                .generics
+                .iter()
+                .map(|id| names.name_from_id(*id).clone())
+                .collect();
+            Item {
+                name: names.name_from_id(decl.name).clone(),
+                signature: format!(
+                    "type {}{} = {};",
+                    names.name_from_id(decl.name),
+                    generic_list(generics),
+                    tipe.print(type_tree),
+                ),
+                docs: decl.docs.clone(),
+            }
+        })
+        .collect();
+
+    // methods are documented by the interfaces they implement
+    let funcs: Vec<_> = module
+        .funcs
+        .iter()
+        .filter(|func| func.public && func.interface.is_none())
+        .map(|func| {
+            let generics: Vec<_> = func
+                .generics
+                .iter()
+                .zip(&func.generic_bounds)
+                .map(|(id, bound)| match bound {
+                    Some(bound) => format!(
+                        "{}: {}",
+                        names.name_from_id(*id),
+                        names.name_from_id(*bound)
+                    ),
+                    None => names.name_from_id(*id).clone(),
+                })
+                .collect();
+            let args: Vec<_> = func
+                .args
+                .iter()
+                .map(|arg| {
+                    let tipe = name_generics(&arg.tipe, &func.generics, module, type_tree);
+                    format!(
+                        "{}: {}",
+                        names.name_from_id(arg.name),
+                        tipe.print(type_tree)
+                    )
+                })
+                .collect();
+            let ret_type = name_generics(&func.ret_type, &func.generics, module, type_tree);
+
+            let mut signature = String::from("public ");
+            if func.properties.view {
+                signature.push_str("view ");
+            }
+            if func.properties.write {
+                signature.push_str("write ");
+            }
+            signature.push_str(&format!(
+                "func {}{}({})",
+                func.name,
+                generic_list(generics),
+                args.join(", "),
+            ));
+            if ret_type != Type::Void {
+                signature.push_str(&format!(" -> {}", ret_type.print(type_tree).trim_end()));
+            }
+            Item {
+                name: func.name.clone(),
+                signature,
+                docs: func.docs.clone(),
+            }
+        })
+        .collect();
+
+    ModuleDocs {
+        name: module.path.join("::"),
+        sections: vec![("Constants", constants), ("Types", types), ("Funcs", funcs)]
+            .into_iter()
+            .filter(|(_, items)| !items.is_empty())
+            .collect(),
+    }
+}
+
+/// Undoes the generalization of a declaration's type, so that its generic parameters print with
+/// the names they were declared with.
+fn name_generics(
+    tipe: &Type,
+    generics: &[StringId],
+    module: &Module,
+    type_tree: &mut TypeTree,
+) -> Type {
+    for (slot, id) in generics.iter().enumerate() {
+        type_tree
+            .entry((module.path.clone(), *id))
+            .or_insert_with(|| {
+                (
+                    Type::GenericSlot(slot),
+                    module.string_table.name_from_id(*id).clone(),
+                )
+            });
+    }
+    let mut tipe = tipe.clone();
+    tipe.replace(&mut |tipe| {
+        if let Type::GenericSlot(slot) = tipe {
+            if let Some(id) = generics.get(*slot) {
+                *tipe = Type::Nominal(module.path.clone(), *id, vec![]);
+            }
+        }
+    });
+    tipe
+}
+
+fn generic_list(generics: Vec<String>) -> String {
+    match generics.is_empty() {
+        true => String::new(),
+        false => format!("<{}>", generics.join(", ")),
+    }
+}
+
+fn markdown(docs: &[ModuleDocs]) -> String {
+    let mut out = String::new();
+    for module in docs {
+        out.push_str(&format!("# {}\n\n", module.name));
+        for (section, items) in &module.sections {
+            out.push_str(&format!("## {}\n\n", section));
+            for item in items {
+                out.push_str(&format!("### {}\n\n", item.name));
+                out.push_str(&format!("```\n{}\n```\n\n", item.signature));
+                if let Some(docs) = &item.docs {
+                    out.push_str(&format!("{}\n\n", docs));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn html(docs: &[ModuleDocs]) -> String {
+    let escape = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+
+    let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<title>API documentation</title>\n</head>\n<body>\n<ul>\n");
+    for module in docs {
+        let name = escape(&module.name);
+        out.push_str(&format!("<li><a href=\"#{0}\">{0}</a></li>\n", name));
+    }
+    out.push_str("</ul>\n");
+
+    for module in docs {
+        let name = escape(&module.name);
+        out.push_str(&format!("<h1 id=\"{0}\">{0}</h1>\n", name));
+        for (section, items) in &module.sections {
+            out.push_str(&format!("<h2>{}</h2>\n", section));
+            for item in items {
+                out.push_str(&format!(
+                    "<h3 id=\"{}::{}\">{}</h3>\n",
+                    name,
+                    escape(&item.name),
+                    escape(&item.name),
+                ));
+                out.push_str(&format!(
+                    "<pre><code>{}</code></pre>\n",
+                    escape(&item.signature)
+                ));
+                // paragraphs are separated by blank lines, as in markdown
+                for paragraph in item.docs.iter().flat_map(|docs| docs.split("\n\n")) {
+                    out.push_str(&format!("<p>{}</p>\n", escape(paragraph.trim())));
+                }
+            }
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
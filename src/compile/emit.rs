@@ -0,0 +1,158 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the intermediate representations `compile --emit` can write in place of a mexe, so
+//! that a miscompile can be traced to the stage that introduced it.
+
+use super::{path_display, CompileError, CompiledFunc, Module, TypeCheckedModule};
+use crate::console::Color;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+/// A representation of the program to write as the output of a compile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Emit {
+    /// The parsed modules, once their imports and impls have been resolved
+    Ast,
+    /// The typechecked modules, as codegen receives them
+    TypedAst,
+    /// The code generated for each func, before linking
+    Mavm,
+    /// The linked program
+    Mexe,
+}
+
+impl Emit {
+    pub const ALL: [Emit; 4] = [Emit::Ast, Emit::TypedAst, Emit::Mavm, Emit::Mexe];
+
+    /// The name of the representation, as given on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Emit::Ast => "ast",
+            Emit::TypedAst => "typed-ast",
+            Emit::Mavm => "mavm",
+            Emit::Mexe => "mexe",
+        }
+    }
+}
+
+impl Default for Emit {
+    fn default() -> Self {
+        Emit::Mexe
+    }
+}
+
+impl fmt::Display for Emit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Emit {
+    type Err = CompileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase().replace('_', "-");
+        Emit::ALL
+            .iter()
+            .find(|emit| emit.name() == name)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<_> = Emit::ALL.iter().map(|emit| emit.name()).collect();
+                CompileError::new(
+                    String::from("Emit error"),
+                    format!(
+                        "Unknown representation {}, expected one of {}",
+                        Color::red(s),
+                        names.join(", ")
+                    ),
+                    vec![],
+                )
+            })
+    }
+}
+
+/// Collects the text of the representation being emitted as compilation reaches it. Modules and
+/// funcs are written sorted by path and name, so that the text doesn't depend on the order the
+/// compiler happened to hold them in.
+#[derive(Debug)]
+pub struct Emitter {
+    emit: Emit,
+    text: String,
+}
+
+impl Emitter {
+    pub fn new(emit: Emit) -> Self {
+        Emitter {
+            emit,
+            text: String::new(),
+        }
+    }
+
+    pub fn into_text(self) -> String {
+        self.text
+    }
+
+    /// Writes the parsed modules, if they're what's being emitted.
+    pub(super) fn ast(&mut self, modules: &BTreeMap<Vec<String>, Module>) {
+        if self.emit != Emit::Ast {
+            return;
+        }
+        for module in modules.values() {
+            let mut funcs: Vec<_> = module.funcs.iter().collect();
+            funcs.sort_by(|a, b| a.name.cmp(&b.name));
+            self.section("module", &module.path);
+            self.items(&module.type_decls);
+            self.items(&module.global_vars);
+            self.items(&funcs);
+        }
+    }
+
+    /// Writes the typechecked modules, if they're what's being emitted.
+    pub(super) fn typed_ast(&mut self, modules: &[TypeCheckedModule]) {
+        if self.emit != Emit::TypedAst {
+            return;
+        }
+        let mut modules: Vec<_> = modules.iter().collect();
+        modules.sort_by(|a, b| a.path.cmp(&b.path));
+        for module in modules {
+            let mut funcs: Vec<_> = module.checked_funcs.values().collect();
+            funcs.sort_by(|a, b| a.name.cmp(&b.name));
+            self.section("module", &module.path);
+            self.items(&module.global_vars);
+            self.items(&funcs);
+        }
+    }
+
+    /// Writes the code generated for each func, if it's what's being emitted.
+    pub(super) fn mavm(&mut self, funcs: &[CompiledFunc]) {
+        if self.emit != Emit::Mavm {
+            return;
+        }
+        let mut funcs: Vec<_> = funcs.iter().collect();
+        funcs.sort_by(|a, b| (&a.path, &a.name).cmp(&(&b.path, &b.name)));
+        for func in funcs {
+            let mut path = func.path.clone();
+            path.push(func.name.clone());
+            self.section("func", &path);
+            for (index, insn) in func.code.iter().enumerate() {
+                writeln!(self.text, "{:05}:  {}", index, insn).unwrap();
+            }
+        }
+    }
+
+    fn section(&mut self, kind: &str, path: &Vec<String>) {
+        if !self.text.is_empty() {
+            self.text.push('\n');
+        }
+        writeln!(self.text, "// {} {}", kind, path_display(path)).unwrap();
+    }
+
+    fn items<T: fmt::Debug>(&mut self, items: &[T]) {
+        for item in items {
+            writeln!(self.text, "{:#?}", item).unwrap();
+        }
+    }
+}
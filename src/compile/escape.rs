@@ -0,0 +1,192 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides an escape analysis that finds the tuples and structs a func builds only to read and
+//! write their fields, so that each field can be kept in a local slot of its own instead.
+
+use super::ast::{FuncArg, Pattern};
+use super::typecheck::{
+    AbstractSyntaxTree, TypeCheckedExpr, TypeCheckedExprKind, TypeCheckedFunc, TypeCheckedNode,
+    TypeCheckedStatementKind,
+};
+use crate::stringtable::{StringId, StringTable};
+use std::collections::BTreeMap;
+
+/// How a func refers to one of its locals.
+#[derive(Default)]
+struct Uses {
+    /// The number of places the local is bound, whether by `let`, args, or patterns
+    binds: usize,
+    /// The width of the tuple the local is bound to, if it's bound to one being built
+    width: Option<usize>,
+    /// The number of times the local's value is referred to
+    refs: usize,
+    /// The number of those references that read or write a single field
+    field_refs: usize,
+    /// Whether the value is used in a way that needs it whole
+    escapes: bool,
+}
+
+/// Replaces the locals of a func that are bound to a tuple or struct being built, and that
+/// otherwise only have their fields read and written, with a local for each field.
+///
+/// A local escapes when it's passed, returned, copied, captured by a closure, matched, or
+/// reassigned whole, since each of these needs the tuple to exist. Locals bound more than once,
+/// even in separate scopes, are left alone, and nested tuples only lose their outer level. The
+/// tuple a local is bound to is unpacked as it's built, so no tuple is ever made.
+pub fn scalarize_tuples(func: &mut TypeCheckedFunc, string_table: &mut StringTable) {
+    let mut uses: BTreeMap<StringId, Uses> = BTreeMap::new();
+    for FuncArg { name, .. } in &func.args {
+        uses.entry(*name).or_default().binds += 1;
+    }
+    find_uses(func.child_nodes(), &mut uses);
+
+    let fields: BTreeMap<StringId, Vec<StringId>> = uses
+        .into_iter()
+        .filter(|(_, uses)| uses.binds == 1 && !uses.escapes && uses.refs == uses.field_refs)
+        .filter_map(|(id, uses)| {
+            let name = string_table.name_from_id(id).clone();
+            // a tuple of one field is unpacked by reading that field, so it's left alone
+            let width = uses.width.filter(|width| *width > 1)?;
+            let fields = (0..width)
+                .map(|index| string_table.get(format!("{}.{}", name, index)))
+                .collect();
+            Some((id, fields))
+        })
+        .collect();
+
+    if !fields.is_empty() {
+        scalarize(func.child_nodes(), &fields);
+    }
+}
+
+fn find_uses(mut nodes: Vec<TypeCheckedNode>, uses: &mut BTreeMap<StringId, Uses>) {
+    for node in &mut nodes {
+        match node {
+            TypeCheckedNode::Statement(stat) => {
+                if let TypeCheckedStatementKind::SetLocals(assigned, expr) = &stat.kind {
+                    let single = assigned.len() == 1;
+                    for assign in assigned {
+                        let local = uses.entry(assign.id).or_default();
+                        match (assign.shadow, &expr.kind) {
+                            (true, TypeCheckedExprKind::Tuple(fields, _)) if single => {
+                                local.binds += 1;
+                                local.width = Some(fields.len());
+                            }
+                            (true, _) => local.binds += 1,
+                            (false, TypeCheckedExprKind::StructMod(struc, ..))
+                                if single && local_ref(struc) == Some(assign.id) =>
+                            {
+                                local.field_refs += 1;
+                            }
+                            (false, _) => local.escapes = true,
+                        }
+                    }
+                }
+            }
+            TypeCheckedNode::Expression(expr) => match &expr.kind {
+                TypeCheckedExprKind::LocalVariableRef(id, _) => {
+                    uses.entry(*id).or_default().refs += 1;
+                }
+                TypeCheckedExprKind::TupleRef(tuple, ..) => {
+                    if let Some(id) = local_ref(tuple) {
+                        uses.entry(id).or_default().field_refs += 1;
+                    }
+                }
+                TypeCheckedExprKind::ClosureLoad(_, captures, _) => {
+                    for id in captures {
+                        uses.entry(*id).or_default().escapes = true;
+                    }
+                }
+                TypeCheckedExprKind::IfLet(id, ..) => {
+                    uses.entry(*id).or_default().binds += 1;
+                }
+                TypeCheckedExprKind::Match(_, arms, ..) => {
+                    for arm in arms {
+                        pattern_binds(&arm.pattern, uses);
+                    }
+                }
+                _ => {}
+            },
+            TypeCheckedNode::Type(_) => {}
+        }
+        find_uses(node.child_nodes(), uses);
+    }
+}
+
+fn pattern_binds(pattern: &Pattern, uses: &mut BTreeMap<StringId, Uses>) {
+    match pattern {
+        Pattern::Bind(id) => uses.entry(*id).or_default().binds += 1,
+        Pattern::Some(inner) => pattern_binds(inner, uses),
+        Pattern::Tuple(inner) | Pattern::Variant(_, _, inner) => {
+            for pattern in inner {
+                pattern_binds(pattern, uses);
+            }
+        }
+        Pattern::Wildcard | Pattern::Const(_) | Pattern::None => {}
+    }
+}
+
+/// The local an expression refers to, if it's just a reference to one.
+fn local_ref(expr: &TypeCheckedExpr) -> Option<StringId> {
+    match &expr.kind {
+        TypeCheckedExprKind::LocalVariableRef(id, _) => Some(*id),
+        _ => None,
+    }
+}
+
+fn scalarize(mut nodes: Vec<TypeCheckedNode>, fields: &BTreeMap<StringId, Vec<StringId>>) {
+    for node in &mut nodes {
+        match node {
+            TypeCheckedNode::Statement(stat) => {
+                if let TypeCheckedStatementKind::SetLocals(assigned, expr) = &mut stat.kind {
+                    let local = match assigned.as_slice() {
+                        [assign] => fields.get(&assign.id).map(|ids| (assign.clone(), ids)),
+                        _ => None,
+                    };
+                    match local {
+                        Some((assign, fields)) if assign.shadow => {
+                            *assigned = fields
+                                .iter()
+                                .map(|id| {
+                                    let mut field = assign.clone();
+                                    field.id = *id;
+                                    field
+                                })
+                                .collect();
+                        }
+                        Some((mut assign, fields)) => {
+                            let placeholder =
+                                TypeCheckedExpr::new(TypeCheckedExprKind::Error, expr.debug_info);
+                            match std::mem::replace(expr, placeholder).kind {
+                                TypeCheckedExprKind::StructMod(_, slot, _, item, _) => {
+                                    assign.id = fields[slot];
+                                    *assigned = vec![assign];
+                                    *expr = *item;
+                                }
+                                _ => unreachable!("a local written whole was scalarized"),
+                            }
+                        }
+                        None => {}
+                    }
+                }
+            }
+            TypeCheckedNode::Expression(expr) => {
+                let field = match &expr.kind {
+                    TypeCheckedExprKind::TupleRef(tuple, offset, _, tipe) => {
+                        local_ref(tuple).and_then(|id| fields.get(&id)).map(|ids| {
+                            TypeCheckedExprKind::LocalVariableRef(ids[*offset], tipe.clone())
+                        })
+                    }
+                    _ => None,
+                };
+                if let Some(field) = field {
+                    expr.kind = field;
+                }
+            }
+            TypeCheckedNode::Type(_) => {}
+        }
+        scalarize(node.child_nodes(), fields);
+    }
+}
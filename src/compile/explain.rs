@@ -0,0 +1,189 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the codes common compile errors are given, and the longer explanations of each that
+//! `mini explain` prints.
+
+use super::CompileError;
+use crate::console::Color;
+use clap::Clap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Command line options for explain subcommand.
+///
+/// Prints the explanation of an error code, such as `E0004`, with an example of code causing the
+/// error and how it's usually fixed. Without a code, lists every code along with what it means.
+#[derive(Clap, Debug, Default)]
+pub struct ExplainStruct {
+    pub code: Option<String>,
+}
+
+impl ExplainStruct {
+    /// Gets the explanation of the code asked for, or the list of codes if none was.
+    pub fn invoke(&self) -> Result<String, CompileError> {
+        match &self.code {
+            Some(code) => Ok(code.parse::<ErrorCode>()?.explanation().to_string()),
+            None => Ok(ErrorCode::ALL
+                .iter()
+                .map(|code| format!("{}: {}\n", code, code.summary()))
+                .collect()),
+        }
+    }
+}
+
+/// A kind of compile error, given a stable code so that its explanation can be looked up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ErrorCode {
+    /// Tokens the grammar doesn't allow where they're found
+    UnexpectedToken,
+    /// Use and const declarations that come after others
+    MisplacedDeclaration,
+    /// Use statements naming modules or symbols that can't be found
+    UnresolvedImport,
+    /// Names that aren't in scope
+    UnknownIdentifier,
+    /// Assignments to variables never declared
+    UndeclaredAssignment,
+    /// Assignments of values of another type than the variable's
+    MismatchedAssignment,
+    /// Returns of values of another type than the func's
+    MismatchedReturn,
+    /// Funcs that can reach their end without returning a value
+    MissingReturn,
+    /// Calls with the wrong number or types of args
+    MismatchedArgs,
+    /// Fields a value's type doesn't have
+    UnknownField,
+    /// Funcs and methods used outside the modules they're private to
+    PrivateItem,
+    /// Funcs that are view or write without being declared so
+    UndeclaredPurity,
+    /// Divisions of constants by zero
+    DivideByZero,
+    /// Uses of `?` in funcs that don't return options
+    TryWithoutOption,
+    /// Impls that leave out a method of their interface
+    MissingMethod,
+    /// Locals named after globals and other top-level symbols
+    GlobalShadowing,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 16] = [
+        ErrorCode::UnexpectedToken,
+        ErrorCode::MisplacedDeclaration,
+        ErrorCode::UnresolvedImport,
+        ErrorCode::UnknownIdentifier,
+        ErrorCode::UndeclaredAssignment,
+        ErrorCode::MismatchedAssignment,
+        ErrorCode::MismatchedReturn,
+        ErrorCode::MissingReturn,
+        ErrorCode::MismatchedArgs,
+        ErrorCode::UnknownField,
+        ErrorCode::PrivateItem,
+        ErrorCode::UndeclaredPurity,
+        ErrorCode::DivideByZero,
+        ErrorCode::TryWithoutOption,
+        ErrorCode::MissingMethod,
+        ErrorCode::GlobalShadowing,
+    ];
+
+    /// The code, as printed with errors. Codes are never reused, so they stay the same across
+    /// compiler versions.
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorCode::UnexpectedToken => "E0001",
+            ErrorCode::MisplacedDeclaration => "E0002",
+            ErrorCode::UnresolvedImport => "E0003",
+            ErrorCode::UnknownIdentifier => "E0004",
+            ErrorCode::UndeclaredAssignment => "E0005",
+            ErrorCode::MismatchedAssignment => "E0006",
+            ErrorCode::MismatchedReturn => "E0007",
+            ErrorCode::MissingReturn => "E0008",
+            ErrorCode::MismatchedArgs => "E0009",
+            ErrorCode::UnknownField => "E0010",
+            ErrorCode::PrivateItem => "E0011",
+            ErrorCode::UndeclaredPurity => "E0012",
+            ErrorCode::DivideByZero => "E0013",
+            ErrorCode::TryWithoutOption => "E0014",
+            ErrorCode::MissingMethod => "E0015",
+            ErrorCode::GlobalShadowing => "E0016",
+        }
+    }
+
+    /// The first line of the code's explanation, which says what the error means.
+    pub fn summary(self) -> &'static str {
+        self.explanation().lines().next().unwrap_or("")
+    }
+
+    /// What the error means, with an example of code causing it and how it's usually fixed.
+    pub fn explanation(self) -> &'static str {
+        match self {
+            ErrorCode::UnexpectedToken => include_str!("explain/E0001.md"),
+            ErrorCode::MisplacedDeclaration => include_str!("explain/E0002.md"),
+            ErrorCode::UnresolvedImport => include_str!("explain/E0003.md"),
+            ErrorCode::UnknownIdentifier => include_str!("explain/E0004.md"),
+            ErrorCode::UndeclaredAssignment => include_str!("explain/E0005.md"),
+            ErrorCode::MismatchedAssignment => include_str!("explain/E0006.md"),
+            ErrorCode::MismatchedReturn => include_str!("explain/E0007.md"),
+            ErrorCode::MissingReturn => include_str!("explain/E0008.md"),
+            ErrorCode::MismatchedArgs => include_str!("explain/E0009.md"),
+            ErrorCode::UnknownField => include_str!("explain/E0010.md"),
+            ErrorCode::PrivateItem => include_str!("explain/E0011.md"),
+            ErrorCode::UndeclaredPurity => include_str!("explain/E0012.md"),
+            ErrorCode::DivideByZero => include_str!("explain/E0013.md"),
+            ErrorCode::TryWithoutOption => include_str!("explain/E0014.md"),
+            ErrorCode::MissingMethod => include_str!("explain/E0015.md"),
+            ErrorCode::GlobalShadowing => include_str!("explain/E0016.md"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = CompileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.to_uppercase();
+        ErrorCode::ALL
+            .iter()
+            .find(|known| known.code() == code)
+            .copied()
+            .ok_or_else(|| {
+                CompileError::new(
+                    String::from("Explain error"),
+                    format!(
+                        "Unknown error code {}, expected one of E0001 through {}",
+                        Color::red(s),
+                        ErrorCode::ALL[ErrorCode::ALL.len() - 1],
+                    ),
+                    vec![],
+                )
+            })
+    }
+}
+
+#[test]
+fn test_error_codes() {
+    let codes: std::collections::BTreeSet<_> =
+        ErrorCode::ALL.iter().map(|code| code.code()).collect();
+    assert_eq!(codes.len(), ErrorCode::ALL.len());
+
+    for code in &ErrorCode::ALL {
+        assert_eq!(code.code().parse::<ErrorCode>().unwrap(), *code);
+        assert!(code.explanation().contains("```mini"));
+    }
+    assert_eq!(
+        "e0004".parse::<ErrorCode>().unwrap(),
+        ErrorCode::UnknownIdentifier
+    );
+    assert!("E9999".parse::<ErrorCode>().is_err());
+}
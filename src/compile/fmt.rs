@@ -0,0 +1,406 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Formats mini source in a canonical style.
+//!
+//! The formatter works on the tokens of a file rather than its AST, so that comments survive, and
+//! only ever changes the whitespace between tokens. Lines are kept where the author broke them,
+//! but are indented by four spaces for each bracket they're nested in, plus four more when they
+//! continue the expression of the line before. Runs of blank lines are collapsed to one, and blank
+//! lines at the start and end of a block are dropped. Within a line, spaces are normalized around
+//! punctuation whose spacing is unambiguous, while tokens like `<` and `-`, which may be binary
+//! or not, keep whether they had a space. Since a formatted file lexes to the same tokens as the
+//! original, it parses to the same AST.
+
+use super::macros::{tokenize, Kind};
+use super::CompileError;
+use clap::Clap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Command line options for fmt subcommand.
+///
+/// Formats the given `.mini` files, and the `.mini` files in the given folders, in place. With
+/// `--check`, the files are left alone, and the command fails if any isn't already formatted.
+#[derive(Clap, Debug, Default)]
+pub struct FmtStruct {
+    pub input: Vec<String>,
+    #[clap(long)]
+    pub check: bool,
+}
+
+/// The tokens the parser reads as one, longest first.
+const COMPOUNDS: &[&str] = &[
+    "#![", "!=", "#[", "&&", "->", "::", "<<", "<=", "==", "=>", ">=", ">>", "||",
+];
+
+/// Binary operators that are always surrounded by spaces.
+const BINARY: &[&str] = &[
+    "=", "==", "!=", "<=", ">=", "&&", "||", "=>", "->", "<<", ">>", "+", "/", "%", "^",
+];
+
+/// Keywords followed by a space when they precede a parenthesis.
+const SPACED_KEYWORDS: &[&str] = &[
+    "if", "while", "return", "match", "xif", "xloop", "in", "let", "set", "else",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AtomKind {
+    Word,
+    Punct,
+    Comment,
+}
+
+/// A token, with compound punctuation merged, along with the whitespace that preceded it.
+#[derive(Clone, Debug)]
+struct Atom {
+    kind: AtomKind,
+    text: String,
+    newlines_before: usize,
+    space_before: bool,
+}
+
+impl Atom {
+    fn is(&self, text: &str) -> bool {
+        self.kind != AtomKind::Comment && self.text == text
+    }
+
+    fn is_opener(&self) -> bool {
+        self.is("(") || self.is("[") || self.is("{") || self.is("#[") || self.is("#![")
+    }
+
+    fn is_closer(&self) -> bool {
+        self.is(")") || self.is("]") || self.is("}")
+    }
+}
+
+impl FmtStruct {
+    /// Formats the input, returning the files that were changed, or with `--check`, the files
+    /// that aren't formatted.
+    pub fn invoke(&self) -> Result<Vec<PathBuf>, CompileError> {
+        let mut files = vec![];
+        for input in &self.input {
+            collect_files(Path::new(input), &mut files)?;
+        }
+        let mut changed = vec![];
+        for file in files {
+            let source = fs::read_to_string(&file).map_err(|_| {
+                CompileError::new(
+                    String::from("Format error"),
+                    format!("Could not read \"{}\"", file.display()),
+                    vec![],
+                )
+            })?;
+            let formatted = format_source(&source).map_err(|description| {
+                CompileError::new(
+                    String::from("Format error"),
+                    format!("{} in \"{}\"", description, file.display()),
+                    vec![],
+                )
+            })?;
+            if formatted == source {
+                continue;
+            }
+            if !self.check {
+                fs::write(&file, formatted).map_err(|_| {
+                    CompileError::new(
+                        String::from("Format error"),
+                        format!("Could not write \"{}\"", file.display()),
+                        vec![],
+                    )
+                })?;
+            }
+            changed.push(file);
+        }
+        Ok(changed)
+    }
+}
+
+/// Adds path to files if it's a file, or the `.mini` files within it, in order, if it's a folder.
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), CompileError> {
+    if !path.is_dir() {
+        match path.is_file() {
+            true => files.push(path.to_path_buf()),
+            false => {
+                return Err(CompileError::new(
+                    String::from("Format error"),
+                    format!("Could not find \"{}\"", path.display()),
+                    vec![],
+                ))
+            }
+        }
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)
+        .map_err(|_| {
+            CompileError::new(
+                String::from("Format error"),
+                format!("Could not read folder \"{}\"", path.display()),
+                vec![],
+            )
+        })?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() || entry.extension().map(|ext| ext == "mini") == Some(true) {
+            collect_files(&entry, files)?;
+        }
+    }
+    Ok(())
+}
+
+/// Formats mini source, failing if the source can't be formatted without changing its tokens.
+pub fn format_source(source: &str) -> Result<String, String> {
+    let mut lines: Vec<(usize, Vec<Atom>)> = vec![];
+    for atom in atomize(source) {
+        match (atom.newlines_before > 0, lines.last_mut()) {
+            (false, Some((_, line))) => line.push(atom),
+            _ => lines.push((atom.newlines_before.saturating_sub(1), vec![atom])),
+        }
+    }
+
+    let mut output = String::new();
+    // the indentation of the line each open bracket is on, and whether it opens an asm block
+    let mut open: Vec<(usize, bool)> = vec![];
+    let mut pending_asm = false;
+    let mut previous_code: Option<(Atom, bool)> = None;
+    let mut previous_line_opened = true;
+    for (blank_lines, line) in lines {
+        let first = &line[0];
+        if blank_lines > 0 && !output.is_empty() && !previous_line_opened && !first.is_closer() {
+            output.push('\n');
+        }
+
+        let in_asm = open.last().map(|(_, asm)| *asm) == Some(true);
+        let continues = match &previous_code {
+            Some((last, attribute)) => {
+                !attribute
+                    && !in_asm
+                    && !first.is_closer()
+                    && !first.is("{")
+                    && (first.is("with")
+                        || first.is(".")
+                        || BINARY.contains(&first.text.as_str())
+                        || !(last.is(";") || last.is(",") || last.is_opener() || last.is("}")))
+            }
+            None => false,
+        };
+        let indent = match (first.is_closer(), open.last()) {
+            (true, Some((level, _))) => *level,
+            (false, Some((level, _))) => level + 1 + continues as usize,
+            (_, None) => continues as usize,
+        };
+
+        output.push_str(&"    ".repeat(indent));
+        for (index, atom) in line.iter().enumerate() {
+            if index > 0 && spaced(&line[index - 1], atom) {
+                output.push(' ');
+            }
+            output.push_str(&atom.text);
+            if atom.kind == AtomKind::Word && atom.text == "asm" {
+                pending_asm = true;
+            }
+            if atom.is_opener() {
+                open.push((indent, atom.is("{") && pending_asm));
+                if atom.is("{") {
+                    pending_asm = false;
+                }
+            } else if atom.is_closer() {
+                open.pop();
+            }
+        }
+        output.push('\n');
+
+        let last_code = line
+            .iter()
+            .rev()
+            .find(|atom| atom.kind != AtomKind::Comment);
+        if let Some(last) = last_code {
+            previous_code = Some((last.clone(), first.is("#[") || first.is("#![")));
+        }
+        previous_line_opened = line.last().map(Atom::is_opener) == Some(true);
+    }
+
+    if significant(&atomize(&output)) != significant(&atomize(source)) {
+        return Err(String::from("formatting would change the tokens"));
+    }
+    Ok(output)
+}
+
+/// Whether a space belongs between two atoms on the same line.
+fn spaced(a: &Atom, b: &Atom) -> bool {
+    let word_boundary = a.kind == AtomKind::Word && b.kind == AtomKind::Word;
+    let decided = match (a.text.as_str(), b.text.as_str()) {
+        _ if a.kind == AtomKind::Comment => true,
+        _ if b.kind == AtomKind::Comment => true,
+        _ if word_boundary => true,
+        (_, "," | ";" | ":" | "." | "::" | "?" | ")" | "]") => false,
+        ("(" | "[" | "." | "::" | "#[" | "#![", _) => false,
+        ("," | ";" | ":", _) => true,
+        ("{", "}") => false,
+        ("{", _) | (_, "}") | (_, "{") | ("}", _) => true,
+        (left, right) if BINARY.contains(&left) || BINARY.contains(&right) => true,
+        (")" | "]", "(") => false,
+        (_, "(") if a.kind == AtomKind::Word => SPACED_KEYWORDS.contains(&a.text.as_str()),
+        (")", _) if b.kind == AtomKind::Word => true,
+        _ => b.space_before,
+    };
+    // never join tokens that the lexer would then read differently
+    decided || (b.space_before && !joinable(a, b))
+}
+
+/// Whether two atoms still lex as themselves when written with no space between them.
+fn joinable(a: &Atom, b: &Atom) -> bool {
+    let joined = atomize(&format!("{}{}", a.text, b.text));
+    joined.len() == 2 && joined[0].text == a.text && joined[1].text == b.text
+}
+
+/// Splits source into atoms, merging the punctuation of compound tokens and recording the
+/// whitespace before each.
+fn atomize(source: &str) -> Vec<Atom> {
+    let tokens = tokenize(source);
+    let mut atoms: Vec<Atom> = vec![];
+    let mut newlines = 0;
+    let mut space = false;
+    let mut index = 0;
+    while index < tokens.len() {
+        let token = &tokens[index];
+        index += 1;
+        let kind = match token.kind {
+            Kind::Trivia if !token.text.starts_with('/') => {
+                newlines += token.text.matches('\n').count();
+                space = true;
+                continue;
+            }
+            Kind::Trivia => AtomKind::Comment,
+            Kind::Punct => AtomKind::Punct,
+            _ => AtomKind::Word,
+        };
+        let mut text = token.text.clone();
+        if kind == AtomKind::Punct {
+            let mut following = String::new();
+            for next in tokens[index..].iter().take(2) {
+                match next.kind {
+                    Kind::Punct => following.push_str(&next.text),
+                    _ => break,
+                }
+            }
+            let compound = COMPOUNDS.iter().find(|compound| {
+                compound.starts_with(&text)
+                    && format!("{}{}", text, following).starts_with(*compound)
+            });
+            if let Some(compound) = compound {
+                index += compound.len() - text.len();
+                text = compound.to_string();
+            }
+        }
+        atoms.push(Atom {
+            kind,
+            text,
+            newlines_before: newlines,
+            space_before: space,
+        });
+        newlines = 0;
+        space = false;
+    }
+    atoms
+}
+
+/// The atoms of source, ignoring whitespace and any trailing whitespace in comments.
+fn significant(atoms: &[Atom]) -> Vec<(AtomKind, &str)> {
+    atoms
+        .iter()
+        .map(|atom| (atom.kind, atom.text.trim_end()))
+        .collect()
+}
+
+#[test]
+fn test_format_source() {
+    let source = "\
+//
+// Copyright
+//
+
+
+
+use std::bytearray::ByteArray;
+type Pair=struct{first:uint,second :option<uint> ,}
+
+/// Adds things.
+public   func add(a:uint,b :uint)->uint{
+
+    let x=a+b ;   // the sum
+    if(x>=10 ){return x-1;}
+    let y = asm(x, 2) uint { add };
+    let stack = unsafecast<[8]uint>(newfixedarray(8))
+    with { [0]=3 }
+    with { [1] = -4 };
+      while (x<y) {
+            x = x*2;
+      }
+
+
+    #[cfg(feature = \"a\")]
+    let z = f(g(
+    x,
+    y),
+    );
+    asm(x) {
+        pop
+        noop
+    };
+    return !f(x) && x-1 < y;
+
+}
+";
+    let expected = "\
+//
+// Copyright
+//
+
+use std::bytearray::ByteArray;
+type Pair = struct { first: uint, second: option<uint>, }
+
+/// Adds things.
+public func add(a: uint, b: uint) -> uint {
+    let x = a + b; // the sum
+    if (x >= 10) { return x-1; }
+    let y = asm(x, 2) uint { add };
+    let stack = unsafecast<[8]uint>(newfixedarray(8))
+        with { [0] = 3 }
+        with { [1] = -4 };
+    while (x<y) {
+        x = x*2;
+    }
+
+    #[cfg(feature = \"a\")]
+    let z = f(g(
+        x,
+        y),
+    );
+    asm(x) {
+        pop
+        noop
+    };
+    return !f(x) && x-1 < y;
+}
+";
+    let formatted = format_source(source).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format_source(&formatted).unwrap(), formatted);
+
+    // a nested generic's closing brackets stay apart, since together they'd be a shift
+    let nested = "var x: option<option<uint> >;\n";
+    assert_eq!(format_source(nested).unwrap(), nested);
+
+    // the ArbOS sources stay the same when reformatted a second time
+    let mut files = vec![];
+    collect_files(Path::new("arb_os"), &mut files).unwrap();
+    assert!(!files.is_empty());
+    for file in files {
+        let once = format_source(&fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(format_source(&once).unwrap(), once, "{}", file.display());
+    }
+}
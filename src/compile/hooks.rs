@@ -0,0 +1,273 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Gives the attributes written on funcs their meaning through hooks registered by name, so that
+//! new attributes can be added without special-casing them in the parser or codegen.
+
+use crate::compile::ast::{Attributes, Func, NamedAttribute};
+use crate::compile::typecheck::TypeCheckedFunc;
+use crate::compile::{CompileError, DebugInfo};
+use crate::console::Color;
+use crate::mavm::{AVMOpcode, Instruction, Opcode, Value};
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+/// What an attribute does to the funcs it's written on. Hooks are run once the func has been
+/// typechecked, so an attribute can't change what a func means to the rest of the program.
+pub trait AttributeHook: Send + Sync {
+    /// The name the attribute is written with, as in `#[name]`.
+    fn name(&self) -> &'static str;
+
+    /// Checks that the func can have the attribute, before the func is typechecked.
+    fn check(&self, _func: &Func) -> Option<CompileError> {
+        None
+    }
+
+    /// Marks the func's `DebugInfo`, which the code generated for the func inherits.
+    fn attach(&self, _attributes: &mut Attributes) {}
+
+    /// Rewrites the code generated for the func, before its frame is laid out. The code begins
+    /// with the func's label.
+    fn rewrite(
+        &self,
+        _func_name: &str,
+        _debug_info: DebugInfo,
+        code: Vec<Instruction>,
+    ) -> Result<Vec<Instruction>, CompileError> {
+        Ok(code)
+    }
+}
+
+/// The attributes funcs can be written with, keyed by name.
+#[derive(Clone)]
+pub struct AttributeHooks {
+    hooks: BTreeMap<&'static str, Arc<dyn AttributeHook>>,
+}
+
+impl AttributeHooks {
+    /// Makes a set of hooks with no attributes at all, not even the built-in ones.
+    pub fn empty() -> Self {
+        AttributeHooks {
+            hooks: BTreeMap::new(),
+        }
+    }
+
+    /// Registers a hook, replacing any of the same name.
+    pub fn register(&mut self, hook: impl AttributeHook + 'static) {
+        self.hooks.insert(hook.name(), Arc::new(hook));
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.hooks.keys().copied()
+    }
+
+    /// Finds the hook of each attribute written on a func, in the order they were written.
+    fn hooks_of<'a>(
+        &'a self,
+        attributes: &'a [NamedAttribute],
+    ) -> impl Iterator<Item = &'a Arc<dyn AttributeHook>> + 'a {
+        attributes
+            .iter()
+            .filter_map(move |attribute| self.hooks.get(attribute.name.as_str()))
+    }
+
+    /// Reports the attributes written on funcs that no hook gives meaning to, and those written
+    /// on funcs that can't have them.
+    pub(super) fn check(&self, funcs: &[Func]) -> Vec<CompileError> {
+        let unrecognized = funcs
+            .iter()
+            .flat_map(|func| &func.attributes)
+            .filter(|attribute| !self.hooks.contains_key(attribute.name.as_str()))
+            .map(|attribute| {
+                CompileError::new(
+                    "Attribute error",
+                    format!("Unrecognized attribute {}", Color::red(&attribute.name)),
+                    attribute.location.into_iter().collect(),
+                )
+            });
+        let misplaced = funcs.iter().flat_map(|func| {
+            self.hooks_of(&func.attributes)
+                .filter_map(move |hook| hook.check(func))
+        });
+        unrecognized.chain(misplaced).collect()
+    }
+
+    /// Lets each of the func's attributes mark its `DebugInfo`.
+    pub(super) fn attach(&self, func: &mut TypeCheckedFunc) {
+        for hook in self.hooks_of(&func.attributes) {
+            hook.attach(&mut func.debug_info.attributes);
+        }
+    }
+
+    /// Lets each of the func's attributes rewrite the code generated for it.
+    pub(super) fn rewrite(
+        &self,
+        attributes: &[NamedAttribute],
+        func_name: &str,
+        debug_info: DebugInfo,
+        mut code: Vec<Instruction>,
+    ) -> Result<Vec<Instruction>, CompileError> {
+        for hook in self.hooks_of(attributes) {
+            code = hook.rewrite(func_name, debug_info, code)?;
+        }
+        Ok(code)
+    }
+}
+
+/// The built-in attributes: `breakpoint`, `init`, `inline`, `override`, `print`, `test`, `trace`,
+/// and `wrapping`.
+impl Default for AttributeHooks {
+    fn default() -> Self {
+        let mut hooks = AttributeHooks::empty();
+        hooks.register(Breakpoint);
+        hooks.register(Init);
+        hooks.register(Inline);
+        hooks.register(Override);
+        hooks.register(Print);
+        hooks.register(Test);
+        hooks.register(Trace);
+        hooks.register(Wrapping);
+        hooks
+    }
+}
+
+impl Debug for AttributeHooks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
+
+/// Stops the debugger on entering the func.
+struct Breakpoint;
+
+impl AttributeHook for Breakpoint {
+    fn name(&self) -> &'static str {
+        "breakpoint"
+    }
+
+    fn attach(&self, attributes: &mut Attributes) {
+        attributes.breakpoint = true;
+    }
+}
+
+/// Prints the code generated for the func to the console.
+struct Print;
+
+impl AttributeHook for Print {
+    fn name(&self) -> &'static str {
+        "print"
+    }
+
+    fn attach(&self, attributes: &mut Attributes) {
+        attributes.codegen_print = true;
+    }
+}
+
+/// Prints the func's name each time it's called.
+struct Trace;
+
+impl AttributeHook for Trace {
+    fn name(&self) -> &'static str {
+        "trace"
+    }
+
+    fn rewrite(
+        &self,
+        func_name: &str,
+        debug_info: DebugInfo,
+        mut code: Vec<Instruction>,
+    ) -> Result<Vec<Instruction>, CompileError> {
+        let text = format!("trace: {}", func_name);
+        let print = Instruction::from_opcode_imm(
+            Opcode::AVMOpcode(AVMOpcode::DebugPrint),
+            Value::from(text.as_ref()),
+            debug_info,
+        );
+        code.insert(1, print);
+        Ok(code)
+    }
+}
+
+/// Lets the func's arithmetic wrap around when compiling with overflow checks, for code like
+/// hashing that relies on it.
+struct Wrapping;
+
+impl AttributeHook for Wrapping {
+    fn name(&self) -> &'static str {
+        "wrapping"
+    }
+
+    fn attach(&self, attributes: &mut Attributes) {
+        attributes.wrapping = true;
+    }
+}
+
+/// Inlines the func into its callers no matter its size, as long as it calls nothing itself.
+struct Inline;
+
+impl AttributeHook for Inline {
+    fn name(&self) -> &'static str {
+        "inline"
+    }
+
+    fn attach(&self, attributes: &mut Attributes) {
+        attributes.inline = true;
+    }
+}
+
+/// Marks the func as one initializing globals, which runs before the rest of the program does, so
+/// that when propagating constant globals, the constants it alone sets globals to can be read in
+/// place of the globals. It isn't inlined, so that the linker can tell where it's called.
+struct Init;
+
+impl AttributeHook for Init {
+    fn name(&self) -> &'static str {
+        "init"
+    }
+
+    fn attach(&self, attributes: &mut Attributes) {
+        attributes.init = true;
+    }
+}
+
+/// Links the func in place of the builtin or library func of the same name, sending every call to
+/// that func here instead. The func must take the args and return the type of the one it replaces,
+/// which linking checks.
+struct Override;
+
+impl AttributeHook for Override {
+    fn name(&self) -> &'static str {
+        "override"
+    }
+
+    fn attach(&self, attributes: &mut Attributes) {
+        attributes.overrides = true;
+    }
+}
+
+/// Marks the func as a unit test, which `mini test` runs as the entry point of a program of its
+/// own. Tests are only linked when testing, and take no args.
+struct Test;
+
+impl AttributeHook for Test {
+    fn name(&self) -> &'static str {
+        "test"
+    }
+
+    fn check(&self, func: &Func) -> Option<CompileError> {
+        match func.args.is_empty() {
+            true => None,
+            false => Some(CompileError::new(
+                "Attribute error",
+                format!("Test {} can't take args", Color::red(&func.name)),
+                func.debug_info.locs(),
+            )),
+        }
+    }
+
+    fn attach(&self, attributes: &mut Attributes) {
+        attributes.test = true;
+    }
+}
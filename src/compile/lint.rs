@@ -0,0 +1,125 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the lints compile warnings belong to, and how severely each is treated.
+
+use super::CompileError;
+use crate::console::Color;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A kind of compile warning, which can be allowed, warned about, or denied as a group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Lint {
+    /// Variables and arguments whose values are never used
+    UnusedVariables,
+    /// Use statements that are duplicated or never needed
+    UnusedImports,
+    /// Imports that don't name a type or func
+    UnresolvedImports,
+    /// Constants that are never used
+    UnusedConstants,
+    /// Names marked as intentionally unused that are used anyway
+    UsedUnderscore,
+    /// Statements and match arms that can never run
+    UnreachableCode,
+    /// Funcs nothing calls
+    DeadCode,
+    /// Lets that rebind a variable already in scope
+    Shadowing,
+    /// Funcs marked view or write that aren't
+    Purity,
+    /// Funcs that can call themselves without bound
+    Recursion,
+    /// Call stacks that can grow beyond the stack budget
+    StackBudget,
+    /// Strings that look like hex
+    HexStrings,
+}
+
+/// How severely to treat the warnings of a lint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LintLevel {
+    /// The warnings are dropped
+    Allow,
+    /// The warnings are reported
+    Warn,
+    /// The warnings are reported as errors
+    Deny,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 12] = [
+        Lint::UnusedVariables,
+        Lint::UnusedImports,
+        Lint::UnresolvedImports,
+        Lint::UnusedConstants,
+        Lint::UsedUnderscore,
+        Lint::UnreachableCode,
+        Lint::DeadCode,
+        Lint::Shadowing,
+        Lint::Purity,
+        Lint::Recursion,
+        Lint::StackBudget,
+        Lint::HexStrings,
+    ];
+
+    /// The name of the lint, as given on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused-variables",
+            Lint::UnusedImports => "unused-imports",
+            Lint::UnresolvedImports => "unresolved-imports",
+            Lint::UnusedConstants => "unused-constants",
+            Lint::UsedUnderscore => "used-underscore",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::DeadCode => "dead-code",
+            Lint::Shadowing => "shadowing",
+            Lint::Purity => "purity",
+            Lint::Recursion => "recursion",
+            Lint::StackBudget => "stack-budget",
+            Lint::HexStrings => "hex-strings",
+        }
+    }
+
+    /// How the lint is treated unless the command line says otherwise. Shadowing is common and
+    /// usually intentional in mini, so it's only reported when asked for.
+    pub fn default_level(self) -> LintLevel {
+        match self {
+            Lint::Shadowing => LintLevel::Allow,
+            _ => LintLevel::Warn,
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Lint {
+    type Err = CompileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase().replace('_', "-");
+        Lint::ALL
+            .iter()
+            .find(|lint| lint.name() == name)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<_> = Lint::ALL.iter().map(|lint| lint.name()).collect();
+                CompileError::new(
+                    String::from("Lint error"),
+                    format!(
+                        "Unknown lint {}, expected one of {}",
+                        Color::red(s),
+                        names.join(", ")
+                    ),
+                    vec![],
+                )
+            })
+    }
+}
@@ -0,0 +1,996 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides a language server for mini, which editors talk to over the Language Server Protocol
+//! to show the errors and warnings of programs, the types of the names in them, and where those
+//! names are declared, and to apply the fixes some errors and warnings come with.
+
+use super::ast::{Func, Type, TypeTree};
+use super::typecheck::{
+    TypeCheckedExpr, TypeCheckedExprKind, TypeCheckedNode, TypeCheckedStatementKind,
+};
+use super::{
+    create_program_tree, create_type_tree, locate_main, parse_features, resolve_impls,
+    resolve_imports, typecheck_module, AbstractSyntaxTree, CompileError, ErrorSystem, FileInfo,
+    Fix, Manifest, Module, TypeCheckedModule,
+};
+use crate::console::Color;
+use crate::pos::Location;
+use crate::stringtable::{StringId, StringTable};
+use clap::Clap;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Command line options for lsp subcommand.
+///
+/// Serves the Language Server Protocol over stdin and stdout. Each file is analyzed as part of
+/// the program in its folder, whose main file is `main.mini`, or on its own if the folder has no
+/// main file. Programs are analyzed when their files are opened or saved, so what's reported
+/// reflects the files on disk rather than unsaved edits.
+#[derive(Clap, Debug, Default)]
+pub struct LspStruct {
+    #[clap(short, long)]
+    pub consts_file: Option<String>,
+    /// Enables features, as when compiling
+    #[clap(long)]
+    pub features: Vec<String>,
+    #[clap(short, long)]
+    pub no_builtins: bool,
+}
+
+impl LspStruct {
+    /// Serves requests until the editor asks the server to exit.
+    pub fn invoke(&self) -> Result<(), CompileError> {
+        // typechecking recurses deeply, so it needs the same stack the compiler gets
+        drop(
+            rayon::ThreadPoolBuilder::new()
+                .stack_size(4 * 8192 * 1024)
+                .build_global(),
+        );
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        let mut server = LanguageServer::new(self);
+        server
+            .serve(stdin.lock(), stdout.lock())
+            .map_err(|e| CompileError::new("LSP error", e.to_string(), vec![]))?;
+        match server.shut_down {
+            true => Ok(()),
+            false => Err(CompileError::new(
+                "LSP error",
+                "The editor exited without shutting the server down",
+                vec![],
+            )),
+        }
+    }
+}
+
+/// A name in the source, along with what it refers to.
+struct Symbol {
+    location: Location,
+    /// The length of the name, in bytes
+    len: usize,
+    /// What's shown when hovering over the name, as mini source
+    hover: String,
+    /// Where the name is declared, if it's known
+    definition: Option<Location>,
+}
+
+/// What's known about a program after parsing and typechecking as much of it as possible.
+struct Analysis {
+    /// The file each file id refers to
+    files: BTreeMap<u64, PathBuf>,
+    file_info_chart: BTreeMap<u64, FileInfo>,
+    symbols: Vec<Symbol>,
+    /// The diagnostics of each file, as LSP `Diagnostic`s
+    diagnostics: BTreeMap<PathBuf, Vec<Value>>,
+}
+
+impl Analysis {
+    fn file_id(&self, path: &Path) -> Option<u64> {
+        self.files
+            .iter()
+            .find(|(_, file)| file.as_path() == path)
+            .map(|(id, _)| *id)
+    }
+
+    /// Finds the symbol at an LSP position in a file.
+    fn symbol_at(&self, path: &Path, position: &Value) -> Option<&Symbol> {
+        let file_id = self.file_id(path)?;
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+        let text = self.file_info_chart.get(&file_id)?.contents.get(line)?;
+        let column = byte_column(text, character);
+        self.symbols.iter().find(|symbol| {
+            let start = symbol.location.column.to_usize();
+            symbol.location.file_id == file_id
+                && symbol.location.line.to_usize() == line
+                && (start..start + symbol.len).contains(&column)
+        })
+    }
+
+    /// Gets the LSP `Location` of a name, or of the word at loc if the name isn't known.
+    fn lsp_location(&self, loc: &Location, len: Option<usize>) -> Option<Value> {
+        let path = self.files.get(&loc.file_id)?;
+        Some(json!({
+            "uri": path_to_uri(path),
+            "range": self.range(loc, len),
+        }))
+    }
+
+    /// Gets the LSP `Range` of the len bytes at loc, or of the word there if len isn't given.
+    fn range(&self, loc: &Location, len: Option<usize>) -> Value {
+        let line = loc.line.to_usize();
+        let start = loc.column.to_usize();
+        let text = self
+            .file_info_chart
+            .get(&loc.file_id)
+            .and_then(|info| info.contents.get(line))
+            .map(String::as_str)
+            .unwrap_or("");
+        let len = len.unwrap_or_else(|| {
+            let word = text
+                .get(start..)
+                .unwrap_or("")
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .map(char::len_utf8)
+                .sum();
+            std::cmp::max(word, 1)
+        });
+        json!({
+            "start": {"line": line, "character": utf16_column(text, start)},
+            "end": {"line": line, "character": utf16_column(text, start + len)},
+        })
+    }
+
+    /// Gets the LSP `TextEdit` of a fix. Removals that leave their lines blank take the lines with
+    /// them, so that removing a use statement doesn't leave a gap.
+    fn text_edit(&self, fix: &Fix) -> Value {
+        let text = |loc: &Location| {
+            self.file_info_chart
+                .get(&loc.file_id)
+                .and_then(|info| info.contents.get(loc.line.to_usize()))
+                .map(String::as_str)
+                .unwrap_or("")
+        };
+        let position = |loc: &Location| {
+            let line = loc.line.to_usize();
+            json!({"line": line, "character": utf16_column(text(loc), loc.column.to_usize())})
+        };
+        let blank = |text: Option<&str>| text.map_or(false, |text| text.trim().is_empty());
+        let (start, end) = (fix.start.column.to_usize(), fix.end.column.to_usize());
+        let whole_lines = fix.replacement.is_empty()
+            && blank(text(&fix.start).get(..start))
+            && blank(text(&fix.end).get(end..));
+        let range = match whole_lines {
+            true => json!({
+                "start": {"line": fix.start.line.to_usize(), "character": 0},
+                "end": {"line": fix.end.line.to_usize() + 1, "character": 0},
+            }),
+            false => json!({"start": position(&fix.start), "end": position(&fix.end)}),
+        };
+        json!({"range": range, "newText": fix.replacement})
+    }
+}
+
+/// Answers an editor's requests, keeping the analysis of each program it has opened files of.
+pub struct LanguageServer {
+    consts_file: Option<PathBuf>,
+    features: BTreeSet<String>,
+    builtins: bool,
+    analyses: BTreeMap<PathBuf, Analysis>,
+    /// Whether the editor has asked the server to shut down
+    shut_down: bool,
+}
+
+impl LanguageServer {
+    pub fn new(options: &LspStruct) -> Self {
+        LanguageServer {
+            consts_file: options.consts_file.as_ref().map(PathBuf::from),
+            features: parse_features(&options.features),
+            builtins: !options.no_builtins,
+            analyses: BTreeMap::new(),
+            shut_down: false,
+        }
+    }
+
+    /// Reads messages from input and writes the responses and notifications they lead to, until
+    /// the editor sends `exit` or closes input.
+    pub fn serve(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        while let Some(message) = read_message(&mut input)? {
+            if message["method"] == "exit" {
+                break;
+            }
+            for reply in self.handle(&message) {
+                write_message(&mut output, &reply)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a request or notification, returning the messages to send back.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let params = &message["params"];
+        let respond = |result: Value| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": message["id"],
+                "result": result,
+            })
+        };
+        let document = uri_to_path(params["textDocument"]["uri"].as_str().unwrap_or(""));
+
+        match message["method"].as_str().unwrap_or("") {
+            "initialize" => vec![respond(json!({
+                "capabilities": {
+                    "textDocumentSync": {
+                        "openClose": true,
+                        "change": 0,
+                        "save": {"includeText": false},
+                    },
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "codeActionProvider": {"codeActionKinds": ["quickfix"]},
+                },
+                "serverInfo": {"name": "mini"},
+            }))],
+            "shutdown" => {
+                self.shut_down = true;
+                vec![respond(Value::Null)]
+            }
+            "textDocument/didOpen" | "textDocument/didSave" => self.reanalyze(&document),
+            "textDocument/definition" => {
+                let analysis = self.analysis(&document);
+                let definition = analysis
+                    .symbol_at(&document, &params["position"])
+                    .and_then(|symbol| symbol.definition.as_ref())
+                    .and_then(|loc| analysis.lsp_location(loc, None));
+                vec![respond(definition.unwrap_or(Value::Null))]
+            }
+            "textDocument/hover" => {
+                let analysis = self.analysis(&document);
+                let hover = analysis
+                    .symbol_at(&document, &params["position"])
+                    .map(|symbol| {
+                        json!({
+                            "contents": {"kind": "markdown", "value": symbol.hover},
+                            "range": analysis.range(&symbol.location, Some(symbol.len)),
+                        })
+                    });
+                vec![respond(hover.unwrap_or(Value::Null))]
+            }
+            "textDocument/codeAction" => {
+                // diagnostics come back with the fixes they were published with
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                let diagnostics = params["context"]["diagnostics"].as_array();
+                let actions: Vec<_> = diagnostics
+                    .into_iter()
+                    .flatten()
+                    .filter(|diagnostic| diagnostic["data"]["edit"].is_object())
+                    .map(|diagnostic| {
+                        let mut changes = serde_json::Map::new();
+                        changes.insert(uri.to_string(), json!([diagnostic["data"]["edit"]]));
+                        json!({
+                            "title": diagnostic["data"]["title"],
+                            "kind": "quickfix",
+                            "diagnostics": [diagnostic],
+                            "isPreferred": true,
+                            "edit": {"changes": changes},
+                        })
+                    })
+                    .collect();
+                vec![respond(json!(actions))]
+            }
+            // requests must be answered, even those the server doesn't know
+            method if !message["id"].is_null() => vec![json!({
+                "jsonrpc": "2.0",
+                "id": message["id"],
+                "error": {
+                    "code": -32601,
+                    "message": format!("Unknown method {}", method),
+                },
+            })],
+            _ => vec![],
+        }
+    }
+
+    /// Gets the analysis of the program a file is part of, analyzing it if it hasn't been.
+    fn analysis(&mut self, file: &Path) -> &Analysis {
+        let program = program_of(file);
+        if !self.analyses.contains_key(&program) {
+            let analysis = self.analyze(&program, file);
+            self.analyses.insert(program.clone(), analysis);
+        }
+        &self.analyses[&program]
+    }
+
+    /// Analyzes the program a file is part of again, publishing its diagnostics. Files that had
+    /// diagnostics before get an empty list if they no longer do.
+    fn reanalyze(&mut self, file: &Path) -> Vec<Value> {
+        let program = program_of(file);
+        let analysis = self.analyze(&program, file);
+        let mut diagnostics = analysis.diagnostics.clone();
+        if let Some(previous) = self.analyses.get(&program) {
+            for path in previous.diagnostics.keys() {
+                diagnostics.entry(path.clone()).or_default();
+            }
+        }
+        self.analyses.insert(program, analysis);
+
+        diagnostics
+            .into_iter()
+            .map(|(path, diagnostics)| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": {"uri": path_to_uri(&path), "diagnostics": diagnostics},
+                })
+            })
+            .collect()
+    }
+
+    /// Parses, typechecks, and flowchecks a program, keeping what typechecks even if some of it
+    /// doesn't. Issues that aren't in any file are reported in the file that led to the analysis.
+    fn analyze(&self, program: &Path, file: &Path) -> Analysis {
+        let mut error_system = ErrorSystem {
+            errors: vec![],
+            warnings: vec![],
+            warnings_are_errors: false,
+            warn_color: Color::YELLOW,
+            file_info_chart: BTreeMap::new(),
+            lint_levels: BTreeMap::new(),
+        };
+        let mut file_info_chart = BTreeMap::new();
+        let (parsed, mut checked, type_tree) =
+            match self.typecheck(program, &mut file_info_chart, &mut error_system) {
+                Ok(modules) => modules,
+                Err(error) => {
+                    error_system.errors.push(error);
+                    (vec![], vec![], TypeTree::new())
+                }
+            };
+        for module in &mut checked {
+            module.flowcheck(&mut error_system);
+        }
+        error_system.apply_lint_levels();
+
+        let files: BTreeMap<_, _> = file_info_chart
+            .iter()
+            .map(|(id, info)| {
+                let path = PathBuf::from(&info.path);
+                (*id, fs::canonicalize(&path).unwrap_or(path))
+            })
+            .collect();
+
+        let mut analysis = Analysis {
+            files,
+            file_info_chart,
+            symbols: program_symbols(&parsed, checked, &type_tree),
+            diagnostics: BTreeMap::new(),
+        };
+        let issues = error_system.errors.iter().chain(&error_system.warnings);
+        for issue in issues {
+            let (path, range) = match issue.locations.first() {
+                Some(loc) => match analysis.files.get(&loc.file_id) {
+                    Some(path) => (path.clone(), analysis.range(loc, None)),
+                    None => continue,
+                },
+                None => (
+                    file.to_path_buf(),
+                    json!({"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 0}}),
+                ),
+            };
+            let mut diagnostic = json!({
+                "range": range,
+                "severity": match issue.is_warning {
+                    true => 2,
+                    false => 1,
+                },
+                "source": "mini",
+                "message": Color::uncolored(format!("{}: {}", issue.title, issue.description)),
+            });
+            match (issue.lint, issue.code) {
+                (Some(lint), _) => diagnostic["code"] = json!(lint.name()),
+                (None, Some(code)) => diagnostic["code"] = json!(code.code()),
+                (None, None) => {}
+            }
+            if let Some(fix) = &issue.fix {
+                diagnostic["data"] = json!({"title": fix.title, "edit": analysis.text_edit(fix)});
+            }
+            analysis
+                .diagnostics
+                .entry(path)
+                .or_default()
+                .push(diagnostic);
+        }
+        analysis
+    }
+
+    /// Parses and typechecks the modules of a program, returning every module that parses along
+    /// with those that typecheck, and recording the errors of the rest.
+    fn typecheck(
+        &self,
+        program: &Path,
+        file_info_chart: &mut BTreeMap<u64, FileInfo>,
+        error_system: &mut ErrorSystem,
+    ) -> Result<(Vec<Module>, Vec<TypeCheckedModule>, TypeTree), CompileError> {
+        let (folder, library, main) = locate_main(program)?;
+        let constants_default = folder.join("constants.json");
+        let constants_path = match &self.consts_file {
+            Some(path) => Some(path.as_path()),
+            None => match constants_default.exists() {
+                true => Some(constants_default.as_path()),
+                false => None,
+            },
+        };
+
+        let (mut programs, mut import_map) = create_program_tree(
+            folder,
+            library,
+            main,
+            file_info_chart,
+            constants_path,
+            &self.features,
+            error_system,
+            self.builtins,
+            &Manifest::load(folder)?,
+        )?;
+        resolve_imports(&mut programs, &mut import_map, error_system)?;
+        let type_tree = create_type_tree(&programs);
+        resolve_impls(&mut programs, &type_tree)?;
+
+        let parsed: Vec<_> = programs.values().cloned().collect();
+        let issues = &*error_system;
+        let results: Vec<_> = programs
+            .into_par_iter()
+            .map(|(_, module)| typecheck_module(&type_tree, module, issues))
+            .collect();
+        let mut modules = vec![];
+        for result in results {
+            match result {
+                Ok((module, issues)) => {
+                    modules.push(module);
+                    for issue in issues {
+                        match issue.is_warning {
+                            true => error_system.warnings.push(issue),
+                            false => error_system.errors.push(issue),
+                        }
+                    }
+                }
+                Err(errors) => error_system.errors.extend(errors),
+            }
+        }
+        Ok((parsed, modules, type_tree))
+    }
+}
+
+/// A name found while walking a func, before locals are matched with their declarations.
+enum Occurrence {
+    /// A local declared here, with its type if it's known
+    Binding(StringId, Location, Option<Type>),
+    Local(StringId, Location, Type),
+    Global(StringId, Location),
+    Func(StringId, Location, Type),
+}
+
+/// Gathers the symbols of a program. The funcs and globals of every module that parses are
+/// known, while the names used within funcs are only known for modules that typecheck.
+fn program_symbols(
+    parsed: &[Module],
+    mut checked: Vec<TypeCheckedModule>,
+    type_tree: &TypeTree,
+) -> Vec<Symbol> {
+    let mut symbols = vec![];
+
+    // funcs are found by the module they're declared in, since imports name them that way
+    let mut funcs = HashMap::new();
+    let mut globals = HashMap::new();
+    for module in parsed {
+        // closures are named after where they're declared, rather than with identifiers
+        let named = module.funcs.iter().filter(|func| !func.name.contains(' '));
+        for func in named {
+            if let Some(loc) = func.debug_info.location {
+                let hover = signature(func, &module.string_table, type_tree);
+                symbols.push(Symbol {
+                    location: loc,
+                    len: func.name.len(),
+                    hover: hover.clone(),
+                    definition: Some(loc),
+                });
+                funcs.insert((module.path.clone(), func.name.clone()), (loc, hover));
+            }
+        }
+        for global in &module.global_vars {
+            if let Some(loc) = global.debug_info.location {
+                let hover = mini_source(format!(
+                    "var {}: {}",
+                    global.name,
+                    global.tipe.print(type_tree).trim_end()
+                ));
+                symbols.push(Symbol {
+                    location: loc,
+                    len: global.name.len(),
+                    hover: hover.clone(),
+                    definition: Some(loc),
+                });
+                globals.insert((module.path.clone(), global.id), (loc, hover));
+            }
+        }
+    }
+
+    for module in &mut checked {
+        let names = &module.string_table;
+        for func in module.checked_funcs.values_mut() {
+            let func_loc = match func.debug_info.location {
+                Some(loc) => loc,
+                None => continue,
+            };
+            let mut occurrences: Vec<_> = func
+                .args
+                .iter()
+                .map(|arg| {
+                    let loc = arg.debug_info.location.unwrap_or(func_loc);
+                    Occurrence::Binding(arg.name, loc, Some(arg.tipe.clone()))
+                })
+                .collect();
+            find_occurrences(func.child_nodes(), &mut occurrences);
+
+            let bindings: Vec<_> = occurrences
+                .iter()
+                .filter_map(|occurrence| match occurrence {
+                    Occurrence::Binding(id, loc, _) => Some((*id, *loc)),
+                    _ => None,
+                })
+                .collect();
+            let declaration = |id: StringId, at: &Location| {
+                bindings
+                    .iter()
+                    .filter(|(bound, loc)| *bound == id && loc.absolute <= at.absolute)
+                    .max_by_key(|(_, loc)| loc.absolute)
+                    .map(|(_, loc)| *loc)
+            };
+
+            for occurrence in &occurrences {
+                let symbol = match occurrence {
+                    Occurrence::Binding(id, loc, tipe) => Symbol {
+                        location: *loc,
+                        len: names.name_from_id(*id).len(),
+                        hover: local_hover(names.name_from_id(*id), tipe.as_ref(), type_tree),
+                        definition: Some(*loc),
+                    },
+                    Occurrence::Local(id, loc, tipe) => Symbol {
+                        location: *loc,
+                        len: names.name_from_id(*id).len(),
+                        hover: local_hover(names.name_from_id(*id), Some(tipe), type_tree),
+                        definition: declaration(*id, loc),
+                    },
+                    Occurrence::Global(id, loc) => match globals.get(&(module.path.clone(), *id)) {
+                        Some((declared, hover)) => Symbol {
+                            location: *loc,
+                            len: names.name_from_id(*id).len(),
+                            hover: hover.clone(),
+                            definition: Some(*declared),
+                        },
+                        None => continue,
+                    },
+                    Occurrence::Func(id, loc, tipe) => {
+                        let name = names.name_from_id(*id);
+                        let path = match module.imports.iter().find(|imp| &imp.name == name) {
+                            Some(import) => import.path.clone(),
+                            None => module.path.clone(),
+                        };
+                        let (definition, hover) = match funcs.get(&(path, name.clone())) {
+                            Some((loc, hover)) => (Some(*loc), hover.clone()),
+                            None => (
+                                None,
+                                mini_source(format!("{}: {}", name, tipe.print(type_tree))),
+                            ),
+                        };
+                        Symbol {
+                            location: *loc,
+                            len: name.len(),
+                            hover,
+                            definition,
+                        }
+                    }
+                };
+                symbols.push(symbol);
+            }
+        }
+    }
+    symbols
+}
+
+/// Finds the names used in nodes and their descendants.
+fn find_occurrences(mut nodes: Vec<TypeCheckedNode>, occurrences: &mut Vec<Occurrence>) {
+    for node in &mut nodes {
+        match node {
+            TypeCheckedNode::Statement(stat) => {
+                let loc = stat.debug_info.location;
+                match &stat.kind {
+                    TypeCheckedStatementKind::SetLocals(refs, expr) => {
+                        let types = match (refs.len(), expr.get_type()) {
+                            (1, tipe) => vec![tipe],
+                            (_, Type::Tuple(types)) if types.len() == refs.len() => types,
+                            _ => vec![],
+                        };
+                        for (index, assigned) in refs.iter().enumerate() {
+                            let loc = match assigned.debug_info.location {
+                                Some(loc) => loc,
+                                None => continue,
+                            };
+                            let tipe = types.get(index).cloned();
+                            occurrences.push(match (assigned.shadow, tipe) {
+                                (true, tipe) => Occurrence::Binding(assigned.id, loc, tipe),
+                                (false, Some(tipe)) => Occurrence::Local(assigned.id, loc, tipe),
+                                (false, None) => Occurrence::Local(assigned.id, loc, Type::Any),
+                            });
+                        }
+                    }
+                    TypeCheckedStatementKind::AssignGlobal(id, _) => {
+                        occurrences.extend(loc.map(|loc| Occurrence::Global(*id, loc)));
+                    }
+                    _ => {}
+                }
+            }
+            TypeCheckedNode::Expression(expr) => {
+                if let Some(occurrence) = expr_occurrence(expr) {
+                    occurrences.push(occurrence);
+                }
+            }
+            TypeCheckedNode::Type(_) => continue,
+        }
+        find_occurrences(node.child_nodes(), occurrences);
+    }
+}
+
+fn expr_occurrence(expr: &TypeCheckedExpr) -> Option<Occurrence> {
+    let loc = expr.debug_info.location?;
+    match &expr.kind {
+        TypeCheckedExprKind::LocalVariableRef(id, tipe) => {
+            Some(Occurrence::Local(*id, loc, tipe.clone()))
+        }
+        TypeCheckedExprKind::GlobalVariableRef(id, _) => Some(Occurrence::Global(*id, loc)),
+        TypeCheckedExprKind::FuncRef(id, _, tipe) => Some(Occurrence::Func(*id, loc, tipe.clone())),
+        _ => None,
+    }
+}
+
+/// Describes a func the way it's declared.
+fn signature<T>(func: &Func<T>, names: &StringTable, type_tree: &TypeTree) -> String {
+    let mut signature = String::new();
+    if func.public {
+        signature.push_str("public ");
+    }
+    if func.properties.view {
+        signature.push_str("view ");
+    }
+    if func.properties.write {
+        signature.push_str("write ");
+    }
+    let args: Vec<_> = func
+        .args
+        .iter()
+        .map(|arg| {
+            format!(
+                "{}: {}",
+                names.name_from_id(arg.name),
+                arg.tipe.print(type_tree).trim_end()
+            )
+        })
+        .collect();
+    signature.push_str(&format!("func {}({})", func.name, args.join(", ")));
+    if func.ret_type != Type::Void {
+        signature.push_str(&format!(
+            " -> {}",
+            func.ret_type.print(type_tree).trim_end()
+        ));
+    }
+    let mut hover = mini_source(signature);
+    if let Some(docs) = &func.docs {
+        hover.push_str("\n\n");
+        hover.push_str(docs);
+    }
+    hover
+}
+
+fn local_hover(name: &str, tipe: Option<&Type>, type_tree: &TypeTree) -> String {
+    match tipe {
+        Some(tipe) => mini_source(format!(
+            "let {}: {}",
+            name,
+            tipe.print(type_tree).trim_end()
+        )),
+        None => mini_source(format!("let {}", name)),
+    }
+}
+
+/// Formats source as a markdown code block.
+fn mini_source(source: String) -> String {
+    format!("```mini\n{}\n```", source)
+}
+
+/// Finds the program a file is part of: the folder it's in if the folder has a main file, and
+/// the file on its own otherwise.
+fn program_of(file: &Path) -> PathBuf {
+    match file.parent() {
+        Some(folder) if folder.join("main.mini").exists() => folder.to_path_buf(),
+        _ => file.to_path_buf(),
+    }
+}
+
+/// Reads a message framed with a `Content-Length` header, or `None` once input is closed.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "message lacks a Content-Length")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    let path = PathBuf::from(String::from_utf8_lossy(&bytes).to_string());
+    fs::canonicalize(&path).unwrap_or(path)
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            true => uri.push(byte as char),
+            false => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Converts a column counted in bytes to one counted in UTF-16 code units, as LSP counts them.
+fn utf16_column(line: &str, column: usize) -> usize {
+    match line.get(..column) {
+        Some(prefix) => prefix.encode_utf16().count(),
+        None => column,
+    }
+}
+
+/// Converts a column counted in UTF-16 code units to one counted in bytes.
+fn byte_column(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (index, c) in line.char_indices() {
+        if units >= character {
+            return index;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+#[test]
+fn test_language_server() {
+    let folder = std::env::temp_dir().join(format!("mini-lsp-{}", std::process::id()));
+    drop(fs::remove_dir_all(&folder));
+    fs::create_dir_all(&folder).unwrap();
+    let folder = fs::canonicalize(&folder).unwrap();
+    let (main, other) = (folder.join("main.mini"), folder.join("other.mini"));
+    fs::write(
+        &main,
+        "use other::helper;\n\nvar counter: uint;\n\nview write func main() -> uint {\n    \
+         let total = helper(2);\n    counter = total;\n    return total + counter;\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        &other,
+        "/// Doubles a number\npublic func helper(x: uint) -> uint {\n    return x * 2;\n}\n\n\
+         func broken() -> uint {\n    return true;\n}\n",
+    )
+    .unwrap();
+
+    let (main_uri, other_uri) = (path_to_uri(&main), path_to_uri(&other));
+    let at = |uri: &str, line: u64, character: u64| json!({"textDocument": {"uri": uri}, "position": {"line": line, "character": character}});
+    let requests = vec![
+        json!({"id": 1, "method": "initialize", "params": {}}),
+        json!({"method": "initialized", "params": {}}),
+        json!({"method": "textDocument/didOpen", "params": {"textDocument": {"uri": main_uri}}}),
+        json!({"id": 2, "method": "textDocument/definition", "params": at(&main_uri, 5, 17)}),
+        json!({"id": 3, "method": "textDocument/hover", "params": at(&main_uri, 5, 17)}),
+        json!({"id": 4, "method": "textDocument/definition", "params": at(&main_uri, 7, 12)}),
+        json!({"id": 5, "method": "textDocument/hover", "params": at(&main_uri, 7, 12)}),
+        json!({"id": 6, "method": "textDocument/definition", "params": at(&main_uri, 7, 21)}),
+        json!({"id": 7, "method": "textDocument/hover", "params": at(&other_uri, 5, 6)}),
+        json!({"id": 8, "method": "textDocument/hover", "params": at(&main_uri, 4, 0)}),
+        json!({"id": 9, "method": "workspace/symbol", "params": {}}),
+        json!({"id": 10, "method": "shutdown"}),
+        json!({"method": "exit"}),
+        json!({"id": 11, "method": "shutdown"}),
+    ];
+    let mut input = vec![];
+    for request in requests {
+        write_message(&mut input, &request).unwrap();
+    }
+    let mut output = vec![];
+    let mut server = LanguageServer::new(&LspStruct {
+        consts_file: Some("arb_os/constants.json".to_string()),
+        no_builtins: true,
+        ..LspStruct::default()
+    });
+    server.serve(&input[..], &mut output).unwrap();
+    assert!(server.shut_down);
+
+    let mut replies = BTreeMap::new();
+    let mut diagnostics = BTreeMap::new();
+    let mut output = &output[..];
+    while let Some(message) = read_message(&mut output).unwrap() {
+        match message["id"].as_u64() {
+            Some(id) => drop(replies.insert(id, message)),
+            None => {
+                let params = &message["params"];
+                let uri = params["uri"].as_str().unwrap().to_string();
+                diagnostics.insert(uri, params["diagnostics"].clone());
+            }
+        }
+    }
+    // nothing is read after exit
+    assert_eq!(
+        replies.keys().copied().collect::<Vec<_>>(),
+        (1..=10).collect::<Vec<_>>()
+    );
+    assert_eq!(replies[&1]["result"]["capabilities"]["hoverProvider"], true);
+
+    // the type error in the imported module is reported there, where it is
+    assert_eq!(diagnostics.len(), 1);
+    let errors = diagnostics[&other_uri].as_array().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0]["severity"], 1);
+    assert_eq!(errors[0]["range"]["start"]["line"], 6);
+
+    // helper is declared in the other module, which it's documented in
+    let span = |line: u64, start: u64, end: u64| json!({"start": {"line": line, "character": start}, "end": {"line": line, "character": end}});
+    assert_eq!(replies[&2]["result"]["uri"], other_uri);
+    assert_eq!(replies[&2]["result"]["range"], span(1, 12, 18));
+    assert_eq!(
+        replies[&3]["result"]["contents"]["value"],
+        "```mini\npublic func helper(x: uint) -> uint\n```\n\nDoubles a number"
+    );
+    assert_eq!(replies[&3]["result"]["range"], span(5, 16, 22));
+
+    // locals go to where they're bound, and globals to where they're declared
+    assert_eq!(replies[&4]["result"]["uri"], main_uri);
+    assert_eq!(replies[&4]["result"]["range"], span(5, 8, 13));
+    assert_eq!(
+        replies[&5]["result"]["contents"]["value"],
+        "```mini\nlet total: uint\n```"
+    );
+    assert_eq!(replies[&6]["result"]["range"], span(2, 4, 11));
+
+    // funcs are known even in modules that don't typecheck
+    assert_eq!(
+        replies[&7]["result"]["contents"]["value"],
+        "```mini\nfunc broken() -> uint\n```"
+    );
+
+    // there's nothing to show between names, and unknown requests are refused
+    assert_eq!(replies[&8]["result"], Value::Null);
+    assert_eq!(replies[&9]["error"]["code"], -32601);
+
+    fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_language_server_fixes() {
+    let folder = std::env::temp_dir().join(format!("mini-lsp-fixes-{}", std::process::id()));
+    drop(fs::remove_dir_all(&folder));
+    fs::create_dir_all(&folder).unwrap();
+    let folder = fs::canonicalize(&folder).unwrap();
+    let (main, other) = (folder.join("main.mini"), folder.join("other.mini"));
+    fs::write(
+        &main,
+        "use other::helper;\nuse other::spare;\n\nfunc main() -> uint {\n    \
+         let scratch = helper(1);\n    return helper(2);\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        &other,
+        "public func helper(x: uint) -> uint {\n    return x * 2;\n}\n\n\
+         public func spare() -> uint {\n    return 0;\n}\n",
+    )
+    .unwrap();
+
+    let mut server = LanguageServer::new(&LspStruct {
+        consts_file: Some("arb_os/constants.json".to_string()),
+        no_builtins: true,
+        ..LspStruct::default()
+    });
+    let main_uri = path_to_uri(&main);
+    let document = json!({"textDocument": {"uri": main_uri}});
+    let published = server.handle(&json!({"method": "textDocument/didOpen", "params": document}));
+    let diagnostics: Vec<_> = published
+        .iter()
+        .filter(|message| message["params"]["uri"] == main_uri)
+        .flat_map(|message| message["params"]["diagnostics"].as_array().unwrap().clone())
+        .filter(|diagnostic| !diagnostic["data"].is_null())
+        .collect();
+    assert_eq!(diagnostics.len(), 2);
+
+    let replies = server.handle(&json!({
+        "id": 1,
+        "method": "textDocument/codeAction",
+        "params": {
+            "textDocument": {"uri": main_uri},
+            "context": {"diagnostics": diagnostics},
+        },
+    }));
+    let actions = replies[0]["result"].as_array().unwrap();
+    let edit = |title: &str| {
+        let action = actions
+            .iter()
+            .find(|action| action["title"] == title)
+            .unwrap();
+        assert_eq!(action["kind"], "quickfix");
+        action["edit"]["changes"][&main_uri][0].clone()
+    };
+    let at = |line: u64, character: u64| json!({"line": line, "character": character});
+
+    // the unneeded use statement goes, line and all
+    let removal = edit("remove the use statement");
+    assert_eq!(
+        removal["range"],
+        json!({"start": at(1, 0), "end": at(2, 0)})
+    );
+    assert_eq!(removal["newText"], "");
+
+    // the unread let is marked unused where it's bound
+    let marking = edit("prefix it with an underscore");
+    assert_eq!(
+        marking["range"],
+        json!({"start": at(4, 8), "end": at(4, 8)})
+    );
+    assert_eq!(marking["newText"], "_");
+
+    fs::remove_dir_all(&folder).unwrap();
+}
@@ -0,0 +1,564 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides declarative macros, which are expanded in a module's source before it's parsed.
+//!
+//! A macro is declared at the top level of a module and may be used anywhere in it:
+//!
+//! ```text
+//! macro getter($func, $Type, $field, $FieldType) {
+//!     public func $func(item: $Type) -> $FieldType {
+//!         item.$field
+//!     }
+//! }
+//!
+//! getter!(account_getBalance, Account, ethBalance, uint);
+//! ```
+//!
+//! Each use is replaced by the body of the macro, with each parameter replaced by the tokens given
+//! for it. The semicolon after a use at the top level is dropped. Variables the body binds with
+//! `let` are renamed in each expansion, so they never capture the variables of the code passed in.
+
+use super::{CompileError, Lines};
+use crate::console::Color;
+use crate::pos::{BytePos, Location};
+use std::collections::{HashMap, HashSet};
+
+/// How deeply macros may expand into uses of other macros.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Kind {
+    /// Whitespace and comments
+    Trivia,
+    Ident,
+    /// A macro parameter, like `$name`
+    Param,
+    Literal,
+    Punct,
+}
+
+/// A token along with the byte in the original source it came from.
+#[derive(Clone, Debug)]
+pub(super) struct Token {
+    pub(super) kind: Kind,
+    pub(super) text: String,
+    origin: usize,
+}
+
+impl Token {
+    fn is(&self, text: &str) -> bool {
+        self.kind != Kind::Trivia && self.text == text
+    }
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+    origin: usize,
+}
+
+/// A module's source with its macros expanded.
+pub struct Expansion {
+    pub source: String,
+    /// The start of each stretch of the expanded source, paired with where it came from
+    pub origins: Vec<(BytePos, BytePos)>,
+}
+
+/// Expands the macros declared in source. Sources that neither declare nor use any are returned as
+/// they are, with no origins, since each byte comes from where it is.
+pub fn expand_macros(source: &str, file_id: u64) -> Result<Expansion, CompileError> {
+    let tokens = tokenize(source);
+    let uses_macros = tokens.iter().enumerate().any(|(index, token)| {
+        token.is("macro") || (token.kind == Kind::Ident && is_use(&tokens, index))
+    });
+    if !uses_macros {
+        return Ok(Expansion {
+            source: source.to_string(),
+            origins: vec![],
+        });
+    }
+
+    let lines = Lines::new(source.bytes());
+    let mut expander = Expander {
+        lines: &lines,
+        file_id,
+        macros: HashMap::new(),
+        expansions: 0,
+    };
+    let tokens = expander.collect_macros(tokens)?;
+    let tokens = expander.expand(tokens, 0, true)?;
+
+    let mut expanded = String::new();
+    let mut origins = vec![];
+    for token in tokens {
+        origins.push((BytePos::from(expanded.len()), BytePos::from(token.origin)));
+        expanded.push_str(&token.text);
+    }
+    Ok(Expansion {
+        source: expanded,
+        origins,
+    })
+}
+
+struct Expander<'a> {
+    lines: &'a Lines,
+    file_id: u64,
+    macros: HashMap<String, Macro>,
+    /// How many expansions there have been, which keeps the variables of each distinct
+    expansions: usize,
+}
+
+impl Expander<'_> {
+    fn error(&self, description: String, origin: usize) -> CompileError {
+        CompileError::new(
+            "Macro error",
+            description,
+            self.location(origin).into_iter().collect(),
+        )
+    }
+
+    fn location(&self, origin: usize) -> Option<Location> {
+        self.lines.location(BytePos::from(origin), self.file_id)
+    }
+
+    /// Removes the macro declarations at the top level of tokens, remembering them.
+    fn collect_macros(&mut self, tokens: Vec<Token>) -> Result<Vec<Token>, CompileError> {
+        let mut rest = vec![];
+        let mut depth = 0;
+        let mut index = 0;
+        while index < tokens.len() {
+            let token = &tokens[index];
+            if depth > 0 || !token.is("macro") {
+                depth = nesting(depth, token);
+                rest.push(token.clone());
+                index += 1;
+                continue;
+            }
+
+            let origin = token.origin;
+            let mut cursor = Cursor::new(&tokens, index + 1);
+            let name = match cursor.next() {
+                Some(name) if name.kind == Kind::Ident => name.text.clone(),
+                _ => return Err(self.error("Expected a name after macro".to_string(), origin)),
+            };
+            if !cursor.eat("(") {
+                return Err(self.error(
+                    format!("Expected the parameters of macro {}", Color::red(&name)),
+                    origin,
+                ));
+            }
+            let mut params = vec![];
+            while !cursor.eat(")") {
+                match cursor.next() {
+                    Some(param) if param.kind == Kind::Param => params.push(param.text.clone()),
+                    _ => {
+                        return Err(self.error(
+                            format!(
+                                "The parameters of macro {} must each be written like $name",
+                                Color::red(&name)
+                            ),
+                            origin,
+                        ))
+                    }
+                }
+                if !cursor.eat(",") && !cursor.peek_is(")") {
+                    return Err(self.error(
+                        format!("Expected a comma between the parameters of {}", name),
+                        origin,
+                    ));
+                }
+            }
+            if !cursor.eat("{") {
+                return Err(self.error(
+                    format!("Expected the body of macro {}", Color::red(&name)),
+                    origin,
+                ));
+            }
+            let start = cursor.index;
+            let end = match matching_close(&tokens, start - 1) {
+                Some(end) => end,
+                None => {
+                    return Err(self.error(
+                        format!("The body of macro {} is never closed", Color::red(&name)),
+                        origin,
+                    ))
+                }
+            };
+            let body = tokens[start..end].to_vec();
+            for token in &body {
+                if token.kind == Kind::Param && !params.contains(&token.text) {
+                    return Err(self.error(
+                        format!(
+                            "Macro {} has no parameter {}",
+                            Color::red(&name),
+                            Color::red(&token.text)
+                        ),
+                        token.origin,
+                    ));
+                }
+            }
+            if self.macros.contains_key(&name) {
+                return Err(self.error(
+                    format!("Macro {} is declared more than once", Color::red(&name)),
+                    origin,
+                ));
+            }
+            self.macros.insert(
+                name,
+                Macro {
+                    params,
+                    body,
+                    origin,
+                },
+            );
+            index = end + 1;
+        }
+        Ok(rest)
+    }
+
+    /// Replaces the uses of macros in tokens with their expansions, which are themselves expanded.
+    fn expand(
+        &mut self,
+        tokens: Vec<Token>,
+        depth: usize,
+        top_level: bool,
+    ) -> Result<Vec<Token>, CompileError> {
+        let mut expanded = vec![];
+        let mut nested = 0;
+        let mut index = 0;
+        while index < tokens.len() {
+            let token = &tokens[index];
+            if token.kind != Kind::Ident || !is_use(&tokens, index) {
+                nested = nesting(nested, token);
+                expanded.push(token.clone());
+                index += 1;
+                continue;
+            }
+
+            let name = &token.text;
+            let origin = token.origin;
+            if depth >= MAX_DEPTH {
+                return Err(self.error(
+                    format!(
+                        "Macro {} expands more than {} levels deep",
+                        Color::red(name),
+                        MAX_DEPTH
+                    ),
+                    origin,
+                ));
+            }
+            let close = match matching_close(&tokens, index + 2) {
+                Some(close) => close,
+                None => {
+                    return Err(self.error(
+                        format!("The use of macro {} is never closed", Color::red(name)),
+                        origin,
+                    ))
+                }
+            };
+            let args = split_args(&tokens[index + 3..close]);
+            let body = self.substitute(name, args, origin)?;
+            expanded.extend(self.expand(body, depth + 1, top_level && nested == 0)?);
+
+            index = close + 1;
+            if top_level && nested == 0 {
+                let mut cursor = Cursor::new(&tokens, index);
+                if cursor.eat(";") {
+                    index = cursor.index;
+                }
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Gets the body of the macro name, with its parameters replaced by args.
+    fn substitute(
+        &mut self,
+        name: &str,
+        args: Vec<Vec<Token>>,
+        origin: usize,
+    ) -> Result<Vec<Token>, CompileError> {
+        let mac = match self.macros.get(name) {
+            Some(mac) => mac,
+            None => return Err(self.error(format!("Unknown macro {}", Color::red(name)), origin)),
+        };
+        if mac.params.len() != args.len() {
+            let declared = self
+                .location(mac.origin)
+                .map(|loc| format!(", as declared on line {}", loc.line))
+                .unwrap_or_default();
+            return Err(self.error(
+                format!(
+                    "Macro {} takes {} args but was given {}{}",
+                    Color::red(name),
+                    mac.params.len(),
+                    args.len(),
+                    declared
+                ),
+                origin,
+            ));
+        }
+
+        self.expansions += 1;
+        let bound = let_bindings(&mac.body);
+        let mut body = vec![];
+        for (index, token) in mac.body.iter().enumerate() {
+            let is_field = index > 0 && mac.body[index - 1].is(".");
+            match token.kind {
+                Kind::Param => {
+                    let param = mac.params.iter().position(|param| param == &token.text);
+                    body.extend(args[param.unwrap()].iter().cloned());
+                }
+                Kind::Ident if bound.contains(&token.text) && !is_field => body.push(Token {
+                    text: format!("{}__{}", token.text, self.expansions),
+                    ..token.clone()
+                }),
+                _ => body.push(token.clone()),
+            }
+        }
+        Ok(body)
+    }
+}
+
+/// Walks the tokens of a macro declaration, skipping trivia.
+struct Cursor<'a> {
+    tokens: &'a [Token],
+    index: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(tokens: &'a [Token], index: usize) -> Self {
+        Cursor { tokens, index }
+    }
+
+    fn skip_trivia(&mut self) {
+        while self.index < self.tokens.len() && self.tokens[self.index].kind == Kind::Trivia {
+            self.index += 1;
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        self.skip_trivia();
+        let token = self.tokens.get(self.index)?;
+        self.index += 1;
+        Some(token)
+    }
+
+    fn peek_is(&mut self, text: &str) -> bool {
+        self.skip_trivia();
+        self.tokens
+            .get(self.index)
+            .map_or(false, |token| token.is(text))
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        match self.peek_is(text) {
+            true => {
+                self.index += 1;
+                true
+            }
+            false => false,
+        }
+    }
+}
+
+/// Whether the token at index names a macro being used, as in `name!(`.
+fn is_use(tokens: &[Token], index: usize) -> bool {
+    tokens.get(index + 1).map_or(false, |next| next.is("!"))
+        && tokens.get(index + 2).map_or(false, |next| next.is("("))
+}
+
+/// Tracks how deeply nested within brackets a token leaves things.
+fn nesting(depth: usize, token: &Token) -> usize {
+    match token.kind {
+        Kind::Punct => match token.text.as_str() {
+            "(" | "[" | "{" => depth + 1,
+            ")" | "]" | "}" => depth.saturating_sub(1),
+            _ => depth,
+        },
+        _ => depth,
+    }
+}
+
+/// Finds the bracket closing the one at open.
+fn matching_close(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(open) {
+        let after = nesting(depth, token);
+        if after < depth && after == 0 {
+            return Some(index);
+        }
+        depth = after;
+    }
+    None
+}
+
+/// Splits the tokens between the parentheses of a macro use at its top level commas, trimming the
+/// trivia around each arg.
+fn split_args(tokens: &[Token]) -> Vec<Vec<Token>> {
+    let mut args = vec![vec![]];
+    let mut depth = 0;
+    for token in tokens {
+        if depth == 0 && token.is(",") {
+            args.push(vec![]);
+            continue;
+        }
+        depth = nesting(depth, token);
+        args.last_mut().unwrap().push(token.clone());
+    }
+    for arg in &mut args {
+        while arg.last().map_or(false, |token| token.kind == Kind::Trivia) {
+            arg.pop();
+        }
+        let leading = arg
+            .iter()
+            .take_while(|token| token.kind == Kind::Trivia)
+            .count();
+        arg.drain(..leading);
+    }
+    // a use with nothing between its parentheses has no args, rather than one empty one
+    if args.len() == 1 && args[0].is_empty() {
+        args.clear();
+    }
+    args
+}
+
+/// Finds the variables a macro body binds with let, which are those between a let and its equals
+/// sign, other than the names of patterns like `Some`.
+fn let_bindings(body: &[Token]) -> HashSet<String> {
+    let mut bound = HashSet::new();
+    let mut binding = false;
+    for (index, token) in body.iter().enumerate() {
+        if token.is("let") {
+            binding = true;
+        } else if token.is("=") {
+            binding = false;
+        } else if binding && token.kind == Kind::Ident && token.text != "_" {
+            let next = body[index + 1..]
+                .iter()
+                .find(|token| token.kind != Kind::Trivia);
+            if !next.map_or(false, |next| next.is("(")) {
+                bound.insert(token.text.clone());
+            }
+        }
+    }
+    bound
+}
+
+/// Splits source into tokens.
+pub(super) fn tokenize(source: &str) -> Vec<Token> {
+    let bytes = source.as_bytes();
+    let mut tokens = vec![];
+    let mut start = 0;
+    while start < bytes.len() {
+        let rest = &source[start..];
+        let ident_len = |from: usize| {
+            rest[from..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .map_or(rest.len(), |end| from + end)
+        };
+        let (kind, len) = match bytes[start] {
+            c if c.is_ascii_whitespace() => (
+                Kind::Trivia,
+                rest.find(|c: char| !c.is_ascii_whitespace())
+                    .unwrap_or(rest.len()),
+            ),
+            b'/' if rest.starts_with("//") => (Kind::Trivia, rest.find('\n').unwrap_or(rest.len())),
+            b'/' if rest.starts_with("/*") => (
+                Kind::Trivia,
+                rest[2..].find("*/").map_or(rest.len(), |end| end + 4),
+            ),
+            b'"' => {
+                let mut len = 1;
+                let mut escaped = false;
+                for c in rest[1..].chars() {
+                    len += c.len_utf8();
+                    match c {
+                        '\\' if !escaped => escaped = true,
+                        '"' if !escaped => break,
+                        _ => escaped = false,
+                    }
+                }
+                (Kind::Literal, len)
+            }
+            b'$' if ident_len(1) > 1 => (Kind::Param, ident_len(1)),
+            c if c.is_ascii_digit() => (Kind::Literal, ident_len(0)),
+            c if c.is_ascii_alphabetic() || c == b'_' => (Kind::Ident, ident_len(0)),
+            _ => (Kind::Punct, rest.chars().next().unwrap().len_utf8()),
+        };
+        tokens.push(Token {
+            kind,
+            text: rest[..len].to_string(),
+            origin: start,
+        });
+        start += len;
+    }
+    tokens
+}
+
+#[test]
+fn test_macro_expansion() {
+    let source = "\
+macro getter($func, $field) {
+    public func $func(item: Item) -> uint {
+        let value = item.$field;
+        value
+    }
+}
+
+getter!(item_getCount, count);
+getter!(item_getSize, size);
+func main(value: uint) -> uint { twice!(value) }
+macro twice($x) { ($x + $x) }
+";
+    let expansion = expand_macros(source, 0).unwrap();
+    let expanded: Vec<&str> = expansion
+        .source
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+    assert_eq!(
+        expanded,
+        vec![
+            "public func item_getCount(item: Item) -> uint {",
+            "let value__1 = item.count;",
+            "value__1",
+            "}",
+            "public func item_getSize(item: Item) -> uint {",
+            "let value__2 = item.size;",
+            "value__2",
+            "}",
+            "func main(value: uint) -> uint {  (value + value)  }",
+        ]
+    );
+
+    // locations within expansions lead back to where their tokens were written
+    let lines = Lines::new(source.bytes()).with_origins(expansion.origins);
+    let field = expansion.source.find("item.size").unwrap() + "item.".len();
+    let location = lines.location(BytePos::from(field), 0).unwrap();
+    assert_eq!((location.line(), location.column.to_usize()), (8, 22));
+    let body = expansion.source.find("item.count").unwrap();
+    let location = lines.location(BytePos::from(body), 0).unwrap();
+    assert_eq!((location.line(), location.column.to_usize()), (2, 20));
+
+    let error = |source: &str| match expand_macros(source, 0) {
+        Ok(_) => panic!("expanded {}", source),
+        Err(error) => Color::uncolored(error.description),
+    };
+    assert_eq!(
+        error("macro m($a) { $a }\nfunc f() -> uint { m!(1, 2) }"),
+        "Macro m takes 1 args but was given 2, as declared on line 1"
+    );
+    assert_eq!(error("macro m($a) { $b }"), "Macro m has no parameter $b");
+    assert_eq!(
+        error("macro m() { n!() }\nfunc f() { m!() }"),
+        "Unknown macro n"
+    );
+    assert_eq!(
+        error("macro m() { m!() }\nfunc f() { m!() }"),
+        "Macro m expands more than 64 levels deep"
+    );
+}
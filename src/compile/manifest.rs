@@ -0,0 +1,172 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the manifest a program's folder can describe its modules with.
+
+use super::{path_display, CompileError, Module};
+use crate::console::Color;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// The name of the manifest within a program's folder.
+pub const MANIFEST_FILE: &str = "manifest.toml";
+
+/// The module the compiler writes to register the precompiles a manifest lists.
+pub const PRECOMPILE_EXTENSIONS: &str = "precompileExtensions";
+
+/// What a program's `manifest.toml` says about its modules. A folder without one has a manifest
+/// that says nothing.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// The modules release builds leave out, written as paths like `inspect` or `std::debug`.
+    /// Release builds fail if anything else reaches them.
+    #[serde(default)]
+    debug_only: Vec<String>,
+    /// The precompiles extension modules implement, which are registered along with those the
+    /// program dispatches to itself.
+    #[serde(default)]
+    precompiles: Vec<Precompile>,
+}
+
+/// A precompile implemented by an extension module, written in the manifest as
+///
+/// ```toml
+/// [[precompiles]]
+/// module = "arbexperimental"
+/// address = 0x70
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Precompile {
+    /// The module implementing the precompile
+    module: String,
+    /// The address the precompile is called at
+    address: u64,
+    /// The func the precompile's calls go to, `<module>_txcall` unless given
+    #[serde(default)]
+    func: Option<String>,
+}
+
+impl Precompile {
+    fn func(&self) -> String {
+        match &self.func {
+            Some(func) => func.clone(),
+            None => format!("{}_txcall", self.module),
+        }
+    }
+}
+
+impl Manifest {
+    /// Reads the manifest in folder, if there is one.
+    pub fn load(folder: &Path) -> Result<Self, CompileError> {
+        let path = folder.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+        let manifest_error = |description: String| {
+            CompileError::new(String::from("Manifest error"), description, vec![])
+        };
+        let text = fs::read_to_string(&path).map_err(|e| {
+            manifest_error(format!(
+                "Could not read {}: {}",
+                Color::red(path.display()),
+                e
+            ))
+        })?;
+        toml::from_str(&text).map_err(|e| {
+            manifest_error(format!(
+                "Could not parse {}: {}",
+                Color::red(path.display()),
+                e
+            ))
+        })
+    }
+
+    /// Reports the precompiles that couldn't be registered: those whose module or func isn't
+    /// named like one, and those sharing an address with another.
+    pub(super) fn check_precompiles(&self) -> Vec<CompileError> {
+        let mut errors = vec![];
+        let mut addresses = BTreeSet::new();
+        for precompile in &self.precompiles {
+            let manifest_error = |description: String| {
+                CompileError::new(String::from("Manifest error"), description, vec![])
+            };
+            for name in &[precompile.module.clone(), precompile.func()] {
+                let identifier = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    && name.chars().next().map_or(false, |c| !c.is_ascii_digit());
+                if !identifier {
+                    errors.push(manifest_error(format!(
+                        "Precompile at {:#x} names {}, which isn't an identifier",
+                        precompile.address,
+                        Color::red(name)
+                    )));
+                }
+            }
+            if !addresses.insert(precompile.address) {
+                errors.push(manifest_error(format!(
+                    "Precompiles share the address {}",
+                    Color::red(format!("{:#x}", precompile.address))
+                )));
+            }
+        }
+        errors
+    }
+
+    /// The source of a module the manifest asks the compiler to write, if path names one.
+    ///
+    /// The precompile extensions module registers each precompile the manifest lists through the
+    /// program's `precompiles` module, which provides the `PrecompileRegistry` they're added to
+    /// and the `precompiles_register` func adding them.
+    pub(super) fn generated_module(&self, path: &[String]) -> Option<(String, String)> {
+        if path != [PRECOMPILE_EXTENSIONS] {
+            return None;
+        }
+        let mut imports = String::from(
+            "use precompiles::PrecompileRegistry;\nuse precompiles::precompiles_register;\n",
+        );
+        let mut body = String::new();
+        for precompile in &self.precompiles {
+            imports += &format!("use {}::{};\n", precompile.module, precompile.func());
+            body += &format!(
+                "    registry = precompiles_register(registry, address({:#x}), {})?;\n",
+                precompile.address,
+                precompile.func()
+            );
+        }
+        let source = format!(
+            "// written by the compiler from the precompiles in {}\n\n{}\n\
+             public view write func {}_register(registry: PrecompileRegistry) \
+             -> option<PrecompileRegistry> {{\n{}    return Some(registry);\n}}\n",
+            MANIFEST_FILE, imports, PRECOMPILE_EXTENSIONS, body
+        );
+        Some((format!("{}.mini", PRECOMPILE_EXTENSIONS), source))
+    }
+
+    /// Reports the debug-only modules that aren't part of the program.
+    pub(super) fn check(&self, modules: &BTreeMap<Vec<String>, Module>) -> Vec<CompileError> {
+        self.debug_only
+            .iter()
+            .filter(|name| !modules.keys().any(|path| &path_display(path) == *name))
+            .map(|name| {
+                CompileError::new(
+                    String::from("Manifest error"),
+                    format!(
+                        "Module {} is marked debug-only, but isn't part of the program",
+                        Color::red(name)
+                    ),
+                    vec![],
+                )
+            })
+            .collect()
+    }
+
+    /// Whether the module at path is left out of release builds.
+    pub fn is_debug_only(&self, path: &Vec<String>) -> bool {
+        let name = path_display(path);
+        self.debug_only.iter().any(|debug| debug == &name)
+    }
+}
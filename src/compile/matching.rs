@@ -0,0 +1,242 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides exhaustiveness and reachability checking for the arms of match expressions.
+
+use super::ast::{Constant, Pattern, Type, TypeTree};
+
+/// A pattern stripped of its bindings and described by the constructors of the types it matches.
+#[derive(Clone, Debug, PartialEq)]
+enum Space {
+    Any,
+    Ctor(Ctor, Vec<Space>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Ctor {
+    Some,
+    None,
+    Bool(bool),
+    Tuple,
+    Const(Constant),
+    Variant(String),
+}
+
+impl Space {
+    fn from(pattern: &Pattern) -> Self {
+        match pattern {
+            Pattern::Wildcard | Pattern::Bind(_) => Space::Any,
+            Pattern::Const(Constant::Bool(value)) => Space::Ctor(Ctor::Bool(*value), vec![]),
+            Pattern::Const(constant) => Space::Ctor(Ctor::Const(constant.clone()), vec![]),
+            Pattern::Some(inner) => Space::Ctor(Ctor::Some, vec![Space::from(inner)]),
+            Pattern::None => Space::Ctor(Ctor::None, vec![]),
+            Pattern::Tuple(fields) => {
+                Space::Ctor(Ctor::Tuple, fields.iter().map(Space::from).collect())
+            }
+            Pattern::Variant(_, name, fields) => Space::Ctor(
+                Ctor::Variant(name.clone()),
+                fields.iter().map(Space::from).collect(),
+            ),
+        }
+    }
+
+    fn print(&self) -> String {
+        let fields = |fields: &[Space]| {
+            fields
+                .iter()
+                .map(|field| field.print())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Space::Any => "_".to_string(),
+            Space::Ctor(Ctor::Some, inner) => format!("Some({})", fields(inner)),
+            Space::Ctor(Ctor::None, _) => "None".to_string(),
+            Space::Ctor(Ctor::Bool(value), _) => value.to_string(),
+            Space::Ctor(Ctor::Tuple, inner) => format!("({})", fields(inner)),
+            Space::Ctor(Ctor::Const(Constant::Uint(value)), _)
+            | Space::Ctor(Ctor::Const(Constant::Int(value)), _) => value.to_string(),
+            Space::Ctor(Ctor::Const(_), _) => "_".to_string(),
+            Space::Ctor(Ctor::Variant(name), inner) if inner.is_empty() => name.clone(),
+            Space::Ctor(Ctor::Variant(name), inner) => format!("{}({})", name, fields(inner)),
+        }
+    }
+}
+
+/// Lists every constructor of a type along with the types of its fields, or `None` when there are
+/// too many to enumerate, as is the case for integers.
+fn constructors(tipe: &Type, type_tree: &TypeTree) -> Option<Vec<(Ctor, Vec<Type>)>> {
+    match tipe.rep(type_tree).ok()? {
+        Type::Option(inner) => Some(vec![(Ctor::Some, vec![*inner]), (Ctor::None, vec![])]),
+        Type::Bool => Some(vec![
+            (Ctor::Bool(false), vec![]),
+            (Ctor::Bool(true), vec![]),
+        ]),
+        Type::Tuple(fields) => Some(vec![(Ctor::Tuple, fields)]),
+        Type::String => Some(vec![(Ctor::Tuple, Type::string_fields())]),
+        Type::Enum(variants) => Some(
+            variants
+                .into_iter()
+                .map(|variant| (Ctor::Variant(variant.name), variant.fields))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// Gets the constructors of a type when the first column of rows uses every one of them.
+fn complete(
+    rows: &[Vec<Space>],
+    tipe: &Type,
+    type_tree: &TypeTree,
+) -> Option<Vec<(Ctor, Vec<Type>)>> {
+    let ctors = constructors(tipe, type_tree)?;
+    let used = |ctor: &Ctor| {
+        rows.iter()
+            .any(|row| matches!(&row[0], Space::Ctor(other, _) if other == ctor))
+    };
+    match ctors.iter().all(|(ctor, _)| used(ctor)) {
+        true => Some(ctors),
+        false => None,
+    }
+}
+
+/// Keeps the rows that could match a value built by ctor, replacing their first column with the
+/// fields of that value.
+fn specialize(rows: &[Vec<Space>], ctor: &Ctor, arity: usize) -> Vec<Vec<Space>> {
+    rows.iter()
+        .filter_map(|row| {
+            let rest = row[1..].iter().cloned();
+            match &row[0] {
+                Space::Any => Some(vec![Space::Any; arity].into_iter().chain(rest).collect()),
+                Space::Ctor(other, fields) if other == ctor => {
+                    Some(fields.iter().cloned().chain(rest).collect())
+                }
+                Space::Ctor(..) => None,
+            }
+        })
+        .collect()
+}
+
+/// Keeps the rows that match anything in their first column, dropping that column.
+fn default(rows: &[Vec<Space>]) -> Vec<Vec<Space>> {
+    rows.iter()
+        .filter(|row| row[0] == Space::Any)
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+fn field_types(ctor: &Ctor, tipe: &Type, type_tree: &TypeTree) -> Vec<Type> {
+    constructors(tipe, type_tree)
+        .into_iter()
+        .flatten()
+        .find(|(other, _)| other == ctor)
+        .map(|(_, fields)| fields)
+        .unwrap_or_default()
+}
+
+/// Whether some value matched by row isn't matched by any of rows.
+fn useful(rows: &[Vec<Space>], row: &[Space], types: &[Type], type_tree: &TypeTree) -> bool {
+    if row.is_empty() {
+        return rows.is_empty();
+    }
+    let (rest, rest_types) = (&row[1..], &types[1..]);
+
+    match &row[0] {
+        Space::Ctor(ctor, fields) => {
+            let row: Vec<_> = fields.iter().chain(rest).cloned().collect();
+            let types = [field_types(ctor, &types[0], type_tree), rest_types.to_vec()].concat();
+            useful(
+                &specialize(rows, ctor, fields.len()),
+                &row,
+                &types,
+                type_tree,
+            )
+        }
+        Space::Any => match complete(rows, &types[0], type_tree) {
+            Some(ctors) => ctors.into_iter().any(|(ctor, fields)| {
+                let row: Vec<_> = vec![Space::Any; fields.len()]
+                    .into_iter()
+                    .chain(rest.iter().cloned())
+                    .collect();
+                let types = [fields.clone(), rest_types.to_vec()].concat();
+                useful(
+                    &specialize(rows, &ctor, fields.len()),
+                    &row,
+                    &types,
+                    type_tree,
+                )
+            }),
+            None => useful(&default(rows), rest, rest_types, type_tree),
+        },
+    }
+}
+
+/// Finds values of the given types that none of rows match.
+fn missing(rows: &[Vec<Space>], types: &[Type], type_tree: &TypeTree) -> Option<Vec<Space>> {
+    if types.is_empty() {
+        return match rows.is_empty() {
+            true => Some(vec![]),
+            false => None,
+        };
+    }
+    let rest_types = &types[1..];
+
+    if let Some(ctors) = complete(rows, &types[0], type_tree) {
+        for (ctor, fields) in ctors {
+            let arity = fields.len();
+            let types = [fields, rest_types.to_vec()].concat();
+            if let Some(mut value) = missing(&specialize(rows, &ctor, arity), &types, type_tree) {
+                let rest = value.split_off(arity);
+                return Some(
+                    vec![Space::Ctor(ctor, value)]
+                        .into_iter()
+                        .chain(rest)
+                        .collect(),
+                );
+            }
+        }
+        return None;
+    }
+
+    let mut value = missing(&default(rows), rest_types, type_tree)?;
+    let unused = constructors(&types[0], type_tree).and_then(|ctors| {
+        ctors.into_iter().find(|(ctor, _)| {
+            !rows
+                .iter()
+                .any(|row| matches!(&row[0], Space::Ctor(other, _) if other == ctor))
+        })
+    });
+    value.insert(
+        0,
+        match unused {
+            Some((ctor, fields)) => Space::Ctor(ctor, vec![Space::Any; fields.len()]),
+            None => Space::Any,
+        },
+    );
+    Some(value)
+}
+
+/// Checks that the patterns of a match on a value of type tipe cover every value it could hold.
+/// On success this returns the indices of the patterns that can never be reached since earlier
+/// ones match everything they would. Otherwise, this returns an example of a value not covered.
+pub fn check_patterns(
+    patterns: &[&Pattern],
+    tipe: &Type,
+    type_tree: &TypeTree,
+) -> Result<Vec<usize>, String> {
+    let rows: Vec<Vec<Space>> = patterns
+        .iter()
+        .map(|pattern| vec![Space::from(pattern)])
+        .collect();
+    let types = vec![tipe.clone()];
+
+    if let Some(value) = missing(&rows, &types, type_tree) {
+        return Err(value[0].print());
+    }
+
+    Ok((0..rows.len())
+        .filter(|index| !useful(&rows[..*index], &rows[*index], &types, type_tree))
+        .collect())
+}
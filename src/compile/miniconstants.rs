@@ -5,6 +5,7 @@
 //! Creates a fixed list of globally accessible constants.
 
 use crate::compile::CompileError;
+use crate::console::Color;
 use crate::evm::{contract_path, AbiForContract};
 use crate::uint256::Uint256;
 use keccak_hash::keccak;
@@ -25,17 +26,13 @@ pub struct ConstantsFile {
     parameters_hex: BTreeMap<String, String>,
 }
 
-/// Creates a fixed list of globally accessible constants.
-pub fn init_constant_table(
-    constants_path: Option<&Path>,
-) -> Result<HashMap<String, Uint256>, CompileError> {
-    let mut ret = HashMap::new();
-
-    let consts = if let Some(consts_file) = constants_path {
-        let mut file = File::open(consts_file).map_err(|_| {
+impl ConstantsFile {
+    /// Reads the constants file at path.
+    pub fn read(path: &Path) -> Result<Self, CompileError> {
+        let mut file = File::open(path).map_err(|_| {
             CompileError::new(
                 String::from("Compile error"),
-                format!("Could not open constants file {:?}", consts_file),
+                format!("Could not open constants file {:?}", path),
                 vec![],
             )
         })?;
@@ -43,19 +40,90 @@ pub fn init_constant_table(
         file.read_to_string(&mut consts_string).map_err(|_| {
             CompileError::new(
                 String::from("Compile error"),
-                format!("Could not read file {:?} to a string", consts_file),
+                format!("Could not read file {:?} to a string", path),
                 vec![],
             )
         })?;
         serde_json::from_str::<ConstantsFile>(&consts_string).map_err(|_| {
             CompileError::new(
                 String::from("Compile error"),
-                format!("Could not parse {:?} as constants file", consts_file),
+                format!("Could not parse {:?} as constants file", path),
                 vec![],
             )
-        })?
-    } else {
-        ConstantsFile::default()
+        })
+    }
+
+    /// Overrides the value of the constant named name, which must be one the file defines.
+    /// Parameters are overridden by the name of their default, as in `Default_Param_X`.
+    pub fn define(&mut self, name: &str, value: &Uint256) -> Result<(), CompileError> {
+        if name == "ArbosVersionNumber" {
+            self.arbos_version = value.to_u64().ok_or_else(|| {
+                CompileError::new(
+                    "Define error",
+                    format!("{} must fit in 64 bits", Color::red(name)),
+                    vec![],
+                )
+            })?;
+            return Ok(());
+        }
+        let (ints, hexes, key) = match name.strip_prefix("Default_Param_") {
+            Some(param) => (&mut self.parameters_int, &mut self.parameters_hex, param),
+            None => (&mut self.integer, &mut self.hex, name),
+        };
+        if !ints.contains_key(key) && !hexes.contains_key(key) {
+            return Err(CompileError::new(
+                "Define error",
+                format!(
+                    "The constants file defines no constant named {}",
+                    Color::red(name)
+                ),
+                vec![],
+            ));
+        }
+        ints.remove(key);
+        hexes.remove(key);
+        match value.to_u64() {
+            Some(int) => {
+                ints.insert(key.to_string(), int);
+            }
+            None => {
+                hexes.insert(key.to_string(), hex::encode(value.to_bytes_be()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a definition given as `NAME=VALUE`, where the value is decimal or hex prefixed by `0x`.
+pub fn parse_define(define: &str) -> Result<(String, Uint256), CompileError> {
+    let error = || {
+        CompileError::new(
+            "Define error",
+            format!(
+                "Could not parse {} as a definition of the form NAME=VALUE",
+                Color::red(define)
+            ),
+            vec![],
+        )
+    };
+    let (name, value) = define.split_once('=').ok_or_else(error)?;
+    let value = match value.strip_prefix("0x") {
+        Some(hex) => Uint256::from_string_hex(hex),
+        None => Uint256::from_string(value),
+    }
+    .ok_or_else(error)?;
+    Ok((name.trim().to_string(), value))
+}
+
+/// Creates a fixed list of globally accessible constants.
+pub fn init_constant_table(
+    constants_path: Option<&Path>,
+) -> Result<HashMap<String, Uint256>, CompileError> {
+    let mut ret = HashMap::new();
+
+    let consts = match constants_path {
+        Some(consts_file) => ConstantsFile::read(consts_file)?,
+        None => ConstantsFile::default(),
     };
 
     for (s, i) in consts.integer {
@@ -162,31 +230,9 @@ pub fn make_parameters_list(
 ) -> Result<BTreeMap<String, String>, CompileError> {
     let mut ret = BTreeMap::new();
 
-    let consts = if let Some(consts_file) = constants_path {
-        let mut file = File::open(consts_file).map_err(|_| {
-            CompileError::new(
-                String::from("Compile error"),
-                format!("Could not open constants file {:?}", consts_file),
-                vec![],
-            )
-        })?;
-        let mut consts_string = String::new();
-        file.read_to_string(&mut consts_string).map_err(|_| {
-            CompileError::new(
-                String::from("Compile error"),
-                format!("Could not read file {:?} to a string", consts_file),
-                vec![],
-            )
-        })?;
-        serde_json::from_str::<ConstantsFile>(&consts_string).map_err(|_| {
-            CompileError::new(
-                String::from("Compile error"),
-                format!("Could not parse {:?} as constants file", consts_file),
-                vec![],
-            )
-        })?
-    } else {
-        ConstantsFile::default()
+    let consts = match constants_path {
+        Some(consts_file) => ConstantsFile::read(consts_file)?,
+        None => ConstantsFile::default(),
     };
 
     for (s, _) in consts.parameters_int {
@@ -201,3 +247,36 @@ pub fn make_parameters_list(
 
     Ok(ret)
 }
+
+#[test]
+fn test_define() {
+    let mut consts = ConstantsFile::read(Path::new("arb_os/constants.json")).unwrap();
+    let big = Uint256::from_string_hex("1111000000000000000000000000000000001112").unwrap();
+    for define in &[
+        "AVM_add=7",
+        "HashOfEmptyCode=0x10",
+        "L1ToL2MapConstant=0x1111000000000000000000000000000000001112",
+        "Default_Param_FeesEnabled=1",
+        "ArbosVersionNumber=99",
+    ] {
+        let (name, value) = parse_define(define).unwrap();
+        consts.define(&name, &value).unwrap();
+    }
+    let path = std::env::temp_dir().join(format!("test-{}.json", std::process::id()));
+    std::fs::write(&path, serde_json::to_string(&consts).unwrap()).unwrap();
+    let table = init_constant_table(Some(&path)).unwrap();
+    assert_eq!(table["AVM_add"], Uint256::from_u64(7));
+    assert_eq!(table["HashOfEmptyCode"], Uint256::from_u64(16));
+    assert_eq!(table["L1ToL2MapConstant"], big);
+    assert_eq!(table["Default_Param_FeesEnabled"], Uint256::one());
+    assert_eq!(table["ArbosVersionNumber"], Uint256::from_u64(99));
+    std::fs::remove_file(&path).unwrap();
+
+    // only the constants the file defines can be overridden
+    assert!(consts.define("Missing", &Uint256::one()).is_err());
+    assert!(consts
+        .define("Atom_Param_FeesEnabled", &Uint256::one())
+        .is_err());
+    assert!(parse_define("AVM_add").is_err());
+    assert!(parse_define("AVM_add=0xg").is_err());
+}
@@ -5,39 +5,75 @@
 //! Contains utilities for compiling mini source code.
 
 use crate::console::Color;
-use crate::link::{link, postlink_compile, Import, LinkedProgram};
-use crate::mavm::{Instruction, Label, LabelId};
+use crate::link::{
+    check_target_version, func_names, link, link_cache_path, postlink_compile, sidecar_path,
+    symbol_map_path, symbol_table, CallSites, Import, JumpProfile, LibraryArchive, LinkCache,
+    LinkedProgram,
+};
+use crate::mavm::{stable_hash, Instruction, Label, LabelId, Opcode};
 use crate::optimize::BasicGraph;
-use crate::pos::{BytePos, Location};
+use crate::pos::{BytePos, Column, Location};
 use crate::stringtable::{StringId, StringTable};
-use ast::Func;
+use crate::uint256::Uint256;
+use ast::{ConstDecl, Func, ImplDecl, ImplMethod, InterfaceDecl, StaticAssert, TypeDecl};
+use cache::ModuleCache;
 use clap::Clap;
+use emit::Emitter;
+use hooks::AttributeHooks;
 use lalrpop_util::lalrpop_mod;
 use lalrpop_util::ParseError;
+use manifest::Manifest;
 use mini::DeclsParser;
-use miniconstants::init_constant_table;
+use miniconstants::{init_constant_table, parse_define, ConstantsFile};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::hash_map::DefaultHasher;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt::{Debug, Display, Formatter};
 use std::fs::File;
-use std::hash::{Hash, Hasher};
 use std::io::{self, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use typecheck::TypeCheckedFunc;
 
+#[cfg(test)]
+pub use ast::Attributes;
 pub use ast::{DebugInfo, FuncProperties, GlobalVar, StructField, TopLevelDecl, Type, TypeTree};
+pub use cache::CACHE_DIR;
 pub use codegen::{FrameSize, SlotNum};
+pub use doc::DocStruct;
+pub use emit::Emit;
+pub use explain::{ErrorCode, ExplainStruct};
+pub use fmt::FmtStruct;
+pub use hooks::AttributeHook;
+pub use lint::{Lint, LintLevel};
+pub use lsp::LspStruct;
+#[cfg(test)]
+pub use manifest::MANIFEST_FILE;
 pub use source::Lines;
 pub use typecheck::{AbstractSyntaxTree, TypeCheckedNode};
 
 mod ast;
+mod bounds;
+mod cache;
 mod codegen;
+mod consteval;
+mod deadcode;
+mod doc;
+mod emit;
+mod escape;
+mod explain;
+mod fmt;
+mod hooks;
+mod lint;
+mod lsp;
+mod macros;
+mod manifest;
+mod matching;
 pub mod miniconstants;
+mod monomorph;
 mod source;
 mod translate;
 mod typecheck;
+mod visibility;
 lalrpop_mod!(mini);
 
 /// Command line options for compile subcommand.
@@ -48,20 +84,131 @@ pub struct CompileStruct {
     pub debug_mode: bool,
     #[clap(short, long)]
     pub test_mode: bool,
-    #[clap(short, long)]
+    #[clap(short, long, alias = "warnings-as-errors")]
     pub warnings_are_errors: bool,
     #[clap(short, long)]
     pub output: Option<String>,
+    /// The format the mexe is written in: json, the default, or cbor or json.gz, which are far
+    /// smaller and which runs tell apart from json as they load them
     #[clap(short, long)]
     pub format: Option<String>,
     #[clap(short, long)]
     pub consts_file: Option<String>,
+    /// Overrides a constant of the constants file, as in `--define NAME=VALUE`, where the value is
+    /// decimal or hex prefixed by `0x`. Can be given several times
+    #[clap(long)]
+    pub define: Vec<String>,
     #[clap(short, long)]
     pub must_use_global_consts: bool,
     #[clap(short, long)]
     pub release_build: bool,
     #[clap(short, long)]
     pub no_builtins: bool,
+    #[clap(long)]
+    pub no_tail_calls: bool,
+    /// Keeps small funcs and those marked `#[inline]` from being inlined into their callers
+    #[clap(long)]
+    pub no_inline: bool,
+    /// Replaces reads of globals that the funcs marked `#[init]` only ever set to one constant with
+    /// that constant
+    #[clap(long)]
+    pub const_globals: bool,
+    /// Keeps the linked program from being optimized as a whole, folding constants and resolving
+    /// branches across modules
+    #[clap(long)]
+    pub no_lto: bool,
+    /// Leaves out the pass reordering the instructions of each basic block to shuffle the stack
+    /// less, so that what it saves can be measured
+    #[clap(long)]
+    pub no_schedule: bool,
+    /// Keeps one copy of each large constant used in several places in the jump table, which the
+    /// instructions using it look it up from. This shrinks the mexe at the cost of the gas the
+    /// lookups take
+    #[clap(long)]
+    pub pool_constants: bool,
+    /// Nests the jump table by how often each of its entries was reached in the run `mini
+    /// profiler --jump-profile` wrote this profile of, so that the lookups made most take the
+    /// fewest hops
+    #[clap(long)]
+    pub jump_profile: Option<String>,
+    /// Keeps the optimized code of each func in a .linkcache beside the output, so that relinking
+    /// only optimizes the funcs whose code has changed since
+    #[clap(long)]
+    pub incremental: bool,
+    /// How hard to optimize. Level 2 also keeps the fields of tuples and structs that never leave
+    /// the func building them in locals of their own, rather than building the tuple
+    #[clap(short = 'O', long, default_value = "1")]
+    pub opt_level: u8,
+    /// Begins each statement with a probe, so that runs can report which source lines they reach
+    #[clap(long)]
+    pub coverage: bool,
+    /// Makes `+`, `-`, and `*` on uints and ints raise an error instead of wrapping around, except
+    /// in funcs marked `#[wrapping]`
+    #[clap(long)]
+    pub overflow_checks: bool,
+    /// Writes the file names, instruction locations, and symbol table to a .mdbg sidecar beside
+    /// the output, leaving them out of the mexe
+    #[clap(long)]
+    pub debug_sidecar: bool,
+    /// Writes a .symbols.json beside the output, naming the func at each codepoint of the mexe
+    #[clap(long)]
+    pub symbol_map: bool,
+    /// Rewrites the paths of source files beginning with one prefix to begin with another instead,
+    /// as in `--remap-path-prefix FROM=TO`, wherever they're written or reported, so that builds
+    /// from checkouts in different places match. Can be given several times, with the last one
+    /// matching a path taking precedence
+    #[clap(long)]
+    pub remap_path_prefix: Vec<String>,
+    /// Starts the program at this public func rather than at `main`. Where several modules have a
+    /// func of the name, it can be named by its module's path too, as in `module::func`
+    #[clap(long)]
+    pub entry: Option<String>,
+    /// Installs this public func as the program's error handler once its globals are set, so that
+    /// errors call it with a tuple describing the fault instead of stopping the machine. It's
+    /// named as the entry point is
+    #[clap(long)]
+    pub error_handler: Option<String>,
+    #[clap(long)]
+    pub stack_budget: Option<usize>,
+    /// Compiles for an older ArbOS, failing if the program uses opcodes its AVM can't run, and
+    /// stamps the mexe with that version rather than the one in the constants file. The program
+    /// is compiled with that version as its `ArbosVersionNumber`, which decides the declarations
+    /// `#[cfg(arbos_version = "...")]` keeps
+    #[clap(long)]
+    pub target_arbos_version: Option<u64>,
+    #[clap(long)]
+    pub keep_all: bool,
+    /// Typechecks every module, rather than reusing what earlier compiles left in .mini-cache
+    #[clap(long)]
+    pub no_cache: bool,
+    /// Writes a library archive of the compiled modules to the output instead of a mexe
+    #[clap(long)]
+    pub module: bool,
+    /// Takes the modules in a library archive from there rather than compiling them, so long as
+    /// they'd compile the same way. Can be given several times
+    #[clap(long)]
+    pub lib: Vec<String>,
+    /// Drops the warnings of a lint
+    #[clap(long)]
+    pub allow: Vec<Lint>,
+    /// Reports the warnings of a lint, including those only reported when asked for
+    #[clap(long)]
+    pub warn: Vec<Lint>,
+    /// Reports the warnings of a lint as errors
+    #[clap(long)]
+    pub deny: Vec<Lint>,
+    /// Enables features, keeping the declarations marked `#[cfg(feature = "...")]` with them.
+    /// Several can be given at once, separated by commas
+    #[clap(long)]
+    pub features: Vec<String>,
+    /// Writes the given representation of the program to the output instead of a mexe: one of
+    /// ast, typed-ast, mavm, or mexe
+    #[clap(long, default_value = "mexe")]
+    pub emit: Emit,
+    /// The attributes funcs can be written with, which are the built-in ones unless others are
+    /// registered
+    #[clap(skip)]
+    pub hooks: AttributeHooks,
 }
 
 /// Represents the contents of a source file after parsing.
@@ -81,6 +228,16 @@ struct Module {
     string_table: StringTable,
     /// Map from `StringId`s to the types of the functions they represent.
     func_table: HashMap<StringId, Type>,
+    /// Map from `StringId`s to the interfaces declared in or imported into this file.
+    interfaces: HashMap<StringId, InterfaceDecl>,
+    /// List of impls declared within this file.
+    impls: Vec<ImplDecl>,
+    /// List of types declared within this file, in the order they appear.
+    type_decls: Vec<TypeDecl>,
+    /// List of constants declared within this file, in the order they appear.
+    const_decls: Vec<ConstDecl>,
+    /// List of static asserts declared within this file, in the order they appear.
+    static_asserts: Vec<StaticAssert>,
     /// The path to the module
     path: Vec<String>,
     /// The name of the module, this may be removed later.
@@ -88,7 +245,7 @@ struct Module {
 }
 
 /// Represents the contents of a source file after type checking is done.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct TypeCheckedModule {
     /// Collection of functions defined locally within the source file that have been validated by
     /// typechecking
@@ -111,6 +268,13 @@ struct TypeCheckedModule {
 
 impl CompileStruct {
     pub fn invoke(&self) -> Result<(LinkedProgram, ErrorSystem), ErrorSystem> {
+        self.invoke_emitting()
+            .map(|(program, _, error_system)| (program, error_system))
+    }
+
+    /// Compiles the program like `invoke`, also returning the text of the representation given
+    /// by `emit`, which is empty when that's the mexe itself.
+    pub fn invoke_emitting(&self) -> Result<(LinkedProgram, String, ErrorSystem), ErrorSystem> {
         // Initialize rayon to use a large stack size. We do this here rather than
         // main() so that tests are affected.
         drop(
@@ -119,6 +283,216 @@ impl CompileStruct {
                 .build_global(),
         );
 
+        let mut error_system = self.error_system();
+        let mut file_info_chart = BTreeMap::new();
+        let mut emitter = Emitter::new(self.emit);
+        let compiled =
+            match self.compile_inputs(&mut file_info_chart, &mut error_system, &mut emitter) {
+                Some(compiled) => compiled,
+                None => {
+                    error_system.file_info_chart = file_info_chart;
+                    error_system.apply_lint_levels();
+                    return Err(error_system);
+                }
+            };
+        self.link_compiled(compiled, file_info_chart, error_system, emitter)
+    }
+
+    /// Links the programs compiled into the library archives `compile --module` writes, given as
+    /// the inputs, rather than compiling sources, so that compiling and linking can happen apart.
+    /// The archives are linked with the options given as the program would be when compiled.
+    pub fn invoke_link(&self) -> Result<(LinkedProgram, ErrorSystem), ErrorSystem> {
+        let mut error_system = self.error_system();
+        let mut archives = vec![];
+        for path in &self.input {
+            match LibraryArchive::read(Path::new(path)) {
+                Ok(archive) => archives.push(archive),
+                Err(err) => error_system.errors.push(CompileError::new(
+                    "Library error",
+                    format!("Could not read library {}: {}", Color::red(path), err),
+                    vec![],
+                )),
+            }
+        }
+        if !error_system.errors.is_empty() {
+            return Err(error_system);
+        }
+        let (compiled, file_info_chart) = match LibraryArchive::unpack(&archives) {
+            Ok(unpacked) => unpacked,
+            Err(err) => {
+                error_system.errors.push(err);
+                return Err(error_system);
+            }
+        };
+        self.link_compiled(
+            compiled,
+            file_info_chart,
+            error_system,
+            Emitter::new(Emit::Mexe),
+        )
+        .map(|(program, _, error_system)| (program, error_system))
+    }
+
+    /// Links compiled funcs into a program, with the files they were compiled from.
+    fn link_compiled(
+        &self,
+        compiled: CompiledFolder,
+        file_info_chart: BTreeMap<u64, FileInfo>,
+        mut error_system: ErrorSystem,
+        emitter: Emitter,
+    ) -> Result<(LinkedProgram, String, ErrorSystem), ErrorSystem> {
+        let (unlinked_progs, globals, interface) = compiled;
+        let found = (
+            self.find_entry(&unlinked_progs),
+            self.find_error_handler(&unlinked_progs),
+        );
+        let (entry, error_handler) = match found {
+            (Ok(entry), Ok(error_handler)) => (entry, error_handler),
+            (Err(err), _) | (_, Err(err)) => {
+                error_system.errors.push(err);
+                error_system.file_info_chart = file_info_chart;
+                error_system.apply_lint_levels();
+                return Err(error_system);
+            }
+        };
+        let symbols = symbol_table(&unlinked_progs);
+        let func_names = func_names(&unlinked_progs);
+        let linked_prog = link(
+            unlinked_progs,
+            globals,
+            &mut error_system,
+            self.test_mode,
+            self.stack_budget,
+            self.release_build,
+            !self.no_inline,
+            self.const_globals,
+            entry,
+            error_handler,
+        );
+        let arbos_version = match self.arbos_version() {
+            Ok(version) => version,
+            Err(err) => {
+                error_system.errors.push(err);
+                0
+            }
+        };
+        error_system
+            .errors
+            .extend(check_target_version(&linked_prog, arbos_version));
+        let defines = match self.defines() {
+            Ok(defines) => defines,
+            Err(err) => {
+                error_system.errors.push(err);
+                BTreeMap::new()
+            }
+        };
+        let symbol_map_path = match self.symbol_map {
+            true => match &self.output {
+                Some(output) => Some(symbol_map_path(Path::new(output))),
+                None => {
+                    error_system.errors.push(CompileError::new(
+                        "Symbol map error",
+                        "A symbol map is written beside the output, so one must be given",
+                        vec![],
+                    ));
+                    None
+                }
+            },
+            false => None,
+        };
+        let link_cache_path = match self.incremental {
+            true => match &self.output {
+                Some(output) => Some(link_cache_path(Path::new(output))),
+                None => {
+                    error_system.errors.push(CompileError::new(
+                        "Link cache error",
+                        "The link cache is kept beside the output, so one must be given",
+                        vec![],
+                    ));
+                    None
+                }
+            },
+            false => None,
+        };
+        let jump_profile = match &self.jump_profile {
+            Some(path) => match JumpProfile::read(Path::new(path)) {
+                Ok(profile) => Some(profile),
+                Err(e) => {
+                    error_system.errors.push(CompileError::new(
+                        "Jump profile error",
+                        format!("Could not read {}: {}", Color::red(path), e),
+                        vec![],
+                    ));
+                    None
+                }
+            },
+            None => None,
+        };
+        if !error_system.errors.is_empty() {
+            error_system.file_info_chart = file_info_chart;
+            error_system.apply_lint_levels();
+            return Err(error_system);
+        }
+
+        let mut link_cache = link_cache_path.as_deref().and_then(LinkCache::open);
+        let mut postlinked_prog = match postlink_compile(
+            linked_prog,
+            file_info_chart.clone(),
+            self.test_mode,
+            self.debug_mode,
+            arbos_version,
+            !self.no_lto,
+            !self.no_schedule,
+            self.pool_constants,
+            jump_profile.as_ref().map(|profile| (profile, &func_names)),
+            link_cache.as_mut(),
+            symbol_map_path.as_deref().map(|path| (path, &func_names)),
+        ) {
+            Ok(idk) => idk,
+            Err(err) => {
+                error_system.errors.push(err);
+                error_system.file_info_chart = file_info_chart;
+                error_system.apply_lint_levels();
+                return Err(error_system);
+            }
+        };
+
+        if let (Some(cache), Some(path)) = (&link_cache, &link_cache_path) {
+            // a cache that can't be written just means the next link optimizes every func
+            drop(cache.write(path));
+        }
+
+        error_system.file_info_chart = file_info_chart;
+        postlinked_prog.interface = interface;
+        postlinked_prog.symbols = symbols;
+        postlinked_prog.defines = defines;
+
+        if self.debug_sidecar && self.emit == Emit::Mexe {
+            if let Err(err) = self.write_sidecar(&mut postlinked_prog) {
+                error_system.errors.push(err);
+                error_system.apply_lint_levels();
+                return Err(error_system);
+            }
+        }
+
+        let denied = error_system.apply_lint_levels();
+
+        if error_system.warnings.len() > 0 && error_system.warnings_are_errors {
+            error_system.errors.push(CompileError::new(
+                "Compile Error",
+                "Found warning with -w on",
+                vec![],
+            ));
+            Err(error_system)
+        } else if denied {
+            Err(error_system)
+        } else {
+            Ok((postlinked_prog, emitter.into_text(), error_system))
+        }
+    }
+
+    /// Makes the error system compilation reports to, with the lint levels the flags give.
+    fn error_system(&self) -> ErrorSystem {
         let mut error_system = ErrorSystem {
             errors: vec![],
             warnings: vec![],
@@ -128,74 +502,351 @@ impl CompileStruct {
                 false => Color::YELLOW,
             },
             file_info_chart: BTreeMap::new(),
+            lint_levels: BTreeMap::new(),
         };
+        // later flags override earlier ones, so denying wins over warning, which wins over allowing
+        let levels = [
+            (&self.allow, LintLevel::Allow),
+            (&self.warn, LintLevel::Warn),
+            (&self.deny, LintLevel::Deny),
+        ];
+        for (lints, level) in levels.iter() {
+            for lint in lints.iter() {
+                error_system.lint_levels.insert(*lint, *level);
+            }
+        }
+        error_system
+    }
 
+    /// Compiles each input into funcs ready to be linked, along with the program's globals and
+    /// interface. The other inputs are still compiled when one fails, so that their errors are
+    /// reported too, and `None` is returned once they have been.
+    fn compile_inputs(
+        &self,
+        file_info_chart: &mut BTreeMap<u64, FileInfo>,
+        error_system: &mut ErrorSystem,
+        emitter: &mut Emitter,
+    ) -> Option<CompiledFolder> {
         let mut unlinked_progs = vec![];
-        let mut file_info_chart = BTreeMap::new();
         let mut globals = vec![];
+        let mut interface = BTreeMap::new();
+        let mut failed = false;
+
+        let features = parse_features(&self.features);
+
+        let mut libraries = vec![];
+        for path in &self.lib {
+            match LibraryArchive::read(Path::new(path)) {
+                Ok(library) => libraries.push(library),
+                Err(err) => {
+                    error_system.errors.push(CompileError::new(
+                        "Library error",
+                        format!("Could not read library {}: {}", Color::red(path), err),
+                        vec![],
+                    ));
+                    failed = true;
+                }
+            }
+        }
+
+        // inputs are compiled in a canonical order, so that the order they're given in doesn't
+        // change the program built from them
+        let mut inputs: Vec<&String> = self.input.iter().collect();
+        inputs.sort();
+        inputs.dedup();
+
+        let constants_path = match self.constants_path() {
+            Ok(path) => path,
+            Err(err) => {
+                error_system.errors.push(err);
+                return None;
+            }
+        };
+        let remaps = match self.path_remaps() {
+            Ok(remaps) => remaps,
+            Err(err) => {
+                error_system.errors.push(err);
+                return None;
+            }
+        };
 
-        for filename in &self.input {
+        for filename in inputs {
             let path = Path::new(filename);
-            let constants_path = match &self.consts_file {
-                Some(path) => Some(Path::new(path)),
-                None => None,
-            };
-            let (progs, all_globals) = match compile_from_file(
+            let (progs, all_globals, constants) = match compile_from_file(
                 path,
-                &mut file_info_chart,
-                constants_path,
+                file_info_chart,
+                constants_path.as_deref(),
+                &features,
                 self.must_use_global_consts,
-                &mut error_system,
+                error_system,
                 self.release_build,
+                !self.no_tail_calls,
+                self.coverage,
+                self.overflow_checks,
+                self.opt_level,
+                !self.keep_all,
                 !self.no_builtins,
+                !self.no_cache,
+                &self.hooks,
+                &libraries,
+                emitter,
             ) {
                 Ok(idk) => idk,
                 Err(err) => {
                     error_system.errors.push(err);
-                    error_system.file_info_chart = file_info_chart;
-                    return Err(error_system);
+                    failed = true;
+                    continue;
                 }
             };
 
             globals = all_globals;
+            interface.extend(constants);
 
             unlinked_progs.extend(progs);
         }
+        remap_paths(file_info_chart, &remaps);
+        if failed {
+            return None;
+        }
 
         // If this condition is true it means that __fixedLocationGlobal will not be at
         // index [0], but rather [0][0] or [0][0][0] etc
         if globals.len() >= 58 {
             panic!("Too many globals defined in program, location of first global is not correct")
         }
+        Some((unlinked_progs, globals, interface))
+    }
 
-        let linked_prog = link(unlinked_progs, globals, &mut error_system, self.test_mode);
-
-        let postlinked_prog = match postlink_compile(
-            linked_prog,
-            file_info_chart.clone(),
-            self.test_mode,
-            self.debug_mode,
-        ) {
-            Ok(idk) => idk,
+    /// Compiles the program's unit tests, the funcs marked `#[test]`, linking a program for each
+    /// with the test as its entry point, so that each can run on a machine of its own. Tests are
+    /// linked as test programs are, and are named by their module's path and their own name.
+    pub fn invoke_tests(&self) -> Result<(Vec<UnitTest>, ErrorSystem), ErrorSystem> {
+        let mut error_system = self.error_system();
+        let mut file_info_chart = BTreeMap::new();
+        let mut emitter = Emitter::new(Emit::Mexe);
+        let compiled = self.compile_inputs(&mut file_info_chart, &mut error_system, &mut emitter);
+        let arbos_version = match self.arbos_version() {
+            Ok(version) => version,
             Err(err) => {
                 error_system.errors.push(err);
+                0
+            }
+        };
+        let (funcs, globals) = match compiled {
+            Some((funcs, globals, _)) if error_system.errors.is_empty() => (funcs, globals),
+            _ => {
                 error_system.file_info_chart = file_info_chart;
+                error_system.apply_lint_levels();
                 return Err(error_system);
             }
         };
 
+        let mut tests = vec![];
+        for test in funcs.iter().filter(|func| func.debug_info.attributes.test) {
+            // linking warns of the funcs each test doesn't reach, which are expected to be many
+            let mut link_issues = error_system.fork();
+            let linked_prog = link(
+                funcs.clone(),
+                globals.clone(),
+                &mut link_issues,
+                true,
+                None,
+                self.release_build,
+                !self.no_inline,
+                self.const_globals,
+                Some(test.unique_id),
+                None,
+            );
+            error_system.errors.extend(link_issues.errors);
+            let program = postlink_compile(
+                linked_prog,
+                file_info_chart.clone(),
+                true,
+                false,
+                arbos_version,
+                !self.no_lto,
+                !self.no_schedule,
+                self.pool_constants,
+                None,
+                None,
+                None,
+            );
+            match program {
+                Ok(program) => tests.push(UnitTest {
+                    name: format!("{}::{}", path_display(&test.path), test.name),
+                    location: test.debug_info.location,
+                    program,
+                }),
+                Err(err) => error_system.errors.push(err),
+            }
+        }
+
         error_system.file_info_chart = file_info_chart;
+        let denied = error_system.apply_lint_levels();
+        match error_system.errors.is_empty() && !denied {
+            true => Ok((tests, error_system)),
+            false => Err(error_system),
+        }
+    }
 
-        if error_system.warnings.len() > 0 && error_system.warnings_are_errors {
-            error_system.errors.push(CompileError::new(
-                "Compile Error",
-                "Found warning with -w on",
+    /// Compiles the program's modules into a library archive, so that programs built from them can
+    /// reuse the funcs compiled here. Nothing is linked, so the archive holds every func compiled.
+    pub fn invoke_module(&self) -> Result<(LibraryArchive, ErrorSystem), ErrorSystem> {
+        let mut error_system = self.error_system();
+        let mut file_info_chart = BTreeMap::new();
+        let mut emitter = Emitter::new(Emit::Mexe);
+        let compiled = self.compile_inputs(&mut file_info_chart, &mut error_system, &mut emitter);
+        let archive = compiled.map(|compiled| LibraryArchive::new(&compiled, &file_info_chart));
+        error_system.file_info_chart = file_info_chart;
+        let denied = error_system.apply_lint_levels();
+        match archive {
+            Some(archive) if error_system.errors.is_empty() && !denied => {
+                Ok((archive, error_system))
+            }
+            _ => Err(error_system),
+        }
+    }
+
+    /// Finds the func `entry` names, if it names one, for the program to start at in place of
+    /// `main`. Private funcs `main` doesn't reach have been dropped by then, so only public ones
+    /// can be found.
+    fn find_entry(&self, funcs: &[CompiledFunc]) -> Result<Option<LabelId>, CompileError> {
+        match &self.entry {
+            Some(entry) => find_public_func(funcs, entry, "Entry point error").map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Finds the func `error_handler` names, if it names one, to install as the program's error
+    /// handler. As with the entry point, only public funcs can be found.
+    fn find_error_handler(&self, funcs: &[CompiledFunc]) -> Result<Option<LabelId>, CompileError> {
+        match &self.error_handler {
+            Some(handler) => find_public_func(funcs, handler, "Error handler error").map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The ArbOS version the program is compiled for, which is the one in the constants file
+    /// unless an older one is targeted.
+    fn arbos_version(&self) -> Result<u64, CompileError> {
+        let constants = self
+            .write_constants(&self.defines()?)?
+            .unwrap_or_else(|| PathBuf::from("arb_os/constants.json"));
+        let current = init_constant_table(Some(&constants))?["ArbosVersionNumber"].trim_to_u64();
+        match self.target_arbos_version {
+            Some(target) if target > current => Err(CompileError::new(
+                "Target error",
+                format!(
+                    "ArbOS version {} is newer than {}, the version of {}",
+                    Color::red(target),
+                    Color::red(current),
+                    Color::red(constants.display()),
+                ),
                 vec![],
-            ));
-            Err(error_system)
-        } else {
-            Ok((postlinked_prog, error_system))
+            )),
+            Some(target) => Ok(target),
+            None => Ok(current),
+        }
+    }
+
+    /// The constants overridden with `--define`, by name.
+    fn defines(&self) -> Result<BTreeMap<String, Uint256>, CompileError> {
+        self.define
+            .iter()
+            .map(|define| parse_define(define))
+            .collect()
+    }
+
+    /// The prefixes `--remap-path-prefix` replaces, each with what replaces it, in the order given.
+    fn path_remaps(&self) -> Result<Vec<(PathBuf, String)>, CompileError> {
+        self.remap_path_prefix
+            .iter()
+            .map(|remap| match remap.split_once('=') {
+                Some((from, to)) if !from.is_empty() => Ok((PathBuf::from(from), to.to_string())),
+                _ => Err(CompileError::new(
+                    "Remap error",
+                    format!(
+                        "Could not parse {} as a remapping of the form FROM=TO",
+                        Color::red(remap)
+                    ),
+                    vec![],
+                )),
+            })
+            .collect()
+    }
+
+    /// The constants file compiled with, which is the one given with any definitions overriding
+    /// its constants, and with the ArbOS version set to the one targeted, if any.
+    fn constants_path(&self) -> Result<Option<PathBuf>, CompileError> {
+        let mut defines = self.defines()?;
+        if let Some(target) = self.target_arbos_version {
+            defines.insert(
+                String::from("ArbosVersionNumber"),
+                Uint256::from_u64(target),
+            );
+        }
+        self.write_constants(&defines)
+    }
+
+    /// The constants file given, with defines overriding its constants. Constants are substituted
+    /// as modules are parsed, so the overridden file is written out for them to be parsed with,
+    /// named by its contents so that compiles with the same overrides share it and the module
+    /// cache tells compiles with different ones apart. Without a constants file, only the ArbOS
+    /// version can be set, which overrides it in an empty one.
+    fn write_constants(
+        &self,
+        defines: &BTreeMap<String, Uint256>,
+    ) -> Result<Option<PathBuf>, CompileError> {
+        if defines.is_empty() {
+            return Ok(self.consts_file.as_ref().map(PathBuf::from));
+        }
+        let mut consts = match &self.consts_file {
+            Some(base) => ConstantsFile::read(Path::new(base))?,
+            None if self.define.is_empty() => ConstantsFile::default(),
+            None => {
+                return Err(CompileError::new(
+                    "Define error",
+                    "Definitions override a constants file, so one must be given",
+                    vec![],
+                ))
+            }
+        };
+        for (name, value) in defines {
+            consts.define(name, value)?;
         }
+        let json = serde_json::to_string_pretty(&consts).unwrap_or_default();
+        let path =
+            std::env::temp_dir().join(format!("mini-constants-{:016x}.json", stable_hash(&json)));
+        std::fs::write(&path, json).map_err(|e| {
+            CompileError::new(
+                "Define error",
+                format!("Could not write {}: {}", Color::red(path.display()), e),
+                vec![],
+            )
+        })?;
+        Ok(Some(path))
+    }
+
+    /// Splits the program's debug info into a sidecar beside the output.
+    fn write_sidecar(&self, program: &mut LinkedProgram) -> Result<(), CompileError> {
+        let sidecar_error =
+            |description: String| CompileError::new("Sidecar error", description, vec![]);
+        let output = match &self.output {
+            Some(output) => Path::new(output),
+            None => {
+                return Err(sidecar_error(String::from(
+                    "A debug sidecar is written beside the output, so one must be given",
+                )))
+            }
+        };
+        let path = sidecar_path(output);
+        program.split_debug_info().write(&path).map_err(|e| {
+            sidecar_error(format!(
+                "Could not write {}: {}",
+                Color::red(path.display()),
+                e
+            ))
+        })
     }
 }
 
@@ -208,6 +859,11 @@ impl Module {
         imports: Vec<Import>,
         string_table: StringTable,
         func_table: HashMap<usize, Type>,
+        interfaces: HashMap<StringId, InterfaceDecl>,
+        impls: Vec<ImplDecl>,
+        type_decls: Vec<TypeDecl>,
+        const_decls: Vec<ConstDecl>,
+        static_asserts: Vec<StaticAssert>,
         path: Vec<String>,
         name: String,
     ) -> Self {
@@ -219,6 +875,11 @@ impl Module {
             imports,
             string_table,
             func_table,
+            interfaces,
+            impls,
+            type_decls,
+            const_decls,
+            static_asserts,
             path,
             name,
         }
@@ -248,9 +909,11 @@ impl TypeCheckedModule {
         }
     }
 
-    /// Propagates inherited attributes down top-level decls.
-    fn propagate_attributes(&mut self) {
+    /// Applies the attributes written on funcs, then propagates inherited attributes down
+    /// top-level decls.
+    fn propagate_attributes(&mut self, hooks: &AttributeHooks) {
         for (_id, func) in &mut self.checked_funcs {
+            hooks.attach(func);
             let attributes = func.debug_info.attributes.clone();
             TypeCheckedNode::propagate_attributes(func.child_nodes(), &attributes);
         }
@@ -266,21 +929,26 @@ impl TypeCheckedModule {
             let id = self.string_table.get_if_exists(&import.name).unwrap();
 
             if let Some(prior) = imports.get(&id) {
-                flow_warnings.push(CompileError::new_warning(
-                    "Compile Warning",
-                    format!(
-                        "use statement {} is a duplicate",
-                        Color::color(error_system.warn_color, &import.name)
-                    ),
-                    prior
-                        .location
-                        .into_iter()
-                        .chain(import.location.into_iter())
-                        .collect(),
-                ));
+                flow_warnings.push(
+                    CompileError::new_warning(
+                        Lint::UnusedImports,
+                        "Compile Warning",
+                        format!(
+                            "use statement {} is a duplicate",
+                            Color::color(error_system.warn_color, &import.name)
+                        ),
+                        prior
+                            .location
+                            .into_iter()
+                            .chain(import.location.into_iter())
+                            .collect(),
+                    )
+                    .with_fix(remove_import(import)),
+                );
             }
 
-            if import.path[0] != "core" {
+            // re-exports are there for other modules, so needn't be used here
+            if import.path[0] != "core" && !import.public {
                 imports.insert(id, import.clone());
             }
         }
@@ -306,14 +974,18 @@ impl TypeCheckedModule {
         }
 
         for (_id, import) in imports {
-            flow_warnings.push(CompileError::new_warning(
-                "Compile Warning",
-                format!(
-                    "use statement {} is unnecessary",
-                    Color::color(error_system.warn_color, import.name)
-                ),
-                import.location.into_iter().collect(),
-            ));
+            flow_warnings.push(
+                CompileError::new_warning(
+                    Lint::UnusedImports,
+                    "Compile Warning",
+                    format!(
+                        "use statement {} is unnecessary",
+                        Color::color(error_system.warn_color, &import.name)
+                    ),
+                    import.location.into_iter().collect(),
+                )
+                .with_fix(remove_import(&import)),
+            );
         }
 
         flow_warnings.sort_by(|a, b| {
@@ -329,9 +1001,68 @@ impl TypeCheckedModule {
     }
 }
 
+/// Finds the public func name refers to, which is named by its name, or by its module's path too
+/// where several modules have a public func of the name, as in `module::func`. Private funcs of
+/// the name are pointed to when there's no public one.
+fn find_public_func(
+    funcs: &[CompiledFunc],
+    name: &str,
+    title: &str,
+) -> Result<LabelId, CompileError> {
+    let (named, private): (Vec<_>, Vec<_>) = funcs
+        .iter()
+        .filter(|func| {
+            func.name == name || format!("{}::{}", path_display(&func.path), func.name) == name
+        })
+        .partition(|func| func.is_public());
+    match named.as_slice() {
+        [func] => Ok(func.unique_id),
+        [] => Err(CompileError::new(
+            title,
+            format!("There's no public func named {}", Color::red(name)),
+            private
+                .iter()
+                .filter_map(|func| func.debug_info.location)
+                .collect(),
+        )),
+        _ => Err(CompileError::new(
+            title,
+            format!(
+                "Several funcs are named {}, so name the one meant by its module too, as in {}",
+                Color::red(name),
+                Color::red(format!("{}::{}", path_display(&named[0].path), name)),
+            ),
+            named
+                .iter()
+                .filter_map(|func| func.debug_info.location)
+                .collect(),
+        )),
+    }
+}
+
+/// The fix for an unneeded import, which removes its use statement.
+fn remove_import(import: &Import) -> Option<Fix> {
+    Some(Fix::remove(
+        "remove the use statement",
+        import.location?,
+        import.end?,
+    ))
+}
+
+/// A unit test, linked as a program of its own.
+pub struct UnitTest {
+    /// The test's module path and name
+    pub name: String,
+    /// Where the test is declared
+    pub location: Option<Location>,
+    /// The program entered through the test
+    pub program: LinkedProgram,
+}
+
 /// Maps the `StringId` of a capture to a slot in a func's frame
 pub type ClosureAssignments = HashMap<StringId, SlotNum>;
 
+#[derive(Clone)]
 pub struct CompiledFunc {
     /// Name of the func from which it was derived
     pub name: String,
@@ -351,9 +1082,23 @@ pub struct CompiledFunc {
     pub unique_id: LabelId,
     /// This func's debug info
     pub debug_info: DebugInfo,
+    /// The type of the func, which an override has to share with the func it replaces
+    pub tipe: Type,
+    /// The funcs this func calls
+    pub calls: CallSites,
+    /// Whether the func's module is left out of release builds
+    pub debug_only: bool,
+    /// Covers everything codegen read of the func's module, so that library archives can tell
+    /// when it would compile the same way again
+    pub fingerprint: u64,
 }
 
 impl CompiledFunc {
+    /// Whether the func was declared public.
+    pub fn is_public(&self) -> bool {
+        matches!(&self.tipe, Type::Func(prop, ..) if prop.public)
+    }
+
     pub fn new(
         name: String,
         path: Vec<String>,
@@ -363,6 +1108,7 @@ impl CompiledFunc {
         globals: Vec<GlobalVar>,
         type_tree: TypeTree,
         debug_info: DebugInfo,
+        tipe: Type,
     ) -> Self {
         let unique_id = Import::unique_id(&path, &name);
         CompiledFunc {
@@ -375,6 +1121,10 @@ impl CompiledFunc {
             type_tree,
             unique_id,
             debug_info,
+            tipe,
+            calls: CallSites::default(),
+            debug_only: false,
+            fingerprint: 0,
         }
     }
 }
@@ -460,15 +1210,56 @@ impl CompiledProgram {
 ///
 /// The file_id specified will be used as the file_id in locations originating from this source
 /// file, and if debug is set to true, then compiler internal debug information will be printed.
+/// The funcs and globals of a compiled folder, along with the constants describing its interface.
+pub type CompiledFolder = (Vec<CompiledFunc>, Vec<GlobalVar>, BTreeMap<String, Uint256>);
+
 pub fn compile_from_file(
     path: &Path,
     file_info_chart: &mut BTreeMap<u64, FileInfo>,
     constants_path: Option<&Path>,
+    features: &BTreeSet<String>,
     must_use_global_consts: bool,
     error_system: &mut ErrorSystem,
     release_build: bool,
+    tail_calls: bool,
+    coverage: bool,
+    overflow_checks: bool,
+    opt_level: u8,
+    drop_dead_code: bool,
     builtins: bool,
-) -> Result<(Vec<CompiledFunc>, Vec<GlobalVar>), CompileError> {
+    cache: bool,
+    hooks: &AttributeHooks,
+    libraries: &[LibraryArchive],
+    emitter: &mut Emitter,
+) -> Result<CompiledFolder, CompileError> {
+    let (folder, library, main) = locate_main(path)?;
+    compile_from_folder(
+        folder,
+        library,
+        main,
+        file_info_chart,
+        constants_path,
+        features,
+        must_use_global_consts,
+        error_system,
+        release_build,
+        tail_calls,
+        coverage,
+        overflow_checks,
+        opt_level,
+        drop_dead_code,
+        builtins,
+        cache,
+        hooks,
+        libraries,
+        emitter,
+    )
+}
+
+/// Splits the path to a program into the folder holding its sources, the library prefix of that
+/// folder if it's one of the standard libraries, and the name of its main file. A folder's main
+/// file is `main.mini`.
+fn locate_main(path: &Path) -> Result<(&Path, Option<&'static str>, &str), CompileError> {
     let library = path
         .parent()
         .map(|par| {
@@ -489,19 +1280,9 @@ pub fn compile_from_file(
         })
         .unwrap_or(None);
     if path.is_dir() {
-        compile_from_folder(
-            path,
-            library,
-            "main",
-            file_info_chart,
-            constants_path,
-            must_use_global_consts,
-            error_system,
-            release_build,
-            builtins,
-        )
+        Ok((path, library, "main"))
     } else if let (Some(parent), Some(file_name)) = (path.parent(), path.file_stem()) {
-        compile_from_folder(
+        Ok((
             parent,
             library,
             file_name.to_str().ok_or_else(|| {
@@ -511,13 +1292,7 @@ pub fn compile_from_file(
                     vec![],
                 )
             })?,
-            file_info_chart,
-            constants_path,
-            must_use_global_consts,
-            error_system,
-            release_build,
-            builtins,
-        )
+        ))
     } else {
         Err(CompileError::new(
             "Compile error",
@@ -540,24 +1315,49 @@ fn _print_node(node: &mut TypeCheckedNode, state: &String, mut_state: &mut usize
     true
 }
 
-/// Compiles a `Vec<CompiledProgram>` from a folder along with it's `Vec<GlobalVar>`
+/// Gets the features enabled by `--features` flags, each of which can list several separated by
+/// commas.
+fn parse_features(flags: &[String]) -> BTreeSet<String> {
+    flags
+        .iter()
+        .flat_map(|features| features.split(','))
+        .filter(|feature| !feature.is_empty())
+        .map(|feature| feature.to_string())
+        .collect()
+}
+
+/// Compiles a `Vec<CompiledProgram>` from a folder along with it's `Vec<GlobalVar>`
 /// or generates a `CompileError` if a problem is encountered during compilation.
 ///
 /// The `folder` argument gives the path to the folder, `library` optionally contains a library
 /// prefix attached to the front of all paths, `main` contains the name of the main file in the
 /// folder, `file_info_chart` contains a map from the `u64` hashes of file names to the `FileInfo`
-/// they represent, useful for formatting errors
+/// they represent, useful for formatting errors. Declarations with cfg attributes are only kept
+/// when `features` satisfies them. Unless `drop_dead_code` is false, private funcs and globals
+/// that can't be reached from the entry points of their modules are left out. The representation
+/// `emitter` asks for is written to it as compilation reaches it. The funcs of modules the folder's
+/// manifest marks debug-only are flagged, so that release builds can leave them out.
 pub fn compile_from_folder(
     folder: &Path,
     library: Option<&str>,
     main: &str,
     file_info_chart: &mut BTreeMap<u64, FileInfo>,
     constants_path: Option<&Path>,
+    features: &BTreeSet<String>,
     must_use_global_consts: bool,
     error_system: &mut ErrorSystem,
     release_build: bool,
+    tail_calls: bool,
+    coverage: bool,
+    overflow_checks: bool,
+    opt_level: u8,
+    drop_dead_code: bool,
     builtins: bool,
-) -> Result<(Vec<CompiledFunc>, Vec<GlobalVar>), CompileError> {
+    cache: bool,
+    hooks: &AttributeHooks,
+    libraries: &[LibraryArchive],
+    emitter: &mut Emitter,
+) -> Result<CompiledFolder, CompileError> {
     let constants_default = folder.join("constants.json");
     let constants_path = match constants_path {
         Some(path) => Some(path),
@@ -567,20 +1367,40 @@ pub fn compile_from_folder(
         },
     };
 
+    let manifest = Manifest::load(folder)?;
+    error_system.fail_with(manifest.check_precompiles())?;
+
     let (mut programs, mut import_map) = create_program_tree(
         folder,
         library,
         main,
         file_info_chart,
         constants_path,
+        features,
         error_system,
         builtins,
+        &manifest,
     )?;
 
     resolve_imports(&mut programs, &mut import_map, error_system)?;
 
+    let unrecognized: Vec<_> = programs
+        .values()
+        .flat_map(|module| hooks.check(&module.funcs))
+        .collect();
+    error_system.errors.extend(unrecognized);
+
+    error_system.fail_with(manifest.check(&programs))?;
+
     // Conversion of programs from `HashMap` to `Vec` for typechecking
     let type_tree = create_type_tree(&programs);
+    let methods = resolve_impls(&mut programs, &type_tree)?;
+    let failed_asserts: Vec<_> = programs
+        .values()
+        .flat_map(|module| consteval::check_static_asserts(module, &type_tree))
+        .collect();
+    error_system.fail_with(failed_asserts)?;
+    emitter.ast(&programs);
     let mut modules = vec![programs
         .remove(&if let Some(lib) = library {
             vec![lib.to_string(), main.to_string()]
@@ -593,28 +1413,98 @@ pub fn compile_from_folder(
         out.sort_by(|module1, module2| module2.name.cmp(&module1.name));
         out
     });
-    let mut typechecked_modules =
-        typecheck_programs(&type_tree, modules, file_info_chart, error_system)?;
+    let cache = match cache {
+        true => ModuleCache::new(
+            folder,
+            constants_path,
+            features,
+            builtins,
+            error_system.warnings_are_errors,
+        ),
+        false => None,
+    };
+    let mut typechecked_modules = typecheck_programs(
+        &type_tree,
+        modules,
+        file_info_chart,
+        cache.as_ref(),
+        error_system,
+    )?;
+    error_system.fail_with(visibility::check_method_visibility(
+        &mut typechecked_modules,
+        &methods,
+    ))?;
 
     if must_use_global_consts {
         check_global_constants(&typechecked_modules, constants_path, error_system);
     }
-
-    // Control flow analysis stage
-    for module in &mut typechecked_modules {
-        module.flowcheck(error_system);
+    let interface = interface_constants(&typechecked_modules, constants_path)?;
+
+    // Control flow analysis and dead code elimination stages, which look at one module at a time
+    let module_issues: Vec<_> = typechecked_modules
+        .par_iter_mut()
+        .map(|module| {
+            let mut issues = error_system.fork();
+            module.flowcheck(&mut issues);
+            if drop_dead_code {
+                deadcode::eliminate_dead_code(module, &mut issues);
+            }
+            module.propagate_attributes(hooks);
+            issues
+        })
+        .collect();
+    for issues in module_issues {
+        error_system.join(issues);
     }
-
-    for module in &mut typechecked_modules {
-        module.propagate_attributes();
+    emitter.typed_ast(&typechecked_modules);
+
+    let (mut progs, globals) = codegen_modules(
+        typechecked_modules,
+        type_tree,
+        &methods,
+        release_build,
+        tail_calls,
+        coverage,
+        overflow_checks,
+        opt_level,
+        hooks,
+        libraries,
+    )?;
+    emitter.mavm(&progs);
+    for func in &mut progs {
+        func.debug_only = manifest.is_debug_only(&func.path);
     }
+    Ok((progs, globals, interface))
+}
 
-    let (progs, globals) = codegen_modules(typechecked_modules, type_tree, release_build)?;
-    Ok((progs, globals))
+/// Gets the id of the file a module is parsed from, which locations within it refer to. The id
+/// depends only on the module's path, not on where its folder is, so the locations of a program
+/// compiled on different machines are the same.
+fn module_file_id(path: &[String]) -> u64 {
+    stable_hash(path)
+}
+
+/// Rewrites the path of each charted file that begins with a prefix remaps replaces, using the
+/// last remap whose prefix it begins with. Prefixes are matched by whole components, so `/src`
+/// doesn't match `/srcs/main.mini`.
+fn remap_paths(file_info_chart: &mut BTreeMap<u64, FileInfo>, remaps: &[(PathBuf, String)]) {
+    for info in file_info_chart.values_mut() {
+        let path = Path::new(&info.path);
+        let remapped = remaps.iter().rev().find_map(|(from, to)| {
+            let rest = path.strip_prefix(from).ok()?;
+            Some(match rest.as_os_str().is_empty() {
+                true => to.clone(),
+                false => Path::new(to).join(rest).display().to_string(),
+            })
+        });
+        if let Some(remapped) = remapped {
+            info.path = remapped;
+        }
+    }
 }
 
 /// Converts the `Vec<String>` used to identify a path into a single formatted string
-fn path_display(path: &Vec<String>) -> String {
+pub fn path_display(path: &Vec<String>) -> String {
     let mut s = "".to_string();
     if let Some(first) = path.get(0) {
         s.push_str(first);
@@ -629,18 +1519,26 @@ fn path_display(path: &Vec<String>) -> String {
 /// Parsing stage of the compiler, creates a `HashMap` containing a list of modules and imports
 /// generated by interpreting the contents of `folder` as source code. Returns a `CompileError` if
 /// the contents of `folder` fail to parse.
+///
+/// Modules are parsed in waves, each made of the modules the last one imported that haven't been
+/// seen yet, with the modules of a wave parsed in parallel. A module failing to parse doesn't stop
+/// the others from being parsed, so that the errors of every module are reported together.
+/// Modules the manifest writes, like the one registering its precompiles, are parsed from the
+/// source it writes rather than read from `folder`.
 fn create_program_tree(
     folder: &Path,
     library: Option<&str>,
     main: &str,
     file_info_chart: &mut BTreeMap<u64, FileInfo>,
     constants_path: Option<&Path>,
+    features: &BTreeSet<String>,
     error_system: &mut ErrorSystem,
     builtins: bool,
+    manifest: &Manifest,
 ) -> Result<
     (
-        HashMap<Vec<String>, Module>,
-        HashMap<Vec<String>, Vec<Import>>,
+        BTreeMap<Vec<String>, Module>,
+        BTreeMap<Vec<String>, Vec<Import>>,
     ),
     CompileError,
 > {
@@ -650,128 +1548,267 @@ fn create_program_tree(
         vec![vec![main.to_owned()]]
     };
 
-    let mut programs = HashMap::new();
-    let mut import_map = HashMap::new();
+    let mut programs = BTreeMap::new();
+    let mut import_map = BTreeMap::new();
     let mut seen_paths = HashSet::new();
-    while let Some(path) = paths.pop() {
-        if seen_paths.contains(&path) {
-            continue;
-        } else {
-            seen_paths.insert(path.clone());
-        }
-        let name = if path.len() == 1 {
-            path[0].clone()
-        } else if path[0] == "std" {
-            format!("../stdlib/{}", path[1])
-        } else if path[0] == "std2" {
-            format!("../stdlib2/{}", path[1])
-        } else if path[0] == "core" {
-            format!("../builtin/{}", path[1])
-        } else {
-            path[0].clone()
-        } + ".mini";
-        let mut file = File::open(folder.join(name.clone())).map_err(|why| {
-            CompileError::new(
-                "Compile error",
-                format!("Can not open {}/{}: {:?}", folder.display(), name, why),
-                vec![],
-            )
-        })?;
+    let mut failures = vec![];
+    while !paths.is_empty() {
+        let mut wave: Vec<_> = paths
+            .drain(..)
+            .filter(|path| seen_paths.insert(path.clone()))
+            .collect();
+        wave.sort();
+
+        let parsed: Vec<_> = wave
+            .into_par_iter()
+            .map(|path| {
+                let mut issues = error_system.fork();
+                let source = match manifest.generated_module(&path) {
+                    Some(generated) => Ok(generated),
+                    None => read_module(folder, &path)
+                        .map_err(|error| missing_module(error, folder, &path, &import_map)),
+                };
+                let parsed = source.map(|(name, source)| {
+                    let file_info = FileInfo {
+                        name: path_display(&path),
+                        path: folder.join(name.clone()).display().to_string(),
+                        contents: source.split("\n").map(|x| x.to_string()).collect(),
+                    };
+                    let file_id = module_file_id(&path);
+                    let module = parse_module(
+                        path,
+                        name,
+                        source,
+                        constants_path,
+                        features,
+                        &mut issues,
+                        builtins,
+                    );
+                    (file_id, file_info, module)
+                });
+                (parsed, issues)
+            })
+            .collect();
+
+        // the file is charted even if it fails to parse, so the errors can show where
+        for (parsed, issues) in parsed {
+            error_system.join(issues);
+            let module = parsed.and_then(|(file_id, file_info, module)| {
+                file_info_chart.insert(file_id, file_info);
+                module
+            });
+            match module {
+                Ok((module, syntax_errors)) => {
+                    failures.extend(syntax_errors);
+                    paths.extend(module.imports.iter().map(|imp| imp.path.clone()));
+                    import_map.insert(module.path.clone(), module.imports.clone());
+                    programs.insert(module.path.clone(), module);
+                }
+                Err(error) => failures.push(error),
+            }
+        }
+    }
+    error_system.fail_with(failures)?;
+    Ok((programs, import_map))
+}
 
-        let mut source = String::new();
-        file.read_to_string(&mut source).map_err(|why| {
-            CompileError::new(
-                "Compile error",
-                format!("Can not read {}/{}: {:?}", folder.display(), name, why),
-                vec![],
-            )
-        })?;
-        let mut file_hasher = DefaultHasher::new();
-        path.hash(&mut file_hasher);
-        let file_id = file_hasher.finish();
+/// The file the module at path is read from, relative to the program's folder.
+fn module_file(path: &[String]) -> String {
+    let name = if path.len() == 1 {
+        path[0].clone()
+    } else if path[0] == "std" {
+        format!("../stdlib/{}", path[1])
+    } else if path[0] == "std2" {
+        format!("../stdlib2/{}", path[1])
+    } else if path[0] == "core" {
+        format!("../builtin/{}", path[1])
+    } else {
+        path[0].clone()
+    };
+    name + ".mini"
+}
 
-        file_info_chart.insert(
-            file_id,
-            FileInfo {
-                name: path_display(&path),
-                path: folder.join(name.clone()).display().to_string(),
-                contents: source.split("\n").map(|x| x.to_string()).collect(),
-            },
-        );
+/// Reads the source of the module at path, returning the name of its file along with the source.
+fn read_module(folder: &Path, path: &[String]) -> Result<(String, String), CompileError> {
+    let name = module_file(path);
+    let mut file = File::open(folder.join(name.clone())).map_err(|why| {
+        CompileError::new(
+            "Compile error",
+            format!("Can not open {}/{}: {:?}", folder.display(), name, why),
+            vec![],
+        )
+    })?;
 
-        let mut string_table = StringTable::new();
-        let mut used_constants = HashSet::new();
-        let (imports, funcs, named_types, global_vars, func_table) =
-            typecheck::sort_top_level_decls(
-                parse_from_source(
-                    source,
-                    file_id,
-                    &path,
-                    &mut string_table,
-                    constants_path,
-                    &mut used_constants,
-                    error_system,
-                )?,
-                path.clone(),
-                &mut string_table,
-                builtins,
-            );
-        paths.append(&mut imports.iter().map(|imp| imp.path.clone()).collect());
-        import_map.insert(path.clone(), imports.clone());
-        programs.insert(
-            path.clone(),
-            Module::new(
-                funcs,
-                named_types,
-                used_constants,
-                global_vars,
-                imports,
-                string_table,
-                func_table,
-                path,
-                name,
-            ),
-        );
-    }
-    Ok((programs, import_map))
+    let mut source = String::new();
+    file.read_to_string(&mut source).map_err(|why| {
+        CompileError::new(
+            "Compile error",
+            format!("Can not read {}/{}: {:?}", folder.display(), name, why),
+            vec![],
+        )
+    })?;
+    Ok((name, source))
+}
+
+/// Points the error of a module that couldn't be read at the use statements importing it. File
+/// names are matched exactly, so when a file's name differs from the module's only in case, the
+/// first of them is given a fix that imports the module by the file's name.
+fn missing_module(
+    mut error: CompileError,
+    folder: &Path,
+    path: &[String],
+    import_map: &BTreeMap<Vec<String>, Vec<Import>>,
+) -> CompileError {
+    let importers: Vec<&Import> = import_map
+        .values()
+        .flatten()
+        .filter(|import| import.path == path)
+        .collect();
+    error.locations = importers
+        .iter()
+        .filter_map(|import| import.location)
+        .collect();
+
+    let file = folder.join(module_file(path));
+    let found = file
+        .parent()
+        .and_then(|parent| std::fs::read_dir(parent).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .find(|name| {
+            file.file_name()
+                .and_then(|wanted| wanted.to_str())
+                .map_or(false, |wanted| {
+                    name != wanted && name.eq_ignore_ascii_case(wanted)
+                })
+        });
+    let fix = found.zip(importers.first()).and_then(|(found, import)| {
+        let stem = found.strip_suffix(".mini")?;
+        let path: Vec<_> = path
+            .iter()
+            .map(|part| match part.eq_ignore_ascii_case(stem) {
+                true => stem.to_string(),
+                false => part.clone(),
+            })
+            .collect();
+        let public = if import.public { "public " } else { "" };
+        let statement = format!("{}use {}::{};", public, path.join("::"), import.name);
+        Some(Fix::replace(
+            &format!("import from {} instead", path_display(&path)),
+            import.location?,
+            import.end?,
+            &statement,
+        ))
+    });
+    error.with_fix(fix)
+}
+
+/// Parses the source of the module at path, found in the file name, returning it along with the
+/// syntax errors of the declarations left out of it.
+fn parse_module(
+    path: Vec<String>,
+    name: String,
+    source: String,
+    constants_path: Option<&Path>,
+    features: &BTreeSet<String>,
+    error_system: &mut ErrorSystem,
+    builtins: bool,
+) -> Result<(Module, Vec<CompileError>), CompileError> {
+    let file_id = module_file_id(&path);
+    let mut string_table = StringTable::new();
+    let mut used_constants = HashSet::new();
+    let (decls, closures, syntax_errors) = parse_from_source(
+        source,
+        file_id,
+        &path,
+        &mut string_table,
+        constants_path,
+        features,
+        &mut used_constants,
+        error_system,
+    )?;
+    let (
+        imports,
+        funcs,
+        named_types,
+        global_vars,
+        func_table,
+        interfaces,
+        impls,
+        type_decls,
+        const_decls,
+        static_asserts,
+    ) = typecheck::sort_top_level_decls(
+        (decls, closures),
+        path.clone(),
+        &mut string_table,
+        builtins,
+    );
+    let module = Module::new(
+        funcs,
+        named_types,
+        used_constants,
+        global_vars,
+        imports,
+        string_table,
+        func_table,
+        interfaces,
+        impls,
+        type_decls,
+        const_decls,
+        static_asserts,
+        path,
+        name,
+    );
+    Ok((module, syntax_errors))
 }
 
 fn resolve_imports(
-    modules: &mut HashMap<Vec<String>, Module>,
-    import_map: &mut HashMap<Vec<String>, Vec<Import>>,
+    modules: &mut BTreeMap<Vec<String>, Module>,
+    import_map: &mut BTreeMap<Vec<String>, Vec<Import>>,
     error_system: &mut ErrorSystem,
 ) -> Result<(), CompileError> {
+    follow_reexports(modules, import_map)?;
+
     for (name, imports) in import_map {
         for import in imports {
             let import_path = import.path.clone();
-            let (named_type, imp_func) = if let Some(module) = modules.get_mut(&import_path) {
-                // Looks up info from target module
-                let string_id = module
-                    .string_table
-                    .get_if_exists(&import.name.clone())
-                    .ok_or(CompileError::new(
-                        "Import Error",
+            let (named_type, imp_func, interface, recased) =
+                if let Some(module) = modules.get_mut(&import_path) {
+                    // Looks up info from target module
+                    let recased = recase_import(import, module);
+                    let string_id = module
+                        .string_table
+                        .get_if_exists(&import.name.clone())
+                        .ok_or_else(|| {
+                            CompileError::new(
+                                "Import Error",
+                                format!(
+                                    "Symbol {} does not exist in {}",
+                                    Color::red(&import.name),
+                                    Color::red(&import.path.join("/"))
+                                ),
+                                import.location.into_iter().collect(),
+                            )
+                            .with_code(ErrorCode::UnresolvedImport)
+                            .with_fix(recased.clone())
+                        })?;
+                    let named_type = module.named_types.get(&string_id).cloned();
+                    let imp_func = module.func_table.get(&string_id).cloned();
+                    let interface = module.interfaces.get(&string_id).cloned();
+                    (named_type, imp_func, interface, recased)
+                } else {
+                    return Err(CompileError::new(
+                        "Internal error",
                         format!(
-                            "Symbol {} does not exist in {}",
-                            Color::red(&import.name),
-                            Color::red(&import.path.join("/"))
+                            "Can not find target file for import \"{}::{}\"",
+                            import.path.get(0).cloned().unwrap_or_else(String::new),
+                            import.name
                         ),
                         import.location.into_iter().collect(),
-                    ))?;
-                let named_type = module.named_types.get(&string_id).cloned();
-                let imp_func = module.func_table.get(&string_id).cloned();
-                (named_type, imp_func)
-            } else {
-                return Err(CompileError::new(
-                    "Internal error",
-                    format!(
-                        "Can not find target file for import \"{}::{}\"",
-                        import.path.get(0).cloned().unwrap_or_else(String::new),
-                        import.name
-                    ),
-                    import.location.into_iter().collect(),
-                ));
-            };
+                    ));
+                };
 
             // Modifies origin module to include import
             let origin_module = modules.get_mut(name).ok_or_else(|| {
@@ -801,6 +1838,8 @@ fn resolve_imports(
                 origin_module
                     .named_types
                     .insert(string_id, named_type.clone());
+            } else if let Some(interface) = interface {
+                origin_module.interfaces.insert(string_id, interface);
             } else if let Some(imp_func) = imp_func {
                 let public = match imp_func {
                     Type::Func(prop, _, _) => prop.public,
@@ -820,27 +1859,370 @@ fn resolve_imports(
                             format!("Import error"),
                             format!("Func {} is private", Color::red(&import.name)),
                             import.loc(),
-                        ))
+                        )
+                        .with_code(ErrorCode::PrivateItem))
                     }
                 }
             } else {
-                error_system.warnings.push(CompileError::new_warning(
-                    "Compile Warning",
+                error_system.warnings.push(
+                    CompileError::new_warning(
+                        Lint::UnresolvedImports,
+                        "Compile Warning",
+                        format!(
+                            "import \"{}::{}\" does not correspond to a type or function",
+                            import.path.get(0).cloned().unwrap_or_else(String::new),
+                            import.name
+                        ),
+                        import.location.into_iter().collect(),
+                    )
+                    .with_fix(recased),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The fix for an import whose name differs only in case from one its module declares, which
+/// writes the declared name in its place. The name is written just before the use statement's
+/// semicolon.
+fn recase_import(import: &Import, module: &Module) -> Option<Fix> {
+    let declared = module
+        .named_types
+        .keys()
+        .chain(module.func_table.keys())
+        .chain(module.interfaces.keys())
+        .map(|id| module.string_table.name_from_id(*id))
+        .find(|name| **name != import.name && name.eq_ignore_ascii_case(&import.name))?;
+    let back = |location: Location, bytes: usize| {
+        Some(Location {
+            column: Column::from(location.column.to_usize().checked_sub(bytes)?),
+            absolute: BytePos::from(location.absolute.to_usize().checked_sub(bytes)?),
+            ..location
+        })
+    };
+    let name_end = back(import.end?, 1)?;
+    Some(Fix::replace(
+        &format!("import {} instead", declared),
+        back(name_end, import.name.len())?,
+        name_end,
+        declared,
+    ))
+}
+
+/// Points each import at the module declaring what it imports, following the `public use`
+/// declarations of the modules that re-export it along the way, so that a module can present the
+/// names of others as its own. Importing a name a module only imports privately is an error.
+fn follow_reexports(
+    modules: &mut BTreeMap<Vec<String>, Module>,
+    import_map: &mut BTreeMap<Vec<String>, Vec<Import>>,
+) -> Result<(), CompileError> {
+    let mut reexports = HashMap::new();
+    let mut private = HashSet::new();
+    for (path, imports) in import_map.iter() {
+        for import in imports {
+            let key = (path.clone(), import.name.clone());
+            match import.public {
+                true => drop(reexports.insert(key, import.path.clone())),
+                false => drop(private.insert(key)),
+            }
+        }
+    }
+
+    for imports in import_map.values_mut() {
+        for import in imports.iter_mut() {
+            let mut seen = HashSet::new();
+            let mut key = (import.path.clone(), import.name.clone());
+            while let Some(source) = reexports.get(&key) {
+                if !seen.insert(key.0.clone()) {
+                    return Err(CompileError::new(
+                        "Import error",
+                        format!(
+                            "{} is re-exported in a cycle through {}",
+                            Color::red(&import.name),
+                            Color::red(path_display(&key.0))
+                        ),
+                        import.loc(),
+                    ));
+                }
+                key.0 = source.clone();
+            }
+
+            let declared = modules.get(&key.0).map_or(false, |module| {
+                match module.string_table.get_if_exists(&key.1) {
+                    Some(id) => {
+                        module.named_types.contains_key(&id)
+                            || module.func_table.contains_key(&id)
+                            || module.interfaces.contains_key(&id)
+                    }
+                    None => false,
+                }
+            });
+            if private.contains(&key) && !declared {
+                return Err(CompileError::new(
+                    "Import error",
                     format!(
-                        "import \"{}::{}\" does not correspond to a type or function",
-                        import.path.get(0).cloned().unwrap_or_else(String::new),
-                        import.name
+                        "{} is only imported into {}, which must re-export it with {}",
+                        Color::red(&import.name),
+                        Color::red(path_display(&key.0)),
+                        Color::red("public use"),
                     ),
-                    import.location.into_iter().collect(),
+                    import.loc(),
                 ));
             }
+
+            import.path = key.0;
+            import.unique_id = Import::unique_id(&import.path, &import.name);
+        }
+    }
+
+    for (path, imports) in import_map.iter() {
+        if let Some(module) = modules.get_mut(path) {
+            module.imports = imports.clone();
         }
     }
     Ok(())
 }
 
+/// Checks each impl against the interface it implements, if any, then enters every method into the
+/// func table of every module and every visible interface's signatures into the func table of the
+/// modules that can see them. Returns the methods so calls can be linked to their funcs.
+fn resolve_impls(
+    modules: &mut BTreeMap<Vec<String>, Module>,
+    type_tree: &TypeTree,
+) -> Result<Vec<ImplMethod>, CompileError> {
+    let mut methods: Vec<ImplMethod> = vec![];
+
+    let paths: Vec<_> = modules.keys().cloned().collect();
+
+    for path in &paths {
+        let module = &modules[path];
+        let name = |id: StringId| module.string_table.name_from_id(id).clone();
+
+        for func in &module.funcs {
+            for bound in func.generic_bounds.iter().flatten() {
+                if !module.interfaces.contains_key(bound) {
+                    return Err(CompileError::new(
+                        "Interface error",
+                        format!("Interface {} doesn't exist", Color::red(name(*bound))),
+                        func.debug_info.locs(),
+                    ));
+                }
+            }
+        }
+
+        for decl in &module.impls {
+            let locs = decl.debug_info.locs();
+            let interface = match decl.interface {
+                Some(interface) => match module.interfaces.get(&interface) {
+                    Some(interface) => Some(interface),
+                    None => {
+                        return Err(CompileError::new(
+                            "Interface error",
+                            format!("Interface {} doesn't exist", Color::red(name(interface))),
+                            locs,
+                        ))
+                    }
+                },
+                None => None,
+            };
+            let implemented = match decl.interface {
+                Some(interface) => Color::red(name(interface)),
+                None => String::from("methods"),
+            };
+
+            let generic = match type_tree.get(&(path.clone(), decl.tipe)) {
+                Some((tipe, _)) => tipe.count_generic_slots() > 0,
+                None => {
+                    return Err(CompileError::new(
+                        "Interface error",
+                        format!(
+                            "Can't implement {} for unknown type {}",
+                            implemented,
+                            Color::red(name(decl.tipe))
+                        ),
+                        locs,
+                    ))
+                }
+            };
+            if generic {
+                return Err(CompileError::new(
+                    "Interface error",
+                    format!(
+                        "Can't implement {} for generic type {}",
+                        implemented,
+                        Color::red(name(decl.tipe))
+                    ),
+                    locs,
+                ));
+            }
+
+            let receiver = Type::Nominal(path.clone(), decl.tipe, vec![]);
+            let equivalent = |left: &Type, right: &Type| {
+                left.assignable(right, type_tree, HashSet::new())
+                    && right.assignable(left, type_tree, HashSet::new())
+            };
+
+            if let Some(interface) = interface {
+                for required in &interface.methods {
+                    let implemented = decl
+                        .funcs
+                        .iter()
+                        .any(|func| func.name.rsplit("::").next() == Some(required.name.as_str()));
+                    if !implemented {
+                        return Err(CompileError::new(
+                            "Interface error",
+                            format!(
+                                "Type {} doesn't implement {}'s method {}",
+                                Color::red(name(decl.tipe)),
+                                Color::red(name(interface.name)),
+                                Color::red(&required.name)
+                            ),
+                            locs.iter()
+                                .chain(required.debug_info.location.iter())
+                                .cloned()
+                                .collect(),
+                        )
+                        .with_code(ErrorCode::MissingMethod));
+                    }
+                }
+            }
+
+            for func in &decl.funcs {
+                let method = func
+                    .name
+                    .rsplit("::")
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                let locs = func.debug_info.locs();
+
+                if !func.generics.is_empty() {
+                    return Err(CompileError::new(
+                        "Interface error",
+                        format!("Method {} can't be generic", Color::red(&func.name)),
+                        locs,
+                    ));
+                }
+
+                let signature = match &func.tipe {
+                    Type::Func(prop, args, ret) if !args.is_empty() => {
+                        Some((&args[0], Type::Func(*prop, args[1..].to_vec(), ret.clone())))
+                    }
+                    _ => None,
+                };
+
+                match interface {
+                    Some(interface) => {
+                        let required = match interface.methods.iter().find(|m| m.name == method) {
+                            Some(required) => required,
+                            None => {
+                                return Err(CompileError::new(
+                                    "Interface error",
+                                    format!(
+                                        "Func {} isn't a method of {}",
+                                        Color::red(&method),
+                                        Color::red(name(interface.name))
+                                    ),
+                                    locs,
+                                ))
+                            }
+                        };
+                        match signature {
+                            Some((takes, signature))
+                                if equivalent(takes, &receiver)
+                                    && equivalent(&signature, &required.tipe) => {}
+                            _ => {
+                                return Err(CompileError::new(
+                                    "Interface error",
+                                    format!(
+                                        "Method {} must take a {} followed by the args of {}",
+                                        Color::red(&func.name),
+                                        Color::red(name(decl.tipe)),
+                                        Color::red(required.tipe.print(type_tree))
+                                    ),
+                                    locs.into_iter()
+                                        .chain(required.debug_info.location)
+                                        .collect(),
+                                ))
+                            }
+                        }
+                    }
+                    None => match signature {
+                        Some((takes, _)) if equivalent(takes, &receiver) => {}
+                        _ => {
+                            return Err(CompileError::new(
+                                "Interface error",
+                                format!(
+                                    "Method {} must take a {} as its first arg",
+                                    Color::red(&func.name),
+                                    Color::red(name(decl.tipe))
+                                ),
+                                locs,
+                            ))
+                        }
+                    },
+                }
+
+                let prior = methods
+                    .iter()
+                    .find(|prior| prior.name == method && equivalent(&prior.receiver, &receiver));
+                if let Some(prior) = prior {
+                    return Err(CompileError::new(
+                        "Interface error",
+                        format!(
+                            "Method {} is implemented twice, as {} and {}, for the same type",
+                            Color::red(&method),
+                            Color::red(&prior.func.name),
+                            Color::red(&func.name)
+                        ),
+                        prior.func.loc().into_iter().chain(locs).collect(),
+                    ));
+                }
+
+                methods.push(ImplMethod {
+                    receiver: receiver.clone(),
+                    name: method,
+                    func: Import::new(
+                        path.clone(),
+                        func.name.clone(),
+                        None,
+                        func.debug_info.location,
+                    ),
+                    tipe: func.tipe.clone(),
+                    public: func.public || interface.is_some(),
+                });
+            }
+        }
+    }
+
+    for module in modules.values_mut() {
+        for method in &methods {
+            let key = ast::impl_method_key(&method.func.path, &method.func.name);
+            let id = module.string_table.get(key);
+            module.func_table.insert(id, method.tipe.clone());
+        }
+        let mut interfaces: Vec<_> = module.interfaces.iter().collect();
+        interfaces.sort_by_key(|(id, _)| **id);
+        let mut signatures = vec![];
+        for (id, interface) in interfaces {
+            for method in &interface.methods {
+                let interface = module.string_table.name_from_id(*id);
+                let key = ast::interface_method_key(interface, &method.name);
+                signatures.push((key, method.tipe.clone()));
+            }
+        }
+        for (key, tipe) in signatures {
+            let id = module.string_table.get(key);
+            module.func_table.insert(id, tipe);
+        }
+    }
+
+    Ok(methods)
+}
+
 /// Constructor for `TypeTree`
-fn create_type_tree(program_tree: &HashMap<Vec<String>, Module>) -> TypeTree {
+fn create_type_tree(program_tree: &BTreeMap<Vec<String>, Module>) -> TypeTree {
     program_tree
         .iter()
         .map(|(path, program)| {
@@ -865,116 +2247,162 @@ fn create_type_tree(program_tree: &HashMap<Vec<String>, Module>) -> TypeTree {
         .collect()
 }
 
+/// Typechecks each module, reusing the results cached for those that haven't changed.
 fn typecheck_programs(
     type_tree: &TypeTree,
     modules: Vec<Module>,
-    _file_info_chart: &mut BTreeMap<u64, FileInfo>,
+    file_info_chart: &BTreeMap<u64, FileInfo>,
+    cache: Option<&ModuleCache>,
     error_system: &mut ErrorSystem,
 ) -> Result<Vec<TypeCheckedModule>, CompileError> {
-    let (typechecked_modules, module_issues) = modules
+    let keys = match cache {
+        Some(cache) => cache.keys(&modules, type_tree, file_info_chart),
+        None => vec![None; modules.len()],
+    };
+    let results: Vec<Result<_, Vec<CompileError>>> = modules
         .into_par_iter()
-        .map(
-            |Module {
-                 funcs,
-                 named_types,
-                 constants,
-                 global_vars,
-                 imports,
-                 string_table,
-                 func_table,
-                 path,
-                 name,
-             }| {
-                let mut typecheck_issues = vec![];
-                let (mut checked_funcs, global_vars, string_table) =
-                    typecheck::typecheck_top_level_decls(
-                        funcs,
-                        &named_types,
-                        global_vars,
-                        &imports,
-                        string_table,
-                        func_table,
-                        type_tree,
-                        &path,
-                    )?;
-
-                checked_funcs.iter_mut().for_each(|(id, func)| {
-                    let detected_view = func.is_view(type_tree);
-                    let detected_write = func.is_write(type_tree);
-
-                    let name = string_table.name_from_id(*id);
-
-                    if detected_view && !func.properties.view {
-                        typecheck_issues.push(CompileError::new_type_error(
-                            format!(
-                                "Func {} is {} but was not declared so",
-                                Color::red(name),
-                                Color::red("view")
-                            ),
-                            func.debug_info.locs(),
-                        ));
+        .zip(keys)
+        .map(|(module, key)| {
+            let cached = match (cache, key) {
+                (Some(cache), Some(key)) => cache.load(key),
+                _ => None,
+            };
+            if let Some(entry) = cached {
+                return Ok(entry);
+            }
+            let entry = typecheck_module(type_tree, module, error_system)?;
+            if let (Some(cache), Some(key)) = (cache, key) {
+                cache.store(key, &entry);
+            }
+            Ok(entry)
+        })
+        .collect();
+
+    // the errors of modules that fail are all reported, rather than only those of the first
+    let mut typechecked_modules = vec![];
+    let mut failures = vec![];
+    for result in results {
+        match result {
+            Ok((module, issues)) => {
+                typechecked_modules.push(module);
+                for issue in issues {
+                    match issue.is_warning {
+                        true => error_system.warnings.push(issue),
+                        false => error_system.errors.push(issue),
                     }
+                }
+            }
+            Err(errors) => failures.extend(errors),
+        }
+    }
+    error_system.fail_with(failures)?;
 
-                    if detected_write && !func.properties.write {
-                        typecheck_issues.push(CompileError::new_type_error(
-                            format!(
-                                "Func {} is {} but was not declared so",
-                                Color::red(name),
-                                Color::red("write")
-                            ),
-                            func.debug_info.locs(),
-                        ));
-                    }
+    Ok(typechecked_modules)
+}
 
-                    if !detected_view && func.properties.view {
-                        typecheck_issues.push(CompileError::new_warning(
-                            String::from("Typecheck warning"),
-                            format!(
-                                "Func {} is marked {} but isn't",
-                                Color::color(error_system.warn_color, name),
-                                Color::color(error_system.warn_color, "view")
-                            ),
-                            func.debug_info.locs(),
-                        ));
-                    }
+/// Typechecks a module, returning it along with the issues found in it.
+fn typecheck_module(
+    type_tree: &TypeTree,
+    module: Module,
+    error_system: &ErrorSystem,
+) -> Result<(TypeCheckedModule, Vec<CompileError>), Vec<CompileError>> {
+    let Module {
+        funcs,
+        named_types,
+        constants,
+        global_vars,
+        imports,
+        string_table,
+        func_table,
+        path,
+        name,
+        ..
+    } = module;
+    let mut typecheck_issues = vec![];
+    let (mut checked_funcs, global_vars, string_table) = typecheck::typecheck_top_level_decls(
+        funcs,
+        &named_types,
+        global_vars,
+        &imports,
+        string_table,
+        func_table,
+        type_tree,
+        &path,
+    )?;
 
-                    if !detected_write && func.properties.write {
-                        typecheck_issues.push(CompileError::new_warning(
-                            String::from("Typecheck warning"),
-                            format!(
-                                "Func {} is marked {} but isn't",
-                                Color::color(error_system.warn_color, name),
-                                Color::color(error_system.warn_color, "write")
-                            ),
-                            func.debug_info.locs(),
-                        ));
-                    }
-                });
-                Ok((
-                    TypeCheckedModule::new(
-                        checked_funcs,
-                        string_table,
-                        named_types,
-                        constants,
-                        global_vars,
-                        imports,
-                        path,
-                        name,
+    checked_funcs.iter_mut().for_each(|(id, func)| {
+        let detected_view = func.is_view(type_tree);
+        let detected_write = func.is_write(type_tree);
+
+        let name = string_table.name_from_id(*id);
+
+        if detected_view && !func.properties.view {
+            typecheck_issues.push(
+                CompileError::new_type_error(
+                    format!(
+                        "Func {} is {} but was not declared so",
+                        Color::red(name),
+                        Color::red("view")
                     ),
-                    typecheck_issues,
-                ))
-            },
-        )
-        .collect::<Result<(Vec<TypeCheckedModule>, Vec<Vec<CompileError>>), CompileError>>()?;
+                    func.debug_info.locs(),
+                )
+                .with_code(ErrorCode::UndeclaredPurity),
+            );
+        }
 
-    for issue in module_issues.into_iter().flatten() {
-        match issue.is_warning {
-            true => error_system.warnings.push(issue),
-            false => error_system.errors.push(issue),
+        if detected_write && !func.properties.write {
+            typecheck_issues.push(
+                CompileError::new_type_error(
+                    format!(
+                        "Func {} is {} but was not declared so",
+                        Color::red(name),
+                        Color::red("write")
+                    ),
+                    func.debug_info.locs(),
+                )
+                .with_code(ErrorCode::UndeclaredPurity),
+            );
         }
-    }
 
-    Ok(typechecked_modules)
+        if !detected_view && func.properties.view {
+            typecheck_issues.push(CompileError::new_warning(
+                Lint::Purity,
+                String::from("Typecheck warning"),
+                format!(
+                    "Func {} is marked {} but isn't",
+                    Color::color(error_system.warn_color, name),
+                    Color::color(error_system.warn_color, "view")
+                ),
+                func.debug_info.locs(),
+            ));
+        }
+
+        if !detected_write && func.properties.write {
+            typecheck_issues.push(CompileError::new_warning(
+                Lint::Purity,
+                String::from("Typecheck warning"),
+                format!(
+                    "Func {} is marked {} but isn't",
+                    Color::color(error_system.warn_color, name),
+                    Color::color(error_system.warn_color, "write")
+                ),
+                func.debug_info.locs(),
+            ));
+        }
+    });
+    Ok((
+        TypeCheckedModule::new(
+            checked_funcs,
+            string_table,
+            named_types,
+            constants,
+            global_vars,
+            imports,
+            path,
+            name,
+        ),
+        typecheck_issues,
+    ))
 }
 
 fn check_global_constants(
@@ -992,6 +2420,7 @@ fn check_global_constants(
     for (constant, _) in global_constants {
         if !constant.starts_with('_') {
             error_system.warnings.push(CompileError::new_warning(
+                Lint::UnusedConstants,
                 "Compile Warning",
                 format!(
                     "global constant {} is never used",
@@ -1003,18 +2432,73 @@ fn check_global_constants(
     }
 }
 
+/// The prefixes of the global constants that describe the interface a program presents to the
+/// chain: the selectors of precompile methods, the topics of the events precompiles emit, the kinds
+/// of logs sent to the node, and the defaults of chain parameters.
+pub const INTERFACE_CONSTANTS: [&str; 4] =
+    ["funcCode_", "eventTopic_", "LogType_", "Default_Param_"];
+
+/// Finds the global constants describing the program's interface that its modules use.
+fn interface_constants(
+    modules: &[TypeCheckedModule],
+    constants_path: Option<&Path>,
+) -> Result<BTreeMap<String, Uint256>, CompileError> {
+    let global_constants = init_constant_table(constants_path)?;
+    Ok(modules
+        .iter()
+        .flat_map(|module| &module.constants)
+        .filter(|name| {
+            INTERFACE_CONSTANTS
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+        .filter_map(|name| {
+            global_constants
+                .get(name)
+                .map(|value| (name.clone(), value.clone()))
+        })
+        .collect())
+}
+
 fn codegen_modules(
     typechecked_modules: Vec<TypeCheckedModule>,
-    type_tree: TypeTree,
+    mut type_tree: TypeTree,
+    methods: &[ImplMethod],
     release_build: bool,
+    tail_calls: bool,
+    coverage: bool,
+    overflow_checks: bool,
+    opt_level: u8,
+    hooks: &AttributeHooks,
+    libraries: &[LibraryArchive],
 ) -> Result<(Vec<CompiledFunc>, Vec<GlobalVar>), CompileError> {
     let mut work_list = vec![];
+    let mut templates = BTreeMap::new();
     let mut globals_so_far = 0;
+    let mut prebuilt = vec![];
+    let mut fingerprints = HashMap::new();
+    let options = (
+        release_build,
+        tail_calls,
+        coverage,
+        overflow_checks,
+        opt_level,
+    );
+
+    // archived funcs are linked alongside the app's, so they see its types as well as their own
+    for library in libraries {
+        for (key, entry) in &library.type_tree {
+            type_tree
+                .entry(key.clone())
+                .or_insert_with(|| entry.clone());
+        }
+    }
 
     for mut module in typechecked_modules {
         // assign globals to the right of all prior
         let mut global_vars = HashMap::new();
-        for mut global in module.global_vars {
+        for global in &module.global_vars {
+            let mut global = global.clone();
             global.offset = Some(globals_so_far);
             global_vars.insert(global.id, global);
             globals_so_far += 1;
@@ -1039,64 +2523,117 @@ fn codegen_modules(
                 None => panic!("Import without id {:#?}", &import),
             }
         }
+        for method in methods {
+            let key = ast::impl_method_key(&method.func.path, &method.func.name);
+            if let Some(id) = module.string_table.get_if_exists(&key) {
+                func_labels.insert(id, Label::Func(method.func.unique_id));
+            }
+        }
+
+        // a module compiled into a library archive the same way it would be now is taken from
+        // there, though its generic funcs are still kept for specializing
+        let fingerprint = module_fingerprint(&module, &func_labels, &type_tree, options);
+        let archived = libraries
+            .iter()
+            .find_map(|library| library.module(&module.path, fingerprint))
+            .and_then(|archived| archived.instantiate(&global_vars, &type_tree));
+        let reused = archived.is_some();
+        prebuilt.extend(archived.into_iter().flatten());
+        fingerprints.insert(module.path.clone(), fingerprint);
 
         for (_, func) in module.checked_funcs {
-            work_list.push((
+            let unique_id = func.unique_id.unwrap();
+            let generic = !func.generics.is_empty();
+            if reused && !generic {
+                continue;
+            }
+            let item = (
                 func,
                 func_labels.clone(),
                 module.string_table.clone(),
                 global_vars.clone(),
                 module.path.clone(),
-            ));
+            );
+            match generic {
+                true => drop(templates.insert(unique_id, item)),
+                false => work_list.push(item),
+            }
         }
     }
 
+    monomorph::monomorphize(&mut work_list, templates, &type_tree)?;
+    monomorph::resolve_methods(&mut work_list, methods, &type_tree)?;
+
     let mut funcs = work_list
         .into_par_iter()
-        .map(|(func, func_labels, string_table, globals, module_path)| {
-            let func_name = func.name.clone();
-            let debug_info = func.debug_info;
-
-            let (code, mut label_gen, frame_size) = codegen::mavm_codegen_func(
-                func,
-                &string_table,
-                &globals,
-                &func_labels,
-                release_build,
-            )?;
-
-            let mut graph = BasicGraph::new(code);
-
-            graph.pop_useless_locals();
-            graph.color(frame_size);
-            let frame_size = graph.shrink_frame();
-
-            let code = graph.flatten();
-            let code = translate::expand_calls(code, &mut label_gen);
-            let code = translate::untag_jumps(code);
-            let code = translate::replace_phi_nodes(code);
-            let (code, captures) = translate::read_capture_data(code);
-
-            let globals: Vec<_> = globals.into_iter().map(|g| g.1).collect();
-
-            let prog = CompiledFunc::new(
-                func_name,
-                module_path,
-                code,
-                captures,
-                frame_size,
-                globals,
-                type_tree.clone(),
-                debug_info,
-            );
+        .map(
+            |(mut func, func_labels, mut string_table, globals, module_path)| {
+                consteval::fold_constants(&mut func, &type_tree);
+                bounds::elide_bounds_checks(&mut func);
+                if opt_level >= 2 {
+                    escape::scalarize_tuples(&mut func, &mut string_table);
+                }
 
-            Ok(prog)
-        })
+                let func_name = func.name.clone();
+                let tipe = func.tipe.clone();
+                let unique_id = func.unique_id.unwrap();
+                let debug_info = func.debug_info;
+                let attributes = func.attributes.clone();
+
+                let (code, mut label_gen, frame_size) = codegen::mavm_codegen_func(
+                    func,
+                    &string_table,
+                    &globals,
+                    &func_labels,
+                    release_build,
+                    tail_calls,
+                    coverage,
+                    overflow_checks,
+                )?;
+                let code = hooks.rewrite(&attributes, &func_name, debug_info, code)?;
+
+                let mut graph = BasicGraph::new(code);
+
+                graph.pop_useless_locals();
+                graph.color(frame_size);
+                let frame_size = graph.shrink_frame();
+
+                let code = graph.flatten();
+                let calls = CallSites::new(&code);
+                let code = translate::expand_calls(code, &mut label_gen);
+                let code = translate::untag_jumps(code);
+                let code = translate::replace_phi_nodes(code);
+                let (code, captures) = translate::read_capture_data(code);
+
+                let globals: Vec<_> = globals.into_iter().map(|g| g.1).collect();
+
+                let mut prog = CompiledFunc::new(
+                    func_name,
+                    module_path,
+                    code,
+                    captures,
+                    frame_size,
+                    globals,
+                    type_tree.clone(),
+                    debug_info,
+                    tipe,
+                );
+                prog.unique_id = unique_id; // specialized copies aren't named after their ids
+                prog.calls = calls;
+                prog.fingerprint = fingerprints[&prog.path];
+
+                Ok(prog)
+            },
+        )
         .collect::<Result<Vec<CompiledFunc>, CompileError>>()?;
 
+    // specializations made for archived funcs are archived too, but those made again are kept
+    let compiled: HashSet<LabelId> = funcs.iter().map(|func| func.unique_id).collect();
+    prebuilt.retain(|func| !compiled.contains(&func.unique_id));
+
     let mut capture_map = HashMap::new();
     let mut frame_sizes = HashMap::new();
-    for func in &funcs {
+    for func in funcs.iter().chain(&prebuilt) {
         let func_id = func.unique_id;
         capture_map.insert(func_id, func.captures.clone());
         frame_sizes.insert(func_id, func.frame_size);
@@ -1104,6 +2641,8 @@ fn codegen_modules(
     for func in &mut funcs {
         func.code = translate::pack_closures(&func.code, &capture_map, &frame_sizes);
     }
+    funcs.extend(prebuilt);
+    check_label_collisions(&funcs)?;
 
     let mut globals = BTreeMap::new();
     for func in &funcs {
@@ -1123,6 +2662,174 @@ fn codegen_modules(
     Ok((funcs, globals))
 }
 
+/// Hashes everything codegen reads of a module: its funcs, globals, and imports, the labels its
+/// names refer to, the types its funcs and globals use, and the options codegen is given.
+fn module_fingerprint(
+    module: &TypeCheckedModule,
+    func_labels: &HashMap<StringId, Label>,
+    type_tree: &TypeTree,
+    options: (bool, bool, bool, bool, u8),
+) -> u64 {
+    let labels: BTreeMap<_, _> = func_labels.iter().collect();
+    let mut roots: Vec<&Type> = module
+        .checked_funcs
+        .values()
+        .map(|func| &func.tipe)
+        .collect();
+    roots.extend(module.global_vars.iter().map(|global| &global.tipe));
+    let types = cache::reachable_types(roots, type_tree);
+    let contents = (
+        &module.path,
+        &module.checked_funcs,
+        &module.global_vars,
+        &module.imports,
+        labels,
+        types,
+        options,
+    );
+    stable_hash(&bincode::serialize(&contents).expect("failed to serialize module"))
+}
+
+/// Fails if two funcs define labels with the same id, which would otherwise silently send the
+/// jumps and calls meant for one of them to the other.
+fn check_label_collisions(funcs: &[CompiledFunc]) -> Result<(), CompileError> {
+    let mut owners: HashMap<LabelId, &CompiledFunc> = HashMap::new();
+    for func in funcs {
+        let labels = func.code.iter().filter_map(|insn| match insn.opcode {
+            Opcode::Label(Label::Func(id) | Label::Closure(id) | Label::Anon(id)) => Some(id),
+            _ => None,
+        });
+        for id in std::iter::once(func.unique_id).chain(labels) {
+            let owner = *owners.entry(id).or_insert(func);
+            if !std::ptr::eq(owner, func) {
+                let name = |func: &CompiledFunc| {
+                    let mut path = func.path.clone();
+                    path.push(func.name.clone());
+                    Color::red(path_display(&path))
+                };
+                return Err(CompileError::new(
+                    "Label error",
+                    format!(
+                        "Funcs {} and {} were given the same label id {}",
+                        name(owner),
+                        name(func),
+                        id
+                    ),
+                    owner
+                        .debug_info
+                        .locs()
+                        .into_iter()
+                        .chain(func.debug_info.locs())
+                        .collect(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_label_collisions() {
+    let func = |name: &str| {
+        CompiledFunc::new(
+            name.to_string(),
+            vec![String::from("main")],
+            vec![],
+            HashMap::new(),
+            0,
+            vec![],
+            HashMap::new(),
+            DebugInfo::default(),
+            Type::Void,
+        )
+    };
+    let (first, mut second) = (func("first"), func("second"));
+    assert!(check_label_collisions(&[func("first"), func("second")]).is_ok());
+
+    // a label generated within one func may not reuse the id of another
+    second.code.push(Instruction::from_opcode(
+        Opcode::Label(Label::Anon(first.unique_id)),
+        DebugInfo::default(),
+    ));
+    let error = check_label_collisions(&[first, second]).err().unwrap();
+    assert!(
+        error.description.contains("main::first") && error.description.contains("main::second")
+    );
+}
+
+/// Converts an error from the parser into a `CompileError` pointing to where it happened.
+pub fn parse_error<T: Display>(
+    error: ParseError<usize, T, CompileError>,
+    source: &str,
+    lines: &Lines,
+    file_id: u64,
+) -> CompileError {
+    match error {
+        ParseError::UnrecognizedToken {
+            token: (offset, tok, _),
+            expected,
+        } => CompileError::new(
+            String::from("Compile error: unexpected token"),
+            format!("{}, expected one of: {}", tok, comma_list(&expected),),
+            vec![lines.location(BytePos::from(offset), file_id).unwrap()],
+        )
+        .with_code(ErrorCode::UnexpectedToken)
+        .with_fix(missing_semicolon(&expected, source, offset, lines, file_id)),
+        ParseError::InvalidToken { location } => CompileError::new(
+            "Compile error",
+            "found invalid token",
+            lines
+                .location(location.into(), file_id)
+                .into_iter()
+                .collect(),
+        )
+        .with_code(ErrorCode::UnexpectedToken),
+        ParseError::UnrecognizedEOF { location, expected } => CompileError::new(
+            String::from("Compile error: unexpected end of file"),
+            format!("expected one of: {}", comma_list(&expected)),
+            lines
+                .location(location.into(), file_id)
+                .into_iter()
+                .collect(),
+        )
+        .with_code(ErrorCode::UnexpectedToken)
+        .with_fix(missing_semicolon(
+            &expected, source, location, lines, file_id,
+        )),
+        ParseError::ExtraToken {
+            token: (offset, tok, _),
+        } => CompileError::new(
+            String::from("Compile error: extra token"),
+            format!("{}", tok,),
+            vec![lines.location(BytePos::from(offset), file_id).unwrap()],
+        )
+        .with_code(ErrorCode::UnexpectedToken),
+        ParseError::User { error } => error,
+    }
+}
+
+/// The fix for a statement missing its semicolon, which is suggested when the parser would have
+/// taken one and the token it found instead starts a later line than the one before it.
+fn missing_semicolon(
+    expected: &[String],
+    source: &str,
+    offset: usize,
+    lines: &Lines,
+    file_id: u64,
+) -> Option<Fix> {
+    if !expected.iter().any(|token| token == r#"";""#) {
+        return None;
+    }
+    let before = source.get(..offset)?.trim_end();
+    let last_line = before.rsplit('\n').next().unwrap_or(before);
+    if before.is_empty() || !source[before.len()..offset].contains('\n') || last_line.contains("//")
+    {
+        return None;
+    }
+    let at = lines.location(BytePos::from(before.len()), file_id)?;
+    Some(Fix::insert("add a semicolon", at, ";"))
+}
+
 pub fn comma_list(input: &[String]) -> String {
     let mut base = String::new();
     if input.len() > 0 {
@@ -1136,24 +2843,38 @@ pub fn comma_list(input: &[String]) -> String {
     base
 }
 
+/// The declarations of a source file, the closures found in them, and the syntax errors of the
+/// declarations that couldn't be parsed.
+pub type ParsedSource = (
+    Vec<TopLevelDecl>,
+    BTreeMap<StringId, Func>,
+    Vec<CompileError>,
+);
+
 /// Converts source string `source` into a series of `TopLevelDecl`s, uses identifiers from
 /// `string_table` and records new ones in it as well. The `file_id` argument is used to construct
 /// file information for the location fields.
+///
+/// The parser skips over declarations it can't parse, so along with the declarations it returns
+/// the syntax errors of those it skipped.
 pub fn parse_from_source(
     source: String,
     file_id: u64,
     file_path: &[String],
     string_table: &mut StringTable,
     constants_path: Option<&Path>,
+    features: &BTreeSet<String>,
     used_constants: &mut HashSet<String>,
     error_system: &mut ErrorSystem,
-) -> Result<(Vec<TopLevelDecl>, BTreeMap<StringId, Func>), CompileError> {
-    let lines = Lines::new(source.bytes());
+) -> Result<ParsedSource, CompileError> {
+    let expansion = macros::expand_macros(&source, file_id)?;
+    let lines = Lines::new(source.bytes()).with_origins(expansion.origins);
+    let source = expansion.source;
     let mut constants = init_constant_table(constants_path)?;
     let mut local_constants = HashMap::<String, Location>::new();
     let mut closures = BTreeMap::new();
 
-    let parsed = DeclsParser::new()
+    let (parsed, syntax_errors) = DeclsParser::new()
         .parse(
             string_table,
             &lines,
@@ -1163,51 +2884,17 @@ pub fn parse_from_source(
             &mut local_constants,
             used_constants,
             &mut closures,
+            features,
             error_system,
             &source,
         )
-        .map_err(|e| match e {
-            ParseError::UnrecognizedToken {
-                token: (offset, _tok, end),
-                expected,
-            } => CompileError::new(
-                String::from("Compile error: unexpected token"),
-                format!(
-                    "{}, expected one of: {}",
-                    &source[offset..end],
-                    comma_list(&expected),
-                ),
-                vec![lines.location(BytePos::from(offset), file_id).unwrap()],
-            ),
-            ParseError::InvalidToken { location } => CompileError::new(
-                "Compile error",
-                format!("found invalid token"),
-                lines
-                    .location(location.into(), file_id)
-                    .into_iter()
-                    .collect(),
-            ),
-            ParseError::UnrecognizedEOF { location, expected } => CompileError::new(
-                String::from("Compile error: unexpected end of file"),
-                format!("expected one of: {}", comma_list(&expected)),
-                lines
-                    .location(location.into(), file_id)
-                    .into_iter()
-                    .collect(),
-            ),
-            ParseError::ExtraToken {
-                token: (offset, _tok, end),
-            } => CompileError::new(
-                String::from("Compile error: extra token"),
-                format!("{}", &source[offset..end],),
-                vec![lines.location(BytePos::from(offset), file_id).unwrap()],
-            ),
-            ParseError::User { error } => error,
-        })?;
+        .map_err(|error| parse_error(error, &source, &lines, file_id))?;
 
+    // uses of constants in the declarations that were skipped aren't seen
     for (constant, loc) in local_constants {
-        if !used_constants.contains(&constant) {
+        if !used_constants.contains(&constant) && syntax_errors.is_empty() {
             error_system.warnings.push(CompileError::new_warning(
+                Lint::UnusedConstants,
                 "Compile Warning",
                 format!(
                     "Constant {} is never used",
@@ -1218,11 +2905,11 @@ pub fn parse_from_source(
         }
     }
 
-    Ok((parsed, closures))
+    Ok((parsed, closures, syntax_errors))
 }
 
 /// Represents any error encountered during compilation.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompileError {
     /// The error title
     pub title: String,
@@ -1232,6 +2919,95 @@ pub struct CompileError {
     pub locations: Vec<Location>,
     /// Whether the error should not stop compilation
     pub is_warning: bool,
+    /// The lint a warning belongs to
+    pub lint: Option<Lint>,
+    /// The code of the kind of error, which `mini explain` explains
+    #[serde(default)]
+    pub code: Option<ErrorCode>,
+    /// An edit to the source that resolves the error, shown as a hint and offered by tools
+    #[serde(default)]
+    pub fix: Option<Fix>,
+}
+
+/// An edit that resolves an error or warning by replacing the source between two locations.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fix {
+    /// What the edit does, as offered to the user
+    pub title: String,
+    /// Where the replaced source starts
+    pub start: Location,
+    /// Where the replaced source ends, exclusively
+    pub end: Location,
+    /// What's written in its place
+    pub replacement: String,
+}
+
+impl Fix {
+    /// A fix that removes the source between two locations.
+    pub fn remove(title: &str, start: Location, end: Location) -> Self {
+        Fix {
+            title: title.to_string(),
+            start,
+            end,
+            replacement: String::new(),
+        }
+    }
+
+    /// A fix that writes text at a location.
+    pub fn insert(title: &str, at: Location, text: &str) -> Self {
+        Fix {
+            title: title.to_string(),
+            start: at,
+            end: at,
+            replacement: text.to_string(),
+        }
+    }
+
+    /// A fix that writes text in place of the source between two locations.
+    pub fn replace(title: &str, start: Location, end: Location, text: &str) -> Self {
+        Fix {
+            title: title.to_string(),
+            start,
+            end,
+            replacement: text.to_string(),
+        }
+    }
+
+    /// Shows the line the fix edits as it reads once fixed, with what's written marked beneath.
+    /// Only fixes that write text within a single line are shown this way.
+    fn pretty_fmt(&self, file_info_chart: &BTreeMap<u64, FileInfo>) -> String {
+        if self.replacement.is_empty()
+            || self.replacement.contains('\n')
+            || self.start.line != self.end.line
+        {
+            return String::new();
+        }
+        let (start, end) = (self.start.column.to_usize(), self.end.column.to_usize());
+        let line = file_info_chart
+            .get(&self.start.file_id)
+            .and_then(|info| info.contents.get(self.start.line.to_usize()))
+            .and_then(|line| Some((line.get(..start)?, line.get(end..)?)));
+        let marker = match start == end {
+            true => "+",
+            false => "~",
+        };
+        match line {
+            Some((before, after)) => format!(
+                "     {}\n{} {} {}{}{}\n     {}{:space$}{}\n",
+                Color::blue("|"),
+                Color::blue(format!("{: <4}", self.start.line)),
+                Color::blue("|"),
+                before,
+                Color::mint(&self.replacement),
+                after,
+                Color::blue("|"),
+                "",
+                Color::mint(marker.repeat(self.replacement.chars().count())),
+                space = start + 1,
+            ),
+            None => String::new(),
+        }
+    }
 }
 
 impl Display for CompileError {
@@ -1257,10 +3033,13 @@ impl CompileError {
             description: description.to_string(),
             locations,
             is_warning: false,
+            lint: None,
+            code: None,
+            fix: None,
         }
     }
 
-    pub fn new_warning<S, U>(title: S, description: U, locations: Vec<Location>) -> Self
+    pub fn new_warning<S, U>(lint: Lint, title: S, description: U, locations: Vec<Location>) -> Self
     where
         S: std::string::ToString,
         U: std::string::ToString,
@@ -1270,9 +3049,24 @@ impl CompileError {
             description: description.to_string(),
             locations,
             is_warning: true,
+            lint: Some(lint),
+            code: None,
+            fix: None,
         }
     }
 
+    /// Gives the error the code of its kind.
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Attaches an edit that resolves the warning, when there is one.
+    pub fn with_fix(mut self, fix: Option<Fix>) -> Self {
+        self.fix = fix;
+        self
+    }
+
     pub fn new_type_error<S>(description: S, locations: Vec<Location>) -> Self
     where
         S: std::string::ToString,
@@ -1282,6 +3076,9 @@ impl CompileError {
             description: description.to_string(),
             locations,
             is_warning: false,
+            lint: None,
+            code: None,
+            fix: None,
         }
     }
 
@@ -1294,6 +3091,9 @@ impl CompileError {
             description: description.to_string(),
             locations: location.into_iter().collect(),
             is_warning: false,
+            lint: None,
+            code: None,
+            fix: None,
         }
     }
 
@@ -1316,8 +3116,13 @@ impl CompileError {
         let last_line = &self.locations.last();
 
         let mut pretty = format!(
-            "{}: {}\n{}    --> {}{}\n",
+            "{}{}: {}\n{}    --> {}{}\n",
             Color::color(err_color, &self.title),
+            match (self.lint, self.code) {
+                (Some(lint), _) => Color::grey(format!(" [{}]", lint)),
+                (None, Some(code)) => Color::grey(format!(" [{}]", code)),
+                (None, None) => String::new(),
+            },
             self.description,
             blue,
             match last_line {
@@ -1370,6 +3175,18 @@ impl CompileError {
             })
             .collect::<String>();
 
+        if let Some(fix) = &self.fix {
+            pretty += &format!("     {} help: {}\n", Color::blue("="), fix.title);
+            pretty += &fix.pretty_fmt(file_info_chart);
+        }
+        if let Some(code) = self.code {
+            pretty += &format!(
+                "     {} note: run `mini explain {}` to learn more about this error\n",
+                Color::blue("="),
+                code
+            );
+        }
+
         pretty
     }
 
@@ -1390,9 +3207,70 @@ pub struct ErrorSystem {
     pub warn_color: &'static str,
     /// File information that helps the error system pretty-print errors and warnings
     pub file_info_chart: BTreeMap<u64, FileInfo>,
+    /// How severely to treat the warnings of lints, where different from their defaults
+    pub lint_levels: BTreeMap<Lint, LintLevel>,
 }
 
 impl ErrorSystem {
+    /// Fails if there are any errors, returning the last one and keeping the rest, so that all of
+    /// them are reported once the caller records the one returned.
+    pub fn fail_with(&mut self, mut errors: Vec<CompileError>) -> Result<(), CompileError> {
+        match errors.pop() {
+            Some(last) => {
+                self.errors.extend(errors);
+                Err(last)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Gets how severely to treat the warnings of a lint.
+    pub fn lint_level(&self, lint: Lint) -> LintLevel {
+        self.lint_levels
+            .get(&lint)
+            .copied()
+            .unwrap_or_else(|| lint.default_level())
+    }
+
+    /// Drops the warnings of allowed lints and turns those of denied lints into errors, returning
+    /// whether any were denied.
+    pub fn apply_lint_levels(&mut self) -> bool {
+        let mut denied = false;
+        for warning in std::mem::take(&mut self.warnings) {
+            match warning.lint.map(|lint| self.lint_level(lint)) {
+                Some(LintLevel::Allow) => {}
+                Some(LintLevel::Deny) => {
+                    denied = true;
+                    self.errors.push(CompileError {
+                        is_warning: false,
+                        ..warning
+                    });
+                }
+                Some(LintLevel::Warn) | None => self.warnings.push(warning),
+            }
+        }
+        denied
+    }
+
+    /// Creates an empty error system with the same settings, to collect the issues of work done
+    /// on another thread.
+    fn fork(&self) -> ErrorSystem {
+        ErrorSystem {
+            errors: vec![],
+            warnings: vec![],
+            warnings_are_errors: self.warnings_are_errors,
+            warn_color: self.warn_color,
+            file_info_chart: BTreeMap::new(),
+            lint_levels: self.lint_levels.clone(),
+        }
+    }
+
+    /// Takes in the issues collected by a fork.
+    fn join(&mut self, fork: ErrorSystem) {
+        self.errors.extend(fork.errors);
+        self.warnings.extend(fork.warnings);
+    }
+
     pub fn print(&self) {
         for warning in &self.warnings {
             warning.print(&self.file_info_chart, self.warnings_are_errors);
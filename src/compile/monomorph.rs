@@ -0,0 +1,292 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides monomorphization, which gives each specialization of a generic func its own copy.
+
+use super::ast::{impl_method_key, GlobalVar, ImplMethod, Type, TypeTree};
+use super::typecheck::{AbstractSyntaxTree, TypeCheckedExprKind, TypeCheckedFunc, TypeCheckedNode};
+use crate::compile::CompileError;
+use crate::console::Color;
+use crate::mavm::{label_id, Label, LabelId};
+use crate::stringtable::{StringId, StringTable};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// A func to be generated along with the module state needed to generate it.
+pub type WorkItem = (
+    TypeCheckedFunc,
+    HashMap<StringId, Label>,
+    StringTable,
+    HashMap<StringId, GlobalVar>,
+    Vec<String>,
+);
+
+/// The most copies of a single func, which bounds funcs that call themselves with ever-growing types.
+const MAX_SPECIALIZATIONS: usize = 256;
+
+/// Whether a func reference with this specialization refers to a monomorphized copy of its func.
+/// Specializations that depend on the generics of the func they appear in can only be resolved
+/// once that func is itself specialized.
+pub fn is_concrete(specialization: &[Type]) -> bool {
+    !specialization.is_empty() && !specialization.iter().any(|tipe| tipe.is_generic())
+}
+
+/// Gets the label id of a func's copy for the given specialization.
+pub fn specialized_id(unique_id: LabelId, specialization: &[Type]) -> LabelId {
+    label_id(&(unique_id, specialization))
+}
+
+/// Adds a copy of each generic func in `templates` to `work_list` for every specialization used
+/// by the funcs reachable from those already in `work_list`. Within each copy the generics are
+/// replaced, so default values like those filling a `newarray<T>` match the specialized type.
+///
+/// Generic funcs referenced without a specialization, and those never referenced at all, are
+/// added as-is so they keep their erased behavior.
+pub fn monomorphize(
+    work_list: &mut Vec<WorkItem>,
+    templates: BTreeMap<LabelId, WorkItem>,
+    type_tree: &TypeTree,
+) -> Result<(), CompileError> {
+    let mut queue = vec![];
+    for (func, func_labels, ..) in work_list.iter_mut() {
+        queue.extend(find_references(func, func_labels, &templates, None));
+    }
+
+    let mut reached = HashSet::new();
+    let mut emitted = HashSet::new();
+    let mut copies = HashMap::new();
+
+    loop {
+        while let Some((unique_id, specialization)) = queue.pop() {
+            if !emitted.insert((unique_id, specialization.clone())) {
+                continue;
+            }
+            reached.insert(unique_id);
+
+            let (mut func, mut func_labels, string_table, globals, path) =
+                templates[&unique_id].clone();
+
+            if let Some(spec) = &specialization {
+                let count = copies.entry(unique_id).or_insert(0);
+                *count += 1;
+                if *count > MAX_SPECIALIZATIONS {
+                    return Err(CompileError::new(
+                        "Generics error",
+                        format!(
+                            "Func {} has more than {} specializations. Does it call itself with an ever-growing type?",
+                            Color::red(&func.name),
+                            MAX_SPECIALIZATIONS,
+                        ),
+                        func.debug_info.locs(),
+                    ));
+                }
+                func = specialize_func(&func, spec, type_tree);
+            }
+
+            queue.extend(find_references(
+                &mut func,
+                &func_labels,
+                &templates,
+                specialization.as_ref(),
+            ));
+
+            // a specialized func loads the closures specialized alongside it
+            if let Some(spec) = &specialization {
+                for label in func_labels.values_mut() {
+                    if let Label::Closure(closure_id) = label {
+                        if templates.contains_key(closure_id) {
+                            *label = Label::Closure(specialized_id(*closure_id, spec));
+                        }
+                    }
+                }
+            }
+
+            work_list.push((func, func_labels, string_table, globals, path));
+        }
+
+        // generate unused generic funcs so the linker can warn about them
+        match templates.keys().find(|id| !reached.contains(id)) {
+            Some(unique_id) => queue.push((*unique_id, None)),
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Replaces the method calls of specialized generic funcs with calls to the funcs implementing
+/// them for the specialized receiver. Calls in funcs that were never specialized are left as-is.
+pub fn resolve_methods(
+    work_list: &mut [WorkItem],
+    methods: &[ImplMethod],
+    type_tree: &TypeTree,
+) -> Result<(), CompileError> {
+    fn resolve(
+        mut nodes: Vec<TypeCheckedNode>,
+        func_labels: &mut HashMap<StringId, Label>,
+        string_table: &mut StringTable,
+        methods: &[ImplMethod],
+        type_tree: &TypeTree,
+    ) -> Result<(), CompileError> {
+        for node in &mut nodes {
+            if let TypeCheckedNode::Expression(expr) = node {
+                if let TypeCheckedExprKind::MethodRef(receiver, name, tipe) = &expr.kind {
+                    if !receiver.is_generic() {
+                        let method = methods.iter().find(|method| {
+                            &method.name == name
+                                && method
+                                    .receiver
+                                    .assignable(receiver, type_tree, HashSet::new())
+                                && receiver.assignable(&method.receiver, type_tree, HashSet::new())
+                        });
+                        let method = match method {
+                            Some(method) => method,
+                            None => {
+                                return Err(CompileError::new(
+                                    "Interface error",
+                                    format!(
+                                        "Type {} doesn't implement method {}",
+                                        Color::red(receiver.print(type_tree)),
+                                        Color::red(name),
+                                    ),
+                                    expr.debug_info.locs(),
+                                ))
+                            }
+                        };
+                        let key = impl_method_key(&method.func.path, &method.func.name);
+                        let id = string_table.get(key);
+                        func_labels.insert(id, Label::Func(method.func.unique_id));
+                        expr.kind = TypeCheckedExprKind::FuncRef(id, vec![], tipe.clone());
+                    }
+                }
+            }
+            resolve(
+                node.child_nodes(),
+                func_labels,
+                string_table,
+                methods,
+                type_tree,
+            )?;
+        }
+        Ok(())
+    }
+
+    for (func, func_labels, string_table, ..) in work_list.iter_mut() {
+        resolve(
+            func.child_nodes(),
+            func_labels,
+            string_table,
+            methods,
+            type_tree,
+        )?;
+    }
+    Ok(())
+}
+
+/// Makes a copy of a generic func with its generics replaced by those in the specialization.
+fn specialize_func(
+    func: &TypeCheckedFunc,
+    specialization: &[Type],
+    type_tree: &TypeTree,
+) -> TypeCheckedFunc {
+    let mut func = func.clone();
+    let names: Vec<_> = specialization.iter().map(|tipe| tipe.display()).collect();
+    func.name = format!("{}::<{}>", func.name, names.join(", "));
+    func.unique_id = func.unique_id.map(|id| specialized_id(id, specialization));
+    func.generics = vec![];
+    func.tipe = func.tipe.specialize_generics(specialization);
+    func.ret_type = func.ret_type.specialize_generics(specialization);
+    for arg in &mut func.args {
+        arg.tipe = arg.tipe.specialize_generics(specialization);
+    }
+    specialize_nodes(func.child_nodes(), specialization, type_tree);
+    func
+}
+
+fn specialize_nodes(
+    mut nodes: Vec<TypeCheckedNode>,
+    specialization: &[Type],
+    type_tree: &TypeTree,
+) {
+    for node in &mut nodes {
+        if let TypeCheckedNode::Expression(expr) = node {
+            match &mut expr.kind {
+                TypeCheckedExprKind::FuncRef(_, spec, tipe) => {
+                    for generic in spec.iter_mut() {
+                        *generic = generic.specialize_generics(specialization);
+                    }
+                    *tipe = tipe.specialize_generics(specialization);
+                }
+                TypeCheckedExprKind::MethodRef(receiver, _, tipe) => {
+                    *receiver = receiver.specialize_generics(specialization);
+                    *tipe = tipe.specialize_generics(specialization);
+                }
+                TypeCheckedExprKind::Const(value, tipe) if tipe.is_generic() => {
+                    // generic consts are the default values of their types
+                    *tipe = tipe.specialize_generics(specialization);
+                    *value = tipe.default_value(type_tree);
+                }
+                _ => {}
+            }
+        }
+        specialize_nodes(node.child_nodes(), specialization, type_tree);
+    }
+}
+
+/// Finds the generic funcs and closures a func refers to, along with the specialization of each.
+fn find_references(
+    func: &mut TypeCheckedFunc,
+    func_labels: &HashMap<StringId, Label>,
+    templates: &BTreeMap<LabelId, WorkItem>,
+    specialization: Option<&Vec<Type>>,
+) -> Vec<(LabelId, Option<Vec<Type>>)> {
+    fn find(
+        mut nodes: Vec<TypeCheckedNode>,
+        func_labels: &HashMap<StringId, Label>,
+        templates: &BTreeMap<LabelId, WorkItem>,
+        specialization: Option<&Vec<Type>>,
+        found: &mut Vec<(LabelId, Option<Vec<Type>>)>,
+    ) {
+        for node in &mut nodes {
+            if let TypeCheckedNode::Expression(expr) = node {
+                let reference = match &expr.kind {
+                    TypeCheckedExprKind::FuncRef(id, spec, _) => match func_labels.get(id) {
+                        Some(Label::Func(unique_id)) if is_concrete(spec) => {
+                            Some((*unique_id, Some(spec.clone())))
+                        }
+                        Some(Label::Func(unique_id)) => Some((*unique_id, None)),
+                        _ => None,
+                    },
+                    // closures share the generics of the func they're declared in
+                    TypeCheckedExprKind::ClosureLoad(id, ..) => match func_labels.get(id) {
+                        Some(Label::Closure(unique_id)) => {
+                            Some((*unique_id, specialization.cloned()))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some(reference) = reference {
+                    if templates.contains_key(&reference.0) {
+                        found.push(reference);
+                    }
+                }
+            }
+            find(
+                node.child_nodes(),
+                func_labels,
+                templates,
+                specialization,
+                found,
+            );
+        }
+    }
+
+    let mut found = vec![];
+    find(
+        func.child_nodes(),
+        func_labels,
+        templates,
+        specialization,
+        &mut found,
+    );
+    found
+}
@@ -12,6 +12,8 @@ use crate::pos::{BytePos, Column, Line, Location};
 pub struct Lines {
     starting_bytes: Vec<BytePos>,
     end: usize,
+    /// Where each stretch of the parsed source came from, when it isn't the source itself
+    origins: Vec<(BytePos, BytePos)>,
 }
 
 impl Lines {
@@ -36,6 +38,27 @@ impl Lines {
         Lines {
             starting_bytes,
             end: len,
+            origins: vec![],
+        }
+    }
+
+    /// Maps the bytes of source that was derived from `src`, like source with its macros expanded,
+    /// back to `src`. Each origin pairs the start of a stretch of the derived source with the byte
+    /// it came from.
+    pub fn with_origins(mut self, origins: Vec<(BytePos, BytePos)>) -> Self {
+        self.origins = origins;
+        self
+    }
+
+    /// Finds the byte of `src` that `byte` of the derived source came from.
+    fn origin(&self, byte: BytePos) -> BytePos {
+        let index = self.origins.partition_point(|(start, _)| *start <= byte);
+        match index.checked_sub(1) {
+            Some(index) => {
+                let (start, origin) = self.origins[index];
+                origin + (byte - start)
+            }
+            None => byte,
         }
     }
 
@@ -47,6 +70,7 @@ impl Lines {
 
     /// Returns the line and column location of `byte`
     pub fn location(&self, byte: BytePos, file_id: u64) -> Option<Location> {
+        let byte = self.origin(byte);
         if byte.to_usize() <= self.end {
             let line_index = self.line_number_at_byte(byte);
 
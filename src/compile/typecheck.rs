@@ -5,18 +5,21 @@
 //! Converts non-type checked ast nodes to type checked versions, and other related utilities.
 
 use super::ast::{
-    AssignRef, Attributes, BinaryOp, CodeBlock, Constant, DebugInfo, Expr, ExprKind, Func,
-    GlobalVar, Statement, StatementKind, StructField, TopLevelDecl, TrinaryOp, Type, TypeTree,
-    UnaryOp,
+    interface_method_key, AssignRef, Attributes, BinaryOp, CodeBlock, ConstDecl, Constant,
+    DebugInfo, EnumVariant, Expr, ExprKind, Func, GlobalVar, ImplDecl, InterfaceDecl, MatchArm,
+    Pattern, Statement, StatementKind, StaticAssert, StructField, TopLevelDecl, TrinaryOp, Type,
+    TypeDecl, TypeTree, UnaryOp,
 };
+use super::matching;
 use crate::compile::ast::{FieldInitializer, FuncProperties};
-use crate::compile::{CompileError, ErrorSystem};
+use crate::compile::{CompileError, ErrorCode, ErrorSystem, Fix, Lint};
 use crate::console::{human_readable_index, Color};
 use crate::link::Import;
-use crate::mavm::{Instruction, Value};
+use crate::mavm::{Instruction, Opcode, Value};
 use crate::pos::Location;
 use crate::stringtable::{StringId, StringTable};
 use crate::uint256::Uint256;
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 type TypeTable = HashMap<usize, Type>;
@@ -146,7 +149,7 @@ fn flowcheck_imports(mut nodes: Vec<TypeCheckedNode>, imports: &mut BTreeMap<usi
             }
 
             // observe any function calls or pointers
-            if let TypeCheckedExprKind::FuncRef(id, _) = &expr.kind {
+            if let TypeCheckedExprKind::FuncRef(id, ..) = &expr.kind {
                 imports.remove(&id);
             }
         }
@@ -155,6 +158,33 @@ fn flowcheck_imports(mut nodes: Vec<TypeCheckedNode>, imports: &mut BTreeMap<usi
     }
 }
 
+/// Discovers match arms that could never be taken
+fn flowcheck_match_arms(mut nodes: Vec<TypeCheckedNode>) -> Vec<CompileError> {
+    let mut warnings = vec![];
+
+    for node in nodes.iter_mut() {
+        if let TypeCheckedNode::Expression(expr) = node {
+            if let TypeCheckedExprKind::Match(_, _, unreachable, _) = &expr.kind {
+                if !unreachable.is_empty() {
+                    warnings.push(CompileError::new_warning(
+                        Lint::UnreachableCode,
+                        "Compile warning",
+                        match unreachable.len() {
+                            1 => "found unreachable match arm",
+                            _ => "found unreachable match arms",
+                        },
+                        unreachable.clone(),
+                    ));
+                }
+            }
+        }
+
+        warnings.extend(flowcheck_match_arms(node.child_nodes()));
+    }
+
+    warnings
+}
+
 /// Discovers code segments that could never be executed
 fn flowcheck_reachability<T: AbstractSyntaxTree>(node: &mut T) -> Vec<CompileError> {
     let mut children = node.child_nodes();
@@ -206,6 +236,7 @@ fn flowcheck_reachability<T: AbstractSyntaxTree>(node: &mut T) -> Vec<CompileErr
     }
 
     warnings.push(CompileError::new_warning(
+        Lint::UnreachableCode,
         "Compile warning",
         if locations.len() == 2 {
             "found unreachable statement"
@@ -218,6 +249,82 @@ fn flowcheck_reachability<T: AbstractSyntaxTree>(node: &mut T) -> Vec<CompileErr
     warnings
 }
 
+/// Discovers lets that rebind variables already in scope, giving the locations of both bindings
+fn flowcheck_shadowing(
+    mut nodes: Vec<TypeCheckedNode>,
+    in_scope: &HashMap<StringId, Option<Location>>,
+    shadows: &mut Vec<(StringId, Vec<Location>)>,
+) {
+    let mut scope = in_scope.clone();
+
+    for node in &mut nodes {
+        // the branches of an if are separate scopes, but its child nodes don't say where each ends
+        if let TypeCheckedNode::Expression(expr) = node {
+            match &mut expr.kind {
+                TypeCheckedExprKind::If(cond, block, else_block, _)
+                | TypeCheckedExprKind::IfLet(_, cond, block, else_block, _) => {
+                    flowcheck_shadowing(vec![TypeCheckedNode::Expression(cond)], &scope, shadows);
+                    flowcheck_shadowing(block.child_nodes(), &scope, shadows);
+                    if let Some(branch) = else_block {
+                        flowcheck_shadowing(branch.child_nodes(), &scope, shadows);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        flowcheck_shadowing(node.child_nodes(), &scope, shadows);
+
+        if let TypeCheckedNode::Statement(stat) = node {
+            if let TypeCheckedStatementKind::SetLocals(assigned, _) = &stat.kind {
+                for local in assigned.iter().filter(|local| local.shadow) {
+                    if let Some(prior) = scope.get(&local.id) {
+                        shadows.push((
+                            local.id,
+                            prior
+                                .iter()
+                                .chain(local.debug_info.location.iter())
+                                .cloned()
+                                .collect(),
+                        ));
+                    }
+                    scope.insert(local.id, local.debug_info.location);
+                }
+            }
+        }
+    }
+}
+
+/// Finds where each let binds a variable, and the names referred to other than by a let
+fn flowcheck_bindings(
+    mut nodes: Vec<TypeCheckedNode>,
+    lets: &mut BTreeSet<Location>,
+    named: &mut BTreeSet<StringId>,
+) {
+    for node in &mut nodes {
+        match node {
+            TypeCheckedNode::Statement(stat) => {
+                if let TypeCheckedStatementKind::SetLocals(assigned, _) = &stat.kind {
+                    for local in assigned {
+                        match local.shadow {
+                            true => lets.extend(local.debug_info.location),
+                            false => drop(named.insert(local.id)),
+                        }
+                    }
+                }
+            }
+            TypeCheckedNode::Expression(expr) => match &expr.kind {
+                TypeCheckedExprKind::LocalVariableRef(id, ..) => drop(named.insert(*id)),
+                TypeCheckedExprKind::ClosureLoad(_, captures, _) => named.extend(captures),
+                _ => {}
+            },
+            _ => {}
+        }
+        flowcheck_bindings(node.child_nodes(), lets, named);
+    }
+}
+
 /// Discovers assigned values that are never used
 fn flowcheck_liveliness(
     mut nodes: Vec<TypeCheckedNode>,
@@ -340,6 +447,30 @@ fn flowcheck_liveliness(
                     process!((if_killed, if_reborn));
                     continue;
                 }
+                TypeCheckedExprKind::Match(scrutinee, arms, ..) => {
+                    process!(
+                        vec![TypeCheckedNode::Expression(scrutinee)],
+                        problems,
+                        false
+                    );
+
+                    let (mut arm_killed, mut arm_reborn) = (
+                        BTreeSet::<StringId>::new(),
+                        BTreeMap::<StringId, Location>::new(),
+                    );
+                    for arm in arms {
+                        let (child_killed, child_reborn) = flowcheck_liveliness(
+                            vec![TypeCheckedNode::Expression(&mut arm.body)],
+                            problems,
+                            false,
+                        );
+                        arm_killed.extend(child_killed);
+                        arm_reborn.extend(child_reborn);
+                    }
+
+                    process!((arm_killed, arm_reborn));
+                    continue;
+                }
                 TypeCheckedExprKind::Loop(_body, _) => true,
                 TypeCheckedExprKind::ClosureLoad(_, captures, _) => {
                     // In the future we'll walk into the closure in case a captured value is overwritten,
@@ -425,33 +556,57 @@ impl TypeCheckedFunc {
             imports.remove(&id);
         }
 
+        for id in self
+            .generic_bounds
+            .iter()
+            .flatten()
+            .chain(self.interface.iter())
+            .chain(self.receiver.iter())
+        {
+            imports.remove(id);
+        }
+
         flowcheck_warnings.extend(flowcheck_reachability(self));
+        flowcheck_warnings.extend(flowcheck_match_arms(self.child_nodes()));
 
         let mut unused_assignments = vec![];
 
         let (killed, reborn) =
             flowcheck_liveliness(self.child_nodes(), &mut unused_assignments, false);
 
+        // a variable can only be marked unused where nothing else refers to it by name
+        let (mut lets, mut named) = (BTreeSet::new(), BTreeSet::new());
+        flowcheck_bindings(self.child_nodes(), &mut lets, &mut named);
+        let mark_unused = |loc: Option<Location>, id: &StringId| match named.contains(id) {
+            true => None,
+            false => Some(Fix::insert("prefix it with an underscore", loc?, "_")),
+        };
+
         for arg in self.args.iter() {
             // allow intentional lack of use
             if !string_table.name_from_id(arg.name.clone()).starts_with('_') {
                 if !killed.contains(&arg.name) {
-                    flowcheck_warnings.push(CompileError::new_warning(
-                        String::from("Compile warning"),
-                        format!(
-                            "func {}'s argument {} is declared but never used",
-                            Color::color(error_system.warn_color, &self.name),
-                            Color::color(
-                                error_system.warn_color,
-                                string_table.name_from_id(arg.name.clone())
+                    flowcheck_warnings.push(
+                        CompileError::new_warning(
+                            Lint::UnusedVariables,
+                            String::from("Compile warning"),
+                            format!(
+                                "func {}'s argument {} is declared but never used",
+                                Color::color(error_system.warn_color, &self.name),
+                                Color::color(
+                                    error_system.warn_color,
+                                    string_table.name_from_id(arg.name.clone())
+                                ),
                             ),
-                        ),
-                        arg.debug_info.location.into_iter().collect(),
-                    ));
+                            arg.debug_info.location.into_iter().collect(),
+                        )
+                        .with_fix(mark_unused(arg.debug_info.location, &arg.name)),
+                    );
                 }
 
                 if let Some(loc) = reborn.get(&arg.name) {
                     flowcheck_warnings.push(CompileError::new_warning(
+                        Lint::UnusedVariables,
                         String::from("Compile warning"),
                         format!(
                             "func {}'s argument {} is assigned but never used",
@@ -467,33 +622,60 @@ impl TypeCheckedFunc {
             }
         }
 
-        for &(loc, id) in unused_assignments.iter() {
-            // allow intentional lack of use
-            if !string_table.name_from_id(id.clone()).starts_with('_') {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| (arg.name, arg.debug_info.location))
+            .collect();
+        let mut shadows = vec![];
+        flowcheck_shadowing(self.child_nodes(), &args, &mut shadows);
+
+        for (id, locations) in shadows {
+            // names marked as unused are placeholders, which are rebound all the time
+            if !string_table.name_from_id(id).starts_with('_') {
                 flowcheck_warnings.push(CompileError::new_warning(
+                    Lint::Shadowing,
                     String::from("Compile warning"),
                     format!(
-                        "value {} is assigned but never used",
+                        "let {} shadows a variable already in scope",
                         Color::color(error_system.warn_color, string_table.name_from_id(id)),
                     ),
-                    vec![loc],
+                    locations,
                 ));
             }
         }
 
+        for &(loc, id) in unused_assignments.iter() {
+            // allow intentional lack of use
+            if !string_table.name_from_id(id.clone()).starts_with('_') {
+                flowcheck_warnings.push(
+                    CompileError::new_warning(
+                        Lint::UnusedVariables,
+                        String::from("Compile warning"),
+                        format!(
+                            "value {} is assigned but never used",
+                            Color::color(error_system.warn_color, string_table.name_from_id(id)),
+                        ),
+                        vec![loc],
+                    )
+                    .with_fix(mark_unused(lets.get(&loc).copied(), &id)),
+                );
+            }
+        }
+
         flowcheck_warnings
     }
 }
 
 /// A mini statement that has been type checked.
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TypeCheckedStatement {
     pub kind: TypeCheckedStatementKind,
     pub debug_info: DebugInfo,
 }
 
 /// A mini statement that has been type checked.
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum TypeCheckedStatementKind {
     ReturnVoid(),
     Return(TypeCheckedExpr),
@@ -538,14 +720,14 @@ impl AbstractSyntaxTree for TypeCheckedStatement {
 }
 
 /// A mini expression with associated `DebugInfo` that has been type checked.
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TypeCheckedExpr {
     pub kind: TypeCheckedExprKind,
     pub debug_info: DebugInfo,
 }
 
 /// A mini expression that has been type checked.
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum TypeCheckedExprKind {
     NewBuffer,
     Quote(Vec<u8>),
@@ -563,7 +745,8 @@ pub enum TypeCheckedExprKind {
     LocalVariableRef(StringId, Type),
     GlobalVariableRef(StringId, Type),
     Variant(Box<TypeCheckedExpr>),
-    FuncRef(StringId, Type),
+    FuncRef(StringId, Vec<Type>, Type),
+    MethodRef(Type, String, Type),
     TupleRef(Box<TypeCheckedExpr>, usize, usize, Type),
     Const(Value, Type),
     FunctionCall(
@@ -573,15 +756,25 @@ pub enum TypeCheckedExprKind {
         FuncProperties,
     ),
     CodeBlock(TypeCheckedCodeBlock),
-    FixedArrayRef(Box<TypeCheckedExpr>, Box<TypeCheckedExpr>, usize, Type),
+    /// The array, the index, the array's size, and whether the index is known to be in bounds
+    FixedArrayRef(
+        Box<TypeCheckedExpr>,
+        Box<TypeCheckedExpr>,
+        usize,
+        bool,
+        Type,
+    ),
     ClosureLoad(StringId, BTreeSet<StringId>, Type),
     Tuple(Vec<TypeCheckedExpr>, Type),
     NewFixedArray(usize, Box<TypeCheckedExpr>, Type),
+    /// The array, the index, the new value, the array's size, and whether the index is known to
+    /// be in bounds
     FixedArrayMod(
         Box<TypeCheckedExpr>,
         Box<TypeCheckedExpr>,
         Box<TypeCheckedExpr>,
         usize,
+        bool,
         Type,
     ),
     StructMod(
@@ -610,15 +803,25 @@ pub enum TypeCheckedExprKind {
         Option<TypeCheckedCodeBlock>,
         Type,
     ),
+    Match(
+        Box<TypeCheckedExpr>,
+        Vec<TypeCheckedMatchArm>,
+        Vec<Location>,
+        Type,
+    ),
     Loop(TypeCheckedCodeBlock, Type),
 }
 
+/// An arm of a match expression that has been type checked.
+pub type TypeCheckedMatchArm = MatchArm<TypeCheckedExpr>;
+
 impl AbstractSyntaxTree for TypeCheckedExpr {
     fn child_nodes(&mut self) -> Vec<TypeCheckedNode> {
         match &mut self.kind {
             TypeCheckedExprKind::LocalVariableRef(..)
             | TypeCheckedExprKind::GlobalVariableRef(..)
             | TypeCheckedExprKind::FuncRef(..)
+            | TypeCheckedExprKind::MethodRef(..)
             | TypeCheckedExprKind::ClosureLoad(..)
             | TypeCheckedExprKind::Const(..)
             | TypeCheckedExprKind::NewBuffer
@@ -640,7 +843,7 @@ impl AbstractSyntaxTree for TypeCheckedExpr {
             TypeCheckedExprKind::Binary(_, lexp, rexp, _)
             | TypeCheckedExprKind::ShortcutOr(lexp, rexp)
             | TypeCheckedExprKind::ShortcutAnd(lexp, rexp)
-            | TypeCheckedExprKind::FixedArrayRef(lexp, rexp, _, _)
+            | TypeCheckedExprKind::FixedArrayRef(lexp, rexp, ..)
             | TypeCheckedExprKind::StructMod(lexp, _, _, rexp, _) => vec![
                 TypeCheckedNode::Expression(lexp),
                 TypeCheckedNode::Expression(rexp),
@@ -662,7 +865,7 @@ impl AbstractSyntaxTree for TypeCheckedExpr {
                 .iter_mut()
                 .map(|exp| TypeCheckedNode::Expression(exp))
                 .collect(),
-            TypeCheckedExprKind::FixedArrayMod(exp1, exp2, exp3, _, _) => vec![
+            TypeCheckedExprKind::FixedArrayMod(exp1, exp2, exp3, ..) => vec![
                 TypeCheckedNode::Expression(exp1),
                 TypeCheckedNode::Expression(exp2),
                 TypeCheckedNode::Expression(exp3),
@@ -680,6 +883,15 @@ impl AbstractSyntaxTree for TypeCheckedExpr {
                     )
                     .collect()
             }
+            TypeCheckedExprKind::Match(scrutinee, arms, ..) => {
+                vec![TypeCheckedNode::Expression(scrutinee)]
+                    .into_iter()
+                    .chain(
+                        arms.iter_mut()
+                            .map(|arm| TypeCheckedNode::Expression(&mut arm.body)),
+                    )
+                    .collect()
+            }
         }
     }
     fn is_view(&mut self, type_tree: &TypeTree) -> bool {
@@ -754,7 +966,7 @@ impl TypeCheckedExpr {
             _ => return error_msg,
         };
         Ok(TypeCheckedExpr::new(
-            TypeCheckedExprKind::FuncRef(builtin_id, builtin_type),
+            TypeCheckedExprKind::FuncRef(builtin_id, vec![], builtin_type),
             debug_info,
         ))
     }
@@ -763,7 +975,7 @@ impl TypeCheckedExpr {
     pub fn get_type(&self) -> Type {
         match &self.kind {
             TypeCheckedExprKind::NewBuffer => Type::Buffer,
-            TypeCheckedExprKind::Quote(_) => Type::Tuple(vec![Type::Uint, Type::Buffer]),
+            TypeCheckedExprKind::Quote(_) => Type::String,
             TypeCheckedExprKind::Error => Type::Every,
             TypeCheckedExprKind::GetGas => Type::Uint,
             TypeCheckedExprKind::SetGas(_t) => Type::Void,
@@ -776,6 +988,7 @@ impl TypeCheckedExpr {
             TypeCheckedExprKind::LocalVariableRef(.., t) => t.clone(),
             TypeCheckedExprKind::GlobalVariableRef(.., t) => t.clone(),
             TypeCheckedExprKind::FuncRef(.., t) => t.clone(),
+            TypeCheckedExprKind::MethodRef(.., t) => t.clone(),
             TypeCheckedExprKind::TupleRef(.., t) => t.clone(),
             TypeCheckedExprKind::Variant(t) => Type::Option(Box::new(t.get_type())),
             TypeCheckedExprKind::Const(.., t) => t.clone(),
@@ -792,6 +1005,7 @@ impl TypeCheckedExpr {
             TypeCheckedExprKind::Try(.., t) => t.clone(),
             TypeCheckedExprKind::If(.., t) => t.clone(),
             TypeCheckedExprKind::IfLet(.., t) => t.clone(),
+            TypeCheckedExprKind::Match(.., t) => t.clone(),
             TypeCheckedExprKind::Loop(.., t) => t.clone(),
         }
     }
@@ -811,6 +1025,103 @@ impl AbstractSyntaxTree for TypeCheckedFieldInitializer {
     }
 }
 
+/// Checks that a pattern can match values of type `tipe`, adding the names it binds to `bindings`.
+/// Returns the pattern with its variants lowered into the tagged tuples that represent them.
+fn typecheck_pattern(
+    pattern: &Pattern,
+    tipe: &Type,
+    bindings: &mut HashMap<StringId, Type>,
+    type_tree: &TypeTree,
+    string_table: &StringTable,
+    debug_info: DebugInfo,
+) -> Result<Pattern, CompileError> {
+    macro_rules! error {
+        ($text:expr $(,$args:expr)* $(,)?) => {
+            return Err(CompileError::new("Typecheck error", format!($text, $(Color::red($args),)*), debug_info.locs()))
+        };
+    }
+
+    let rep = tipe.rep(type_tree)?;
+    let fields = match &rep {
+        Type::String => Type::Tuple(Type::string_fields()),
+        _ => rep.clone(),
+    };
+    let lowered = match (pattern, &fields) {
+        (Pattern::Wildcard, _) => Pattern::Wildcard,
+        (Pattern::Bind(id), _) => {
+            if bindings.insert(*id, tipe.clone()).is_some() {
+                error!(
+                    "Pattern binds {} more than once",
+                    string_table.name_from_id(*id)
+                );
+            }
+            Pattern::Bind(*id)
+        }
+        (Pattern::Const(constant), _) if constant.type_of() == rep => pattern.clone(),
+        (Pattern::Some(inner), Type::Option(inner_type)) => {
+            Pattern::Some(Box::new(typecheck_pattern(
+                inner,
+                inner_type,
+                bindings,
+                type_tree,
+                string_table,
+                debug_info,
+            )?))
+        }
+        (Pattern::None, Type::Option(_)) => Pattern::None,
+        (Pattern::Tuple(fields), Type::Tuple(types)) if fields.len() == types.len() => {
+            let mut lowered = vec![];
+            for (field, field_type) in fields.iter().zip(types) {
+                lowered.push(typecheck_pattern(
+                    field,
+                    field_type,
+                    bindings,
+                    type_tree,
+                    string_table,
+                    debug_info,
+                )?);
+            }
+            Pattern::Tuple(lowered)
+        }
+        (Pattern::Variant(named, name, fields), Type::Enum(variants))
+            if named.rep(type_tree)? == rep =>
+        {
+            let (tag, variant) = match EnumVariant::lookup(variants, name) {
+                Some(found) => found,
+                None => error!("There's no variant {} in {}", name, named.print(type_tree)),
+            };
+            if fields.len() != variant.fields.len() {
+                error!(
+                    "Variant {} has {} fields but its pattern has {}",
+                    name,
+                    variant.fields.len(),
+                    fields.len()
+                );
+            }
+            let mut lowered = vec![];
+            for (field, field_type) in fields.iter().zip(&variant.fields) {
+                lowered.push(typecheck_pattern(
+                    field,
+                    field_type,
+                    bindings,
+                    type_tree,
+                    string_table,
+                    debug_info,
+                )?);
+            }
+            Pattern::Tuple(vec![
+                Pattern::Const(Constant::Uint(Uint256::from_usize(tag))),
+                Pattern::Tuple(lowered),
+            ])
+        }
+        _ => error!(
+            "Pattern can't match values of type {}",
+            tipe.print(type_tree)
+        ),
+    };
+    Ok(lowered)
+}
+
 /// Returns a vector of `ImportFuncDecl`s corresponding to the builtins as defined by string_table,
 /// if they are not defined in string_table, they are inserted.
 fn builtin_func_decls() -> Vec<Import> {
@@ -821,6 +1132,9 @@ fn builtin_func_decls() -> Vec<Import> {
         Import::new_builtin("kvs", "builtin_kvsNew"),
         Import::new_builtin("kvs", "builtin_kvsGet"),
         Import::new_builtin("kvs", "builtin_kvsSet"),
+        Import::new_builtin("string", "builtin_stringConcat"),
+        Import::new_builtin("string", "builtin_stringSlice"),
+        Import::new_builtin("string", "builtin_stringCompare"),
     ]
 }
 
@@ -836,6 +1150,11 @@ pub fn sort_top_level_decls(
     HashMap<usize, Type>,
     Vec<GlobalVar>,
     HashMap<usize, Type>,
+    HashMap<StringId, InterfaceDecl>,
+    Vec<ImplDecl>,
+    Vec<TypeDecl>,
+    Vec<ConstDecl>,
+    Vec<StaticAssert>,
 ) {
     let (decls, closures) = parsed;
 
@@ -858,6 +1177,11 @@ pub fn sort_top_level_decls(
     let mut named_types = HashMap::new();
     let mut func_table = HashMap::new();
     let mut globals = vec![];
+    let mut interfaces = HashMap::new();
+    let mut impls = vec![];
+    let mut type_decls = vec![];
+    let mut const_decls = vec![];
+    let mut static_asserts = vec![];
 
     for decl in decls {
         match decl {
@@ -869,12 +1193,26 @@ pub fn sort_top_level_decls(
                 funcs.push(fd);
             }
             TopLevelDecl::TypeDecl(td) => {
-                named_types.insert(td.name, td.tipe);
+                named_types.insert(td.name, td.tipe.clone());
+                type_decls.push(td);
             }
             TopLevelDecl::VarDecl(vd) => {
                 globals.push(vd);
             }
-            TopLevelDecl::ConstDecl => {}
+            TopLevelDecl::ConstDecl(cd) => {
+                const_decls.push(cd);
+            }
+            TopLevelDecl::InterfaceDecl(id) => {
+                interfaces.insert(id.name, id);
+            }
+            TopLevelDecl::ImplDecl(id) => {
+                // methods are entered into the func table once every impl is known
+                funcs.extend(id.funcs.iter().cloned());
+                impls.push(id);
+            }
+            TopLevelDecl::StaticAssert(sa) => {
+                static_asserts.push(sa);
+            }
         }
     }
 
@@ -882,11 +1220,70 @@ pub fn sort_top_level_decls(
         func_table.insert(*id, closure.tipe.clone());
     }
 
-    (imports, funcs, named_types, globals, func_table)
+    (
+        imports,
+        funcs,
+        named_types,
+        globals,
+        func_table,
+        interfaces,
+        impls,
+        type_decls,
+        const_decls,
+        static_asserts,
+    )
+}
+
+/// Typechecks the condition of a static assert. Conditions are checked outside of any func, so
+/// they can use constants and the module's types, but nothing that only exists at runtime.
+pub fn typecheck_static_assert(
+    assert: &StaticAssert,
+    string_table: &StringTable,
+    type_tree: &TypeTree,
+) -> Result<TypeCheckedExpr, CompileError> {
+    let func = Func::new(
+        String::from("static_assert"),
+        usize::MAX,
+        false,
+        false,
+        false,
+        false,
+        vec![],
+        None,
+        vec![],
+        BTreeSet::new(),
+        vec![],
+        assert.debug_info,
+    );
+    let condition = typecheck_expr(
+        &assert.condition,
+        &HashMap::new(),
+        &HashMap::new(),
+        &HashMap::new(),
+        &func,
+        type_tree,
+        string_table,
+        &mut HashMap::new(),
+        &mut BTreeMap::new(),
+        &mut vec![],
+    )?;
+    match condition.get_type() {
+        Type::Bool => Ok(condition),
+        tipe => Err(CompileError::new(
+            "Typecheck error",
+            format!(
+                "static_assert condition must be bool, found {}",
+                Color::red(tipe.print(type_tree))
+            ),
+            assert.debug_info.locs(),
+        )),
+    }
 }
 
 /// Performs typechecking various top level declarations, `FuncDecl`s,
 /// named `Type`s, and global variables.
+///
+/// Each func is checked even if others fail, so that the errors of all of them are returned.
 pub fn typecheck_top_level_decls(
     funcs: Vec<Func>,
     named_types: &HashMap<usize, Type>,
@@ -902,7 +1299,7 @@ pub fn typecheck_top_level_decls(
         Vec<GlobalVar>,
         StringTable,
     ),
-    CompileError,
+    Vec<CompileError>,
 > {
     if let Some(var) = global_vars
         .iter()
@@ -927,6 +1324,7 @@ pub fn typecheck_top_level_decls(
 
     let mut checked_funcs = BTreeMap::new();
     let mut checked_closures = BTreeMap::new();
+    let mut errors = vec![];
 
     for func in &funcs {
         let mut type_tree = type_tree.clone();
@@ -939,19 +1337,24 @@ pub fn typecheck_top_level_decls(
                 ),
             );
         }
-        checked_funcs.insert(
-            func.id,
-            typecheck_function(
-                &func,
-                &type_table,
-                &global_vars_map,
-                &func_table,
-                &type_tree,
-                &string_table,
-                &mut checked_closures,
-                &mut undefinable_ids,
-            )?,
-        );
+        match typecheck_function(
+            func,
+            &type_table,
+            &global_vars_map,
+            &func_table,
+            &type_tree,
+            &string_table,
+            &mut checked_closures,
+            &mut undefinable_ids,
+        ) {
+            Ok(checked) => {
+                checked_funcs.insert(func.id, checked);
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
     }
 
     checked_funcs.extend(checked_closures);
@@ -1026,7 +1429,8 @@ pub fn typecheck_function(
                     .chain(arg.debug_info.location.iter())
                     .cloned()
                     .collect(),
-            ));
+            )
+            .with_code(ErrorCode::GlobalShadowing));
         }
         hm.insert(arg.name, arg.tipe.clone());
     }
@@ -1062,7 +1466,8 @@ pub fn typecheck_function(
                     Color::red(string_table.name_from_id(func.id))
                 ),
                 func.debug_info.locs(),
-            ));
+            )
+            .with_code(ErrorCode::MissingReturn));
         }
         if let Some(stat) = func.code.last() {
             match &stat.kind {
@@ -1078,7 +1483,8 @@ pub fn typecheck_function(
                             .into_iter()
                             .chain(stat.debug_info.location.into_iter())
                             .collect(),
-                    ))
+                    )
+                    .with_code(ErrorCode::MissingReturn))
                 }
             }
         }
@@ -1094,8 +1500,13 @@ pub fn typecheck_function(
         public: func.public,
         captures: BTreeSet::new(),
         generics: func.generics.clone(),
+        generic_bounds: func.generic_bounds.clone(),
+        interface: func.interface,
+        receiver: func.receiver,
         unique_id: func.unique_id,
         properties: func.properties,
+        docs: func.docs,
+        attributes: func.attributes,
         debug_info: DebugInfo::from(func.debug_info),
     })
 }
@@ -1201,6 +1612,9 @@ fn typecheck_statement<'a>(
     let debug_info = statement.debug_info;
 
     macro_rules! error {
+        ($code:ident: $text:expr $(,$args:expr)* $(,)?) => {
+            return Err(CompileError::new("Typecheck error", format!($text, $(Color::red($args),)*), debug_info.locs()).with_code(ErrorCode::$code))
+        };
         ($text:expr $(,$args:expr)* $(,)?) => {
             return Err(CompileError::new("Typecheck error", format!($text, $(Color::red($args),)*), debug_info.locs()))
         };
@@ -1212,7 +1626,7 @@ fn typecheck_statement<'a>(
                 Ok((TypeCheckedStatementKind::ReturnVoid(), vec![]))
             } else {
                 error!(
-                    "Tried to return without type in function that returns {}",
+                    MismatchedReturn: "Tried to return without type in function that returns {}",
                     &func.ret_type.print(type_tree)
                 );
             }
@@ -1238,7 +1652,7 @@ fn typecheck_statement<'a>(
                 Ok((TypeCheckedStatementKind::Return(expr), vec![]))
             } else {
                 error!(
-                    "return statement has wrong type:\nencountered {}\ninstead of  {}",
+                    MismatchedReturn: "return statement has wrong type:\nencountered {}\ninstead of  {}",
                     tipe.print(type_tree),
                     ret_type.print(type_tree),
                 );
@@ -1262,7 +1676,30 @@ fn typecheck_statement<'a>(
             )?;
             let tipe = expr.get_type();
             if !matches!(tipe, Type::Void | Type::Every) {
-                error!("Statement discards {} value", tipe.print(type_tree));
+                // the value of a func's last statement was likely meant to be returned
+                let last = func
+                    .code
+                    .last()
+                    .map_or(false, |last| std::ptr::eq(last, statement));
+                let returnable = last
+                    && func.ret_type.rep(type_tree)?.assignable(
+                        &tipe.rep(type_tree)?,
+                        type_tree,
+                        HashSet::new(),
+                    );
+                let fix = debug_info
+                    .location
+                    .filter(|_| returnable)
+                    .map(|loc| Fix::insert("return the value", loc, "return "));
+                return Err(CompileError::new(
+                    "Typecheck error",
+                    format!(
+                        "Statement discards {} value",
+                        Color::red(tipe.print(type_tree))
+                    ),
+                    debug_info.locs(),
+                )
+                .with_fix(fix));
             }
             Ok((TypeCheckedStatementKind::Expression(expr), vec![]))
         }
@@ -1282,6 +1719,7 @@ fn typecheck_statement<'a>(
 
             let types = match expr.get_type() {
                 Type::Tuple(vec) if assigned.len() > 1 => vec.clone(),
+                Type::String if assigned.len() > 1 => Type::string_fields(),
                 x => vec![x.clone()],
             };
 
@@ -1307,7 +1745,8 @@ fn typecheck_statement<'a>(
                             .chain(statement.debug_info.location.iter())
                             .cloned()
                             .collect(),
-                    ))?
+                    )
+                    .with_code(ErrorCode::GlobalShadowing))?
                 }
 
                 if let Some(_) = global_vars.get(&id) {
@@ -1317,7 +1756,8 @@ fn typecheck_statement<'a>(
                             false => format!("Tried to let-assign global variable {}", name),
                         },
                         assigned.debug_info.locs(),
-                    ))?
+                    )
+                    .with_code(ErrorCode::GlobalShadowing))?
                 }
 
                 if tipe == Type::Void {
@@ -1342,13 +1782,15 @@ fn typecheck_statement<'a>(
                                             .unwrap_or("Did not find mismatch".to_string())
                                     ),
                                     assigned.debug_info.locs(),
-                                ))?
+                                )
+                                .with_code(ErrorCode::MismatchedAssignment))?
                             }
                         }
                         None => Err(CompileError::new_type_error(
                             format!("assignment to undeclared variable {}", name),
                             assigned.debug_info.locs(),
-                        ))?,
+                        )
+                        .with_code(ErrorCode::UndeclaredAssignment))?,
                     }
                 }
             }
@@ -1379,7 +1821,7 @@ fn typecheck_statement<'a>(
                     Ok((TypeCheckedStatementKind::SetLocals(assigned, expr), vec![]))
                 } else {
                     error!(
-                        "mismatched types in assignment statement {}",
+                        MismatchedAssignment: "mismatched types in assignment statement {}",
                         var_type
                             .mismatch_string(&tipe, type_tree)
                             .unwrap_or("Did not find mismatch".to_string())
@@ -1390,7 +1832,7 @@ fn typecheck_statement<'a>(
                     Ok((TypeCheckedStatementKind::AssignGlobal(*id, expr), vec![]))
                 } else {
                     error!(
-                        "mismatched types in assignment statement {}",
+                        MismatchedAssignment: "mismatched types in assignment statement {}",
                         var_type
                             .mismatch_string(&tipe, type_tree)
                             .unwrap_or("Did not find mismatch".to_string())
@@ -1398,7 +1840,7 @@ fn typecheck_statement<'a>(
                 }
             } else {
                 error!(
-                    "assignment to undeclared variable {}",
+                    UndeclaredAssignment: "assignment to undeclared variable {}",
                     string_table.name_from_id(*id)
                 );
             }
@@ -1509,6 +1951,9 @@ fn typecheck_expr(
     let loc = debug_info.location;
 
     macro_rules! error {
+        ($code:ident: $text:expr $(,$args:expr)* $(,)?) => {
+            return Err(CompileError::new("Typecheck error", format!($text, $(Color::red($args),)*), debug_info.locs()).with_code(ErrorCode::$code))
+        };
         ($text:expr $(,$args:expr)* $(,)?) => {
             return Err(CompileError::new("Typecheck error", format!($text, $(Color::red($args),)*), debug_info.locs()))
         };
@@ -1559,7 +2004,21 @@ fn typecheck_expr(
                     closures,
                     scopes,
                 )?;
-                typecheck_binary_op(*op, tc_sub1, tc_sub2, type_tree, loc)
+                let is_string = |expr: &TypeCheckedExpr| {
+                    expr.get_type().rep(type_tree).ok() == Some(Type::String)
+                };
+                match is_string(&tc_sub1) || is_string(&tc_sub2) {
+                    true => typecheck_string_op(
+                        *op,
+                        tc_sub1,
+                        tc_sub2,
+                        func_table,
+                        type_tree,
+                        string_table,
+                        debug_info,
+                    ),
+                    false => typecheck_binary_op(*op, tc_sub1, tc_sub2, type_tree, loc),
+                }
             }
             ExprKind::Trinary(op, sub1, sub2, sub3) => {
                 let tc_sub1 = typecheck_expr(
@@ -1707,7 +2166,8 @@ fn typecheck_expr(
                     }
 
                     let tipe = template_type.make_specific(spec)?;
-                    Ok(TypeCheckedExprKind::FuncRef(*id, tipe))
+                    let spec = spec.iter().map(|t| t.resolve_generics(type_tree)).collect();
+                    Ok(TypeCheckedExprKind::FuncRef(*id, spec, tipe))
                 } else if let Some(tipe) = type_table.get(id) {
                     if !spec.is_empty() {
                         return Err(CompileError::new(
@@ -1739,7 +2199,7 @@ fn typecheck_expr(
                     Ok(TypeCheckedExprKind::GlobalVariableRef(*id, tipe))
                 } else {
                     error!(
-                        "reference to unrecognized identifier {}",
+                        UnknownIdentifier: "reference to unrecognized identifier {}",
                         string_table.name_from_id(*id)
                     );
                 }
@@ -1761,6 +2221,7 @@ fn typecheck_expr(
 
                 let tipe = match tuple_expr.get_type().rep(type_tree)? {
                     Type::Tuple(tup) => tup,
+                    Type::String => Type::string_fields(),
                     wrong => error!("{} isn't a tuple", wrong.print(type_tree)),
                 };
 
@@ -1788,34 +2249,7 @@ fn typecheck_expr(
                     closures,
                     scopes,
                 )?;
-                let tipe = expr.get_type().rep(type_tree)?;
-
-                let fields = match &tipe {
-                    Type::Struct(fields) => fields,
-                    _ => {
-                        error!(
-                            "can't lookup .{} for non-struct {}",
-                            name,
-                            tipe.print(type_tree)
-                        )
-                    }
-                };
-
-                let slot = fields.into_iter().position(|field| &field.name == name);
-
-                let slot = match slot {
-                    Some(slot) => slot,
-                    None => {
-                        error!("There's no field .{} in {}", name, tipe.print(type_tree))
-                    }
-                };
-
-                Ok(TypeCheckedExprKind::TupleRef(
-                    Box::new(expr),
-                    slot,
-                    fields.len(),
-                    fields[slot].tipe.clone(),
-                ))
+                typecheck_dot_ref(expr, name, type_tree, debug_info)
             }
             ExprKind::StructMod(struc, name, item) => {
                 let struc = typecheck_expr(
@@ -1850,7 +2284,7 @@ fn typecheck_expr(
                     Type::Struct(fields) => fields,
                     _ => {
                         error!(
-                            "can't edit .{} for non-struct {}",
+                            UnknownField: "can't edit .{} for non-struct {}",
                             name,
                             struc_type.print(type_tree)
                         )
@@ -1863,7 +2297,7 @@ fn typecheck_expr(
                     Some(slot) => slot,
                     None => {
                         error!(
-                            "There's no field .{} in {}",
+                            UnknownField: "There's no field .{} in {}",
                             name,
                             struc_type.print(type_tree)
                         )
@@ -1898,22 +2332,58 @@ fn typecheck_expr(
                 Constant::Option(o) => TypeCheckedExprKind::Const(o.value(), o.type_of()),
             }),
             ExprKind::FunctionCall(expr, args) => {
-                let expr = typecheck_expr(
-                    expr,
-                    type_table,
-                    global_vars,
-                    func_table,
-                    func,
-                    type_tree,
-                    string_table,
-                    undefinable_ids,
-                    closures,
-                    scopes,
-                )?;
+                let mut receiver = None;
+                let expr = match &expr.kind {
+                    ExprKind::DotRef(object, name) => {
+                        let object = typecheck_expr(
+                            object,
+                            type_table,
+                            global_vars,
+                            func_table,
+                            func,
+                            type_tree,
+                            string_table,
+                            undefinable_ids,
+                            closures,
+                            scopes,
+                        )?;
+                        let method = typecheck_method_ref(
+                            &object,
+                            name,
+                            func,
+                            func_table,
+                            type_tree,
+                            string_table,
+                        )?;
+                        match method {
+                            Some(method) => {
+                                receiver = Some(object);
+                                TypeCheckedExpr::new(method, expr.debug_info)
+                            }
+                            None => TypeCheckedExpr::new(
+                                typecheck_dot_ref(object, name, type_tree, expr.debug_info)?,
+                                expr.debug_info,
+                            ),
+                        }
+                    }
+                    _ => typecheck_expr(
+                        expr,
+                        type_table,
+                        global_vars,
+                        func_table,
+                        func,
+                        type_tree,
+                        string_table,
+                        undefinable_ids,
+                        closures,
+                        scopes,
+                    )?,
+                };
 
-                let args = args
-                    .iter()
-                    .map(|arg| {
+                let args = receiver
+                    .into_iter()
+                    .map(Ok)
+                    .chain(args.iter().map(|arg| {
                         typecheck_expr(
                             arg,
                             type_table,
@@ -1926,7 +2396,7 @@ fn typecheck_expr(
                             closures,
                             scopes,
                         )
-                    })
+                    }))
                     .collect::<Result<_, _>>()?;
 
                 Ok(build_function_call(expr, args, string_table, type_tree)?)
@@ -1948,6 +2418,7 @@ fn typecheck_expr(
 
                 // a closures inherits its parent's generics
                 closure_func.generics = func.generics.clone();
+                closure_func.generic_bounds = func.generic_bounds.clone();
 
                 let id = closure_func.id;
                 let tipe = closure_func.tipe.clone();
@@ -1988,6 +2459,11 @@ fn typecheck_expr(
                                 TypeCheckedExprKind::IfLet(id, ..) => {
                                     local.insert(*id);
                                 }
+                                TypeCheckedExprKind::Match(_, arms, ..) => {
+                                    for arm in arms {
+                                        local.extend(arm.pattern.bindings());
+                                    }
+                                }
                                 TypeCheckedExprKind::LocalVariableRef(id, _tipe) => {
                                     if !local.contains(&id) {
                                         captures.insert(*id);
@@ -2029,7 +2505,10 @@ fn typecheck_expr(
                 )?;
 
                 let fill = TypeCheckedExpr::new(
-                    TypeCheckedExprKind::Const(tipe.default_value(type_tree), tipe.clone()),
+                    TypeCheckedExprKind::Const(
+                        tipe.default_value(type_tree),
+                        tipe.resolve_generics(type_tree),
+                    ),
                     debug_info,
                 );
 
@@ -2124,6 +2603,24 @@ fn typecheck_expr(
                 }
                 Ok(TypeCheckedExprKind::Tuple(tc_fields, Type::Tuple(types)))
             }
+            ExprKind::Variant(tipe, name, args) => {
+                let mut fields = vec![];
+                for arg in args {
+                    fields.push(typecheck_expr(
+                        arg,
+                        type_table,
+                        global_vars,
+                        func_table,
+                        func,
+                        type_tree,
+                        string_table,
+                        undefinable_ids,
+                        closures,
+                        scopes,
+                    )?);
+                }
+                typecheck_variant(tipe, name, fields, type_tree, debug_info)
+            }
             ExprKind::StructInitializer(fieldvec) => {
                 let mut fields = vec![];
                 let mut types = vec![];
@@ -2166,6 +2663,46 @@ fn typecheck_expr(
                     type_tree,
                 )?)
             }
+            ExprKind::Slice(string, start, end) => {
+                let mut typecheck = |expr: &Expr| {
+                    typecheck_expr(
+                        expr,
+                        type_table,
+                        global_vars,
+                        func_table,
+                        func,
+                        type_tree,
+                        string_table,
+                        undefinable_ids,
+                        closures,
+                        scopes,
+                    )
+                };
+                let args = vec![typecheck(string)?, typecheck(start)?, typecheck(end)?];
+                let expected = [Type::String, Type::Uint, Type::Uint];
+                for (arg, tipe) in args.iter().zip(&expected) {
+                    if !tipe.assignable(&arg.get_type(), type_tree, HashSet::new()) {
+                        error!(
+                            "slicing needs a string and uint indices, found {}",
+                            arg.get_type().print(type_tree)
+                        );
+                    }
+                }
+                let builtin_ref = TypeCheckedExpr::builtin_ref(
+                    "builtin_stringSlice",
+                    expected.iter().collect(),
+                    &Type::String,
+                    func_table,
+                    string_table,
+                    debug_info,
+                )?;
+                Ok(build_function_call(
+                    builtin_ref,
+                    args,
+                    string_table,
+                    type_tree,
+                )?)
+            }
             ExprKind::ArrayOrMapRef(unchecked_store, unchecked_key) => {
                 let store = typecheck_expr(
                     &*unchecked_store,
@@ -2209,6 +2746,7 @@ fn typecheck_expr(
                             Box::new(store),
                             Box::new(key),
                             size,
+                            false,
                             *inner_type,
                         ))
                     }
@@ -2334,6 +2872,7 @@ fn typecheck_expr(
                             Box::new(key),
                             Box::new(item),
                             size,
+                            false,
                             Type::FixedArray(inner_type, size),
                         ))
                     }
@@ -2471,6 +3010,11 @@ fn typecheck_expr(
                     }
                     args.push(arg);
                 }
+                let outputs = match ret_type.rep(type_tree)? {
+                    Type::Void => 0,
+                    _ => 1,
+                };
+                check_asm_stack(insns, args.len(), outputs, debug_info)?;
                 Ok(TypeCheckedExprKind::Asm(
                     ret_type.clone(),
                     insns.to_vec(),
@@ -2482,7 +3026,7 @@ fn typecheck_expr(
                     Type::Option(_) | Type::Any => {}
                     ret => {
                         error!(
-                            "Can only use {} operator in functions that can return option, found {}",
+                            TryWithoutOption: "Can only use {} operator in functions that can return option, found {}",
                             "?",
                             ret.print(type_tree),
                         );
@@ -2677,6 +3221,89 @@ fn typecheck_expr(
                     if_let_type,
                 ))
             }
+            ExprKind::Match(scrutinee, arms) => {
+                let scrutinee = typecheck_expr(
+                    scrutinee,
+                    type_table,
+                    global_vars,
+                    func_table,
+                    func,
+                    type_tree,
+                    string_table,
+                    undefinable_ids,
+                    closures,
+                    scopes,
+                )?;
+                let tipe = scrutinee.get_type();
+
+                let mut checked_arms = vec![];
+                let mut match_type = None;
+                for arm in arms {
+                    let mut bindings = HashMap::new();
+                    let pattern = typecheck_pattern(
+                        &arm.pattern,
+                        &tipe,
+                        &mut bindings,
+                        type_tree,
+                        string_table,
+                        arm.debug_info,
+                    )?;
+                    let mut inner_type_table = type_table.clone();
+                    inner_type_table.extend(bindings);
+                    let body = typecheck_expr(
+                        &arm.body,
+                        &inner_type_table,
+                        global_vars,
+                        func_table,
+                        func,
+                        type_tree,
+                        string_table,
+                        undefinable_ids,
+                        closures,
+                        scopes,
+                    )?;
+
+                    let body_type = body.get_type();
+                    match_type = match match_type {
+                        None => Some(body_type),
+                        Some(prior) => {
+                            if body_type.assignable(&prior, type_tree, HashSet::new()) {
+                                Some(prior)
+                            } else if prior.assignable(&body_type, type_tree, HashSet::new()) {
+                                Some(body_type)
+                            } else {
+                                error!(
+                                    "Mismatch of match arm types found: {} and {}",
+                                    prior.print(type_tree),
+                                    body_type.print(type_tree)
+                                );
+                            }
+                        }
+                    };
+                    checked_arms.push(MatchArm::new(pattern, body, arm.debug_info));
+                }
+
+                let patterns: Vec<_> = arms.iter().map(|arm| &arm.pattern).collect();
+                let unreachable = match matching::check_patterns(&patterns, &tipe, type_tree) {
+                    Ok(unreachable) => unreachable,
+                    Err(missing) => error!(
+                        "Match on {} isn't exhaustive: {} is not covered",
+                        tipe.print(type_tree),
+                        missing
+                    ),
+                };
+                let unreachable = unreachable
+                    .into_iter()
+                    .flat_map(|index| arms[index].debug_info.location)
+                    .collect();
+
+                Ok(TypeCheckedExprKind::Match(
+                    Box::new(scrutinee),
+                    checked_arms,
+                    unreachable,
+                    match_type.unwrap_or(Type::Void),
+                ))
+            }
             ExprKind::Loop(block, tipe) => {
                 let expr = typecheck_codeblock(
                     block,
@@ -2730,7 +3357,7 @@ fn typecheck_expr(
 /// Attempts to apply the `UnaryOp` op, to `TypeCheckedExpr` sub_expr, producing a `TypeCheckedExpr`
 /// if successful, and a `CompileError` otherwise.  The argument loc is used to record the location of
 /// op for use in formatting the `CompileError`.
-fn typecheck_unary_op(
+pub fn typecheck_unary_op(
     op: UnaryOp,
     sub_expr: TypeCheckedExpr,
     loc: Option<Location>,
@@ -2845,6 +3472,12 @@ fn typecheck_unary_op(
                 Box::new(sub_expr),
                 Type::Uint,
             )),
+            Type::String => Ok(TypeCheckedExprKind::TupleRef(
+                Box::new(sub_expr),
+                0,
+                2,
+                Type::Uint,
+            )),
             other => Err(CompileError::new_type_error(
                 format!(
                     "invalid operand type {} for len",
@@ -2956,7 +3589,7 @@ fn typecheck_unary_op(
 ///
 /// This produces a `TypeCheckedExpr` if successful, and a `CompileError` otherwise.  The argument loc
 /// is used to record the location of op for use in formatting the `CompileError`.
-fn typecheck_binary_op(
+pub fn typecheck_binary_op(
     mut op: BinaryOp,
     mut tcs1: TypeCheckedExpr,
     mut tcs2: TypeCheckedExpr,
@@ -3333,6 +3966,70 @@ fn typecheck_trinary_op(
 /// The arguments val1, and t1 represent the value of the left subexpression, and its type, and val2
 /// and t2 represent the value and type of the right subexpression, loc is used to format the
 /// `CompileError` in case of failure.
+/// Typechecks a binary op on strings, which compiles into calls to the builtins in string.mini.
+/// Strings are concatenated with `+`, and compared byte by byte.
+fn typecheck_string_op(
+    op: BinaryOp,
+    left: TypeCheckedExpr,
+    right: TypeCheckedExpr,
+    func_table: &HashMap<usize, Type>,
+    type_tree: &TypeTree,
+    string_table: &StringTable,
+    debug_info: DebugInfo,
+) -> Result<TypeCheckedExprKind, CompileError> {
+    let (left_type, right_type) = (left.get_type(), right.get_type());
+    let operands_ok = Type::String.assignable(&left_type, type_tree, HashSet::new())
+        && Type::String.assignable(&right_type, type_tree, HashSet::new());
+    let builtin = match op {
+        BinaryOp::Plus => "builtin_stringConcat",
+        BinaryOp::Equal
+        | BinaryOp::NotEqual
+        | BinaryOp::LessThan
+        | BinaryOp::GreaterThan
+        | BinaryOp::LessEq
+        | BinaryOp::GreaterEq => "builtin_stringCompare",
+        _ => "",
+    };
+    if !operands_ok || builtin.is_empty() {
+        return Err(CompileError::new_type_error(
+            format!(
+                "invalid argument types to {:?}: {} and {}",
+                op,
+                Color::red(left_type.print(type_tree)),
+                Color::red(right_type.print(type_tree))
+            ),
+            debug_info.locs(),
+        ));
+    }
+    let ret = match op {
+        BinaryOp::Plus => Type::String,
+        _ => Type::Int,
+    };
+    let builtin_ref = TypeCheckedExpr::builtin_ref(
+        builtin,
+        vec![&Type::String, &Type::String],
+        &ret,
+        func_table,
+        string_table,
+        debug_info,
+    )?;
+    let call = build_function_call(builtin_ref, vec![left, right], string_table, type_tree)?;
+    match op {
+        BinaryOp::Plus => Ok(call),
+        _ => {
+            // the comparison returns -1, 0, or 1, which is compared against 0 in its place
+            let zero = TypeCheckedExprKind::Const(Value::Int(Uint256::zero()), Type::Int);
+            typecheck_binary_op(
+                op,
+                TypeCheckedExpr::new(call, debug_info),
+                TypeCheckedExpr::new(zero, debug_info),
+                type_tree,
+                debug_info.location,
+            )
+        }
+    }
+}
+
 fn typecheck_binary_op_const(
     op: BinaryOp,
     val1: Uint256,
@@ -3379,14 +4076,16 @@ fn typecheck_binary_op_const(
                 None => Err(CompileError::new_type_error(
                     "divide by constant zero".to_string(),
                     loc.into_iter().collect(),
-                )),
+                )
+                .with_code(ErrorCode::DivideByZero)),
             },
             (Type::Int, Type::Int) => match val1.sdiv(&val2) {
                 Some(v) => Ok(TypeCheckedExprKind::Const(Value::Int(v), t1)),
                 None => Err(CompileError::new_type_error(
                     "divide by constant zero".to_string(),
                     loc.into_iter().collect(),
-                )),
+                )
+                .with_code(ErrorCode::DivideByZero)),
             },
             _ => Err(CompileError::new_type_error(
                 format!(
@@ -3403,14 +4102,16 @@ fn typecheck_binary_op_const(
                 None => Err(CompileError::new_type_error(
                     "divide by constant zero".to_string(),
                     loc.into_iter().collect(),
-                )),
+                )
+                .with_code(ErrorCode::DivideByZero)),
             },
             (Type::Int, Type::Int) => match val1.smodulo(&val2) {
                 Some(v) => Ok(TypeCheckedExprKind::Const(Value::Int(v), t1)),
                 None => Err(CompileError::new_type_error(
                     "divide by constant zero".to_string(),
                     loc.into_iter().collect(),
-                )),
+                )
+                .with_code(ErrorCode::DivideByZero)),
             },
             _ => Err(CompileError::new_type_error(
                 format!(
@@ -3599,7 +4300,7 @@ fn typecheck_binary_op_const(
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TypeCheckedCodeBlock {
     pub body: Vec<TypeCheckedStatement>,
     pub ret_expr: Option<Box<TypeCheckedExpr>>,
@@ -3721,6 +4422,208 @@ fn typecheck_codeblock(
     })
 }
 
+/// Builds the variant `name` of enum `tipe` from the values of its fields, as a tuple of the
+/// variant's tag and a tuple of those values.
+fn typecheck_variant(
+    tipe: &Type,
+    name: &str,
+    fields: Vec<TypeCheckedExpr>,
+    type_tree: &TypeTree,
+    debug_info: DebugInfo,
+) -> Result<TypeCheckedExprKind, CompileError> {
+    macro_rules! error {
+        ($text:expr $(,$args:expr)* $(,)?) => {
+            return Err(CompileError::new("Typecheck error", format!($text, $(Color::red($args),)*), debug_info.locs()))
+        };
+    }
+
+    let variants = match tipe.rep(type_tree)? {
+        Type::Enum(variants) => variants,
+        _ => error!("{} isn't an enum", tipe.print(type_tree)),
+    };
+    let (tag, variant) = match EnumVariant::lookup(&variants, name) {
+        Some(found) => found,
+        None => error!("There's no variant {} in {}", name, tipe.print(type_tree)),
+    };
+    if fields.len() != variant.fields.len() {
+        error!(
+            "Variant {} has {} fields but was given {}",
+            name,
+            variant.fields.len(),
+            fields.len()
+        );
+    }
+    for (index, (field, field_type)) in fields.iter().zip(&variant.fields).enumerate() {
+        if !field_type.assignable(&field.get_type(), type_tree, HashSet::new()) {
+            error!(
+                "Field {} of variant {} has the wrong type {}",
+                index,
+                name,
+                field_type
+                    .mismatch_string(&field.get_type(), type_tree)
+                    .unwrap_or("Did not find mismatch".to_string())
+            );
+        }
+    }
+
+    let tag = TypeCheckedExprKind::Const(Value::Int(Uint256::from_usize(tag)), Type::Uint);
+    let payload = TypeCheckedExprKind::Tuple(fields, Type::Tuple(variant.fields.clone()));
+    Ok(TypeCheckedExprKind::Tuple(
+        vec![
+            TypeCheckedExpr::new(tag, debug_info),
+            TypeCheckedExpr::new(payload, debug_info),
+        ],
+        tipe.clone(),
+    ))
+}
+
+/// Looks up field `name` of the struct produced by expr.
+fn typecheck_dot_ref(
+    expr: TypeCheckedExpr,
+    name: &str,
+    type_tree: &TypeTree,
+    debug_info: DebugInfo,
+) -> Result<TypeCheckedExprKind, CompileError> {
+    let tipe = expr.get_type().rep(type_tree)?;
+
+    let fields = match &tipe {
+        Type::Struct(fields) => fields,
+        _ => {
+            return Err(CompileError::new(
+                "Typecheck error",
+                format!(
+                    "can't lookup .{} for non-struct {}",
+                    Color::red(name),
+                    Color::red(tipe.print(type_tree))
+                ),
+                debug_info.locs(),
+            )
+            .with_code(ErrorCode::UnknownField))
+        }
+    };
+
+    let slot = match fields.iter().position(|field| field.name == name) {
+        Some(slot) => slot,
+        None => {
+            return Err(CompileError::new(
+                "Typecheck error",
+                format!(
+                    "There's no field .{} in {}",
+                    Color::red(name),
+                    Color::red(tipe.print(type_tree))
+                ),
+                debug_info.locs(),
+            )
+            .with_code(ErrorCode::UnknownField))
+        }
+    };
+
+    Ok(TypeCheckedExprKind::TupleRef(
+        Box::new(expr),
+        slot,
+        fields.len(),
+        fields[slot].tipe.clone(),
+    ))
+}
+
+/// Resolves a call of method `name` on receiver to the func implementing it. Generic receivers
+/// are resolved using the interface bounding them once their func is specialized.
+///
+/// Returns `None` when receiver has no such method, in which case the call is of a struct field.
+fn typecheck_method_ref(
+    receiver: &TypeCheckedExpr,
+    name: &str,
+    func: &Func,
+    func_table: &TypeTable,
+    type_tree: &TypeTree,
+    string_table: &StringTable,
+) -> Result<Option<TypeCheckedExprKind>, CompileError> {
+    let receiver_type = receiver.get_type();
+    let locs = receiver.debug_info.locs();
+
+    if let Type::Struct(fields) = receiver_type.rep(type_tree)? {
+        if fields.iter().any(|field| field.name == name) {
+            return Ok(None);
+        }
+    }
+
+    if let Type::Generic(index) = receiver_type {
+        let interface = match func.generic_bounds.get(index).cloned().flatten() {
+            Some(interface) => interface,
+            None => {
+                let generic = func.generics.get(index).cloned().unwrap_or_default();
+                return Err(CompileError::new_type_error(
+                    format!(
+                        "Generic {} needs an interface bound to call method {}",
+                        Color::red(string_table.name_from_id(generic)),
+                        Color::red(name)
+                    ),
+                    locs,
+                ));
+            }
+        };
+        let interface = string_table.name_from_id(interface);
+        let key = interface_method_key(interface, name);
+        let signature = string_table
+            .get_if_exists(&key)
+            .and_then(|id| func_table.get(&id));
+        return match signature {
+            Some(Type::Func(prop, args, ret)) => {
+                let mut args = args.clone();
+                args.insert(0, receiver_type.clone());
+                let tipe = Type::Func(*prop, args, ret.clone());
+                Ok(Some(TypeCheckedExprKind::MethodRef(
+                    receiver_type,
+                    name.to_string(),
+                    tipe,
+                )))
+            }
+            _ => Err(CompileError::new_type_error(
+                format!(
+                    "Interface {} has no method {}",
+                    Color::red(interface),
+                    Color::red(name)
+                ),
+                locs,
+            )),
+        };
+    }
+
+    // impl methods are the only funcs with paths for names
+    let suffix = format!("::{}", name);
+    let mut methods = vec![];
+    for (id, tipe) in func_table {
+        let method = string_table.name_from_id(*id);
+        if !method.ends_with(&suffix) {
+            continue;
+        }
+        if let Type::Func(_, args, _) = tipe {
+            let takes = args.first().cloned().unwrap_or(Type::Void);
+            if takes.assignable(&receiver_type, type_tree, HashSet::new())
+                && receiver_type.assignable(&takes, type_tree, HashSet::new())
+            {
+                methods.push((*id, tipe.clone()));
+            }
+        }
+    }
+
+    match methods.len() {
+        0 => Ok(None),
+        1 => {
+            let (id, tipe) = methods.pop().unwrap();
+            Ok(Some(TypeCheckedExprKind::FuncRef(id, vec![], tipe)))
+        }
+        _ => Err(CompileError::new_type_error(
+            format!(
+                "Method {} of {} is ambiguous",
+                Color::red(name),
+                Color::red(receiver_type.print(type_tree))
+            ),
+            locs,
+        )),
+    }
+}
+
 fn build_function_call(
     func_expr: TypeCheckedExpr,
     args: Vec<TypeCheckedExpr>,
@@ -3728,9 +4631,10 @@ fn build_function_call(
     type_tree: &TypeTree,
 ) -> Result<TypeCheckedExprKind, CompileError> {
     let func_name = match &func_expr.kind {
-        TypeCheckedExprKind::FuncRef(id, _) => {
+        TypeCheckedExprKind::FuncRef(id, ..) => {
             format!(" {}", string_table.name_from_id(*id))
         }
+        TypeCheckedExprKind::MethodRef(_, name, _) => format!(" {}", name),
         _ => String::new(),
     };
 
@@ -3758,7 +4662,8 @@ fn build_function_call(
                 Color::red(func_name)
             ),
             func_expr.debug_info.locs(),
-        ));
+        )
+        .with_code(ErrorCode::MismatchedArgs));
     }
 
     for (index, (arg, tipe)) in args.iter().zip(arg_types).enumerate() {
@@ -3774,7 +4679,8 @@ fn build_function_call(
                     Color::red(tipe.print(type_tree)),
                 ),
                 arg.debug_info.locs(),
-            ));
+            )
+            .with_code(ErrorCode::MismatchedArgs));
         }
     }
 
@@ -3785,3 +4691,57 @@ fn build_function_call(
         prop,
     ))
 }
+
+/// Checks that an asm block only consumes the args it's given, and that it leaves a result on the
+/// stack when it has one. Values left beneath the result, like the second half of an ecadd, are
+/// allowed, since a following block without values of its own can pick them up. Blocks that jump
+/// or halt aren't checked past that point.
+fn check_asm_stack(
+    insns: &[Instruction],
+    nargs: usize,
+    outputs: usize,
+    debug_info: DebugInfo,
+) -> Result<(), CompileError> {
+    let mut depth = nargs;
+    for insn in insns {
+        let (pops, pushes) = match &insn.opcode {
+            Opcode::AVMOpcode(avm) => match avm.stack_effect() {
+                Some(effect) => effect,
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        if insn.immediate.is_some() {
+            depth += 1;
+        }
+        if depth < pops {
+            return Err(CompileError::new(
+                "Typecheck error",
+                format!(
+                    "Asm instruction {} takes {} values, but only {} are on the stack",
+                    Color::red(insn.opcode.to_name()),
+                    Color::red(pops),
+                    Color::red(depth),
+                ),
+                insn.debug_info
+                    .location
+                    .or(debug_info.location)
+                    .into_iter()
+                    .collect(),
+            ));
+        }
+        depth = depth - pops + pushes;
+    }
+    let inputs = nargs + insns.iter().filter(|insn| insn.immediate.is_some()).count();
+    if inputs > 0 && depth < outputs {
+        return Err(CompileError::new(
+            "Typecheck error",
+            format!(
+                "Asm block doesn't leave its result on the stack, as it consumes all {} of its values",
+                Color::red(inputs),
+            ),
+            debug_info.locs(),
+        ));
+    }
+    Ok(())
+}
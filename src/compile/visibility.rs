@@ -0,0 +1,75 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Keeps the methods a module doesn't mark `public` private to it, as imports do for its funcs.
+
+use super::ast::{impl_method_key, ImplMethod};
+use super::typecheck::{AbstractSyntaxTree, TypeCheckedExprKind, TypeCheckedNode};
+use super::{path_display, CompileError, ErrorCode, TypeCheckedModule};
+use crate::console::Color;
+use crate::link::Import;
+use crate::stringtable::StringTable;
+use std::collections::HashMap;
+
+/// Reports each call a module makes to a method another module implements without marking it
+/// `public`. Methods implementing an interface are as visible as the interface.
+pub(super) fn check_method_visibility(
+    modules: &mut [TypeCheckedModule],
+    methods: &[ImplMethod],
+) -> Vec<CompileError> {
+    let private: HashMap<_, _> = methods
+        .iter()
+        .filter(|method| !method.public)
+        .map(|method| {
+            let key = impl_method_key(&method.func.path, &method.func.name);
+            (key, &method.func)
+        })
+        .collect();
+
+    let mut errors = vec![];
+    for module in modules {
+        for func in module.checked_funcs.values_mut() {
+            find_private_calls(
+                func.child_nodes(),
+                &module.string_table,
+                &module.path,
+                &private,
+                &mut errors,
+            );
+        }
+    }
+    errors
+}
+
+fn find_private_calls(
+    mut nodes: Vec<TypeCheckedNode>,
+    string_table: &StringTable,
+    path: &Vec<String>,
+    private: &HashMap<String, &Import>,
+    errors: &mut Vec<CompileError>,
+) {
+    for node in &mut nodes {
+        if let TypeCheckedNode::Expression(expr) = node {
+            if let TypeCheckedExprKind::FuncRef(id, ..) = &expr.kind {
+                let method = private.get(string_table.name_from_id(*id));
+                if let Some(method) = method.filter(|method| &method.path != path) {
+                    errors.push(
+                        CompileError::new(
+                            "Visibility error",
+                            format!(
+                                "Method {} is private to {}, so {} can't call it",
+                                Color::red(&method.name),
+                                Color::red(path_display(&method.path)),
+                                Color::red(path_display(path)),
+                            ),
+                            expr.debug_info.locs(),
+                        )
+                        .with_code(ErrorCode::PrivateItem),
+                    );
+                }
+            }
+        }
+        find_private_calls(node.child_nodes(), string_table, path, private, errors);
+    }
+}
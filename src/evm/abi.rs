@@ -2,7 +2,7 @@
  * Copyright 2020, Offchain Labs, Inc. All rights reserved.
  */
 
-use crate::evm::test_contract_path;
+use crate::evm::{test_contract_path, Tx};
 use crate::mavm::Value;
 use crate::run::runtime_env::remap_l1_sender_address;
 use crate::run::{ArbosReceipt, Machine};
@@ -95,15 +95,11 @@ impl AbiForContract {
             self.code_bytes.clone()
         };
 
-        let request_id = machine.runtime_env.insert_tx_message(
-            Uint256::from_u64(1025),
-            Uint256::from_usize(1_000_000_000),
-            None,
-            Uint256::zero(),
-            payment,
-            &augmented_code,
-            false,
-        );
+        let request_id = Tx::deploy()
+            .calldata(augmented_code)
+            .value(payment)
+            .gas(Uint256::from_usize(1_000_000_000))
+            .send(&mut machine.runtime_env, Uint256::from_u64(1025));
 
         if let Some(delta_blocks) = advance_time {
             machine
@@ -173,15 +169,11 @@ impl AbiForContract {
         let this_function = self.contract.function(func_name)?;
         let calldata = this_function.encode_input(args).unwrap();
 
-        machine.runtime_env.insert_tx_message_from_contract(
-            sender_addr,
-            Uint256::from_usize(10_000_000),
-            None,
-            self.address.clone(),
-            payment,
-            &calldata,
-            false,
-        );
+        Tx::call(self.address.clone())
+            .calldata(calldata)
+            .value(payment)
+            .gas(Uint256::from_usize(10_000_000))
+            .send_from_contract(&mut machine.runtime_env, sender_addr);
 
         let num_logs_before = machine.runtime_env.get_all_receipt_logs().len();
         let num_sends_before = machine.runtime_env.get_all_sends().len();
@@ -212,15 +204,11 @@ impl AbiForContract {
         let this_function = self.contract.function(func_name)?;
         let calldata = this_function.encode_input(args).unwrap();
 
-        machine.runtime_env.insert_gas_estimation_message(
-            Uint256::from_usize(10_000_000),
-            None,
-            self.address.clone(),
-            payment,
-            &calldata,
-            aggregator,
-            wallet,
-        );
+        Tx::call(self.address.clone())
+            .calldata(calldata)
+            .value(payment)
+            .gas(Uint256::from_usize(10_000_000))
+            .estimate_gas(&mut machine.runtime_env, aggregator, wallet);
 
         let num_logs_before = machine.runtime_env.get_all_receipt_logs().len();
         let num_sends_before = machine.runtime_env.get_all_sends().len();
@@ -299,15 +287,10 @@ impl AbiForContract {
         let this_function = self.contract.function(func_name)?;
         let calldata = this_function.encode_input(args).unwrap();
 
-        machine.runtime_env.insert_tx_message_from_contract(
-            sender_addr,
-            Uint256::from_usize(100_000_000),
-            None,
-            self.address.clone(),
-            payment,
-            &calldata,
-            false,
-        );
+        Tx::call(self.address.clone())
+            .calldata(calldata)
+            .value(payment)
+            .send_from_contract(&mut machine.runtime_env, sender_addr);
 
         let num_logs_before = machine.runtime_env.get_all_receipt_logs().len();
         let num_sends_before = machine.runtime_env.get_all_sends().len();
@@ -337,15 +320,11 @@ impl AbiForContract {
         let this_function = self.contract.function(func_name)?;
         let calldata = this_function.encode_input(args).unwrap();
 
-        machine.runtime_env.insert_tx_message(
-            sender_addr,
-            Uint256::from_usize(100_000_000),
-            None,
-            self.address.clone(),
-            payment,
-            &calldata,
-            true,
-        );
+        Tx::call(self.address.clone())
+            .calldata(calldata)
+            .value(payment)
+            .with_deposit()
+            .send(&mut machine.runtime_env, sender_addr);
 
         let num_logs_before = machine.runtime_env.get_all_receipt_logs().len();
         let num_sends_before = machine.runtime_env.get_all_sends().len();
@@ -375,16 +354,10 @@ impl AbiForContract {
         let this_function = self.contract.function(func_name)?;
         let calldata = this_function.encode_input(args).unwrap();
 
-        let (tx_contents, _tx_id_bytes) =
-            machine.runtime_env.make_compressed_and_signed_l2_message(
-                None,
-                Uint256::from_usize(100_000_000),
-                self.address.clone(),
-                payment,
-                &calldata,
-                wallet,
-                false,
-            );
+        let (tx_contents, _tx_hash) = Tx::call(self.address.clone())
+            .calldata(calldata)
+            .value(payment)
+            .sign(&mut machine.runtime_env, wallet);
         machine
             .runtime_env
             .insert_l2_message(sender_addr, &tx_contents);
@@ -416,20 +389,10 @@ impl AbiForContract {
         let this_function = self.contract.function(func_name)?;
         let calldata = this_function.encode_input(args).unwrap();
 
-        let tx_id_bytes = machine
-            .runtime_env
-            ._append_compressed_and_signed_tx_message_to_batch(
-                batch,
-                Uint256::from_usize(100_000_000),
-                None,
-                self.address.clone(),
-                payment,
-                calldata,
-                &wallet,
-                false,
-            );
-
-        Ok((Uint256::from_bytes(&tx_id_bytes)))
+        Ok(Tx::call(self.address.clone())
+            .calldata(calldata)
+            .value(payment)
+            .add_to_batch(&mut machine.runtime_env, batch, wallet))
     }
 
     pub fn _add_function_call_to_compressed_batch(
@@ -445,20 +408,13 @@ impl AbiForContract {
         let this_function = self.contract.function(func_name)?;
         let calldata = this_function.encode_input(args).unwrap();
 
-        let tx_id_bytes = machine
-            .runtime_env
-            ._append_compressed_and_signed_tx_message_to_batch(
-                batch,
-                maybe_max_gas.unwrap_or(Uint256::from_usize(100_000_000)),
-                None,
-                self.address.clone(),
-                payment,
-                calldata,
-                &wallet,
-                false,
-            );
-
-        Ok((Uint256::from_bytes(&tx_id_bytes)))
+        let mut tx = Tx::call(self.address.clone())
+            .calldata(calldata)
+            .value(payment);
+        if let Some(max_gas) = maybe_max_gas {
+            tx = tx.gas(max_gas);
+        }
+        Ok(tx.add_to_batch(&mut machine.runtime_env, batch, wallet))
     }
 
     pub fn short_signature_for_function(&self, func_name: &str) -> Result<[u8; 4], ethabi::Error> {
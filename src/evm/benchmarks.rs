@@ -3,11 +3,15 @@
 */
 
 use crate::evm::abi::deploy_add;
-use crate::run::load_from_file;
+use crate::evm::live_code::ArbosTest;
+use crate::evm::preinstalled_contracts::{_ArbOwner, write_fee_history, ArbGasInfo};
+use crate::run::{load_from_file, TestAccounts};
 use crate::uint256::Uint256;
+use std::collections::BTreeMap;
 use std::path::Path;
 
-pub fn make_benchmarks() -> Result<(), ethabi::Error> {
+/// Runs each benchmark, logging it to the benchmarks folder, and returns the gas each used.
+pub fn make_benchmarks() -> Result<BTreeMap<String, u64>, ethabi::Error> {
     let benchmarks: Vec<(
         fn(u64, &Path) -> Result<u64, ethabi::Error>,
         u64,
@@ -29,15 +33,23 @@ pub fn make_benchmarks() -> Result<(), ethabi::Error> {
             "500 signed batched null txs",
             "nulltx_batch_500",
         ),
+        (
+            benchmark_congestion,
+            100,
+            "100 blocks of congestion",
+            "congestion_100",
+        ),
     ];
 
-    for (bm_func, iterations, description, filename) in benchmarks {
-        let filename = format!("benchmarks/{}.aoslog", filename);
+    let mut results = BTreeMap::new();
+    for (bm_func, iterations, description, name) in benchmarks {
+        let filename = format!("benchmarks/{}.aoslog", name);
         let gas_used = bm_func(iterations, Path::new(&filename))?;
         println!("ArbGas for {}: {}", description, gas_used);
         eprintln!("ArbGas for {}: {}", description, gas_used);
+        results.insert(name.to_string(), gas_used);
     }
-    Ok(())
+    Ok(results)
 }
 
 pub fn benchmark_boot(_iterations: u64, log_to: &Path) -> Result<u64, ethabi::Error> {
@@ -125,3 +137,50 @@ pub fn benchmark_add_batched(iterations: u64, log_to: &Path) -> Result<u64, etha
         .unwrap();
     Ok(machine.get_total_gas_usage().to_u64().unwrap())
 }
+
+/// Fills blocks past the speed limit with fees enabled, writing the prices ArbGasInfo reports
+/// after each block to a csv next to the log.
+pub fn benchmark_congestion(iterations: u64, log_to: &Path) -> Result<u64, ethabi::Error> {
+    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
+    machine.start_at_zero(false);
+
+    let accounts = TestAccounts::new(&mut machine.runtime_env);
+    let alice = accounts.alice();
+    let arbowner = _ArbOwner::_new(&alice.wallet, false);
+    let arbgasinfo = ArbGasInfo::new(&alice.wallet, false);
+    let arbtest = ArbosTest::new(false);
+
+    machine.runtime_env.fund(alice, Uint256::_from_eth(1000));
+    machine.run(None);
+    arbowner._set_fees_enabled(&mut machine, true, true)?;
+
+    let params = arbgasinfo.gas_accounting_params(&mut machine)?;
+    println!(
+        "speed limit {}, pool max {}, tx gas limit {}",
+        params.speed_limit_per_second, params.gas_pool_max, params.max_tx_gas_limit
+    );
+
+    let mut history = vec![];
+    for _ in 0..iterations {
+        for _ in 0..8 {
+            arbtest._burn_arb_gas(
+                &mut machine,
+                alice.address.clone(),
+                Uint256::from_u64(2_000_000),
+            )?;
+        }
+        machine
+            .runtime_env
+            ._advance_time(Uint256::one(), None, false);
+        history.push(arbgasinfo.sample(&mut machine)?);
+    }
+
+    write_fee_history(&history, &log_to.with_extension("csv"))
+        .map_err(|e| ethabi::Error::from(e.to_string()))?;
+    machine
+        .runtime_env
+        .recorder
+        .to_file(log_to, machine.get_total_gas_usage().to_u64().unwrap())
+        .unwrap();
+    Ok(machine.get_total_gas_usage().to_u64().unwrap())
+}
@@ -0,0 +1,327 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides a development chain that picks up edits to ArbOS without losing its state.
+
+use crate::compile::{CompileError, CompileStruct};
+use crate::console::Color;
+use crate::evm::abi::ArbSys;
+use crate::evm::preinstalled_contracts::{_ArbOwner, _try_upgrade};
+use crate::gen_code::gen_upgrade_code;
+use crate::link::LinkedProgram;
+use crate::run::{
+    runtime_env::remap_l1_sender_address, BootCache, Machine, RetentionPolicy, RuntimeEnvironment,
+};
+use crate::uint256::Uint256;
+use crate::upload::CodeUploader;
+use crate::GenUpgrade;
+use ethers_signers::{Signer, Wallet};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The generated file that moves globals from the running version of ArbOS to the next.
+const BRIDGE_FILE: &str = "bridge_arbos_versions.mini";
+/// The stand-in bridge used when building a version of ArbOS that nothing upgrades to.
+const DUMMY_BRIDGE_FILE: &str = "dummy_version_bridge.mini";
+/// The module with the hand-written parts of the bridge.
+const BRIDGE_IMPL: &str = "customize_arbos_bridge_versions";
+/// Lists the globals whose values the hand-written parts of the bridge provide.
+const BRIDGE_CONFIG_FILE: &str = "upgrade.toml";
+
+/// A chain running ArbOS built from source, which can be upgraded in place as the source changes.
+pub struct DevNode {
+    folder: PathBuf,
+    consts_file: Option<String>,
+    machine: Machine,
+    /// The program the chain is currently running, serialized as a mexe
+    running: String,
+    wallet: Wallet,
+    /// The code hash of the last upgrade, which the next one must build on
+    upgrade_hash: Option<Uint256>,
+}
+
+impl DevNode {
+    /// Compiles the ArbOS in folder and starts a chain running it, owned by the node's wallet.
+    /// The chain keeps the logs and sends that retention allows.
+    pub fn new(
+        folder: &Path,
+        consts_file: Option<String>,
+        retention: RetentionPolicy,
+    ) -> Result<Self, CompileError> {
+        let program = compile_arbos(folder, &consts_file, None)?;
+        let running = serialize(&program)?;
+
+        let mut env = RuntimeEnvironment::default();
+        env.set_retention_policy(retention)
+            .map_err(|err| dev_node_error(format!("Could not open spill file: {}", err)))?;
+        let mut machine = Machine::new(program, env);
+        match BootCache::new(folder) {
+            Some(cache) => {
+                cache.boot(&mut machine, running.as_bytes());
+            }
+            None => machine.start_at_zero(false),
+        }
+
+        let wallet = machine.runtime_env.new_wallet();
+        let owner = Uint256::from_bytes(wallet.address().as_bytes());
+        _ArbOwner::_new(&wallet, false)
+            ._add_chain_owner(&mut machine, remap_l1_sender_address(owner), true, false)
+            .map_err(|err| dev_node_error(format!("Could not become chain owner: {}", err)))?;
+
+        Ok(DevNode {
+            folder: folder.to_path_buf(),
+            consts_file,
+            machine,
+            running,
+            wallet,
+            upgrade_hash: None,
+        })
+    }
+
+    /// Recompiles ArbOS and upgrades the chain to the result. The chain keeps running the version
+    /// it had if compilation or the upgrade fails.
+    pub fn reload(&mut self) -> Result<(), CompileError> {
+        // the bridge is generated from the globals of the versions on both sides of it
+        let base = compile_arbos(&self.folder, &self.consts_file, None)?;
+        let dir = std::env::temp_dir();
+        let from = dir.join(format!("mini-dev-node-{}-running.mexe", std::process::id()));
+        let to = dir.join(format!("mini-dev-node-{}-base.mexe", std::process::id()));
+        write_file(&from, &self.running)?;
+        write_file(&to, &serialize(&base)?)?;
+
+        let bridge = self.folder.join(BRIDGE_FILE);
+        drop(fs::remove_file(&bridge));
+        let config = self.folder.join(BRIDGE_CONFIG_FILE);
+        let generated = gen_upgrade_code(GenUpgrade {
+            from: from.clone(),
+            to: to.clone(),
+            out_file: bridge,
+            impl_file: BRIDGE_IMPL.to_string(),
+            config_file: match config.exists() {
+                true => Some(config.to_string_lossy().to_string()),
+                false => None,
+            },
+        });
+        drop(fs::remove_file(&from));
+        drop(fs::remove_file(&to));
+        generated.map_err(|err| dev_node_error(format!("Could not generate bridge: {}", err)))?;
+
+        let upgrade = compile_arbos(&self.folder, &self.consts_file, Some(BRIDGE_FILE))?;
+        let running = serialize(&upgrade)?;
+        let uploader = CodeUploader::_new_from_program(&upgrade);
+        let arbowner = _ArbOwner::_new(&self.wallet, false);
+        match _try_upgrade(
+            &arbowner,
+            &mut self.machine,
+            uploader,
+            self.upgrade_hash.clone(),
+        ) {
+            Ok(Some(hash)) => {
+                self.upgrade_hash = Some(hash);
+                self.running = running;
+                Ok(())
+            }
+            Ok(None) => Err(dev_node_error("The chain rejected the upgrade".to_string())),
+            Err(err) => Err(dev_node_error(format!("Could not upgrade: {}", err))),
+        }
+    }
+
+    /// Asks the chain which version of ArbOS it's running.
+    pub fn arbos_version(&mut self) -> Option<Uint256> {
+        ArbSys::new(&self.wallet, false)
+            .arbos_version(&mut self.machine)
+            .ok()
+    }
+}
+
+/// Runs a dev node on the ArbOS in folder, upgrading it each time a source file changes.
+/// Runs until the process is killed.
+pub fn run_dev_node(
+    folder: &Path,
+    consts_file: Option<String>,
+    interval: Duration,
+    retention: RetentionPolicy,
+) -> Result<(), CompileError> {
+    let mut watcher = SourceWatcher::new(folder, consts_file.as_deref().map(Path::new));
+    let mut node = DevNode::new(folder, consts_file, retention)?;
+    println!(
+        "Dev node running ArbOS version {}, watching {}",
+        describe_version(node.arbos_version()),
+        Color::grey(folder.display()),
+    );
+
+    loop {
+        thread::sleep(interval);
+        let changed = watcher.changed();
+        if changed.is_empty() {
+            continue;
+        }
+        for path in &changed {
+            println!("Changed {}", Color::grey(path.display()));
+        }
+
+        let start = Instant::now();
+        match node.reload() {
+            Ok(()) => {
+                let elapsed = Instant::now() - start;
+                println!(
+                    "Upgraded to ArbOS version {} in {}.{:0>3} seconds, state preserved",
+                    describe_version(node.arbos_version()),
+                    elapsed.as_secs(),
+                    elapsed.subsec_millis(),
+                );
+            }
+            Err(err) => {
+                err.print(&BTreeMap::new(), false);
+                println!("Still running the previous version");
+            }
+        }
+    }
+}
+
+/// Notices when the source files in a folder are created, modified, or removed.
+pub struct SourceWatcher {
+    folder: PathBuf,
+    consts_file: Option<PathBuf>,
+    seen: BTreeMap<PathBuf, SystemTime>,
+}
+
+impl SourceWatcher {
+    pub fn new(folder: &Path, consts_file: Option<&Path>) -> Self {
+        let mut watcher = SourceWatcher {
+            folder: folder.to_path_buf(),
+            consts_file: consts_file.map(Path::to_path_buf),
+            seen: BTreeMap::new(),
+        };
+        watcher.seen = watcher.scan();
+        watcher
+    }
+
+    /// Gets the files that changed since the last call.
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        let now = self.scan();
+        let mut changed: Vec<_> = now
+            .iter()
+            .filter(|(path, time)| self.seen.get(*path) != Some(time))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(
+            self.seen
+                .keys()
+                .filter(|path| !now.contains_key(*path))
+                .cloned(),
+        );
+        changed.sort();
+        self.seen = now;
+        changed
+    }
+
+    /// Gets the modification time of each watched file. The bridge is generated and so isn't
+    /// watched.
+    fn scan(&self) -> BTreeMap<PathBuf, SystemTime> {
+        let mut files = BTreeMap::new();
+        let entries = fs::read_dir(&self.folder).into_iter().flatten().flatten();
+        let sources = entries.map(|entry| entry.path()).filter(|path| {
+            path.extension() == Some(OsStr::new("mini"))
+                && path.file_name() != Some(OsStr::new(BRIDGE_FILE))
+        });
+        for path in sources.chain(self.consts_file.clone()) {
+            if let Ok(time) = fs::metadata(&path).and_then(|meta| meta.modified()) {
+                files.insert(path, time);
+            }
+        }
+        files
+    }
+}
+
+/// Compiles the ArbOS in folder, with the bridge already present or the dummy bridge in its place.
+fn compile_arbos(
+    folder: &Path,
+    consts_file: &Option<String>,
+    bridge: Option<&str>,
+) -> Result<LinkedProgram, CompileError> {
+    if bridge.is_none() {
+        fs::copy(folder.join(DUMMY_BRIDGE_FILE), folder.join(BRIDGE_FILE)).map_err(|_| {
+            dev_node_error(format!(
+                "Could not copy {} to {}",
+                Color::red(DUMMY_BRIDGE_FILE),
+                Color::red(BRIDGE_FILE)
+            ))
+        })?;
+    }
+    let compile = CompileStruct {
+        input: vec![folder.to_string_lossy().to_string()],
+        consts_file: consts_file.clone(),
+        must_use_global_consts: true,
+        ..Default::default()
+    };
+    match compile.invoke() {
+        Ok((program, error_system)) => {
+            error_system.print();
+            Ok(program)
+        }
+        Err(error_system) => {
+            error_system.print();
+            Err(dev_node_error(String::from(
+                "Errors were encountered during compilation",
+            )))
+        }
+    }
+}
+
+fn serialize(program: &LinkedProgram) -> Result<String, CompileError> {
+    serde_json::to_string(program)
+        .map_err(|err| dev_node_error(format!("Could not serialize program: {}", err)))
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), CompileError> {
+    fs::write(path, contents)
+        .map_err(|_| dev_node_error(format!("Could not write {}", Color::red(path.display()))))
+}
+
+fn describe_version(version: Option<Uint256>) -> String {
+    match version {
+        Some(version) => version.to_string(),
+        None => String::from("unknown"),
+    }
+}
+
+fn dev_node_error(description: String) -> CompileError {
+    CompileError::new(String::from("Dev node error"), description, vec![])
+}
+
+#[test]
+fn test_source_watcher() {
+    let folder = std::env::temp_dir().join(format!("mini-watch-{}", std::process::id()));
+    drop(fs::remove_dir_all(&folder));
+    fs::create_dir_all(&folder).unwrap();
+    let source = folder.join("main.mini");
+    fs::write(&source, "func main() {}").unwrap();
+    fs::write(folder.join(BRIDGE_FILE), "").unwrap();
+    fs::write(folder.join("notes.txt"), "").unwrap();
+
+    let mut watcher = SourceWatcher::new(&folder, None);
+    assert!(watcher.changed().is_empty());
+
+    // generated and non-source files don't count
+    fs::write(folder.join(BRIDGE_FILE), "// regenerated").unwrap();
+    fs::write(folder.join("notes.txt"), "todo").unwrap();
+    assert!(watcher.changed().is_empty());
+
+    thread::sleep(Duration::from_millis(20));
+    fs::write(&source, "func main() { let _ = 1; }").unwrap();
+    assert_eq!(watcher.changed(), vec![source.clone()]);
+    assert!(watcher.changed().is_empty());
+
+    let added = folder.join("extra.mini");
+    fs::write(&added, "").unwrap();
+    fs::remove_file(&source).unwrap();
+    assert_eq!(watcher.changed(), vec![added, source]);
+
+    fs::remove_dir_all(&folder).unwrap();
+}
@@ -0,0 +1,224 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Checks that the toolchain works end to end, taking a tiny program through compiling, linking,
+//! and running, then booting ArbOS and having it process a deposit. Each step reports what went
+//! wrong if it fails, so that a toolchain that doesn't work can be told apart from a program that
+//! doesn't.
+
+use crate::compile::{CompileStruct, ErrorSystem};
+use crate::console::Color;
+use crate::evm::preinstalled_contracts::_ArbInfo;
+use crate::link::LinkedProgram;
+use crate::mavm::Value;
+use crate::run::{boot, run, Machine, RuntimeEnvironment};
+use crate::uint256::Uint256;
+use std::any::Any;
+use std::fmt;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+/// The program compiled, which logs a value it computes.
+const PROGRAM: &str = "write func main() {\n    asm(6 * 7) { log };\n}\n";
+
+/// How a step of the checkup went.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    /// The step worked, with a note on what it did
+    Passed(String),
+    /// The step didn't work, with what went wrong
+    Failed(String),
+    /// The step wasn't tried, as a step it builds on failed
+    Skipped,
+}
+
+/// The steps of a checkup and how each went, in the order they were taken.
+#[derive(Clone, Debug, Default)]
+pub struct DoctorReport {
+    pub steps: Vec<(&'static str, Outcome)>,
+}
+
+impl DoctorReport {
+    pub fn passed(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|(_, outcome)| matches!(outcome, Outcome::Passed(_)))
+    }
+
+    /// Takes a step on what an earlier step produced, skipping it if that step failed.
+    fn step<I, T, F>(&mut self, name: &'static str, input: Option<I>, step: F) -> Option<T>
+    where
+        F: FnOnce(I) -> Result<(T, String), String>,
+    {
+        let input = match input {
+            Some(input) => input,
+            None => {
+                self.steps.push((name, Outcome::Skipped));
+                return None;
+            }
+        };
+        // the steps run code that panics on what it can't handle, which is a diagnosis too
+        let result = panic::catch_unwind(AssertUnwindSafe(|| step(input)))
+            .unwrap_or_else(|panic| Err(format!("panicked: {}", panic_message(&*panic))));
+        match result {
+            Ok((value, note)) => {
+                self.steps.push((name, Outcome::Passed(note)));
+                Some(value)
+            }
+            Err(diagnosis) => {
+                self.steps.push((name, Outcome::Failed(diagnosis)));
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, outcome) in &self.steps {
+            match outcome {
+                Outcome::Passed(note) => writeln!(
+                    f,
+                    "{} {:<8} {}",
+                    Color::mint("pass"),
+                    name,
+                    Color::grey(note)
+                )?,
+                Outcome::Failed(diagnosis) => {
+                    writeln!(f, "{} {:<8} {}", Color::red("fail"), name, diagnosis)?
+                }
+                Outcome::Skipped => writeln!(f, "{} {}", Color::grey("skip"), name)?,
+            }
+        }
+        match self.passed() {
+            true => writeln!(f, "Everything works"),
+            false => writeln!(f, "Fix the first failure above, then run the checkup again"),
+        }
+    }
+}
+
+/// Takes a tiny program through the toolchain, then boots the ArbOS mexe at arbos and deposits
+/// into it, compiling with the constants in consts_file.
+pub fn run_doctor(arbos: &Path, consts_file: &Path) -> DoctorReport {
+    let mut report = DoctorReport::default();
+    let folder = std::env::temp_dir().join(format!("mini-doctor-{}", std::process::id()));
+    let source = folder.join("doctor.mini");
+    let compile = CompileStruct {
+        input: vec![source.display().to_string()],
+        consts_file: Some(consts_file.display().to_string()),
+        no_cache: true,
+        ..CompileStruct::default()
+    };
+
+    let compiled = report.step("compile", Some(()), |()| {
+        if !consts_file.exists() {
+            return Err(format!(
+                "there's no constants file at {}; run from the root of the repo or pass \
+                 --consts-file",
+                consts_file.display()
+            ));
+        }
+        fs::create_dir_all(&folder)
+            .and_then(|_| fs::write(&source, PROGRAM))
+            .map_err(|e| format!("couldn't write {}: {}", source.display(), e))?;
+        compile.invoke_module().map_err(describe_errors)?;
+        Ok(((), format!("compiled {}", source.display())))
+    });
+    let program = report.step("link", compiled, |()| {
+        let (program, _) = compile.invoke().map_err(describe_errors)?;
+        let note = format!("linked {} instructions", program.code.len());
+        Ok((program, note))
+    });
+    drop(fs::remove_dir_all(&folder));
+    report.step("run", program, |program| {
+        let mut machine = Machine::new(program, RuntimeEnvironment::default());
+        let logs = run(&mut machine, vec![], false, None)
+            .map_err(|(error, _)| format!("the program stopped with {:?}", error))?;
+        match logs == vec![Value::from(42)] {
+            true => Ok(((), String::from("the program logged 42"))),
+            false => Err(format!("the program logged {:?} rather than 42", logs)),
+        }
+    });
+
+    let machine = report.step("boot", Some(()), |()| {
+        let mexe = fs::read_to_string(arbos).map_err(|e| {
+            format!(
+                "couldn't read {}: {}; build it with `make arbos`",
+                arbos.display(),
+                e
+            )
+        })?;
+        let program = LinkedProgram::from_json(&mexe).map_err(|e| {
+            format!(
+                "{} isn't a mexe this build can load, so it may be stale: {}",
+                arbos.display(),
+                e
+            )
+        })?;
+        let mut machine = Machine::new(program, RuntimeEnvironment::default());
+        boot(&mut machine, arbos);
+        match machine.state.is_running() {
+            true => Ok((machine, format!("booted {}", arbos.display()))),
+            false => Err(format!("ArbOS stopped while booting: {:?}", machine.state)),
+        }
+    });
+    report.step("deposit", machine, |mut machine| {
+        let payee = Uint256::from_u64(0xd0c);
+        let amount = Uint256::_from_eth(1);
+        machine.runtime_env.insert_eth_deposit_message(
+            payee.clone(),
+            payee.clone(),
+            amount.clone(),
+            false,
+        );
+        let _ = machine.run(None);
+        let balance = _ArbInfo::_new(false)
+            ._get_balance(&mut machine, &payee)
+            .map_err(|e| format!("couldn't read the balance deposited to: {}", e))?;
+        match balance == amount {
+            true => Ok(((), format!("deposited 1 ETH to {}", payee))),
+            false => Err(format!(
+                "a deposit of 1 ETH left a balance of {} wei",
+                balance
+            )),
+        }
+    });
+    report
+}
+
+fn describe_errors(error_system: ErrorSystem) -> String {
+    let errors: Vec<String> = error_system
+        .errors
+        .iter()
+        .map(|error| format!("{}: {}", error.title, error.description))
+        .collect();
+    match errors.is_empty() {
+        true => String::from("denied by a lint"),
+        false => errors.join("; "),
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => String::from("with no message"),
+    }
+}
+
+#[test]
+fn test_doctor() {
+    let consts = Path::new("arb_os/constants.json");
+    let report = run_doctor(Path::new("arb_os/arbos.mexe"), consts);
+    assert!(report.passed(), "{}", report);
+    assert_eq!(report.steps.len(), 5);
+
+    // a missing mexe fails the boot, so the deposit isn't tried, while the steps before go on
+    let report = run_doctor(Path::new("arb_os/missing.mexe"), consts);
+    assert!(!report.passed());
+    assert!(matches!(report.steps[2].1, Outcome::Passed(_)));
+    assert!(matches!(report.steps[3].1, Outcome::Failed(_)));
+    assert_eq!(report.steps[4].1, Outcome::Skipped);
+}
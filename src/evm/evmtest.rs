@@ -3,7 +3,7 @@
  */
 
 use crate::evm::live_code::ArbosTest;
-use crate::run::{load_from_file_and_env, Machine, RuntimeEnvironment};
+use crate::run::{boot, load_from_file_and_env, Machine, RuntimeEnvironment};
 use crate::uint256::Uint256;
 use std::collections::HashMap;
 use std::io;
@@ -192,8 +192,9 @@ fn storage_from_jval(jval: serde_json::Value) -> HashMap<Uint256, Uint256> {
 
 fn start_test(blocknum: Uint256, timestamp: Uint256) -> (Machine, ArbosTest) {
     let rt_env = RuntimeEnvironment::new_with_blocknum_timestamp(blocknum, timestamp, None, None);
-    let mut machine = load_from_file_and_env(Path::new("arb_os/arbos.mexe"), rt_env);
-    machine.start_at_zero(true);
+    let path = Path::new("arb_os/arbos.mexe");
+    let mut machine = load_from_file_and_env(path, rt_env);
+    boot(&mut machine, path);
 
     let arbos_test = ArbosTest::new(false);
 
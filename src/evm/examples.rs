@@ -0,0 +1,274 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the scenarios `examples run` walks through. Each boots ArbOS and drives it only
+//! through the API the rest of the tooling uses, narrating as it goes, so that the examples both
+//! show how that API fits together and fail loudly when it breaks.
+
+use crate::evm::abi::{deploy_add, ArbSys};
+use crate::evm::preinstalled_contracts::_ArbReplayableTx;
+use crate::evm::ArbosHarness;
+use crate::uint256::Uint256;
+
+/// A scenario, run against a freshly booted ArbOS whose test accounts have been funded.
+pub struct Example {
+    pub name: &'static str,
+    pub description: &'static str,
+    run: fn(&mut ArbosHarness, bool) -> Result<(), ethabi::Error>,
+}
+
+pub static EXAMPLES: &[Example] = &[
+    Example {
+        name: "deposit-transfer",
+        description: "deposit eth from L1, then send some of it to another account",
+        run: deposit_and_transfer,
+    },
+    Example {
+        name: "erc20",
+        description: "deploy an ERC-20, then transfer tokens directly and through an allowance",
+        run: deploy_erc20,
+    },
+    Example {
+        name: "withdrawal",
+        description: "withdraw eth to L1 through ArbSys and find the resulting send",
+        run: withdrawal,
+    },
+    Example {
+        name: "retryable",
+        description: "submit a retryable transaction from L1, then redeem it on L2",
+        run: retryable,
+    },
+];
+
+/// Runs the example with the given name, failing if there isn't one.
+pub fn run_example(name: &str, debug: bool) -> Result<(), ethabi::Error> {
+    let example = EXAMPLES
+        .iter()
+        .find(|example| example.name == name)
+        .ok_or_else(|| {
+            let names: Vec<_> = EXAMPLES.iter().map(|example| example.name).collect();
+            ethabi::Error::from(format!(
+                "no example named {}, try one of: {}",
+                name,
+                names.join(", ")
+            ))
+        })?;
+
+    let mut harness = ArbosHarness::new().debug(debug);
+    println!("{}: {}", example.name, example.description);
+    (example.run)(&mut harness, debug)
+}
+
+fn deposit_and_transfer(harness: &mut ArbosHarness, _debug: bool) -> Result<(), ethabi::Error> {
+    let (alice, bob) = (
+        harness.accounts.alice().clone(),
+        harness.accounts.bob().clone(),
+    );
+
+    let deposit = Uint256::_from_eth(5);
+    let before = harness.balance_of(&alice.address)?;
+    harness.machine.runtime_env.fund(&alice, deposit.clone());
+    harness.run();
+    let after = harness.balance_of(&alice.address)?;
+    println!(
+        "alice deposited {} wei, her balance went from {} to {}",
+        deposit, before, after
+    );
+    expect(after == before.add(&deposit), "the deposit wasn't credited")?;
+
+    let amount = Uint256::_from_eth(2);
+    let bob_before = harness.balance_of(&bob.address)?;
+    let receipt = harness.transfer(&alice, &bob.address, amount.clone())?;
+    let bob_after = harness.balance_of(&bob.address)?;
+    println!(
+        "alice sent bob {} wei using {} gas, his balance went from {} to {}",
+        amount,
+        receipt.get_gas_used(),
+        bob_before,
+        bob_after
+    );
+    expect(bob_after == bob_before.add(&amount), "bob wasn't paid")
+}
+
+fn deploy_erc20(harness: &mut ArbosHarness, debug: bool) -> Result<(), ethabi::Error> {
+    let (alice, bob) = (
+        harness.accounts.alice().clone(),
+        harness.accounts.bob().clone(),
+    );
+    let address = |account: &Uint256| ethabi::Token::Address(account.to_h160());
+    let uint = |value: &Uint256| ethabi::Token::Uint(value.to_u256());
+
+    let supply = Uint256::_from_eth(1_000_000);
+    let token = harness.deploy("ExampleToken", &[address(&alice.address), uint(&supply)])?;
+    println!(
+        "deployed the token at {}, minting {} to alice",
+        token, supply
+    );
+
+    let amount = Uint256::_from_eth(250);
+    harness.call(
+        &alice,
+        "ExampleToken",
+        "transfer",
+        &[address(&bob.address), uint(&amount)],
+    )?;
+    println!("alice transferred {} to bob", amount);
+    expect(
+        token_balance(harness, &bob.address, debug)? == amount,
+        "bob didn't receive the tokens",
+    )?;
+
+    // bob spends from alice's balance, up to what she allows
+    let allowance = Uint256::_from_eth(100);
+    harness.call(
+        &alice,
+        "ExampleToken",
+        "approve",
+        &[address(&bob.address), uint(&allowance)],
+    )?;
+    harness.call(
+        &bob,
+        "ExampleToken",
+        "transferFrom",
+        &[
+            address(&alice.address),
+            address(&bob.address),
+            uint(&allowance),
+        ],
+    )?;
+
+    let alice_balance = token_balance(harness, &alice.address, debug)?;
+    let bob_balance = token_balance(harness, &bob.address, debug)?;
+    println!(
+        "alice approved bob for {}, who took it; alice now has {} and bob {}",
+        allowance, alice_balance, bob_balance
+    );
+    expect(
+        bob_balance == amount.add(&allowance) && alice_balance.add(&bob_balance) == supply,
+        "the tokens don't add up",
+    )
+}
+
+fn withdrawal(harness: &mut ArbosHarness, debug: bool) -> Result<(), ethabi::Error> {
+    let alice = harness.accounts.alice().clone();
+    let arbsys = ArbSys::new(&alice.wallet, debug);
+
+    let amount = Uint256::_from_eth(3);
+    let before = harness.balance_of(&alice.address)?;
+    arbsys._withdraw_eth(&mut harness.machine, alice.address.clone(), amount.clone())?;
+    let after = harness.balance_of(&alice.address)?;
+    println!(
+        "alice withdrew {} wei, her balance went from {} to {}",
+        amount, before, after
+    );
+    expect(
+        after.add(&amount) <= before,
+        "the withdrawal wasn't debited",
+    )?;
+
+    // sends are only emitted once the block they're in ends
+    harness.advance_block();
+    let send = harness
+        .machine
+        .runtime_env
+        ._get_last_send()
+        .ok_or_else(|| ethabi::Error::from("no send was emitted"))?;
+    println!("ArbOS emitted a {}-byte send for L1 to execute", send.len());
+    expect(
+        send[0] == 3 && send[1..33] == alice.address.to_bytes_be()[..],
+        "the send isn't alice's withdrawal",
+    )
+}
+
+fn retryable(harness: &mut ArbosHarness, debug: bool) -> Result<(), ethabi::Error> {
+    let alice = harness.accounts.alice().clone();
+    let machine = &mut harness.machine;
+    let add_contract = deploy_add(machine)?;
+    let arb_replayable = _ArbReplayableTx::_new(debug);
+
+    let (_, txid, _) = add_contract._send_retryable_tx(
+        alice.address.clone(),
+        "add",
+        &[
+            ethabi::Token::Uint(Uint256::one().to_u256()),
+            ethabi::Token::Uint(Uint256::one().to_u256()),
+        ],
+        machine,
+        Uint256::zero(),
+        Uint256::zero(),
+        Uint256::zero(),
+        None,
+        None,
+        None,
+        None,
+    )?;
+    harness.run();
+    let machine = &mut harness.machine;
+    let timeout = arb_replayable._get_timeout(machine, txid.clone())?;
+    println!(
+        "alice submitted retryable {}, which times out at {}",
+        txid, timeout
+    );
+    expect(
+        timeout > machine.runtime_env.current_timestamp,
+        "the retryable wasn't created",
+    )?;
+
+    arb_replayable._redeem(machine, txid.clone())?;
+    let receipts = machine.runtime_env.get_all_receipt_logs();
+    let redeemed = receipts
+        .iter()
+        .rev()
+        .find(|receipt| receipt.get_request_id() == txid)
+        .ok_or_else(|| ethabi::Error::from("the redeemed call has no receipt"))?;
+    println!(
+        "redeeming it ran add(1, 1), returning {}",
+        Uint256::from_bytes(&redeemed.get_return_data())
+    );
+    expect(
+        arb_replayable._get_timeout(machine, txid)? == Uint256::zero(),
+        "the retryable outlived its redemption",
+    )
+}
+
+/// Reads an account's balance of the ERC-20 deployed as ExampleToken.
+fn token_balance(
+    harness: &mut ArbosHarness,
+    owner: &Uint256,
+    debug: bool,
+) -> Result<Uint256, ethabi::Error> {
+    let token = harness
+        .contract("ExampleToken")
+        .cloned()
+        .ok_or_else(|| ethabi::Error::from("the token wasn't deployed"))?;
+    let (receipts, _sends) = token.call_function(
+        Uint256::zero(),
+        "balanceOf",
+        &[ethabi::Token::Address(owner.to_h160())],
+        &mut harness.machine,
+        Uint256::zero(),
+        debug,
+    )?;
+    match receipts.last() {
+        Some(receipt) if receipt.succeeded() => Ok(Uint256::from_bytes(&receipt.get_return_data())),
+        _ => Err(ethabi::Error::from("balanceOf reverted")),
+    }
+}
+
+fn expect(condition: bool, failure: &str) -> Result<(), ethabi::Error> {
+    match condition {
+        true => Ok(()),
+        false => Err(ethabi::Error::from(failure)),
+    }
+}
+
+#[test]
+fn test_examples() {
+    for example in EXAMPLES {
+        if let Err(e) = run_example(example.name, false) {
+            panic!("example {} failed: {}", example.name, e);
+        }
+    }
+    assert!(run_example("no-such-example", false).is_err());
+}
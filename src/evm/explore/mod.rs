@@ -0,0 +1,449 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides an interactive browser for the blocks, receipts, logs, accounts, and contract storage
+//! of a simulated chain, driven either by commands read a line at a time or, when built with the
+//! `tui` feature, by the terminal UI of the `tui` module.
+
+use crate::evm::abi::{builtin_contract_path, AbiForContract};
+use crate::evm::evmtest::deserialize_storage;
+use crate::evm::layout::{DecodedStorage, StorageLayout};
+use crate::evm::live_code::ArbosTest;
+use crate::evm::preinstalled_contracts::_ArbInfo;
+use crate::run::runtime_env::{_ArbosBlockSummaryLog, EvmLog, RtEnvRecorder};
+use crate::run::{ArbosReceipt, Machine};
+use crate::uint256::Uint256;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, Write};
+
+#[cfg(feature = "tui")]
+pub use tui::ExplorerTui;
+
+#[cfg(feature = "tui")]
+mod tui;
+
+/// The builtin contracts whose events are decoded without being asked for.
+const BUILTIN_EVENT_SOURCES: [&str; 3] = ["ArbSys", "ArbRetryableTx", "ArbAggregator"];
+
+const HELP: &str = "\
+commands:
+  summary           counts of blocks, transactions, logs, and sends
+  blocks            lists the blocks
+  block <num>       lists the transactions in a block
+  txs               lists every transaction
+  tx <index>        shows a transaction's receipt and logs
+  logs [<address>]  lists the logs, optionally only those a contract emitted
+  sends             lists the messages sent to L1
+  account <address> shows an account's balance and code size
+  storage <address> shows a contract's storage, by variable if its layout was given
+  quit              leaves the explorer";
+
+/// A chain that has finished running, along with what it produced.
+pub struct Explorer {
+    machine: Machine,
+    receipts: Vec<ArbosReceipt>,
+    blocks: Vec<_ArbosBlockSummaryLog>,
+    sends: Vec<Vec<u8>>,
+    /// The contracts whose events can be decoded, by event signature
+    events: BTreeMap<Uint256, ethabi::Event>,
+    /// The storage layouts of contracts, by address
+    layouts: BTreeMap<Uint256, StorageLayout>,
+}
+
+impl Explorer {
+    /// Explores a chain that has already run. Receipts are gathered up front, so that the calls
+    /// made to look up accounts don't show up among them.
+    pub fn new(machine: Machine) -> Self {
+        let mut explorer = Explorer {
+            receipts: machine.runtime_env.get_all_receipt_logs(),
+            blocks: machine.runtime_env._get_all_block_summary_logs(),
+            sends: machine.runtime_env.get_all_sends(),
+            machine,
+            events: BTreeMap::new(),
+            layouts: BTreeMap::new(),
+        };
+        for name in &BUILTIN_EVENT_SOURCES {
+            if let Ok(contract) = AbiForContract::new_from_file(&builtin_contract_path(name)) {
+                explorer.add_abi(&contract);
+            }
+        }
+        explorer
+    }
+
+    /// Replays a test log and explores the resulting chain.
+    pub fn from_testlog(filename: &str) -> io::Result<Self> {
+        let mut machine = RtEnvRecorder::from_file(filename)?.load_machine();
+        machine.start_at_zero(false);
+        let _ = machine.run(None);
+        Ok(Explorer::new(machine))
+    }
+
+    /// Decodes the logs of a contract's events by name.
+    pub fn add_abi(&mut self, contract: &AbiForContract) {
+        for event in contract.contract.events() {
+            let signature = Uint256::from_bytes(event.signature().as_bytes());
+            self.events.insert(signature, event.clone());
+        }
+    }
+
+    /// Shows the storage of the contract at address by the variables of its layout.
+    pub fn add_storage_layout(&mut self, address: Uint256, layout: StorageLayout) {
+        self.layouts.insert(address, layout);
+    }
+
+    /// Runs commands from input until it ends or the user quits, writing what they show to output.
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        self.summary(&mut output)?;
+        writeln!(output, "Enter a command, or help for a list of them")?;
+        for line in input.lines() {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match (words.first().copied(), words.get(1).copied()) {
+                (None, _) => {}
+                (Some("quit"), _) | (Some("q"), _) => return Ok(()),
+                (Some("help"), _) | (Some("h"), _) => writeln!(output, "{}", HELP)?,
+                (Some("summary"), _) => self.summary(&mut output)?,
+                (Some("blocks"), _) => self.list_blocks(&mut output)?,
+                (Some("block"), Some(num)) => match Uint256::from_string(num) {
+                    Some(num) => self.show_block(&mut output, &num)?,
+                    None => writeln!(output, "{} isn't a block number", num)?,
+                },
+                (Some("txs"), _) => self.list_txs(&mut output, None)?,
+                (Some("tx"), Some(index)) => match index.parse::<usize>() {
+                    Ok(index) if index < self.receipts.len() => self.show_tx(&mut output, index)?,
+                    _ => writeln!(output, "There's no transaction {}", index)?,
+                },
+                (Some("logs"), None) => self.list_logs(&mut output, None)?,
+                (Some("logs"), Some(address)) => match parse_address(address) {
+                    Some(address) => self.list_logs(&mut output, Some(address))?,
+                    None => writeln!(output, "{} isn't an address", address)?,
+                },
+                (Some("sends"), _) => self.list_sends(&mut output)?,
+                (Some("account"), Some(address)) => match parse_address(address) {
+                    Some(address) => self.show_account(&mut output, &address)?,
+                    None => writeln!(output, "{} isn't an address", address)?,
+                },
+                (Some("storage"), Some(address)) => match parse_address(address) {
+                    Some(address) => self.show_storage(&mut output, &address)?,
+                    None => writeln!(output, "{} isn't an address", address)?,
+                },
+                (Some(command), _) => {
+                    writeln!(output, "Unrecognized command {}, try help", command)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn summary(&self, output: &mut impl Write) -> io::Result<()> {
+        let logs: usize = self.receipts.iter().map(|r| r._get_evm_logs().len()).sum();
+        writeln!(
+            output,
+            "{} blocks, {} transactions, {} logs, {} sends",
+            self.blocks.len(),
+            self.receipts.len(),
+            logs,
+            self.sends.len()
+        )
+    }
+
+    fn list_blocks(&self, output: &mut impl Write) -> io::Result<()> {
+        for block in &self.blocks {
+            let txs = self
+                .receipts
+                .iter()
+                .filter(|r| r._get_block_number() == block.block_num)
+                .count();
+            writeln!(
+                output,
+                "block {}  timestamp {}  gas limit {}  {} txs",
+                block.block_num, block.timestamp, block.gas_limit, txs
+            )?;
+        }
+        Ok(())
+    }
+
+    fn show_block(&self, output: &mut impl Write, num: &Uint256) -> io::Result<()> {
+        match self.blocks.iter().find(|block| &block.block_num == num) {
+            Some(block) => writeln!(
+                output,
+                "block {}  timestamp {}  gas limit {}",
+                block.block_num, block.timestamp, block.gas_limit
+            )?,
+            None => writeln!(output, "block {} (not yet closed)", num)?,
+        }
+        self.list_txs(output, Some(num))
+    }
+
+    fn list_txs(&self, output: &mut impl Write, block: Option<&Uint256>) -> io::Result<()> {
+        for (index, receipt) in self.receipts.iter().enumerate() {
+            if block.is_none() || block == Some(&receipt._get_block_number()) {
+                writeln!(
+                    output,
+                    "tx {}  block {}  request {}  {}  gas {}",
+                    index,
+                    receipt._get_block_number(),
+                    receipt.get_request_id(),
+                    receipt._get_return_code_text(),
+                    receipt.get_gas_used(),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn show_tx(&self, output: &mut impl Write, index: usize) -> io::Result<()> {
+        let receipt = &self.receipts[index];
+        writeln!(output, "request id   {}", receipt.get_request_id())?;
+        writeln!(output, "block        {}", receipt._get_block_number())?;
+        writeln!(output, "result       {}", receipt._get_return_code_text())?;
+        writeln!(output, "gas used     {}", receipt.get_gas_used())?;
+        writeln!(
+            output,
+            "return data  0x{}",
+            hex::encode(receipt.get_return_data())
+        )?;
+        for log in receipt._get_evm_logs() {
+            self.show_log(output, &log)?;
+        }
+        Ok(())
+    }
+
+    fn list_logs(&self, output: &mut impl Write, address: Option<Uint256>) -> io::Result<()> {
+        for (index, receipt) in self.receipts.iter().enumerate() {
+            for log in receipt._get_evm_logs() {
+                if address.is_none() || address.as_ref() == Some(&log.addr) {
+                    write!(output, "tx {}  ", index)?;
+                    self.show_log(output, &log)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Shows a log, by event name and args if it's from an event of a known contract.
+    fn show_log(&self, output: &mut impl Write, log: &EvmLog) -> io::Result<()> {
+        let decoded = log.vals.first().and_then(|topic| {
+            let event = self.events.get(topic)?;
+            let raw = ethabi::RawLog {
+                topics: log
+                    .vals
+                    .iter()
+                    .map(|topic| ethabi::Hash::from_slice(&topic.to_bytes_be()))
+                    .collect(),
+                data: log.data.clone(),
+            };
+            let params = event.parse_log(raw).ok()?.params;
+            let args: Vec<_> = params
+                .iter()
+                .map(|param| format!("{}: {}", param.name, param.value))
+                .collect();
+            Some(format!("{}({})", event.name, args.join(", ")))
+        });
+        match decoded {
+            Some(event) => writeln!(output, "log from {:?}  {}", log.addr.to_h160(), event),
+            None => {
+                let topics: Vec<_> = log.vals.iter().map(|topic| topic.to_string()).collect();
+                writeln!(
+                    output,
+                    "log from {:?}  topics [{}]  data 0x{}",
+                    log.addr.to_h160(),
+                    topics.join(", "),
+                    hex::encode(&log.data)
+                )
+            }
+        }
+    }
+
+    fn list_sends(&self, output: &mut impl Write) -> io::Result<()> {
+        for (index, send) in self.sends.iter().enumerate() {
+            writeln!(output, "send {}  0x{}", index, hex::encode(send))?;
+        }
+        Ok(())
+    }
+
+    /// Shows an account as of the end of the chain, by asking ArbOS about it.
+    fn show_account(&mut self, output: &mut impl Write, address: &Uint256) -> io::Result<()> {
+        let info = _ArbInfo::_new(false);
+        match info._get_balance(&mut self.machine, address) {
+            Ok(balance) => writeln!(output, "balance    {}", balance)?,
+            Err(e) => writeln!(output, "Could not get balance: {}", e)?,
+        }
+        match info._get_code(&mut self.machine, address) {
+            Ok(code) => writeln!(output, "code size  {}", code.len()),
+            Err(e) => writeln!(output, "Could not get code: {}", e),
+        }
+    }
+
+    /// Shows a contract's storage as of the end of the chain, by variable if its layout is known
+    /// and otherwise by slot.
+    fn show_storage(&mut self, output: &mut impl Write, address: &Uint256) -> io::Result<()> {
+        let arbos_test = ArbosTest::new(false);
+        let storage = match arbos_test._get_marshalled_storage(&mut self.machine, address.clone()) {
+            Ok(marshalled) => deserialize_storage(marshalled),
+            Err(e) => return writeln!(output, "Could not get storage: {}", e),
+        };
+        let decoded = match self.layouts.get(address) {
+            Some(layout) => layout.decode(&storage, &self.mapping_keys()),
+            None => {
+                let unknown = storage.into_iter().filter(|(_, value)| !value.is_zero());
+                DecodedStorage {
+                    vars: vec![],
+                    unknown: unknown.collect(),
+                }
+            }
+        };
+        for (name, value) in &decoded.vars {
+            writeln!(output, "{} = {}", name, value)?;
+        }
+        for (slot, value) in &decoded.unknown {
+            writeln!(output, "slot {}  {}", slot, value)?;
+        }
+        Ok(())
+    }
+
+    /// The keys mappings are searched for entries under: the addresses and topics of every log,
+    /// which include the accounts of indexed event args, and a few small ints for ids.
+    fn mapping_keys(&self) -> Vec<Uint256> {
+        let mut keys: BTreeSet<Uint256> = (0..16).map(Uint256::from_u64).collect();
+        for log in self.receipts.iter().flat_map(ArbosReceipt::_get_evm_logs) {
+            keys.insert(log.addr.clone());
+            keys.extend(log.vals.iter().cloned());
+        }
+        keys.into_iter().collect()
+    }
+}
+
+/// Parses an address given in hex, with or without a leading 0x.
+fn parse_address(s: &str) -> Option<Uint256> {
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    match hex.len() <= 40 {
+        true => Uint256::from_string_hex(hex),
+        false => None,
+    }
+}
+
+#[test]
+fn test_explorer() {
+    use crate::evm::Tx;
+    use crate::run::{load_from_file_and_env, RuntimeEnvironment, TestAccounts};
+    use std::path::Path;
+
+    let mut env = RuntimeEnvironment::default();
+    let accounts = TestAccounts::new(&mut env);
+    let (alice, bob) = (accounts.alice(), accounts.bob());
+    env.fund(alice, Uint256::_from_eth(10));
+    Tx::call(bob.address.clone())
+        .value(Uint256::_from_eth(1))
+        .send(&mut env, alice.address.clone());
+
+    let mut machine = load_from_file_and_env(Path::new("arb_os/arbos.mexe"), env);
+    machine.start_at_zero(false);
+    let _ = machine.run(None);
+
+    let mut explorer = Explorer::new(machine);
+    let commands = format!(
+        "txs\ntx 1\ntx 99\naccount {:?}\naccount {:?}\naccount 0xnope\nbogus\nquit\nsummary\n",
+        alice.address.to_h160(),
+        bob.address.to_h160()
+    );
+    let mut output = vec![];
+    explorer.run(commands.as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    assert!(output.contains("2 transactions"));
+    assert_eq!(output.matches("tx 1  block").count(), 1);
+    assert!(output.contains("result       success"));
+    assert!(output.contains("There's no transaction 99"));
+    assert!(output.contains(&format!("balance    {}", Uint256::_from_eth(1))));
+    assert!(output.contains("0xnope isn't an address"));
+    assert!(output.contains("Unrecognized command bogus"));
+    // nothing after quit runs, so the summary is only shown on entry
+    assert_eq!(output.matches("transactions,").count(), 1);
+}
+
+#[test]
+fn test_explorer_storage() {
+    use crate::evm::ArbosHarness;
+    use ethabi::Token;
+
+    let mut harness = ArbosHarness::new();
+    let (alice, bob) = (
+        harness.accounts.alice().clone(),
+        harness.accounts.bob().clone(),
+    );
+    let supply = Uint256::_from_eth(1000);
+    let (sent, allowed) = (Uint256::_from_eth(250), Uint256::_from_eth(40));
+    let token = harness
+        .deploy(
+            "ExampleToken",
+            &[
+                Token::Address(alice.address.to_h160()),
+                Token::Uint(supply.to_u256()),
+            ],
+        )
+        .unwrap();
+    let to_bob = |amount: &Uint256| {
+        vec![
+            Token::Address(bob.address.to_h160()),
+            Token::Uint(amount.to_u256()),
+        ]
+    };
+    harness
+        .call(&alice, "ExampleToken", "transfer", &to_bob(&sent))
+        .unwrap();
+    harness
+        .call(&alice, "ExampleToken", "approve", &to_bob(&allowed))
+        .unwrap();
+
+    // the layout solc emits for ExampleToken, whose constants take up no storage
+    let layout = r#"{
+        "storage": [
+            {"label": "totalSupply", "offset": 0, "slot": "0", "type": "t_uint256"},
+            {"label": "balances", "offset": 0, "slot": "1",
+             "type": "t_mapping(t_address,t_uint256)"},
+            {"label": "allowances", "offset": 0, "slot": "2",
+             "type": "t_mapping(t_address,t_mapping(t_address,t_uint256))"}
+        ],
+        "types": {
+            "t_address": {"encoding": "inplace", "label": "address", "numberOfBytes": "20"},
+            "t_uint256": {"encoding": "inplace", "label": "uint256", "numberOfBytes": "32"},
+            "t_mapping(t_address,t_uint256)": {
+                "encoding": "mapping", "label": "mapping(address => uint256)",
+                "numberOfBytes": "32", "key": "t_address", "value": "t_uint256"
+            },
+            "t_mapping(t_address,t_mapping(t_address,t_uint256))": {
+                "encoding": "mapping",
+                "label": "mapping(address => mapping(address => uint256))",
+                "numberOfBytes": "32", "key": "t_address",
+                "value": "t_mapping(t_address,t_uint256)"
+            }
+        }
+    }"#;
+    let path = std::env::temp_dir().join("ExampleToken.layout.json");
+    std::fs::write(&path, layout).unwrap();
+
+    let mut explorer = Explorer::new(harness.machine);
+    let commands = format!("storage {:?}\n", token.to_h160());
+    let mut raw = vec![];
+    explorer.run(commands.as_bytes(), &mut raw).unwrap();
+    let raw = String::from_utf8(raw).unwrap();
+    assert_eq!(raw.matches("slot ").count(), 4);
+
+    let layout = StorageLayout::from_file(path.to_str().unwrap()).unwrap();
+    explorer.add_storage_layout(token, layout);
+    let mut output = vec![];
+    explorer.run(commands.as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let (alice, bob) = (alice.address.to_h160(), bob.address.to_h160());
+    assert!(output.contains(&format!("totalSupply = {}", supply)));
+    assert!(output.contains(&format!(
+        "balances[{:?}] = {}",
+        alice,
+        supply.unchecked_sub(&sent)
+    )));
+    assert!(output.contains(&format!("balances[{:?}] = {}", bob, sent)));
+    assert!(output.contains(&format!("allowances[{:?}][{:?}] = {}", alice, bob, allowed)));
+    assert!(!output.contains("slot "));
+}
@@ -0,0 +1,356 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the terminal UI `mini explore` opens when run in a terminal, which browses the same
+//! chain its commands do, listing the blocks, transactions, logs, or sends on one side and showing
+//! whichever is selected in full on the other.
+
+use super::{parse_address, Explorer};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Tabs, Wrap};
+use ratatui::{Frame, Terminal};
+use std::io;
+
+const HELP: &str =
+    "tab/1-5 switch lists  up/down select  pgup/pgdn scroll  / look up an address  q quit";
+
+/// The lists the explorer shows, one to a tab.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Tab {
+    Blocks,
+    Txs,
+    Logs,
+    Sends,
+    Account,
+}
+
+const TABS: [Tab; 5] = [Tab::Blocks, Tab::Txs, Tab::Logs, Tab::Sends, Tab::Account];
+
+impl Tab {
+    fn title(self) -> &'static str {
+        match self {
+            Tab::Blocks => "Blocks",
+            Tab::Txs => "Transactions",
+            Tab::Logs => "Logs",
+            Tab::Sends => "Sends",
+            Tab::Account => "Account",
+        }
+    }
+}
+
+/// An explorer being browsed in the terminal.
+pub struct ExplorerTui {
+    explorer: Explorer,
+    tab: usize,
+    /// The item selected in each tab's list
+    selected: Vec<ListState>,
+    /// How many lines the detail pane is scrolled down by
+    scroll: u16,
+    /// The address being typed, while one is
+    input: Option<String>,
+    /// What was found for the last address looked up, which is kept as looking it up runs ArbOS
+    account: Vec<String>,
+    /// What went wrong with the last key pressed, if anything
+    message: Option<String>,
+    quit: bool,
+}
+
+impl ExplorerTui {
+    pub fn new(explorer: Explorer) -> Self {
+        ExplorerTui {
+            explorer,
+            tab: 0,
+            selected: TABS.iter().map(|_| ListState::default()).collect(),
+            scroll: 0,
+            input: None,
+            account: vec![String::from("Press / and enter an address to look it up")],
+            message: None,
+            quit: false,
+        }
+    }
+
+    /// Takes over the terminal until the user quits, putting it back as it was afterwards, even
+    /// if drawing to it failed.
+    pub fn run(&mut self) -> io::Result<()> {
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+        let result = self.event_loop(&mut terminal);
+
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+        result
+    }
+
+    fn event_loop<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.handle_key(key.code);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyCode) {
+        self.message = None;
+        if let Some(input) = &mut self.input {
+            match key {
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Backspace => drop(input.pop()),
+                KeyCode::Esc => self.input = None,
+                KeyCode::Enter => {
+                    let typed = self.input.take().unwrap_or_default();
+                    self.look_up(&typed);
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Tab | KeyCode::Right => self.switch_to((self.tab + 1) % TABS.len()),
+            KeyCode::BackTab | KeyCode::Left => {
+                self.switch_to((self.tab + TABS.len() - 1) % TABS.len())
+            }
+            KeyCode::Char(c @ '1'..='5') => self.switch_to(c as usize - '1' as usize),
+            KeyCode::Down | KeyCode::Char('j') => self.select(1),
+            KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Char('/') => {
+                self.switch_to(TABS.len() - 1);
+                self.input = Some(String::new());
+            }
+            _ => {}
+        }
+    }
+
+    fn switch_to(&mut self, tab: usize) {
+        self.tab = tab;
+        self.scroll = 0;
+    }
+
+    /// Moves the selection of the current list by offset, keeping it within the list.
+    fn select(&mut self, offset: isize) {
+        let len = self.items().len();
+        if len == 0 {
+            return;
+        }
+        let state = &mut self.selected[self.tab];
+        let index = match state.selected() {
+            Some(index) => (index as isize + offset).max(0).min(len as isize - 1) as usize,
+            None => 0,
+        };
+        state.select(Some(index));
+        self.scroll = 0;
+    }
+
+    /// Shows the account and storage of the address typed, as the explorer's commands would.
+    fn look_up(&mut self, typed: &str) {
+        let address = match parse_address(typed.trim()) {
+            Some(address) => address,
+            None => {
+                self.message = Some(format!("{} isn't an address", typed));
+                return;
+            }
+        };
+        let explorer = &mut self.explorer;
+        let mut lines = vec![format!("account {}", typed.trim())];
+        lines.extend(capture(|output| explorer.show_account(output, &address)));
+        lines.push(String::new());
+        lines.extend(capture(|output| explorer.show_storage(output, &address)));
+        self.account = lines;
+        self.scroll = 0;
+    }
+
+    /// The lines of the current tab's list, one to an item.
+    fn items(&self) -> Vec<String> {
+        let explorer = &self.explorer;
+        match TABS[self.tab] {
+            Tab::Blocks => capture(|output| explorer.list_blocks(output)),
+            Tab::Txs => capture(|output| explorer.list_txs(output, None)),
+            Tab::Logs => capture(|output| explorer.list_logs(output, None)),
+            Tab::Sends => capture(|output| explorer.list_sends(output)),
+            Tab::Account => vec![],
+        }
+    }
+
+    /// What the detail pane shows of the item selected in the current tab.
+    fn detail(&self) -> Vec<String> {
+        let explorer = &self.explorer;
+        let selected = self.selected[self.tab].selected();
+        match (TABS[self.tab], selected) {
+            (Tab::Account, _) => self.account.clone(),
+            (_, None) => vec![String::from("Select an item with up and down")],
+            (Tab::Blocks, Some(index)) => match explorer.blocks.get(index) {
+                Some(block) => capture(|output| explorer.show_block(output, &block.block_num)),
+                None => vec![],
+            },
+            (Tab::Txs, Some(index)) if index < explorer.receipts.len() => {
+                capture(|output| explorer.show_tx(output, index))
+            }
+            (Tab::Logs, Some(index)) => {
+                // a log is shown along with the rest of the transaction emitting it
+                let tx = explorer
+                    .receipts
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(tx, receipt)| vec![tx; receipt._get_evm_logs().len()])
+                    .nth(index);
+                match tx {
+                    Some(tx) => {
+                        let mut lines = vec![format!("tx {}", tx)];
+                        lines.extend(capture(|output| explorer.show_tx(output, tx)));
+                        lines
+                    }
+                    None => vec![],
+                }
+            }
+            (Tab::Sends, Some(index)) => match explorer.sends.get(index) {
+                Some(send) => vec![
+                    format!("send {}  {} bytes", index, send.len()),
+                    format!("0x{}", hex::encode(send)),
+                ],
+                None => vec![],
+            },
+            (Tab::Txs, Some(_)) => vec![],
+        }
+    }
+
+    fn status(&self) -> String {
+        match (&self.input, &self.message) {
+            (Some(input), _) => format!("address: {}", input),
+            (None, Some(message)) => message.clone(),
+            (None, None) => String::from(HELP),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ])
+            .split(frame.size());
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+            .split(rows[1]);
+        let bordered = |title: String| Block::default().borders(Borders::ALL).title(title);
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+        let summary = capture(|output| self.explorer.summary(output)).join(" ");
+        let titles: Vec<_> = TABS
+            .iter()
+            .enumerate()
+            .map(|(index, tab)| format!("{} {}", index + 1, tab.title()))
+            .collect();
+        let tabs = Tabs::new(titles)
+            .block(bordered(summary))
+            .select(self.tab)
+            .highlight_style(highlight.add_modifier(Modifier::BOLD));
+        frame.render_widget(tabs, rows[0]);
+
+        let items: Vec<_> = self.items().into_iter().map(ListItem::new).collect();
+        let detail: Vec<_> = self.detail().into_iter().map(Line::from).collect();
+        let list = List::new(items)
+            .block(bordered(TABS[self.tab].title().to_string()))
+            .highlight_style(highlight);
+        frame.render_stateful_widget(list, panes[0], &mut self.selected[self.tab]);
+        let detail = Paragraph::new(detail)
+            .block(bordered(String::from("Detail")))
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0));
+        frame.render_widget(detail, panes[1]);
+
+        frame.render_widget(Paragraph::new(self.status()), rows[2]);
+    }
+}
+
+/// Collects what one of the explorer's commands shows as lines. Writing to memory can't fail, so
+/// any error is of the command's own, and is shown in its place.
+fn capture(show: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> Vec<String> {
+    let mut output = vec![];
+    if let Err(e) = show(&mut output) {
+        return vec![e.to_string()];
+    }
+    String::from_utf8_lossy(&output)
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn test_explorer_tui() {
+    use crate::evm::Tx;
+    use crate::run::{load_from_file_and_env, RuntimeEnvironment, TestAccounts};
+    use crate::uint256::Uint256;
+    use ratatui::backend::TestBackend;
+    use std::path::Path;
+
+    let mut env = RuntimeEnvironment::default();
+    let accounts = TestAccounts::new(&mut env);
+    let (alice, bob) = (accounts.alice(), accounts.bob());
+    env.fund(alice, Uint256::_from_eth(10));
+    Tx::call(bob.address.clone())
+        .value(Uint256::_from_eth(1))
+        .send(&mut env, alice.address.clone());
+
+    let mut machine = load_from_file_and_env(Path::new("arb_os/arbos.mexe"), env);
+    machine.start_at_zero(false);
+    let _ = machine.run(None);
+
+    let mut tui = ExplorerTui::new(Explorer::new(machine));
+    let mut terminal = Terminal::new(TestBackend::new(160, 40)).unwrap();
+    let mut screen = |tui: &mut ExplorerTui| -> String {
+        terminal.draw(|frame| tui.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer.content.iter().map(|cell| cell.symbol()).collect()
+    };
+    assert!(screen(&mut tui).contains("2 transactions"));
+
+    // the transfer is the second transaction, and is shown in full once selected
+    tui.handle_key(KeyCode::Char('2'));
+    tui.handle_key(KeyCode::Down);
+    tui.handle_key(KeyCode::Down);
+    let shown = screen(&mut tui);
+    assert!(shown.contains("tx 1  block"));
+    assert!(shown.contains("result       success"));
+
+    // addresses are looked up as they're typed
+    tui.handle_key(KeyCode::Char('/'));
+    for c in format!("{:?}", bob.address.to_h160()).chars() {
+        tui.handle_key(KeyCode::Char(c));
+    }
+    tui.handle_key(KeyCode::Enter);
+    let shown = screen(&mut tui);
+    assert!(shown.contains(&format!("balance    {}", Uint256::_from_eth(1))));
+
+    tui.handle_key(KeyCode::Char('/'));
+    for c in "0xnope".chars() {
+        tui.handle_key(KeyCode::Char(c));
+    }
+    tui.handle_key(KeyCode::Enter);
+    assert!(screen(&mut tui).contains("0xnope isn't an address"));
+
+    tui.handle_key(KeyCode::Char('q'));
+    assert!(tui.quit);
+}
@@ -0,0 +1,390 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Models the warm and cold account and storage access costs of EIP-2929, and checks whether
+//! ArbOS charges the same difference between a first and a repeated access.
+//!
+//! Each probed opcode gets its own contract, which reads the gas left around two identical
+//! accesses to a fresh account or slot and returns what each access cost. ArbOS charges ArbGas
+//! rather than Ethereum gas, so the costs themselves aren't comparable, but the surcharge a cold
+//! access pays over a warm one is: an ArbOS that modeled EIP-2929 would charge the same surcharge
+//! that Ethereum does.
+
+use crate::evm::tx::TxBuilder;
+use crate::evm::Tx;
+use crate::run::{load_from_file_and_env, Machine, RuntimeEnvironment};
+use crate::uint256::Uint256;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+
+/// The cost of the first read of a storage slot in a transaction.
+pub const COLD_SLOAD_COST: u64 = 2100;
+/// The cost of the first access of an account in a transaction.
+pub const COLD_ACCOUNT_ACCESS_COST: u64 = 2600;
+/// The cost of any later read of a slot or access of an account.
+pub const WARM_STORAGE_READ_COST: u64 = 100;
+
+/// Whether an access is the first of its account or slot in a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Warmth {
+    Cold,
+    Warm,
+}
+
+/// The accounts and storage slots a transaction has accessed so far.
+#[derive(Clone, Debug, Default)]
+pub struct AccessSet {
+    accounts: BTreeSet<Uint256>,
+    slots: BTreeSet<(Uint256, Uint256)>,
+}
+
+impl AccessSet {
+    /// Creates the access set a transaction starts with, in which its sender, its recipient, and
+    /// the precompiles are already warm.
+    pub fn new(origin: Uint256, to: Uint256) -> Self {
+        let mut set = AccessSet::default();
+        set.accounts.insert(origin);
+        set.accounts.insert(to);
+        for precompile in 1..=9 {
+            set.accounts.insert(Uint256::from_u64(precompile));
+        }
+        set
+    }
+
+    /// Accesses an account, returning whether it was cold.
+    pub fn access_account(&mut self, account: &Uint256) -> Warmth {
+        match self.accounts.insert(account.clone()) {
+            true => Warmth::Cold,
+            false => Warmth::Warm,
+        }
+    }
+
+    /// Accesses a storage slot of an account, returning whether it was cold.
+    pub fn access_slot(&mut self, account: &Uint256, slot: &Uint256) -> Warmth {
+        match self.slots.insert((account.clone(), slot.clone())) {
+            true => Warmth::Cold,
+            false => Warmth::Warm,
+        }
+    }
+}
+
+/// An EVM opcode whose cost depends on whether the account or slot it accesses is warm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AccessOpcode {
+    Balance,
+    ExtCodeSize,
+    ExtCodeCopy,
+    ExtCodeHash,
+    Sload,
+    Sstore,
+    Call,
+    StaticCall,
+}
+
+impl AccessOpcode {
+    pub fn all() -> Vec<AccessOpcode> {
+        vec![
+            AccessOpcode::Balance,
+            AccessOpcode::ExtCodeSize,
+            AccessOpcode::ExtCodeCopy,
+            AccessOpcode::ExtCodeHash,
+            AccessOpcode::Sload,
+            AccessOpcode::Sstore,
+            AccessOpcode::Call,
+            AccessOpcode::StaticCall,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccessOpcode::Balance => "BALANCE",
+            AccessOpcode::ExtCodeSize => "EXTCODESIZE",
+            AccessOpcode::ExtCodeCopy => "EXTCODECOPY",
+            AccessOpcode::ExtCodeHash => "EXTCODEHASH",
+            AccessOpcode::Sload => "SLOAD",
+            AccessOpcode::Sstore => "SSTORE",
+            AccessOpcode::Call => "CALL",
+            AccessOpcode::StaticCall => "STATICCALL",
+        }
+    }
+
+    /// The Ethereum gas an access costs under EIP-2929, leaving out the costs of copying, memory
+    /// expansion, and value transfer. For SSTORE, this is the cost of writing a slot's current
+    /// value back to it.
+    pub fn access_cost(&self, warmth: Warmth) -> u64 {
+        match (self, warmth) {
+            (_, Warmth::Warm) => WARM_STORAGE_READ_COST,
+            (AccessOpcode::Sload, Warmth::Cold) => COLD_SLOAD_COST,
+            (AccessOpcode::Sstore, Warmth::Cold) => COLD_SLOAD_COST + WARM_STORAGE_READ_COST,
+            (_, Warmth::Cold) => COLD_ACCOUNT_ACCESS_COST,
+        }
+    }
+
+    /// Updates an access set with an access by a contract, returning whether it was cold.
+    pub fn access(&self, set: &mut AccessSet, contract: &Uint256, target: &Uint256) -> Warmth {
+        match self {
+            AccessOpcode::Sload | AccessOpcode::Sstore => set.access_slot(contract, target),
+            _ => set.access_account(target),
+        }
+    }
+
+    /// EVM code accessing an account, or a slot of the running contract, leaving the stack as it
+    /// found it.
+    fn probe_code(&self, target: &Uint256) -> Vec<u8> {
+        let mut push_target = vec![0x7f];
+        push_target.extend(target.to_bytes_be());
+        let zeros = |count: usize| [0x60, 0x00].repeat(count);
+        let code = match self {
+            AccessOpcode::Balance => vec![push_target, vec![0x31, 0x50]],
+            AccessOpcode::ExtCodeSize => vec![push_target, vec![0x3b, 0x50]],
+            AccessOpcode::ExtCodeHash => vec![push_target, vec![0x3f, 0x50]],
+            AccessOpcode::ExtCodeCopy => vec![zeros(3), push_target, vec![0x3c]],
+            AccessOpcode::Sload => vec![push_target, vec![0x54, 0x50]],
+            AccessOpcode::Sstore => vec![zeros(1), push_target, vec![0x55]],
+            AccessOpcode::Call => vec![
+                zeros(5),
+                push_target,
+                vec![0x62, 0x0f, 0x42, 0x40, 0xf1, 0x50],
+            ],
+            AccessOpcode::StaticCall => vec![
+                zeros(4),
+                push_target,
+                vec![0x62, 0x0f, 0x42, 0x40, 0xfa, 0x50],
+            ],
+        };
+        code.concat()
+    }
+
+    /// Init code deploying a contract that accesses target twice and returns the gas each access
+    /// used, measured by the gas left before and after it.
+    fn probe_contract(&self, target: &Uint256) -> Vec<u8> {
+        let access = self.probe_code(target);
+        let mut runtime = vec![0x5a];
+        runtime.extend(&access);
+        runtime.push(0x5a);
+        runtime.extend(&access);
+        runtime.push(0x5a);
+        // with the gas left at each point on the stack, store g1 - g2 at 0x20 and g0 - g1 at 0
+        runtime.extend(&[
+            0x81, 0x03, 0x60, 0x20, 0x52, 0x90, 0x03, 0x60, 0x00, 0x52, 0x60, 0x40, 0x60, 0x00,
+            0xf3,
+        ]);
+        // copy the runtime code, which follows this 12 byte prefix, to memory and return it
+        let mut init = vec![0x61];
+        init.extend(&(runtime.len() as u16).to_be_bytes());
+        init.extend(&[0x80, 0x60, 0x0c, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3]);
+        assert_eq!(init.len(), 12);
+        init.extend(runtime);
+        init
+    }
+}
+
+impl fmt::Display for AccessOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// What a first and a second access by an opcode were expected to cost and what ArbOS charged.
+#[derive(Clone, Debug)]
+pub struct ParityRow {
+    pub opcode: AccessOpcode,
+    pub expected_cold: u64,
+    pub expected_warm: u64,
+    pub observed_cold: u64,
+    pub observed_warm: u64,
+}
+
+impl ParityRow {
+    /// The extra ArbGas ArbOS charged for the cold access.
+    pub fn observed_surcharge(&self) -> i128 {
+        self.observed_cold as i128 - self.observed_warm as i128
+    }
+
+    pub fn expected_surcharge(&self) -> i128 {
+        self.expected_cold as i128 - self.expected_warm as i128
+    }
+
+    pub fn matches(&self) -> bool {
+        self.observed_surcharge() == self.expected_surcharge()
+    }
+}
+
+/// The result of probing ArbOS with each access opcode.
+#[derive(Clone, Debug, Default)]
+pub struct GasParityReport {
+    pub rows: Vec<ParityRow>,
+}
+
+impl GasParityReport {
+    /// The opcodes whose cold surcharge in ArbOS differs from EIP-2929's.
+    pub fn mismatches(&self) -> Vec<AccessOpcode> {
+        self.rows
+            .iter()
+            .filter(|row| !row.matches())
+            .map(|row| row.opcode)
+            .collect()
+    }
+}
+
+impl fmt::Display for GasParityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:>13} {:>13} {:>13} {:>13}  parity",
+            "opcode", "eip2929 cold", "eip2929 warm", "arbos cold", "arbos warm"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:<12} {:>13} {:>13} {:>13} {:>13}  {}",
+                row.opcode.name(),
+                row.expected_cold,
+                row.expected_warm,
+                row.observed_cold,
+                row.observed_warm,
+                match row.matches() {
+                    true => "ok".to_string(),
+                    false => format!(
+                        "surcharge {} instead of {}",
+                        row.observed_surcharge(),
+                        row.expected_surcharge()
+                    ),
+                }
+            )?;
+        }
+        let mismatches = self.mismatches();
+        match mismatches.is_empty() {
+            true => writeln!(f, "all {} opcodes match EIP-2929", self.rows.len()),
+            false => writeln!(
+                f,
+                "{} of {} opcodes don't match EIP-2929: {}",
+                mismatches.len(),
+                self.rows.len(),
+                mismatches
+                    .iter()
+                    .map(|opcode| opcode.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// Probes the ArbOS in mexe with each access opcode, reporting whether it charges cold accesses
+/// the surcharge EIP-2929 does.
+pub fn check_gas_parity(mexe: &Path) -> Result<GasParityReport, String> {
+    let sender = Uint256::from_u64(1025);
+    let mut env = RuntimeEnvironment::default();
+    env.insert_eth_deposit_message(sender.clone(), sender.clone(), Uint256::_from_eth(1), true);
+    let mut machine = load_from_file_and_env(mexe, env);
+    machine.start_at_zero(true);
+    let _ = machine.run(None);
+
+    let mut report = GasParityReport::default();
+    for (index, opcode) in AccessOpcode::all().into_iter().enumerate() {
+        // an account no probe has touched, which is also a slot no probe has written
+        let target = Uint256::from_u64(0xc01d_0000 + index as u64);
+        let contract = send_and_check(
+            &mut machine,
+            Tx::deploy().calldata(opcode.probe_contract(&target)),
+            &sender,
+        )
+        .map_err(|e| format!("couldn't deploy the {} probe: {}", opcode, e))?;
+        let contract = Uint256::from_bytes(&contract);
+
+        let mut access_set = AccessSet::new(sender.clone(), contract.clone());
+        let first = opcode.access(&mut access_set, &contract, &target);
+        let second = opcode.access(&mut access_set, &contract, &target);
+        let returned = send_and_check(&mut machine, Tx::call(contract), &sender)
+            .map_err(|e| format!("the {} probe failed: {}", opcode, e))?;
+        if returned.len() != 64 {
+            return Err(format!(
+                "the {} probe returned {} bytes",
+                opcode,
+                returned.len()
+            ));
+        }
+        let observed = |bytes: &[u8]| {
+            Uint256::from_bytes(bytes)
+                .to_u64()
+                .ok_or_else(|| format!("the {} probe measured a negative cost", opcode))
+        };
+        report.rows.push(ParityRow {
+            opcode,
+            expected_cold: opcode.access_cost(first),
+            expected_warm: opcode.access_cost(second),
+            observed_cold: observed(&returned[..32])?,
+            observed_warm: observed(&returned[32..])?,
+        });
+    }
+    Ok(report)
+}
+
+/// Runs a transaction to completion, returning its return data if it succeeded.
+fn send_and_check(
+    machine: &mut Machine,
+    tx: TxBuilder,
+    sender: &Uint256,
+) -> Result<Vec<u8>, String> {
+    let request_id = tx.send(&mut machine.runtime_env, sender.clone());
+    let _ = machine.run(None);
+    let receipt = machine
+        .runtime_env
+        .get_all_receipt_logs()
+        .into_iter()
+        .find(|receipt| receipt.get_request_id() == request_id)
+        .ok_or_else(|| "ArbOS didn't emit a receipt".to_string())?;
+    match receipt.succeeded() {
+        true => Ok(receipt.get_return_data()),
+        false => Err(format!("ArbOS returned code {}", receipt.get_return_code())),
+    }
+}
+
+#[test]
+fn test_gas_parity() {
+    let sender = Uint256::from_u64(1025);
+    let contract = Uint256::from_u64(0xc0de);
+    let other = Uint256::from_u64(0xbeef);
+    let mut set = AccessSet::new(sender.clone(), contract.clone());
+    for precompile in &[Uint256::from_u64(1), Uint256::from_u64(9), contract.clone()] {
+        assert_eq!(set.access_account(precompile), Warmth::Warm);
+    }
+    assert_eq!(set.access_account(&other), Warmth::Cold);
+    assert_eq!(set.access_account(&other), Warmth::Warm);
+    // a slot is warmed by an access through either opcode, but only for its own contract
+    let slot = Uint256::from_u64(7);
+    assert_eq!(
+        AccessOpcode::Sload.access(&mut set, &contract, &slot),
+        Warmth::Cold
+    );
+    assert_eq!(
+        AccessOpcode::Sstore.access(&mut set, &contract, &slot),
+        Warmth::Warm
+    );
+    assert_eq!(set.access_slot(&other, &slot), Warmth::Cold);
+    assert_eq!(AccessOpcode::Sload.access_cost(Warmth::Cold), 2100);
+    assert_eq!(AccessOpcode::Sstore.access_cost(Warmth::Cold), 2200);
+    assert_eq!(AccessOpcode::StaticCall.access_cost(Warmth::Cold), 2600);
+    assert_eq!(AccessOpcode::StaticCall.access_cost(Warmth::Warm), 100);
+
+    let report = check_gas_parity(Path::new("arb_os/arbos.mexe")).unwrap();
+    assert_eq!(report.rows.len(), AccessOpcode::all().len());
+    for row in &report.rows {
+        assert_eq!(row.expected_cold, row.opcode.access_cost(Warmth::Cold));
+        assert_eq!(row.expected_warm, WARM_STORAGE_READ_COST);
+        assert!(row.observed_warm > 0, "{} cost nothing", row.opcode);
+        assert_eq!(
+            report.mismatches().contains(&row.opcode),
+            !row.matches(),
+            "{}",
+            row.opcode
+        );
+    }
+    let printed = report.to_string();
+    for opcode in report.mismatches() {
+        assert!(printed.contains(opcode.name()));
+    }
+}
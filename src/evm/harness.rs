@@ -0,0 +1,210 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides a harness for integration tests, bundling a booted ArbOS with funded test accounts
+//! and the contracts deployed to it, so that a test can deploy, call, and check balances in a line
+//! each rather than driving the machine, environment, and ABIs itself.
+
+use crate::evm::preinstalled_contracts::_ArbInfo;
+use crate::evm::{test_contract_path, AbiForContract, Tx};
+use crate::run::{_booted_arbos, ArbosReceipt, Machine, TestAccount, TestAccounts};
+use crate::uint256::Uint256;
+use std::collections::HashMap;
+
+/// A booted ArbOS whose test accounts have been funded, along with the contracts deployed to it.
+pub struct ArbosHarness {
+    pub machine: Machine,
+    pub accounts: TestAccounts,
+    /// The contracts deployed so far, by name
+    contracts: HashMap<String, AbiForContract>,
+    debug: bool,
+}
+
+impl ArbosHarness {
+    /// Boots ArbOS and funds the test accounts.
+    pub fn new() -> Self {
+        let mut machine = _booted_arbos();
+        let accounts = TestAccounts::new(&mut machine.runtime_env);
+        machine.runtime_env.fund_test_accounts(&accounts);
+        let mut harness = ArbosHarness {
+            machine,
+            accounts,
+            contracts: HashMap::new(),
+            debug: false,
+        };
+        harness.run();
+        harness
+    }
+
+    /// Runs the machine in the debugger whenever it handles messages.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Has ArbOS handle the messages sent so far.
+    pub fn run(&mut self) {
+        let _gas_used = match self.debug {
+            true => self.machine.debug(None),
+            false => self.machine.run(None),
+        };
+    }
+
+    /// Deploys the test contract of the given name, returning its address. The contract can then
+    /// be called by that name.
+    pub fn deploy(&mut self, name: &str, args: &[ethabi::Token]) -> Result<Uint256, ethabi::Error> {
+        let mut contract = AbiForContract::new_from_file(&test_contract_path(name))?;
+        let address = contract
+            .deploy(args, &mut self.machine, Uint256::zero(), None, self.debug)
+            .map_err(|_| ethabi::Error::from(format!("deploying {} failed", name)))?;
+        self.contracts.insert(name.to_string(), contract);
+        Ok(address)
+    }
+
+    /// Finds a contract deployed by name.
+    pub fn contract(&self, name: &str) -> Option<&AbiForContract> {
+        self.contracts.get(name)
+    }
+
+    /// Calls a function of a contract deployed by name in a tx signed by sender, returning what
+    /// the function returns. Fails if the tx reverts.
+    pub fn call(
+        &mut self,
+        sender: &TestAccount,
+        contract: &str,
+        func: &str,
+        args: &[ethabi::Token],
+    ) -> Result<Vec<ethabi::Token>, ethabi::Error> {
+        let contract = self
+            .contracts
+            .get(contract)
+            .ok_or_else(|| ethabi::Error::from(format!("no contract named {}", contract)))?;
+        let function = contract.get_function(func)?.clone();
+        let request_id = Tx::call(contract.address.clone())
+            .calldata(function.encode_input(args)?)
+            .send_signed(&mut self.machine.runtime_env, &sender.wallet);
+        let receipt = self.receipt_for(&request_id)?;
+        function.decode_output(&receipt.get_return_data())
+    }
+
+    /// Sends wei from one account to another, returning the transfer's receipt. Fails if the
+    /// transfer does.
+    pub fn transfer(
+        &mut self,
+        sender: &TestAccount,
+        to: &Uint256,
+        amount: Uint256,
+    ) -> Result<ArbosReceipt, ethabi::Error> {
+        let request_id = Tx::call(to.clone())
+            .value(amount)
+            .send_signed(&mut self.machine.runtime_env, &sender.wallet);
+        self.receipt_for(&request_id)
+    }
+
+    /// Moves on to the next L1 block, closing the L2 block ArbOS is building.
+    pub fn advance_block(&mut self) {
+        self.machine
+            .runtime_env
+            ._advance_time(Uint256::one(), None, true);
+        self.run();
+    }
+
+    /// Reads the balance of an address, in wei.
+    pub fn balance_of(&mut self, address: &Uint256) -> Result<Uint256, ethabi::Error> {
+        _ArbInfo::_new(self.debug)._get_balance(&mut self.machine, address)
+    }
+
+    /// Runs the machine, then finds the receipt of the request, failing if it didn't succeed.
+    fn receipt_for(&mut self, request_id: &Uint256) -> Result<ArbosReceipt, ethabi::Error> {
+        self.run();
+        let receipt = self
+            .machine
+            .runtime_env
+            .get_all_receipt_logs()
+            .into_iter()
+            .rev()
+            .find(|receipt| &receipt.get_request_id() == request_id)
+            .ok_or_else(|| ethabi::Error::from(format!("request {} has no receipt", request_id)))?;
+        match receipt.succeeded() {
+            true => Ok(receipt),
+            false => Err(ethabi::Error::from(format!(
+                "request {} failed with code {}",
+                request_id,
+                receipt.get_return_code()
+            ))),
+        }
+    }
+}
+
+impl Default for ArbosHarness {
+    fn default() -> Self {
+        ArbosHarness::new()
+    }
+}
+
+#[test]
+fn test_arbos_harness() {
+    let mut harness = ArbosHarness::new();
+    let (alice, bob) = (
+        harness.accounts.alice().clone(),
+        harness.accounts.bob().clone(),
+    );
+
+    let supply = Uint256::_from_eth(1000);
+    let token = harness
+        .deploy(
+            "ExampleToken",
+            &[
+                ethabi::Token::Address(alice.address.to_h160()),
+                ethabi::Token::Uint(supply.to_u256()),
+            ],
+        )
+        .unwrap();
+    assert_eq!(harness.contract("ExampleToken").unwrap().address, token);
+
+    let amount = Uint256::_from_eth(250);
+    let sent = harness
+        .call(
+            &alice,
+            "ExampleToken",
+            "transfer",
+            &[
+                ethabi::Token::Address(bob.address.to_h160()),
+                ethabi::Token::Uint(amount.to_u256()),
+            ],
+        )
+        .unwrap();
+    assert_eq!(sent, vec![ethabi::Token::Bool(true)]);
+    let held = harness
+        .call(
+            &bob,
+            "ExampleToken",
+            "balanceOf",
+            &[ethabi::Token::Address(bob.address.to_h160())],
+        )
+        .unwrap();
+    assert_eq!(held, vec![ethabi::Token::Uint(amount.to_u256())]);
+
+    // moving more tokens than alice has reverts
+    assert!(harness
+        .call(
+            &alice,
+            "ExampleToken",
+            "transfer",
+            &[
+                ethabi::Token::Address(bob.address.to_h160()),
+                ethabi::Token::Uint(supply.to_u256()),
+            ],
+        )
+        .is_err());
+    assert!(harness.call(&alice, "Missing", "transfer", &[]).is_err());
+
+    let before = harness.balance_of(&bob.address).unwrap();
+    let paid = Uint256::_from_eth(3);
+    harness
+        .transfer(&alice, &bob.address, paid.clone())
+        .unwrap();
+    harness.advance_block();
+    assert_eq!(harness.balance_of(&bob.address).unwrap(), before.add(&paid));
+}
@@ -0,0 +1,437 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Decodes a contract's storage by the storage layout solc emits for it, so that its state can be
+//! read as named variables rather than raw slots. Mappings don't record their keys, so entries are
+//! found by trying keys seen elsewhere on the chain, such as the addresses in logs.
+
+use crate::uint256::Uint256;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+
+/// How many levels of mappings, arrays, and structs are decoded before giving up, as a struct can
+/// hold mappings of itself.
+const MAX_DEPTH: usize = 8;
+
+/// How many elements of a dynamic array are shown.
+const MAX_ELEMENTS: usize = 64;
+
+/// The storage layout solc emits with `--storage-layout`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct StorageLayout {
+    pub storage: Vec<StorageVar>,
+    #[serde(default)]
+    pub types: BTreeMap<String, StorageType>,
+}
+
+/// A variable, or a member of a struct, whose slot is relative to the struct's.
+#[derive(Clone, Debug, Deserialize)]
+pub struct StorageVar {
+    pub label: String,
+    /// The slot, in decimal
+    pub slot: String,
+    /// The byte within the slot the variable starts at, counting from the right
+    pub offset: usize,
+    #[serde(rename = "type")]
+    pub type_id: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageType {
+    /// One of `inplace`, `mapping`, `dynamic_array`, or `bytes`
+    pub encoding: String,
+    pub label: String,
+    /// The size of the type, in decimal
+    pub number_of_bytes: String,
+    /// The key type of a mapping
+    pub key: Option<String>,
+    /// The value type of a mapping
+    pub value: Option<String>,
+    /// The element type of an array
+    pub base: Option<String>,
+    pub members: Option<Vec<StorageVar>>,
+}
+
+impl StorageType {
+    fn size(&self) -> usize {
+        self.number_of_bytes.parse().unwrap_or(32)
+    }
+}
+
+/// A contract's storage, by variable.
+#[derive(Debug, Default)]
+pub struct DecodedStorage {
+    /// Each variable, array element, struct member, and mapping entry found, by its path
+    pub vars: Vec<(String, String)>,
+    /// The slots no variable accounts for, such as those of mapping entries whose keys weren't
+    /// tried
+    pub unknown: BTreeMap<Uint256, Uint256>,
+}
+
+impl StorageLayout {
+    /// Reads the layout from a file, which may hold the layout itself or a contract artifact with
+    /// the layout under `storageLayout`.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let json: serde_json::Value = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+        let layout = match json.get("storageLayout") {
+            Some(layout) => layout.clone(),
+            None => json,
+        };
+        serde_json::from_value(layout).map_err(|e| format!("bad storage layout: {}", e))
+    }
+
+    /// Decodes storage, trying each of keys as a key of any mappings.
+    pub fn decode(&self, storage: &HashMap<Uint256, Uint256>, keys: &[Uint256]) -> DecodedStorage {
+        let mut decoder = Decoder {
+            types: &self.types,
+            storage,
+            keys,
+            vars: vec![],
+            read: HashSet::new(),
+        };
+        for var in &self.storage {
+            let slot = Uint256::from_string(&var.slot).unwrap_or_else(Uint256::zero);
+            decoder.decode(var.label.clone(), &var.type_id, slot, var.offset, 0);
+        }
+
+        let unknown = storage
+            .iter()
+            .filter(|(slot, value)| !decoder.read.contains(*slot) && !value.is_zero())
+            .map(|(slot, value)| (slot.clone(), value.clone()))
+            .collect();
+        DecodedStorage {
+            vars: decoder.vars,
+            unknown,
+        }
+    }
+}
+
+struct Decoder<'a> {
+    types: &'a BTreeMap<String, StorageType>,
+    storage: &'a HashMap<Uint256, Uint256>,
+    keys: &'a [Uint256],
+    vars: Vec<(String, String)>,
+    /// The slots decoded so far
+    read: HashSet<Uint256>,
+}
+
+impl<'a> Decoder<'a> {
+    fn word(&mut self, slot: &Uint256) -> Uint256 {
+        self.read.insert(slot.clone());
+        self.storage
+            .get(slot)
+            .cloned()
+            .unwrap_or_else(Uint256::zero)
+    }
+
+    /// Decodes a value of the type at slot and offset, returning whether any of it is nonzero.
+    fn decode(
+        &mut self,
+        name: String,
+        type_id: &str,
+        slot: Uint256,
+        offset: usize,
+        depth: usize,
+    ) -> bool {
+        let types = self.types;
+        let tipe = match types.get(type_id) {
+            Some(tipe) => tipe,
+            None => {
+                let value = self.word(&slot);
+                self.vars
+                    .push((name, format!("{} (unknown type {})", value, type_id)));
+                return !value.is_zero();
+            }
+        };
+        if depth > MAX_DEPTH {
+            self.vars
+                .push((name, String::from("(nested too deeply to show)")));
+            return false;
+        }
+
+        match tipe.encoding.as_str() {
+            "mapping" => self.decode_mapping(name, tipe, slot, depth),
+            "dynamic_array" => {
+                let length = self.word(&slot);
+                let base = tipe.base.as_deref().unwrap_or_default();
+                let count = length.to_usize().unwrap_or(usize::MAX);
+                self.vars
+                    .push((format!("{}.length", name), length.to_string()));
+                self.decode_elements(&name, base, slot.avm_hash(), count, depth);
+                !length.is_zero()
+            }
+            "bytes" => {
+                let (value, nonzero) = self.decode_bytes(tipe, &slot);
+                self.vars.push((name, value));
+                nonzero
+            }
+            _ => match (&tipe.members, &tipe.base) {
+                (Some(members), _) => {
+                    let mut nonzero = false;
+                    for member in members {
+                        let member_slot = Uint256::from_string(&member.slot)
+                            .unwrap_or_else(Uint256::zero)
+                            .add(&slot);
+                        nonzero |= self.decode(
+                            format!("{}.{}", name, member.label),
+                            &member.type_id,
+                            member_slot,
+                            member.offset,
+                            depth + 1,
+                        );
+                    }
+                    nonzero
+                }
+                (None, Some(base)) => {
+                    let count = static_length(&tipe.label);
+                    self.decode_elements(&name, base, slot, count, depth)
+                }
+                (None, None) => {
+                    let value = field(&self.word(&slot), offset, tipe.size());
+                    self.vars.push((name, show_value(tipe, &value)));
+                    !value.is_zero()
+                }
+            },
+        }
+    }
+
+    /// Decodes the entries of a mapping under each key that could be one. Only entries with
+    /// something in them are shown, as every key maps to a zero that was never written.
+    fn decode_mapping(
+        &mut self,
+        name: String,
+        tipe: &StorageType,
+        slot: Uint256,
+        depth: usize,
+    ) -> bool {
+        let types = self.types;
+        let key_type = tipe.key.as_deref().and_then(|key| types.get(key));
+        let value_type = tipe.value.as_deref().unwrap_or_default();
+        let key_type = match key_type {
+            Some(key_type) if key_type.encoding == "inplace" => key_type,
+            _ => {
+                self.vars
+                    .push((name, String::from("(keys of this type can't be found)")));
+                return false;
+            }
+        };
+
+        let limit = match key_type.size() {
+            size if size >= 32 => None,
+            size => Some(Uint256::one().shift_left(8 * size)),
+        };
+        let mut nonzero = false;
+        for key in self.keys {
+            if limit.as_ref().map_or(false, |limit| key >= limit) {
+                continue;
+            }
+            let shown = self.vars.len();
+            let entry = format!("{}[{}]", name, show_value(key_type, key));
+            let entry_slot = Uint256::avm_hash2(key, &slot);
+            match self.decode(entry, value_type, entry_slot, 0, depth + 1) {
+                true => nonzero = true,
+                false => self.vars.truncate(shown),
+            }
+        }
+        if !nonzero {
+            self.vars
+                .push((name, String::from("(no entries under the keys tried)")));
+        }
+        nonzero
+    }
+
+    /// Decodes count elements of an array starting at slot, packing those small enough to share
+    /// a slot as solc does.
+    fn decode_elements(
+        &mut self,
+        name: &str,
+        base: &str,
+        slot: Uint256,
+        count: usize,
+        depth: usize,
+    ) -> bool {
+        let size = self.types.get(base).map_or(32, StorageType::size);
+        let mut nonzero = false;
+        for index in 0..count.min(MAX_ELEMENTS) {
+            let (element_slot, offset) = match size <= 16 {
+                true => {
+                    let per_slot = 32 / size;
+                    let slot = slot.add(&Uint256::from_usize(index / per_slot));
+                    (slot, (index % per_slot) * size)
+                }
+                false => {
+                    let slots = (size + 31) / 32;
+                    (slot.add(&Uint256::from_usize(index * slots)), 0)
+                }
+            };
+            let element = format!("{}[{}]", name, index);
+            nonzero |= self.decode(element, base, element_slot, offset, depth + 1);
+        }
+        if count > MAX_ELEMENTS {
+            let more = format!("{} more", count - MAX_ELEMENTS);
+            self.vars.push((format!("{}[..]", name), more));
+        }
+        nonzero
+    }
+
+    /// Decodes a `bytes` or `string`, which is kept in its slot along with its length if it's
+    /// shorter than 32 bytes, and otherwise from the hash of its slot on.
+    fn decode_bytes(&mut self, tipe: &StorageType, slot: &Uint256) -> (String, bool) {
+        let word = self.word(slot);
+        let header = word.to_bytes_be();
+        let data = match header[31] & 1 {
+            0 => header[..(header[31] / 2).min(31) as usize].to_vec(),
+            _ => {
+                let length = word.shift_right(1).to_usize().unwrap_or(usize::MAX);
+                let length = length.min(32 * MAX_ELEMENTS);
+                let start = slot.avm_hash();
+                let mut data = vec![];
+                for index in 0..(length + 31) / 32 {
+                    let chunk = self.word(&start.add(&Uint256::from_usize(index)));
+                    data.extend(chunk.to_bytes_be());
+                }
+                data.truncate(length);
+                data
+            }
+        };
+        let shown = match tipe.label.as_str() {
+            "string" => format!("{:?}", String::from_utf8_lossy(&data)),
+            _ => format!("0x{}", hex::encode(&data)),
+        };
+        (shown, !word.is_zero())
+    }
+}
+
+/// The size bytes at offset within a word, counting from the right as solc does.
+fn field(word: &Uint256, offset: usize, size: usize) -> Uint256 {
+    let bytes = word.to_bytes_be();
+    let end = 32 - offset.min(32);
+    let start = end - size.min(end);
+    Uint256::from_bytes(&bytes[start..end])
+}
+
+/// The length of a static array, from a label like `uint8[3]`.
+fn static_length(label: &str) -> usize {
+    label
+        .rsplit('[')
+        .next()
+        .and_then(|length| length.strip_suffix(']'))
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Shows a value the way it'd be written in solidity, as far as its type's label says.
+fn show_value(tipe: &StorageType, value: &Uint256) -> String {
+    let label = tipe.label.as_str();
+    let bits = 8 * tipe.size().min(32);
+    if label == "bool" {
+        return (!value.is_zero()).to_string();
+    }
+    if label.starts_with("address") || label.starts_with("contract ") {
+        return format!("{:?}", value.to_h160());
+    }
+    if label.starts_with("int") && *value >= Uint256::one().shift_left(bits - 1) {
+        let magnitude = match bits {
+            256 => Uint256::zero().unchecked_sub(value),
+            _ => Uint256::one().shift_left(bits).unchecked_sub(value),
+        };
+        return format!("-{}", magnitude);
+    }
+    if label.starts_with("int") || label.starts_with("uint") || label.starts_with("enum ") {
+        return value.to_string();
+    }
+    let bytes = value.to_bytes_be();
+    format!("0x{}", hex::encode(&bytes[32 - bits / 8..]))
+}
+
+#[test]
+fn test_storage_layout() {
+    let layout = r#"{
+        "storageLayout": {
+            "storage": [
+                {"label": "total", "offset": 0, "slot": "0", "type": "t_uint256"},
+                {"label": "paused", "offset": 0, "slot": "1", "type": "t_bool"},
+                {"label": "delta", "offset": 1, "slot": "1", "type": "t_int8"},
+                {"label": "owner", "offset": 2, "slot": "1", "type": "t_address"},
+                {"label": "name", "offset": 0, "slot": "2", "type": "t_string_storage"},
+                {"label": "balances", "offset": 0, "slot": "3",
+                 "type": "t_mapping(t_address,t_struct(Account)_storage)"},
+                {"label": "history", "offset": 0, "slot": "4",
+                 "type": "t_array(t_uint64)dyn_storage"}
+            ],
+            "types": {
+                "t_address": {"encoding": "inplace", "label": "address", "numberOfBytes": "20"},
+                "t_bool": {"encoding": "inplace", "label": "bool", "numberOfBytes": "1"},
+                "t_int8": {"encoding": "inplace", "label": "int8", "numberOfBytes": "1"},
+                "t_uint64": {"encoding": "inplace", "label": "uint64", "numberOfBytes": "8"},
+                "t_uint256": {"encoding": "inplace", "label": "uint256", "numberOfBytes": "32"},
+                "t_string_storage": {"encoding": "bytes", "label": "string", "numberOfBytes": "32"},
+                "t_array(t_uint64)dyn_storage": {
+                    "encoding": "dynamic_array", "label": "uint64[]", "numberOfBytes": "32",
+                    "base": "t_uint64"
+                },
+                "t_mapping(t_address,t_struct(Account)_storage)": {
+                    "encoding": "mapping", "label": "mapping(address => struct Account)",
+                    "numberOfBytes": "32", "key": "t_address",
+                    "value": "t_struct(Account)_storage"
+                },
+                "t_struct(Account)_storage": {
+                    "encoding": "inplace", "label": "struct Account", "numberOfBytes": "64",
+                    "members": [
+                        {"label": "balance", "offset": 0, "slot": "0", "type": "t_uint256"},
+                        {"label": "nonce", "offset": 0, "slot": "1", "type": "t_uint64"}
+                    ]
+                }
+            }
+        }
+    }"#;
+    let path = std::env::temp_dir().join("test_storage_layout.json");
+    fs::write(&path, layout).unwrap();
+    let layout = StorageLayout::from_file(path.to_str().unwrap()).unwrap();
+
+    let int = |value: u64| Uint256::from_u64(value);
+    let owner = Uint256::from_string_hex("abcdef").unwrap();
+    let stranger = Uint256::from_string_hex("1234").unwrap();
+    let account = Uint256::avm_hash2(&owner, &int(3));
+    let history = int(4).avm_hash();
+
+    let mut storage = HashMap::new();
+    storage.insert(int(0), int(1000));
+    // owner, then delta as -2, then paused
+    let packed = owner.shift_left(16).add(&int(0xfe00)).add(&int(1));
+    storage.insert(int(1), packed);
+    let name = Uint256::from_bytes(b"token")
+        .shift_left(8 * 27)
+        .add(&int(10));
+    storage.insert(int(2), name);
+    storage.insert(account.clone(), int(250));
+    storage.insert(account.add(&int(1)), int(3));
+    storage.insert(int(4), int(5));
+    storage.insert(history.clone(), int(7).shift_left(64).add(&int(6)));
+    storage.insert(history.add(&int(1)), int(9));
+    storage.insert(int(99), int(42));
+
+    let decoded = layout.decode(&storage, &[stranger, owner.clone()]);
+    let vars: BTreeMap<_, _> = decoded.vars.into_iter().collect();
+    let owner = format!("{:?}", owner.to_h160());
+    assert_eq!(vars["total"], "1000");
+    assert_eq!(vars["paused"], "true");
+    assert_eq!(vars["delta"], "-2");
+    assert_eq!(vars["owner"], owner);
+    assert_eq!(vars["name"], "\"token\"");
+    assert_eq!(vars[&format!("balances[{}].balance", owner)], "250");
+    assert_eq!(vars[&format!("balances[{}].nonce", owner)], "3");
+    assert_eq!(vars["history.length"], "5");
+    assert_eq!(vars["history[1]"], "7");
+    assert_eq!(vars["history[4]"], "9");
+    // keys without entries aren't shown, and slots no variable accounts for are kept raw
+    assert_eq!(vars.len(), 13);
+    assert_eq!(
+        decoded.unknown.into_iter().collect::<Vec<_>>(),
+        vec![(int(99), int(42))]
+    );
+}
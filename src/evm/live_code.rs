@@ -1,4 +1,4 @@
-use crate::evm::{builtin_contract_path, AbiForContract};
+use crate::evm::{builtin_contract_path, AbiForContract, Tx};
 use crate::run::Machine;
 use crate::uint256::Uint256;
 
@@ -66,15 +66,11 @@ impl ArbosTest {
             callvalue.clone(),
             false,
         );
-        let _tx_id = machine.runtime_env.insert_tx_message(
-            caller_addr,
-            Uint256::from_usize(1_000_000_000),
-            None,
-            callee_addr,
-            callvalue,
-            &calldata,
-            false,
-        );
+        let _tx_id = Tx::call(callee_addr)
+            .calldata(calldata)
+            .value(callvalue)
+            .gas(Uint256::from_usize(1_000_000_000))
+            .send(&mut machine.runtime_env, caller_addr);
         let num_logs_before = machine.runtime_env.get_all_receipt_logs().len();
         let num_sends_before = machine.runtime_env.get_all_sends().len();
         let _arbgas_used = if self.debug {
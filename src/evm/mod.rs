@@ -5,7 +5,7 @@
 use crate::evm::abi::FunctionTable;
 use crate::evm::abi::{ArbAddressTable, ArbFunctionTable, ArbSys};
 use crate::evm::preinstalled_contracts::_ArbInfo;
-use crate::run::{load_from_file, load_from_file_and_env, RuntimeEnvironment};
+use crate::run::{_booted_arbos, load_from_file, load_from_file_and_env, RuntimeEnvironment};
 use crate::uint256::Uint256;
 
 #[cfg(test)]
@@ -21,8 +21,18 @@ use std::path::Path;
 use crate::compile::miniconstants::init_constant_table;
 pub use abi::{builtin_contract_path, contract_path, AbiForContract};
 pub use benchmarks::make_benchmarks;
+pub use devnode::run_dev_node;
+pub use doctor::run_doctor;
 pub use evmtest::run_evm_tests;
+pub use examples::{run_example, EXAMPLES};
+pub use explore::Explorer;
+#[cfg(feature = "tui")]
+pub use explore::ExplorerTui;
+pub use gasparity::check_gas_parity;
+pub use harness::ArbosHarness;
+pub use layout::StorageLayout;
 use std::option::Option::None;
+pub use tx::Tx;
 
 #[cfg(test)]
 use std::collections::HashMap;
@@ -31,9 +41,19 @@ pub mod abi;
 mod benchmarks;
 #[cfg(test)]
 mod bls;
+mod devnode;
+mod doctor;
 mod evmtest;
+mod examples;
+mod explore;
+mod gasparity;
+mod harness;
+mod layout;
 mod live_code;
 pub mod preinstalled_contracts;
+#[cfg(test)]
+mod sequencer;
+mod tx;
 
 #[derive(Clone)]
 pub struct CallInfo<'a> {
@@ -147,8 +167,7 @@ pub fn evm_tx_with_deposit(
     _profile: bool,
 ) -> Result<bool, ethabi::Error> {
     use std::convert::TryFrom;
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let my_addr = Uint256::from_usize(1025);
 
@@ -223,8 +242,7 @@ pub fn evm_tx_with_deposit(
 
 #[cfg(test)]
 pub fn evm_block_num_consistency_test(debug: bool) -> Result<(), ethabi::Error> {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let my_addr = Uint256::from_usize(1025);
 
@@ -428,8 +446,7 @@ pub fn evm_test_function_table_access(
 
 #[cfg(test)]
 pub fn basic_evm_add_test(log_to: Option<&Path>, debug: bool) -> Result<(), ethabi::Error> {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let arbos_test = ArbosTest::new(debug);
 
@@ -464,8 +481,7 @@ pub fn basic_evm_add_test(log_to: Option<&Path>, debug: bool) -> Result<(), etha
 
 #[cfg(test)]
 pub fn arbos_ethcall_test(log_to: Option<&Path>, debug: bool) -> Result<(), ethabi::Error> {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let arbos_test = ArbosTest::new(debug);
     let arbinfo = _ArbInfo::_new(debug);
@@ -590,8 +606,7 @@ pub fn underfunded_nested_call_test(
     log_to: Option<&Path>,
     debug: bool,
 ) -> Result<(), ethabi::Error> {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let mut contract = AbiForContract::new_from_file(&test_contract_path("Underfunded"))?;
     if contract
@@ -639,8 +654,7 @@ pub fn underfunded_nested_call_test(
 
 #[cfg(test)]
 pub fn evm_test_callback(log_to: Option<&Path>, debug: bool) -> Result<(), ethabi::Error> {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let mut contract = AbiForContract::new_from_file(&test_contract_path("Callback"))?;
     if contract
@@ -976,8 +990,7 @@ pub fn evm_direct_deploy_add(log_to: Option<&Path>, debug: bool) {
 
 #[cfg(test)]
 pub fn evm_test_extcodesize_of_constructor(log_to: Option<&Path>) {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     match AbiForContract::new_from_file(&test_contract_path("ExtCodeSizeTest")) {
         Ok(mut contract) => {
@@ -1074,8 +1087,7 @@ pub fn _evm_pay_eoa_from_contract(log_to: Option<&Path>, debug: bool) {
 
 #[cfg(test)]
 pub fn evm_test_payment_in_constructor(log_to: Option<&Path>, debug: bool) {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let my_addr = Uint256::from_usize(1025);
     machine.runtime_env.insert_eth_deposit_message(
@@ -1323,8 +1335,7 @@ pub fn evm_direct_deploy_and_call_add(log_to: Option<&Path>, debug: bool) {
 #[cfg(test)]
 pub fn evm_test_contract_call(log_to: Option<&Path>, debug: bool) {
     use std::convert::TryFrom;
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let my_addr = Uint256::from_usize(1025);
     let contract = match AbiForContract::new_from_file(&test_contract_path("Add")) {
@@ -1460,8 +1471,7 @@ fn evm_reverter_factory_test() {
 }
 
 fn _evm_reverter_factory_test_impl() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let _contract = match AbiForContract::new_from_file(&test_contract_path("ReverterFactory")) {
         Ok(mut contract) => {
@@ -1501,15 +1511,10 @@ pub fn evm_payment_to_empty_address(log_to: Option<&Path>, debug: bool) {
         Uint256::from_u64(20000),
         true,
     );
-    let tx_id = machine.runtime_env.insert_tx_message(
-        my_addr,
-        Uint256::from_u64(1000000000),
-        None,
-        dest_addr,
-        Uint256::from_u64(10000),
-        &vec![],
-        false,
-    );
+    let tx_id = Tx::call(dest_addr)
+        .value(Uint256::from_u64(10000))
+        .gas(Uint256::from_u64(1000000000))
+        .send(&mut machine.runtime_env, my_addr);
 
     let _ = if debug {
         machine.debug(None)
@@ -1540,15 +1545,10 @@ pub fn evm_eval_sha256(log_to: Option<&Path>, debug: bool) {
 
     let my_addr = Uint256::from_u64(1025);
 
-    let tx_id = machine.runtime_env.insert_tx_message(
-        my_addr,
-        Uint256::from_u64(10000000),
-        None,
-        Uint256::from_u64(2), // sha256 precompile
-        Uint256::from_u64(0),
-        &vec![0xCCu8],
-        false,
-    );
+    let tx_id = Tx::call(Uint256::from_u64(2)) // sha256 precompile
+        .calldata(vec![0xCCu8])
+        .gas(Uint256::from_u64(10000000))
+        .send(&mut machine.runtime_env, my_addr);
 
     let _ = if debug {
         machine.debug(None)
@@ -1617,15 +1617,10 @@ fn _evm_ecpairing_precompile_test_one(calldata: &str, result: bool, debug: bool)
     let calldata = hex::decode(calldata).unwrap();
     assert_eq!(calldata.len() % (6 * 32), 0);
 
-    let tx_id = machine.runtime_env.insert_tx_message(
-        my_addr,
-        Uint256::from_u64(1000000000),
-        None,
-        Uint256::from_u64(8), // ecpairing precompile
-        Uint256::from_u64(0),
-        &calldata,
-        false,
-    );
+    let tx_id = Tx::call(Uint256::from_u64(8)) // ecpairing precompile
+        .calldata(calldata)
+        .gas(Uint256::from_u64(1000000000))
+        .send(&mut machine.runtime_env, my_addr);
 
     let _ = if debug {
         machine.debug(None)
@@ -1650,19 +1645,13 @@ fn _evm_ecpairing_precompile_test_one(calldata: &str, result: bool, debug: bool)
 
 #[cfg(test)]
 pub fn evm_eval_ripemd160(log_to: Option<&Path>, debug: bool) {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let my_addr = Uint256::from_u64(1025);
-    let tx_id = machine.runtime_env.insert_tx_message(
-        my_addr,
-        Uint256::from_u64(1000000000),
-        None,
-        Uint256::from_u64(3), // ripemd160 precompile
-        Uint256::from_u64(0),
-        &vec![0x61u8],
-        false,
-    );
+    let tx_id = Tx::call(Uint256::from_u64(3)) // ripemd160 precompile
+        .calldata(vec![0x61u8])
+        .gas(Uint256::from_u64(1000000000))
+        .send(&mut machine.runtime_env, my_addr);
 
     let _ = if debug {
         machine.debug(None)
@@ -1701,8 +1690,7 @@ fn evm_bad_receipt_revert_test() {
 }
 
 fn _evm_bad_receipt_revert_test_impl() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let my_addr = Uint256::from_u64(1025);
 
@@ -1728,15 +1716,11 @@ fn _evm_bad_receipt_revert_test_impl() {
     let _ = machine.run(None);
     let total_receipts_before = machine.runtime_env.get_all_receipt_logs().len();
 
-    let txid = machine.runtime_env.insert_tx_message(
-        my_addr,
-        Uint256::zero(),
-        None,
-        add_contract.address,
-        Uint256::one(),
-        &[],
-        true,
-    );
+    let txid = Tx::call(add_contract.address)
+        .value(Uint256::one())
+        .gas(Uint256::zero())
+        .with_deposit()
+        .send(&mut machine.runtime_env, my_addr);
     assert!(txid != receipts[0].get_request_id());
     let _ = machine.run(None);
 
@@ -1756,8 +1740,7 @@ fn evm_test_constructor_recursion() {
 }
 
 pub fn _test_constructor_recursion() -> Result<(), ethabi::Error> {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let my_addr = Uint256::from_usize(1025);
 
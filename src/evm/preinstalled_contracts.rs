@@ -1,12 +1,15 @@
 use super::*;
 #[cfg(test)]
 use crate::evm::live_code::ArbosTest;
+use crate::evm::Tx;
 use crate::run::runtime_env::{_inverse_remap_l1_sender_address, remap_l1_sender_address};
-use crate::run::{load_from_file, Machine, RuntimeEnvironment};
+use crate::run::{_booted_arbos, load_from_file, Machine, RuntimeEnvironment, TestAccounts};
 use crate::uint256::Uint256;
 use crate::upload::CodeUploader;
 use ethers_core::utils::keccak256;
 use ethers_signers::{Signer, Wallet};
+use std::fs::File;
+use std::io::{self, Write};
 use std::path::Path;
 
 pub struct _ArbInfo {
@@ -912,19 +915,123 @@ fn _param_id_from_name(name: &str) -> Uint256 {
     Uint256::from_bytes(&keccak256(name.as_bytes()))
 }
 
-pub struct _ArbGasInfo<'a> {
+/// Gas prices in wei, as ArbGasInfo reports them for some aggregator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PricesInWei {
+    pub per_l2_tx: Uint256,
+    /// A zero byte of calldata is 4 units, and a nonzero byte 16
+    pub per_l1_calldata_unit: Uint256,
+    pub per_storage_allocation: Uint256,
+    pub per_arbgas_base: Uint256,
+    pub per_arbgas_congestion: Uint256,
+    pub per_arbgas_total: Uint256,
+}
+
+impl PricesInWei {
+    fn from_uints(prices: Vec<Uint256>) -> Self {
+        let mut prices = prices.into_iter();
+        let mut next = || prices.next().unwrap();
+        PricesInWei {
+            per_l2_tx: next(),
+            per_l1_calldata_unit: next(),
+            per_storage_allocation: next(),
+            per_arbgas_base: next(),
+            per_arbgas_congestion: next(),
+            per_arbgas_total: next(),
+        }
+    }
+}
+
+/// The prices of the non-computational parts of a tx in ArbGas, as ArbGasInfo reports them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PricesInArbGas {
+    pub per_l2_tx: Uint256,
+    pub per_l1_calldata_unit: Uint256,
+    pub per_storage_allocation: Uint256,
+}
+
+impl PricesInArbGas {
+    fn from_uints(prices: Vec<Uint256>) -> Self {
+        let mut prices = prices.into_iter();
+        let mut next = || prices.next().unwrap();
+        PricesInArbGas {
+            per_l2_tx: next(),
+            per_l1_calldata_unit: next(),
+            per_storage_allocation: next(),
+        }
+    }
+}
+
+/// The limits ArbOS's gas accounting works within.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GasAccountingParams {
+    pub speed_limit_per_second: Uint256,
+    pub gas_pool_max: Uint256,
+    pub max_tx_gas_limit: Uint256,
+}
+
+/// What fees were at some point of a chain's history, for seeing how they change over time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeeSample {
+    pub block_num: Uint256,
+    pub timestamp: Uint256,
+    pub prices_in_wei: PricesInWei,
+    pub prices_in_arbgas: PricesInArbGas,
+    pub l1_gas_price_estimate: Uint256,
+}
+
+impl FeeSample {
+    const CSV_HEADER: &'static str = "block,timestamp,wei_per_l2_tx,wei_per_l1_calldata_unit,\
+        wei_per_storage_allocation,wei_per_arbgas_base,wei_per_arbgas_congestion,\
+        wei_per_arbgas_total,arbgas_per_l2_tx,arbgas_per_l1_calldata_unit,\
+        arbgas_per_storage_allocation,l1_gas_price_estimate";
+
+    fn csv_row(&self) -> String {
+        let wei = &self.prices_in_wei;
+        let arbgas = &self.prices_in_arbgas;
+        let fields = [
+            &self.block_num,
+            &self.timestamp,
+            &wei.per_l2_tx,
+            &wei.per_l1_calldata_unit,
+            &wei.per_storage_allocation,
+            &wei.per_arbgas_base,
+            &wei.per_arbgas_congestion,
+            &wei.per_arbgas_total,
+            &arbgas.per_l2_tx,
+            &arbgas.per_l1_calldata_unit,
+            &arbgas.per_storage_allocation,
+            &self.l1_gas_price_estimate,
+        ];
+        let fields: Vec<_> = fields.iter().map(|field| field.to_string()).collect();
+        fields.join(",")
+    }
+}
+
+/// Writes samples to path as a csv with a row per sample, ready to be plotted.
+pub fn write_fee_history(samples: &[FeeSample], path: &Path) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "{}", FeeSample::CSV_HEADER)?;
+    for sample in samples {
+        writeln!(file, "{}", sample.csv_row())?;
+    }
+    Ok(())
+}
+
+/// Queries ArbGasInfo, the precompile reporting gas prices and the parameters of gas accounting.
+pub struct ArbGasInfo<'a> {
     pub contract_abi: AbiForContract,
     _wallet: &'a Wallet,
     my_address: Uint256,
     debug: bool,
 }
 
-impl<'a> _ArbGasInfo<'a> {
-    pub fn _new(wallet: &'a Wallet, debug: bool) -> Self {
+impl<'a> ArbGasInfo<'a> {
+    pub fn new(wallet: &'a Wallet, debug: bool) -> Self {
         let mut contract_abi =
             AbiForContract::new_from_file(&builtin_contract_path("ArbGasInfo")).unwrap();
         contract_abi.bind_interface_to_address(Uint256::from_u64(108));
-        _ArbGasInfo {
+        ArbGasInfo {
             contract_abi,
             _wallet: wallet,
             my_address: Uint256::from_bytes(wallet.address().as_bytes()),
@@ -932,124 +1039,91 @@ impl<'a> _ArbGasInfo<'a> {
         }
     }
 
-    pub fn _get_prices_in_wei_with_aggregator(
+    /// Gets the prices in wei of the caller's preferred aggregator, or the default one if they
+    /// haven't picked one.
+    pub fn prices_in_wei(&self, machine: &mut Machine) -> Result<PricesInWei, ethabi::Error> {
+        let prices = self.call_for_uints(machine, "getPricesInWei", &[], 6)?;
+        Ok(PricesInWei::from_uints(prices))
+    }
+
+    pub fn _prices_in_wei_with_aggregator(
         &self,
         machine: &mut Machine,
         aggregator: Uint256,
-    ) -> Result<(Uint256, Uint256, Uint256, Uint256, Uint256, Uint256), ethabi::Error> {
-        let (receipts, _sends) = self.contract_abi.call_function(
-            self.my_address.clone(),
+    ) -> Result<PricesInWei, ethabi::Error> {
+        let prices = self.call_for_uints(
+            machine,
             "getPricesInWeiWithAggregator",
             &[ethabi::Token::Address(aggregator.to_h160())],
-            machine,
-            Uint256::zero(),
-            self.debug,
+            6,
         )?;
+        Ok(PricesInWei::from_uints(prices))
+    }
 
-        if receipts.len() != 1 {
-            return Err(ethabi::Error::from("wrong number of receipts"));
-        }
-
-        if receipts[0].succeeded() {
-            let return_vals = ethabi::decode(
-                &[
-                    ethabi::ParamType::Uint(256),
-                    ethabi::ParamType::Uint(256),
-                    ethabi::ParamType::Uint(256),
-                    ethabi::ParamType::Uint(256),
-                    ethabi::ParamType::Uint(256),
-                    ethabi::ParamType::Uint(256),
-                ],
-                &receipts[0].get_return_data(),
-            )?;
-
-            match (
-                &return_vals[0],
-                &return_vals[1],
-                &return_vals[2],
-                &return_vals[3],
-                &return_vals[4],
-                &return_vals[5],
-            ) {
-                (
-                    ethabi::Token::Uint(ui0),
-                    ethabi::Token::Uint(ui1),
-                    ethabi::Token::Uint(ui2),
-                    ethabi::Token::Uint(ui3),
-                    ethabi::Token::Uint(ui4),
-                    ethabi::Token::Uint(ui5),
-                ) => Ok((
-                    Uint256::from_u256(&ui0),
-                    Uint256::from_u256(&ui1),
-                    Uint256::from_u256(&ui2),
-                    Uint256::from_u256(&ui3),
-                    Uint256::from_u256(&ui4),
-                    Uint256::from_u256(&ui5),
-                )),
-                _ => panic!(),
-            }
-        } else {
-            Err(ethabi::Error::from(format!(
-                "tx failed: {}",
-                receipts[0]._get_return_code_text()
-            )))
-        }
+    /// Gets the prices in ArbGas of the caller's preferred aggregator, or the default one if they
+    /// haven't picked one.
+    pub fn prices_in_arbgas(&self, machine: &mut Machine) -> Result<PricesInArbGas, ethabi::Error> {
+        let prices = self.call_for_uints(machine, "getPricesInArbGas", &[], 3)?;
+        Ok(PricesInArbGas::from_uints(prices))
     }
 
-    pub fn _get_prices_in_arbgas_with_aggregator(
+    pub fn _prices_in_arbgas_with_aggregator(
         &self,
         machine: &mut Machine,
         aggregator: Uint256,
-    ) -> Result<(Uint256, Uint256, Uint256), ethabi::Error> {
-        let (receipts, _sends) = self.contract_abi.call_function(
-            self.my_address.clone(),
+    ) -> Result<PricesInArbGas, ethabi::Error> {
+        let prices = self.call_for_uints(
+            machine,
             "getPricesInArbGasWithAggregator",
             &[ethabi::Token::Address(aggregator.to_h160())],
-            machine,
-            Uint256::zero(),
-            self.debug,
+            3,
         )?;
-
-        if receipts.len() != 1 {
-            return Err(ethabi::Error::from("wrong number of receipts"));
-        }
-
-        if receipts[0].succeeded() {
-            let return_vals = ethabi::decode(
-                &[
-                    ethabi::ParamType::Uint(256),
-                    ethabi::ParamType::Uint(256),
-                    ethabi::ParamType::Uint(256),
-                ],
-                &receipts[0].get_return_data(),
-            )?;
-
-            match (&return_vals[0], &return_vals[1], &return_vals[2]) {
-                (ethabi::Token::Uint(ui0), ethabi::Token::Uint(ui1), ethabi::Token::Uint(ui2)) => {
-                    Ok((
-                        Uint256::from_u256(&ui0),
-                        Uint256::from_u256(&ui1),
-                        Uint256::from_u256(&ui2),
-                    ))
-                }
-                _ => panic!(),
-            }
-        } else {
-            Err(ethabi::Error::from(format!(
-                "tx failed: {}",
-                receipts[0]._get_return_code_text()
-            )))
-        }
+        Ok(PricesInArbGas::from_uints(prices))
     }
 
-    pub fn _get_gas_accounting_params(
+    pub fn gas_accounting_params(
+        &self,
+        machine: &mut Machine,
+    ) -> Result<GasAccountingParams, ethabi::Error> {
+        let mut params = self
+            .call_for_uints(machine, "getGasAccountingParams", &[], 3)?
+            .into_iter();
+        let mut next = || params.next().unwrap();
+        Ok(GasAccountingParams {
+            speed_limit_per_second: next(),
+            gas_pool_max: next(),
+            max_tx_gas_limit: next(),
+        })
+    }
+
+    pub fn l1_gas_price_estimate(&self, machine: &mut Machine) -> Result<Uint256, ethabi::Error> {
+        let estimate = self.call_for_uints(machine, "getL1GasPriceEstimate", &[], 1)?;
+        Ok(estimate[0].clone())
+    }
+
+    /// Gets the prices the caller would pay in the machine's current block.
+    pub fn sample(&self, machine: &mut Machine) -> Result<FeeSample, ethabi::Error> {
+        Ok(FeeSample {
+            block_num: machine.runtime_env.current_block_num.clone(),
+            timestamp: machine.runtime_env.current_timestamp.clone(),
+            prices_in_wei: self.prices_in_wei(machine)?,
+            prices_in_arbgas: self.prices_in_arbgas(machine)?,
+            l1_gas_price_estimate: self.l1_gas_price_estimate(machine)?,
+        })
+    }
+
+    /// Calls func with args, expecting it to return count uints.
+    fn call_for_uints(
         &self,
         machine: &mut Machine,
-    ) -> Result<(Uint256, Uint256, Uint256), ethabi::Error> {
+        func: &str,
+        args: &[ethabi::Token],
+        count: usize,
+    ) -> Result<Vec<Uint256>, ethabi::Error> {
         let (receipts, _sends) = self.contract_abi.call_function(
             self.my_address.clone(),
-            "getGasAccountingParams",
-            &[],
+            func,
+            args,
             machine,
             Uint256::zero(),
             self.debug,
@@ -1058,33 +1132,21 @@ impl<'a> _ArbGasInfo<'a> {
         if receipts.len() != 1 {
             return Err(ethabi::Error::from("wrong number of receipts"));
         }
-
-        if receipts[0].succeeded() {
-            let return_vals = ethabi::decode(
-                &[
-                    ethabi::ParamType::Uint(256),
-                    ethabi::ParamType::Uint(256),
-                    ethabi::ParamType::Uint(256),
-                ],
-                &receipts[0].get_return_data(),
-            )?;
-
-            match (&return_vals[0], &return_vals[1], &return_vals[2]) {
-                (ethabi::Token::Uint(ui0), ethabi::Token::Uint(ui1), ethabi::Token::Uint(ui2)) => {
-                    Ok((
-                        Uint256::from_u256(&ui0),
-                        Uint256::from_u256(&ui1),
-                        Uint256::from_u256(&ui2),
-                    ))
-                }
-                _ => panic!(),
-            }
-        } else {
-            Err(ethabi::Error::from(format!(
+        if !receipts[0].succeeded() {
+            return Err(ethabi::Error::from(format!(
                 "tx failed: {}",
                 receipts[0]._get_return_code_text()
-            )))
+            )));
         }
+
+        let types = vec![ethabi::ParamType::Uint(256); count];
+        ethabi::decode(&types, &receipts[0].get_return_data())?
+            .into_iter()
+            .map(|token| match token {
+                ethabi::Token::Uint(ui) => Ok(Uint256::from_u256(&ui)),
+                _ => Err(ethabi::Error::from("expected a uint")),
+            })
+            .collect()
     }
 }
 
@@ -1571,15 +1633,10 @@ pub fn _evm_payment_to_self(log_to: Option<&Path>, debug: bool) -> Result<(), et
     let balance = arbinfo._get_balance(&mut machine, &remapped_addr)?;
     assert_eq!(balance, Uint256::from_u64(20000));
 
-    let tx_id = machine.runtime_env.insert_tx_message(
-        my_addr.clone(),
-        Uint256::from_u64(1000000000),
-        None,
-        remapped_addr.clone(),
-        Uint256::from_u64(10000),
-        &vec![],
-        false,
-    );
+    let tx_id = Tx::call(remapped_addr.clone())
+        .value(Uint256::from_u64(10000))
+        .gas(Uint256::from_u64(1000000000))
+        .send(&mut machine.runtime_env, my_addr.clone());
 
     let _ = if debug {
         machine.debug(None)
@@ -1726,15 +1783,10 @@ pub fn _evm_run_with_gas_charging(
     let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
     machine.start_at_zero(true);
 
-    let wallet = machine.runtime_env.new_wallet();
-    let my_addr = Uint256::from_bytes(wallet.address().as_bytes());
+    let accounts = TestAccounts::new(&mut machine.runtime_env);
+    let alice = accounts.alice();
 
-    machine.runtime_env.insert_eth_deposit_message(
-        my_addr.clone(),
-        my_addr.clone(),
-        funding.clone(),
-        true,
-    );
+    machine.runtime_env.fund(alice, funding.clone());
     let _gas_used = if debug {
         machine.debug(None)
     } else {
@@ -1772,7 +1824,7 @@ pub fn _evm_run_with_gas_charging(
     }
 
     // turn on gas charging
-    let arbowner = _ArbOwner::_new(&wallet, false);
+    let arbowner = _ArbOwner::_new(&alice.wallet, false);
     arbowner._set_fees_enabled(&mut machine, true, true)?;
     machine
         .runtime_env
@@ -1780,7 +1832,7 @@ pub fn _evm_run_with_gas_charging(
 
     println!("Function call ...");
     let (logs, sends) = pc_contract.call_function(
-        my_addr.clone(),
+        alice.address.clone(),
         "deposit",
         &[],
         &mut machine,
@@ -1800,7 +1852,7 @@ pub fn _evm_run_with_gas_charging(
     }
 
     let (logs, sends) = pc_contract.call_function(
-        my_addr,
+        alice.address.clone(),
         "transferFib",
         &[
             ethabi::Token::Address(ethabi::Address::from_low_u64_be(1025)),
@@ -1930,8 +1982,7 @@ fn test_arb_fair_gas_price_list() {
 }
 
 fn _evm_test_arb_fair_gas_price_list() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let wallet = machine.runtime_env.new_wallet();
     let _my_addr = Uint256::from_bytes(wallet.address().as_bytes());
@@ -2063,71 +2114,65 @@ pub fn _evm_test_arbgasinfo(log_to: Option<&Path>, debug: bool) -> Result<(), et
     let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
     machine.start_at_zero(true);
 
-    let wallet = machine.runtime_env.new_wallet();
-    let my_addr = Uint256::from_bytes(wallet.address().as_bytes());
+    let accounts = TestAccounts::new(&mut machine.runtime_env);
+    let alice = accounts.alice();
 
-    let arbowner = _ArbOwner::_new(&wallet, debug);
-    let arbgasinfo = _ArbGasInfo::_new(&wallet, debug);
+    let arbowner = _ArbOwner::_new(&alice.wallet, debug);
+    let arbgasinfo = ArbGasInfo::new(&alice.wallet, debug);
     let arbaggregator = _ArbAggregator::_new(debug);
 
-    machine.runtime_env.insert_eth_deposit_message(
-        my_addr.clone(),
-        my_addr.clone(),
-        Uint256::_from_eth(100),
-        true,
-    );
+    machine.runtime_env.fund(alice, Uint256::_from_eth(100));
     let _ = if debug {
         machine.debug(None)
     } else {
         machine.run(None)
     };
 
-    let (l2tx, l1calldata, storage, basegas, conggas, totalgas) =
-        arbgasinfo._get_prices_in_wei_with_aggregator(&mut machine, Uint256::zero())?;
-    assert!(l2tx.is_zero());
-    assert!(l1calldata.is_zero());
-    assert!(storage.is_zero());
-    assert!(basegas.is_zero());
-    assert!(conggas.is_zero());
-    assert_eq!(basegas.add(&conggas), totalgas);
+    let prices = arbgasinfo._prices_in_wei_with_aggregator(&mut machine, Uint256::zero())?;
+    assert!(prices.per_l2_tx.is_zero());
+    assert!(prices.per_l1_calldata_unit.is_zero());
+    assert!(prices.per_storage_allocation.is_zero());
+    assert!(prices.per_arbgas_base.is_zero());
+    assert!(prices.per_arbgas_congestion.is_zero());
+    assert_eq!(
+        prices.per_arbgas_base.add(&prices.per_arbgas_congestion),
+        prices.per_arbgas_total
+    );
 
     arbowner._set_fees_enabled(&mut machine, true, true)?;
     machine
         .runtime_env
         ._advance_time(Uint256::one(), None, true);
 
-    let (l2tx, l1calldata, storage, basegas, conggas, totalgas) =
-        arbgasinfo._get_prices_in_wei_with_aggregator(&mut machine, Uint256::zero())?;
-    println!(
-        "L2 tx {}, L1 calldata {}, L2 storage {}, base gas {}, congestion gas {}, total gas {}",
-        l2tx, l1calldata, storage, basegas, conggas, totalgas
-    );
-    assert_eq!(l2tx, Uint256::from_u64(690000000000000));
-    assert_eq!(l1calldata, Uint256::from_u64(172500000000));
-    assert_eq!(storage, Uint256::from_u64(300000000000000));
-    assert_eq!(basegas, Uint256::from_u64(1500000000));
-    assert!(conggas.is_zero());
-    assert_eq!(basegas.add(&conggas), totalgas);
-
-    let (l2tx, l1calldata, storage) =
-        arbgasinfo._get_prices_in_arbgas_with_aggregator(&mut machine, Uint256::zero())?;
-    println!(
-        "L2 tx / ag {}, L1 calldata / ag {}, L2 storage / ag {}",
-        l2tx, l1calldata, storage
+    let prices = arbgasinfo._prices_in_wei_with_aggregator(&mut machine, Uint256::zero())?;
+    println!("{:?}", prices);
+    assert_eq!(prices.per_l2_tx, Uint256::from_u64(690000000000000));
+    assert_eq!(prices.per_l1_calldata_unit, Uint256::from_u64(172500000000));
+    assert_eq!(
+        prices.per_storage_allocation,
+        Uint256::from_u64(300000000000000)
     );
-    assert_eq!(l2tx, Uint256::from_u64(460000));
-    assert_eq!(l1calldata, Uint256::from_u64(115));
-    assert_eq!(storage, Uint256::from_u64(200000));
-
-    let (speed_limit, gas_pool_max, tx_gas_limit) =
-        arbgasinfo._get_gas_accounting_params(&mut machine)?;
-    println!(
-        "speed limit {}, pool max {}, tx gas limit {}",
-        speed_limit, gas_pool_max, tx_gas_limit
+    assert_eq!(prices.per_arbgas_base, Uint256::from_u64(1500000000));
+    assert!(prices.per_arbgas_congestion.is_zero());
+    assert_eq!(
+        prices.per_arbgas_base.add(&prices.per_arbgas_congestion),
+        prices.per_arbgas_total
     );
-    assert_eq!(speed_limit, Uint256::from_u64(400_000));
-    assert_eq!(gas_pool_max, Uint256::from_u64(288_000_000));
-    assert_eq!(tx_gas_limit, Uint256::from_u64(8_000_000));
+
+    // alice hasn't picked an aggregator, so she'd use the default one
+    assert_eq!(arbgasinfo.prices_in_wei(&mut machine)?, prices);
+
+    let prices = arbgasinfo._prices_in_arbgas_with_aggregator(&mut machine, Uint256::zero())?;
+    println!("{:?}", prices);
+    assert_eq!(prices.per_l2_tx, Uint256::from_u64(460000));
+    assert_eq!(prices.per_l1_calldata_unit, Uint256::from_u64(115));
+    assert_eq!(prices.per_storage_allocation, Uint256::from_u64(200000));
+
+    let params = arbgasinfo.gas_accounting_params(&mut machine)?;
+    println!("{:?}", params);
+    assert_eq!(params.speed_limit_per_second, Uint256::from_u64(400_000));
+    assert_eq!(params.gas_pool_max, Uint256::from_u64(288_000_000));
+    assert_eq!(params.max_tx_gas_limit, Uint256::from_u64(8_000_000));
 
     let agg_addr = Uint256::from_u64(777);
     let fee = arbaggregator
@@ -2159,8 +2204,7 @@ pub fn _evm_test_arbgasinfo(log_to: Option<&Path>, debug: bool) -> Result<(), et
 
 #[cfg(test)]
 pub fn evm_test_rate_control(log_to: Option<&Path>, debug: bool) -> Result<(), ethabi::Error> {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let wallet = machine.runtime_env.new_wallet();
     let my_addr = Uint256::from_bytes(wallet.address().as_bytes());
@@ -2550,8 +2594,7 @@ fn test_arb_statistics() {
 }
 
 fn _test_arb_stats() -> Result<(), ethabi::Error> {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let arbstats = _ArbStatistics::_new(false);
 
@@ -2574,8 +2617,7 @@ fn test_allowed_senders() {
 }
 
 fn _evm_test_allowed_senders() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
     let wallet = machine.runtime_env.new_wallet();
 
     let arbowner = _ArbOwner::_new(&wallet, false);
@@ -2636,21 +2678,15 @@ fn _evm_test_allowed_senders() {
 
 #[test]
 fn test_eventual_congestion_reject() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
-    let wallet = machine.runtime_env.new_wallet();
-    let my_address = Uint256::from_bytes(wallet.address().as_bytes());
+    let mut machine = _booted_arbos();
+    let accounts = TestAccounts::new(&mut machine.runtime_env);
+    let alice = accounts.alice();
 
-    let arbowner = _ArbOwner::_new(&wallet, false);
+    let arbowner = _ArbOwner::_new(&alice.wallet, false);
     let _ = arbowner
         ._set_fees_enabled(&mut machine, true, true)
         .unwrap();
-    machine.runtime_env.insert_eth_deposit_message(
-        my_address.clone(),
-        my_address.clone(),
-        Uint256::_from_eth(1000),
-        true,
-    );
+    machine.runtime_env.fund(alice, Uint256::_from_eth(1000));
     let _ = machine.run(None);
     machine
         .runtime_env
@@ -2660,7 +2696,7 @@ fn test_eventual_congestion_reject() {
     for _ in 0..1200 {
         let res_code = match arbtest._burn_arb_gas(
             &mut machine,
-            my_address.clone(),
+            alice.address.clone(),
             Uint256::from_u64(2_000_000),
         ) {
             Ok(rc) => rc,
@@ -2677,22 +2713,16 @@ fn test_eventual_congestion_reject() {
 
 #[test]
 fn test_congestion_price_adjustment() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
-    let wallet = machine.runtime_env.new_wallet();
-    let my_address = Uint256::from_bytes(wallet.address().as_bytes());
+    let mut machine = _booted_arbos();
+    let accounts = TestAccounts::new(&mut machine.runtime_env);
+    let alice = accounts.alice();
 
-    let arbowner = _ArbOwner::_new(&wallet, false);
-    let arbgasinfo = _ArbGasInfo::_new(&wallet, false);
+    let arbowner = _ArbOwner::_new(&alice.wallet, false);
+    let arbgasinfo = ArbGasInfo::new(&alice.wallet, false);
     let _ = arbowner
         ._set_fees_enabled(&mut machine, true, true)
         .unwrap();
-    machine.runtime_env.insert_eth_deposit_message(
-        my_address.clone(),
-        my_address.clone(),
-        Uint256::_from_eth(1000),
-        true,
-    );
+    machine.runtime_env.fund(alice, Uint256::_from_eth(1000));
     let _ = machine.run(None);
     machine
         .runtime_env
@@ -2702,9 +2732,9 @@ fn test_congestion_price_adjustment() {
     assert_eq!(
         arbgasinfo
             //._get_prices_in_wei(&mut machine, randomish_address.clone())  preserve this for later integration
-            ._get_prices_in_wei_with_aggregator(&mut machine, my_address.clone())
+            ._prices_in_wei_with_aggregator(&mut machine, alice.address.clone())
             .unwrap()
-            .4,
+            .per_arbgas_congestion,
         Uint256::zero()
     );
 
@@ -2713,7 +2743,7 @@ fn test_congestion_price_adjustment() {
     for _ in 0..1200 {
         let res_code = match arbtest._burn_arb_gas(
             &mut machine,
-            my_address.clone(),
+            alice.address.clone(),
             Uint256::from_u64(2_000_000),
         ) {
             Ok(rc) => rc,
@@ -2734,38 +2764,32 @@ fn test_congestion_price_adjustment() {
 
     let prices = arbgasinfo
         //._get_prices_in_wei(&mut machine, randomish_address.clone())  preserve this for later integration
-        ._get_prices_in_wei_with_aggregator(&mut machine, my_address.clone())
+        ._prices_in_wei_with_aggregator(&mut machine, alice.address.clone())
         .unwrap();
-    assert!(prices.4 > Uint256::zero());
+    assert!(prices.per_arbgas_congestion > Uint256::zero());
 
     machine
         .runtime_env
         ._advance_time(Uint256::from_u64(48), Some(Uint256::from_u64(720)), false);
     let prices2 = arbgasinfo
         //._get_prices_in_wei(&mut machine, randomish_address.clone())  preserve this for later integration
-        ._get_prices_in_wei_with_aggregator(&mut machine, my_address.clone())
+        ._prices_in_wei_with_aggregator(&mut machine, alice.address.clone())
         .unwrap();
-    assert_eq!(prices2.4, Uint256::zero());
+    assert_eq!(prices2.per_arbgas_congestion, Uint256::zero());
 
     machine.write_coverage("test_congestion_price_adjustment".to_string());
 }
 
 #[test]
 fn test_set_gas_price_estimate() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
-    let wallet = machine.runtime_env.new_wallet();
-    let my_address = Uint256::from_bytes(wallet.address().as_bytes());
+    let mut machine = _booted_arbos();
+    let accounts = TestAccounts::new(&mut machine.runtime_env);
+    let alice = accounts.alice();
 
-    let arbowner = _ArbOwner::_new(&wallet, false);
-    let arbgasinfo = _ArbGasInfo::_new(&wallet, false);
+    let arbowner = _ArbOwner::_new(&alice.wallet, false);
+    let arbgasinfo = ArbGasInfo::new(&alice.wallet, false);
 
-    machine.runtime_env.insert_eth_deposit_message(
-        my_address.clone(),
-        my_address.clone(),
-        Uint256::_from_eth(1000),
-        true,
-    );
+    machine.runtime_env.fund(alice, Uint256::_from_eth(1000));
     let _ = machine.run(None);
 
     arbowner
@@ -2776,13 +2800,13 @@ fn test_set_gas_price_estimate() {
     let new_storage_price = new_gas_price.mul(&Uint256::from_u64(2_000_000_000_000));
 
     let storage_price = arbgasinfo
-        ._get_prices_in_wei_with_aggregator(&mut machine, my_address.clone())
+        ._prices_in_wei_with_aggregator(&mut machine, alice.address.clone())
         .unwrap()
-        .2;
+        .per_storage_allocation;
     assert!(storage_price != new_storage_price);
 
     arbowner
-        ._set_l1_gas_price_estimate(&mut machine, new_gas_price)
+        ._set_l1_gas_price_estimate(&mut machine, new_gas_price.clone())
         .unwrap();
 
     machine
@@ -2790,10 +2814,14 @@ fn test_set_gas_price_estimate() {
         ._advance_time(Uint256::one(), None, false);
 
     let storage_price = arbgasinfo
-        ._get_prices_in_wei_with_aggregator(&mut machine, my_address.clone())
+        ._prices_in_wei_with_aggregator(&mut machine, alice.address.clone())
         .unwrap()
-        .2;
+        .per_storage_allocation;
     assert_eq!(storage_price, new_storage_price);
+    assert_eq!(
+        arbgasinfo.l1_gas_price_estimate(&mut machine).unwrap(),
+        new_gas_price
+    );
 
     machine.write_coverage("test_set_gas_price_estimate".to_string());
 }
@@ -0,0 +1,197 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides a sequencer that signs and posts batches of transactions, for tests of how ArbOS
+//! authenticates what's in them.
+//!
+//! ArbOS doesn't check a signature over a batch as a whole. The L1 inbox vouches for the address
+//! that posted it, and only batches posted by the chain's default aggregator may end L2 blocks.
+//! Each transaction in a batch is authenticated by its own signature instead, so one with a bad
+//! signature is dropped whoever posts it.
+
+use crate::evm::tx::{append_to_batch, TxBuilder};
+use crate::run::RuntimeEnvironment;
+use crate::uint256::Uint256;
+use ethers_signers::{Signer, Wallet};
+
+/// A sequencer, identified by the address of its key.
+pub struct Sequencer {
+    key: Wallet,
+}
+
+impl Sequencer {
+    pub fn new(key: Wallet) -> Self {
+        Sequencer { key }
+    }
+
+    /// The L1 address the sequencer posts batches from.
+    pub fn address(&self) -> Uint256 {
+        Uint256::from_bytes(self.key.address().as_bytes())
+    }
+
+    /// Makes an environment whose chain trusts this sequencer to end blocks.
+    pub fn new_env(&self) -> RuntimeEnvironment {
+        RuntimeEnvironment::_new_with_sequencer(self.address())
+    }
+
+    /// Starts a batch to be posted by this sequencer.
+    pub fn new_batch(&self) -> SequencerBatch {
+        SequencerBatch {
+            poster: self.address(),
+            msg: vec![3u8],
+        }
+    }
+}
+
+/// A batch of signed transactions being built, along with the address that will post it.
+pub struct SequencerBatch {
+    poster: Uint256,
+    msg: Vec<u8>,
+}
+
+impl SequencerBatch {
+    /// Signs a transaction with wallet and adds it to the batch, returning its hash.
+    pub fn add(&mut self, env: &mut RuntimeEnvironment, tx: TxBuilder, wallet: &Wallet) -> Uint256 {
+        let (msg, tx_hash) = tx.sign(env, wallet);
+        append_to_batch(&mut self.msg, msg);
+        tx_hash
+    }
+
+    /// Signs a transaction with wallet, then corrupts the signature before adding it to the
+    /// batch, returning the hash the transaction would have had.
+    pub fn add_tampered(
+        &mut self,
+        env: &mut RuntimeEnvironment,
+        tx: TxBuilder,
+        wallet: &Wallet,
+    ) -> Uint256 {
+        let (mut msg, tx_hash) = tx.sign(env, wallet);
+        // the signature's r, s, and v end the message, and an r of zero recovers no signer
+        let r = msg.len() - 65;
+        for byte in &mut msg[r..r + 32] {
+            *byte = 0;
+        }
+        append_to_batch(&mut self.msg, msg);
+        tx_hash
+    }
+
+    /// Adds the empty message that ends an L2 block, if it's last in a batch the default
+    /// aggregator posts.
+    pub fn end_block(&mut self) {
+        append_to_batch(&mut self.msg, vec![]);
+    }
+
+    /// Has the batch posted by another address, which ArbOS shouldn't trust to end blocks.
+    pub fn posted_by(self, poster: Uint256) -> Self {
+        SequencerBatch { poster, ..self }
+    }
+
+    /// Posts the batch to the L1 inbox.
+    pub fn post(self, env: &mut RuntimeEnvironment) {
+        env.insert_batch_message(self.poster, &self.msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::Tx;
+    use crate::run::{load_from_file_and_env, Machine};
+    use ethers_core::rand::rngs::StdRng;
+    use ethers_core::rand::SeedableRng;
+    use std::path::Path;
+
+    fn sequencer() -> Sequencer {
+        Sequencer::new(Wallet::new(&mut StdRng::seed_from_u64(7)))
+    }
+
+    fn start(sequencer: &Sequencer) -> Machine {
+        let mut machine =
+            load_from_file_and_env(Path::new("arb_os/arbos.mexe"), sequencer.new_env());
+        machine.start_at_zero(false);
+        let _ = machine.run(None);
+        machine
+    }
+
+    /// Runs the batches posted since the machine last ran, returning the request ids of the
+    /// receipts they produced and the number of blocks they ended.
+    fn run(machine: &mut Machine) -> (Vec<Uint256>, usize) {
+        let receipts = machine.runtime_env.get_all_receipt_logs().len();
+        let blocks = machine.runtime_env.blocks().len();
+        let _ = machine.run(None);
+        let ids = machine.runtime_env.get_all_receipt_logs()[receipts..]
+            .iter()
+            .map(|receipt| {
+                assert!(receipt.succeeded());
+                receipt.get_request_id()
+            })
+            .collect();
+        (ids, machine.runtime_env.blocks().len() - blocks)
+    }
+
+    #[test]
+    fn test_sequencer_batch_ends_block() {
+        let sequencer = sequencer();
+        let mut machine = start(&sequencer);
+        let env = &mut machine.runtime_env;
+        let wallet = env.new_wallet();
+
+        let mut batch = sequencer.new_batch();
+        let first = batch.add(env, Tx::call(Uint256::from_u64(2)), &wallet);
+        let second = batch.add(env, Tx::call(Uint256::from_u64(3)), &wallet);
+        batch.end_block();
+        batch.post(env);
+
+        let (ids, blocks) = run(&mut machine);
+        assert_eq!(ids, vec![first.clone(), second.clone()]);
+        assert_eq!(blocks, 1);
+        let block = machine.runtime_env.blocks().pop().unwrap();
+        let in_block: Vec<_> = block.receipts.iter().map(|r| r.get_request_id()).collect();
+        assert_eq!(in_block, vec![first, second]);
+    }
+
+    #[test]
+    fn test_unauthorized_batch_cannot_end_block() {
+        let sequencer = sequencer();
+        let mut machine = start(&sequencer);
+        let env = &mut machine.runtime_env;
+        let wallet = env.new_wallet();
+        let impostor = Uint256::from_bytes(env.new_wallet().address().as_bytes());
+
+        // its txs carry their own signatures, so they're run, but the block stays open
+        let mut batch = sequencer.new_batch().posted_by(impostor);
+        let tx = batch.add(env, Tx::call(Uint256::from_u64(2)), &wallet);
+        batch.end_block();
+        batch.post(env);
+        assert_eq!(run(&mut machine), (vec![tx.clone()], 0));
+
+        // the sequencer's end of block closes it, with the impostor's tx inside
+        let mut batch = sequencer.new_batch();
+        batch.end_block();
+        batch.post(&mut machine.runtime_env);
+        assert_eq!(run(&mut machine), (vec![], 1));
+        let block = machine.runtime_env.blocks().pop().unwrap();
+        assert_eq!(block.receipts.last().unwrap().get_request_id(), tx);
+    }
+
+    #[test]
+    fn test_tampered_tx_is_dropped() {
+        let sequencer = sequencer();
+        let mut machine = start(&sequencer);
+        let env = &mut machine.runtime_env;
+        let wallet = env.new_wallet();
+        let other = env.new_wallet();
+
+        let mut batch = sequencer.new_batch();
+        let tampered = batch.add_tampered(env, Tx::call(Uint256::from_u64(2)), &wallet);
+        let signed = batch.add(env, Tx::call(Uint256::from_u64(3)), &other);
+        batch.end_block();
+        batch.post(env);
+
+        let (ids, blocks) = run(&mut machine);
+        assert_ne!(tampered, signed);
+        assert_eq!(ids, vec![signed]);
+        assert_eq!(blocks, 1);
+    }
+}
@@ -0,0 +1,213 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides a builder for the transactions tests and tools send to ArbOS.
+
+use crate::run::RuntimeEnvironment;
+use crate::uint256::Uint256;
+use ethers_core::types::TransactionRequest;
+use ethers_core::utils::keccak256;
+use ethers_signers::{Signer, Wallet};
+
+/// Starts building transactions.
+pub struct Tx;
+
+impl Tx {
+    /// Starts a transaction calling the contract at an address.
+    pub fn call(to: Uint256) -> TxBuilder {
+        TxBuilder {
+            to,
+            calldata: vec![],
+            value: Uint256::zero(),
+            gas: Uint256::from_u64(100_000_000),
+            deposit: false,
+        }
+    }
+
+    /// Starts a transaction deploying a contract, whose code and constructor args are the calldata.
+    pub fn deploy() -> TxBuilder {
+        Tx::call(Uint256::zero())
+    }
+}
+
+/// A transaction being built. Fields that aren't set keep their defaults: no calldata, no value,
+/// and a gas limit of 100 million. It bids the gas price of the environment it's sent in.
+#[derive(Clone, Debug)]
+pub struct TxBuilder {
+    to: Uint256,
+    calldata: Vec<u8>,
+    value: Uint256,
+    gas: Uint256,
+    deposit: bool,
+}
+
+impl TxBuilder {
+    pub fn calldata(mut self, calldata: impl Into<Vec<u8>>) -> Self {
+        self.calldata = calldata.into();
+        self
+    }
+
+    /// Sets the wei sent along with the call.
+    pub fn value(mut self, value: Uint256) -> Self {
+        self.value = value;
+        self
+    }
+
+    /// Sets the gas limit.
+    pub fn gas(mut self, gas: Uint256) -> Self {
+        self.gas = gas;
+        self
+    }
+
+    /// Has the value deposited from L1 along with the transaction, so that the sender needn't
+    /// already hold it. Only unsigned transactions can carry deposits.
+    pub fn with_deposit(mut self) -> Self {
+        self.deposit = true;
+        self
+    }
+
+    /// Sends the transaction unsigned from an L2 account, returning its request id.
+    pub fn send(self, env: &mut RuntimeEnvironment, sender: Uint256) -> Uint256 {
+        let mut msg = vec![0u8];
+        let seq_num = env.get_seq_num(&sender, true);
+        msg.extend(self.gas.to_bytes_be());
+        msg.extend(env.get_gas_price().to_bytes_be());
+        msg.extend(seq_num.to_bytes_be());
+        msg.extend(self.to.to_bytes_be());
+        msg.extend(self.value.to_bytes_be());
+        msg.extend(&self.calldata);
+        self.insert(env, sender, &msg)
+    }
+
+    /// Sends the transaction unsigned from an L1 contract, returning its request id. Transactions
+    /// from contracts don't have sequence numbers.
+    pub fn send_from_contract(self, env: &mut RuntimeEnvironment, sender: Uint256) -> Uint256 {
+        let mut msg = vec![1u8];
+        msg.extend(self.gas.to_bytes_be());
+        msg.extend(env.get_gas_price().to_bytes_be());
+        msg.extend(self.to.to_bytes_be());
+        msg.extend(self.value.to_bytes_be());
+        msg.extend(&self.calldata);
+        self.insert(env, sender, &msg)
+    }
+
+    /// Signs the transaction with wallet, returning the compressed message carrying it along with
+    /// its hash.
+    pub fn sign(self, env: &mut RuntimeEnvironment, wallet: &Wallet) -> (Vec<u8>, Uint256) {
+        self.signed_message(env, wallet, false)
+    }
+
+    /// Signs the transaction with wallet and sends it from the wallet's address, returning its
+    /// hash.
+    pub fn send_signed(self, env: &mut RuntimeEnvironment, wallet: &Wallet) -> Uint256 {
+        let sender = Uint256::from_bytes(wallet.address().as_bytes());
+        let (msg, tx_hash) = self.sign(env, wallet);
+        env.insert_l2_message(sender, &msg);
+        tx_hash
+    }
+
+    /// Signs the transaction with wallet and appends it to a batch, returning its hash.
+    pub fn add_to_batch(
+        self,
+        env: &mut RuntimeEnvironment,
+        batch: &mut Vec<u8>,
+        wallet: &Wallet,
+    ) -> Uint256 {
+        let (msg, tx_hash) = self.sign(env, wallet);
+        append_to_batch(batch, msg);
+        tx_hash
+    }
+
+    /// Asks an aggregator what the transaction would cost, without running it for real. Since
+    /// nothing changes, the wallet's sequence number isn't used up.
+    #[cfg(test)]
+    pub fn estimate_gas(self, env: &mut RuntimeEnvironment, aggregator: Uint256, wallet: &Wallet) {
+        let mut batch = vec![3u8];
+        batch.extend(aggregator.to_bytes_be());
+        batch.extend(self.gas.to_bytes_be());
+        let (msg, _) = self.signed_message(env, wallet, true);
+        append_to_batch(&mut batch, msg);
+        env.insert_l2_message_for_gas_estimation(aggregator, &batch);
+    }
+
+    fn insert(&self, env: &mut RuntimeEnvironment, sender: Uint256, msg: &[u8]) -> Uint256 {
+        match self.deposit {
+            true => env.insert_l2_message_with_deposit(sender, msg),
+            false => env.insert_l2_message(sender, msg),
+        }
+    }
+
+    fn signed_message(
+        self,
+        env: &mut RuntimeEnvironment,
+        wallet: &Wallet,
+        is_gas_estimation: bool,
+    ) -> (Vec<u8>, Uint256) {
+        let sender = Uint256::from_bytes(wallet.address().as_bytes());
+        let seq_num = env.get_seq_num(&sender, !is_gas_estimation);
+        let gas_price = env.get_gas_price();
+
+        let mut msg = vec![7u8, 0xffu8];
+        msg.extend(seq_num.rlp_encode());
+        msg.extend(gas_price.rlp_encode());
+        msg.extend(self.gas.rlp_encode());
+        msg.extend(env.compressor.compress_address(self.to.clone()));
+        msg.extend(env.compressor.compress_token_amount(self.value.clone()));
+        msg.extend(&self.calldata);
+
+        let tx_for_signing = TransactionRequest::new()
+            .from(sender.to_h160())
+            .to(self.to.to_h160())
+            .gas(self.gas.to_u256())
+            .gas_price(gas_price.to_u256())
+            .value(self.value.to_u256())
+            .data(self.calldata)
+            .nonce(seq_num.to_u256());
+        let tx = wallet.sign_transaction(tx_for_signing).unwrap();
+
+        msg.extend(Uint256::from_u256(&tx.r).to_bytes_be());
+        msg.extend(Uint256::from_u256(&tx.s).to_bytes_be());
+        msg.push((tx.v.as_u64() % 2) as u8);
+
+        (msg, Uint256::from_bytes(&keccak256(tx.rlp().as_ref())))
+    }
+}
+
+/// Appends a message to a batch, prefixed by its length.
+pub(super) fn append_to_batch(batch: &mut Vec<u8>, msg: Vec<u8>) {
+    batch.extend(Uint256::from_usize(msg.len()).rlp_encode());
+    batch.extend(msg);
+}
+
+#[test]
+fn test_tx_builder() {
+    let mut env = RuntimeEnvironment::default();
+    let to = Uint256::from_u64(2);
+    let sender = Uint256::from_u64(1025);
+    let queued = env.l1_inbox.len();
+
+    let first = Tx::call(to.clone())
+        .calldata(vec![0xcc])
+        .send(&mut env, sender.clone());
+    let second = Tx::call(to.clone())
+        .value(Uint256::one())
+        .gas(Uint256::from_u64(21_000))
+        .with_deposit()
+        .send(&mut env, sender.clone());
+    Tx::call(to.clone()).send_from_contract(&mut env, sender.clone());
+    assert_ne!(first, second);
+    assert_eq!(env.l1_inbox.len(), queued + 3);
+    assert_eq!(env.get_seq_num(&sender, false), Uint256::from_u64(2));
+
+    // estimating gas doesn't use up a sequence number, so the batched tx is the wallet's second
+    let wallet = env.new_wallet();
+    let signer = Uint256::from_bytes(wallet.address().as_bytes());
+    let sent = Tx::call(to.clone()).send_signed(&mut env, &wallet);
+    Tx::call(to.clone()).estimate_gas(&mut env, Uint256::zero(), &wallet);
+    let mut batch = env.new_batch();
+    let batched = Tx::call(to).add_to_batch(&mut env, &mut batch, &wallet);
+    assert_ne!(sent, batched);
+    assert_eq!(env.get_seq_num(&signer, false), Uint256::from_u64(2));
+    assert!(batch.len() > 1);
+}
@@ -287,7 +287,7 @@ fn get_globals_and_version_from_file(
             path.to_str().unwrap_or("")
         ))
     })?;
-    let globals: LinkedProgram = serde_json::from_str(&s).map_err(|error| {
+    let globals = LinkedProgram::from_json(&s).map_err(|error| {
         GenCodeError::new(format!(
             "Failed to deserialize file \"{}\"\n{}",
             Color::red(path.to_str().unwrap_or("")),
@@ -295,7 +295,7 @@ fn get_globals_and_version_from_file(
         ))
     })?;
 
-    let type_tree = globals.type_tree.into_type_tree();
+    let type_tree = globals.type_tree.clone().into_type_tree();
 
     let mut state: (Vec<_>, Rc<RefCell<HashSet<(Type, String)>>>) =
         (vec![], Rc::new(RefCell::new(HashSet::new())));
@@ -307,11 +307,11 @@ fn get_globals_and_version_from_file(
         if global.id != usize::max_value() {
             let mut tipe = global.tipe;
             if let Type::Nominal(file_path, id, _) = tipe {
-                tipe = type_tree
-                    .get(&(file_path.clone(), id))
-                    .cloned()
-                    .unwrap_or((Type::Any, "fail1".to_string()))
-                    .0;
+                tipe = globals
+                    .type_tree
+                    .get(&file_path, id)
+                    .map(|entry| entry.tipe.clone())
+                    .unwrap_or(Type::Any);
             }
             tipe.recursive_apply(replace_nominal, &type_tree, &mut state);
 
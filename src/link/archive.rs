@@ -0,0 +1,255 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides library archives, the .mlib files `compile --module` writes, which bundle the compiled
+//! modules of a library so that programs using it can link its funcs rather than compile them.
+//! An archive also holds what linking needs of the program it was compiled from, so that `link`
+//! can build that program from its archives alone, on a machine other than the one compiling it.
+
+use super::CallSites;
+use crate::compile::{
+    path_display, ClosureAssignments, CompileError, CompiledFolder, CompiledFunc, DebugInfo,
+    FileInfo, FrameSize, GlobalVar, Type, TypeTree,
+};
+use crate::console::Color;
+use crate::mavm::{Instruction, LabelId, Opcode};
+use crate::stringtable::StringId;
+use crate::uint256::Uint256;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Changes whenever the layout of archives does, so that older archives are rejected.
+const ARCHIVE_FORMAT: u64 = 3;
+
+/// The compiled modules of a library, along with the types they use and the funcs they define.
+#[derive(Serialize, Deserialize)]
+pub struct LibraryArchive {
+    format_version: u64,
+    pub modules: Vec<ArchivedModule>,
+    /// The entries of the type tree the modules were compiled with, sorted by path and id
+    pub type_tree: Vec<((Vec<String>, usize), (Type, String))>,
+    /// The funcs the archive defines, by path, keyed to their label ids
+    pub symbols: BTreeMap<String, LabelId>,
+    /// The globals of the program the modules were compiled from, at their offsets in it
+    pub globals: Vec<GlobalVar>,
+    /// The constants describing the interface of that program, keyed by name
+    pub interface: BTreeMap<String, Uint256>,
+    /// The source files of that program, keyed by file id
+    pub file_info_chart: BTreeMap<u64, FileInfo>,
+}
+
+/// The funcs of a compiled module, which a program can reuse as long as the module compiles the
+/// same way for it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ArchivedModule {
+    pub path: Vec<String>,
+    /// Covers everything codegen reads of the module, as found by the compiler
+    pub fingerprint: u64,
+    /// The module's globals, at the offsets its funcs were compiled with
+    pub globals: Vec<GlobalVar>,
+    pub funcs: Vec<ArchivedFunc>,
+}
+
+/// A compiled func, less what the program linking it supplies.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ArchivedFunc {
+    pub name: String,
+    pub code: Vec<Instruction>,
+    pub captures: ClosureAssignments,
+    pub frame_size: FrameSize,
+    pub unique_id: LabelId,
+    pub debug_info: DebugInfo,
+    pub tipe: Type,
+    pub calls: CallSites,
+    /// Whether the func is from a module the manifest marks debug-only
+    pub debug_only: bool,
+    /// Whether the func is marked `#[override]`, which its debug info doesn't keep
+    #[serde(default)]
+    pub overrides: bool,
+    /// Whether the func is marked `#[init]`, which its debug info doesn't keep
+    #[serde(default)]
+    pub init: bool,
+}
+
+impl LibraryArchive {
+    /// Bundles the compiled funcs of a program by the modules they're from, along with the
+    /// program's globals, interface, and source files.
+    pub fn new(compiled: &CompiledFolder, file_info_chart: &BTreeMap<u64, FileInfo>) -> Self {
+        let (funcs, globals, interface) = compiled;
+        let mut modules: BTreeMap<&Vec<String>, ArchivedModule> = BTreeMap::new();
+        let mut type_tree = BTreeMap::new();
+        let mut symbols = BTreeMap::new();
+        for func in funcs {
+            let module = modules.entry(&func.path).or_insert_with(|| ArchivedModule {
+                path: func.path.clone(),
+                fingerprint: func.fingerprint,
+                globals: func.globals.clone(),
+                funcs: vec![],
+            });
+            module.funcs.push(ArchivedFunc {
+                name: func.name.clone(),
+                code: func.code.clone(),
+                captures: func.captures.clone(),
+                frame_size: func.frame_size,
+                unique_id: func.unique_id,
+                debug_info: func.debug_info,
+                tipe: func.tipe.clone(),
+                calls: func.calls.clone(),
+                debug_only: func.debug_only,
+                overrides: func.debug_info.attributes.overrides,
+                init: func.debug_info.attributes.init,
+            });
+            type_tree.extend(func.type_tree.clone());
+            symbols.insert(
+                format!("{}::{}", path_display(&func.path), func.name),
+                func.unique_id,
+            );
+        }
+        LibraryArchive {
+            format_version: ARCHIVE_FORMAT,
+            modules: modules.into_iter().map(|(_, module)| module).collect(),
+            type_tree: type_tree.into_iter().collect(),
+            symbols,
+            globals: globals.clone(),
+            interface: interface.clone(),
+            file_info_chart: file_info_chart.clone(),
+        }
+    }
+
+    /// Unpacks archives into the funcs, globals, and interface of the program they were compiled
+    /// from, along with its source files, for that program to be linked. Several archives can be
+    /// linked together if they were compiled from the same program, as when its modules were
+    /// compiled on different machines, with the modules they share taken once.
+    pub fn unpack(
+        archives: &[LibraryArchive],
+    ) -> Result<(CompiledFolder, BTreeMap<u64, FileInfo>), CompileError> {
+        let link_error = |description: String| CompileError::new("Link error", description, vec![]);
+        let first = archives
+            .first()
+            .ok_or_else(|| link_error(String::from("There are no objects to link")))?;
+
+        let mut modules: BTreeMap<&Vec<String>, &ArchivedModule> = BTreeMap::new();
+        let mut type_tree = TypeTree::new();
+        let mut interface = BTreeMap::new();
+        let mut file_info_chart = BTreeMap::new();
+        for archive in archives {
+            if archive.globals != first.globals {
+                return Err(link_error(String::from(
+                    "The objects were compiled from programs with different globals, so they \
+                     can't be linked together",
+                )));
+            }
+            for module in &archive.modules {
+                match modules.insert(&module.path, module) {
+                    Some(other) if other.fingerprint != module.fingerprint => {
+                        return Err(link_error(format!(
+                            "Module {} was compiled differently for two of the objects",
+                            Color::red(path_display(&module.path)),
+                        )))
+                    }
+                    _ => {}
+                }
+            }
+            type_tree.extend(archive.type_tree.iter().cloned());
+            interface.extend(archive.interface.clone());
+            file_info_chart.extend(archive.file_info_chart.clone());
+        }
+
+        let mut funcs = vec![];
+        for module in modules.values() {
+            // the module was compiled for this program, so its globals are where it expects
+            let globals = module
+                .globals
+                .iter()
+                .map(|global| (global.id, global.clone()))
+                .collect();
+            funcs.extend(module.instantiate(&globals, &type_tree).ok_or_else(|| {
+                link_error(format!(
+                    "Module {} refers to a global it doesn't have an offset for",
+                    Color::red(path_display(&module.path)),
+                ))
+            })?);
+        }
+        Ok(((funcs, first.globals.clone(), interface), file_info_chart))
+    }
+
+    /// Finds the archived module at path, if it was compiled to the fingerprint given.
+    pub fn module(&self, path: &[String], fingerprint: u64) -> Option<&ArchivedModule> {
+        self.modules
+            .iter()
+            .find(|module| module.path == path && module.fingerprint == fingerprint)
+    }
+
+    pub fn write(&self, output: &mut dyn Write) -> io::Result<()> {
+        let bytes = bincode::serialize(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        output.write_all(&bytes)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        // the version leads the archive, so it can be checked before the rest is trusted
+        let version: u64 =
+            bincode::deserialize(&bytes).map_err(|e| format!("malformed archive: {}", e))?;
+        if version != ARCHIVE_FORMAT {
+            return Err(format!(
+                "archive has format version {}, but version {} is needed",
+                version, ARCHIVE_FORMAT
+            ));
+        }
+        bincode::deserialize(&bytes).map_err(|e| format!("malformed archive: {}", e))
+    }
+}
+
+impl ArchivedModule {
+    /// Converts the module's funcs into those of a program whose globals for the module are
+    /// given, moving their reads and writes of globals to the offsets the program assigned. Fails
+    /// if the program lacks one of the globals the module was compiled with.
+    pub fn instantiate(
+        &self,
+        globals: &HashMap<StringId, GlobalVar>,
+        type_tree: &TypeTree,
+    ) -> Option<Vec<CompiledFunc>> {
+        let mut offsets = HashMap::new();
+        for archived in &self.globals {
+            let global = globals.get(&archived.id)?;
+            offsets.insert(archived.offset?, global.offset?);
+        }
+        let relocate = |offset: usize| offsets.get(&offset).copied();
+
+        let mut funcs = vec![];
+        for func in &self.funcs {
+            let mut code = func.code.clone();
+            for insn in &mut code {
+                insn.opcode = match insn.opcode {
+                    Opcode::GetGlobalVar(offset) => Opcode::GetGlobalVar(relocate(offset)?),
+                    Opcode::SetGlobalVar(offset) => Opcode::SetGlobalVar(relocate(offset)?),
+                    opcode => opcode,
+                };
+            }
+            let mut debug_info = func.debug_info;
+            debug_info.attributes.overrides = func.overrides;
+            debug_info.attributes.init = func.init;
+            funcs.push(CompiledFunc {
+                name: func.name.clone(),
+                path: self.path.clone(),
+                code,
+                captures: func.captures.clone(),
+                frame_size: func.frame_size,
+                globals: globals.values().cloned().collect(),
+                type_tree: type_tree.clone(),
+                unique_id: func.unique_id,
+                debug_info,
+                tipe: func.tipe.clone(),
+                calls: func.calls.clone(),
+                debug_only: func.debug_only,
+                fingerprint: self.fingerprint,
+            });
+        }
+        Some(funcs)
+    }
+}
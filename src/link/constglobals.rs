@@ -0,0 +1,170 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Propagates globals that are only ever set to one constant, by the funcs that initialize the
+//! program, as immediates. ArbOS writes many of its globals once at boot, and reading one costs a
+//! register read and a walk through the globals tuple, where an immediate costs nothing.
+
+use super::FuncGraph;
+use crate::mavm::{AVMOpcode, Instruction, LabelId, Opcode, Value};
+use petgraph::graph::NodeIndex;
+use petgraph::visit::Dfs;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Replaces reads of constant globals with their values. A global is constant when every write
+/// to it sets it to the same constant, and each is made by an init func, one marked `#[init]` that
+/// the entry reaches. Init funcs are expected to run before the rest of the program, so that reads
+/// elsewhere see the value written. Reads that could run before the write aren't propagated, and
+/// any global read by one isn't propagated at all. Those are the reads in:
+/// - the entry, and every func it reaches through what it calls up to its last call reaching an
+///   init func
+/// - every func init funcs reach
+/// - every func the error handler reaches, since an error can be raised at any time
+///
+/// A program setting the globals register itself, as ArbOS does to keep the globals of the ArbOS
+/// it upgrades from, may replace the globals init funcs set with ones they never saw, so nothing
+/// is propagated in one. Code built at runtime could set it too, so neither is anything propagated
+/// in a program building code. The func setting it is returned as the error.
+pub(super) fn propagate_const_globals(
+    funcs: &mut FuncGraph,
+    main: NodeIndex,
+    error_handler: Option<NodeIndex>,
+) -> Result<(), NodeIndex> {
+    let reached = reachable(funcs, std::iter::once(main).chain(error_handler));
+    if let Some(setter) = reached
+        .iter()
+        .copied()
+        .find(|node| funcs[*node].code.iter().any(sets_register))
+    {
+        return Err(setter);
+    }
+
+    let init: HashSet<NodeIndex> = reached
+        .iter()
+        .copied()
+        .filter(|node| funcs[*node].debug_info.attributes.init)
+        .collect();
+    if init.is_empty() {
+        return Ok(());
+    }
+
+    // everything referred to up to the entry's last reference reaching an init func runs early,
+    // though what the entry refers to after it doesn't, so the entry itself isn't a root
+    let nodes: HashMap<LabelId, NodeIndex> = funcs
+        .node_indices()
+        .map(|node| (funcs[node].unique_id, node))
+        .collect();
+    let reaches_init = |node: NodeIndex| {
+        reachable(funcs, std::iter::once(node))
+            .iter()
+            .any(|reached| init.contains(reached))
+    };
+    let referred: Vec<Vec<NodeIndex>> = funcs[main]
+        .code
+        .iter()
+        .map(|insn| {
+            insn.get_uniques()
+                .into_iter()
+                .filter_map(|id| nodes.get(&id).copied())
+                .filter(|node| *node != main)
+                .collect()
+        })
+        .collect();
+    let last_init = referred
+        .iter()
+        .rposition(|nodes| nodes.iter().any(|node| reaches_init(*node)));
+    let mut roots: Vec<NodeIndex> = error_handler.into_iter().collect();
+    if let Some(last_init) = last_init {
+        roots.extend(referred[..=last_init].iter().flatten());
+    }
+    roots.extend(init.iter().copied());
+    let mut early = reachable(funcs, roots.into_iter());
+    early.insert(main);
+
+    // each global written maps to its constant, or to None once a write isn't one
+    let mut written: BTreeMap<usize, Option<Value>> = BTreeMap::new();
+    let mut read_early = HashSet::new();
+    for node in funcs.node_indices() {
+        let code = &funcs[node].code;
+        for (index, insn) in code.iter().enumerate() {
+            match insn.opcode {
+                Opcode::SetGlobalVar(global) => {
+                    let value = index
+                        .checked_sub(1)
+                        .and_then(|prior| pushed_constant(&code[prior]))
+                        .filter(|_| init.contains(&node));
+                    let constant = written.entry(global).or_insert_with(|| value.clone());
+                    if *constant != value {
+                        *constant = None;
+                    }
+                }
+                Opcode::GetGlobalVar(global) if early.contains(&node) => {
+                    read_early.insert(global);
+                }
+                _ => {}
+            }
+        }
+    }
+    let constants: BTreeMap<usize, Value> = written
+        .into_iter()
+        .filter(|(global, _)| !read_early.contains(global))
+        .filter_map(|(global, value)| Some((global, value?)))
+        .collect();
+
+    for node in funcs.node_indices() {
+        if early.contains(&node) {
+            continue;
+        }
+        for insn in &mut funcs[node].code {
+            if let Opcode::GetGlobalVar(global) = insn.opcode {
+                if let Some(value) = constants.get(&global) {
+                    *insn = Instruction::from_opcode_imm(
+                        Opcode::AVMOpcode(AVMOpcode::Noop),
+                        value.clone(),
+                        insn.debug_info,
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether an instruction sets the globals register, or might, by building code that does.
+fn sets_register(insn: &Instruction) -> bool {
+    matches!(
+        insn.opcode,
+        Opcode::AVMOpcode(AVMOpcode::Rset | AVMOpcode::PushInsn | AVMOpcode::PushInsnImm)
+    )
+}
+
+/// The funcs reachable from roots, roots included.
+fn reachable(funcs: &FuncGraph, roots: impl Iterator<Item = NodeIndex>) -> HashSet<NodeIndex> {
+    let mut reached = HashSet::new();
+    for root in roots {
+        if reached.contains(&root) {
+            continue;
+        }
+        let mut dfs = Dfs::new(funcs, root);
+        while let Some(node) = dfs.next(funcs) {
+            reached.insert(node);
+        }
+    }
+    reached
+}
+/// The constant an instruction pushes, if it pushes one that means the same wherever it's written.
+/// Labels and codepoints are left out, since those can refer to a func's own code.
+fn pushed_constant(insn: &Instruction) -> Option<Value> {
+    fn portable(value: &Value) -> bool {
+        match value {
+            Value::Int(_) | Value::Buffer(_) => true,
+            Value::Tuple(tup) => tup.iter().all(portable),
+            Value::CodePoint(_) | Value::Label(_) => false,
+        }
+    }
+    match (&insn.opcode, &insn.immediate) {
+        (Opcode::AVMOpcode(AVMOpcode::Noop), Some(value)) if portable(value) => Some(value.clone()),
+        _ => None,
+    }
+}
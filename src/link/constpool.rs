@@ -0,0 +1,150 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Pools the large constants a linked program uses in several places, keeping one copy of each in
+//! the jump table rather than one in each instruction using it. Instructions look pooled constants
+//! up from the table as they do the labels they jump back to, so the pool is carried through
+//! upgrades along with the table. The lookups cost gas, so only constants large enough that their
+//! copies outweigh the lookups replacing them are pooled.
+
+use super::xformcode::fold_wide_tuples;
+use crate::mavm::{Instruction, Opcode, Value};
+use std::collections::HashMap;
+use std::fmt;
+
+/// About how many instructions looking a constant up from the jump table takes, which is what
+/// each use of a pooled constant costs in place of its copy.
+const LOOKUP_SIZE: usize = 8;
+
+/// The constants worth pooling, keyed by their serialized form.
+#[derive(Debug, Default)]
+pub(super) struct ConstantPool {
+    constants: HashMap<Vec<u8>, Pooled>,
+}
+
+#[derive(Debug)]
+struct Pooled {
+    /// The constant as the jump table holds it, with its wide tuples nested
+    value: Value,
+    uses: usize,
+    size: usize,
+}
+
+impl ConstantPool {
+    /// Finds the constants in code worth pooling, which are the tuples used as the immediates of
+    /// enough AVM instructions that pooling them saves space. Tuples holding labels or codepoints
+    /// are left alone, as labels are only resolved in the table as entries of their own.
+    pub(super) fn new(code: &[Instruction]) -> Self {
+        let mut found: HashMap<Vec<u8>, Pooled> = HashMap::new();
+        for insn in code {
+            let (value, size) = match (&insn.opcode, &insn.immediate) {
+                (Opcode::AVMOpcode(_), Some(value @ Value::Tuple(_))) => match size(value) {
+                    Some(size) if size > LOOKUP_SIZE => (value, size),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            found
+                .entry(key(value))
+                .or_insert_with(|| {
+                    let mut value = value.clone();
+                    fold_wide_tuples(&mut value);
+                    Pooled {
+                        value,
+                        uses: 0,
+                        size,
+                    }
+                })
+                .uses += 1;
+        }
+        found.retain(|_, pooled| (pooled.uses - 1) * pooled.size > pooled.uses * LOOKUP_SIZE);
+        ConstantPool { constants: found }
+    }
+
+    /// The key and pooled form of a constant, if it's pooled.
+    pub(super) fn get(&self, value: &Value) -> Option<(Vec<u8>, &Value)> {
+        if self.constants.is_empty() || !matches!(value, Value::Tuple(_)) {
+            return None;
+        }
+        let key = key(value);
+        let pooled = self.constants.get(&key)?;
+        Some((key, &pooled.value))
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.constants.is_empty()
+    }
+
+    /// About how many values pooling leaves out of the program.
+    fn saved(&self) -> usize {
+        self.constants
+            .values()
+            .map(|pooled| (pooled.uses - 1) * pooled.size - pooled.uses * LOOKUP_SIZE)
+            .sum()
+    }
+}
+
+impl fmt::Display for ConstantPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let uses: usize = self.constants.values().map(|pooled| pooled.uses).sum();
+        write!(
+            f,
+            "Pooled {} constants used {} times, leaving out about {} values",
+            self.constants.len(),
+            uses,
+            self.saved()
+        )
+    }
+}
+
+/// How many values make up a constant, counting each tuple and each int, if it holds only those.
+fn size(value: &Value) -> Option<usize> {
+    match value {
+        Value::Int(_) => Some(1),
+        Value::Tuple(tup) => tup
+            .iter()
+            .map(size)
+            .sum::<Option<usize>>()
+            .map(|sum| sum + 1),
+        _ => None,
+    }
+}
+
+fn key(value: &Value) -> Vec<u8> {
+    bincode::serialize(value).unwrap_or_default()
+}
+
+#[test]
+fn test_constant_pool() {
+    use crate::compile::DebugInfo;
+    use crate::mavm::AVMOpcode;
+
+    let wide = |n: u64| Value::new_tuple((0..20).map(|i| Value::from(n + i)).collect());
+    let noop = |value: Value| {
+        Instruction::from_opcode_imm(
+            Opcode::AVMOpcode(AVMOpcode::Noop),
+            value,
+            DebugInfo::default(),
+        )
+    };
+    let small = Value::new_tuple(vec![Value::from(1), Value::from(2)]);
+
+    // a wide tuple used three times is pooled, but not one used once, nor a small one
+    let mut code = vec![noop(wide(0)), noop(wide(0)), noop(wide(100)), noop(wide(0))];
+    code.extend(vec![noop(small.clone()); 5]);
+    let pool = ConstantPool::new(&code);
+    let (_, pooled) = pool.get(&wide(0)).unwrap();
+    assert!(matches!(pooled, Value::Tuple(tup) if tup.len() <= 8));
+    assert!(pool.get(&wide(100)).is_none());
+    assert!(pool.get(&small).is_none());
+    assert_eq!(pool.saved(), 2 * 21 - 3 * LOOKUP_SIZE);
+
+    // tuples holding codepoints aren't pooled
+    let mut with_codepoint = vec![Value::from(0); 20];
+    with_codepoint[0] = Value::CodePoint(crate::mavm::CodePt::new_internal(0));
+    let with_codepoint = Value::new_tuple(with_codepoint);
+    let code: Vec<_> = (0..5).map(|_| noop(with_codepoint.clone())).collect();
+    let pool = ConstantPool::new(&code);
+    assert!(pool.is_empty());
+}
@@ -0,0 +1,116 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Leaves the modules a program's manifest marks as debug-only out of release builds, so that
+//! instrumentation can't ship in them by accident.
+
+use super::FuncGraph;
+use crate::compile::{path_display, CompileError, ErrorSystem};
+use crate::console::Color;
+use petgraph::graph::NodeIndex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// How much of the program a release build left out, grouped by module.
+#[derive(Debug, Default)]
+pub struct SizeReport {
+    /// The number of funcs and instructions left out of each module
+    modules: BTreeMap<String, (usize, usize)>,
+    /// The number of instructions that were linked
+    linked: usize,
+}
+
+/// Finds the funcs a release build leaves out: those of debug-only modules, along with those only
+/// they reach. Funcs that are kept but reach a debug-only func are errors, since leaving it out
+/// would break them.
+pub(super) fn find_debug_only(
+    funcs: &FuncGraph,
+    main: NodeIndex,
+    error_system: &mut ErrorSystem,
+) -> BTreeSet<NodeIndex> {
+    let mut kept = BTreeSet::new();
+    let mut stack = vec![main];
+    while let Some(node) = stack.pop() {
+        if funcs[node].debug_only || !kept.insert(node) {
+            continue;
+        }
+        for callee in funcs.neighbors(node) {
+            if !funcs[callee].debug_only {
+                stack.push(callee);
+                continue;
+            }
+            let (caller, callee) = (&funcs[node], &funcs[callee]);
+            let location = caller
+                .calls
+                .direct
+                .get(&callee.unique_id)
+                .copied()
+                .flatten();
+            error_system.errors.push(CompileError::new(
+                "Link error",
+                format!(
+                    "Func {} reaches {} of a debug-only module, which release builds leave out",
+                    Color::red(&caller.name),
+                    Color::red(&callee.name),
+                ),
+                location
+                    .into_iter()
+                    .chain(caller.debug_info.location)
+                    .collect(),
+            ));
+        }
+    }
+
+    let mut left_out = BTreeSet::new();
+    let mut stack: Vec<_> = funcs
+        .node_indices()
+        .filter(|node| funcs[*node].debug_only)
+        .collect();
+    while let Some(node) = stack.pop() {
+        if kept.contains(&node) || !left_out.insert(node) {
+            continue;
+        }
+        stack.extend(funcs.neighbors(node));
+    }
+    left_out
+}
+
+impl SizeReport {
+    pub(super) fn new(funcs: &FuncGraph, left_out: &BTreeSet<NodeIndex>, linked: usize) -> Self {
+        let mut report = SizeReport {
+            modules: BTreeMap::new(),
+            linked,
+        };
+        for node in left_out {
+            let func = &funcs[*node];
+            let entry = report
+                .modules
+                .entry(path_display(&func.path))
+                .or_insert((0, 0));
+            entry.0 += 1;
+            entry.1 += func.code.len();
+        }
+        report
+    }
+
+    /// The number of instructions left out.
+    pub fn left_out(&self) -> usize {
+        self.modules.values().map(|(_, insns)| insns).sum()
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Left out of the release build:")?;
+        for (module, (funcs, insns)) in &self.modules {
+            writeln!(f, "    {}: {} funcs, {} instructions", module, funcs, insns)?;
+        }
+        write!(
+            f,
+            "{} instructions left out, {} linked",
+            self.left_out(),
+            self.linked
+        )
+    }
+}
@@ -0,0 +1,148 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Folds funcs whose code is identical into one. Generated accessors and the copies generics make
+//! of their helpers often compile to the same code, which the linked program need only hold once.
+
+use super::incremental::split_funcs;
+use crate::mavm::{Instruction, Label, LabelId, Opcode, Value};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// Drops each func whose code is identical to that of a func before it, sending everything that
+/// referred to it to the earlier func instead. Code is compared with the labels a func defines
+/// numbered in order, so that funcs differing only in their labels are identical. Folding funcs
+/// can make their callers identical too, so this repeats until nothing more folds.
+///
+/// Funcs with breakpoints or coverage probes are kept, so that each can still be told apart.
+pub(super) fn fold_identical_funcs(code: Vec<Instruction>) -> Vec<Instruction> {
+    let mut code = code;
+    loop {
+        let segments = split_funcs(code);
+        let mut first_with_code = HashMap::new();
+        let mut folded = HashMap::new();
+        let mut kept = vec![];
+        for segment in segments {
+            let label = match segment[0].opcode {
+                Opcode::Label(label @ Label::Func(_)) if foldable(&segment) => label,
+                _ => {
+                    kept.push(segment);
+                    continue;
+                }
+            };
+            match first_with_code.entry(canonical_code(&segment)) {
+                Entry::Occupied(first) => {
+                    folded.insert(label, *first.get());
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(label);
+                    kept.push(segment);
+                }
+            }
+        }
+
+        code = kept.into_iter().flatten().collect();
+        if folded.is_empty() {
+            return code;
+        }
+        code = code
+            .into_iter()
+            .map(|insn| relabel(insn, &|label| *folded.get(&label).unwrap_or(&label)))
+            .collect();
+    }
+}
+
+fn foldable(func: &[Instruction]) -> bool {
+    func.iter().all(|insn| {
+        let attributes = &insn.debug_info.attributes;
+        !attributes.breakpoint && !attributes.probe
+    })
+}
+
+/// The code of a func with the labels it defines numbered in order and its debug info left out,
+/// serialized so it can be compared and hashed.
+fn canonical_code(func: &[Instruction]) -> Vec<u8> {
+    let mut numbered = HashMap::new();
+    for insn in func {
+        if let Opcode::Label(label) = insn.opcode {
+            numbered.insert(label, Label::Anon(numbered.len() as LabelId));
+        }
+    }
+    let code: Vec<_> = func
+        .iter()
+        .map(|insn| {
+            let insn = relabel(insn.clone(), &|label| {
+                *numbered.get(&label).unwrap_or(&label)
+            });
+            (insn.opcode, insn.immediate)
+        })
+        .collect();
+    bincode::serialize(&code).unwrap_or_default()
+}
+
+/// Replaces each label an instruction defines or refers to with what map gives for it.
+pub(super) fn relabel(insn: Instruction, map: &dyn Fn(Label) -> Label) -> Instruction {
+    let opcode = match insn.opcode {
+        Opcode::Label(label) => Opcode::Label(map(label)),
+        Opcode::JumpTo(label) => Opcode::JumpTo(map(label)),
+        Opcode::CjumpTo(label) => Opcode::CjumpTo(map(label)),
+        opcode => opcode,
+    };
+    let immediate = insn.immediate.map(|value| relabel_value(value, map));
+    Instruction::new(opcode, immediate, insn.debug_info)
+}
+
+fn relabel_value(value: Value, map: &dyn Fn(Label) -> Label) -> Value {
+    match value {
+        Value::Label(label) => Value::Label(map(label)),
+        Value::Tuple(tup) => Value::new_tuple(
+            tup.iter()
+                .map(|value| relabel_value(value.clone(), map))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+#[test]
+fn test_fold_identical_funcs() {
+    use crate::compile::DebugInfo;
+    use crate::mavm::AVMOpcode;
+
+    let label = |label| Instruction::from_opcode(Opcode::Label(label), DebugInfo::default());
+    let jump = |opcode, label| {
+        Instruction::from_opcode_imm(
+            Opcode::AVMOpcode(opcode),
+            Value::Label(label),
+            DebugInfo::default(),
+        )
+    };
+    // a func that loops back to its own label before jumping to the func given
+    let func = |id, anon, to| {
+        vec![
+            label(Label::Func(id)),
+            label(Label::Anon(anon)),
+            jump(AVMOpcode::Cjump, Label::Anon(anon)),
+            jump(AVMOpcode::Jump, Label::Func(to)),
+        ]
+    };
+
+    // 2 and 3 are the same but for their labels, which makes 4 and 5 the same once they're folded
+    let mut code = vec![jump(AVMOpcode::Jump, Label::Func(5))];
+    code.extend(func(1, 10, 4));
+    code.extend(func(2, 20, 1));
+    code.extend(func(3, 30, 1));
+    code.extend(func(4, 40, 2));
+    code.extend(func(5, 50, 3));
+    let mut probed = func(6, 60, 1);
+    probed[2].debug_info.attributes.probe = true;
+    code.extend(probed.clone());
+
+    let mut expected = vec![jump(AVMOpcode::Jump, Label::Func(4))];
+    expected.extend(func(1, 10, 4));
+    expected.extend(func(2, 20, 1));
+    expected.extend(func(4, 40, 2));
+    expected.extend(probed);
+    assert_eq!(fold_identical_funcs(code), expected);
+}
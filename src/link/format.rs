@@ -0,0 +1,258 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Versions the mexe format, so that mexes written by older compilers are brought up to date as
+//! they're loaded, and those written by newer ones are rejected by version rather than by whatever
+//! serde makes of the fields it doesn't know.
+//!
+//! Mexes can also be written as cbor, or as json compressed with gzip. Only json mexes are
+//! migrated, as the fields cbor keys by number can't be edited as json, so cbor mexes are read
+//! only at the version they were written in.
+
+use crate::link::LinkedProgram;
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use serde_json::{Map, Value as Json};
+use std::io::Read;
+
+/// The version of the mexe format this compiler writes. Bump it whenever `LinkedProgram`, or a
+/// type within it, changes in a way older mexes can't be read as, adding a migration from the
+/// version it replaces to `MIGRATIONS`.
+pub const FORMAT_VERSION: u64 = 4;
+
+/// Mexes written before the format was versioned, which have no `format_version` field.
+const UNVERSIONED: u64 = 1;
+
+/// How to bring mexes of each older version up to the next one.
+const MIGRATIONS: [(u64, fn(&mut Map<String, Json>)); 3] = [
+    (UNVERSIONED, from_unversioned),
+    (2, without_symbols),
+    (3, without_defines),
+];
+
+/// The one field read before the rest, to know what the rest should look like.
+#[derive(Deserialize)]
+struct Versioned {
+    format_version: Option<u64>,
+}
+
+/// The bytes a gzip stream begins with.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+impl LinkedProgram {
+    /// Reads a mexe written in any format it can be read back from: json, cbor, or either of them
+    /// compressed with gzip, telling them apart by how they begin.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            let mut decompressed = vec![];
+            GzDecoder::new(bytes)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| format!("not a compressed mexe: {}", e))?;
+            return Self::from_bytes(&decompressed);
+        }
+        match bytes.iter().find(|byte| !byte.is_ascii_whitespace()) {
+            Some(b'{') => match std::str::from_utf8(bytes) {
+                Ok(json) => Self::from_json(json),
+                Err(e) => Err(format!("not a mexe: {}", e)),
+            },
+            _ => Self::from_cbor(bytes),
+        }
+    }
+
+    /// Reads a mexe from its cbor, which must be of the version this compiler writes.
+    fn from_cbor(cbor: &[u8]) -> Result<Self, String> {
+        let version = serde_cbor::from_slice::<Versioned>(cbor)
+            .map_err(|e| format!("not a mexe: {}", e))?
+            .format_version
+            .unwrap_or(UNVERSIONED);
+        if version != FORMAT_VERSION {
+            return Err(format!(
+                "format version {} isn't the {} this compiler reads, and cbor mexes aren't \
+                 migrated, so it must be reformatted as json by the compiler that wrote it",
+                version, FORMAT_VERSION
+            ));
+        }
+        serde_cbor::from_slice(cbor)
+            .map_err(|e| format!("malformed mexe of format version {}: {}", version, e))
+    }
+
+    /// Reads a mexe from its json, migrating it if it was written in an older version.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let version = serde_json::from_str::<Versioned>(json)
+            .map_err(|e| format!("not a mexe: {}", e))?
+            .format_version
+            .unwrap_or(UNVERSIONED);
+
+        if version > FORMAT_VERSION {
+            return Err(format!(
+                "format version {} is newer than the {} this compiler reads, so it must be read \
+                 by the compiler that wrote it",
+                version, FORMAT_VERSION
+            ));
+        }
+        if version == FORMAT_VERSION {
+            return serde_json::from_str(json)
+                .map_err(|e| format!("malformed mexe of format version {}: {}", version, e));
+        }
+
+        let mut fields = match serde_json::from_str(json) {
+            Ok(Json::Object(fields)) => fields,
+            _ => return Err(String::from("not a mexe: expected a json object")),
+        };
+        for from in version..FORMAT_VERSION {
+            let migrate = MIGRATIONS
+                .iter()
+                .find(|(start, _)| *start == from)
+                .map(|(_, migrate)| migrate)
+                .ok_or_else(|| format!("format version {} is too old to be read", version))?;
+            migrate(&mut fields);
+        }
+        fields.insert(String::from("format_version"), Json::from(FORMAT_VERSION));
+
+        serde_json::from_value(Json::Object(fields)).map_err(|e| {
+            format!(
+                "malformed mexe of format version {}, migrated to {}: {}",
+                version, FORMAT_VERSION, e
+            )
+        })
+    }
+}
+
+/// Fills in the fields unversioned mexes could leave out, which used to be defaulted by serde.
+fn from_unversioned(fields: &mut Map<String, Json>) {
+    let empty = || Json::Object(Map::new());
+    fields
+        .entry("arbos_version")
+        .or_insert_with(|| Json::from(0));
+    fields.entry("file_info_chart").or_insert_with(empty);
+    fields.entry("interface").or_insert_with(empty);
+}
+
+/// Gives mexes written before the symbol table an empty one, leaving their funcs unnamed.
+fn without_symbols(fields: &mut Map<String, Json>) {
+    fields.insert(String::from("symbols"), Json::Object(Map::new()));
+}
+
+/// Gives mexes written before definitions were recorded an empty set, as they were built from
+/// their constants files as written.
+fn without_defines(fields: &mut Map<String, Json>) {
+    fields.insert(String::from("defines"), Json::Object(Map::new()));
+}
+
+#[test]
+fn test_format_versions() {
+    use crate::link::SerializableTypeTree;
+    use crate::mavm::Value;
+    use std::collections::BTreeMap;
+
+    let mut program = LinkedProgram {
+        format_version: FORMAT_VERSION,
+        arbos_version: 7,
+        code: vec![],
+        static_val: Value::none(),
+        globals: vec![],
+        file_info_chart: BTreeMap::new(),
+        type_tree: SerializableTypeTree::default(),
+        interface: BTreeMap::new(),
+        symbols: BTreeMap::new(),
+        defines: BTreeMap::new(),
+    };
+    program
+        .interface
+        .insert(String::from("funcCode_a"), crate::uint256::Uint256::one());
+    let json = serde_json::to_value(&program).unwrap();
+    let loaded = LinkedProgram::from_json(&json.to_string()).unwrap();
+    assert_eq!(loaded.arbos_version, 7);
+    assert_eq!(loaded.interface, program.interface);
+
+    // mexes from before versioning are migrated, getting the fields they could leave out
+    let mut unversioned = json.clone();
+    let fields = unversioned.as_object_mut().unwrap();
+    fields.remove("format_version");
+    fields.remove("interface");
+    fields.remove("arbos_version");
+    let loaded = LinkedProgram::from_json(&unversioned.to_string()).unwrap();
+    assert_eq!(loaded.format_version, FORMAT_VERSION);
+    assert_eq!(loaded.arbos_version, 0);
+    assert!(loaded.interface.is_empty());
+
+    // as are those from before the symbol table
+    let mut before_symbols = json.clone();
+    before_symbols["format_version"] = Json::from(2);
+    before_symbols.as_object_mut().unwrap().remove("symbols");
+    let loaded = LinkedProgram::from_json(&before_symbols.to_string()).unwrap();
+    assert!(loaded.symbols.is_empty());
+
+    // versions that aren't known are errors naming the version, rather than serde errors
+    let mut newer = json.clone();
+    newer["format_version"] = Json::from(FORMAT_VERSION + 1);
+    let error = LinkedProgram::from_json(&newer.to_string()).err().unwrap();
+    assert!(error.contains(&format!("format version {}", FORMAT_VERSION + 1)));
+    let mut older = json;
+    older["format_version"] = Json::from(0);
+    assert!(LinkedProgram::from_json(&older.to_string()).is_err());
+    assert!(LinkedProgram::from_json("[1, 2]").is_err());
+}
+
+#[test]
+fn test_output_formats() {
+    use crate::compile::{DebugInfo, FileInfo};
+    use crate::link::SerializableTypeTree;
+    use crate::mavm::{AVMOpcode, Instruction, Value};
+    use std::collections::BTreeMap;
+
+    let mut program = LinkedProgram {
+        format_version: FORMAT_VERSION,
+        arbos_version: 7,
+        code: vec![Instruction::new(
+            AVMOpcode::Noop,
+            Some(Value::new_tuple(vec![Value::from(1), Value::none()])),
+            DebugInfo::default(),
+        )],
+        static_val: Value::none(),
+        globals: vec![],
+        file_info_chart: BTreeMap::new(),
+        type_tree: SerializableTypeTree::default(),
+        interface: BTreeMap::new(),
+        symbols: BTreeMap::new(),
+        defines: BTreeMap::new(),
+    };
+    program.file_info_chart.insert(
+        3,
+        FileInfo {
+            name: String::from("main.mini"),
+            path: String::new(),
+            contents: vec![],
+        },
+    );
+    let write = |program: &LinkedProgram, format: Option<&str>| {
+        let mut output = vec![];
+        program.to_output(&mut output, format);
+        output
+    };
+    let json = write(&program, None);
+
+    // each format is read back as the program written, and the compressed ones are smaller
+    for format in &["json", "cbor", "json.gz"] {
+        let written = write(&program, Some(format));
+        let loaded = LinkedProgram::from_bytes(&written).unwrap();
+        assert_eq!(write(&loaded, None), json);
+    }
+    let big = LinkedProgram {
+        code: vec![program.code[0].clone(); 1000],
+        ..program
+    };
+    let json = write(&big, None);
+    assert!(write(&big, Some("cbor")).len() < json.len());
+    assert!(write(&big, Some("json.gz")).len() < json.len() / 10);
+
+    // cbor isn't migrated, so other versions of it are errors naming the version
+    let mut newer = big;
+    newer.format_version = FORMAT_VERSION + 1;
+    let error = LinkedProgram::from_bytes(&write(&newer, Some("cbor")))
+        .err()
+        .unwrap();
+    assert!(error.contains(&format!("format version {}", FORMAT_VERSION + 1)));
+    assert!(LinkedProgram::from_bytes(&[0xff, 0x00]).is_err());
+}
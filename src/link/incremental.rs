@@ -0,0 +1,181 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the cache incremental linking keeps beside a mexe, holding the optimized code of each
+//! func from the last link. Since the optimizations after labels are fixed never reach across a
+//! func's label, a func whose code comes out of label fixing the same as last time optimizes the
+//! same too, so relinking only optimizes the funcs that changed, along with those whose lookups
+//! into the jump table it moved.
+
+use crate::compile::CompileError;
+use crate::mavm::{Instruction, Label, Opcode, StableHasher};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// The extension of the link cache written beside a mexe.
+pub const LINK_CACHE_EXTENSION: &str = "linkcache";
+
+/// Changes whenever the layout of the cache does, so that older caches are ignored.
+const LINK_CACHE_FORMAT: u64 = 2;
+
+/// Where the link cache of the mexe at path goes.
+pub fn link_cache_path(mexe: &Path) -> PathBuf {
+    mexe.with_extension(LINK_CACHE_EXTENSION)
+}
+
+/// The optimized code of each func of the last link, keyed by a stable hash of its code before
+/// optimizing and of the options it was optimized with, so that keys don't change with the Rust
+/// release the compiler is built with.
+#[derive(Serialize, Deserialize)]
+pub struct LinkCache {
+    /// Identifies the compiler that wrote the cache, as its optimizations may differ from ours
+    salt: u64,
+    segments: HashMap<u64, Vec<Instruction>>,
+}
+
+impl LinkCache {
+    /// Opens the cache at path, starting afresh if there's none the running compiler wrote. As
+    /// with the module cache, there's no cache at all if the compiler can't be identified.
+    pub fn open(path: &Path) -> Option<Self> {
+        let exe = std::env::current_exe().ok()?;
+        let meta = fs::metadata(&exe).ok()?;
+        let mut hasher = StableHasher::default();
+        LINK_CACHE_FORMAT.hash(&mut hasher);
+        exe.hash(&mut hasher);
+        meta.len().hash(&mut hasher);
+        meta.modified().ok()?.hash(&mut hasher);
+        let salt = hasher.finish();
+
+        let cached = fs::read(path)
+            .ok()
+            .and_then(|bytes| bincode::deserialize::<LinkCache>(&bytes).ok());
+        Some(match cached {
+            Some(cache) if cache.salt == salt => cache,
+            _ => LinkCache {
+                salt,
+                segments: HashMap::new(),
+            },
+        })
+    }
+
+    /// Optimizes the code of each func with optimize, in parallel, taking the code of those
+    /// optimized the same way last time from the cache instead. Code before the first func is
+    /// treated as a func of its own. Only the funcs of this link are kept for the next.
+    pub(super) fn optimize_funcs<F>(
+        &mut self,
+        code: Vec<Instruction>,
+        options: u64,
+        optimize: F,
+    ) -> Result<Vec<Instruction>, CompileError>
+    where
+        F: Fn(Vec<Instruction>) -> Result<Vec<Instruction>, CompileError> + Sync,
+    {
+        let segments: Vec<_> = split_funcs(code)
+            .into_iter()
+            .map(|segment| (segment_key(&segment, options), segment))
+            .collect();
+        let keys: Vec<u64> = segments.iter().map(|(key, _)| *key).collect();
+
+        let cached = &self.segments;
+        let optimized = segments
+            .into_par_iter()
+            .map(|(key, segment)| match cached.get(&key) {
+                Some(optimized) => Ok(optimized.clone()),
+                None => optimize(segment),
+            })
+            .collect::<Result<Vec<_>, CompileError>>()?;
+
+        let code = optimized.iter().flatten().cloned().collect();
+        self.segments = keys.into_iter().zip(optimized).collect();
+        Ok(code)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let bytes = bincode::serialize(self).map_err(|e| e.to_string())?;
+        fs::write(path, bytes).map_err(|e| e.to_string())
+    }
+}
+
+/// Splits code into the code of each func, each beginning with the func's label.
+pub(super) fn split_funcs(code: Vec<Instruction>) -> Vec<Vec<Instruction>> {
+    let mut segments: Vec<Vec<Instruction>> = vec![vec![]];
+    for insn in code {
+        if let Opcode::Label(Label::Func(_) | Label::Closure(_)) = insn.opcode {
+            segments.push(vec![]);
+        }
+        segments.last_mut().unwrap().push(insn);
+    }
+    segments.retain(|segment| !segment.is_empty());
+    segments
+}
+
+fn segment_key(segment: &[Instruction], options: u64) -> u64 {
+    let mut hasher = StableHasher::default();
+    options.hash(&mut hasher);
+    bincode::serialize(segment)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn test_link_cache() {
+    use crate::compile::DebugInfo;
+    use crate::mavm::{AVMOpcode, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let label = |label| Instruction::from_opcode(Opcode::Label(label), DebugInfo::default());
+    let push = |value: u64| {
+        Instruction::from_opcode_imm(
+            Opcode::AVMOpcode(AVMOpcode::Noop),
+            Value::from(value),
+            DebugInfo::default(),
+        )
+    };
+    let program = |second: u64| {
+        vec![
+            push(0),
+            label(Label::Func(1)),
+            push(1),
+            label(Label::Anon(2)),
+            push(2),
+            label(Label::Closure(3)),
+            push(second),
+        ]
+    };
+    let path = std::env::temp_dir().join(format!("test-{}.linkcache", std::process::id()));
+    drop(fs::remove_file(&path));
+
+    // funcs are optimized when they're new, and taken from the cache when they aren't
+    let optimized = AtomicUsize::new(0);
+    let optimize = |code: Vec<Instruction>| -> Result<_, CompileError> {
+        optimized.fetch_add(1, Ordering::Relaxed);
+        Ok(code.into_iter().rev().collect())
+    };
+    let relink = |second: u64, options: u64| {
+        let mut cache = LinkCache::open(&path).unwrap();
+        let code = cache
+            .optimize_funcs(program(second), options, optimize)
+            .unwrap();
+        cache.write(&path).unwrap();
+        code
+    };
+
+    let first = relink(3, 0);
+    assert_eq!(optimized.swap(0, Ordering::Relaxed), 3);
+    assert_eq!(first[0], push(0));
+    assert_eq!(first[1..4], [push(2), label(Label::Anon(2)), push(1)]);
+    assert_eq!(relink(3, 0), first);
+    assert_eq!(optimized.swap(0, Ordering::Relaxed), 0);
+    assert_ne!(relink(4, 0), first);
+    assert_eq!(optimized.swap(0, Ordering::Relaxed), 1);
+    relink(4, 1);
+    assert_eq!(optimized.swap(0, Ordering::Relaxed), 3);
+
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,190 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Inlines small funcs at the places they're called, which linking can do across modules since
+//! every func's body is in reach. Accessors often cost less to run than the call and return
+//! that reach them.
+
+use super::FuncGraph;
+use crate::compile::{CompiledFunc, DebugInfo, FrameSize};
+use crate::mavm::{label_id, AVMOpcode, Instruction, Label, LabelId, Opcode, Value};
+use petgraph::graph::NodeIndex;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// The most instructions a func can have to be inlined without being marked `#[inline]`.
+pub const INLINE_THRESHOLD: usize = 16;
+
+/// Inlines each func small enough or marked `#[inline]` at its direct calls, returning the funcs
+/// each was inlined into. Only funcs that neither call nor refer to others are inlined, so that
+/// inlining never recurses, and funcs of debug-only modules never are, so that release builds
+/// can still leave them out.
+pub(super) fn inline_funcs(funcs: &mut FuncGraph) -> BTreeMap<NodeIndex, BTreeSet<NodeIndex>> {
+    let bodies: HashMap<LabelId, (NodeIndex, Vec<Instruction>)> = funcs
+        .node_indices()
+        .filter_map(|node| {
+            let body = inlinable_body(&funcs[node])?.to_vec();
+            Some((funcs[node].unique_id, (node, body)))
+        })
+        .collect();
+
+    let mut inlined = BTreeMap::new();
+    for node in funcs.node_indices() {
+        let caller = &mut funcs[node];
+        let frame = caller.code.iter().find_map(|insn| match insn.opcode {
+            Opcode::MakeFrame(space, _) => Some(space),
+            _ => None,
+        });
+        let frame = match frame {
+            Some(frame) => frame,
+            None => continue,
+        };
+
+        let mut code = Vec::with_capacity(caller.code.len());
+        let mut index = 0;
+        while index < caller.code.len() {
+            let call = direct_call(&caller.code[index..])
+                .and_then(|(callee, ret)| Some((bodies.get(&callee)?, ret)));
+            match call {
+                Some(((callee, body), ret)) => {
+                    let site = label_id(&(caller.unique_id, index));
+                    let debug_info = caller.code[index].debug_info;
+                    expand(body, site, ret, frame, debug_info, &mut code);
+                    inlined
+                        .entry(*callee)
+                        .or_insert_with(BTreeSet::new)
+                        .insert(node);
+                    index += 4;
+                }
+                None => {
+                    code.push(caller.code[index].clone());
+                    index += 1;
+                }
+            }
+        }
+        caller.code = code;
+    }
+    inlined
+}
+
+/// The code of a func that can be inlined, after its label and the push of its return address.
+/// Funcs are laid out as their label, the push of their return address, and the making and push
+/// of their frame, so only those with other prologues, like closures or funcs whose code hooks
+/// rewrote, are left alone.
+fn inlinable_body(func: &CompiledFunc) -> Option<&[Instruction]> {
+    if func.debug_only || func.debug_info.attributes.init {
+        return None;
+    }
+    if func.code.len() > INLINE_THRESHOLD && !func.debug_info.attributes.inline {
+        return None;
+    }
+    let aux_push = |insn: &Instruction| {
+        insn.opcode == Opcode::AVMOpcode(AVMOpcode::AuxPush) && insn.immediate.is_none()
+    };
+    match func.code.as_slice() {
+        [Instruction {
+            opcode: Opcode::Label(Label::Func(id)),
+            ..
+        }, ret, Instruction {
+            opcode: Opcode::MakeFrame(_, false),
+            immediate: None,
+            ..
+        }, frame, ..]
+            if *id == func.unique_id && aux_push(ret) && aux_push(frame) => {}
+        _ => return None,
+    }
+    if func.code[1..]
+        .iter()
+        .any(|insn| !insn.get_uniques().is_empty())
+    {
+        return None;
+    }
+    Some(&func.code[2..])
+}
+
+/// Finds the func called by name at the start of code, along with the label the call returns to.
+/// A call pushes the func's label and buries the return label beneath it before jumping.
+fn direct_call(code: &[Instruction]) -> Option<(LabelId, Label)> {
+    match code {
+        [Instruction {
+            opcode: Opcode::AVMOpcode(AVMOpcode::Noop),
+            immediate: Some(Value::Label(Label::Func(callee))),
+            ..
+        }, Instruction {
+            opcode: Opcode::AVMOpcode(AVMOpcode::Swap1),
+            immediate: Some(Value::Label(ret)),
+            ..
+        }, Instruction {
+            opcode: Opcode::AVMOpcode(AVMOpcode::Jump),
+            immediate: None,
+            ..
+        }, Instruction {
+            opcode: Opcode::Label(label),
+            ..
+        }, ..]
+            if label == ret =>
+        {
+            Some((*callee, *ret))
+        }
+        _ => None,
+    }
+}
+
+/// Writes a copy of a func's body in place of a call to it. The copy's labels are renamed after
+/// the call site so that copies don't collide, and its returns pop its frame and jump to where the
+/// call would have returned to. The caller's frame is laid back out once the copy is done.
+fn expand(
+    body: &[Instruction],
+    site: LabelId,
+    ret: Label,
+    frame: FrameSize,
+    debug_info: DebugInfo,
+    code: &mut Vec<Instruction>,
+) {
+    let relabel = |label: Label| match label {
+        Label::Anon(id) => Label::Anon(label_id(&(site, id))),
+        label => label,
+    };
+
+    for (index, insn) in body.iter().enumerate() {
+        let mut insn = insn.clone();
+        if let Opcode::Label(label) = insn.opcode {
+            insn.opcode = Opcode::Label(relabel(label));
+        }
+        if let Some(value) = &mut insn.immediate {
+            value.replace2(&mut |value| {
+                if let Value::Label(label) = value {
+                    *label = relabel(*label);
+                }
+            });
+        }
+
+        match insn.opcode {
+            Opcode::Return => {
+                let debug_info = insn.debug_info;
+                code.push(Instruction::new(
+                    Opcode::AVMOpcode(AVMOpcode::AuxPop),
+                    insn.immediate,
+                    debug_info,
+                ));
+                code.push(Instruction::from_opcode(
+                    Opcode::AVMOpcode(AVMOpcode::Pop),
+                    debug_info,
+                ));
+                if index + 1 < body.len() {
+                    code.push(Instruction::from_opcode_imm(
+                        Opcode::AVMOpcode(AVMOpcode::Jump),
+                        Value::Label(ret),
+                        debug_info,
+                    ));
+                }
+            }
+            _ => code.push(insn),
+        }
+    }
+    code.push(Instruction::from_opcode(Opcode::Label(ret), debug_info));
+    code.push(Instruction::from_opcode(
+        Opcode::MakeFrame(frame, true),
+        debug_info,
+    ));
+}
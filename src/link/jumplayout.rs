@@ -0,0 +1,385 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Lays out the jump table by how often a profiled run reached each of its entries. Each lookup
+//! walks the table's nested tuples with one `Tget` per level, so rather than nest every entry
+//! equally deep, the entries reached most are kept near the top and those reached least sink
+//! below them, as a Huffman code would arrange them.
+//!
+//! Profiles are written by `mini profiler --jump-profile`, which counts how often each instruction
+//! was jumped to. Entries are matched to those counts by the func they lead into and which of that
+//! func's entries they are, so a profile taken on one build still lays out the next, even once
+//! lines have been added to the source before them.
+
+use super::symbolmap::{FuncNames, SymbolMap};
+use super::xformcode::{jump_table_to_value, TupleTree, TUPLE_SIZE};
+use crate::compile::DebugInfo;
+use crate::mavm::{AVMOpcode, CodePt, Instruction, Label, LabelId, Opcode, Value};
+use crate::uint256::Uint256;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What each `Tget` of a lookup costs.
+const HOP_GAS: u64 = 2;
+
+/// How often a profiled run jumped to each entry of the jump table.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct JumpProfile {
+    pub targets: Vec<JumpTarget>,
+}
+
+/// How often one of the jump table's entries was jumped to.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct JumpTarget {
+    /// The path and name of the func the entry leads into, as the symbol map names it
+    pub func: String,
+    /// Which of the func's entries it is, counting them in the order their code appears
+    pub label: usize,
+    pub count: u64,
+}
+
+impl JumpProfile {
+    /// Collects the counts of jumps to each codepoint, naming the entries they were to by the
+    /// symbol map of the program run. Jumps to codepoints the jump table doesn't hold are left out.
+    pub fn new(jumps: &HashMap<CodePt, u64>, symbols: &SymbolMap) -> Self {
+        let mut counts = BTreeMap::new();
+        for (codepoint, count) in jumps {
+            let pc = match codepoint {
+                CodePt::Internal(pc) => *pc,
+                _ => continue,
+            };
+            let func = symbols
+                .funcs
+                .iter()
+                .find(|func| func.start <= pc && pc < func.end);
+            if let Some(func) = func {
+                if let Some(label) = func.targets.iter().position(|target| *target == pc) {
+                    *counts.entry((func.name.clone(), label)).or_insert(0) += count;
+                }
+            }
+        }
+        let targets = counts
+            .into_iter()
+            .map(|((func, label), count)| JumpTarget { func, label, count })
+            .collect();
+        JumpProfile { targets }
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, json)
+    }
+
+    /// The number of jumps profiled.
+    pub fn total(&self) -> u64 {
+        self.targets.iter().map(|target| target.count).sum()
+    }
+
+    /// How often the profiled run reached each entry of the jump table, found by the func each
+    /// entry's label is in and which of that func's entries it is. Pooled constants aren't jumped
+    /// to, so they're never counted as reached.
+    pub(super) fn weigh(
+        &self,
+        code: &[Instruction],
+        jump_table: &[Value],
+        names: &FuncNames,
+    ) -> Vec<u64> {
+        let counts: HashMap<_, _> = self
+            .targets
+            .iter()
+            .map(|target| ((target.func.as_str(), target.label), target.count))
+            .collect();
+
+        let reached: HashMap<Label, u64> = target_sites(code, jump_table)
+            .into_iter()
+            .filter_map(|site| {
+                let (name, _) = names.get(&site.func)?;
+                Some((site.label, *counts.get(&(name.as_str(), site.number))?))
+            })
+            .collect();
+        jump_table
+            .iter()
+            .map(|entry| match entry {
+                Value::Label(label) => reached.get(label).copied().unwrap_or(0),
+                _ => 0,
+            })
+            .collect()
+    }
+}
+
+/// Where a label of the jump table is found in code.
+pub(super) struct TargetSite {
+    pub label: Label,
+    /// The number of instructions before the label
+    pub codepoint: usize,
+    /// The func whose code the label is in
+    pub func: LabelId,
+    /// Which of the func's labels in the table it is
+    pub number: usize,
+}
+
+/// Finds the labels of the jump table in code, numbering those of each func in the order their
+/// code appears, with the func's own label first when it's in the table. Jump profiles name the
+/// entries they count this way, which code added to other funcs, or lines added above a func,
+/// leave as they were.
+pub(super) fn target_sites(code: &[Instruction], jump_table: &[Value]) -> Vec<TargetSite> {
+    let table: HashSet<Label> = jump_table
+        .iter()
+        .filter_map(|entry| match entry {
+            Value::Label(label) => Some(*label),
+            _ => None,
+        })
+        .collect();
+
+    let mut sites = vec![];
+    let mut func = None;
+    let mut number = 0;
+    let mut codepoint = 0;
+    for insn in code {
+        match insn.opcode {
+            Opcode::Label(label) => {
+                if let Label::Func(id) | Label::Closure(id) = label {
+                    func = Some(id);
+                    number = 0;
+                }
+                if let (Some(func), true) = (func, table.contains(&label)) {
+                    sites.push(TargetSite {
+                        label,
+                        codepoint,
+                        func,
+                        number,
+                    });
+                    number += 1;
+                }
+            }
+            _ => codepoint += 1,
+        }
+    }
+    sites
+}
+
+/// How the entries of the jump table are nested.
+#[derive(Debug)]
+pub(super) enum JumpTableLayout {
+    /// Every entry as deep as every other, which is how tables are laid out without a profile
+    Balanced(usize),
+    /// Entries nested by how often they were reached, with the path to each entry by its index
+    Weighted {
+        shape: Shape,
+        paths: Vec<Vec<usize>>,
+    },
+}
+
+/// A level of a weighted table.
+#[derive(Debug)]
+pub(super) enum Shape {
+    Entry(usize),
+    /// A slot filling out a tuple, as each tuple of the table but the last to be formed is full
+    Filler,
+    Tuple(Vec<Shape>),
+}
+
+impl JumpTableLayout {
+    /// Nests entries so that those reached more often take fewer hops to look up, counting each
+    /// as reached at least once, so that entries the profile missed aren't sunk without bound.
+    /// Tables small enough to fit in one tuple are left balanced, as every entry is already one
+    /// hop away.
+    pub(super) fn weighted(reached: &[u64]) -> Self {
+        if reached.len() <= TUPLE_SIZE {
+            return JumpTableLayout::Balanced(reached.len());
+        }
+
+        let mut shapes: Vec<Option<Shape>> = vec![];
+        let mut heap = BinaryHeap::new();
+        for (index, count) in reached.iter().enumerate() {
+            heap.push(Reverse((count.saturating_add(1), shapes.len())));
+            shapes.push(Some(Shape::Entry(index)));
+        }
+        while (shapes.len() - 1) % (TUPLE_SIZE - 1) != 0 {
+            heap.push(Reverse((0, shapes.len())));
+            shapes.push(Some(Shape::Filler));
+        }
+
+        while heap.len() > 1 {
+            let mut weight: u64 = 0;
+            let mut tuple = vec![];
+            for _ in 0..TUPLE_SIZE {
+                if let Some(Reverse((count, index))) = heap.pop() {
+                    weight = weight.saturating_add(count);
+                    tuple.extend(shapes[index].take());
+                }
+            }
+            heap.push(Reverse((weight, shapes.len())));
+            shapes.push(Some(Shape::Tuple(tuple)));
+        }
+        let shape = shapes.pop().flatten().unwrap_or(Shape::Filler);
+
+        let mut paths = vec![vec![]; reached.len()];
+        shape.find_paths(&mut vec![], &mut paths);
+        JumpTableLayout::Weighted { shape, paths }
+    }
+
+    /// The slots a lookup of the index-th entry walks through.
+    pub(super) fn path(&self, index: usize) -> Vec<usize> {
+        match self {
+            JumpTableLayout::Balanced(len) => TupleTree::new(*len, false)
+                .slot_path(index)
+                .unwrap_or_default(),
+            JumpTableLayout::Weighted { paths, .. } => paths[index].clone(),
+        }
+    }
+
+    /// The code looking the index-th entry up from the table on top of the stack.
+    pub(super) fn lookup_code(&self, index: usize, debug_info: DebugInfo) -> Vec<Instruction> {
+        match self {
+            JumpTableLayout::Balanced(len) => vec![Instruction::from_opcode(
+                Opcode::TupleGet(index, *len),
+                debug_info,
+            )],
+            JumpTableLayout::Weighted { paths, .. } => paths[index]
+                .iter()
+                .map(|slot| {
+                    Instruction::from_opcode_imm(
+                        Opcode::AVMOpcode(AVMOpcode::Tget),
+                        Value::Int(Uint256::from_usize(*slot)),
+                        debug_info,
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Places the entries of the table, which have had their labels stripped, into nested tuples
+    /// as laid out.
+    pub(super) fn make_value(&self, entries: Vec<Value>) -> Value {
+        match self {
+            JumpTableLayout::Balanced(_) => jump_table_to_value(entries),
+            JumpTableLayout::Weighted { shape, .. } => shape.make_value(&entries),
+        }
+    }
+}
+
+impl Shape {
+    fn find_paths(&self, path: &mut Vec<usize>, paths: &mut [Vec<usize>]) {
+        match self {
+            Shape::Entry(index) => paths[*index] = path.clone(),
+            Shape::Filler => {}
+            Shape::Tuple(shapes) => {
+                for (slot, shape) in shapes.iter().enumerate() {
+                    path.push(slot);
+                    shape.find_paths(path, paths);
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    fn make_value(&self, entries: &[Value]) -> Value {
+        match self {
+            Shape::Entry(index) => entries[*index].clone(),
+            Shape::Filler => Value::none(),
+            Shape::Tuple(shapes) => Value::new_tuple(
+                shapes
+                    .iter()
+                    .map(|shape| shape.make_value(entries))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// What laying the table out by a profile saves the run profiled, as compared to leaving it
+/// balanced.
+pub(super) struct LayoutSavings {
+    lookups: u64,
+    hops_before: u64,
+    hops_after: u64,
+}
+
+impl LayoutSavings {
+    pub(super) fn new(layout: &JumpTableLayout, reached: &[u64]) -> Self {
+        let balanced = JumpTableLayout::Balanced(reached.len());
+        let hops = |layout: &JumpTableLayout| -> u64 {
+            reached
+                .iter()
+                .enumerate()
+                .map(|(index, count)| count * layout.path(index).len() as u64)
+                .sum()
+        };
+        LayoutSavings {
+            lookups: reached.iter().sum(),
+            hops_before: hops(&balanced),
+            hops_after: hops(layout),
+        }
+    }
+}
+
+impl fmt::Display for LayoutSavings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let saved = self.hops_before as i128 - self.hops_after as i128;
+        write!(
+            f,
+            "Laid out the jump table for {} profiled lookups, taking {} Tget hops rather than {}, \
+             which saves about {} ArbGas over the run profiled",
+            self.lookups,
+            self.hops_after,
+            self.hops_before,
+            saved * HOP_GAS as i128
+        )
+    }
+}
+
+#[test]
+fn test_jump_table_layout() {
+    // one hot entry among many cold ones is looked up in one hop, where balanced takes two
+    let mut reached = vec![0; 40];
+    reached[33] = 1000;
+    let layout = JumpTableLayout::weighted(&reached);
+    assert_eq!(layout.path(33).len(), 1);
+    assert_eq!(JumpTableLayout::Balanced(40).path(33).len(), 2);
+
+    // each entry is found where its path leads, and no two share a path
+    let entries: Vec<_> = (0..40usize).map(Value::from).collect();
+    let table = layout.make_value(entries.clone());
+    let mut paths = vec![];
+    for (index, entry) in entries.iter().enumerate() {
+        let path = layout.path(index);
+        let mut value = &table;
+        for slot in &path {
+            value = match value {
+                Value::Tuple(tup) => {
+                    assert!(tup.len() <= TUPLE_SIZE);
+                    &tup[*slot]
+                }
+                _ => panic!("path {:?} leads past the table", path),
+            };
+        }
+        assert_eq!(value, entry);
+        paths.push(path);
+    }
+    paths.sort();
+    paths.dedup();
+    assert_eq!(paths.len(), entries.len());
+
+    let savings = LayoutSavings::new(&layout, &reached);
+    assert_eq!(savings.lookups, 1000);
+    assert_eq!(savings.hops_before - savings.hops_after, 1000);
+
+    // small tables are left as they are
+    assert!(matches!(
+        JumpTableLayout::weighted(&[5, 0, 3]),
+        JumpTableLayout::Balanced(3)
+    ));
+}
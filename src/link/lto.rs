@@ -0,0 +1,230 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Optimizes the program as a whole once its modules are linked. Calls into other modules keep
+//! each module's optimizations from seeing past them, but once inlining and constant globals have
+//! put constants beside the code that uses them, those constants can be folded, the branches they
+//! decide resolved, and the register reads they leave behind dropped.
+
+use super::optimize::peephole;
+use crate::mavm::{AVMOpcode, Instruction, Opcode, Value};
+use crate::uint256::Uint256;
+
+/// Folds constants, resolves the branches they decide, and drops register reads that are undone
+/// right away, running the peephole optimizer over the result until none of these find anything
+/// more to improve. Labels are never optimized across, since code can jump to them.
+pub(super) fn optimize_linked(code: Vec<Instruction>) -> Vec<Instruction> {
+    let mut code = code;
+    loop {
+        let mut changed = false;
+        code = fold_constants(code, &mut changed);
+        code = drop_register_round_trips(code, &mut changed);
+        if !changed {
+            return code;
+        }
+        code = peephole(&code);
+    }
+}
+
+/// The int an instruction pushes without doing anything else, if any.
+fn pushed_int(insn: &Instruction) -> Option<&Uint256> {
+    match (&insn.opcode, &insn.immediate) {
+        (Opcode::AVMOpcode(AVMOpcode::Noop), Some(Value::Int(value)))
+            if !insn.debug_info.attributes.probe =>
+        {
+            Some(value)
+        }
+        _ => None,
+    }
+}
+
+/// Replaces ops whose operands are all constants with a push of their result, and conditional
+/// jumps on constants with the jump taken, if any.
+fn fold_constants(code: Vec<Instruction>, changed: &mut bool) -> Vec<Instruction> {
+    let mut code_out: Vec<Instruction> = Vec::with_capacity(code.len());
+
+    for insn in code {
+        let op = match insn.opcode {
+            Opcode::AVMOpcode(op) => op,
+            _ => {
+                code_out.push(insn);
+                continue;
+            }
+        };
+        let push = |value: Uint256| {
+            Instruction::from_opcode_imm(
+                Opcode::AVMOpcode(AVMOpcode::Noop),
+                Value::Int(value),
+                insn.debug_info,
+            )
+        };
+
+        // an immediate is pushed before the op runs, so it's the top operand
+        let top = match &insn.immediate {
+            Some(Value::Int(top)) => Some(top),
+            _ => None,
+        };
+        if let Some(result) = top.and_then(|top| fold_unary(op, top)) {
+            code_out.push(push(result));
+            *changed = true;
+            continue;
+        }
+
+        let below = code_out.last().and_then(pushed_int).cloned();
+        let below = match below {
+            Some(below) => below,
+            None => {
+                code_out.push(insn);
+                continue;
+            }
+        };
+        if let Some(result) = top.and_then(|top| fold_binary(op, top, &below)) {
+            code_out.pop();
+            code_out.push(push(result));
+            *changed = true;
+            continue;
+        }
+        if op == AVMOpcode::Cjump && insn.immediate.is_some() {
+            code_out.pop();
+            if !below.is_zero() {
+                code_out.push(Instruction::new(
+                    Opcode::AVMOpcode(AVMOpcode::Jump),
+                    insn.immediate,
+                    insn.debug_info,
+                ));
+            }
+            *changed = true;
+            continue;
+        }
+        code_out.push(insn);
+    }
+
+    code_out
+}
+
+fn fold_unary(op: AVMOpcode, value: &Uint256) -> Option<Uint256> {
+    match op {
+        AVMOpcode::IsZero => Some(bool_int(value.is_zero())),
+        AVMOpcode::BitwiseNeg => Some(value.bitwise_neg()),
+        _ => None,
+    }
+}
+
+/// Folds an op that takes two operands, as the emulator would run it with top on the stack above
+/// below. Ops that can fail are left for the machine, so that they fail when it runs them.
+fn fold_binary(op: AVMOpcode, top: &Uint256, below: &Uint256) -> Option<Uint256> {
+    Some(match op {
+        AVMOpcode::Add => top.add(below),
+        AVMOpcode::Sub => top.unchecked_sub(below),
+        AVMOpcode::Mul => top.mul(below),
+        AVMOpcode::LessThan => bool_int(top < below),
+        AVMOpcode::GreaterThan => bool_int(top > below),
+        AVMOpcode::Equal => bool_int(top == below),
+        AVMOpcode::BitwiseAnd => top.bitwise_and(below),
+        AVMOpcode::BitwiseOr => top.bitwise_or(below),
+        AVMOpcode::BitwiseXor => top.bitwise_xor(below),
+        _ => return None,
+    })
+}
+
+fn bool_int(value: bool) -> Uint256 {
+    match value {
+        true => Uint256::one(),
+        false => Uint256::zero(),
+    }
+}
+
+/// Drops reads of the register that are written straight back to it or popped, which inlining a
+/// func that writes a global into one that reads it can leave behind.
+fn drop_register_round_trips(code: Vec<Instruction>, changed: &mut bool) -> Vec<Instruction> {
+    let mut code_out: Vec<Instruction> = Vec::with_capacity(code.len());
+
+    for insn in code {
+        let read = Opcode::AVMOpcode(AVMOpcode::Rpush);
+        let undone = match (code_out.last(), insn.opcode, &insn.immediate) {
+            (Some(prev), Opcode::AVMOpcode(AVMOpcode::Rset | AVMOpcode::Pop), None) => {
+                prev.opcode == read && prev.immediate.is_none()
+            }
+            _ => false,
+        };
+        match undone {
+            true => {
+                code_out.pop();
+                *changed = true;
+            }
+            false => code_out.push(insn),
+        }
+    }
+
+    code_out
+}
+
+#[test]
+fn test_link_time_optimization() {
+    use crate::compile::DebugInfo;
+    use crate::mavm::Label;
+
+    let insn = |opcode, immediate: Option<u64>| {
+        Instruction::new(
+            Opcode::AVMOpcode(opcode),
+            immediate.map(Value::from),
+            DebugInfo::default(),
+        )
+    };
+    let jump = |opcode, label| {
+        Instruction::from_opcode_imm(
+            Opcode::AVMOpcode(opcode),
+            Value::Label(label),
+            DebugInfo::default(),
+        )
+    };
+    let target = Label::Anon(1);
+    let here = Instruction::from_opcode(Opcode::Label(target), DebugInfo::default());
+
+    // whether 7 - 3 is more than 2 decides the branch, and the register is read for nothing
+    let code = vec![
+        insn(AVMOpcode::Noop, Some(2)),
+        insn(AVMOpcode::Noop, Some(3)),
+        insn(AVMOpcode::Sub, Some(7)),
+        insn(AVMOpcode::GreaterThan, None),
+        jump(AVMOpcode::Cjump, target),
+        insn(AVMOpcode::Log, Some(0)),
+        here.clone(),
+        insn(AVMOpcode::Rpush, None),
+        insn(AVMOpcode::Rset, None),
+        insn(AVMOpcode::Rpush, None),
+        insn(AVMOpcode::Pop, None),
+        insn(AVMOpcode::Log, Some(1)),
+    ];
+    let optimized = optimize_linked(code);
+    assert_eq!(
+        optimized,
+        vec![
+            jump(AVMOpcode::Jump, target),
+            insn(AVMOpcode::Log, Some(0)),
+            here.clone(),
+            insn(AVMOpcode::Log, Some(1)),
+        ]
+    );
+
+    // branches on a zero fall through, and nothing is folded across a label, nor made to fail
+    let code = vec![
+        insn(AVMOpcode::IsZero, Some(5)),
+        jump(AVMOpcode::Cjump, target),
+        insn(AVMOpcode::Noop, Some(5)),
+        here.clone(),
+        insn(AVMOpcode::Add, Some(1)),
+        insn(AVMOpcode::Div, Some(0)),
+    ];
+    let optimized = optimize_linked(code);
+    assert_eq!(
+        optimized,
+        vec![
+            insn(AVMOpcode::Noop, Some(5)),
+            here,
+            insn(AVMOpcode::Add, Some(1)),
+            insn(AVMOpcode::Div, Some(0)),
+        ]
+    );
+}
@@ -5,79 +5,95 @@
 //! Provides types and utilities for linking together compiled mini programs
 
 use crate::compile::{
-    comma_list, CompileError, CompiledFunc, CompiledProgram, DebugInfo, ErrorSystem, FileInfo,
-    GlobalVar, Type, TypeTree,
+    path_display, CompileError, CompiledFunc, CompiledProgram, DebugInfo, ErrorSystem, FileInfo,
+    GlobalVar, Lint,
 };
 use crate::console::Color;
-use crate::mavm::{AVMOpcode, Instruction, LabelId, Opcode, Value};
+use crate::mavm::{label_id, stable_hash, AVMOpcode, Instruction, LabelId, Opcode, Value};
 use crate::pos::{try_display_location, Location};
 use crate::stringtable::StringId;
+use crate::uint256::Uint256;
+use constpool::ConstantPool;
+use debugonly::SizeReport;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use jumplayout::{JumpTableLayout, LayoutSavings};
 use petgraph::dot::{Config, Dot};
 use petgraph::graph::DiGraph;
 use petgraph::visit::DfsPostOrder;
+use prologue::Prologue;
 use serde::{Deserialize, Serialize};
-use std::collections::hash_map::{DefaultHasher, HashMap};
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::File;
-use std::hash::{Hash, Hasher};
 use std::io;
 use std::io::Write;
-
-use crate::compile::miniconstants::init_constant_table;
 use std::path::Path;
+
+pub use archive::LibraryArchive;
+pub use format::FORMAT_VERSION;
+pub use incremental::{link_cache_path, LinkCache};
+pub use jumplayout::JumpProfile;
+pub use semver::{classify, semver_check};
+#[cfg(test)]
+pub use semver::{Bump, Change};
+pub use sidecar::{sidecar_path, DebugSidecar};
+pub use stack::CallSites;
+pub use symbolmap::{func_names, symbol_map_path, FuncNames, SymbolMap};
+pub use target::check_target_version;
+pub use typetree::SerializableTypeTree;
 pub use xformcode::{TupleTree, TUPLE_SIZE};
 
+mod archive;
+mod constglobals;
+mod constpool;
+mod debugonly;
+mod fold;
+mod format;
+mod incremental;
+mod inline;
+mod jumplayout;
+mod lto;
 mod optimize;
+mod overrides;
+mod prologue;
+mod semver;
+mod sidecar;
+mod stack;
 mod striplabels;
+mod symbolmap;
+mod target;
+mod typetree;
 mod xformcode;
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct SerializableTypeTree {
-    inner: BTreeMap<String, (Type, String)>,
-}
-
-impl SerializableTypeTree {
-    pub fn from_type_tree(tree: TypeTree) -> Self {
-        let mut inner = BTreeMap::new();
-        for ((path, id), tipe) in tree.into_iter() {
-            inner.insert(format!("{}, {}", comma_list(&path), id), tipe);
-        }
-        Self { inner }
-    }
-    pub fn into_type_tree(self) -> TypeTree {
-        let mut type_tree = HashMap::new();
-        for (path, tipe) in self.inner.into_iter() {
-            let mut x: Vec<_> = path.split(", ").map(|val| val.to_string()).collect();
-            let id = x
-                .pop()
-                .map(|id| id.parse::<usize>())
-                .expect("empty list")
-                .expect("failed to parse");
-            type_tree.insert((x, id), tipe);
-        }
-        type_tree
-    }
-}
-
 /// Represents a mini program that has gone through the post-link compilation step.
 ///
 /// This is typically constructed via the `postlink_compile` function.
 #[derive(Serialize, Deserialize)]
 pub struct LinkedProgram {
-    #[serde(default)]
+    /// The version of the mexe format the program was written in, see `FORMAT_VERSION`.
+    pub format_version: u64,
     pub arbos_version: u64,
     pub code: Vec<Instruction<AVMOpcode>>,
     pub static_val: Value,
     pub globals: Vec<GlobalVar>,
-    // #[serde(default)]
     pub file_info_chart: BTreeMap<u64, FileInfo>,
     pub type_tree: SerializableTypeTree,
+    /// The constants describing the interface the program presents to the chain, keyed by name.
+    pub interface: BTreeMap<String, Uint256>,
+    /// Where each func's code begins in the source, keyed by its path-qualified name.
+    pub symbols: BTreeMap<String, Location>,
+    /// The constants overridden when the program was compiled, keyed by name, so that a variant
+    /// built from a shared constants file can be told apart from the others.
+    pub defines: BTreeMap<String, Uint256>,
 }
 
 impl LinkedProgram {
     /// Serializes self to the format specified by the format argument, with a default of json for
     /// None. The output is written to a dynamically dispatched implementor of `std::io::Write`,
     /// specified by the output argument.
+    ///
+    /// Besides json, a mexe can be written as cbor or as json compressed with gzip, which are far
+    /// smaller for programs the size of ArbOS, and which `from_bytes` tells apart when loading it.
     pub fn to_output(&self, output: &mut dyn io::Write, format: Option<&str>) {
         match format {
             Some("pretty") => {
@@ -116,6 +132,20 @@ impl LinkedProgram {
                     writeln!(output, "bincode serialization error: {:?}", e).unwrap();
                 }
             },
+            Some("cbor") => {
+                if let Err(e) = serde_cbor::to_writer(&mut *output, self) {
+                    writeln!(output, "cbor serialization error: {:?}", e).unwrap();
+                }
+            }
+            Some("json.gz") => {
+                let mut encoder = GzEncoder::new(&mut *output, Compression::default());
+                let written = serde_json::to_writer(&mut encoder, self)
+                    .map_err(io::Error::from)
+                    .and_then(|_| encoder.finish().map(|_| ()));
+                if let Err(e) = written {
+                    writeln!(output, "json.gz serialization error: {:?}", e).unwrap();
+                }
+            }
             Some(weird_value) => {
                 writeln!(output, "invalid format: {}", weird_value).unwrap();
             }
@@ -136,6 +166,11 @@ pub struct Import {
     pub id: Option<StringId>,
     /// Location of the use-statement in code
     pub location: Option<Location>,
+    /// Location just past the use-statement's semicolon, so the statement can be removed whole
+    #[serde(default)]
+    pub end: Option<Location>,
+    /// Whether the importing module re-exports what's imported, so others can import it from there
+    pub public: bool,
 }
 
 impl Import {
@@ -152,6 +187,8 @@ impl Import {
             unique_id,
             id,
             location,
+            end: None,
+            public: false,
         }
     }
 
@@ -169,14 +206,13 @@ impl Import {
             unique_id,
             id: None,
             location: None,
+            end: None,
+            public: false,
         }
     }
 
     pub fn unique_id(path: &Vec<String>, name: &String) -> LabelId {
-        let mut hasher = DefaultHasher::new();
-        path.hash(&mut hasher);
-        name.hash(&mut hasher);
-        hasher.finish()
+        label_id(&(path, name))
     }
 }
 
@@ -184,13 +220,47 @@ pub type FuncGraph = DiGraph<CompiledFunc, usize>;
 
 /// Creates a graph of the `CompiledProgram`s and then combines them into a single
 /// `CompiledProgram` in such a way as to reduce the number of backward jumps.
+///
+/// When a `stack_budget` is given, the call paths reachable from main are checked for unbounded
+/// recursion and for depths exceeding the budget's number of frames, and the deepest each entry
+/// point can take the stacks is reported.
+///
+/// Release builds leave out the funcs of debug-only modules, along with those only they reach,
+/// reporting how much was left out.
+///
+/// When inlining, small funcs and those marked `#[inline]` are copied into their callers in
+/// place of each direct call. When propagating constant globals, reads of globals that the funcs
+/// marked `#[init]` only ever set to one constant are replaced by that constant.
+///
+/// The program is entered through main, or through the func given as its `entry` instead, as
+/// unit tests are. Only the funcs the entry reaches, through calls and references alike, are
+/// linked, so funcs nothing reaches are left out of the program and reported as unreachable.
+/// Unit tests aren't reported.
+///
+/// The program begins with the prologue test_mode calls for, which `postlink_compile` fills the
+/// globals into once they're known. When given an `error_handler`, the prologue goes on to install
+/// it, so that errors call it with the fault rather than stopping the machine. The handler is
+/// linked along with the funcs it reaches, whether or not the entry reaches it too.
+///
+/// Funcs are linked in a canonical order, by path and then name, with the type trees of all of
+/// them merged, so that the same funcs always link to the same program whatever order they're
+/// given in.
 pub fn link(
     funcs: Vec<CompiledFunc>,
     globals: Vec<GlobalVar>,
     error_system: &mut ErrorSystem,
     test_mode: bool,
+    stack_budget: Option<usize>,
+    release_build: bool,
+    inline: bool,
+    const_globals: bool,
+    entry: Option<LabelId>,
+    error_handler: Option<LabelId>,
 ) -> CompiledProgram {
-    let type_tree = funcs[0].type_tree.clone();
+    let type_tree = SerializableTypeTree::merge(funcs.iter().map(|func| &func.type_tree));
+    let type_tree = type_tree.into_type_tree();
+    let mut funcs = overrides::apply_overrides(funcs, &type_tree, error_system);
+    funcs.sort_by(|a, b| (&a.path, &a.name, a.unique_id).cmp(&(&b.path, &b.name, b.unique_id)));
 
     let mut graph = FuncGraph::new();
     let mut id_to_node = HashMap::new();
@@ -201,6 +271,11 @@ pub fn link(
         id_to_node.insert(func_id, node);
     }
 
+    let inlined = match inline {
+        true => inline::inline_funcs(&mut graph),
+        false => BTreeMap::new(),
+    };
+
     for node in graph.node_indices() {
         let prog = &graph[node];
 
@@ -224,46 +299,56 @@ pub fn link(
         .iter()
         .any(|x| x.debug_info.attributes.codegen_print);
 
-    // Initialize globals or allow jump table retrieval
-    let mut linked_code = if test_mode {
-        vec![
-            Instruction::from_opcode_imm(
-                Opcode::AVMOpcode(AVMOpcode::Noop),
-                Value::none(),
-                debug_info,
-            ),
-            Instruction::from_opcode_imm(
-                Opcode::AVMOpcode(AVMOpcode::Rset),
-                Value::none(), // gets hardcoded later
-                debug_info,
-            ),
-        ]
-    } else {
-        vec![
-            Instruction::from_opcode(Opcode::AVMOpcode(AVMOpcode::Rpush), debug_info),
-            Instruction::from_opcode_imm(
-                Opcode::AVMOpcode(AVMOpcode::Noop),
-                Value::none(),
-                debug_info,
-            ),
-            Instruction::from_opcode_imm(
-                Opcode::AVMOpcode(AVMOpcode::Rset),
-                Value::none(), // gets hardcoded later
-                debug_info,
-            ),
-        ]
+    let mut linked_code = Prologue::new(test_mode).code(debug_info);
+
+    let main = match entry {
+        Some(entry) => id_to_node[&entry],
+        None => graph
+            .node_indices()
+            .find(|node| graph[*node].name == "main")
+            .expect("no main func"),
+    };
+
+    if let Some(handler) = error_handler {
+        let entry = graph[main].unique_id;
+        linked_code.extend(prologue::error_handler_code(handler, entry, debug_info));
+    }
+
+    if const_globals {
+        let handler = error_handler.map(|handler| id_to_node[&handler]);
+        if let Err(setter) = constglobals::propagate_const_globals(&mut graph, main, handler) {
+            println!(
+                "Left every global to be read, as {} may set the globals register",
+                Color::color(error_system.warn_color, &graph[setter].name)
+            );
+        }
+    }
+
+    if let Some(budget) = stack_budget {
+        let report = stack::check_stack_depth(&graph, &id_to_node, main, budget, error_system);
+        println!("{}", report);
+    }
+
+    let left_out = match release_build {
+        true => debugonly::find_debug_only(&graph, main, error_system),
+        false => BTreeSet::new(),
     };
 
-    let main = graph
-        .node_indices()
-        .find(|node| graph[*node].name == "main")
-        .expect("no main func");
     let mut dfs = DfsPostOrder::new(&graph, main);
     let mut traversal = vec![];
     while let Some(node) = dfs.next(&graph) {
         traversal.push(node);
     }
     traversal.reverse();
+    if let Some(handler) = error_handler {
+        // the handler and what it reaches follow the funcs the entry reaches
+        dfs.move_to(id_to_node[&handler]);
+        let mut handled = vec![];
+        while let Some(node) = dfs.next(&graph) {
+            handled.push(node);
+        }
+        traversal.extend(handled.into_iter().rev());
+    }
 
     let mut unvisited: HashSet<_> = graph.node_indices().collect();
     for node in traversal {
@@ -281,8 +366,18 @@ pub fn link(
             continue;
         }
 
-        if unvisited.contains(&node) && !name.starts_with('_') {
+        // funcs inlined into reachable ones are reached, even if their own code isn't
+        let reached = inlined.get(&node).map_or(false, |callers| {
+            callers.iter().any(|caller| !unvisited.contains(caller))
+        });
+        if unvisited.contains(&node)
+            && !left_out.contains(&node)
+            && !reached
+            && !name.starts_with('_')
+            && !debug_info.attributes.test
+        {
             error_system.warnings.push(CompileError::new_warning(
+                Lint::DeadCode,
                 String::from("Compile warning"),
                 format!(
                     "func {} is unreachable",
@@ -293,6 +388,10 @@ pub fn link(
         }
     }
 
+    if !left_out.is_empty() {
+        println!("{}", SizeReport::new(&graph, &left_out, linked_code.len()));
+    }
+
     let graph = graph.map(|_, prog| prog.name.clone(), |_, e| e);
 
     let mut file = File::create("callgraph.dot").expect("failed to open file");
@@ -314,11 +413,38 @@ pub fn link(
 /// Converts a linked `CompiledProgram` into a `LinkedProgram` by fixing non-forward jumps,
 /// converting wide tuples to nested tuples, performing code optimizations, converting the jump
 /// table to a static value, and combining the file info chart with the associated argument.
+///
+/// With link-time optimization, the program is optimized as a whole once the rest of these are
+/// done, folding the constants that inlining and constant globals bring together across modules.
+///
+/// With scheduling, the instructions of each basic block are reordered to shuffle the stack less.
+///
+/// When given a link cache, each func is optimized on its own, and only if the cache doesn't hold
+/// its code from the last link, as it won't when the func or the jump table entries it looks up
+/// have changed. The optimizations never reach across a func's label, so the program is the same
+/// as it would be if it were optimized whole.
+///
+/// When pooling constants, each large constant used in several places is kept once in the jump
+/// table, and looked up from there by the instructions using it.
+///
+/// When given a jump profile and the names of the funcs linked, the jump table is nested by how
+/// often the profiled run reached each of its entries, so that the entries reached most take the
+/// fewest hops to look up.
+///
+/// When given a path and the names of the funcs linked, a symbol map naming the func at each
+/// codepoint of the final program is written there.
 pub fn postlink_compile(
     program: CompiledProgram,
     file_info_chart: BTreeMap<u64, FileInfo>,
     test_mode: bool,
     debug: bool,
+    arbos_version: u64,
+    lto: bool,
+    schedule: bool,
+    pool_constants: bool,
+    jump_profile: Option<(&JumpProfile, &FuncNames)>,
+    link_cache: Option<&mut LinkCache>,
+    symbol_map: Option<(&Path, &FuncNames)>,
 ) -> Result<LinkedProgram, CompileError> {
     let consider_debug_printing = |code: &Vec<Instruction>, did_print: bool, phase: &str| {
         if debug {
@@ -368,25 +494,73 @@ pub fn postlink_compile(
         }
     }
 
-    let (code, jump_table) =
-        striplabels::fix_backward_labels(&program.code, program.globals.len() - 1);
+    // folding comes before labels are fixed, since it can point code back at earlier funcs
+    let code = match lto {
+        true => fold::fold_identical_funcs(program.code),
+        false => program.code,
+    };
+    consider_debug_printing(&code, did_print, "after folding identical funcs");
+
+    let pool = match pool_constants {
+        true => ConstantPool::new(&code),
+        false => ConstantPool::default(),
+    };
+    if !pool.is_empty() {
+        println!("{}", pool);
+    }
+
+    let mut reached = None;
+    let (code, jump_table, layout) =
+        striplabels::fix_backward_labels(&code, program.globals.len() - 1, &pool, |table| {
+            match jump_profile {
+                Some((profile, names)) => {
+                    let counts = profile.weigh(&code, table, names);
+                    let layout = JumpTableLayout::weighted(&counts);
+                    reached = Some(counts);
+                    layout
+                }
+                None => JumpTableLayout::Balanced(table.len()),
+            }
+        });
+    if let Some(reached) = &reached {
+        println!("{}", LayoutSavings::new(&layout, reached));
+    }
     consider_debug_printing(&code, did_print, "after fix_backward_labels");
 
-    let code = xformcode::fix_tuple_size(code, program.globals.len())?;
-    consider_debug_printing(&code, did_print, "after fix_tuple_size");
+    let num_globals = program.globals.len();
+    let code = match link_cache {
+        Some(cache) => {
+            let options = stable_hash(&(num_globals, lto, schedule));
+            let code = cache.optimize_funcs(code, options, |code| {
+                optimize_code(code, num_globals, lto, schedule, &|_, _| {})
+            })?;
+            consider_debug_printing(&code, did_print, "after incremental optimization");
+            code
+        }
+        None => optimize_code(code, num_globals, lto, schedule, &|code, phase| {
+            consider_debug_printing(code, did_print, phase)
+        })?,
+    };
 
-    let code = optimize::peephole(&code);
-    consider_debug_printing(&code, did_print, "after peephole optimization");
+    if let Some((path, names)) = symbol_map {
+        let symbols = SymbolMap::new(&code, &jump_table, names, &file_info_chart);
+        symbols.write(path).map_err(|e| {
+            CompileError::new(
+                "Symbol map error",
+                format!("Could not write {}: {}", Color::red(path.display()), e),
+                vec![],
+            )
+        })?;
+    }
 
     let (mut code, jump_table_final) = striplabels::strip_labels(code, &jump_table)?;
     let jump_table_len = jump_table_final.len();
-    let jump_table_value = xformcode::jump_table_to_value(jump_table_final);
+    let jump_table_value = layout.make_value(jump_table_final);
 
     // hardcode globals & set error codepoints
     let globals =
         xformcode::make_globals_tuple(&program.globals, &jump_table_value, &program.type_tree);
-    let write_offset = if test_mode { 1 } else { 2 };
-    code[write_offset].immediate = Some(globals.clone());
+    Prologue::new(test_mode).set_globals(&mut code, globals.clone())?;
     code = xformcode::set_error_codepoints(code);
 
     let code_final: Vec<_> = code
@@ -446,16 +620,57 @@ pub fn postlink_compile(
     }
 
     Ok(LinkedProgram {
-        arbos_version: init_constant_table(Some(Path::new("arb_os/constants.json")))
-            .unwrap()
-            .get("ArbosVersionNumber")
-            .unwrap()
-            .clone()
-            .trim_to_u64(),
+        format_version: FORMAT_VERSION,
+        arbos_version,
         code: code_final,
         static_val: Value::none(),
         globals: program.globals.clone(),
         file_info_chart,
         type_tree: SerializableTypeTree::from_type_tree(program.type_tree),
+        interface: BTreeMap::new(),
+        symbols: BTreeMap::new(),
+        defines: BTreeMap::new(),
     })
 }
+
+/// Lowers code whose backward labels have been fixed to AVM ops and optimizes it, calling print
+/// with the code after each step. Instructions are only scheduled when schedule is set.
+fn optimize_code(
+    code: Vec<Instruction>,
+    num_globals: usize,
+    lto: bool,
+    schedule: bool,
+    print: &dyn Fn(&Vec<Instruction>, &str),
+) -> Result<Vec<Instruction>, CompileError> {
+    let code = xformcode::fix_tuple_size(code, num_globals)?;
+    print(&code, "after fix_tuple_size");
+
+    let code = optimize::peephole(&code);
+    print(&code, "after peephole optimization");
+
+    let code = match schedule {
+        true => optimize::peephole(&optimize::schedule(&code)),
+        false => code,
+    };
+    print(&code, "after instruction scheduling");
+
+    let code = match lto {
+        true => lto::optimize_linked(code),
+        false => code,
+    };
+    print(&code, "after link-time optimization");
+    Ok(code)
+}
+
+/// Names the funcs by their paths, keyed to where their code begins, so that tools running a
+/// mexe can tell which func an instruction belongs to.
+pub fn symbol_table(funcs: &[CompiledFunc]) -> BTreeMap<String, Location> {
+    funcs
+        .iter()
+        .filter_map(|func| {
+            let location = func.debug_info.location?;
+            let name = format!("{}::{}", path_display(&func.path), func.name);
+            Some((name, location))
+        })
+        .collect()
+}
@@ -6,13 +6,16 @@
 
 use crate::mavm::{AVMOpcode, Instruction, Opcode};
 
-/// Removes instructions that have no effect on the output of the program.
+/// Removes instructions that have no effect on the output of the program. Coverage probes are
+/// kept, since their effect is to be reached.
 fn useless_opcodes_layer<'a, I>(iter: I) -> impl Iterator<Item = &'a Instruction>
 where
     I: Iterator<Item = &'a Instruction>,
 {
     iter.filter(|&insn| {
-        !(insn.opcode == Opcode::AVMOpcode(AVMOpcode::Noop) && insn.immediate.is_none()
+        !(insn.opcode == Opcode::AVMOpcode(AVMOpcode::Noop)
+            && insn.immediate.is_none()
+            && !insn.debug_info.attributes.probe
             || insn.opcode == Opcode::AVMOpcode(AVMOpcode::Pop) && insn.immediate.is_some())
     })
 }
@@ -222,3 +225,277 @@ pub fn peephole(code_in: &[Instruction]) -> Vec<Instruction> {
     }
     code_out
 }
+
+/// Whether insn pushes a single value without reading the stack or having any other effect.
+fn is_independent_push(insn: &Instruction) -> bool {
+    match insn.opcode {
+        Opcode::AVMOpcode(AVMOpcode::Noop) => insn.immediate.is_some(),
+        Opcode::AVMOpcode(AVMOpcode::Rpush) => insn.immediate.is_none(),
+        _ => false,
+    }
+}
+
+/// Whether insn pushes a single value without popping or having any other effect.
+fn is_pure_push(insn: &Instruction) -> bool {
+    match insn.opcode {
+        Opcode::AVMOpcode(AVMOpcode::Dup0)
+        | Opcode::AVMOpcode(AVMOpcode::Dup1)
+        | Opcode::AVMOpcode(AVMOpcode::Dup2) => insn.immediate.is_none(),
+        _ => is_independent_push(insn),
+    }
+}
+
+/// Returns the opcode that computes the same result as opcode when its top two operands are swapped.
+fn with_swapped_operands(opcode: &Opcode) -> Option<AVMOpcode> {
+    match opcode {
+        Opcode::AVMOpcode(inner) => match inner {
+            AVMOpcode::Add
+            | AVMOpcode::Mul
+            | AVMOpcode::AddMod
+            | AVMOpcode::MulMod
+            | AVMOpcode::Equal
+            | AVMOpcode::BitwiseAnd
+            | AVMOpcode::BitwiseOr
+            | AVMOpcode::BitwiseXor => Some(*inner),
+            AVMOpcode::LessThan => Some(AVMOpcode::GreaterThan),
+            AVMOpcode::GreaterThan => Some(AVMOpcode::LessThan),
+            AVMOpcode::SLessThan => Some(AVMOpcode::SGreaterThan),
+            AVMOpcode::SGreaterThan => Some(AVMOpcode::SLessThan),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Takes a slice of `Instruction`s and returns a vector of instructions in which independent
+/// instructions within each basic block have been reordered or combined to remove stack shuffling.
+/// Since labels and jumps never take part in the patterns below, no change crosses a block boundary.
+///
+/// Where X and Y are instructions that push a value without reading the stack, like a Noop with an
+/// immediate, and P is either of these or a Dup with no immediate, the combinations are:
+/// * Swap1 followed by an opcode whose top two operands commute, replaced by the opcode with the
+/// immediate of the Swap1, flipping comparisons as needed
+/// * Swap1 or Swap2 followed by the same swap with no immediate, replaced by a Noop with the
+/// immediate of the first swap
+/// * Dup0 followed by Swap1, both with no immediates, replaced by Dup0
+/// * P followed by Pop with no immediate, removed
+/// * X, Y, then Swap1 with no immediate, replaced by Y then X
+/// * X followed by Swap1 with an immediate, replaced by a Noop with that immediate then X
+/// * X, Swap1 with no immediate, then Pop with no immediate, replaced by Pop then X
+///
+/// Since some of these expose new combinations to `peephole`, it should be run again afterwards.
+pub fn schedule(code_in: &[Instruction]) -> Vec<Instruction> {
+    let mut code_out: Vec<Instruction> = Vec::new();
+
+    // rewritten instructions are fed back in so they can combine with those before them
+    let mut input: Vec<Instruction> = code_in.iter().rev().cloned().collect();
+
+    let opcode = |insn: &Instruction| match insn.opcode {
+        Opcode::AVMOpcode(inner) => Some(inner),
+        _ => None,
+    };
+    let noop = |insn: &Instruction| {
+        insn.immediate.clone().map(|val| {
+            Instruction::from_opcode_imm(Opcode::AVMOpcode(AVMOpcode::Noop), val, insn.debug_info)
+        })
+    };
+
+    while let Some(insn) = input.pop() {
+        let len = code_out.len();
+        if len < 1 || insn.immediate.is_some() && opcode(&insn) != Some(AVMOpcode::Swap1) {
+            code_out.push(insn);
+            continue;
+        }
+
+        let prev = code_out[len - 1].clone();
+        let prev_op = opcode(&prev);
+        let replacement = match (prev_op, opcode(&insn), insn.immediate.is_none()) {
+            (Some(AVMOpcode::Swap1), _, true) if with_swapped_operands(&insn.opcode).is_some() => {
+                let swapped = with_swapped_operands(&insn.opcode).unwrap();
+                Some(vec![Instruction::new(
+                    Opcode::AVMOpcode(swapped),
+                    prev.immediate.clone(),
+                    insn.debug_info,
+                )])
+            }
+            (Some(AVMOpcode::Swap1), Some(AVMOpcode::Swap1), true)
+            | (Some(AVMOpcode::Swap2), Some(AVMOpcode::Swap2), true) => {
+                Some(noop(&prev).into_iter().collect())
+            }
+            (Some(AVMOpcode::Dup0), Some(AVMOpcode::Swap1), true) if prev.immediate.is_none() => {
+                Some(vec![prev.clone()])
+            }
+            (_, Some(AVMOpcode::Pop), true) if is_pure_push(&prev) => Some(vec![]),
+            (_, Some(AVMOpcode::Swap1), false) if is_independent_push(&prev) => {
+                Some(vec![noop(&insn).unwrap(), prev.clone()])
+            }
+            _ => None,
+        };
+        if let Some(replacement) = replacement {
+            code_out.truncate(len - 1);
+            input.extend(replacement.into_iter().rev());
+            continue;
+        }
+
+        if len >= 2 && insn.immediate.is_none() && is_independent_push(&code_out[len - 2]) {
+            let first = code_out[len - 2].clone();
+            let replacement = match opcode(&insn) {
+                Some(AVMOpcode::Swap1) if is_independent_push(&prev) => Some(vec![prev, first]),
+                Some(AVMOpcode::Pop)
+                    if prev_op == Some(AVMOpcode::Swap1) && prev.immediate.is_none() =>
+                {
+                    Some(vec![insn.clone(), first])
+                }
+                _ => None,
+            };
+            if let Some(replacement) = replacement {
+                code_out.truncate(len - 2);
+                input.extend(replacement.into_iter().rev());
+                continue;
+            }
+        }
+
+        code_out.push(insn);
+    }
+    code_out
+}
+
+#[test]
+fn test_schedule_differential() {
+    use crate::compile::DebugInfo;
+    use crate::link::{LinkedProgram, SerializableTypeTree, FORMAT_VERSION};
+    use crate::mavm::Value;
+    use crate::run::{Machine, MachineState, RuntimeEnvironment};
+    use crate::uint256::Uint256;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    // each opcode with the number of values it pops and pushes
+    let alphabet = [
+        (AVMOpcode::Noop, 0, 0),
+        (AVMOpcode::Rpush, 0, 1),
+        (AVMOpcode::Dup0, 1, 2),
+        (AVMOpcode::Dup1, 2, 3),
+        (AVMOpcode::Dup2, 3, 4),
+        (AVMOpcode::Swap1, 2, 2),
+        (AVMOpcode::Swap2, 3, 3),
+        (AVMOpcode::Pop, 1, 0),
+        (AVMOpcode::Add, 2, 1),
+        (AVMOpcode::Mul, 2, 1),
+        (AVMOpcode::Sub, 2, 1),
+        (AVMOpcode::AddMod, 3, 1),
+        (AVMOpcode::LessThan, 2, 1),
+        (AVMOpcode::GreaterThan, 2, 1),
+        (AVMOpcode::SLessThan, 2, 1),
+        (AVMOpcode::SGreaterThan, 2, 1),
+        (AVMOpcode::Equal, 2, 1),
+        (AVMOpcode::BitwiseAnd, 2, 1),
+        (AVMOpcode::BitwiseXor, 2, 1),
+        (AVMOpcode::IsZero, 1, 1),
+        (AVMOpcode::AuxPush, 1, 0),
+        (AVMOpcode::AuxPop, 0, 1),
+    ];
+
+    let mut rng = SmallRng::seed_from_u64(1252);
+    let random_value = |rng: &mut SmallRng| match rng.gen_range(0..5) {
+        4 => Value::Int(Uint256::zero().bitwise_neg()),
+        small => Value::from(small as usize),
+    };
+
+    let execute = |code: &[Instruction]| {
+        let code = code
+            .iter()
+            .map(|insn| match insn.opcode {
+                Opcode::AVMOpcode(inner) => {
+                    Instruction::new(inner, insn.immediate.clone(), insn.debug_info)
+                }
+                _ => panic!("virtual opcode in differential test"),
+            })
+            .collect();
+        let program = LinkedProgram {
+            format_version: FORMAT_VERSION,
+            arbos_version: 0,
+            code,
+            static_val: Value::none(),
+            globals: vec![],
+            file_info_chart: BTreeMap::new(),
+            type_tree: SerializableTypeTree::default(),
+            interface: BTreeMap::new(),
+            symbols: BTreeMap::new(),
+            defines: BTreeMap::new(),
+        };
+        let mut machine = Machine::new(program, RuntimeEnvironment::default());
+        machine.start_at_zero(false);
+        machine.run(None);
+        let failed = matches!(machine.state, MachineState::Error(_));
+        (machine.runtime_env.get_all_raw_logs(), failed)
+    };
+
+    let insn = |opcode, immediate| {
+        Instruction::new(Opcode::AVMOpcode(opcode), immediate, DebugInfo::default())
+    };
+
+    let mut changed = 0;
+    for _ in 0..2000 {
+        let mut code = vec![insn(AVMOpcode::Rset, Some(random_value(&mut rng)))];
+        let mut depth = 6;
+        let mut aux_depth = 0;
+        for _ in 0..depth {
+            code.push(insn(AVMOpcode::Noop, Some(random_value(&mut rng))));
+        }
+
+        for _ in 0..rng.gen_range(1..24) {
+            let (opcode, pops, pushes) = alphabet[rng.gen_range(0..alphabet.len())];
+            let immediate = match opcode == AVMOpcode::Noop || rng.gen_range(0..3) == 0 {
+                true => Some(random_value(&mut rng)),
+                false => None,
+            };
+            let available = depth + immediate.iter().count();
+            let aux_change = match opcode {
+                AVMOpcode::AuxPush => 1,
+                AVMOpcode::AuxPop => -1,
+                _ => 0,
+            };
+            if available < pops || aux_depth + aux_change < 0 {
+                continue;
+            }
+            depth = available - pops + pushes;
+            aux_depth += aux_change;
+            code.push(insn(opcode, immediate));
+        }
+
+        for _ in 0..depth {
+            code.push(insn(AVMOpcode::Log, None));
+        }
+        for _ in 0..aux_depth {
+            code.push(insn(AVMOpcode::AuxPop, None));
+            code.push(insn(AVMOpcode::Log, None));
+        }
+        code.push(insn(AVMOpcode::Halt, None));
+
+        // peephole assumes IsZero is only applied twice to bools, so it's run on both sides
+        let code = peephole(&code);
+        let optimized = schedule(&code);
+        if optimized != code {
+            changed += 1;
+        }
+        let listing = |code: &[Instruction]| {
+            code.iter()
+                .map(|insn| match &insn.immediate {
+                    Some(val) => format!("{} {}", insn.opcode, val),
+                    None => format!("{}", insn.opcode),
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        assert_eq!(
+            execute(&code),
+            execute(&optimized),
+            "scheduling changed the behavior of\n{}\ninto\n{}",
+            listing(&code),
+            listing(&optimized)
+        );
+    }
+    assert!(changed > 0);
+}
@@ -0,0 +1,174 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Lets a func marked `#[override]` take the place of the builtin or library func of the same
+//! name, so that a program can be tested against instrumented versions of low-level funcs without
+//! editing the sources they come from.
+
+use super::fold::relabel;
+use crate::compile::{path_display, CompileError, CompiledFunc, ErrorSystem, Type, TypeTree};
+use crate::console::Color;
+use crate::mavm::{Label, LabelId};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Drops each func an override replaces, sending everything that referred to it to the override
+/// instead. An override replaces the one func of its name in another module, and it's an error
+/// for there to be none or several, for several overrides to replace the same func, or for an
+/// override to take other args or return another type than the func it replaces, as its callers
+/// would then leave the stack other than it expects.
+pub(super) fn apply_overrides(
+    funcs: Vec<CompiledFunc>,
+    type_tree: &TypeTree,
+    error_system: &mut ErrorSystem,
+) -> Vec<CompiledFunc> {
+    let mut replaced: HashMap<LabelId, LabelId> = HashMap::new();
+    let mut overrides: HashMap<LabelId, &CompiledFunc> = HashMap::new();
+    for func in funcs.iter().filter(|func| overrides_func(func)) {
+        let targets: Vec<_> = funcs
+            .iter()
+            .filter(|other| {
+                other.name == func.name && other.path != func.path && !overrides_func(other)
+            })
+            .collect();
+        let target = match targets.as_slice() {
+            [target] => target,
+            [] => {
+                error_system.errors.push(CompileError::new(
+                    "Override error",
+                    format!(
+                        "Func {} overrides nothing, as no other module has a func of its name",
+                        Color::red(&func.name)
+                    ),
+                    func.debug_info.locs(),
+                ));
+                continue;
+            }
+            _ => {
+                error_system.errors.push(CompileError::new(
+                    "Override error",
+                    format!(
+                        "Func {} is an ambiguous override, as {} each have a func of its name",
+                        Color::red(&func.name),
+                        modules(&targets),
+                    ),
+                    func.debug_info.locs(),
+                ));
+                continue;
+            }
+        };
+        if !same_signature(&func.tipe, &target.tipe, type_tree) {
+            error_system.errors.push(CompileError::new(
+                "Override error",
+                format!(
+                    "Func {} has type {}, but overrides a func of {} with type {}",
+                    Color::red(&func.name),
+                    Color::red(func.tipe.print(type_tree)),
+                    Color::red(path_display(&target.path)),
+                    Color::red(target.tipe.print(type_tree)),
+                ),
+                func.debug_info.locs(),
+            ));
+            continue;
+        }
+        if let Some(other) = overrides.insert(target.unique_id, func) {
+            error_system.errors.push(CompileError::new(
+                "Override error",
+                format!(
+                    "Func {} of {} is overridden in both {}",
+                    Color::red(&target.name),
+                    Color::red(path_display(&target.path)),
+                    modules(&[other, func]),
+                ),
+                func.debug_info
+                    .locs()
+                    .into_iter()
+                    .chain(other.debug_info.locs())
+                    .collect(),
+            ));
+            continue;
+        }
+        replaced.insert(target.unique_id, func.unique_id);
+    }
+    if replaced.is_empty() {
+        return funcs;
+    }
+
+    let redirect = |label| match label {
+        Label::Func(id) => Label::Func(*replaced.get(&id).unwrap_or(&id)),
+        label => label,
+    };
+    funcs
+        .into_iter()
+        .filter(|func| !replaced.contains_key(&func.unique_id))
+        .map(|mut func| {
+            func.code = func
+                .code
+                .into_iter()
+                .map(|insn| relabel(insn, &redirect))
+                .collect();
+            let calls = &mut func.calls;
+            calls.direct = rekey(std::mem::take(&mut calls.direct), &replaced, |_, _| {});
+            calls.tail = rekey(std::mem::take(&mut calls.tail), &replaced, |_, _| {});
+            calls.heights = rekey(
+                std::mem::take(&mut calls.heights),
+                &replaced,
+                |kept, (data, aux)| *kept = (kept.0.max(data), kept.1.max(aux)),
+            );
+            func
+        })
+        .collect()
+}
+
+fn overrides_func(func: &CompiledFunc) -> bool {
+    func.debug_info.attributes.overrides
+}
+
+/// Whether two func types take the same args and return the same type. Whether they're view or
+/// write funcs is left out, as an override may well keep counts its target doesn't.
+fn same_signature(left: &Type, right: &Type, type_tree: &TypeTree) -> bool {
+    let same = |left: &Type, right: &Type| {
+        left.assignable(right, type_tree, HashSet::new())
+            && right.assignable(left, type_tree, HashSet::new())
+    };
+    match (left, right) {
+        (Type::Func(_, left_args, left_ret), Type::Func(_, right_args, right_ret)) => {
+            left_args.len() == right_args.len()
+                && left_args
+                    .iter()
+                    .zip(right_args)
+                    .all(|(left, right)| same(left, right))
+                && same(left_ret, right_ret)
+        }
+        _ => false,
+    }
+}
+
+/// Names the modules of funcs, as a list for an error.
+fn modules(funcs: &[&CompiledFunc]) -> String {
+    let paths: Vec<_> = funcs
+        .iter()
+        .map(|func| Color::red(path_display(&func.path)))
+        .collect();
+    paths.join(" and ")
+}
+
+/// Moves the entries of replaced funcs to the funcs replacing them, merging them into any the
+/// replacement already has.
+fn rekey<V>(
+    map: BTreeMap<LabelId, V>,
+    replaced: &HashMap<LabelId, LabelId>,
+    merge: impl Fn(&mut V, V),
+) -> BTreeMap<LabelId, V> {
+    let mut rekeyed = BTreeMap::new();
+    for (id, value) in map {
+        match rekeyed.entry(*replaced.get(&id).unwrap_or(&id)) {
+            Entry::Occupied(mut kept) => merge(kept.get_mut(), value),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+        }
+    }
+    rekeyed
+}
@@ -0,0 +1,183 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the prologue a linked program begins with, which sets up its globals before its entry
+//! point runs. Programs linked for tests and those linked to run as ArbOS differ in one
+//! instruction of it, so both are built here from one sequence. A program linked with an error
+//! handler installs it once its globals are set.
+
+use crate::compile::{CompileError, DebugInfo};
+use crate::console::Color;
+use crate::mavm::{label_id, AVMOpcode, Instruction, Label, LabelId, Opcode, Value};
+
+/// How a linked program is started, which decides what its prologue keeps of the machine it
+/// starts on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Prologue {
+    /// Started on a fresh machine, as tests are, so there's nothing to keep
+    Test,
+    /// Started as ArbOS is, possibly on the machine of an older ArbOS upgrading to it, so the
+    /// register holding that ArbOS's globals is passed to the entry point before it's replaced
+    Production,
+}
+
+impl Prologue {
+    pub(super) fn new(test_mode: bool) -> Self {
+        match test_mode {
+            true => Prologue::Test,
+            false => Prologue::Production,
+        }
+    }
+
+    /// The instructions the program begins with:
+    /// - in production, pushing the register, which the entry point takes as its first argument
+    /// - pushing the unit value, which the entry point takes as the codepoint it returns to, so
+    ///   that returning from it is an error
+    /// - setting the register to the globals, which aren't known until labels are stripped, so
+    ///   the unit value stands in for them until `set_globals` fills them in
+    pub(super) fn code(self, debug_info: DebugInfo) -> Vec<Instruction> {
+        let mut code = vec![];
+        if self == Prologue::Production {
+            code.push(Instruction::from_opcode(
+                Opcode::AVMOpcode(AVMOpcode::Rpush),
+                debug_info,
+            ));
+        }
+        code.push(Instruction::from_opcode_imm(
+            Opcode::AVMOpcode(AVMOpcode::Noop),
+            Value::none(),
+            debug_info,
+        ));
+        code.push(Instruction::from_opcode_imm(
+            Opcode::AVMOpcode(AVMOpcode::Rset),
+            Value::none(),
+            debug_info,
+        ));
+        code
+    }
+
+    /// Where the instruction setting the globals is in the program.
+    fn globals_index(self) -> usize {
+        self.code(DebugInfo::default()).len() - 1
+    }
+
+    /// Fills the globals into the prologue code begins with, failing if the prologue isn't there
+    /// as it was linked, as it won't be if an optimization has moved it.
+    pub(super) fn set_globals(
+        self,
+        code: &mut [Instruction],
+        globals: Value,
+    ) -> Result<(), CompileError> {
+        let index = self.globals_index();
+        match code.get_mut(index) {
+            Some(insn)
+                if insn.opcode == Opcode::AVMOpcode(AVMOpcode::Rset)
+                    && insn.immediate == Some(Value::none()) =>
+            {
+                insn.immediate = Some(globals);
+                Ok(())
+            }
+            insn => Err(CompileError::new(
+                "Postlink error",
+                format!(
+                    "The {:?} prologue should set the globals at codepoint {}, but found {}",
+                    self,
+                    index,
+                    Color::red(match insn {
+                        Some(insn) => insn.pretty_print(Color::RED),
+                        None => String::from("the end of the program"),
+                    }),
+                ),
+                vec![],
+            )),
+        }
+    }
+}
+
+/// The code installing handler as the program's error handler, which follows the prologue, and
+/// after it the stub the machine jumps to on an error, which calls handler with the fault. The
+/// fault is a tuple of:
+/// - the gas left when the error was raised, which is near the most there can be if the error
+///   was running out of it, as the machine then resets it
+/// - whether the data stack was empty
+/// - whether the aux stack was empty
+///
+/// The stub returns to a halt, so a handler that returns halts the machine. The handler stays
+/// installed while it runs, so an error it raises calls it again.
+pub(super) fn error_handler_code(
+    handler: LabelId,
+    entry: LabelId,
+    debug_info: DebugInfo,
+) -> Vec<Instruction> {
+    let stub = label_id(&("/meta", "error handler"));
+    let opcode = |opcode| Instruction::from_opcode(Opcode::AVMOpcode(opcode), debug_info);
+    let opcode_imm =
+        |opcode, value| Instruction::from_opcode_imm(Opcode::AVMOpcode(opcode), value, debug_info);
+    vec![
+        opcode_imm(AVMOpcode::Noop, Value::Label(Label::Func(stub))),
+        opcode(AVMOpcode::ErrSet),
+        opcode_imm(AVMOpcode::Jump, Value::Label(Label::Func(entry))),
+        Instruction::from_opcode(Opcode::Label(Label::Func(stub)), debug_info),
+        opcode(AVMOpcode::StackEmpty),
+        opcode(AVMOpcode::AuxStackEmpty),
+        opcode(AVMOpcode::PushGas),
+        opcode_imm(AVMOpcode::Noop, Value::new_tuple(vec![Value::from(0); 3])),
+        opcode_imm(AVMOpcode::Tset, Value::from(0)),
+        opcode_imm(AVMOpcode::Tset, Value::from(2)),
+        opcode_imm(AVMOpcode::Tset, Value::from(1)),
+        opcode_imm(AVMOpcode::Noop, Value::Label(Label::Anon(stub))),
+        opcode_imm(AVMOpcode::Noop, Value::Label(Label::Func(handler))),
+        opcode(AVMOpcode::Jump),
+        Instruction::from_opcode(Opcode::Label(Label::Anon(stub)), debug_info),
+        opcode(AVMOpcode::Halt),
+    ]
+}
+
+#[test]
+fn test_prologue() {
+    let opcodes =
+        |code: &[Instruction]| -> Vec<Opcode> { code.iter().map(|insn| insn.opcode).collect() };
+    let avm = |opcodes: &[AVMOpcode]| -> Vec<Opcode> {
+        opcodes
+            .iter()
+            .map(|opcode| Opcode::AVMOpcode(*opcode))
+            .collect()
+    };
+
+    // the two prologues are the same but for production's keeping the register
+    let test = Prologue::new(true).code(DebugInfo::default());
+    let production = Prologue::new(false).code(DebugInfo::default());
+    assert_eq!(opcodes(&test), avm(&[AVMOpcode::Noop, AVMOpcode::Rset]));
+    assert_eq!(
+        opcodes(&production),
+        avm(&[AVMOpcode::Rpush, AVMOpcode::Noop, AVMOpcode::Rset])
+    );
+    assert_eq!(production[1..], test[..]);
+
+    for prologue in &[Prologue::Test, Prologue::Production] {
+        let mut code = prologue.code(DebugInfo::default());
+        prologue.set_globals(&mut code, Value::from(7)).unwrap();
+        assert_eq!(code.last().unwrap().immediate, Some(Value::from(7)));
+
+        // globals are only set once, and only where the prologue sets them
+        assert!(prologue.set_globals(&mut code, Value::from(8)).is_err());
+        assert!(prologue
+            .set_globals(&mut code[1..], Value::from(8))
+            .is_err());
+    }
+
+    // the error handler follows the globals being set, leaving where they're set as it was
+    let mut code = Prologue::Test.code(DebugInfo::default());
+    code.extend(error_handler_code(1, 2, DebugInfo::default()));
+    Prologue::Test
+        .set_globals(&mut code, Value::from(7))
+        .unwrap();
+    assert_eq!(code[2].immediate, code[5].get_label().map(Value::Label));
+    assert_eq!(code[3].opcode, Opcode::AVMOpcode(AVMOpcode::ErrSet));
+    assert_eq!(code[4].immediate, Some(Value::Label(Label::Func(2))));
+    assert_eq!(
+        code[code.len() - 4].immediate,
+        Some(Value::Label(Label::Func(1)))
+    );
+}
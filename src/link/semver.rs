@@ -0,0 +1,121 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides a comparison of the interfaces of two compiled programs for release management.
+
+use super::LinkedProgram;
+use std::cmp::Reverse;
+use std::fmt;
+
+/// How much a release changes what depends on a program, in the sense of semantic versioning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bump {
+    /// Nothing depending on the program needs to change
+    Patch,
+    /// The interface grows or its defaults change, but everything that worked still works
+    Minor,
+    /// Something depending on the program may break
+    Major,
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Bump::Patch => write!(f, "patch"),
+            Bump::Minor => write!(f, "minor"),
+            Bump::Major => write!(f, "major"),
+        }
+    }
+}
+
+/// A difference between the interfaces of two programs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub bump: Bump,
+    pub description: String,
+}
+
+/// Compares the interfaces two programs present to the chain, as recorded when they were compiled.
+///
+/// Removing or changing the signature of a precompile method, an event, or a log type is major.
+/// So is removing a chain parameter. Adding any of these is minor, as is changing a chain
+/// parameter's default, which only affects chains created afterward.
+pub fn semver_check(old: &LinkedProgram, new: &LinkedProgram) -> Vec<Change> {
+    let mut changes = vec![];
+    for (name, old_value) in &old.interface {
+        let (kind, what) = describe(name);
+        let (bump, description) = match (new.interface.get(name), kind) {
+            (None, _) => (Bump::Major, format!("{} was removed", what)),
+            (Some(new_value), _) if new_value == old_value => continue,
+            (Some(_), Kind::Signature) => {
+                (Bump::Major, format!("the signature of {} changed", what))
+            }
+            (Some(new_value), Kind::Value) => (
+                Bump::Major,
+                format!("{} changed from {} to {}", what, old_value, new_value),
+            ),
+            (Some(new_value), Kind::Default) => (
+                Bump::Minor,
+                format!(
+                    "the default of {} changed from {} to {}",
+                    what, old_value, new_value
+                ),
+            ),
+        };
+        changes.push(Change { bump, description });
+    }
+    for name in new.interface.keys() {
+        if !old.interface.contains_key(name) {
+            changes.push(Change {
+                bump: Bump::Minor,
+                description: format!("{} was added", describe(name).1),
+            });
+        }
+    }
+    changes.sort_by_key(|change| Reverse(change.bump));
+    changes
+}
+
+/// Gets how much a release with these changes bumps the version.
+pub fn classify(changes: &[Change]) -> Bump {
+    changes
+        .iter()
+        .map(|change| change.bump)
+        .max()
+        .unwrap_or(Bump::Patch)
+}
+
+/// What the value of an interface constant means.
+#[derive(Clone, Copy)]
+enum Kind {
+    /// A hash of a signature, which changes along with the signature
+    Signature,
+    /// A value other programs must agree on
+    Value,
+    /// The default of a chain parameter
+    Default,
+}
+
+/// Describes an interface constant in words.
+fn describe(name: &str) -> (Kind, String) {
+    // method and event names are prefixed by the contracts they belong to
+    let qualified = |rest: &str| match rest.split_once('_') {
+        Some((contract, member)) => format!("{}.{}", contract, member),
+        None => rest.to_string(),
+    };
+    if let Some(rest) = name.strip_prefix("funcCode_") {
+        (
+            Kind::Signature,
+            format!("precompile method {}", qualified(rest)),
+        )
+    } else if let Some(rest) = name.strip_prefix("eventTopic_") {
+        (Kind::Signature, format!("event {}", qualified(rest)))
+    } else if let Some(rest) = name.strip_prefix("Default_Param_") {
+        (Kind::Default, format!("chain parameter {}", rest))
+    } else if let Some(rest) = name.strip_prefix("LogType_") {
+        (Kind::Value, format!("log type {}", rest))
+    } else {
+        (Kind::Value, format!("constant {}", name))
+    }
+}
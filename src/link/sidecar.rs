@@ -0,0 +1,145 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the debug sidecar a mexe's debug info can be split into, so that the mexes shipped to
+//! production stay lean while those running them can still symbolicate their traces.
+
+use crate::compile::FileInfo;
+use crate::link::LinkedProgram;
+use crate::mavm::{stable_hash, AVMOpcode, Instruction};
+use crate::pos::Location;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The extension of the sidecar written beside a mexe.
+pub const SIDECAR_EXTENSION: &str = "mdbg";
+
+/// The debug info split out of a mexe.
+#[derive(Serialize, Deserialize)]
+pub struct DebugSidecar {
+    /// A hash of the opcodes of the mexe the info was split from, so that a stale sidecar isn't
+    /// attached to a different build
+    code_hash: u64,
+    file_info_chart: BTreeMap<u64, FileInfo>,
+    /// The source location of each instruction, by codepoint
+    locations: Vec<Option<Location>>,
+    symbols: BTreeMap<String, Location>,
+}
+
+/// Where the sidecar of the mexe at path goes.
+pub fn sidecar_path(mexe: &Path) -> PathBuf {
+    mexe.with_extension(SIDECAR_EXTENSION)
+}
+
+fn code_hash(code: &[Instruction<AVMOpcode>]) -> u64 {
+    let opcodes: Vec<_> = code.iter().map(|insn| insn.opcode).collect();
+    stable_hash(&opcodes)
+}
+
+impl LinkedProgram {
+    /// Moves the file info chart, the locations of the instructions, and the symbol table out of
+    /// self and into a sidecar.
+    pub fn split_debug_info(&mut self) -> DebugSidecar {
+        let locations = self
+            .code
+            .iter_mut()
+            .map(|insn| insn.debug_info.location.take())
+            .collect();
+        DebugSidecar {
+            code_hash: code_hash(&self.code),
+            file_info_chart: std::mem::take(&mut self.file_info_chart),
+            locations,
+            symbols: std::mem::take(&mut self.symbols),
+        }
+    }
+
+    /// Restores the debug info split out of self, unless the sidecar was split from another
+    /// program.
+    pub fn attach_debug_info(&mut self, sidecar: DebugSidecar) -> Result<(), String> {
+        if sidecar.locations.len() != self.code.len() || sidecar.code_hash != code_hash(&self.code)
+        {
+            return Err(String::from("the sidecar was split from a different mexe"));
+        }
+        for (insn, location) in self.code.iter_mut().zip(sidecar.locations) {
+            insn.debug_info.location = location;
+        }
+        self.file_info_chart = sidecar.file_info_chart;
+        self.symbols = sidecar.symbols;
+        Ok(())
+    }
+}
+
+impl DebugSidecar {
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| e.to_string())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&json).map_err(|e| format!("malformed sidecar: {}", e))
+    }
+}
+
+#[test]
+fn test_debug_sidecar() {
+    use crate::compile::DebugInfo;
+    use crate::link::{SerializableTypeTree, FORMAT_VERSION};
+    use crate::mavm::Value;
+    use crate::pos::Line;
+
+    let location = |line: usize| Location {
+        line: Line::from(line),
+        file_id: 7,
+        ..Location::default()
+    };
+    let program = |opcodes: [AVMOpcode; 2]| {
+        let code = opcodes.iter().enumerate().map(|(line, opcode)| {
+            let mut debug_info = DebugInfo::default();
+            debug_info.location = Some(location(line));
+            Instruction::new(*opcode, None, debug_info)
+        });
+        let mut symbols = BTreeMap::new();
+        symbols.insert(String::from("main::main"), location(0));
+        LinkedProgram {
+            format_version: FORMAT_VERSION,
+            arbos_version: 0,
+            code: code.collect(),
+            static_val: Value::none(),
+            globals: vec![],
+            file_info_chart: BTreeMap::new(),
+            type_tree: SerializableTypeTree::default(),
+            interface: BTreeMap::new(),
+            symbols,
+            defines: BTreeMap::new(),
+        }
+    };
+
+    let mut original = program([AVMOpcode::Noop, AVMOpcode::Pop]);
+    let sidecar = original.split_debug_info();
+    assert!(original
+        .code
+        .iter()
+        .all(|insn| insn.debug_info.location.is_none()));
+    assert!(original.symbols.is_empty());
+
+    let path = std::env::temp_dir().join(format!("mini-sidecar-{}.mdbg", std::process::id()));
+    sidecar.write(&path).unwrap();
+
+    // sidecars only attach to the program they were split from
+    let mut other = program([AVMOpcode::Pop, AVMOpcode::Noop]);
+    other.split_debug_info();
+    assert!(other
+        .attach_debug_info(DebugSidecar::read(&path).unwrap())
+        .is_err());
+
+    original
+        .attach_debug_info(DebugSidecar::read(&path).unwrap())
+        .unwrap();
+    assert_eq!(original.code[1].debug_info.location, Some(location(1)));
+    assert_eq!(original.symbols["main::main"], location(0));
+    std::fs::remove_file(&path).unwrap();
+}
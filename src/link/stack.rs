@@ -0,0 +1,523 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides an analysis of how deep the call stack can grow while a program processes a message.
+
+use super::FuncGraph;
+use crate::compile::{CompileError, ErrorSystem, Lint};
+use crate::console::Color;
+use crate::mavm::{AVMOpcode, Instruction, Label, LabelId, Opcode, Value};
+use crate::pos::Location;
+use petgraph::algo::tarjan_scc;
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::{Dfs, EdgeRef};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+
+/// The calls a func makes, as found in its code before calls are expanded into jumps.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CallSites {
+    /// Funcs called by name, each of which pushes a frame, along with where the first call is made
+    pub direct: BTreeMap<LabelId, Option<Location>>,
+    /// Funcs tail called by name, which replace the caller's frame with their own
+    pub tail: BTreeMap<LabelId, Option<Location>>,
+    /// Where func values are called, whose callees can't be known statically
+    pub indirect: Vec<Option<Location>>,
+    /// The most values the func's own code keeps on the data stack at once, beyond the args and
+    /// return address it's entered with
+    pub data_peak: usize,
+    /// The most values the func's own code keeps on the aux stack at once, its frame included
+    pub aux_peak: usize,
+    /// How many values the func keeps on the data and aux stacks beneath each func it calls by
+    /// name, at the deepest of its calls to it. Tail calls can leave fewer data values than the
+    /// func was entered with.
+    pub heights: BTreeMap<LabelId, (isize, usize)>,
+}
+
+impl CallSites {
+    /// Finds the calls in a func's code, which must not yet have had its calls expanded.
+    pub fn new(code: &[Instruction]) -> Self {
+        let mut calls = CallSites::default();
+        for (index, insn) in code.iter().enumerate() {
+            let location = insn.debug_info.location;
+            match (&insn.opcode, &insn.immediate) {
+                (Opcode::FuncCall(_), _) => {
+                    let callee = match index.checked_sub(1).map(|prior| &code[prior]) {
+                        Some(Instruction {
+                            opcode: Opcode::AVMOpcode(AVMOpcode::Noop),
+                            immediate: Some(Value::Label(Label::Func(id) | Label::Closure(id))),
+                            ..
+                        }) => Some(*id),
+                        _ => None,
+                    };
+                    match callee {
+                        Some(id) => drop(calls.direct.entry(id).or_insert(location)),
+                        None => calls.indirect.push(location),
+                    }
+                }
+                (
+                    Opcode::AVMOpcode(AVMOpcode::Jump),
+                    Some(Value::Label(Label::Func(id) | Label::Closure(id))),
+                ) => drop(calls.tail.entry(*id).or_insert(location)),
+                _ => {}
+            }
+        }
+        calls.measure(code);
+        calls
+    }
+
+    /// Follows each path through a func's code to find how high it stacks values. Codegen leaves
+    /// the stacks equally high at each label, however it's reached, so each instruction is only
+    /// walked once.
+    fn measure(&mut self, code: &[Instruction]) {
+        let labels: HashMap<Label, usize> = code
+            .iter()
+            .enumerate()
+            .filter_map(|(index, insn)| match insn.opcode {
+                Opcode::Label(label) => Some((label, index)),
+                _ => None,
+            })
+            .collect();
+
+        let mut walked = vec![false; code.len()];
+        let mut paths = vec![(0, 0_isize, 0_isize)];
+        let (mut data_peak, mut aux_peak) = (0, 0);
+
+        while let Some((mut index, mut data, mut aux)) = paths.pop() {
+            while index < code.len() && !walked[index] {
+                walked[index] = true;
+                let insn = &code[index];
+                if insn.immediate.is_some() {
+                    data += 1;
+                }
+                data_peak = data_peak.max(data);
+
+                let mut called = |id: LabelId, data: isize, aux: isize| {
+                    let heights = self.heights.entry(id).or_insert((data, 0));
+                    heights.0 = heights.0.max(data);
+                    heights.1 = heights.1.max(aux.max(0) as usize);
+                };
+
+                let (pops, pushes) = match (&insn.opcode, &insn.immediate) {
+                    (Opcode::FuncCall(prop), _) => {
+                        let func = index.checked_sub(1).map(|prior| &code[prior].immediate);
+                        if let Some(Some(Value::Label(Label::Func(id) | Label::Closure(id)))) = func
+                        {
+                            // the func's label makes way for the return address
+                            called(*id, data - 1 + prop.returns as isize, aux);
+                        }
+                        (1 + prop.nargs, prop.nouts)
+                    }
+                    (Opcode::JumpTo(label), _)
+                    | (Opcode::AVMOpcode(AVMOpcode::Jump), Some(Value::Label(label))) => {
+                        match (labels.get(label), label) {
+                            (Some(&target), _) => {
+                                index = target;
+                                data -= 1;
+                                continue;
+                            }
+                            (None, Label::Func(id) | Label::Closure(id)) => {
+                                called(*id, data - 1, aux);
+                                break;
+                            }
+                            _ => break,
+                        }
+                    }
+                    (Opcode::CjumpTo(label), _)
+                    | (Opcode::AVMOpcode(AVMOpcode::Cjump), Some(Value::Label(label))) => {
+                        if let Some(&target) = labels.get(label) {
+                            paths.push((target, data - 2, aux));
+                        }
+                        (2, 0)
+                    }
+                    (Opcode::AVMOpcode(AVMOpcode::AuxPush), _) => {
+                        aux += 1;
+                        aux_peak = aux_peak.max(aux);
+                        (1, 0)
+                    }
+                    (Opcode::AVMOpcode(AVMOpcode::AuxPop), _) => {
+                        aux -= 1;
+                        (0, 1)
+                    }
+                    (Opcode::AVMOpcode(avm), _) => match avm.stack_effect() {
+                        Some(effect) => effect,
+                        None => break,
+                    },
+                    (Opcode::Return, _) => break,
+                    (Opcode::MakeFrame(_, prebuilt), _) => (*prebuilt as usize, 1),
+                    (Opcode::GetLocal(_), _)
+                    | (Opcode::GetGlobalVar(_), _)
+                    | (Opcode::MakeClosure(_), _) => (0, 1),
+                    (Opcode::SetLocal(_), _) | (Opcode::SetGlobalVar(_), _) => (1, 0),
+                    (Opcode::TupleGet(..), _) => (1, 1),
+                    (Opcode::TupleSet(..), _)
+                    | (Opcode::UncheckedFixedArrayGet(_), _)
+                    | (Opcode::Capture(..), _) => (2, 1),
+                    _ => (0, 0),
+                };
+                data += pushes as isize - pops as isize;
+                data_peak = data_peak.max(data);
+                index += 1;
+            }
+        }
+        self.data_peak = data_peak as usize;
+        self.aux_peak = aux_peak as usize;
+    }
+}
+
+/// How deep the stacks can grow from some func, counting its own frame and values.
+#[derive(Clone, Copy, Debug)]
+enum Depth {
+    /// At most this many frames and values, or at least when calls through func values are
+    /// involved
+    Bounded {
+        frames: usize,
+        data: usize,
+        aux: usize,
+        exact: bool,
+    },
+    /// Some path recurses without any limit the analysis can find
+    Unbounded,
+}
+
+/// What a call adds to the stacks beneath its callee: the frame it pushes, if any, and the values
+/// its caller keeps on each stack.
+#[derive(Clone, Copy, Debug)]
+struct Call {
+    frames: usize,
+    data: isize,
+    aux: usize,
+}
+
+impl Call {
+    /// Whether making the call over and over grows the stacks.
+    fn grows(&self) -> bool {
+        self.frames > 0 || self.data > 0 || self.aux > 0
+    }
+}
+
+/// The deepest the stacks can grow from each of a program's entry points, which are main and
+/// each func called through func values.
+#[derive(Debug, Default)]
+pub struct StackReport {
+    entries: Vec<(String, Depth)>,
+}
+
+/// Warns of recursion reachable from an entry point that pushes frames without bound, as well as
+/// of call paths from `entry` whose depth exceeds `budget` frames. Running out of stack while
+/// processing a message halts the machine, so these paths are worth a careful look. Reports how
+/// deep each entry point can take the call, data, and aux stacks.
+///
+/// Tail calls replace their caller's frame and so never deepen the stack. Calls made through func
+/// values aren't followed, so depths involving them are reported as lower bounds, with the funcs
+/// they might reach reported as entry points of their own.
+pub fn check_stack_depth(
+    funcs: &FuncGraph,
+    id_to_node: &HashMap<LabelId, NodeIndex>,
+    entry: NodeIndex,
+    budget: usize,
+    error_system: &mut ErrorSystem,
+) -> StackReport {
+    let mut calls: DiGraph<(), Call> = DiGraph::new();
+    let mut sites = HashMap::new();
+    for _ in funcs.node_indices() {
+        calls.add_node(());
+    }
+    for caller in funcs.node_indices() {
+        let call_sites = &funcs[caller].calls;
+        let direct = call_sites.direct.iter().map(|call| (call, 1));
+        let tail = call_sites.tail.iter().map(|call| (call, 0));
+        for ((callee_id, location), frames) in direct.chain(tail) {
+            if let Some(&callee) = id_to_node.get(callee_id) {
+                if !calls.contains_edge(caller, callee) || frames == 1 {
+                    let (data, aux) = call_sites
+                        .heights
+                        .get(callee_id)
+                        .copied()
+                        .unwrap_or_default();
+                    calls.update_edge(caller, callee, Call { frames, data, aux });
+                    sites.insert((caller, callee), *location);
+                }
+            }
+        }
+    }
+
+    // funcs referred to other than by calls are called through func values
+    let mut entries = vec![entry];
+    for edge in funcs.edge_references() {
+        let (caller, callee) = (&funcs[edge.source()], edge.target());
+        let id = funcs[callee].unique_id;
+        let called = caller.calls.direct.contains_key(&id) || caller.calls.tail.contains_key(&id);
+        if !called && !entries.contains(&callee) {
+            entries.push(callee);
+        }
+    }
+
+    let name = |node: NodeIndex| Color::color(error_system.warn_color, &funcs[node].name);
+    let describe = |path: &[NodeIndex]| {
+        path.iter()
+            .map(|node| name(*node))
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    };
+
+    // the first entry point reaching each func, which paths to it are described from
+    let mut reached_from = HashMap::new();
+    for &entry in &entries {
+        let mut dfs = Dfs::new(&calls, entry);
+        while let Some(node) = dfs.next(&calls) {
+            reached_from.entry(node).or_insert(entry);
+        }
+    }
+
+    let mut depths: HashMap<NodeIndex, Depth> = HashMap::new();
+    let mut deepest: HashMap<NodeIndex, (NodeIndex, NodeIndex)> = HashMap::new();
+    let mut warnings = vec![];
+
+    // components come out of tarjan's algorithm with callees before their callers
+    for component in tarjan_scc(&calls) {
+        let members: HashSet<_> = component.iter().cloned().collect();
+        let member_calls = component.iter().map(|node| &funcs[*node].calls);
+        let mut depth = Depth::Bounded {
+            frames: 1,
+            data: member_calls
+                .clone()
+                .map(|calls| calls.data_peak)
+                .max()
+                .unwrap_or(0),
+            aux: member_calls
+                .clone()
+                .map(|calls| calls.aux_peak)
+                .max()
+                .unwrap_or(0),
+            exact: member_calls.clone().all(|calls| calls.indirect.is_empty()),
+        };
+        let mut best = None;
+        let mut recursive = false;
+
+        for &caller in &component {
+            for edge in calls.edges(caller) {
+                let (callee, call) = (edge.target(), *edge.weight());
+
+                if members.contains(&callee) {
+                    if !call.grows() {
+                        continue;
+                    }
+                    if let (false, Some(&origin)) = (recursive, reached_from.get(&caller)) {
+                        let mut cycle = vec![caller];
+                        cycle.extend(
+                            route(&calls, callee, caller, |node| members.contains(&node))
+                                .unwrap_or_default(),
+                        );
+                        let mut path = route(&calls, origin, caller, |_| true).unwrap_or_default();
+                        path.pop();
+                        path.extend(cycle);
+                        warnings.push(CompileError::new_warning(
+                            Lint::Recursion,
+                            String::from("Compile warning"),
+                            format!(
+                                "func {} can recurse without bound: {}",
+                                name(caller),
+                                describe(&path),
+                            ),
+                            sites[&(caller, callee)].into_iter().collect(),
+                        ));
+                    }
+                    recursive = true;
+                    depth = Depth::Unbounded;
+                    continue;
+                }
+
+                match (depth, depths[&callee]) {
+                    (Depth::Unbounded, _) => {}
+                    (_, Depth::Unbounded) => depth = Depth::Unbounded,
+                    (
+                        Depth::Bounded {
+                            frames,
+                            data,
+                            aux,
+                            exact,
+                        },
+                        Depth::Bounded {
+                            frames: deeper,
+                            data: callee_data,
+                            aux: callee_aux,
+                            exact: callee_exact,
+                        },
+                    ) => {
+                        let deeper = deeper + call.frames;
+                        if deeper > frames {
+                            best = Some((caller, callee));
+                        }
+                        depth = Depth::Bounded {
+                            frames: frames.max(deeper),
+                            data: data.max((call.data + callee_data as isize).max(0) as usize),
+                            aux: aux.max(call.aux + callee_aux),
+                            exact: exact && callee_exact,
+                        };
+                    }
+                }
+            }
+        }
+
+        for &node in &component {
+            depths.insert(node, depth);
+            if let Some(best) = best {
+                deepest.insert(node, best);
+            }
+        }
+    }
+
+    if let Depth::Bounded { frames, exact, .. } = depths[&entry] {
+        if frames > budget {
+            let mut path = vec![entry];
+            while let Some(&(caller, callee)) = deepest.get(path.last().unwrap()) {
+                let hops = route(&calls, *path.last().unwrap(), caller, |node| {
+                    deepest.get(&node) == Some(&(caller, callee))
+                })
+                .unwrap_or_default();
+                path.extend(hops.into_iter().skip(1));
+                path.push(callee);
+            }
+            let last_call = path
+                .windows(2)
+                .last()
+                .and_then(|hop| sites[&(hop[0], hop[1])]);
+            warnings.push(CompileError::new_warning(
+                Lint::StackBudget,
+                String::from("Compile warning"),
+                format!(
+                    "call stack can grow to {}{} frames, exceeding the budget of {}: {}",
+                    match exact {
+                        true => "",
+                        false => "at least ",
+                    },
+                    frames,
+                    budget,
+                    describe(&path),
+                ),
+                last_call.into_iter().collect(),
+            ));
+        }
+    }
+
+    error_system.warnings.extend(warnings);
+    StackReport {
+        entries: entries
+            .into_iter()
+            .map(|node| (funcs[node].name.clone(), depths[&node]))
+            .collect(),
+    }
+}
+
+impl fmt::Display for StackReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Deepest stacks, beyond the args of each entry point:")?;
+        for (name, depth) in &self.entries {
+            match depth {
+                Depth::Bounded {
+                    frames,
+                    data,
+                    aux,
+                    exact,
+                } => write!(
+                    f,
+                    "\n    {}: {}{} frames, {} data values, {} aux values",
+                    name,
+                    match exact {
+                        true => "",
+                        false => "at least ",
+                    },
+                    frames,
+                    data,
+                    aux
+                )?,
+                Depth::Unbounded => write!(f, "\n    {}: unbounded", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Finds the shortest path of calls from `from` to `to` passing only through nodes accepted by
+/// `within`.
+fn route<F>(
+    calls: &DiGraph<(), Call>,
+    from: NodeIndex,
+    to: NodeIndex,
+    within: F,
+) -> Option<Vec<NodeIndex>>
+where
+    F: Fn(NodeIndex) -> bool,
+{
+    let mut prior = HashMap::new();
+    let mut queue = VecDeque::new();
+    prior.insert(from, from);
+    queue.push_back(from);
+
+    while let Some(node) = queue.pop_front() {
+        if node == to {
+            let mut path = vec![node];
+            while *path.last().unwrap() != from {
+                path.push(prior[path.last().unwrap()]);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for next in calls.neighbors(node) {
+            if within(next) && !prior.contains_key(&next) {
+                prior.insert(next, node);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+#[test]
+fn test_stack_heights() {
+    use crate::compile::{DebugInfo, FuncProperties};
+
+    let debug_info = DebugInfo::default();
+    let op = |opcode: Opcode| Instruction::from_opcode(opcode, debug_info);
+    let avm = |opcode: AVMOpcode| op(Opcode::AVMOpcode(opcode));
+    let push =
+        |opcode: Opcode, value: Value| Instruction::from_opcode_imm(opcode, value, debug_info);
+    let noop = |value: Value| push(Opcode::AVMOpcode(AVMOpcode::Noop), value);
+    let branch = Label::Anon(7);
+    let call = FuncProperties::new(false, false, false, false, true, 2, 1);
+
+    let code = vec![
+        op(Opcode::Label(Label::Func(1))),
+        avm(AVMOpcode::AuxPush),
+        op(Opcode::MakeFrame(1, false)),
+        avm(AVMOpcode::AuxPush),
+        op(Opcode::SetLocal(0)),
+        op(Opcode::GetLocal(0)),
+        push(Opcode::CjumpTo(branch), Value::Label(branch)),
+        // tail call, handing over the return address
+        noop(Value::from(4)),
+        avm(AVMOpcode::AuxPop),
+        avm(AVMOpcode::Pop),
+        avm(AVMOpcode::AuxPop),
+        push(
+            Opcode::AVMOpcode(AVMOpcode::Jump),
+            Value::Label(Label::Func(3)),
+        ),
+        op(Opcode::Label(branch)),
+        op(Opcode::GetLocal(0)),
+        noop(Value::from(3)),
+        noop(Value::Label(Label::Func(2))),
+        op(Opcode::FuncCall(call)),
+        op(Opcode::Return),
+    ];
+
+    let calls = CallSites::new(&code);
+    assert_eq!(calls.data_peak, 1);
+    assert_eq!(calls.aux_peak, 2);
+    assert_eq!(calls.heights[&2], (1, 2));
+    assert_eq!(calls.heights[&3], (0, 0));
+    assert!(calls.direct.contains_key(&2) && calls.tail.contains_key(&3));
+}
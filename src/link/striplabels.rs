@@ -4,20 +4,22 @@
 
 //! Provides utilities used in the `postlink_compile` function
 
+use super::constpool::ConstantPool;
+use super::jumplayout::JumpTableLayout;
 use crate::compile::CompileError;
-use crate::mavm::{AVMOpcode, CodePt, Instruction, Label, Opcode, Value};
+use crate::mavm::{AVMOpcode, CodePt, Instruction, Opcode, Value};
 use std::collections::{HashMap, HashSet};
 
 /// Replaces labels with code points in code_in, and in copies of jump_table. A
 /// tuple of these modified values is returned if the function is successful, and the label causing
-/// the error is returned otherwise.
+/// the error is returned otherwise. The pooled constants in the jump table are kept as they are.
 ///
 /// The maybe_evm_pcs argument appends a list of PCs to the immediate of the first instruction, if
 /// set to Some, this should only be done for modules.
 pub fn strip_labels(
     code_in: Vec<Instruction>,
-    jump_table: &[Label],
-) -> Result<(Vec<Instruction>, Vec<CodePt>), CompileError> {
+    jump_table: &[Value],
+) -> Result<(Vec<Instruction>, Vec<Value>), CompileError> {
     let mut label_map = HashMap::new();
 
     let mut after_count = 0;
@@ -59,11 +61,11 @@ pub fn strip_labels(
 
     let mut jump_table_out = Vec::new();
     for jt_item in jump_table {
-        match label_map.get(&jt_item) {
-            Some(index) => {
-                jump_table_out.push(*index);
+        match jt_item.clone().replace_labels(&label_map) {
+            Ok(value) => {
+                jump_table_out.push(value);
             }
-            None => {
+            Err(_) => {
                 return Err(CompileError::new(
                     String::from("Compile error: strip_labels"),
                     format!("lookup failed for jump table item: {:?}", jt_item),
@@ -81,12 +83,21 @@ pub fn strip_labels(
 ///
 /// Returns the modified set of instructions and a vector of labels in order of appearance in the
 /// code.
-pub fn fix_backward_labels(
+///
+/// The constants in pool are looked up from the jump table the same way, rather than each
+/// instruction using one holding a copy, with the table holding one copy of each.
+///
+/// Once the table's entries are known, layout decides how they're nested, and each lookup walks
+/// the nesting it gives, which is returned along with the table.
+pub(super) fn fix_backward_labels(
     code_in: &[Instruction],
     jump_table_index_in_globals: usize,
-) -> (Vec<Instruction>, Vec<Label>) {
+    pool: &ConstantPool,
+    layout: impl FnOnce(&[Value]) -> JumpTableLayout,
+) -> (Vec<Instruction>, Vec<Value>, JumpTableLayout) {
     let mut jump_table = Vec::new();
     let mut jump_table_index = HashMap::new();
+    let mut pool_index = HashMap::new();
     let mut imm_labels_seen = HashSet::new();
     let mut code_out = Vec::new();
 
@@ -104,7 +115,7 @@ pub fn fix_backward_labels(
                             None => {
                                 let index = jump_table.len();
                                 jump_table_index.insert(label.clone(), index);
-                                jump_table.push(label);
+                                jump_table.push(Value::Label(label));
                                 index
                             }
                         };
@@ -125,13 +136,30 @@ pub fn fix_backward_labels(
                         ));
                     }
                 }
-                _ => {
-                    code_out.push(Instruction::from_opcode_imm(
-                        insn_in.opcode,
-                        val,
-                        insn_in.debug_info,
-                    ));
-                }
+                _ => match pool.get(&val) {
+                    Some((key, pooled)) if matches!(insn_in.opcode, Opcode::AVMOpcode(_)) => {
+                        let idx = *pool_index.entry(key).or_insert_with(|| {
+                            jump_table.push(pooled.clone());
+                            jump_table.len() - 1
+                        });
+                        code_out.push(Instruction::from_opcode(
+                            Opcode::GetGlobalVar(jump_table_index_in_globals),
+                            insn_in.debug_info,
+                        ));
+                        code_out.push(Instruction::from_opcode(
+                            Opcode::BackwardLabelTarget(idx),
+                            insn_in.debug_info,
+                        ));
+                        code_out.push(Instruction::from_opcode(insn_in.opcode, insn_in.debug_info));
+                    }
+                    _ => {
+                        code_out.push(Instruction::from_opcode_imm(
+                            insn_in.opcode,
+                            val,
+                            insn_in.debug_info,
+                        ));
+                    }
+                },
             },
             None => {
                 code_out.push(Instruction::from_opcode(insn_in.opcode, insn_in.debug_info));
@@ -142,6 +170,7 @@ pub fn fix_backward_labels(
         }
     }
 
+    let layout = layout(&jump_table);
     let mut code_xformed = Vec::new();
     for insn in code_out.iter() {
         match insn.opcode {
@@ -153,10 +182,7 @@ pub fn fix_backward_labels(
                         insn.debug_info,
                     ));
                 }
-                code_xformed.push(Instruction::from_opcode(
-                    Opcode::TupleGet(index, jump_table.len()),
-                    insn.debug_info,
-                ));
+                code_xformed.extend(layout.lookup_code(index, insn.debug_info));
             }
             _ => {
                 code_xformed.push(insn.clone());
@@ -164,5 +190,5 @@ pub fn fix_backward_labels(
         }
     }
 
-    (code_xformed, jump_table)
+    (code_xformed, jump_table, layout)
 }
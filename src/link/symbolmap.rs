@@ -0,0 +1,126 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the symbol map postlink compilation can write beside a mexe, naming the func each
+//! codepoint belongs to, so that the codepoints in profiles, replay traces, and crash dumps can be
+//! read without walking the mexe by hand.
+
+use super::jumplayout::target_sites;
+use crate::compile::{path_display, CompiledFunc, FileInfo};
+use crate::mavm::{Instruction, Label, LabelId, Opcode, Value};
+use crate::pos::Location;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The extension of the symbol map written beside a mexe.
+pub const SYMBOL_MAP_EXTENSION: &str = "symbols.json";
+
+/// Where the symbol map of the mexe at path goes.
+pub fn symbol_map_path(mexe: &Path) -> PathBuf {
+    mexe.with_extension(SYMBOL_MAP_EXTENSION)
+}
+
+/// The name of each func and where it's declared, by label id.
+pub type FuncNames = BTreeMap<LabelId, (String, Option<Location>)>;
+
+/// Names funcs by their paths, so that they can be found by label once they've been linked.
+pub fn func_names(funcs: &[CompiledFunc]) -> FuncNames {
+    funcs
+        .iter()
+        .map(|func| {
+            let name = format!("{}::{}", path_display(&func.path), func.name);
+            (func.unique_id, (name, func.debug_info.location))
+        })
+        .collect()
+}
+
+/// The funcs of a mexe, in the order their code appears.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SymbolMap {
+    pub funcs: Vec<SymbolEntry>,
+}
+
+/// Where a func's code lies in a mexe, and where it's declared.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SymbolEntry {
+    /// The codepoint of the func's first instruction
+    pub start: usize,
+    /// The codepoint just past the func's last instruction
+    pub end: usize,
+    pub name: String,
+    /// The path of the source file the func is declared in
+    pub file: Option<String>,
+    /// The line the func is declared on, counted from one as errors show them
+    pub line: Option<usize>,
+    /// The codepoints of the func's entries in the jump table, in the order their code appears,
+    /// which jump profiles number the entries by
+    #[serde(default)]
+    pub targets: Vec<usize>,
+}
+
+impl SymbolMap {
+    /// Finds where each func begins in code whose labels have yet to be stripped, along with where
+    /// its entries in the jump table lead. A func's code runs until the next func's begins, or
+    /// until the end of the program.
+    pub(super) fn new(
+        code: &[Instruction],
+        jump_table: &[Value],
+        names: &FuncNames,
+        file_info_chart: &BTreeMap<u64, FileInfo>,
+    ) -> Self {
+        let mut starts = vec![];
+        let mut codepoint = 0;
+        for insn in code {
+            match insn.opcode {
+                Opcode::Label(Label::Func(id) | Label::Closure(id)) => starts.push((codepoint, id)),
+                Opcode::Label(_) => {}
+                _ => codepoint += 1,
+            }
+        }
+
+        let mut targets: BTreeMap<LabelId, Vec<usize>> = BTreeMap::new();
+        for site in target_sites(code, jump_table) {
+            targets.entry(site.func).or_default().push(site.codepoint);
+        }
+
+        let ends: Vec<usize> = starts
+            .iter()
+            .skip(1)
+            .map(|(start, _)| *start)
+            .chain(std::iter::once(codepoint))
+            .collect();
+        let funcs = starts
+            .into_iter()
+            .zip(ends)
+            .filter_map(|((start, id), end)| {
+                let (name, location) = names.get(&id)?;
+                let file = location
+                    .and_then(|location| file_info_chart.get(&location.file_id))
+                    .map(|info| info.path.clone());
+                Some(SymbolEntry {
+                    start,
+                    end,
+                    name: name.clone(),
+                    file,
+                    line: location.map(|location| location.line.to_usize() + 1),
+                    targets: targets.remove(&id).unwrap_or_default(),
+                })
+            })
+            .collect();
+        SymbolMap { funcs }
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| e.to_string())
+    }
+}
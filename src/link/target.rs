@@ -0,0 +1,44 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Checks that a program only uses opcodes the AVM of the ArbOS version it targets can run, so
+//! that patch releases for older chains can be built with the current toolchain.
+
+use crate::compile::{CompileError, CompiledProgram};
+use crate::console::Color;
+use crate::mavm::{AVMOpcode, Opcode};
+use crate::pos::Location;
+use std::collections::BTreeMap;
+
+/// Reports each opcode the program uses that's newer than the targeted ArbOS version, along with
+/// where it's used. Opcodes come from mini's builtins and asm blocks alike, so both are covered.
+pub fn check_target_version(program: &CompiledProgram, target: u64) -> Vec<CompileError> {
+    let mut newer: BTreeMap<u8, (AVMOpcode, Vec<Location>)> = BTreeMap::new();
+    for insn in &program.code {
+        if let Opcode::AVMOpcode(opcode) = insn.opcode {
+            if opcode.arbos_version() > target {
+                let (_, locations) = newer
+                    .entry(opcode.to_number())
+                    .or_insert_with(|| (opcode, vec![]));
+                locations.extend(insn.debug_info.location);
+            }
+        }
+    }
+
+    newer
+        .into_iter()
+        .map(|(_, (opcode, locations))| {
+            CompileError::new(
+                "Target error",
+                format!(
+                    "Opcode {} needs ArbOS version {}, but the target is version {}",
+                    Color::red(Opcode::AVMOpcode(opcode).to_name()),
+                    Color::red(opcode.arbos_version()),
+                    Color::red(target),
+                ),
+                locations,
+            )
+        })
+        .collect()
+}
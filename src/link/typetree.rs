@@ -0,0 +1,413 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides a stable, structured serialization of a program's `TypeTree` along with the queries
+//! needed by tools that inspect linked programs.
+
+use crate::compile::{CompileError, Type, TypeTree};
+use crate::link::TupleTree;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
+use std::io;
+
+/// A single nominal type declaration, identified by the path of the module declaring it and the
+/// `StringId` of its name within that module.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TypeTreeEntry {
+    pub path: Vec<String>,
+    pub id: usize,
+    pub tipe: Type,
+    pub name: String,
+}
+
+/// A serializable form of `TypeTree`, stored as a list of entries sorted by path and id.
+///
+/// Older programs encoded the tree as a map keyed by "comma, separated" strings; these are still
+/// accepted on deserialization and converted to the structured form.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(try_from = "TypeTreeRepr")]
+pub struct SerializableTypeTree {
+    entries: Vec<TypeTreeEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TypeTreeRepr {
+    Structured {
+        entries: Vec<TypeTreeEntry>,
+    },
+    Legacy {
+        inner: BTreeMap<String, (Type, String)>,
+    },
+}
+
+impl TryFrom<TypeTreeRepr> for SerializableTypeTree {
+    type Error = String;
+
+    fn try_from(repr: TypeTreeRepr) -> Result<Self, Self::Error> {
+        let entries = match repr {
+            TypeTreeRepr::Structured { entries } => entries,
+            TypeTreeRepr::Legacy { inner } => inner
+                .into_iter()
+                .map(|(key, (tipe, name))| {
+                    let mut path: Vec<_> = key.split(", ").map(|val| val.to_string()).collect();
+                    let id = path
+                        .pop()
+                        .and_then(|id| id.parse::<usize>().ok())
+                        .ok_or_else(|| format!("type tree key \"{}\" doesn't end in an id", key))?;
+                    Ok(TypeTreeEntry {
+                        path,
+                        id,
+                        tipe,
+                        name,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+        };
+        Ok(Self::from_entries(entries))
+    }
+}
+
+/// Describes how a value of some type is laid out in AVM memory.
+///
+/// Tuples and structs are expanded into their fields, while all other types occupy a single slot
+/// whose contents are opaque to the layout (fixed arrays are reported with their element count).
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeLayout {
+    pub name: String,
+    pub tipe: Type,
+    pub fields: Vec<TypeLayout>,
+}
+
+impl TypeLayout {
+    /// Returns the number of leaf values, or words, needed to represent the type.
+    pub fn size(&self) -> usize {
+        match &self.tipe {
+            _ if !self.fields.is_empty() => self.fields.iter().map(|field| field.size()).sum(),
+            Type::Tuple(_) | Type::Struct(_) | Type::Void => 0,
+            _ => 1,
+        }
+    }
+
+    /// Returns the dotted name, tuple slot path, and type of every leaf in the layout.
+    ///
+    /// Slot paths account for the wide-tuple transform, so tuples with more than `TUPLE_SIZE`
+    /// fields are reported with the nesting they have in the final program.
+    pub fn leaves(&self) -> Vec<(String, Vec<usize>, Type)> {
+        let mut leaves = vec![];
+        self.collect_leaves(&self.name, &mut vec![], &mut leaves);
+        leaves
+    }
+
+    fn collect_leaves(
+        &self,
+        prefix: &str,
+        offsets: &mut Vec<usize>,
+        leaves: &mut Vec<(String, Vec<usize>, Type)>,
+    ) {
+        if self.fields.is_empty() {
+            leaves.push((prefix.to_string(), offsets.clone(), self.tipe.clone()));
+            return;
+        }
+        let shape = TupleTree::new(self.fields.len(), false);
+        for (index, field) in self.fields.iter().enumerate() {
+            let depth = offsets.len();
+            offsets.extend(shape.slot_path(index).unwrap_or_default());
+            let name = match prefix {
+                "" => field.name.clone(),
+                _ => format!("{}.{}", prefix, field.name),
+            };
+            field.collect_leaves(&name, offsets, leaves);
+            offsets.truncate(depth);
+        }
+    }
+}
+
+impl SerializableTypeTree {
+    pub fn from_type_tree(tree: TypeTree) -> Self {
+        Self::from_entries(
+            tree.into_iter()
+                .map(|((path, id), (tipe, name))| TypeTreeEntry {
+                    path,
+                    id,
+                    tipe,
+                    name,
+                })
+                .collect(),
+        )
+    }
+
+    /// Merges trees into one holding every type any of them declares. A type declared by more
+    /// than one is taken from the first that declares it, so merging the same trees in the same
+    /// order always gives the same tree.
+    pub fn merge<'a>(trees: impl IntoIterator<Item = &'a TypeTree>) -> Self {
+        let mut merged = TypeTree::new();
+        for tree in trees {
+            for (key, entry) in tree {
+                if !merged.contains_key(key) {
+                    merged.insert(key.clone(), entry.clone());
+                }
+            }
+        }
+        Self::from_type_tree(merged)
+    }
+
+    pub fn into_type_tree(self) -> TypeTree {
+        self.entries
+            .into_iter()
+            .map(|entry| ((entry.path, entry.id), (entry.tipe, entry.name)))
+            .collect()
+    }
+
+    fn from_entries(mut entries: Vec<TypeTreeEntry>) -> Self {
+        entries.sort_by(|a, b| (&a.path, a.id).cmp(&(&b.path, b.id)));
+        Self { entries }
+    }
+
+    /// Looks up the type declared in module `path` with the given `id`.
+    pub fn get(&self, path: &[String], id: usize) -> Option<&TypeTreeEntry> {
+        self.entries
+            .binary_search_by(|entry| (entry.path.as_slice(), entry.id).cmp(&(path, id)))
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+
+    /// Looks up a type by its declared name within module `path`.
+    pub fn get_by_name(&self, path: &[String], name: &str) -> Option<&TypeTreeEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path && entry.name == name)
+    }
+
+    /// Looks up a type by its fully qualified name, such as `std::bytearray::ByteArray`.
+    pub fn lookup(&self, qualified: &str) -> Option<&TypeTreeEntry> {
+        let mut path: Vec<_> = qualified.split("::").map(|s| s.to_string()).collect();
+        let name = path.pop()?;
+        self.get_by_name(&path, &name)
+    }
+
+    /// Enumerates the types declared in module `path`.
+    pub fn types_in_module<'a>(
+        &'a self,
+        path: &'a [String],
+    ) -> impl Iterator<Item = &'a TypeTreeEntry> + 'a {
+        self.entries.iter().filter(move |entry| entry.path == path)
+    }
+
+    /// Returns the paths of every module that declares at least one type.
+    pub fn modules(&self) -> Vec<Vec<String>> {
+        let mut modules: Vec<Vec<String>> = vec![];
+        for entry in &self.entries {
+            if modules.last() != Some(&entry.path) {
+                modules.push(entry.path.clone());
+            }
+        }
+        modules
+    }
+
+    /// Computes the memory layout of `tipe`, resolving nominal types through this tree.
+    pub fn layout(&self, tipe: &Type) -> Result<TypeLayout, CompileError> {
+        let tree = self.clone().into_type_tree();
+        layout_of(String::new(), tipe, &tree, &mut HashSet::new())
+    }
+
+    /// Writes the layout of every struct type in the tree to output. The format argument selects
+    /// between a human readable listing, the default for None, and json.
+    ///
+    /// Since imported types appear in the tree once per importing module, identical declarations
+    /// are reported once along with the list of modules they appear in.
+    pub fn write_layouts(
+        &self,
+        output: &mut dyn io::Write,
+        format: Option<&str>,
+    ) -> io::Result<()> {
+        let type_tree = self.clone().into_type_tree();
+        let mut layouts: Vec<(&String, &Type, Vec<String>)> = vec![];
+        for entry in &self.entries {
+            if let Type::Struct(_) = entry.tipe {
+                let module = entry.path.join("::");
+                match layouts
+                    .iter_mut()
+                    .find(|(name, tipe, _)| *name == &entry.name && *tipe == &entry.tipe)
+                {
+                    Some((_, _, modules)) => modules.push(module),
+                    None => layouts.push((&entry.name, &entry.tipe, vec![module])),
+                }
+            }
+        }
+        layouts.sort_by(|a, b| (a.0, &a.2).cmp(&(b.0, &b.2)));
+
+        let mut laid_out = vec![];
+        for (name, tipe, modules) in layouts {
+            match self.layout(tipe) {
+                Ok(layout) => laid_out.push((name, modules, layout)),
+                Err(err) => writeln!(output, "could not lay out {}: {}", name, err)?,
+            }
+        }
+
+        match format {
+            None | Some("text") => {
+                for (name, modules, layout) in laid_out {
+                    writeln!(
+                        output,
+                        "{} ({} words, {} bytes)",
+                        name,
+                        layout.size(),
+                        32 * layout.size()
+                    )?;
+                    writeln!(output, "    in {}", modules.join(", "))?;
+                    for (field, path, tipe) in layout.leaves() {
+                        let path = format!("{:?}", path);
+                        writeln!(
+                            output,
+                            "    {:<12} {:<32} {}",
+                            path,
+                            field,
+                            tipe.print(&type_tree).trim_end()
+                        )?;
+                    }
+                    writeln!(output)?;
+                }
+            }
+            Some("json") => {
+                let layouts: Vec<_> = laid_out
+                    .into_iter()
+                    .map(|(name, modules, layout)| {
+                        let fields: Vec<_> = layout
+                            .leaves()
+                            .into_iter()
+                            .map(|(field, path, tipe)| {
+                                serde_json::json!({
+                                    "name": field,
+                                    "path": path,
+                                    "type": tipe.print(&type_tree),
+                                })
+                            })
+                            .collect();
+                        serde_json::json!({
+                            "type": name,
+                            "modules": modules,
+                            "words": layout.size(),
+                            "bytes": 32 * layout.size(),
+                            "fields": fields,
+                        })
+                    })
+                    .collect();
+                writeln!(output, "{}", serde_json::Value::from(layouts))?;
+            }
+            Some(weird_value) => writeln!(output, "invalid format: {}", weird_value)?,
+        }
+        Ok(())
+    }
+}
+
+fn layout_of(
+    name: String,
+    tipe: &Type,
+    tree: &TypeTree,
+    seen: &mut HashSet<(Vec<String>, usize)>,
+) -> Result<TypeLayout, CompileError> {
+    let (tipe, key) = match tipe {
+        Type::Nominal(path, id, _) => (tipe.rep(tree)?, Some((path.clone(), *id))),
+        _ => (tipe.clone(), None),
+    };
+
+    // recursive types can't be expanded, so we stop at the first repeat
+    if let Some(key) = &key {
+        if !seen.insert(key.clone()) {
+            return Ok(TypeLayout {
+                name,
+                tipe,
+                fields: vec![],
+            });
+        }
+    }
+
+    let fields = match &tipe {
+        Type::Tuple(types) => types
+            .iter()
+            .enumerate()
+            .map(|(index, field)| layout_of(index.to_string(), field, tree, seen))
+            .collect::<Result<_, _>>()?,
+        Type::Struct(fields) => fields
+            .iter()
+            .map(|field| layout_of(field.name.clone(), &field.tipe, tree, seen))
+            .collect::<Result<_, _>>()?,
+        _ => vec![],
+    };
+
+    if let Some(key) = &key {
+        seen.remove(key);
+    }
+
+    Ok(TypeLayout { name, tipe, fields })
+}
+
+#[test]
+fn test_type_tree_serialization() {
+    use crate::compile::StructField;
+
+    let path = vec!["std".to_string(), "queue".to_string()];
+    let queue = Type::Struct(vec![
+        StructField::new("size".to_string(), Type::Uint),
+        StructField::new(
+            "contents".to_string(),
+            Type::Tuple(vec![Type::Bool, Type::Any]),
+        ),
+    ]);
+    let mut tree = TypeTree::new();
+    tree.insert((path.clone(), 7), (queue.clone(), "Queue".to_string()));
+    tree.insert(
+        (vec!["main".to_string()], 3),
+        (Type::Uint, "Id".to_string()),
+    );
+
+    let serializable = SerializableTypeTree::from_type_tree(tree.clone());
+    let json = serde_json::to_string(&serializable).unwrap();
+    let parsed: SerializableTypeTree = serde_json::from_str(&json).unwrap();
+    assert!(parsed.into_type_tree() == tree);
+
+    // the first tree to declare a type is the one it's taken from
+    let mut other = TypeTree::new();
+    other.insert((path.clone(), 7), (Type::Bool, "Flag".to_string()));
+    other.insert((path.clone(), 8), (Type::Bool, "Flag".to_string()));
+    let merged = SerializableTypeTree::merge(vec![&tree, &other]);
+    assert_eq!(merged.get(&path, 7).map(|e| e.name.as_str()), Some("Queue"));
+    assert_eq!(merged.get(&path, 8).map(|e| e.name.as_str()), Some("Flag"));
+    assert_eq!(merged.modules().len(), 2);
+
+    let legacy = format!(
+        "{{\"inner\":{{\"std, queue, 7\":[{},\"Queue\"]}}}}",
+        serde_json::to_string(&queue).unwrap()
+    );
+    let parsed: SerializableTypeTree = serde_json::from_str(&legacy).unwrap();
+    assert_eq!(parsed.get(&path, 7).map(|e| e.name.as_str()), Some("Queue"));
+    assert_eq!(parsed.lookup("std::queue::Queue").map(|e| e.id), Some(7));
+
+    // malformed keys are rejected rather than panicking
+    let malformed = legacy.replace("std, queue, 7", "std, queue");
+    assert!(serde_json::from_str::<SerializableTypeTree>(&malformed).is_err());
+
+    let layout = serializable
+        .layout(&Type::Nominal(path.clone(), 7, vec![]))
+        .unwrap();
+    assert_eq!(layout.size(), 3);
+    let leaves: Vec<_> = layout.leaves().into_iter().map(|l| l.1).collect();
+    assert_eq!(leaves, vec![vec![0], vec![1, 0], vec![1, 1]]);
+    assert_eq!(serializable.modules().len(), 2);
+
+    let wide = serializable
+        .layout(&Type::Tuple(vec![Type::Uint; 10]))
+        .unwrap();
+    let paths: Vec<_> = wide.leaves().into_iter().map(|l| l.1).collect();
+    assert_eq!(paths.len(), 10);
+    assert!(paths.iter().all(|path| path[0] < crate::link::TUPLE_SIZE));
+    assert_eq!(paths.iter().collect::<HashSet<_>>().len(), 10);
+
+    let mut listing = vec![];
+    serializable.write_layouts(&mut listing, None).unwrap();
+    let listing = String::from_utf8(listing).unwrap();
+    assert!(listing.starts_with("Queue (3 words, 96 bytes)"));
+}
@@ -118,29 +118,31 @@ pub fn fix_tuple_size(
         // replace any wide immediate values
 
         if let Some(ref mut value) = curr.immediate {
-            value.replace2(&mut |value| match value {
-                Value::Tuple(tup) if tup.len() >= 8 => {
-                    let folded = TupleTree::fold_into_tuple(tup.to_vec());
-                    *value = folded;
-                }
-                _ => {}
-            });
+            fold_wide_tuples(value);
         }
     }
 
     Ok(code_out)
 }
 
+/// Replaces the tuples within value wider than TUPLE_SIZE with nested tuples, as the AVM holds them.
+pub fn fold_wide_tuples(value: &mut Value) {
+    value.replace2(&mut |value| match value {
+        Value::Tuple(tup) if tup.len() >= 8 => {
+            let folded = TupleTree::fold_into_tuple(tup.to_vec());
+            *value = folded;
+        }
+        _ => {}
+    });
+}
+
 /// Used for generating the static_val for a `LinkedProgram`.
 ///
-/// Takes a vector of codepoints, and places them in order into a nested tuple `Value`
-pub fn jump_table_to_value(jump_table: Vec<CodePt>) -> Value {
-    let mut jump_table_codepoints = Vec::new();
-    for pc in &jump_table {
-        jump_table_codepoints.push(Value::CodePoint(*pc));
-    }
+/// Takes a vector of codepoints and pooled constants, and places them in order into a nested tuple
+/// `Value`
+pub fn jump_table_to_value(jump_table: Vec<Value>) -> Value {
     let shape = TupleTree::new(jump_table.len(), false);
-    shape.make_value(jump_table_codepoints)
+    shape.make_value(jump_table)
 }
 
 /// Generates a `Value` that is a nested tuple with size total leaf values, all leaf values are null.
@@ -210,7 +212,7 @@ pub fn set_error_codepoints(mut code: Vec<Instruction>) -> Vec<Instruction> {
 }
 
 /// Represents tuple structure of mini value.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TupleTree {
     Single,
     Tree(usize, Vec<TupleTree>),
@@ -296,6 +298,29 @@ impl TupleTree {
         }
     }
 
+    /// Returns the sequence of tuple slots that must be traversed to reach the index-th element of
+    /// self, or None if index is out of bounds.
+    pub fn slot_path(&self, index: usize) -> Option<Vec<usize>> {
+        match self {
+            TupleTree::Single => match index {
+                0 => Some(vec![]),
+                _ => None,
+            },
+            TupleTree::Tree(_, v) => {
+                let mut index = index;
+                for (slot, subtree) in v.iter().enumerate() {
+                    if index < subtree.tsize() {
+                        let mut path = vec![slot];
+                        path.extend(subtree.slot_path(index)?);
+                        return Some(path);
+                    }
+                    index -= subtree.tsize();
+                }
+                None
+            }
+        }
+    }
+
     /// Gets the total number of nodes in the `TupleTree`
     fn tsize(&self) -> usize {
         match self {
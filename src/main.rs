@@ -5,22 +5,27 @@
 #![allow(unused_parens)]
 
 use crate::compile::miniconstants::make_parameters_list;
-use crate::compile::CompileStruct;
-use crate::link::LinkedProgram;
+use crate::compile::{CompileStruct, DocStruct, Emit, ExplainStruct, FmtStruct, LspStruct};
+use crate::console::Color;
+use crate::link::{classify, semver_check, LinkedProgram};
 use crate::upload::CodeUploader;
 use clap::Clap;
 use compile::CompileError;
 use contracttemplates::generate_contract_template_file_or_die;
+#[cfg(feature = "tui")]
+use crossterm::tty::IsTty;
 use gen_code::gen_upgrade_code;
 use run::{
-    profile_gen_from_file, replay_from_testlog_file, run_from_file, ProfilerMode,
-    RuntimeEnvironment,
+    calibrate, compare_revs, extract_hot_paths, gas_diff_from_testlog_file, load_from_file_and_env,
+    load_records, profile_gen_from_file, replay_from_testlog_file, run_scenario_file,
+    run_unit_tests, runtime_env::RtEnvRecorder, MachineState, PerfRecord, ProfileInput,
+    ProfilerMode, RetentionPolicy, RuntimeEnvironment, TestOutcome, DEFAULT_PERF_DB,
 };
 use std::fs::File;
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 mod compile;
 mod console;
@@ -46,6 +51,37 @@ struct RunStruct {
     debug: bool,
     #[clap(short, long)]
     coverage: Option<String>,
+    #[clap(long)]
+    validate_globals: bool,
+    /// Prints each log and send as it's emitted, rather than the logs all at once at the end
+    #[clap(long)]
+    stream: bool,
+    /// Keeps only this many of the most recent logs and sends, rather than all of them
+    #[clap(long)]
+    retain: Option<usize>,
+    /// Keeps only the logs and sends of this many of the most recent blocks
+    #[clap(long)]
+    retain_blocks: Option<usize>,
+    /// Appends the logs and sends no longer kept to this file, one JSON value to a line
+    #[clap(long)]
+    spill: Option<String>,
+    /// Keeps no record of the run's logs and sends beyond those retained
+    #[clap(long)]
+    no_record: bool,
+    /// Runs from zero until the program waits on its inbox, as ArbOS boots, reusing an earlier
+    /// boot on the same inbox if one's cached
+    #[clap(long)]
+    boot: bool,
+    /// Reads the outcome the program reports as a test, failing if the test did
+    #[clap(long)]
+    test: bool,
+    /// Debugs the program, running the debugger commands in this script before reading any more
+    #[clap(long)]
+    script: Option<String>,
+    /// Debugs the program, writing each debugger command given to this script so the session can
+    /// be replayed
+    #[clap(long)]
+    record: Option<String>,
 }
 
 /// Command line options for EvmDebug subcommand.
@@ -67,14 +103,203 @@ struct Replay {
     profiler: ProfilerMode,
     #[clap(short, long)]
     trace: Option<String>,
+    /// Traces only the txs that touch this address, which may be given more than once
+    #[clap(long)]
+    trace_address: Vec<String>,
+    #[clap(short, long)]
+    coverage: Option<String>,
+}
+
+/// Command line options for explore subcommand.
+///
+/// Opens a terminal UI browsing the chain when built with the `tui` feature and run in a terminal,
+/// and otherwise reads commands from stdin, one to a line.
+#[derive(Clap, Debug)]
+struct Explore {
+    /// The test log to replay and explore
+    input: String,
+    /// Reads commands from stdin even when run in a terminal, rather than opening the terminal UI
+    #[clap(long)]
+    plain: bool,
+    /// Contract json whose events logs are decoded with, beyond those of the builtin contracts
+    #[clap(short, long)]
+    abi: Vec<String>,
+    /// Storage layouts emitted by solc, as <address>=<file>, by which a contract's storage is shown
+    #[clap(short, long)]
+    layout: Vec<String>,
+}
+
+/// Command line options for browse subcommand.
+#[derive(Clap, Debug)]
+struct Browse {
+    /// The test log to replay, after which the globals of ArbOS are browsed
+    input: String,
+}
+
+/// Command line options for scenario subcommand.
+#[derive(Clap, Debug)]
+struct ScenarioStruct {
+    /// The scenario script, whose asserts are checked between the messages it delivers
+    script: String,
+    /// The test log whose messages are delivered
+    testlog: String,
 }
 
+/// Command line options for hot-paths subcommand.
+#[derive(Clap, Debug)]
+struct HotPaths {
+    /// The program the workload is run on
+    input: String,
+    /// The test log whose messages make up the workload
+    #[clap(long)]
+    testlog: String,
+    /// How many consecutive messages each hot path takes in
+    #[clap(long, default_value = "4")]
+    window: usize,
+    /// How many of the hottest paths are saved as fixtures
+    #[clap(long, default_value = "3")]
+    count: usize,
+    /// The folder the fixtures and their manifest are written to
+    #[clap(short, long)]
+    output: String,
+}
+
+/// Command line options for test subcommand.
+///
+/// Compiles the program like compile does, then runs each func marked `#[test]` on a fresh
+/// machine, reporting where any that fail do.
+#[derive(Clap, Debug)]
+struct TestStruct {
+    /// Only runs the tests whose names contain this
+    #[clap(long)]
+    filter: Option<String>,
+    #[clap(flatten)]
+    compile: CompileStruct,
+}
+
+/// Command line options for link subcommand.
+///
+/// Links the library archives `compile --module` wrote, given as the inputs, into a mexe, taking
+/// the options of compile that affect linking. The options that only affect compiling sources
+/// were fixed when the archives were written.
+#[derive(Clap, Debug)]
+struct LinkStruct {
+    #[clap(flatten)]
+    compile: CompileStruct,
+}
+
+/// What's wrong with a profiler command line whose workload isn't one of those supported.
+const PROFILE_USAGE: &str =
+    "--scenario and --checkpoint both need a --testlog, and can't be combined";
+
 /// Command line options for profiler subcommand.
 #[derive(Clap, Debug)]
 struct Profiler {
     input: String,
+    /// A test log whose messages the program is run on, rather than calling it with no args
+    #[clap(long)]
+    testlog: Option<String>,
+    /// A scenario script the program is run through, delivering the messages of the test log as
+    /// it says to
+    #[clap(long)]
+    scenario: Option<String>,
+    /// A checkpoint the program is restored from, like the boots kept in a .mini-cache, before
+    /// it's run on the messages of the test log
+    #[clap(long)]
+    checkpoint: Option<String>,
     #[clap(short, long)]
     mode: ProfilerMode,
+    #[clap(long)]
+    record: bool,
+    #[clap(long)]
+    perf_db: Option<String>,
+    /// Writes how often each entry of the jump table was jumped to here, for compiling with
+    /// `--jump-profile`. The program must have been compiled with `--symbol-map`
+    #[clap(long)]
+    jump_profile: Option<String>,
+}
+
+/// Command line options for calibrate subcommand.
+#[derive(Clap, Debug)]
+struct Calibrate {
+    /// How many times each opcode is run while it's timed
+    #[clap(long, default_value = "2000")]
+    reps: usize,
+    /// How many times over or under the median time per gas an opcode can take before it's
+    /// reported as mispriced
+    #[clap(long, default_value = "2.0")]
+    threshold: f64,
+    /// A test log ArbOS runs to count how often each opcode is used, weighing each mispricing
+    #[clap(long)]
+    testlog: Option<String>,
+}
+
+/// Command line options for make-benchmarks subcommand.
+#[derive(Clap, Debug)]
+struct MakeBenchmarks {
+    #[clap(long)]
+    record: bool,
+    #[clap(long)]
+    perf_db: Option<String>,
+}
+
+/// Command line options for perf subcommand.
+#[derive(Clap, Debug)]
+struct Perf {
+    #[clap(subcommand)]
+    command: PerfCommand,
+}
+
+#[derive(Clap, Debug)]
+enum PerfCommand {
+    Compare(PerfCompare),
+    GasDiff(PerfGasDiff),
+}
+
+/// Command line options for perf compare subcommand.
+#[derive(Clap, Debug)]
+struct PerfCompare {
+    old: String,
+    new: String,
+    #[clap(long)]
+    perf_db: Option<String>,
+    #[clap(short, long, default_value = "0")]
+    threshold: f64,
+}
+
+/// Command line options for perf gas-diff subcommand.
+#[derive(Clap, Debug)]
+struct PerfGasDiff {
+    /// The test log to replay on both builds
+    testlog: String,
+    /// The mexe of the build to compare against
+    old: String,
+    /// The mexe of the build being compared
+    new: String,
+    /// The percent a func's gas must change by to be listed
+    #[clap(short, long, default_value = "0")]
+    threshold: f64,
+}
+
+/// Command line options for examples subcommand.
+#[derive(Clap, Debug)]
+struct Examples {
+    #[clap(subcommand)]
+    command: ExamplesCommand,
+}
+
+#[derive(Clap, Debug)]
+enum ExamplesCommand {
+    List,
+    Run(ExamplesRun),
+}
+
+/// Command line options for examples run subcommand.
+#[derive(Clap, Debug)]
+struct ExamplesRun {
+    name: String,
+    #[clap(short, long)]
+    debug: bool,
 }
 
 /// Command line options for reformat subcommand.
@@ -94,6 +319,60 @@ struct EvmTests {
     savelogs: bool,
 }
 
+/// Command line options for gas-parity subcommand.
+#[derive(Clap, Debug)]
+struct GasParity {
+    #[clap(default_value = "arb_os/arbos.mexe")]
+    mexe: String,
+    /// Fail if any opcode's cold access surcharge differs from EIP-2929's
+    #[clap(long)]
+    strict: bool,
+}
+
+/// Command line options for dev-node subcommand.
+#[derive(Clap, Debug)]
+struct DevNode {
+    #[clap(default_value = "arb_os")]
+    input: String,
+    #[clap(short, long)]
+    consts_file: Option<String>,
+    /// How often to check for edits, in milliseconds
+    #[clap(short, long, default_value = "500")]
+    interval: u64,
+    /// Keeps only the logs and sends of this many of the most recent blocks
+    #[clap(long)]
+    retain_blocks: Option<usize>,
+    /// Appends the logs and sends no longer kept to this file, one JSON value to a line
+    #[clap(long)]
+    spill: Option<String>,
+}
+
+/// Command line options for doctor subcommand.
+#[derive(Clap, Debug)]
+struct Doctor {
+    /// The ArbOS to boot and deposit into
+    #[clap(long, default_value = "arb_os/arbos.mexe")]
+    arbos: String,
+    #[clap(short, long, default_value = "arb_os/constants.json")]
+    consts_file: String,
+}
+
+/// Command line options for emit-layouts subcommand.
+#[derive(Clap, Debug)]
+struct EmitLayouts {
+    input: String,
+    output: Option<String>,
+    #[clap(short, long)]
+    format: Option<String>,
+}
+
+/// Command line options for semver-check subcommand.
+#[derive(Clap, Debug)]
+struct SemverCheck {
+    old: String,
+    new: String,
+}
+
 #[derive(Clap, Debug)]
 struct GenUpgrade {
     from: PathBuf,
@@ -118,15 +397,33 @@ struct MakeParametersList {
 #[derive(Clap, Debug)]
 enum Args {
     Compile(CompileStruct),
+    Link(LinkStruct),
+    Test(TestStruct),
     Run(RunStruct),
     EvmDebug(EvmDebug),
     Profiler(Profiler),
     Replay(Replay),
+    Explore(Explore),
+    Browse(Browse),
+    Scenario(ScenarioStruct),
+    HotPaths(HotPaths),
+    Calibrate(Calibrate),
     MakeTestLogs,
-    MakeBenchmarks,
+    MakeBenchmarks(MakeBenchmarks),
+    Perf(Perf),
+    Examples(Examples),
     MakeTemplates,
     Reformat(Reformat),
+    Fmt(FmtStruct),
+    EmitLayouts(EmitLayouts),
+    Doc(DocStruct),
+    Lsp(LspStruct),
+    Explain(ExplainStruct),
+    SemverCheck(SemverCheck),
     EvmTests(EvmTests),
+    GasParity(GasParity),
+    DevNode(DevNode),
+    Doctor(Doctor),
     GenUpgradeCode(GenUpgrade),
     SerializeUpgrade(SerializeUpgrade),
     MakeParametersList(MakeParametersList),
@@ -146,12 +443,24 @@ fn main() -> Result<(), CompileError> {
                 None => Box::new(io::sink()),
             };
 
-            let error_system = match compile.invoke() {
-                Ok((program, error_system)) => {
-                    program.to_output(&mut output, compile.format.as_deref());
-                    error_system
-                }
-                Err(error_system) => error_system,
+            let error_system = match compile.module {
+                true => match compile.invoke_module() {
+                    Ok((archive, error_system)) => {
+                        archive.write(&mut output).unwrap();
+                        error_system
+                    }
+                    Err(error_system) => error_system,
+                },
+                false => match compile.invoke_emitting() {
+                    Ok((program, emitted, error_system)) => {
+                        match compile.emit {
+                            Emit::Mexe => program.to_output(&mut output, compile.format.as_deref()),
+                            _ => output.write_all(emitted.as_bytes()).unwrap(),
+                        }
+                        error_system
+                    }
+                    Err(error_system) => error_system,
+                },
             };
 
             error_system.print();
@@ -168,16 +477,130 @@ fn main() -> Result<(), CompileError> {
             };
         }
 
+        Args::Link(link) => {
+            let compile = link.compile;
+            let error_system = match compile.invoke_link() {
+                Ok((program, error_system)) => {
+                    let mut output = match compile.output {
+                        Some(ref path) => File::create(path)
+                            .map(|f| Box::new(f) as Box<dyn io::Write>)
+                            .unwrap(),
+                        None => Box::new(io::sink()),
+                    };
+                    program.to_output(&mut output, compile.format.as_deref());
+                    error_system
+                }
+                Err(error_system) => error_system,
+            };
+            error_system.print();
+            if !error_system.errors.is_empty() {
+                return Err(CompileError::new(
+                    String::from("Link Failure"),
+                    String::from("Errors were encountered during linking"),
+                    vec![],
+                ));
+            }
+        }
+
+        Args::Test(test) => {
+            let (tests, error_system) = match test.compile.invoke_tests() {
+                Ok(compiled) => compiled,
+                Err(error_system) => {
+                    error_system.print();
+                    return Err(CompileError::new(
+                        String::from("Compilation Failure"),
+                        String::from("Errors were encountered during compilation"),
+                        vec![],
+                    ));
+                }
+            };
+            error_system.print();
+
+            let passed =
+                run_unit_tests(tests, test.filter.as_deref(), &mut io::stdout()).map_err(|e| {
+                    CompileError::new(String::from("Test error"), e.to_string(), vec![])
+                })?;
+            if !passed {
+                return Err(CompileError::new(
+                    String::from("Test failure"),
+                    String::from("Some tests failed"),
+                    vec![],
+                ));
+            }
+        }
+
         Args::Run(run) => {
             let filename = run.input;
-            let debug = run.debug;
+            let debug = run.debug || run.script.is_some() || run.record.is_some();
             let path = Path::new(&filename);
-            match run_from_file(path, Vec::new(), run.coverage, debug) {
+            let mut env = RuntimeEnvironment::default();
+            if run.stream {
+                env.on_log(|log| println!("Log: {}", log));
+                env.on_send(|send| println!("Send: 0x{}", hex::encode(send)));
+            }
+            env.set_retention_policy(RetentionPolicy {
+                max_items: run.retain,
+                max_blocks: run.retain_blocks,
+                spill: run.spill.as_ref().map(PathBuf::from),
+                unrecorded: run.no_record,
+            })
+            .map_err(|e| {
+                CompileError::new(
+                    String::from("Spill file error"),
+                    format!("Could not open spill file: {}", e),
+                    vec![],
+                )
+            })?;
+            let mut machine = load_from_file_and_env(path, env);
+            if run.validate_globals {
+                machine.validate_globals();
+            }
+            let session = match &run.script {
+                Some(script) => machine.replay_debug_script(Path::new(script)),
+                None => Ok(()),
+            };
+            let record = run.record.clone();
+            let session = session.and_then(|_| match &record {
+                Some(record) => machine.record_debug_session(Path::new(record)),
+                None => Ok(()),
+            });
+            if let Err(err) = session {
+                return Err(CompileError::new(
+                    "Debugger error",
+                    format!("Could not open a debugger script: {}", err),
+                    vec![],
+                ));
+            }
+            let result = match run.boot {
+                true => {
+                    run::boot(&mut machine, path);
+                    match machine.state {
+                        MachineState::Error(e) => Err(e),
+                        _ => Ok(machine.runtime_env.get_all_raw_logs()),
+                    }
+                }
+                false => run::run(&mut machine, Vec::new(), debug, run.coverage).map_err(|e| e.0),
+            };
+            let outcome = match result {
                 Ok(logs) => {
-                    println!("Logs: {:?}", logs);
+                    if !run.stream && !run.test {
+                        println!("Logs: {:?}", logs);
+                    }
+                    TestOutcome::from_logs(&logs)
                 }
                 Err(e) => {
                     println!("{:?}", e);
+                    TestOutcome::Fail(format!("the test stopped with {:?}", e))
+                }
+            };
+            if run.test {
+                println!("{}: {}", filename, outcome);
+                if !outcome.passed() {
+                    return Err(CompileError::new(
+                        String::from("Test failure"),
+                        format!("{} failed", filename),
+                        vec![],
+                    ));
                 }
             }
         }
@@ -190,13 +613,35 @@ fn main() -> Result<(), CompileError> {
         }
 
         Args::Profiler(path) => {
-            let input = path.input;
+            let input = Path::new(&path.input);
+            let perf_db = path.perf_db.as_deref().unwrap_or(DEFAULT_PERF_DB);
+            let profile_error =
+                |e: String| CompileError::new(String::from("Profiler error"), e, vec![]);
+            let workload = match (&path.testlog, &path.scenario, &path.checkpoint) {
+                (None, None, None) => ProfileInput::Call,
+                (Some(testlog), None, None) => ProfileInput::Testlog(testlog.into()),
+                (Some(testlog), Some(script), None) => ProfileInput::Scenario {
+                    script: script.into(),
+                    testlog: testlog.into(),
+                },
+                (Some(testlog), None, Some(checkpoint)) => ProfileInput::Checkpoint {
+                    checkpoint: checkpoint.into(),
+                    testlog: testlog.into(),
+                },
+                _ => return Err(profile_error(String::from(PROFILE_USAGE))),
+            };
             profile_gen_from_file(
-                input.as_ref(),
-                Vec::new(),
+                input,
+                &workload,
                 RuntimeEnvironment::default(),
                 path.mode,
-            );
+                match path.record {
+                    true => Some(Path::new(perf_db)),
+                    false => None,
+                },
+                path.jump_profile.as_deref().map(Path::new),
+            )
+            .map_err(|e| profile_error(e.to_string()))?;
         }
 
         Args::Replay(replay) => {
@@ -204,18 +649,146 @@ fn main() -> Result<(), CompileError> {
             let debug = replay.debug;
             let profiler = replay.profiler;
             let trace_file = replay.trace.as_deref();
+            let trace_addresses = replay
+                .trace_address
+                .iter()
+                .map(|address| {
+                    let hex = address.strip_prefix("0x").unwrap_or(address);
+                    uint256::Uint256::from_string_hex(hex).ok_or_else(|| {
+                        CompileError::new(
+                            String::from("Replay error"),
+                            format!("{} isn't an address", Color::red(address)),
+                            vec![],
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
 
-            if let Err(e) = replay_from_testlog_file(path, true, debug, profiler, trace_file) {
+            if let Err(e) = replay_from_testlog_file(
+                path,
+                true,
+                debug,
+                profiler,
+                trace_file,
+                &trace_addresses,
+                replay.coverage,
+            ) {
                 panic!("Error reading from {}: {}", path, e);
             }
         }
 
+        Args::Explore(explore) => {
+            print_time = false;
+            let explore_error =
+                |e: String| CompileError::new(String::from("Explore error"), e, vec![]);
+            let mut explorer = evm::Explorer::from_testlog(&explore.input)
+                .map_err(|e| explore_error(format!("Could not replay {}: {}", explore.input, e)))?;
+            for path in &explore.abi {
+                let contract = evm::AbiForContract::new_from_file(path)
+                    .map_err(|e| explore_error(format!("Could not load {}: {}", path, e)))?;
+                explorer.add_abi(&contract);
+            }
+            for arg in &explore.layout {
+                let (address, path) = arg
+                    .split_once('=')
+                    .and_then(|(address, path)| {
+                        let hex = address.strip_prefix("0x").unwrap_or(address);
+                        Some((uint256::Uint256::from_string_hex(hex)?, path))
+                    })
+                    .ok_or_else(|| {
+                        explore_error(format!("Expected <address>=<file>, got {}", arg))
+                    })?;
+                let layout = evm::StorageLayout::from_file(path)
+                    .map_err(|e| explore_error(format!("Could not load {}: {}", path, e)))?;
+                explorer.add_storage_layout(address, layout);
+            }
+            let stdin = io::stdin();
+            #[cfg(feature = "tui")]
+            let result = match explore.plain || !io::stdout().is_tty() {
+                true => explorer.run(stdin.lock(), io::stdout()),
+                false => evm::ExplorerTui::new(explorer).run(),
+            };
+            #[cfg(not(feature = "tui"))]
+            let result = explorer.run(stdin.lock(), io::stdout());
+            result.map_err(|e| explore_error(e.to_string()))?;
+        }
+
+        Args::Browse(browse) => {
+            print_time = false;
+            let recorder = RtEnvRecorder::from_file(&browse.input).map_err(|e| {
+                CompileError::new(
+                    String::from("Browse error"),
+                    format!("Could not read {}: {}", browse.input, e),
+                    vec![],
+                )
+            })?;
+            let mut machine = recorder.load_machine();
+            run::boot(&mut machine, Path::new("arb_os/arbos.mexe"));
+            let stdin = io::stdin();
+            machine
+                .browse_state()
+                .run(stdin.lock(), io::stdout())
+                .map_err(|e| {
+                    CompileError::new(String::from("Browse error"), e.to_string(), vec![])
+                })?;
+        }
+
+        Args::Scenario(scenario) => {
+            let passed = run_scenario_file(&scenario.script, &scenario.testlog, &mut io::stdout())
+                .map_err(|e| CompileError::new(String::from("Scenario error"), e, vec![]))?;
+            if !passed {
+                return Err(CompileError::new(
+                    String::from("Scenario error"),
+                    format!("Assertions of {} failed", scenario.script),
+                    vec![],
+                ));
+            }
+        }
+
+        Args::HotPaths(options) => {
+            let paths = extract_hot_paths(
+                Path::new(&options.input),
+                Path::new(&options.testlog),
+                options.window,
+                options.count,
+                Path::new(&options.output),
+            )
+            .map_err(|e| {
+                CompileError::new(String::from("Hot path error"), e.to_string(), vec![])
+            })?;
+            let output = Path::new(&options.output);
+            for path in &paths {
+                println!(
+                    "{}: messages {} to {} took {} ArbGas, profiled with --checkpoint {} --testlog {}",
+                    path.name,
+                    path.start,
+                    path.end - 1,
+                    path.gas,
+                    output.join(&path.checkpoint).display(),
+                    output.join(&path.testlog).display(),
+                );
+            }
+        }
+
+        Args::Calibrate(options) => {
+            let calibration_error =
+                |e: String| CompileError::new(String::from("Calibration error"), e, vec![]);
+            let mut report =
+                calibrate(options.reps, options.threshold).map_err(calibration_error)?;
+            if let Some(testlog) = &options.testlog {
+                report
+                    .weigh_by_testlog(testlog)
+                    .map_err(calibration_error)?;
+            }
+            print!("{}", report);
+        }
+
         Args::MakeTestLogs => {
             evm::make_logs_for_all_arbos_tests();
         }
 
-        Args::MakeBenchmarks => {
-            evm::make_benchmarks().map_err(|e| {
+        Args::MakeBenchmarks(benchmarks) => {
+            let results = evm::make_benchmarks().map_err(|e| {
                 CompileError::new(
                     String::from("Benchmark error"),
                     match e {
@@ -225,8 +798,84 @@ fn main() -> Result<(), CompileError> {
                     vec![],
                 )
             })?;
+            if benchmarks.record {
+                let perf_db = benchmarks.perf_db.as_deref().unwrap_or(DEFAULT_PERF_DB);
+                let total = results.values().sum();
+                let record = PerfRecord::new("benchmark", "benchmarks", total, results);
+                record.append_to(Path::new(perf_db)).map_err(|e| {
+                    CompileError::new(
+                        String::from("Benchmark error: Could not record results"),
+                        format!("\"{}\": {}", perf_db, e),
+                        vec![],
+                    )
+                })?;
+            }
         }
 
+        Args::Perf(perf) => match perf.command {
+            PerfCommand::Compare(compare) => {
+                let perf_db = compare.perf_db.as_deref().unwrap_or(DEFAULT_PERF_DB);
+                let records = load_records(Path::new(perf_db)).map_err(|e| {
+                    CompileError::new(
+                        String::from("Perf error: Could not open results"),
+                        format!("\"{}\": {}", perf_db, e),
+                        vec![],
+                    )
+                })?;
+                let mut stdout = io::stdout();
+                compare_revs(
+                    &records,
+                    &compare.old,
+                    &compare.new,
+                    compare.threshold,
+                    &mut stdout,
+                )
+                .map_err(|e| {
+                    CompileError::new(String::from("Perf error"), format!("{}", e), vec![])
+                })?;
+                print_time = false;
+            }
+            PerfCommand::GasDiff(diff) => {
+                let mut stdout = io::stdout();
+                gas_diff_from_testlog_file(
+                    &diff.testlog,
+                    Path::new(&diff.old),
+                    Path::new(&diff.new),
+                    diff.threshold,
+                    &mut stdout,
+                )
+                .map_err(|e| {
+                    CompileError::new(
+                        String::from("Perf error"),
+                        format!("Could not replay {}: {}", diff.testlog, e),
+                        vec![],
+                    )
+                })?;
+                print_time = false;
+            }
+        },
+
+        Args::Examples(examples) => match examples.command {
+            ExamplesCommand::List => {
+                for example in evm::EXAMPLES {
+                    println!("{:<18} {}", example.name, example.description);
+                }
+                print_time = false;
+            }
+            ExamplesCommand::Run(run) => {
+                evm::run_example(&run.name, run.debug).map_err(|e| {
+                    CompileError::new(
+                        String::from("Example error"),
+                        match e {
+                            ethabi::Error::Other(desc) => desc,
+                            other => format!("{}", other),
+                        },
+                        vec![],
+                    )
+                })?;
+            }
+        },
+
         Args::MakeTemplates => {
             let path = Path::new("arb_os/contractTemplates.mini");
             generate_contract_template_file_or_die(path);
@@ -241,18 +890,18 @@ fn main() -> Result<(), CompileError> {
                     vec![],
                 )
             })?;
-            let mut s = String::new();
-            file.read_to_string(&mut s).map_err(|_| {
+            let mut bytes = vec![];
+            file.read_to_end(&mut bytes).map_err(|_| {
                 CompileError::new(
                     String::from("Reformat error"),
-                    format!("Failed to read input file \"{}\" to string", reformat.input),
+                    format!("Failed to read input file \"{}\"", reformat.input),
                     vec![],
                 )
             })?;
-            let result: LinkedProgram = serde_json::from_str(&s).map_err(|_| {
+            let result = LinkedProgram::from_bytes(&bytes).map_err(|e| {
                 CompileError::new(
                     String::from("Reformat error"),
-                    format!("Could not parse input file \"{}\" as json", reformat.input),
+                    format!("Could not load input file \"{}\": {}", reformat.input, e),
                     vec![],
                 )
             })?;
@@ -263,6 +912,119 @@ fn main() -> Result<(), CompileError> {
             );
         }
 
+        Args::EmitLayouts(layouts) => {
+            let path = Path::new(&layouts.input);
+            let mut file = File::open(path).map_err(|_| {
+                CompileError::new(
+                    String::from("Emit layouts error: Could not open file"),
+                    format!("\"{}\"", path.to_str().unwrap_or("non-utf8")),
+                    vec![],
+                )
+            })?;
+            let mut s = String::new();
+            file.read_to_string(&mut s).map_err(|_| {
+                CompileError::new(
+                    String::from("Emit layouts error"),
+                    format!("Failed to read input file \"{}\" to string", layouts.input),
+                    vec![],
+                )
+            })?;
+            let program = LinkedProgram::from_json(&s).map_err(|e| {
+                CompileError::new(
+                    String::from("Emit layouts error"),
+                    format!("Could not load input file \"{}\": {}", layouts.input, e),
+                    vec![],
+                )
+            })?;
+
+            program
+                .type_tree
+                .write_layouts(
+                    &mut get_output(layouts.output).unwrap(),
+                    layouts.format.as_deref(),
+                )
+                .map_err(|e| {
+                    CompileError::new(
+                        String::from("Emit layouts error"),
+                        format!("Failed to write layouts: {}", e),
+                        vec![],
+                    )
+                })?;
+            print_time = false;
+        }
+
+        Args::Doc(doc) => {
+            let docs = match doc.invoke() {
+                Ok(docs) => docs,
+                Err(error_system) => {
+                    error_system.print();
+                    return Err(CompileError::new(
+                        String::from("Doc error"),
+                        String::from("Errors were encountered while parsing"),
+                        vec![],
+                    ));
+                }
+            };
+            get_output(doc.output)
+                .and_then(|mut output| output.write_all(docs.as_bytes()))
+                .map_err(|e| {
+                    CompileError::new(
+                        String::from("Doc error"),
+                        format!("Failed to write docs: {}", e),
+                        vec![],
+                    )
+                })?;
+            print_time = false;
+        }
+
+        Args::Fmt(fmt) => {
+            let changed = fmt.invoke()?;
+            for file in &changed {
+                println!("{}", file.display());
+            }
+            if fmt.check && !changed.is_empty() {
+                return Err(CompileError::new(
+                    String::from("Format check failure"),
+                    format!("{} files aren't formatted", changed.len()),
+                    vec![],
+                ));
+            }
+            print_time = false;
+        }
+
+        Args::Lsp(lsp) => {
+            lsp.invoke()?;
+            print_time = false;
+        }
+
+        Args::Explain(explain) => {
+            print!("{}", explain.invoke()?);
+            print_time = false;
+        }
+
+        Args::SemverCheck(check) => {
+            let old = read_program(&check.old)?;
+            let new = read_program(&check.new)?;
+            for (path, program) in &[(&check.old, &old), (&check.new, &new)] {
+                if program.interface.is_empty() {
+                    println!(
+                        "{} records no interface, so it may predate semver checking",
+                        Color::yellow(path)
+                    );
+                }
+            }
+
+            let changes = semver_check(&old, &new);
+            for change in &changes {
+                println!("{}: {}", change.bump, change.description);
+            }
+            println!(
+                "This release is a {} change",
+                Color::pink(classify(&changes))
+            );
+            print_time = false;
+        }
+
         Args::EvmTests(options) => {
             let mut paths = options.input;
             if paths.len() == 0 {
@@ -299,6 +1061,44 @@ fn main() -> Result<(), CompileError> {
             }
             println!("{} successes, {} failures", num_successes, num_failures);
         }
+        Args::GasParity(options) => {
+            let report = evm::check_gas_parity(Path::new(&options.mexe)).map_err(|desc| {
+                CompileError::new(String::from("Gas parity error"), desc, vec![])
+            })?;
+            print!("{}", report);
+            if options.strict && !report.mismatches().is_empty() {
+                return Err(CompileError::new(
+                    String::from("Gas parity failure"),
+                    String::from("ArbOS doesn't charge warm and cold accesses as EIP-2929 does"),
+                    vec![],
+                ));
+            }
+        }
+        Args::DevNode(node) => {
+            evm::run_dev_node(
+                Path::new(&node.input),
+                node.consts_file,
+                Duration::from_millis(node.interval),
+                RetentionPolicy {
+                    max_blocks: node.retain_blocks,
+                    spill: node.spill.map(PathBuf::from),
+                    // the node runs until killed, so a record of everything would only grow
+                    unrecorded: true,
+                    ..RetentionPolicy::default()
+                },
+            )?;
+        }
+        Args::Doctor(doctor) => {
+            let report = evm::run_doctor(Path::new(&doctor.arbos), Path::new(&doctor.consts_file));
+            print!("{}", report);
+            if !report.passed() {
+                return Err(CompileError::new(
+                    String::from("Doctor failure"),
+                    String::from("The toolchain doesn't work end to end"),
+                    vec![],
+                ));
+            }
+        }
         Args::GenUpgradeCode(upgrade) => {
             let result = gen_upgrade_code(upgrade);
             if let Err(e) = result {
@@ -345,6 +1145,32 @@ fn main() -> Result<(), CompileError> {
 
 /// Creates a `dyn Write` from an optional filename, if a filename is specified, creates a file
 /// handle, otherwise gives stdout.
+/// Reads a compiled program from a json mexe file.
+fn read_program(path: &str) -> Result<LinkedProgram, CompileError> {
+    let mut file = File::open(path).map_err(|_| {
+        CompileError::new(
+            String::from("Semver check error: Could not open file"),
+            format!("\"{}\"", path),
+            vec![],
+        )
+    })?;
+    let mut s = String::new();
+    file.read_to_string(&mut s).map_err(|_| {
+        CompileError::new(
+            String::from("Semver check error"),
+            format!("Failed to read input file \"{}\" to string", path),
+            vec![],
+        )
+    })?;
+    LinkedProgram::from_json(&s).map_err(|e| {
+        CompileError::new(
+            String::from("Semver check error"),
+            format!("Could not load input file \"{}\": {}", path, e),
+            vec![],
+        )
+    })
+}
+
 fn get_output(output_filename: Option<String>) -> Result<Box<dyn io::Write>, io::Error> {
     match output_filename {
         Some(ref path) => File::create(path).map(|f| Box::new(f) as Box<dyn io::Write>),
@@ -11,10 +11,75 @@ use ethers_core::utils::keccak256;
 use serde::de::Visitor;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_repr::{Deserialize_repr, Serialize_repr};
+use std::hash::{Hash, Hasher};
 use std::{collections::HashMap, fmt, sync::Arc};
 
-/// A label who's value is the same across ArbOS versions
-pub type LabelId = u64;
+/// A label who's value is the same across ArbOS versions. Those of funcs are hashes of the func's
+/// path and name, wide enough that two funcs sharing one is vanishingly unlikely, though
+/// `check_label_collisions` still reports it if they do.
+pub type LabelId = u128;
+
+/// Hashes the way label ids and file ids are derived. Unlike `DefaultHasher`, whose algorithm
+/// may change between Rust releases, and which writes integers in the platform's byte order,
+/// the result is the keccak of the value's bytes in a fixed order, so the same sources always
+/// compile to the same program.
+#[derive(Default)]
+pub struct StableHasher {
+    bytes: Vec<u8>,
+}
+
+impl StableHasher {
+    /// Finishes the hash at the width of a label id.
+    pub fn finish_wide(&self) -> u128 {
+        let mut wide = [0u8; 16];
+        wide.copy_from_slice(&keccak256(&self.bytes)[..16]);
+        u128::from_be_bytes(wide)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        (self.finish_wide() >> 64) as u64
+    }
+}
+
+/// Hashes a value with a `StableHasher`.
+pub fn stable_hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = StableHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Derives a label id from a value with a `StableHasher`.
+pub fn label_id<T: Hash + ?Sized>(value: &T) -> LabelId {
+    let mut hasher = StableHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish_wide()
+}
 
 #[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Label {
@@ -36,11 +101,11 @@ impl Label {
         match self {
             Label::Func(id) | Label::Closure(id) => Value::avm_hash2(
                 &Value::Int(Uint256::from_usize(4)),
-                &Value::Int(Uint256::from_u64(*id)),
+                &Value::Int(Uint256::from_u128(*id)),
             ),
             Label::Anon(n) => Value::avm_hash2(
                 &Value::Int(Uint256::from_usize(5)),
-                &Value::Int(Uint256::from_usize(*n as usize)),
+                &Value::Int(Uint256::from_u128(*n)),
             ),
             Label::Evm(_) => {
                 panic!("tried to avm_hash an EVM label");
@@ -1150,8 +1215,9 @@ impl Opcode {
 }
 
 impl Opcode {
-    pub fn from_name(name: &str) -> Self {
-        match name {
+    /// Gets the opcode an asm block refers to by name, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let opcode = match name {
             "rget" => Opcode::AVMOpcode(AVMOpcode::Rpush),
             "rset" => Opcode::AVMOpcode(AVMOpcode::Rset),
             "spush" => Opcode::AVMOpcode(AVMOpcode::Spush),
@@ -1222,10 +1288,20 @@ impl Opcode {
             "addmod" => Opcode::AVMOpcode(AVMOpcode::AddMod),
             "mulmod" => Opcode::AVMOpcode(AVMOpcode::MulMod),
             "noop" => Opcode::AVMOpcode(AVMOpcode::Noop),
-            _ => {
-                panic!("opcode not supported in asm segment: {}", name);
-            }
-        }
+            "cjump" => Opcode::AVMOpcode(AVMOpcode::Cjump),
+            "pcpush" => Opcode::AVMOpcode(AVMOpcode::PCpush),
+            "breakpoint" => Opcode::AVMOpcode(AVMOpcode::Breakpoint),
+            "halt" => Opcode::AVMOpcode(AVMOpcode::Halt),
+            "newbuffer" => Opcode::AVMOpcode(AVMOpcode::NewBuffer),
+            "getbuffer8" => Opcode::AVMOpcode(AVMOpcode::GetBuffer8),
+            "getbuffer64" => Opcode::AVMOpcode(AVMOpcode::GetBuffer64),
+            "getbuffer256" => Opcode::AVMOpcode(AVMOpcode::GetBuffer256),
+            "setbuffer8" => Opcode::AVMOpcode(AVMOpcode::SetBuffer8),
+            "setbuffer64" => Opcode::AVMOpcode(AVMOpcode::SetBuffer64),
+            "setbuffer256" => Opcode::AVMOpcode(AVMOpcode::SetBuffer256),
+            _ => return None,
+        };
+        Some(opcode)
     }
 
     pub fn to_name(&self) -> &str {
@@ -1259,6 +1335,95 @@ impl From<AVMOpcode> for Opcode {
 }
 
 impl AVMOpcode {
+    /// Gets how many values the opcode pops off the stack and how many it then pushes, or `None`
+    /// for opcodes that transfer control elsewhere.
+    pub fn stack_effect(&self) -> Option<(usize, usize)> {
+        let effect = match self {
+            AVMOpcode::Zero
+            | AVMOpcode::Jump
+            | AVMOpcode::Cjump
+            | AVMOpcode::Error
+            | AVMOpcode::Halt => return None,
+            AVMOpcode::Noop | AVMOpcode::Breakpoint => (0, 0),
+            AVMOpcode::Spush
+            | AVMOpcode::Rpush
+            | AVMOpcode::StackEmpty
+            | AVMOpcode::PCpush
+            | AVMOpcode::AuxPop
+            | AVMOpcode::AuxStackEmpty
+            | AVMOpcode::ErrPush
+            | AVMOpcode::Inbox
+            | AVMOpcode::PushGas
+            | AVMOpcode::ErrCodePoint
+            | AVMOpcode::NewBuffer => (0, 1),
+            AVMOpcode::Pop
+            | AVMOpcode::Rset
+            | AVMOpcode::AuxPush
+            | AVMOpcode::ErrSet
+            | AVMOpcode::Log
+            | AVMOpcode::SetGas
+            | AVMOpcode::DebugPrint => (1, 0),
+            AVMOpcode::IsZero
+            | AVMOpcode::BitwiseNeg
+            | AVMOpcode::Hash
+            | AVMOpcode::Type
+            | AVMOpcode::Keccakf
+            | AVMOpcode::Blake2f
+            | AVMOpcode::Tlen
+            | AVMOpcode::Xget
+            | AVMOpcode::InboxPeek
+            | AVMOpcode::Sideload
+            | AVMOpcode::EcPairing => (1, 1),
+            AVMOpcode::OpenInsn => (1, 2),
+            AVMOpcode::Xset | AVMOpcode::Send => (2, 0),
+            AVMOpcode::Add
+            | AVMOpcode::Mul
+            | AVMOpcode::Sub
+            | AVMOpcode::Div
+            | AVMOpcode::Sdiv
+            | AVMOpcode::Mod
+            | AVMOpcode::Smod
+            | AVMOpcode::Exp
+            | AVMOpcode::SignExtend
+            | AVMOpcode::LessThan
+            | AVMOpcode::GreaterThan
+            | AVMOpcode::SLessThan
+            | AVMOpcode::SGreaterThan
+            | AVMOpcode::Equal
+            | AVMOpcode::BitwiseAnd
+            | AVMOpcode::BitwiseOr
+            | AVMOpcode::BitwiseXor
+            | AVMOpcode::Byte
+            | AVMOpcode::ShiftLeft
+            | AVMOpcode::ShiftRight
+            | AVMOpcode::ShiftArith
+            | AVMOpcode::EthHash2
+            | AVMOpcode::Tget
+            | AVMOpcode::PushInsn
+            | AVMOpcode::GetBuffer8
+            | AVMOpcode::GetBuffer64
+            | AVMOpcode::GetBuffer256 => (2, 1),
+            AVMOpcode::Dup0 => (1, 2),
+            AVMOpcode::Swap1 => (2, 2),
+            AVMOpcode::Dup1 => (2, 3),
+            AVMOpcode::Swap2 => (3, 3),
+            AVMOpcode::Dup2 => (3, 4),
+            AVMOpcode::AddMod
+            | AVMOpcode::MulMod
+            | AVMOpcode::Sha256f
+            | AVMOpcode::Ripemd160f
+            | AVMOpcode::Tset
+            | AVMOpcode::PushInsnImm
+            | AVMOpcode::SetBuffer8
+            | AVMOpcode::SetBuffer64
+            | AVMOpcode::SetBuffer256 => (3, 1),
+            AVMOpcode::EcMul => (3, 2),
+            AVMOpcode::EcRecover => (4, 1),
+            AVMOpcode::EcAdd => (4, 2),
+        };
+        Some(effect)
+    }
+
     fn to_name(&self) -> &str {
         match self {
             AVMOpcode::Rpush => "rpush",
@@ -1347,6 +1512,26 @@ impl AVMOpcode {
         }
     }
 
+    /// Gets the first ArbOS version whose AVM runs the opcode. Opcodes the AVM has always had
+    /// are given version 0.
+    pub fn arbos_version(self) -> u64 {
+        match self {
+            AVMOpcode::EcPairing => 8,
+            AVMOpcode::NewBuffer
+            | AVMOpcode::GetBuffer8
+            | AVMOpcode::GetBuffer64
+            | AVMOpcode::GetBuffer256
+            | AVMOpcode::SetBuffer8
+            | AVMOpcode::SetBuffer64
+            | AVMOpcode::SetBuffer256 => 10,
+            AVMOpcode::Sideload => 13,
+            AVMOpcode::Keccakf | AVMOpcode::Sha256f => 15,
+            AVMOpcode::EcAdd | AVMOpcode::EcMul => 18,
+            AVMOpcode::Ripemd160f | AVMOpcode::Blake2f => 31,
+            _ => 0,
+        }
+    }
+
     pub fn from_number(num: usize) -> Option<Self> {
         match num {
             0x00 => Some(AVMOpcode::Zero),
@@ -1525,6 +1710,22 @@ impl AVMOpcode {
     }
 }
 
+#[test]
+fn test_stable_hash() {
+    // ids must not change between builds, so these are pinned rather than compared to each other
+    let path = vec![String::from("std")];
+    assert_eq!(stable_hash(&(&path, "main")), 13176649430342505866);
+    assert_eq!(
+        label_id(&(&path, "main")),
+        243066279790518959358593994210615414264
+    );
+    assert_eq!(
+        stable_hash(&(&path, "main")),
+        stable_hash(&(path, String::from("main")))
+    );
+    assert_ne!(stable_hash("a"), stable_hash("b"));
+}
+
 #[test]
 fn test_consistent_opcode_numbers() {
     for i in 0..256 {
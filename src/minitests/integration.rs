@@ -1,8 +1,15 @@
-use crate::compile::{CompileError, CompileStruct, FileInfo};
-use crate::mavm::Value;
-use crate::run::{run, Machine, RuntimeEnvironment};
+use crate::compile::miniconstants::init_constant_table;
+use crate::compile::{
+    AttributeHook, Attributes, CompileError, CompileStruct, DocStruct, Emit, FileInfo, Lint,
+    MANIFEST_FILE,
+};
+use crate::console::Color;
+use crate::link::{classify, semver_check, Bump, Change, JumpProfile, LinkedProgram, SymbolMap};
+use crate::mavm::{AVMOpcode, Value};
+use crate::run::{run, Machine, ProfilerMode, RuntimeEnvironment};
 use crate::uint256::Uint256;
 use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
 use std::sync::Arc;
 
 fn compile_run_cycle(input: String) -> Machine {
@@ -224,6 +231,7 @@ fn test_error_system() {
             &[196],
             &[197],
             &[197],
+            &[202],
         ],
         &[&[7], &[45], &[46], &[46], &[105], &[187]],
     );
@@ -253,3 +261,2812 @@ fn test_error_system() {
         &[&[2, 6]],
     );
 }
+
+#[test]
+fn test_validate_globals() {
+    // runs the fixture, optionally checking each write to a global against its declared type
+    let run_fixture = |corrupt: bool, validate: bool| {
+        let mut compile = CompileStruct::default();
+        compile.input = vec!["minitests/validate-globals.mini".to_string()];
+        compile.test_mode = true;
+        compile.consts_file = Some("arb_os/constants.json".to_string());
+
+        let (program, _) = match compile.invoke() {
+            Ok(result) => result,
+            Err(error_system) => {
+                error_system.print();
+                panic!("failed to compile validate-globals.mini");
+            }
+        };
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        if validate {
+            machine.validate_globals();
+        }
+        let args = vec![Value::Int(Uint256::from_bool(corrupt))];
+        run(&mut machine, args, false, None).is_ok()
+    };
+
+    assert!(run_fixture(false, true), "a well typed write was rejected");
+    assert!(
+        run_fixture(true, false),
+        "the unsafecast failed without validation"
+    );
+    assert!(!run_fixture(true, true), "a malformed write was accepted");
+}
+
+#[test]
+fn test_sparse_globals() {
+    use crate::run::{SparseSource, SparseTuple};
+    use std::collections::HashMap;
+
+    let mut compile = CompileStruct::default();
+    compile.input = vec!["minitests/sparse-globals.mini".to_string()];
+    compile.test_mode = true;
+    compile.consts_file = Some("arb_os/constants.json".to_string());
+    let (program, _) = match compile.invoke() {
+        Ok(result) => result,
+        Err(error_system) => {
+            error_system.print();
+            panic!("failed to compile sparse-globals.mini");
+        }
+    };
+
+    // the machine increments the first leaf on the right and zeroes the second, neither of which
+    // the test reads before it runs
+    let mut state = HashMap::new();
+    state.insert(vec![1, 0], Value::from(7));
+    state.insert(vec![1, 1], Value::from(5));
+    let source: Arc<dyn SparseSource> = Arc::new(state);
+    let tree = SparseTuple::new(2, 2, Value::from(0)).with_source(source);
+
+    let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+    machine.back_global("tree", tree).unwrap();
+    assert!(machine
+        .back_global("no_such_global", SparseTuple::new(2, 2, Value::from(0)))
+        .is_err());
+    run(&mut machine, vec![], false, None).unwrap();
+
+    let tree = machine.sparse_global("tree").unwrap();
+    assert_eq!(tree.get(&[1, 0]), Ok(Value::from(8)));
+    assert_eq!(
+        tree.get(&[1, 1]),
+        Ok(Value::from(0)),
+        "the stale value was loaded again"
+    );
+    assert_eq!(tree.get(&[0, 1]), Ok(Value::from(0)));
+}
+
+#[test]
+fn test_stack_depth() {
+    let warnings = |file_path: &str, budget: usize| -> Vec<(usize, String)> {
+        let mut compile = CompileStruct::default();
+        compile.input = vec![file_path.to_string()];
+        compile.consts_file = Some("arb_os/constants.json".to_string());
+        compile.stack_budget = Some(budget);
+
+        let (_, error_system) = match compile.invoke() {
+            Ok(result) => result,
+            Err(error_system) => {
+                error_system.print();
+                panic!("failed to compile {}", file_path);
+            }
+        };
+        let color = error_system.warn_color;
+        let mut warnings: Vec<_> = error_system
+            .warnings
+            .into_iter()
+            .map(|warning| {
+                let line = warning.locations.last().unwrap().line.to_usize() + 1;
+                let plain = warning.description.replace(color, "");
+                (line, plain.replace(Color::RESET, ""))
+            })
+            .collect();
+        warnings.sort();
+        warnings
+    };
+
+    // tail calls, including mutually recursive ones, don't deepen the stack
+    assert_eq!(warnings("minitests/stack-depth.mini", 4), vec![]);
+    assert_eq!(
+        warnings("minitests/stack-depth.mini", 3),
+        vec![(
+            14,
+            "call stack can grow to 4 frames, exceeding the budget of 3: \
+             main -> outer -> middle -> inner"
+                .to_string()
+        )]
+    );
+
+    assert_eq!(
+        warnings("minitests/stack-recursion.mini", 100),
+        vec![
+            (
+                13,
+                "func factorial can recurse without bound: main -> factorial -> factorial"
+                    .to_string()
+            ),
+            (
+                20,
+                "func ping can recurse without bound: main -> ping -> pong -> ping".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_doc() {
+    let docs = |format: Option<&str>| {
+        let doc = DocStruct {
+            input: "minitests/doc.mini".to_string(),
+            consts_file: Some("arb_os/constants.json".to_string()),
+            format: format.map(String::from),
+            ..DocStruct::default()
+        };
+        match doc.invoke() {
+            Ok(docs) => docs,
+            Err(error_system) => {
+                error_system.print();
+                panic!("failed to document minitests/doc.mini");
+            }
+        }
+    };
+
+    let markdown = docs(None);
+    for expected in &[
+        "# doc\n\n## Constants\n\n### MaxEntries\n\n```\nconst MaxEntries = 16;\n```\n\n\
+         The most entries a ledger holds\n\n### Undocumented\n\n```\nconst Undocumented = 2;\n```\n\n\
+         ## Types\n\n",
+        "A running total\n\nEntries are never removed.\n\n",
+        "```\ntype counted<T> = (T, uint, );\n```\n\n",
+        "```\npublic func record(book: ledger, amount: uint) -> ledger\n```\n\n\
+         Adds an amount to the ledger\n\n",
+        "```\npublic func count<T>(value: T) -> counted<T, >\n```\n\n",
+    ] {
+        assert!(markdown.contains(expected), "missing {:?} in\n{}", expected, markdown);
+    }
+
+    // private funcs and ordinary comments aren't documented
+    assert!(!markdown.contains("hidden"));
+    assert!(!markdown.contains("ordinary comment"));
+
+    let html = docs(Some("html"));
+    assert!(html.contains(
+        "<pre><code>public func count&lt;T&gt;(value: T) -&gt; counted&lt;T, &gt;</code></pre>"
+    ));
+    assert!(html.contains("<p>A running total</p>\n<p>Entries are never removed.</p>"));
+}
+
+#[test]
+fn test_dead_code() {
+    let compile = |keep_all: bool| {
+        let compile = CompileStruct {
+            input: vec!["minitests/deadcode.mini".to_string()],
+            consts_file: Some("arb_os/constants.json".to_string()),
+            keep_all,
+            ..CompileStruct::default()
+        };
+        let (program, error_system) = match compile.invoke() {
+            Ok(result) => result,
+            Err(error_system) => {
+                error_system.print();
+                panic!("failed to compile minitests/deadcode.mini");
+            }
+        };
+        let color = error_system.warn_color;
+        let mut warnings: Vec<_> = error_system
+            .warnings
+            .into_iter()
+            .map(|warning| {
+                let plain = warning.description.replace(color, "");
+                plain.replace(Color::RESET, "")
+            })
+            .collect();
+        warnings.sort();
+        let globals: Vec<_> = program
+            .globals
+            .into_iter()
+            .map(|global| global.name)
+            .collect();
+        (globals, warnings)
+    };
+
+    // dropped funcs are still warned of, as are the exported ones the linker can't reach from main
+    let unreachable = vec![
+        "func exported is unreachable".to_string(),
+        "func helper is unreachable".to_string(),
+        "func orphan is unreachable".to_string(),
+        "func orphanHelper is unreachable".to_string(),
+    ];
+    let (globals, warnings) = compile(false);
+    assert_eq!(globals, vec!["counter", "_reserved", "_jump_table"]);
+    assert_eq!(warnings, unreachable);
+
+    // the linker leaves out the same funcs, but the globals they use are kept
+    let (globals, warnings) = compile(true);
+    assert_eq!(
+        globals,
+        vec!["counter", "forgotten", "_reserved", "_jump_table"]
+    );
+    assert_eq!(warnings, unreachable);
+}
+
+#[test]
+fn test_semver_check() {
+    let compile = || {
+        let compile = CompileStruct {
+            input: vec!["minitests/semver.mini".to_string()],
+            consts_file: Some("arb_os/constants.json".to_string()),
+            ..CompileStruct::default()
+        };
+        match compile.invoke() {
+            Ok((program, _)) => program,
+            Err(error_system) => {
+                error_system.print();
+                panic!("failed to compile minitests/semver.mini");
+            }
+        }
+    };
+    let old = compile();
+    let mut new = compile();
+
+    // only the constants describing the interface are recorded
+    let names: Vec<_> = old.interface.keys().cloned().collect();
+    assert_eq!(
+        names,
+        vec![
+            "Default_Param_ChainID",
+            "LogType_send",
+            "eventTopic_ArbRetryableTx_Redeemed",
+            "funcCode_ArbSys_arbOSVersion",
+        ]
+    );
+    assert_eq!(semver_check(&old, &new), vec![]);
+    assert_eq!(classify(&semver_check(&old, &new)), Bump::Patch);
+
+    let describe = |changes: Vec<Change>| -> Vec<(Bump, String)> {
+        changes
+            .into_iter()
+            .map(|change| (change.bump, change.description))
+            .collect()
+    };
+
+    new.interface
+        .insert("Default_Param_ChainID".to_string(), Uint256::from_u64(7));
+    new.interface.insert(
+        "funcCode_ArbSys_arbChainID".to_string(),
+        Uint256::from_u64(1),
+    );
+    let changes = semver_check(&old, &new);
+    assert_eq!(classify(&changes), Bump::Minor);
+    assert_eq!(
+        describe(changes),
+        vec![
+            (
+                Bump::Minor,
+                format!(
+                    "the default of chain parameter ChainID changed from {} to 7",
+                    old.interface["Default_Param_ChainID"]
+                )
+            ),
+            (
+                Bump::Minor,
+                "precompile method ArbSys.arbChainID was added".to_string()
+            ),
+        ]
+    );
+
+    new.interface.remove("eventTopic_ArbRetryableTx_Redeemed");
+    new.interface
+        .insert("funcCode_ArbSys_arbOSVersion".to_string(), Uint256::zero());
+    new.interface
+        .insert("LogType_send".to_string(), Uint256::from_u64(9));
+    let changes = semver_check(&old, &new);
+    assert_eq!(classify(&changes), Bump::Major);
+    assert_eq!(
+        describe(changes)
+            .into_iter()
+            .filter(|(bump, _)| *bump == Bump::Major)
+            .map(|(_, description)| description)
+            .collect::<Vec<_>>(),
+        vec![
+            "log type send changed from 2 to 9",
+            "event ArbRetryableTx.Redeemed was removed",
+            "the signature of precompile method ArbSys.arbOSVersion changed",
+        ]
+    );
+}
+
+#[test]
+fn test_lints() {
+    let compile = |allow: Vec<Lint>, warn: Vec<Lint>, deny: Vec<Lint>| {
+        let compile = CompileStruct {
+            input: vec!["minitests/lints.mini".to_string()],
+            consts_file: Some("arb_os/constants.json".to_string()),
+            allow,
+            warn,
+            deny,
+            ..CompileStruct::default()
+        };
+        let (succeeded, error_system) = match compile.invoke() {
+            Ok((_, error_system)) => (true, error_system),
+            Err(error_system) => (false, error_system),
+        };
+        let lints = |issues: Vec<CompileError>| -> Vec<Lint> {
+            let mut lints: Vec<_> = issues.into_iter().filter_map(|issue| issue.lint).collect();
+            lints.sort();
+            lints
+        };
+        (
+            succeeded,
+            lints(error_system.warnings),
+            lints(error_system.errors),
+        )
+    };
+
+    // shadowing is only reported when asked for, and an else branch doesn't see the lets of its if
+    let default = vec![Lint::UnusedVariables, Lint::UnreachableCode];
+    assert_eq!(compile(vec![], vec![], vec![]), (true, default, vec![]));
+    assert_eq!(
+        compile(vec![], vec![Lint::Shadowing], vec![]),
+        (
+            true,
+            vec![
+                Lint::UnusedVariables,
+                Lint::UnreachableCode,
+                Lint::Shadowing
+            ],
+            vec![]
+        )
+    );
+    assert_eq!(
+        compile(vec![Lint::UnusedVariables], vec![], vec![]),
+        (true, vec![Lint::UnreachableCode], vec![])
+    );
+    assert_eq!(
+        compile(vec![], vec![], vec![Lint::UnreachableCode]),
+        (
+            false,
+            vec![Lint::UnusedVariables],
+            vec![Lint::UnreachableCode]
+        )
+    );
+    assert_eq!(
+        "unreachable_code".parse::<Lint>().ok(),
+        Some(Lint::UnreachableCode)
+    );
+    assert!("unused".parse::<Lint>().is_err());
+}
+
+#[test]
+fn test_compile_cache() {
+    let folder = std::env::temp_dir().join(format!("mini-cache-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let write = |name: &str, source: &str| std::fs::write(folder.join(name), source).unwrap();
+    let compile = |no_cache: bool| {
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            no_builtins: true,
+            no_cache,
+            ..CompileStruct::default()
+        };
+        compile
+            .invoke()
+            .map(|(program, error_system)| {
+                let warnings = error_system.warnings.len();
+                (serde_json::to_string(&program).unwrap(), warnings)
+            })
+            .map_err(|error_system| error_system.errors.len())
+    };
+    let entries = || match std::fs::read_dir(folder.join(".mini-cache")) {
+        Ok(dir) => dir.count(),
+        Err(_) => 0,
+    };
+
+    write(
+        "main.mini",
+        "use lib::double;\n\nfunc main() {\n    let unused = double(2);\n}\n",
+    );
+    write(
+        "lib.mini",
+        "public func double(x: uint) -> uint {\n    return 2 * x;\n}\n",
+    );
+    let (program, warnings) = compile(false).unwrap();
+    assert_eq!(warnings, 1);
+    assert_eq!(entries(), 2);
+
+    // reusing both modules gives the same program, and the same warnings
+    assert_eq!(compile(false), Ok((program.clone(), warnings)));
+    assert_eq!(compile(true), Ok((program, warnings)));
+    assert_eq!(entries(), 2);
+
+    // a new body for double leaves main's entry as it was
+    write(
+        "lib.mini",
+        "public func double(x: uint) -> uint {\n    return x + x;\n}\n",
+    );
+    assert_eq!(compile(false), compile(true));
+    assert_eq!(entries(), 3);
+
+    // but main is typechecked again once double's signature changes
+    write(
+        "lib.mini",
+        "public func double(x: bool) -> uint {\n    return 2;\n}\n",
+    );
+    assert!(compile(false).is_err());
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_reexports() {
+    let folder = std::env::temp_dir().join(format!("mini-reexports-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let write = |name: &str, source: &str| std::fs::write(folder.join(name), source).unwrap();
+    let compile = || {
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        };
+        compile.invoke().map(|(program, _)| program)
+    };
+
+    write(
+        "inner.mini",
+        "type point = struct { x: uint, y: uint };\n\n\
+         public func sum(p: point) -> uint {\n    return p.x + p.y;\n}\n",
+    );
+    write(
+        "facade.mini",
+        "public use inner::point;\npublic use inner::sum;\n\n\
+         type coords = point;\n\n\
+         public func make(x: uint, y: uint) -> coords {\n    return struct { x: x, y: y };\n}\n",
+    );
+    write(
+        "main.mini",
+        "use facade::point;\nuse facade::sum;\nuse facade::make;\n\n\
+         func main() -> uint {\n    return total(make(3, 4));\n}\n\n\
+         func total(p: point) -> uint {\n    return sum(p) + p.x;\n}\n",
+    );
+
+    // main reaches the type and func of inner through facade, and the alias of point is a point
+    let program = compile().unwrap_or_else(|_| panic!("failed to compile"));
+    let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+    run(&mut machine, vec![], false, None).unwrap();
+    assert_eq!(machine.stack_top(), Some(&Value::from(10)));
+
+    // names a module only imports for itself can't be imported from it
+    write(
+        "facade.mini",
+        "public use inner::point;\nuse inner::sum;\n\n\
+         public func make(x: uint, y: uint) -> point {\n    return struct { x: sum(struct { x: x, y: 0 }), y: y };\n}\n",
+    );
+    assert!(compile().is_err());
+
+    // nor can a name be re-exported in a cycle
+    write(
+        "inner.mini",
+        "public use facade::sum;\n\ntype point = struct { x: uint, y: uint };\n",
+    );
+    write(
+        "facade.mini",
+        "public use inner::point;\npublic use inner::sum;\n\n\
+         public func make(x: uint, y: uint) -> point {\n    return struct { x: x, y: y };\n}\n",
+    );
+    assert!(compile().is_err());
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_attribute_hooks() {
+    struct Watched;
+
+    impl AttributeHook for Watched {
+        fn name(&self) -> &'static str {
+            "watched"
+        }
+
+        fn attach(&self, attributes: &mut Attributes) {
+            attributes.breakpoint = true;
+        }
+    }
+
+    let compile = |register: bool| {
+        let mut compile = CompileStruct {
+            input: vec!["minitests/attribute-hooks.mini".to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        };
+        if register {
+            compile.hooks.register(Watched);
+        }
+        compile.invoke().map(|(program, _)| program)
+    };
+
+    // a registered attribute marks the func's code, and trace prints its name on entry
+    let program = compile(true).unwrap_or_else(|_| panic!("failed to compile"));
+    assert!(program
+        .code
+        .iter()
+        .any(|insn| insn.debug_info.attributes.breakpoint));
+    assert!(program.code.iter().any(|insn| {
+        insn.opcode == AVMOpcode::DebugPrint && insn.immediate == Some(Value::from("trace: main"))
+    }));
+    let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+    run(&mut machine, vec![], false, None).unwrap();
+    assert_eq!(machine.stack_top(), Some(&Value::from(3)));
+
+    // attributes nothing is registered for are errors
+    assert!(compile(false).is_err());
+}
+
+#[test]
+fn test_emit() {
+    let emit = |emit: Emit| {
+        let compile = CompileStruct {
+            input: vec!["minitests/emit.mini".to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            emit,
+            ..CompileStruct::default()
+        };
+        let (_, emitted, _) = compile
+            .invoke_emitting()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        emitted
+    };
+
+    // each representation names the funcs, and is the same from one compile to the next
+    for representation in &[Emit::Ast, Emit::TypedAst, Emit::Mavm] {
+        let emitted = emit(*representation);
+        assert!(
+            emitted.contains("helper"),
+            "{} doesn't name helper",
+            representation
+        );
+        assert_eq!(emitted, emit(*representation));
+    }
+    assert!(emit(Emit::Mavm).contains("// func emit::main"));
+    assert!(emit(Emit::Mexe).is_empty());
+    assert_eq!("typed_ast".parse::<Emit>().unwrap(), Emit::TypedAst);
+    assert!("ir".parse::<Emit>().is_err());
+}
+
+#[test]
+fn test_reproducible_builds() {
+    let compile = || {
+        let compile = CompileStruct {
+            input: vec!["minitests/reproducible".to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        };
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        serde_json::to_string(&program).unwrap()
+    };
+
+    // hash maps iterate in a different order each compile, so output depending on it would differ
+    let mexe = compile();
+    for _ in 0..3 {
+        assert!(
+            compile() == mexe,
+            "compiling the same sources twice differed"
+        );
+    }
+}
+
+#[test]
+fn test_debug_only_modules() {
+    let compile = |folder: &Path, release_build: bool, features: &[&str]| {
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            release_build,
+            features: features.iter().map(|feature| feature.to_string()).collect(),
+            ..CompileStruct::default()
+        };
+        compile.invoke().map(|(program, _)| program)
+    };
+    let probed = |program: &LinkedProgram| {
+        let marker = Value::Int(Uint256::from_u64(0x5eed5eed));
+        program
+            .code
+            .iter()
+            .any(|insn| insn.immediate.as_ref() == Some(&marker))
+    };
+    let fixture = Path::new("minitests/debug-only");
+
+    // debug builds keep debug-only modules, and release builds leave them out
+    let program = compile(fixture, false, &["inspect"]);
+    assert!(probed(
+        &program.unwrap_or_else(|_| panic!("failed to compile"))
+    ));
+    let program = compile(fixture, true, &[]).unwrap_or_else(|_| panic!("failed to compile"));
+    assert!(!probed(&program));
+    let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+    run(&mut machine, vec![], false, None).unwrap();
+    assert_eq!(machine.stack_top(), Some(&Value::from(5)));
+
+    // a release build that still reaches a debug-only module fails rather than ship it
+    let errors = compile(fixture, true, &["inspect"]).err().unwrap().errors;
+    assert!(errors
+        .iter()
+        .any(|error| error.description.contains("debug-only")));
+
+    // as does a manifest naming a module the program doesn't have
+    let folder = std::env::temp_dir().join(format!("mini-debug-only-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    for module in &["main.mini", "inspect.mini"] {
+        std::fs::copy(fixture.join(module), folder.join(module)).unwrap();
+    }
+    std::fs::write(
+        folder.join(MANIFEST_FILE),
+        "debug_only = [\"inspect\", \"nowhere\"]\n",
+    )
+    .unwrap();
+    assert!(compile(&folder, false, &[]).is_err());
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_precompile_extensions() {
+    let folder = std::env::temp_dir().join(format!("mini-precompiles-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let write = |name: &str, source: &str| std::fs::write(folder.join(name), source).unwrap();
+    let compile = || {
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        };
+        compile.invoke().map(|(program, _)| program)
+    };
+
+    // registering a precompile adds its address to the registry
+    write(
+        "precompiles.mini",
+        "type PrecompileRegistry = uint;\n\n\
+         public view write func precompiles_register(\n    registry: PrecompileRegistry,\n    \
+         addr: address,\n    txcall: view write func(),\n) -> option<PrecompileRegistry> {\n    \
+         txcall();\n    return Some(registry + uint(addr));\n}\n",
+    );
+    write(
+        "main.mini",
+        "use precompileExtensions::precompileExtensions_register;\n\n\
+         view write func main() -> uint {\n    \
+         if let Some(registry) = precompileExtensions_register(5) {\n        \
+         return registry;\n    }\n    return 0;\n}\n",
+    );
+    write(
+        "doubler.mini",
+        "public view write func doubler_txcall() {\n    return;\n}\n",
+    );
+    write(
+        "tripler.mini",
+        "public view write func tripler_entry() {\n    return;\n}\n",
+    );
+    let run_main = || {
+        let program = compile().unwrap_or_else(|_| panic!("failed to compile"));
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        machine.stack_top().cloned()
+    };
+
+    // without a manifest, there's nothing to register
+    assert_eq!(run_main(), Some(Value::from(5)));
+
+    write(
+        MANIFEST_FILE,
+        "[[precompiles]]\nmodule = \"doubler\"\naddress = 0x70\n\n\
+         [[precompiles]]\nmodule = \"tripler\"\naddress = 0x71\nfunc = \"tripler_entry\"\n",
+    );
+    assert_eq!(run_main(), Some(Value::from(5 + 0x70 + 0x71)));
+
+    // precompiles can't share an address
+    write(
+        MANIFEST_FILE,
+        "[[precompiles]]\nmodule = \"doubler\"\naddress = 0x70\n\n\
+         [[precompiles]]\nmodule = \"tripler\"\naddress = 0x70\nfunc = \"tripler_entry\"\n",
+    );
+    let errors = compile().err().unwrap().errors;
+    assert!(errors
+        .iter()
+        .any(|error| error.description.contains("share the address")));
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_coverage_probes() {
+    let folder = std::env::temp_dir().join(format!("mini-coverage-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        folder.join("main.mini"),
+        "func main() -> uint {\n    let x = 3;\n    if x > 5 {\n        x = x + 1;\n    }\n    return x;\n}\n",
+    )
+    .unwrap();
+
+    let compile = CompileStruct {
+        input: vec![folder.display().to_string()],
+        test_mode: true,
+        no_builtins: true,
+        no_cache: true,
+        coverage: true,
+        ..CompileStruct::default()
+    };
+    let (mexe, _error_system) = compile
+        .invoke()
+        .unwrap_or_else(|_| panic!("failed to compile"));
+    let mut machine = Machine::new(mexe, RuntimeEnvironment::new(None));
+    machine.start_coverage();
+    run(&mut machine, vec![], false, None).unwrap();
+    assert_eq!(machine.stack_top(), Some(&Value::Int(Uint256::from_u64(3))));
+
+    // each statement is reported once, and only the one in the untaken branch wasn't reached
+    machine.write_coverage("test_coverage_probes".to_string());
+    let report = std::fs::read_to_string("coverage/test_coverage_probes.cov").unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines, vec!["+ main 2", "+ main 3", "- main 4", "+ main 6"]);
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_asm_validation() {
+    let folder = std::env::temp_dir().join(format!("mini-asm-{}", std::process::id()));
+    let compile = |body: &str| -> Vec<String> {
+        drop(std::fs::remove_dir_all(&folder));
+        std::fs::create_dir_all(&folder).unwrap();
+        let source = format!(
+            "view write func main(a: uint, b: uint) -> uint {{\n    {}\n}}\n",
+            body
+        );
+        std::fs::write(folder.join("main.mini"), source).unwrap();
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        };
+        match compile.invoke() {
+            Ok(_) => vec![],
+            Err(error_system) => error_system
+                .errors
+                .into_iter()
+                .map(|error| crate::console::Color::uncolored(error.description))
+                .collect(),
+        }
+    };
+
+    // extra values may be left for an empty block to pick up, as is done with ecadd
+    assert!(compile("return asm(a, b) uint { add };").is_empty());
+    assert!(compile("return asm(a) uint { [2] mul };").is_empty());
+    assert!(compile("asm(a, b) { dup0 pop pop pop };\n    return asm() uint {};").is_empty());
+    assert!(compile("asm(a) { noop };\n    return asm() uint { noop };").is_empty());
+    assert!(compile("asm(a) { jump };\n    return b;").is_empty());
+
+    assert_eq!(
+        compile("return asm(a) uint { add };"),
+        vec!["Asm instruction add takes 2 values, but only 1 are on the stack"]
+    );
+    assert_eq!(
+        compile("return asm(a, b) uint { pop pop };"),
+        vec!["Asm block doesn't leave its result on the stack, as it consumes all 2 of its values"]
+    );
+    assert_eq!(
+        compile("return asm(a, b) uint { plus };"),
+        vec!["Unknown opcode plus in asm block"]
+    );
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_cfg_features() {
+    let folder = std::env::temp_dir().join(format!("mini-cfg-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        folder.join("main.mini"),
+        "#[cfg(feature = \"test\")]\nconst Base = 100;\n#[cfg(not(feature = \"test\"))]\nconst Base = 200;\n\n\
+         func main() -> uint {\n    return const::Base + extra();\n}\n\n\
+         /// Only compiled for tests\n#[cfg(feature = \"test\")]\nfunc extra() -> uint {\n    return 1;\n}\n\n\
+         #[cfg(all(not(feature = \"test\"), any(feature = \"fast\", feature = \"slow\")))]\n\
+         #[cfg(feature = \"fast\")]\nfunc extra() -> uint {\n    return 2;\n}\n\n\
+         #[cfg(not(any(feature = \"test\", feature = \"fast\")))]\nfunc extra() -> uint {\n    return 3;\n}\n",
+    )
+    .unwrap();
+
+    let run_with = |features: Vec<&str>| {
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            features: features.into_iter().map(|f| f.to_string()).collect(),
+            ..CompileStruct::default()
+        };
+        let (mexe, _error_system) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        let mut machine = Machine::new(mexe, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        machine.stack_top().cloned()
+    };
+
+    // the cache is used across these compiles, so it must tell the variants apart
+    let result = |value: u64| Some(Value::Int(Uint256::from_u64(value)));
+    assert_eq!(run_with(vec!["test"]), result(101));
+    assert_eq!(run_with(vec!["fast"]), result(202));
+    assert_eq!(run_with(vec!["slow", "other"]), result(203));
+    assert_eq!(run_with(vec![]), result(203));
+    assert_eq!(run_with(vec!["fast,test"]), result(101));
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_error_recovery() {
+    let folder = std::env::temp_dir().join(format!("mini-recovery-{}", std::process::id()));
+    let compile = |files: &[(&str, &str)]| -> Vec<(String, usize)> {
+        drop(std::fs::remove_dir_all(&folder));
+        std::fs::create_dir_all(&folder).unwrap();
+        for (name, source) in files {
+            std::fs::write(folder.join(format!("{}.mini", name)), source).unwrap();
+        }
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        };
+        let error_system = match compile.invoke() {
+            Ok(_) => return vec![],
+            Err(error_system) => error_system,
+        };
+        let mut errors: Vec<_> = error_system
+            .errors
+            .iter()
+            .map(|error| {
+                let file = error_system.file_info_chart[&error.locations[0].file_id]
+                    .name
+                    .clone();
+                (file, error.locations[0].line.to_usize() + 1)
+            })
+            .collect();
+        errors.sort();
+        errors
+    };
+    let main = "use other::helper;\n\nfunc main() -> uint {\n    return helper();\n}\n\n\
+                func extra() -> uint {\n    return 1 1;\n}\n";
+    let in_main = |line: usize| ("main".to_string(), line);
+    let in_other = |line: usize| ("other".to_string(), line);
+
+    // each syntax error is found, in every file, with the parser resuming at the next declaration
+    let errors = compile(&[
+        ("main", main),
+        (
+            "other",
+            "func broken() -> uint {\n    let x = 3 +;\n    return const::A + f(func() { });\n}\n\n\
+             public func helper() -> uint {\n    return 4 4;\n}\n\n\
+             func also() -> uint {\n    return (;\n}\n",
+        ),
+    ]);
+    assert_eq!(
+        errors,
+        vec![in_main(8), in_other(2), in_other(7), in_other(11)]
+    );
+
+    let errors = compile(&[
+        (
+            "main",
+            "use other::helper;\n\nfunc main() -> uint {\n    return helper(;\n}\n",
+        ),
+        (
+            "other",
+            "public func helper() -> uint {\n    return 4 4;\n}\n",
+        ),
+    ]);
+    // the imports of a module with syntax errors are still followed
+    assert_eq!(errors, vec![in_main(4), in_other(2)]);
+
+    // type errors are found in every func of every module
+    let errors = compile(&[
+        (
+            "main",
+            "use other::helper;\n\nfunc main() -> uint {\n    return helper() + true;\n}\n\n\
+             func second() -> bool {\n    return 1;\n}\n",
+        ),
+        (
+            "other",
+            "public func helper() -> uint {\n    return missing;\n}\n",
+        ),
+    ]);
+    assert_eq!(errors, vec![in_main(4), in_main(8), in_other(2)]);
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_static_asserts() {
+    let folder = std::env::temp_dir().join(format!("mini-static-assert-{}", std::process::id()));
+    let compile = |asserts: &str| -> Vec<String> {
+        drop(std::fs::remove_dir_all(&folder));
+        std::fs::create_dir_all(&folder).unwrap();
+        let source = format!(
+            "const QueueSize = 64;\nconst BatchSize = 16;\n\n{}\n\n\
+             func main() -> uint {{\n    return const::QueueSize / const::BatchSize;\n}}\n\n\
+             func helper() -> uint {{\n    return 4;\n}}\n",
+            asserts
+        );
+        std::fs::write(folder.join("main.mini"), source).unwrap();
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        };
+        match compile.invoke() {
+            Ok(_) => vec![],
+            Err(error_system) => error_system
+                .errors
+                .iter()
+                .map(|error| error.description.clone())
+                .collect(),
+        }
+    };
+
+    // asserts that hold let the program compile
+    let errors = compile(
+        "static_assert(const::QueueSize % const::BatchSize == 0, \"batches must fill the queue\");\n\
+         static_assert(const::QueueSize / const::BatchSize > 2 && true, \"too few batches\");",
+    );
+    assert_eq!(errors, Vec::<String>::new());
+
+    // each assert that doesn't is reported, along with those that can't be evaluated
+    let errors = compile(
+        "static_assert(const::BatchSize > const::QueueSize, \"batches must exceed the queue\");\n\
+         static_assert(helper() == 4, \"helper must return 4\");\n\
+         static_assert(const::QueueSize, \"the queue must not be empty\");",
+    );
+    assert_eq!(errors.len(), 3);
+    assert!(errors[0].contains("batches must exceed the queue"));
+    assert!(errors
+        .iter()
+        .all(|error| !error.contains("helper must return 4")));
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_overflow_checks() {
+    let folder = std::env::temp_dir().join(format!("mini-overflow-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let one = Uint256::one();
+    let minus = |x: u64| Uint256::zero().unchecked_sub(&Uint256::from_u64(x));
+    let min = Uint256::max_neg_int();
+    let max = min.unchecked_sub(&one);
+    let big = one.shift_left(128);
+
+    // runs op on left and right, which are passed to a func so that they aren't folded
+    let run_op = |tipe: &str, op: &str, attribute: &str, left: &Uint256, right: &Uint256| {
+        let source = format!(
+            "func main() -> {0} {{\n    return apply({0}(0x{2}), {0}(0x{3}));\n}}\n\n\
+             {4}func apply(a: {0}, b: {0}) -> {0} {{\n    return a {1} b;\n}}\n",
+            tipe,
+            op,
+            hex::encode(left.to_bytes_be()),
+            hex::encode(right.to_bytes_be()),
+            attribute,
+        );
+        std::fs::write(folder.join("main.mini"), source).unwrap();
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            overflow_checks: true,
+            ..CompileStruct::default()
+        };
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        match run(&mut machine, vec![], false, None) {
+            Ok(_) => machine.stack_top().cloned(),
+            Err(_) => None,
+        }
+    };
+    let ok = |tipe, op, left: &Uint256, right: &Uint256, result: Uint256| {
+        assert_eq!(
+            run_op(tipe, op, "", left, right),
+            Some(Value::Int(result)),
+            "{} {} {} {}",
+            tipe,
+            left,
+            op,
+            right
+        );
+    };
+    let wraps = |tipe, op, left: &Uint256, right: &Uint256| {
+        assert_eq!(
+            run_op(tipe, op, "", left, right),
+            None,
+            "{} {} {} {}",
+            tipe,
+            left,
+            op,
+            right
+        );
+    };
+    let uint = Uint256::from_u64;
+
+    ok(
+        "uint",
+        "+",
+        &Uint256::max_uint().unchecked_sub(&one),
+        &one,
+        Uint256::max_uint(),
+    );
+    wraps("uint", "+", &Uint256::max_uint(), &one);
+    ok("uint", "-", &uint(2), &one, one.clone());
+    wraps("uint", "-", &one, &uint(2));
+    ok("uint", "*", &big, &one.shift_left(127), min.clone());
+    ok(
+        "uint",
+        "*",
+        &Uint256::zero(),
+        &Uint256::max_uint(),
+        Uint256::zero(),
+    );
+    wraps("uint", "*", &big, &big);
+
+    ok("int", "+", &minus(1), &one, Uint256::zero());
+    wraps("int", "+", &max, &one);
+    wraps("int", "+", &min, &minus(1));
+    ok("int", "-", &minus(1), &min, max.clone());
+    wraps("int", "-", &min, &one);
+    wraps("int", "-", &Uint256::zero(), &min);
+    ok("int", "*", &minus(2), &uint(3), minus(6));
+    ok("int", "*", &minus(1), &max, min.add(&one));
+    wraps("int", "*", &one.shift_left(254), &uint(2));
+    wraps("int", "*", &minus(1), &min);
+    wraps("int", "*", &min, &minus(1));
+
+    // funcs marked wrapping are left unchecked
+    let wrapped = run_op("uint", "+", "#[wrapping]\n", &Uint256::max_uint(), &one);
+    assert_eq!(wrapped, Some(Value::Int(Uint256::zero())));
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_profiler_symbols() {
+    let folder = std::env::temp_dir().join(format!("mini-symbols-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        folder.join("main.mini"),
+        "func main() -> uint {\n    return helper(3) + helper(4);\n}\n\n\
+         func helper(x: uint) -> uint {\n    return x * x;\n}\n",
+    )
+    .unwrap();
+    let compile = CompileStruct {
+        input: vec![folder.display().to_string()],
+        test_mode: true,
+        no_builtins: true,
+        no_cache: true,
+        no_inline: true,
+        ..CompileStruct::default()
+    };
+    let (program, _) = compile
+        .invoke()
+        .unwrap_or_else(|_| panic!("failed to compile"));
+    assert!(program.symbols.contains_key("main::main"));
+    assert!(program.symbols.contains_key("main::helper"));
+
+    // the mexe's symbols survive being written and read back
+    let json = serde_json::to_string(&program).unwrap();
+    let program = LinkedProgram::from_json(&json).unwrap();
+
+    // profiled funcs are named by their symbols rather than their locations, including the entry
+    // point, which the prologue falls through to rather than calls
+    let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+    let costs = machine
+        .profile_gen(vec![], ProfilerMode::Always)
+        .func_costs();
+    assert!(costs.get("main::main").copied().unwrap_or(0) > 0);
+    assert!(costs.get("main::helper").copied().unwrap_or(0) > 0);
+    assert!(costs.keys().all(|name| !name.contains(".mini:")));
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_debug_sidecar() {
+    let folder = std::env::temp_dir().join(format!("mini-sidecar-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        folder.join("main.mini"),
+        "func main() -> uint {\n    return 3;\n}\n",
+    )
+    .unwrap();
+    let mexe = folder.join("main.mexe");
+    let compile = CompileStruct {
+        input: vec![folder.display().to_string()],
+        output: Some(mexe.display().to_string()),
+        test_mode: true,
+        no_builtins: true,
+        no_cache: true,
+        debug_sidecar: true,
+        ..CompileStruct::default()
+    };
+    let (program, _) = compile
+        .invoke()
+        .unwrap_or_else(|_| panic!("failed to compile"));
+    program.to_output(&mut std::fs::File::create(&mexe).unwrap(), None);
+
+    // the mexe is left without debug info, which is in the sidecar beside it
+    assert!(program.file_info_chart.is_empty());
+    assert!(program.symbols.is_empty());
+    assert!(program
+        .code
+        .iter()
+        .all(|insn| insn.debug_info.location.is_none()));
+    assert!(crate::link::sidecar_path(&mexe).exists());
+
+    // loading the mexe loads the sidecar with it
+    let (mut machine, file_info_chart) = crate::run::load_from_file_and_env_ret_file_info_table(
+        &mexe,
+        RuntimeEnvironment::new(None),
+    );
+    assert!(file_info_chart
+        .values()
+        .any(|info| info.name.contains("main")));
+    run(&mut machine, vec![], false, None).unwrap();
+    assert_eq!(machine.stack_top(), Some(&Value::Int(Uint256::from_u64(3))));
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_method_visibility() {
+    let folder = std::env::temp_dir().join(format!("mini-visibility-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let write = |name: &str, source: &str| std::fs::write(folder.join(name), source).unwrap();
+    let compile = |main: &str| {
+        write("main.mini", main);
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        };
+        compile
+            .invoke()
+            .map(|(program, _)| program)
+            .map_err(|error_system| {
+                error_system
+                    .errors
+                    .iter()
+                    .map(|error| error.description.clone())
+                    .collect::<Vec<_>>()
+            })
+    };
+
+    write(
+        "shapes.mini",
+        "type square = struct { side: uint };\n\n\
+         public func square_new(side: uint) -> square {\n    return struct { side: side };\n}\n\n\
+         impl square {\n    \
+             public func area(self: square) -> uint {\n        return self.side * self.side;\n    }\n    \
+             func doubled(self: square) -> uint {\n        return 2 * self.area();\n    }\n\
+         }\n",
+    );
+
+    // public methods can be called from other modules, and private ones from their own
+    let program = compile(
+        "use shapes::square_new;\n\n\
+         func main() -> uint {\n    return square_new(3).area();\n}\n",
+    )
+    .unwrap();
+    let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+    run(&mut machine, vec![], false, None).unwrap();
+    assert_eq!(machine.stack_top(), Some(&Value::from(9)));
+
+    // but private ones can't be called from anywhere else
+    let errors = compile(
+        "use shapes::square_new;\n\n\
+         func main() -> uint {\n    return square_new(3).doubled();\n}\n",
+    )
+    .err()
+    .unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("square::doubled"));
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_inlining() {
+    let folder = std::env::temp_dir().join(format!("mini-inline-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let write = |name: &str, source: &str| std::fs::write(folder.join(name), source).unwrap();
+    write(
+        "counter.mini",
+        "type counter = struct { count: uint, step: uint };\n\n\
+         public func counter_new(step: uint) -> counter {\n    \
+             return struct { count: 0, step: step };\n}\n\n\
+         public func counter_count(c: counter) -> uint {\n    return c.count;\n}\n\n\
+         #[inline]\n\
+         public func counter_tick(c: counter) -> counter {\n    \
+             if c.count >= 1000 {\n        return c;\n    }\n    \
+             return struct { count: c.count + c.step, step: c.step };\n}\n",
+    );
+    write(
+        "main.mini",
+        "use counter::counter_new;\nuse counter::counter_count;\nuse counter::counter_tick;\n\n\
+         func main() -> uint {\n    \
+             let c = counter_new(3);\n    \
+             let i = 0;\n    \
+             while i < 10 {\n        c = counter_tick(c);\n        i = i + 1;\n    }\n    \
+             return counter_count(c) + i;\n}\n",
+    );
+    let run_compiled = |no_inline: bool| {
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            no_inline,
+            ..CompileStruct::default()
+        };
+        let (program, error_system) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        assert_eq!(machine.stack_top(), Some(&Value::from(40)));
+        let unreachable = error_system
+            .warnings
+            .iter()
+            .filter(|warning| warning.description.contains("unreachable"))
+            .count();
+        (machine.get_total_gas_usage(), unreachable)
+    };
+
+    // inlined funcs behave the same, but cost less to reach, and aren't warned of as unreachable
+    let (called, _) = run_compiled(true);
+    let (inlined, unreachable) = run_compiled(false);
+    assert!(inlined < called);
+    assert_eq!(unreachable, 0);
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_error_codes() {
+    let folder = std::env::temp_dir().join(format!("mini-error-codes-{}", std::process::id()));
+    let compile = |source: &str| -> Vec<String> {
+        drop(std::fs::remove_dir_all(&folder));
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join("main.mini"), source).unwrap();
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        };
+        match compile.invoke() {
+            Ok(_) => vec![],
+            Err(error_system) => error_system
+                .errors
+                .iter()
+                .filter_map(|error| error.code)
+                .map(|code| code.to_string())
+                .collect(),
+        }
+    };
+
+    // the examples of the explanations cause the errors they explain
+    assert_eq!(
+        compile("func main() -> uint {\n    let x = 3\n    return x;\n}\n"),
+        vec!["E0001"]
+    );
+    assert_eq!(
+        compile("func main() -> uint {\n    let total = 3;\n    return totl;\n}\n"),
+        vec!["E0004"]
+    );
+    assert_eq!(
+        compile("func main() -> uint {\n    count = 1;\n    return count;\n}\n"),
+        vec!["E0005"]
+    );
+    assert_eq!(
+        compile("func main() -> uint {\n    return 3 > 1;\n}\n"),
+        vec!["E0007"]
+    );
+    assert_eq!(
+        compile(
+            "func double(x: uint) -> uint {\n    return 2 * x;\n}\n\n\
+             func main() -> uint {\n    return double(true);\n}\n"
+        ),
+        vec!["E0009"]
+    );
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_target_arbos_version() {
+    let folder = std::env::temp_dir().join(format!("mini-target-version-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        folder.join("main.mini"),
+        "func main() -> uint {\n    return getbuffer8(newbuffer(), 0);\n}\n",
+    )
+    .unwrap();
+    let compile = |target_arbos_version: Option<u64>| {
+        CompileStruct {
+            input: vec![folder.display().to_string()],
+            consts_file: Some(String::from("arb_os/constants.json")),
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            target_arbos_version,
+            ..CompileStruct::default()
+        }
+        .invoke()
+    };
+    let current = init_constant_table(Some(Path::new("arb_os/constants.json"))).unwrap()
+        ["ArbosVersionNumber"]
+        .trim_to_u64();
+
+    // without a target, the version comes from the constants file
+    let (program, _) = compile(None).unwrap_or_else(|_| panic!("failed to compile"));
+    assert_eq!(program.arbos_version, current);
+    let (program, _) = compile(Some(10)).unwrap_or_else(|_| panic!("failed to compile"));
+    assert_eq!(program.arbos_version, 10);
+
+    // buffers came after version 9, and versions past the current one don't exist yet
+    let targets_error = |target: u64| match compile(Some(target)) {
+        Ok(_) => false,
+        Err(error_system) => error_system
+            .errors
+            .iter()
+            .any(|error| error.title == "Target error"),
+    };
+    assert!(targets_error(9));
+    assert!(targets_error(current + 1));
+
+    // the target decides the version the program sees, and so which declarations it keeps
+    std::fs::write(
+        folder.join("main.mini"),
+        "#[cfg(arbos_version = \"10\")]\n\
+         func read() -> uint {\n    return getbuffer8(newbuffer(), 0);\n}\n\n\
+         #[cfg(not(arbos_version = \"10\"))]\n\
+         func read() -> uint {\n    return const::ArbosVersionNumber;\n}\n\n\
+         func main() -> uint {\n    return read();\n}\n",
+    )
+    .unwrap();
+    for (target, read) in &[(9, 9), (10, 0)] {
+        let (program, _) = compile(Some(*target)).unwrap_or_else(|_| panic!("failed to compile"));
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        assert_eq!(machine.stack_top(), Some(&Value::from(*read)));
+    }
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_error_fixes() {
+    let folder = std::env::temp_dir().join(format!("mini-error-fixes-{}", std::process::id()));
+    let compile = |source: &str| -> (String, String) {
+        drop(std::fs::remove_dir_all(&folder));
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join("main.mini"), source).unwrap();
+        std::fs::write(
+            folder.join("counter.mini"),
+            "public func counter_new() -> uint {\n    return 0;\n}\n",
+        )
+        .unwrap();
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        };
+        let error_system = match compile.invoke() {
+            Ok(_) => panic!("compiled a mistake"),
+            Err(error_system) => error_system,
+        };
+        let error = error_system
+            .errors
+            .iter()
+            .find(|error| error.fix.is_some())
+            .expect("no fix");
+        let fix = error.fix.as_ref().unwrap();
+        let mut lines: Vec<String> = source.lines().map(String::from).collect();
+        let line = &mut lines[fix.start.line.to_usize()];
+        line.replace_range(
+            fix.start.column.to_usize()..fix.end.column.to_usize(),
+            &fix.replacement,
+        );
+        let pretty = error.pretty_fmt(&error_system.file_info_chart, false);
+        (line.clone(), Color::uncolored(pretty))
+    };
+
+    // each mistake's fix is suggested, showing the fixed line
+    let (fixed, pretty) = compile("func main() -> uint {\n    let x = 3\n    return x;\n}\n");
+    assert_eq!(fixed, "    let x = 3;");
+    assert!(pretty.contains("help: add a semicolon"));
+    assert!(pretty.contains("|     let x = 3;\n"));
+    assert!(pretty.contains("|              +\n"));
+
+    let (fixed, _) = compile("func main() -> uint {\n    let x = 3;\n    x + 1;\n}\n");
+    assert_eq!(fixed, "    return x + 1;");
+
+    let (fixed, pretty) = compile(
+        "use Counter::counter_new;\n\nfunc main() -> uint {\n    return counter_new();\n}\n",
+    );
+    assert_eq!(fixed, "use counter::counter_new;");
+    assert!(pretty.contains("help: import from counter instead"));
+
+    let (fixed, _) = compile(
+        "use counter::Counter_New;\n\nfunc main() -> uint {\n    return Counter_New();\n}\n",
+    );
+    assert_eq!(fixed, "use counter::counter_new;");
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_const_globals() {
+    let run_compiled = |const_globals: bool| {
+        let compile = CompileStruct {
+            input: vec!["minitests/const-globals.mini".to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            no_inline: true,
+            const_globals,
+            ..CompileStruct::default()
+        };
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        assert_eq!(machine.stack_top(), Some(&Value::from(15)));
+        machine.get_total_gas_usage()
+    };
+
+    // only limit is propagated: ready is read by its init func, and mode is set to two values
+    let read = run_compiled(false);
+    let propagated = run_compiled(true);
+    assert!(propagated < read);
+}
+
+#[test]
+fn test_const_globals_before_init() {
+    let run_compiled = |main: &str, const_globals: bool| {
+        let compile = CompileStruct {
+            input: vec![format!("minitests/const-globals-init/{}.mini", main)],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            no_inline: true,
+            const_globals,
+            ..CompileStruct::default()
+        };
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        assert_eq!(machine.stack_top(), Some(&Value::from(7)));
+        machine.get_total_gas_usage()
+    };
+
+    // peek runs before the init func, through a func main calls, so it sees limit unset and limit
+    // is read everywhere
+    assert_eq!(run_compiled("early", true), run_compiled("early", false));
+
+    // when nothing reads it early, limit is propagated to later
+    assert!(run_compiled("late", true) < run_compiled("late", false));
+
+    // once main sets the globals register, the init func may not have set what's read after
+    assert_eq!(
+        run_compiled("replaced", true),
+        run_compiled("replaced", false)
+    );
+}
+
+#[test]
+fn test_link_time_optimization() {
+    let run_compiled = |no_lto: bool| {
+        let compile = CompileStruct {
+            input: vec!["minitests/lto".to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            no_inline: true,
+            const_globals: true,
+            no_lto,
+            ..CompileStruct::default()
+        };
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        assert_eq!(machine.stack_top(), Some(&Value::from(27)));
+        machine.get_total_gas_usage()
+    };
+
+    // the settings are only known to be constants once the program is linked, after which the
+    // branch on loud and the sum with factor fold away
+    let separate = run_compiled(true);
+    let whole = run_compiled(false);
+    assert!(whole < separate);
+}
+
+#[test]
+fn test_link_order_independence() {
+    let folder = std::env::temp_dir().join(format!("mini-link-order-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        folder.join("alpha.mini"),
+        "func main() -> uint {\n    return twice(3);\n}\n\n\
+         func twice(x: uint) -> uint {\n    return 2 * x;\n}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        folder.join("beta.mini"),
+        "func main() -> uint {\n    return 7;\n}\n\n\
+         public func spare() -> uint {\n    return 1;\n}\n",
+    )
+    .unwrap();
+    let compile = |inputs: [&str; 2]| {
+        let compile = CompileStruct {
+            input: inputs
+                .iter()
+                .map(|input| folder.join(input).display().to_string())
+                .collect(),
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        };
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        program
+    };
+
+    // the same inputs build the same program, entered through the first main by path
+    let forward = compile(["alpha.mini", "beta.mini"]);
+    let backward = compile(["beta.mini", "alpha.mini"]);
+    assert_eq!(
+        serde_json::to_string(&forward).unwrap(),
+        serde_json::to_string(&backward).unwrap()
+    );
+    let mut machine = Machine::new(backward, RuntimeEnvironment::new(None));
+    run(&mut machine, vec![], false, None).unwrap();
+    assert_eq!(machine.stack_top(), Some(&Value::from(6)));
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_incremental_linking() {
+    let folder = std::env::temp_dir().join(format!("mini-incremental-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let write_main = |factor: u64| {
+        std::fs::write(
+            folder.join("main.mini"),
+            format!(
+                "func main() -> uint {{\n    return scale(2) + count(3);\n}}\n\n\
+                 func scale(x: uint) -> uint {{\n    return {} * x;\n}}\n\n\
+                 func count(x: uint) -> uint {{\n    let i = 0;\n    \
+                     while i < x {{\n        i = i + 1;\n    }}\n    return i;\n}}\n",
+                factor
+            ),
+        )
+        .unwrap();
+    };
+    let output = folder.join("main.mexe");
+    let compile = |incremental: bool| {
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            output: Some(output.display().to_string()),
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            no_inline: true,
+            incremental,
+            ..CompileStruct::default()
+        };
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        serde_json::to_string(&program).unwrap()
+    };
+
+    // relinking after a change gives the program linking from scratch would
+    write_main(2);
+    assert_eq!(compile(true), compile(false));
+    assert!(folder.join("main.linkcache").exists());
+    write_main(5);
+    let relinked = compile(true);
+    assert_eq!(relinked, compile(false));
+    assert_eq!(compile(true), relinked);
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_unit_tests() {
+    let folder = std::env::temp_dir().join(format!("mini-unit-tests-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let write_main = |tests: &str| {
+        std::fs::write(
+            folder.join("main.mini"),
+            format!(
+                "var count: uint;\n\n\
+                 view write func main() -> uint {{\n    return double(2);\n}}\n\n\
+                 view write func double(x: uint) -> uint {{\n    count = count + 1;\n    return 2 * x;\n}}\n\n{}",
+                tests
+            ),
+        )
+        .unwrap();
+    };
+    let compile = CompileStruct {
+        input: vec![folder.display().to_string()],
+        test_mode: true,
+        no_builtins: true,
+        no_cache: true,
+        ..CompileStruct::default()
+    };
+
+    write_main(
+        "#[test]\nview write func doubles() {\n    assert((double(3) == 6, ()));\n    \
+         assert((count == 1, ()));\n}\n\n\
+         #[test]\nview write func counts() {\n    let _ = double(1);\n    assert((count == 2, count));\n}\n",
+    );
+    let run_tests = |filter: Option<&str>| {
+        let (tests, _) = compile
+            .invoke_tests()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        assert_eq!(tests.len(), 2);
+        let mut output = vec![];
+        let passed = crate::run::run_unit_tests(tests, filter, &mut output).unwrap();
+        (passed, String::from_utf8(output).unwrap())
+    };
+
+    // each test runs on a fresh machine, so counts doesn't see the call doubles made
+    let (passed, output) = run_tests(None);
+    assert!(!passed);
+    assert!(output.contains("doubles ... "));
+    assert!(output.contains("Line: 21"));
+    assert!(output.contains("declared at Line: 19"));
+    assert!(output.contains("1 failed"));
+
+    let (passed, output) = run_tests(Some("doubles"));
+    assert!(passed);
+    assert!(output.contains("running 1 tests"));
+
+    write_main("#[test]\nfunc takes(x: uint) {\n    assert((x == 0, ()));\n}\n");
+    let error_system = match compile.invoke_tests() {
+        Ok(_) => panic!("test with args compiled"),
+        Err(error_system) => error_system,
+    };
+    assert!(error_system
+        .errors
+        .iter()
+        .any(|error| error.description.contains("can't take args")));
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_scalarized_tuples() {
+    let folder = std::env::temp_dir().join(format!("mini-scalarized-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        folder.join("main.mini"),
+        "type Point = struct {\n    x: uint,\n    y: uint,\n};\n\n\
+         func main() -> uint {\n    \
+             let total = 0;\n    let i = 0;\n    \
+             while i < 20 {\n        total = total + walk(i) + kept(i);\n        i = i + 1;\n    }\n    \
+             return total;\n}\n\n\
+         func walk(n: uint) -> uint {\n    \
+             let p = struct { x: n, y: 1 };\n    let pair = (n, 2);\n    \
+             while p.x > 0 {\n        set p.x = p.x - 1;\n        set p.y = p.y + pair.1;\n    }\n    \
+             if n > 10 {\n        let q = (p.y, 3);\n        set p.y = q.0 + q.1;\n    }\n    \
+             return p.y * pair.0;\n}\n\n\
+         func kept(n: uint) -> uint {\n    \
+             let p = struct { x: n, y: 2 };\n    let moved = p with { y: 5 };\n    \
+             set p.x = p.x + moved.y;\n    return sum(p);\n}\n\n\
+         func sum(p: Point) -> uint {\n    return p.x + p.y;\n}\n",
+    )
+    .unwrap();
+
+    // the program is run at both levels, which should agree on its result
+    let run_compiled = |opt_level: u8| {
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            opt_level,
+            ..CompileStruct::default()
+        };
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        (machine.stack_top().cloned(), machine.get_total_gas_usage())
+    };
+
+    let (result, gas) = run_compiled(1);
+    let (scalarized, scalarized_gas) = run_compiled(2);
+    assert_eq!(result, Some(Value::from(5865)));
+    assert_eq!(scalarized, result);
+    assert!(scalarized_gas < gas);
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_scalarized_tuples_differential() {
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Writes random funcs that build, read, set, and leak tuples and structs.
+    struct Generator {
+        rng: SmallRng,
+        widths: Vec<usize>,
+        fresh: usize,
+        /// Whether the tuples and structs have been bound yet
+        bound: bool,
+    }
+
+    impl Generator {
+        fn expr(&mut self, depth: usize) -> String {
+            let choices = match (self.bound, depth > 1) {
+                (false, _) => 2,
+                (true, true) => 4,
+                (true, false) => 6,
+            };
+            match self.rng.gen_range(0..choices) {
+                0 => self.rng.gen_range(0..10).to_string(),
+                1 => ["a", "b"][self.rng.gen_range(0..2)].to_string(),
+                2 => {
+                    let tuple = self.rng.gen_range(0..self.widths.len());
+                    let field = self.rng.gen_range(0..self.widths[tuple]);
+                    format!("t{}.{}", tuple, field)
+                }
+                3 => format!(
+                    "s{}.{}",
+                    self.rng.gen_range(0..2),
+                    ["x", "y"][self.rng.gen_range(0..2)]
+                ),
+                4 => format!("({} + {})", self.expr(depth + 1), self.expr(depth + 1)),
+                _ => format!("({} * {})", self.expr(depth + 1), self.expr(depth + 1)),
+            }
+        }
+
+        fn block(&mut self, depth: usize) -> String {
+            let count = self.rng.gen_range(1..5);
+            (0..count).map(|_| self.statement(depth)).collect()
+        }
+
+        fn statement(&mut self, depth: usize) -> String {
+            let struc = self.rng.gen_range(0..2);
+            match self.rng.gen_range(0..if depth > 1 { 7 } else { 9 }) {
+                0 => format!("a = {};\n", self.expr(0)),
+                1 => format!("b = {};\n", self.expr(0)),
+                2 => format!(
+                    "set s{}.{} = {};\n",
+                    struc,
+                    ["x", "y"][self.rng.gen_range(0..2)],
+                    self.expr(0)
+                ),
+                3 => format!("a = a + sum(s{});\n", struc),
+                4 => format!(
+                    "s{} = struct {{ x: {}, y: {} }};\n",
+                    struc,
+                    self.expr(0),
+                    self.expr(0)
+                ),
+                5 => {
+                    // some names are bound in two scopes, which keeps them whole
+                    self.fresh += self.rng.gen_range(0..2);
+                    let (name, left, right) = (self.fresh, self.expr(0), self.expr(0));
+                    format!(
+                        "let u{0} = ({1}, {2});\nb = b + u{0}.0 * u{0}.1;\n",
+                        name, left, right
+                    )
+                }
+                6 => {
+                    self.fresh += 1;
+                    format!("let c{} = s{};\na = a + c{0}.y;\n", self.fresh, struc)
+                }
+                7 => {
+                    let (left, right) = (self.expr(0), self.expr(0));
+                    let (then, other) = (self.block(depth + 1), self.block(depth + 1));
+                    format!(
+                        "if {} < {} {{\n{}}} else {{\n{}}}\n",
+                        left, right, then, other
+                    )
+                }
+                _ => {
+                    self.fresh += 1;
+                    let (name, times) = (self.fresh, self.rng.gen_range(1..4));
+                    let body = self.block(depth + 1);
+                    format!(
+                        "let i{0} = 0;\nwhile i{0} < {1} {{\n{2}i{0} = i{0} + 1;\n}}\n",
+                        name, times, body
+                    )
+                }
+            }
+        }
+
+        fn program(&mut self) -> String {
+            let widths = (0..2).map(|_| self.rng.gen_range(2..5)).collect();
+            self.widths = widths;
+            self.fresh = 0;
+            self.bound = false;
+            let mut body = format!(
+                "let a = {};\nlet b = {};\n",
+                self.rng.gen_range(0..10),
+                self.rng.gen_range(0..10)
+            );
+            let mut total = vec!["a".to_string(), "b".to_string()];
+            for (tuple, width) in self.widths.clone().into_iter().enumerate() {
+                let fields: Vec<_> = (0..width).map(|_| self.expr(0)).collect();
+                body += &format!("let t{} = ({});\n", tuple, fields.join(", "));
+                total.extend((0..width).map(|field| format!("t{}.{}", tuple, field)));
+            }
+            for struc in 0..2 {
+                body += &format!(
+                    "let s{} = struct {{ x: {}, y: {} }};\n",
+                    struc,
+                    self.expr(0),
+                    self.expr(0)
+                );
+                total.push(format!("s{}.x + s{0}.y", struc));
+            }
+            self.bound = true;
+            body += &self.block(0);
+            format!(
+                "type Point = struct {{\n    x: uint,\n    y: uint,\n}};\n\n\
+                 func main() -> uint {{\n{}return {};\n}}\n\n\
+                 func sum(p: Point) -> uint {{\n    return p.x + p.y;\n}}\n",
+                body,
+                total.join(" + ")
+            )
+        }
+    }
+
+    let folder = std::env::temp_dir().join(format!("mini-scalarized-diff-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+
+    let run_compiled = |opt_level: u8| {
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            opt_level,
+            ..CompileStruct::default()
+        };
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|error_system| panic!("failed to compile: {:?}", error_system.errors));
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        (machine.stack_top().cloned(), machine.get_total_gas_usage())
+    };
+
+    let mut generator = Generator {
+        rng: SmallRng::seed_from_u64(1285),
+        widths: vec![],
+        fresh: 0,
+        bound: false,
+    };
+    let mut scalarized = 0;
+    for _ in 0..100 {
+        let source = generator.program();
+        std::fs::write(folder.join("main.mini"), &source).unwrap();
+        let (result, gas) = run_compiled(1);
+        let (optimized, optimized_gas) = run_compiled(2);
+        assert!(result.is_some());
+        assert_eq!(
+            result, optimized,
+            "scalarizing tuples changed the result of\n{}",
+            source
+        );
+        if optimized_gas < gas {
+            scalarized += 1;
+        }
+    }
+    assert!(scalarized > 0);
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_unreachable_funcs_left_out() {
+    let folder = std::env::temp_dir().join(format!("mini-tree-shaking-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let main = "func main() -> uint {\n    return used(3);\n}\n\n\
+                func used(x: uint) -> uint {\n    let y = x;\n    while y < 100 {\n        \
+                y = y * x;\n    }\n    return y;\n}\n";
+    let linked_size = |source: String| {
+        std::fs::write(folder.join("main.mini"), source).unwrap();
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            keep_all: true,
+            ..CompileStruct::default()
+        };
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        program.code.len()
+    };
+
+    // funcs main never reaches are left out, even when they reach each other
+    let unused = "func unused() -> uint {\n    return unused_too(5) + used(2);\n}\n\n\
+                  func unused_too(x: uint) -> uint {\n    return unused() * x;\n}\n";
+    assert_eq!(
+        linked_size(format!("{}\n{}", main, unused)),
+        linked_size(main.to_string())
+    );
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_library_archives() {
+    let compile = |folder: &Path, lib: Vec<String>| CompileStruct {
+        input: vec![folder.display().to_string()],
+        test_mode: true,
+        no_builtins: true,
+        no_cache: true,
+        lib,
+        ..CompileStruct::default()
+    };
+    let (mut archive, _) = compile(Path::new("minitests/archive-library"), vec![])
+        .invoke_module()
+        .unwrap_or_else(|_| panic!("failed to compile the library"));
+    assert!(archive.symbols.contains_key("counter::counter_add"));
+
+    // renaming the archived func shows whether it's the one a program links
+    for module in &mut archive.modules {
+        for func in &mut module.funcs {
+            func.name.push_str("_archived");
+        }
+    }
+    let folder = std::env::temp_dir().join(format!("mini-archives-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let path = folder.join("counter.mlib");
+    archive
+        .write(&mut std::fs::File::create(&path).unwrap())
+        .unwrap();
+
+    // the program has another global ahead of the counter's, which the archive must make room for
+    let run_compiled = |program: &Path| {
+        let (program, _) = compile(program, vec![path.display().to_string()])
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        let archived = program
+            .symbols
+            .contains_key("counter::counter_add_archived");
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        (machine.stack_top().cloned(), archived)
+    };
+    let program = Path::new("minitests/archive-program");
+    assert_eq!(run_compiled(program), (Some(Value::from(15)), true));
+
+    // once the counter changes, it's compiled again rather than taken from the archive
+    let changed = folder.join("program");
+    std::fs::create_dir_all(&changed).unwrap();
+    std::fs::copy(program.join("main.mini"), changed.join("main.mini")).unwrap();
+    let counter = std::fs::read_to_string(program.join("counter.mini")).unwrap();
+    std::fs::write(
+        changed.join("counter.mini"),
+        counter.replace("total + n", "total + 2 * n"),
+    )
+    .unwrap();
+    assert_eq!(run_compiled(&changed), (Some(Value::from(30)), false));
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_debugger_scripts() {
+    let folder = std::env::temp_dir().join(format!("mini-debug-scripts-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        folder.join("main.mini"),
+        "func main() -> uint {\n    let x = 3;\n    let y = x * 4;\n    return y + 1;\n}\n",
+    )
+    .unwrap();
+    let compile = CompileStruct {
+        input: vec![folder.display().to_string()],
+        test_mode: true,
+        no_builtins: true,
+        no_cache: true,
+        ..CompileStruct::default()
+    };
+    let script = folder.join("walkthrough.txt");
+    let recording = folder.join("recorded.txt");
+    let debug = |commands: &str| {
+        std::fs::write(&script, commands).unwrap();
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        machine.replay_debug_script(&script).unwrap();
+        machine.record_debug_session(&recording).unwrap();
+        let result = run(&mut machine, vec![], true, None).map(|_| machine.stack_top().cloned());
+        let recorded = std::fs::read_to_string(&recording).unwrap();
+        (result.map_err(|(error, _)| error.to_string()), recorded)
+    };
+
+    // lines are counted from zero, so the return is on line 3, and moving the break past the end
+    // runs the program out
+    let (result, recorded) =
+        debug("# walk to the return\nset break\n3\nexpect line\n3\nstep\nset break\n1000\n");
+    assert_eq!(result, Ok(Some(Value::from(13))));
+    assert_eq!(
+        recorded,
+        "set break\n3\nexpect line\n3\nstep\nset break\n1000\n"
+    );
+
+    let (result, _) = debug("set break\n3\nexpect line\n2\n");
+    assert!(result.unwrap_err().contains("debugger expectation failed"));
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_symbol_map() {
+    let folder = std::env::temp_dir().join(format!("mini-symbol-map-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        folder.join("main.mini"),
+        "func main() -> uint {\n    return double(2) + double(3);\n}\n\n\
+         func double(x: uint) -> uint {\n    return 2 * x;\n}\n",
+    )
+    .unwrap();
+    let output = folder.join("main.mexe");
+    let compile = CompileStruct {
+        input: vec![folder.display().to_string()],
+        output: Some(output.display().to_string()),
+        test_mode: true,
+        no_builtins: true,
+        no_cache: true,
+        no_inline: true,
+        symbol_map: true,
+        ..CompileStruct::default()
+    };
+    let (program, _) = compile
+        .invoke()
+        .unwrap_or_else(|_| panic!("failed to compile"));
+
+    let json = std::fs::read_to_string(folder.join("main.symbols.json")).unwrap();
+    let symbols: crate::link::SymbolMap = serde_json::from_str(&json).unwrap();
+    let func = |name: &str| {
+        symbols
+            .funcs
+            .iter()
+            .find(|func| func.name == name)
+            .unwrap_or_else(|| panic!("{} isn't in the symbol map", name))
+    };
+    let (main, double) = (func("main::main"), func("main::double"));
+    assert_eq!((main.line, double.line), (Some(1), Some(5)));
+    assert!(double
+        .file
+        .as_ref()
+        .map_or(false, |file| file.ends_with("main.mini")));
+
+    // each func's codepoints hold the code compiled from it, and no two funcs share any
+    for func in [main, double].iter() {
+        assert!(func.start < func.end && func.end <= program.code.len());
+        let lines: BTreeSet<_> = program.code[func.start..func.end]
+            .iter()
+            .filter_map(|insn| insn.debug_info.location)
+            .map(|location| location.line.to_usize() + 1)
+            .collect();
+        assert!(lines.contains(&func.line.unwrap()) || lines.contains(&(func.line.unwrap() + 1)));
+    }
+    assert!(main.end <= double.start || double.end <= main.start);
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_entry_point() {
+    let folder = std::env::temp_dir().join(format!("mini-entry-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        folder.join("alpha.mini"),
+        "func main() -> uint {\n    return 1;\n}\n\n\
+         public func scale(x: uint) -> uint {\n    return 10 * x;\n}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        folder.join("beta.mini"),
+        "func main() -> uint {\n    return 2;\n}\n\n\
+         public func scale(x: uint) -> uint {\n    return 100 * x;\n}\n\n\
+         public func tester() -> uint {\n    return 3;\n}\n",
+    )
+    .unwrap();
+    let compile = |entry: &str| {
+        CompileStruct {
+            input: vec![
+                folder.join("alpha.mini").display().to_string(),
+                folder.join("beta.mini").display().to_string(),
+            ],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            entry: Some(entry.to_string()),
+            ..CompileStruct::default()
+        }
+        .invoke()
+        .map(|(program, _)| program)
+    };
+    let start = |program: LinkedProgram, args: Vec<Value>| {
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, args, false, None).unwrap();
+        machine.stack_top().cloned()
+    };
+
+    // an entry point is named by its name, or by its module too when that's ambiguous
+    let program = compile("tester").unwrap_or_else(|_| panic!("failed to compile"));
+    assert_eq!(start(program, vec![]), Some(Value::from(3)));
+    let program = compile("beta::tester").unwrap_or_else(|_| panic!("failed to compile"));
+    assert_eq!(start(program, vec![]), Some(Value::from(3)));
+    let program = compile("alpha::scale").unwrap_or_else(|_| panic!("failed to compile"));
+    assert_eq!(start(program, vec![Value::from(4)]), Some(Value::from(40)));
+
+    // only public funcs can be entry points, so the private mains are left out
+    for entry in &["scale", "missing", "beta::main", "main"] {
+        let error_system = compile(*entry).err().unwrap();
+        assert_eq!(error_system.errors[0].title, "Entry point error");
+    }
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_define() {
+    let folder = std::env::temp_dir().join(format!("mini-define-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let source = folder.join("main.mini");
+    std::fs::write(
+        &source,
+        "func main() -> uint {\n    return const::ShouldBeUsed;\n}\n",
+    )
+    .unwrap();
+    let compile = |define: Vec<&str>| {
+        CompileStruct {
+            input: vec![source.display().to_string()],
+            consts_file: Some(String::from("minitests/constants.json")),
+            define: define.into_iter().map(String::from).collect(),
+            test_mode: true,
+            no_builtins: true,
+            ..CompileStruct::default()
+        }
+        .invoke()
+        .map(|(program, _)| program)
+    };
+    let start = |program: LinkedProgram| {
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        machine.stack_top().cloned()
+    };
+
+    // definitions override the constants file, and are recorded in the mexe
+    let program = compile(vec![]).unwrap_or_else(|_| panic!("failed to compile"));
+    assert!(program.defines.is_empty());
+    assert_eq!(start(program), Some(Value::from(8)));
+    let program =
+        compile(vec!["ShouldBeUsed=0x2a"]).unwrap_or_else(|_| panic!("failed to compile"));
+    assert_eq!(program.defines["ShouldBeUsed"], Uint256::from_u64(42));
+    assert_eq!(start(program), Some(Value::from(42)));
+
+    for define in &["Missing=1", "ShouldBeUsed"] {
+        let error_system = compile(vec![*define]).err().unwrap();
+        assert_eq!(error_system.errors[0].title, "Define error");
+    }
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_prologue_modes() {
+    let compile = |test_mode: bool| {
+        CompileStruct {
+            input: vec!["minitests/prologue.mini".to_string()],
+            test_mode,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        }
+        .invoke()
+        .map(|(program, _)| program)
+        .unwrap_or_else(|_| panic!("failed to compile"))
+    };
+
+    // both prologues set the globals before main runs, production's keeping the register first
+    for (test_mode, prologue) in &[
+        (true, vec![AVMOpcode::Noop, AVMOpcode::Rset]),
+        (
+            false,
+            vec![AVMOpcode::Rpush, AVMOpcode::Noop, AVMOpcode::Rset],
+        ),
+    ] {
+        let program = compile(*test_mode);
+        let opcodes: Vec<_> = program.code[..prologue.len()]
+            .iter()
+            .map(|insn| insn.opcode)
+            .collect();
+        assert_eq!(&opcodes, prologue);
+        let globals = &program.code[prologue.len() - 1].immediate;
+        assert!(matches!(globals, Some(Value::Tuple(_))));
+
+        // production's main is given the unit value to return to, as ArbOS's never returns
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        let returned = run(&mut machine, vec![], false, None).is_ok();
+        assert_eq!(returned, *test_mode);
+        let logs = machine.runtime_env.get_all_raw_logs();
+        assert_eq!(logs, vec![Value::from(5)]);
+    }
+}
+
+#[test]
+fn test_link_objects() {
+    let folder = std::env::temp_dir().join(format!("mini-link-objects-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let compile = |input: Vec<String>| CompileStruct {
+        input,
+        test_mode: true,
+        no_builtins: true,
+        no_cache: true,
+        ..CompileStruct::default()
+    };
+    let write_object = |sources: &str, name: &str| {
+        let (archive, _) = compile(vec![sources.to_string()])
+            .invoke_module()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        let path = folder.join(name);
+        archive
+            .write(&mut std::fs::File::create(&path).unwrap())
+            .unwrap();
+        path.display().to_string()
+    };
+    let start = |program: LinkedProgram| {
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        machine.stack_top().cloned()
+    };
+
+    // linking an object builds the program compiling its sources would
+    let object = write_object("minitests/link-object", "main.mlib");
+    let (compiled, _) = compile(vec!["minitests/link-object".to_string()])
+        .invoke()
+        .unwrap_or_else(|_| panic!("failed to compile"));
+    let (linked, _) = compile(vec![object.clone()])
+        .invoke_link()
+        .unwrap_or_else(|_| panic!("failed to link"));
+    assert_eq!(linked.code.len(), compiled.code.len());
+    assert_eq!(linked.symbols, compiled.symbols);
+    assert_eq!(start(linked), Some(Value::from(13)));
+
+    // objects of the same program share their modules, while those of another can't be mixed in
+    let (linked, _) = compile(vec![object.clone(), object.clone()])
+        .invoke_link()
+        .unwrap_or_else(|_| panic!("failed to link"));
+    assert_eq!(start(linked), Some(Value::from(13)));
+    let other = write_object("minitests/link-object-other", "other.mlib");
+    let error_system = compile(vec![object, other]).invoke_link().err().unwrap();
+    assert_eq!(error_system.errors[0].title, "Link error");
+    let missing = folder.join("missing.mlib").display().to_string();
+    let error_system = compile(vec![missing]).invoke_link().err().unwrap();
+    assert_eq!(error_system.errors[0].title, "Library error");
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_pool_constants() {
+    let folder = std::env::temp_dir().join(format!("mini-pool-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let source = folder.join("main.mini");
+    let table = "(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12)";
+    std::fs::write(
+        &source,
+        format!(
+            "write func main() {{\n    asm({0}) {{ log }};\n    asm(7) {{ log }};\n    \
+                 asm({0}) {{ log }};\n    asm({0}) {{ log }};\n}}\n",
+            table
+        ),
+    )
+    .unwrap();
+    let compile = |pool_constants: bool| {
+        CompileStruct {
+            input: vec![source.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            pool_constants,
+            ..CompileStruct::default()
+        }
+        .invoke()
+        .map(|(program, _)| program)
+        .unwrap_or_else(|_| panic!("failed to compile"))
+    };
+    let run_logs = |program: LinkedProgram| {
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap()
+    };
+
+    // the table is looked up rather than copied once pooled, and logs the same either way
+    let logs = run_logs(compile(false));
+    assert_eq!(logs.len(), 4);
+    let (copied, pooled) = (compile(false), compile(true));
+    let uses = |program: &LinkedProgram| {
+        // the prologue's globals are left out, as they hold the jump table, and with no globals
+        // declared and only the table pooled into it, the jump table is the table
+        program
+            .code
+            .iter()
+            .filter(|insn| insn.opcode != AVMOpcode::Rset)
+            .filter(|insn| insn.immediate.as_ref() == Some(&logs[0]))
+            .count()
+    };
+    assert_eq!(uses(&copied), 3);
+    assert_eq!(uses(&pooled), 0);
+    assert_eq!(run_logs(pooled), logs);
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_error_handler() {
+    let folder = std::env::temp_dir().join(format!("mini-error-handler-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let source = folder.join("main.mini");
+    std::fs::write(
+        &source,
+        "write func main() {\n    asm(1) { log };\n    asm(0, 0) { div log };\n}\n\n\
+         public write func onFault(fault: (uint, bool, bool)) {\n    asm(fault) { log };\n}\n\n\
+         write func quiet(fault: (uint, bool, bool)) {\n    asm(fault) { pop };\n}\n",
+    )
+    .unwrap();
+    let compile = |error_handler: Option<&str>| {
+        CompileStruct {
+            input: vec![source.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            error_handler: error_handler.map(String::from),
+            ..CompileStruct::default()
+        }
+        .invoke()
+        .map(|(program, _)| program)
+    };
+    let run_logs = |program: LinkedProgram| {
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        let result = run(&mut machine, vec![], false, None);
+        (result.is_ok(), machine.runtime_env.get_all_raw_logs())
+    };
+
+    // without a handler the division by zero stops the machine where it happens
+    let program = compile(None).unwrap_or_else(|_| panic!("failed to compile"));
+    assert_eq!(run_logs(program), (false, vec![Value::from(1)]));
+
+    // with one, it's called with the fault, and halts the machine when it returns
+    let program = compile(Some("onFault")).unwrap_or_else(|_| panic!("failed to compile"));
+    let (_, logs) = run_logs(program);
+    assert_eq!(logs.len(), 2);
+    match &logs[1] {
+        Value::Tuple(fault) => {
+            assert_eq!(fault.len(), 3);
+            assert!(matches!(&fault[0], Value::Int(gas) if *gas < Uint256::max_uint()));
+            assert!(matches!(&fault[2], Value::Int(_)));
+        }
+        fault => panic!("expected a fault tuple, got {}", fault),
+    }
+
+    // handlers have to be public, as entry points do
+    for handler in &["missing", "quiet"] {
+        let error_system = compile(Some(*handler)).err().unwrap();
+        assert_eq!(error_system.errors[0].title, "Error handler error");
+    }
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_override() {
+    let folder = std::env::temp_dir().join(format!("mini-override-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    let write = |name: &str, source: &str| std::fs::write(folder.join(name), source).unwrap();
+    let compile = || {
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            ..CompileStruct::default()
+        };
+        compile.invoke().map(|(program, _)| program)
+    };
+    let start = |program: LinkedProgram| {
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        machine.stack_top().cloned()
+    };
+    let instrumented = |attribute: &str| {
+        format!(
+            "{}\npublic func double(x: uint) -> uint {{\n    return 3 * x;\n}}\n",
+            attribute
+        )
+    };
+
+    write(
+        "lib.mini",
+        "public func double(x: uint) -> uint {\n    return 2 * x;\n}\n\n\
+         public func quadruple(x: uint) -> uint {\n    return double(double(x));\n}\n",
+    );
+    write(
+        "main.mini",
+        "use lib::quadruple;\nuse instrumented::double;\n\n\
+         func main() -> uint {\n    return quadruple(double(1));\n}\n",
+    );
+
+    // the library's own calls only reach the instrumented func once it overrides the library's
+    write("instrumented.mini", &instrumented(""));
+    let program = compile().unwrap_or_else(|_| panic!("failed to compile"));
+    assert_eq!(start(program), Some(Value::from(12)));
+    write("instrumented.mini", &instrumented("#[override]"));
+    let program = compile().unwrap_or_else(|_| panic!("failed to compile"));
+    assert_eq!(start(program), Some(Value::from(27)));
+
+    // nothing need call a private override for it to be kept
+    let marked = |double: &str| {
+        format!(
+            "#[override]\n{}\n\npublic func marker() -> uint {{\n    return 0;\n}}\n",
+            double
+        )
+    };
+    write(
+        "instrumented.mini",
+        &marked("func double(x: uint) -> uint {\n    return 3 * x;\n}"),
+    );
+    write(
+        "main.mini",
+        "use lib::quadruple;\nuse instrumented::marker;\n\n\
+         func main() -> uint {\n    return quadruple(1) + marker();\n}\n",
+    );
+    let program = compile().unwrap_or_else(|_| panic!("failed to compile"));
+    assert_eq!(start(program), Some(Value::from(9)));
+
+    // an override has to take the args of the func it replaces
+    write(
+        "instrumented.mini",
+        &marked("func double(x: uint, y: uint) -> uint {\n    return x + y;\n}"),
+    );
+    let errors = compile().err().unwrap().errors;
+    assert_eq!(errors[0].title, "Override error");
+    write("instrumented.mini", &instrumented("#[override]"));
+
+    // an override is an error when it's unclear which func it replaces
+    write(
+        "other.mini",
+        "public func double(x: uint) -> uint {\n    return x + x;\n}\n\n\
+         public func halve(x: uint) -> uint {\n    return x / 2;\n}\n",
+    );
+    write(
+        "main.mini",
+        "use lib::quadruple;\nuse instrumented::double;\nuse other::halve;\n\n\
+         func main() -> uint {\n    return halve(quadruple(double(1)));\n}\n",
+    );
+    let errors = compile().err().unwrap().errors;
+    assert_eq!(errors[0].title, "Override error");
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_jump_profile() {
+    let folder = std::env::temp_dir().join(format!("mini-jump-profile-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+
+    // one func recursing hundreds of times among enough recursing a few times to fill the jump
+    // table, as a func calling itself looks its own label up from the table
+    let mut source = String::from("write func main() {\n    let total = 0;\n");
+    for n in 0..60 {
+        source.push_str(&format!("    total = total + cold{}(2);\n", n));
+    }
+    source.push_str(
+        "    total = total + hot(300);\n    asm(total) { log };\n}\n\n\
+         func hot(n: uint) -> uint {\n    \
+         if (n == 0) {\n        return 0;\n    }\n    return hot(n - 1) + 1;\n}\n",
+    );
+    for n in 0..60 {
+        source.push_str(&format!(
+            "\nfunc cold{0}(n: uint) -> uint {{\n    if (n == 0) {{\n        return {0};\n    }}\n    \
+             return cold{0}(n - 1) + 1;\n}}\n",
+            n
+        ));
+    }
+    std::fs::write(folder.join("main.mini"), &source).unwrap();
+    let profile_path = folder.join("jumps.json");
+
+    let compile = |jump_profile: Option<String>| {
+        CompileStruct {
+            input: vec![folder.display().to_string()],
+            output: Some(folder.join("main.mexe").display().to_string()),
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            no_inline: true,
+            symbol_map: true,
+            jump_profile,
+            ..CompileStruct::default()
+        }
+        .invoke()
+        .map(|(program, _)| program)
+        .unwrap_or_else(|_| panic!("failed to compile"))
+    };
+    let profile = |program: LinkedProgram| {
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        machine.profile_gen(vec![], ProfilerMode::Always)
+    };
+    let run_logs = |program: LinkedProgram| {
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap()
+    };
+
+    // the hot func's jumps dominate the profile, which survives being written and read back
+    let balanced = profile(compile(None));
+    let symbols = SymbolMap::read(&folder.join("main.symbols.json")).unwrap();
+    let jumps = balanced.jump_profile(&symbols);
+    assert!(jumps.total() > 300);
+    assert!(jumps
+        .targets
+        .iter()
+        .any(|target| target.func == "main::hot" && target.count >= 300));
+    jumps.write(&profile_path).unwrap();
+    assert_eq!(JumpProfile::read(&profile_path).unwrap(), jumps);
+
+    // laying the table out by the profile changes nothing the program does, and costs less
+    let weighted = || compile(Some(profile_path.display().to_string()));
+    assert_eq!(run_logs(weighted()), run_logs(compile(None)));
+    assert!(profile(weighted()).total_gas() < balanced.total_gas());
+
+    // entries are matched by func and label, so the profile still applies once lines are added
+    let shape = |program: LinkedProgram| -> Vec<_> {
+        program
+            .code
+            .into_iter()
+            .map(|insn| (insn.opcode, insn.immediate))
+            .collect()
+    };
+    let laid_out = shape(weighted());
+    assert!(laid_out != shape(compile(None)));
+    std::fs::write(folder.join("main.mini"), format!("\n\n\n{}", source)).unwrap();
+    assert!(shape(weighted()) == laid_out);
+
+    // a profile that can't be read is an error
+    let missing = CompileStruct {
+        input: vec![folder.display().to_string()],
+        test_mode: true,
+        no_builtins: true,
+        no_cache: true,
+        jump_profile: Some(folder.join("missing.json").display().to_string()),
+        ..CompileStruct::default()
+    };
+    match missing.invoke() {
+        Ok(_) => panic!("compiled with a missing jump profile"),
+        Err(error_system) => assert_eq!(error_system.errors[0].title, "Jump profile error"),
+    }
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_mexe_formats() {
+    let folder = std::env::temp_dir().join(format!("mini-formats-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        folder.join("main.mini"),
+        "func main() -> uint {\n    return 3;\n}\n",
+    )
+    .unwrap();
+    let compile = CompileStruct {
+        input: vec![folder.display().to_string()],
+        test_mode: true,
+        no_builtins: true,
+        no_cache: true,
+        ..CompileStruct::default()
+    };
+    let (program, _) = compile
+        .invoke()
+        .unwrap_or_else(|_| panic!("failed to compile"));
+
+    // runs load each format, telling them apart by their contents rather than their names
+    for format in &["json", "cbor", "json.gz"] {
+        let mexe = folder.join(format!("main.{}.mexe", format));
+        program.to_output(&mut std::fs::File::create(&mexe).unwrap(), Some(format));
+        let mut machine = crate::run::load_from_file(&mexe);
+        run(&mut machine, vec![], false, None).unwrap();
+        assert_eq!(machine.stack_top(), Some(&Value::Int(Uint256::from_u64(3))));
+    }
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_remap_path_prefix() {
+    let folder = std::env::temp_dir().join(format!("mini-remap-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    let compile = |checkout: &str| {
+        let checkout = folder.join(checkout);
+        std::fs::create_dir_all(&checkout).unwrap();
+        let write = |name: &str, source: &str| std::fs::write(checkout.join(name), source).unwrap();
+        write(
+            "main.mini",
+            "use helper::double;\n\nfunc main() -> uint {\n    return double(3);\n}\n",
+        );
+        write(
+            "helper.mini",
+            "public func double(x: uint) -> uint {\n    return 2 * x;\n}\n",
+        );
+        let output = checkout.join("main.mexe");
+        let compile = CompileStruct {
+            input: vec![checkout.display().to_string()],
+            output: Some(output.display().to_string()),
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            no_inline: true,
+            symbol_map: true,
+            remap_path_prefix: vec![format!("{}=/src", checkout.display())],
+            ..CompileStruct::default()
+        };
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|_| panic!("failed to compile"));
+        let symbols = std::fs::read_to_string(checkout.join("main.symbols.json")).unwrap();
+        (serde_json::to_string(&program).unwrap(), symbols)
+    };
+
+    // the same sources in two places build the same mexe and symbol map once paths are remapped
+    let (mexe, symbols) = compile("first");
+    assert!(
+        compile("second") == (mexe, symbols.clone()),
+        "compiling the same sources in different folders differed"
+    );
+    let symbols: crate::link::SymbolMap = serde_json::from_str(&symbols).unwrap();
+    let double = symbols
+        .funcs
+        .iter()
+        .find(|func| func.name == "helper::double")
+        .unwrap();
+    assert_eq!(double.file.as_deref(), Some("/src/helper.mini"));
+
+    let malformed = CompileStruct {
+        input: vec![folder.join("first").display().to_string()],
+        no_builtins: true,
+        no_cache: true,
+        remap_path_prefix: vec![String::from("/src")],
+        ..CompileStruct::default()
+    };
+    match malformed.invoke() {
+        Ok(_) => panic!("compiled with a malformed remapping"),
+        Err(error_system) => assert_eq!(error_system.errors[0].title, "Remap error"),
+    }
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
+
+#[test]
+fn test_no_schedule() {
+    let folder = std::env::temp_dir().join(format!("mini-no-schedule-{}", std::process::id()));
+    drop(std::fs::remove_dir_all(&folder));
+    std::fs::create_dir_all(&folder).unwrap();
+    std::fs::write(
+        folder.join("main.mini"),
+        "func main() -> uint {\n    let total = 0;\n    let i = 0;\n    \
+         while i < 10 {\n        total = total + mix(i, 3);\n        i = i + 1;\n    }\n    \
+         return total;\n}\n\n\
+         func mix(a: uint, b: uint) -> uint {\n    \
+             let c = (b * 2) + a;\n    if 7 < c {\n        return c - b;\n    }\n    \
+             return (a * a) + (c & 5);\n}\n",
+    )
+    .unwrap();
+
+    let run_compiled = |no_schedule: bool| {
+        let compile = CompileStruct {
+            input: vec![folder.display().to_string()],
+            test_mode: true,
+            no_builtins: true,
+            no_cache: true,
+            no_schedule,
+            ..CompileStruct::default()
+        };
+        let (program, _) = compile
+            .invoke()
+            .unwrap_or_else(|error_system| panic!("failed to compile: {:?}", error_system.errors));
+        let mut machine = Machine::new(program, RuntimeEnvironment::new(None));
+        run(&mut machine, vec![], false, None).unwrap();
+        (machine.stack_top().cloned(), machine.get_total_gas_usage())
+    };
+
+    // scheduling changes nothing the program does, and never costs more
+    let (unscheduled, unscheduled_gas) = run_compiled(true);
+    let (scheduled, scheduled_gas) = run_compiled(false);
+    assert_eq!(scheduled, unscheduled);
+    assert!(scheduled_gas <= unscheduled_gas);
+
+    std::fs::remove_dir_all(&folder).unwrap();
+}
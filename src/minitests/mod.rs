@@ -8,13 +8,16 @@ use crate::console::Color;
 use crate::evm::abi::ArbSys;
 use crate::evm::preinstalled_contracts::{_ArbAggregator, _ArbOwner, _try_upgrade};
 use crate::evm::test_contract_path2;
+use crate::evm::Tx;
 use crate::evm::{preinstalled_contracts::_ArbInfo, test_contract_path, AbiForContract};
 use crate::mavm::Buffer;
 use crate::mavm::{AVMOpcode, CodePt, Instruction, Value};
 use crate::run::runtime_env::remap_l1_sender_address;
 use crate::run::RuntimeEnvironment;
-use crate::run::{_bytestack_from_bytes, load_from_file, run, run_from_file, Machine};
-use crate::run::{load_from_file_and_env_ret_file_info_table, MachineState};
+use crate::run::{
+    _booted_arbos, _bytestack_from_bytes, load_from_file, run, run_from_file, Machine,
+};
+use crate::run::{load_from_file_and_env_ret_file_info_table, MachineState, TestOutcome};
 use crate::uint256::Uint256;
 use crate::upload::CodeUploader;
 use ethereum_types::U256;
@@ -76,6 +79,21 @@ fn test_for_numeric_error_code(path: &Path) {
     test_from_file(path, Value::Int(Uint256::from_usize(0)))
 }
 
+/// Runs a .mexe file, seeing if the outcome it reports through `std::testing` is a failure
+fn test_for_outcome(path: &Path) {
+    match run_from_file(path, vec![], None, false) {
+        Ok(logs) => {
+            if let TestOutcome::Fail(message) = TestOutcome::from_logs(&logs) {
+                panic!("{}", message);
+            }
+        }
+        Err((error, trace)) => {
+            println!("{}", error);
+            panic!("{:?}", trace);
+        }
+    }
+}
+
 #[test]
 fn test_address_set() {
     test_for_numeric_error_code(Path::new("stdlib/addressSetTest.mexe"));
@@ -278,6 +296,69 @@ fn test_generics() {
     test_for_error_string(Path::new("minitests/generics/closure.mexe"));
     test_for_error_string(Path::new("minitests/generics/colorful.mexe"));
     test_for_error_string(Path::new("minitests/generics/queue.mexe"));
+    test_for_error_string(Path::new("minitests/generics/mono.mexe"));
+}
+
+#[test]
+fn test_interfaces() {
+    test_for_error_string(Path::new("minitests/interfaces.mexe"));
+}
+
+#[test]
+fn test_match() {
+    test_for_error_string(Path::new("minitests/match.mexe"));
+}
+
+#[test]
+fn test_tail_calls() {
+    test_for_error_string(Path::new("minitests/tailcalls.mexe"));
+}
+
+#[test]
+fn test_const_eval() {
+    test_for_error_string(Path::new("minitests/consteval.mexe"));
+}
+
+#[test]
+fn test_strings() {
+    test_for_error_string(Path::new("minitests/strings.mexe"));
+}
+
+#[test]
+fn test_fixed_arrays() {
+    test_for_error_string(Path::new("minitests/fixedarrays.mexe"));
+}
+
+#[test]
+fn test_enums() {
+    test_for_error_string(Path::new("minitests/enums.mexe"));
+}
+
+#[test]
+fn test_outcomes() {
+    test_for_outcome(Path::new("minitests/outcomes.mexe"));
+}
+
+#[test]
+fn test_bounds_check_elision() {
+    let path = Path::new("minitests/bounds.mexe");
+    test_for_error_string(path);
+
+    // the test indexes arrays of 13 only where it's provably in bounds, and arrays of 11 where not
+    let machine = load_from_file(path);
+    let checks = |size: usize| {
+        machine.code.segments[0]
+            .windows(3)
+            .filter(|insns| {
+                insns[0].opcode == AVMOpcode::GreaterThan
+                    && insns[0].immediate == Some(Value::Int(Uint256::from_usize(size)))
+                    && insns[2].opcode == AVMOpcode::Error
+            })
+            .count()
+    };
+    assert_eq!(checks(13), 0);
+    // the index after a block shadowing it, and the index passed in as a parameter
+    assert_eq!(checks(11), 2);
 }
 
 #[test]
@@ -460,15 +541,10 @@ fn test_call_to_precompile5(
     calldata.extend(e_bytes);
     calldata.extend(m_bytes);
 
-    let txid = machine.runtime_env.insert_tx_message(
-        sender_addr.clone(),
-        Uint256::from_u64(1_000_000_000),
-        None,
-        Uint256::from_u64(5),
-        Uint256::zero(),
-        &calldata,
-        false,
-    );
+    let txid = Tx::call(Uint256::from_u64(5))
+        .calldata(calldata)
+        .gas(Uint256::from_u64(1_000_000_000))
+        .send(&mut machine.runtime_env, sender_addr.clone());
     let num_logs_before = machine.runtime_env.get_all_receipt_logs().len();
     let _gas_used = machine.run(None);
     let logs = machine.runtime_env.get_all_receipt_logs();
@@ -483,8 +559,7 @@ fn test_call_to_precompile5(
 
 #[test]
 fn test_precompile5_small() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
     let my_addr = Uint256::from_usize(1025);
 
     match test_call_to_precompile5(
@@ -507,8 +582,7 @@ fn test_precompile5_small() {
 
 #[test]
 fn test_precompile5_big() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
     let my_addr = Uint256::from_usize(1025);
 
     let mut rng = rand::thread_rng();
@@ -588,8 +662,7 @@ fn small_upgrade_auto_remap() {
 
 #[test]
 fn test_gasleft_with_delegatecall() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
     let my_addr = Uint256::from_u64(1025);
 
     let mut greeter_contract =
@@ -721,7 +794,7 @@ pub fn test_if_still_upgradable() -> Result<(), ethabi::Error> {
 
     _try_upgrade(&arbowner, &mut machine, uploader, None)?;
 
-    assert_eq!(machine.runtime_env.logs.last(), Some(&log_value));
+    assert_eq!(machine.runtime_env.logs.back(), Some(&log_value));
 
     machine.write_coverage("test_if_still_upgradable".to_string());
     Ok(())
@@ -736,8 +809,7 @@ pub fn evm_test_memory_charges() {
 
 #[cfg(test)]
 fn balance_after_memory_usage(usage: u64) -> Uint256 {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
     let wallet = machine.runtime_env.new_wallet();
     let my_addr = Uint256::from_bytes(wallet.address().as_bytes());
 
@@ -812,8 +884,7 @@ fn test_gas_estimation_preferred_aggregator() {
 
 #[cfg(test)]
 fn test_gas_estimation(use_preferred_aggregator: bool) {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
     let wallet = machine.runtime_env.new_wallet();
     let my_addr = Uint256::from_bytes(wallet.address().as_bytes());
 
@@ -923,8 +994,7 @@ fn test_gas_estimation(use_preferred_aggregator: bool) {
 
 #[test]
 fn test_selfdestruct_in_constructor() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let mut victim_contract =
         AbiForContract::new_from_file(&test_contract_path("SelfDestructor")).unwrap();
@@ -969,8 +1039,7 @@ fn test_selfdestruct_in_constructor() {
 
 #[test]
 fn test_selfdestruct() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let mut victim_contract =
         AbiForContract::new_from_file(&test_contract_path("SelfDestructor")).unwrap();
@@ -1022,8 +1091,7 @@ fn test_selfdestruct() {
 
 #[test]
 fn test_l1_sender_rewrite() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let my_addr = Uint256::from_u64(80293481);
 
@@ -1109,8 +1177,7 @@ fn test_recurse_revert() {
 
 #[cfg(test)]
 fn test_recurse_impl(depth: u64, should_revert: bool) {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let my_addr = Uint256::from_u64(80293481);
 
@@ -1149,8 +1216,7 @@ fn test_recurse_impl(depth: u64, should_revert: bool) {
 
 #[test]
 fn test_barely_out_of_gas() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let mut contract = AbiForContract::new_from_file(&test_contract_path("BlockNum")).unwrap();
     if let Err(receipt) = contract.deploy(&[], &mut machine, Uint256::zero(), None, false) {
@@ -1236,8 +1302,7 @@ fn assert_close(x: &Uint256, y: &Uint256) {
 
 #[cfg(test)]
 fn measure_gas_for_alloc_dealloc(args: Vec<&Uint256>) -> Uint256 {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let wallet = machine.runtime_env.new_wallet();
     let my_addr = wallet.address();
@@ -1303,8 +1368,7 @@ fn test_gas_refund_with_subrevert() {
 
 #[cfg(test)]
 fn measure_gas_for_alloc_dealloc_with_revert(args: Vec<&Uint256>) -> Uint256 {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let wallet = machine.runtime_env.new_wallet();
     let my_addr = wallet.address();
@@ -1355,8 +1419,7 @@ fn measure_gas_for_alloc_dealloc_with_revert(args: Vec<&Uint256>) -> Uint256 {
 
 #[test]
 fn test_no_refund_across_txs() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let wallet = machine.runtime_env.new_wallet();
     let my_addr = wallet.address();
@@ -1436,8 +1499,7 @@ fn test_no_refund_across_txs() {
 
 #[test]
 fn test_reverting_payable_constructor() {
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let my_addr = Uint256::from_u64(1025); // because deploy uses this address
     let my_addr_rewritten = remap_l1_sender_address(my_addr.clone());
@@ -0,0 +1,108 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides named accounts for tests, so that each doesn't have to set up its own.
+
+use crate::run::RuntimeEnvironment;
+use crate::uint256::Uint256;
+use ethers_signers::{Signer, Wallet};
+
+/// An account with a key to sign its transactions with, and a balance to start with.
+#[derive(Clone, Debug)]
+pub struct TestAccount {
+    pub wallet: Wallet,
+    pub address: Uint256,
+    /// What `fund_test_accounts` deposits into the account
+    pub initial_balance: Uint256,
+}
+
+/// The accounts tests act as: alice and bob are users, and after them come an owner and an
+/// aggregator, which start out with and without a balance, so that no wallet handed out later is
+/// one that administers the chain or collects fees.
+#[derive(Clone, Debug)]
+pub struct TestAccounts {
+    accounts: Vec<TestAccount>,
+}
+
+impl TestAccounts {
+    /// Generates the accounts from the environment's wallets, so that their keys are the same in
+    /// every run and no wallet the environment hands out later is one of them. Alice has the same
+    /// key a test's first `new_wallet` would otherwise get.
+    pub fn new(env: &mut RuntimeEnvironment) -> Self {
+        let balances = [
+            Uint256::_from_eth(1000),
+            Uint256::_from_eth(1000),
+            Uint256::_from_eth(1000),
+            Uint256::zero(),
+        ];
+        let accounts = balances
+            .iter()
+            .map(|initial_balance| {
+                let wallet = env.new_wallet();
+                TestAccount {
+                    address: Uint256::from_bytes(wallet.address().as_bytes()),
+                    wallet,
+                    initial_balance: initial_balance.clone(),
+                }
+            })
+            .collect();
+        TestAccounts { accounts }
+    }
+
+    pub fn alice(&self) -> &TestAccount {
+        &self.accounts[0]
+    }
+
+    pub fn bob(&self) -> &TestAccount {
+        &self.accounts[1]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TestAccount> {
+        self.accounts.iter()
+    }
+}
+
+impl RuntimeEnvironment {
+    /// Deposits eth into an account from L1. The deposit takes effect once the machine runs.
+    pub fn fund(&mut self, account: &TestAccount, amount: Uint256) {
+        self.insert_eth_deposit_message(
+            account.address.clone(),
+            account.address.clone(),
+            amount,
+            true,
+        );
+    }
+
+    /// Deposits each account's initial balance into it.
+    pub fn fund_test_accounts(&mut self, accounts: &TestAccounts) {
+        for account in accounts.iter() {
+            if account.initial_balance != Uint256::zero() {
+                self.fund(account, account.initial_balance.clone());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_accounts_are_deterministic() {
+    let mut env = RuntimeEnvironment::default();
+    let accounts = TestAccounts::new(&mut env);
+    let again = TestAccounts::new(&mut RuntimeEnvironment::default());
+
+    let addresses: Vec<_> = accounts.iter().map(|account| &account.address).collect();
+    let others: Vec<_> = again.iter().map(|account| &account.address).collect();
+    assert_eq!(addresses, others);
+    assert_eq!(addresses[1], &accounts.bob().address);
+
+    // alice is who a lone test wallet used to be, and later wallets are distinct from everyone
+    let lone = RuntimeEnvironment::default().new_wallet();
+    assert_eq!(lone.address(), accounts.alice().wallet.address());
+    let later = Uint256::from_bytes(env.new_wallet().address().as_bytes());
+    assert!(!addresses.contains(&&later));
+
+    // the aggregator starts out empty, so only the others get deposits
+    let queued = env.l1_inbox.len();
+    env.fund_test_accounts(&accounts);
+    assert_eq!(env.l1_inbox.len(), queued + 3);
+}
@@ -1,11 +1,11 @@
 // Based on https://github.com/ebfe/rust-blake2/blob/master/src/blake2b.rs (BSD 2-clause simplified license)
 // Modifications are Copyright 2021, Offchain Labs, Inc.  All rights reserved.
 
+use crate::evm::Tx;
 use crate::mavm::Buffer;
-use crate::run::load_from_file;
+use crate::run::_booted_arbos;
 use crate::uint256::Uint256;
 use std::convert::TryInto;
-use std::path::Path;
 
 pub const BLOCK_BYTES: usize = 128;
 
@@ -304,23 +304,17 @@ fn _test_eth_blake_precompile_impl() {
         ),
     ];
 
-    let mut machine = load_from_file(Path::new("arb_os/arbos.mexe"));
-    machine.start_at_zero(true);
+    let mut machine = _booted_arbos();
 
     let my_addr = Uint256::from_u64(1025);
 
     for (x, y) in &test_vectors {
         let xbytes = hex::decode(x).unwrap();
 
-        let tx_id = machine.runtime_env.insert_tx_message(
-            my_addr.clone(),
-            Uint256::from_u64(1000000000),
-            None,
-            Uint256::from_u64(9), // blake2f precompile
-            Uint256::from_u64(0),
-            &xbytes,
-            false,
-        );
+        let tx_id = Tx::call(Uint256::from_u64(9)) // blake2f precompile
+            .calldata(xbytes)
+            .gas(Uint256::from_u64(1000000000))
+            .send(&mut machine.runtime_env, my_addr.clone());
         let _ = machine.run(None);
 
         let receipts = machine.runtime_env.get_all_receipt_logs();
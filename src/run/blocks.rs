@@ -0,0 +1,579 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the L2 blocks ArbOS has produced, gathered from the receipts and block summaries it
+//! logs, and the filters that find EVM logs in them the way `eth_getLogs` does.
+
+use crate::mavm::Value;
+use crate::run::{ArbosReceipt, EvmLog, RuntimeEnvironment};
+use crate::uint256::Uint256;
+use ethereum_types::{Bloom, BloomInput};
+use ethers_core::utils::keccak256;
+use rlp::RlpStream;
+
+/// Log items ArbOS emits at the end of each L2 block, after the receipts of its txs.
+const BLOCK_SUMMARY_LOG_TYPE: u64 = 1;
+
+/// An L2 block, along with the receipts of the txs in it.
+#[derive(Clone, Debug)]
+pub struct L2Block {
+    pub number: Uint256,
+    pub timestamp: Uint256,
+    /// The L1 block the L2 block was produced in
+    pub l1_block_number: Uint256,
+    /// The number of the previous L2 block
+    pub parent_number: Uint256,
+    pub gas_used: Uint256,
+    pub receipts: Vec<ArbosReceipt>,
+    /// The bloom filter of the addresses and topics of every EVM log emitted in the block
+    pub log_bloom: Bloom,
+    /// The bloom filter of each receipt, in the same order
+    pub receipt_blooms: Vec<Bloom>,
+    /// The root of the trie of the block's txs, keyed by their RLP-encoded indices as in
+    /// Ethereum, with each tx represented by its request id
+    pub tx_root: Uint256,
+}
+
+impl L2Block {
+    /// Makes a block from the summary ArbOS logged at its end, or `None` if the summary is
+    /// malformed.
+    fn new(summary: &Value, receipts: Vec<ArbosReceipt>) -> Option<Self> {
+        let fields = match summary {
+            Value::Tuple(fields) if fields.len() >= 8 => fields,
+            _ => return None,
+        };
+        let int = |index: usize| match &fields[index] {
+            Value::Int(value) => Some(value.clone()),
+            _ => None,
+        };
+        // the block's stats begin with the gas its txs used
+        let gas_used = match &fields[3] {
+            Value::Tuple(stats) => match stats.first() {
+                Some(Value::Int(gas)) => gas.clone(),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        let receipt_blooms: Vec<_> = receipts
+            .iter()
+            .map(|receipt| log_bloom(&receipt._get_evm_logs()))
+            .collect();
+        let mut log_bloom = Bloom::zero();
+        for bloom in &receipt_blooms {
+            log_bloom.accrue_bloom(bloom);
+        }
+        let txs: Vec<_> = receipts
+            .iter()
+            .map(|receipt| rlp::encode(&receipt.get_request_id().to_bytes_be()))
+            .collect();
+
+        Some(L2Block {
+            number: int(1)?,
+            timestamp: int(2)?,
+            parent_number: int(6)?,
+            l1_block_number: int(7)?,
+            gas_used,
+            receipts,
+            log_bloom,
+            receipt_blooms,
+            tx_root: ordered_trie_root(&txs),
+        })
+    }
+
+    /// Describes the block the way the `eth_getBlockByNumber` RPC does, with its txs given by
+    /// hash.
+    pub fn to_rpc_json(&self) -> serde_json::Value {
+        let quantity = |value: &Uint256| format!("{:#x}", value.to_u256());
+        let hash = |value: &Uint256| format!("0x{}", hex::encode(value.to_bytes_be()));
+        serde_json::json!({
+            "number": quantity(&self.number),
+            "timestamp": quantity(&self.timestamp),
+            "l1BlockNumber": quantity(&self.l1_block_number),
+            "gasUsed": quantity(&self.gas_used),
+            "logsBloom": format!("0x{}", hex::encode(self.log_bloom.as_bytes())),
+            "transactionsRoot": hash(&self.tx_root),
+            "transactions": self
+                .receipts
+                .iter()
+                .map(|receipt| hash(&receipt.get_request_id()))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Finds the block's logs that match a filter, skipping the receipts whose blooms rule out
+    /// a match.
+    pub fn logs(&self, filter: &LogFilter) -> Vec<MatchedLog> {
+        let mut matched = vec![];
+        if !filter.includes_block(&self.number) || !filter.may_match(&self.log_bloom) {
+            return matched;
+        }
+        let mut log_index = 0;
+        for (receipt, bloom) in self.receipts.iter().zip(&self.receipt_blooms) {
+            let logs = receipt._get_evm_logs();
+            if filter.may_match(bloom) {
+                for (offset, log) in logs.iter().enumerate() {
+                    if filter.matches(log) {
+                        matched.push(MatchedLog {
+                            block_number: self.number.clone(),
+                            request_id: receipt.get_request_id(),
+                            log_index: log_index + offset,
+                            log: log.clone(),
+                        });
+                    }
+                }
+            }
+            log_index += logs.len();
+        }
+        matched
+    }
+}
+
+/// Picks out EVM logs the way the filters of `eth_getLogs` do.
+#[derive(Clone, Debug, Default)]
+pub struct LogFilter {
+    /// The first block to search, or the earliest if `None`
+    pub from_block: Option<Uint256>,
+    /// The last block to search, or the latest if `None`
+    pub to_block: Option<Uint256>,
+    /// The addresses one of which must have emitted the log, or any address if empty
+    pub addresses: Vec<Uint256>,
+    /// For each position, the topics one of which the log must have there, or any topic if
+    /// `None`. Logs with fewer topics than are constrained don't match.
+    pub topics: Vec<Option<Vec<Uint256>>>,
+}
+
+impl LogFilter {
+    pub fn includes_block(&self, number: &Uint256) -> bool {
+        let after_from = match &self.from_block {
+            Some(from) => number >= from,
+            None => true,
+        };
+        let before_to = match &self.to_block {
+            Some(to) => number <= to,
+            None => true,
+        };
+        after_from && before_to
+    }
+
+    /// Whether a bloom leaves open that some log in it matches. A `false` is certain, while a
+    /// `true` has to be confirmed with the logs themselves.
+    pub fn may_match(&self, bloom: &Bloom) -> bool {
+        let contains = |value: &Uint256, is_address: bool| match is_address {
+            true => bloom.contains_input(BloomInput::Raw(value.to_h160().as_bytes())),
+            false => bloom.contains_input(BloomInput::Raw(&value.to_bytes_be())),
+        };
+        let addresses_ok =
+            self.addresses.is_empty() || self.addresses.iter().any(|addr| contains(addr, true));
+        addresses_ok
+            && self.topics.iter().all(|topics| match topics {
+                Some(topics) if !topics.is_empty() => {
+                    topics.iter().any(|topic| contains(topic, false))
+                }
+                _ => true,
+            })
+    }
+
+    pub fn matches(&self, log: &EvmLog) -> bool {
+        (self.addresses.is_empty() || self.addresses.contains(&log.addr))
+            && self
+                .topics
+                .iter()
+                .enumerate()
+                .all(|(position, topics)| match topics {
+                    Some(topics) if !topics.is_empty() => match log.vals.get(position) {
+                        Some(topic) => topics.contains(topic),
+                        None => false,
+                    },
+                    _ => true,
+                })
+    }
+
+    /// Finds the logs that match in blocks gathered beforehand, so that many queries over a
+    /// long history needn't gather the blocks again.
+    pub fn apply(&self, blocks: &[L2Block]) -> Vec<MatchedLog> {
+        blocks.iter().flat_map(|block| block.logs(self)).collect()
+    }
+}
+
+/// An EVM log found by a `LogFilter`, along with where it was emitted.
+#[derive(Clone, Debug)]
+pub struct MatchedLog {
+    pub block_number: Uint256,
+    /// The request id of the tx that emitted the log
+    pub request_id: Uint256,
+    /// The position of the log among those of its block
+    pub log_index: usize,
+    pub log: EvmLog,
+}
+
+/// Computes the bloom filter of the addresses and topics of logs, as in Ethereum receipts.
+pub fn log_bloom(logs: &[EvmLog]) -> Bloom {
+    let mut bloom = Bloom::zero();
+    for log in logs {
+        bloom.accrue(BloomInput::Raw(log.addr.to_h160().as_bytes()));
+        for topic in &log.vals {
+            bloom.accrue(BloomInput::Raw(&topic.to_bytes_be()));
+        }
+    }
+    bloom
+}
+
+impl RuntimeEnvironment {
+    /// Gathers the L2 blocks ArbOS has closed, each with the receipts logged since the block
+    /// before it. Txs of the block still being built aren't included. When logs are being
+    /// trimmed, blocks whose summaries have been dropped are missing, and the earliest block
+    /// kept may lack the receipts dropped before it.
+    pub fn blocks(&self) -> Vec<L2Block> {
+        let mut blocks = vec![];
+        let mut receipts = vec![];
+        for log in &self.logs {
+            match is_block_summary(log) {
+                true => blocks.extend(L2Block::new(log, std::mem::take(&mut receipts))),
+                false => receipts.extend(ArbosReceipt::new(log.clone())),
+            }
+        }
+        blocks
+    }
+
+    /// Finds the logs of closed blocks that match a filter, as `eth_getLogs` does.
+    pub fn get_logs(&self, filter: &LogFilter) -> Vec<MatchedLog> {
+        filter.apply(&self.blocks())
+    }
+}
+
+fn is_block_summary(log: &Value) -> bool {
+    match log {
+        Value::Tuple(fields) => {
+            fields.first() == Some(&Value::Int(Uint256::from_u64(BLOCK_SUMMARY_LOG_TYPE)))
+        }
+        _ => false,
+    }
+}
+
+/// Computes the root of the Merkle Patricia trie holding each item under its RLP-encoded index,
+/// the way Ethereum block headers commit to their txs and receipts.
+fn ordered_trie_root(items: &[Vec<u8>]) -> Uint256 {
+    trie_root(
+        items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (rlp::encode(&index), item.as_slice()))
+            .collect(),
+    )
+}
+
+/// Computes the root of the Merkle Patricia trie holding each value under its key.
+fn trie_root(entries: Vec<(Vec<u8>, &[u8])>) -> Uint256 {
+    let mut entries: Vec<_> = entries
+        .into_iter()
+        .map(|(key, value)| (nibbles(&key), value))
+        .collect();
+    entries.sort();
+    let root = match entries.is_empty() {
+        true => rlp::NULL_RLP.to_vec(),
+        false => trie_node(&entries, 0),
+    };
+    Uint256::from_bytes(&keccak256(&root))
+}
+
+/// Encodes the trie node holding entries, whose keys have already been followed to depth.
+fn trie_node(entries: &[(Vec<u8>, &[u8])], depth: usize) -> Vec<u8> {
+    if let [(key, value)] = entries {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&hex_prefix(&key[depth..], true));
+        stream.append(value);
+        return stream.out();
+    }
+
+    // entries sharing more of their keys hang off an extension node
+    let (first, last) = (&entries[0].0, &entries[entries.len() - 1].0);
+    let shared = first[depth..]
+        .iter()
+        .zip(&last[depth..])
+        .take_while(|(a, b)| a == b)
+        .count();
+    if shared > 0 {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&hex_prefix(&first[depth..depth + shared], false));
+        append_child(&mut stream, &trie_node(entries, depth + shared));
+        return stream.out();
+    }
+
+    let mut stream = RlpStream::new_list(17);
+    let mut rest = entries;
+    let value = match rest.first() {
+        Some((key, value)) if key.len() == depth => {
+            rest = &rest[1..];
+            Some(*value)
+        }
+        _ => None,
+    };
+    for nibble in 0..16 {
+        let count = rest
+            .iter()
+            .take_while(|(key, _)| key[depth] == nibble)
+            .count();
+        if count == 0 {
+            stream.append_empty_data();
+        } else {
+            append_child(&mut stream, &trie_node(&rest[..count], depth + 1));
+        }
+        rest = &rest[count..];
+    }
+    if let Some(value) = value {
+        stream.append(&value);
+    } else {
+        stream.append_empty_data();
+    }
+    stream.out()
+}
+
+/// Refers to a child node by its hash, unless it's small enough to be included as is.
+fn append_child(stream: &mut RlpStream, node: &[u8]) {
+    if node.len() < 32 {
+        stream.append_raw(node, 1);
+    } else {
+        stream.append(&keccak256(node).to_vec());
+    }
+}
+
+fn nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|byte| vec![byte >> 4, byte & 0xf])
+        .collect()
+}
+
+/// Packs the nibbles of a path into bytes, flagging whether it leads to a leaf.
+fn hex_prefix(path: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 };
+    let mut bytes = match path.len() % 2 {
+        1 => vec![((flag + 1) << 4) | path[0]],
+        _ => vec![flag << 4],
+    };
+    let rest = &path[path.len() % 2..];
+    bytes.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    bytes
+}
+
+#[test]
+fn test_l2_blocks() {
+    use crate::evm::Tx;
+    use crate::run::{load_from_file_and_env, TestAccounts};
+    use std::path::Path;
+
+    // the empty root, and the puppy and dogs tries of Ethereum's trie tests
+    let root = |entries: &[(&str, &str)]| {
+        let entries = entries
+            .iter()
+            .map(|(key, value)| (key.as_bytes().to_vec(), value.as_bytes()))
+            .collect();
+        hex::encode(trie_root(entries).to_bytes_be())
+    };
+    assert_eq!(
+        root(&[]),
+        "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+    );
+    assert_eq!(
+        root(&[
+            ("do", "verb"),
+            ("horse", "stallion"),
+            ("doge", "coin"),
+            ("dog", "puppy")
+        ]),
+        "5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84"
+    );
+    assert_eq!(
+        root(&[
+            ("doe", "reindeer"),
+            ("dog", "puppy"),
+            ("dogglesworth", "cat")
+        ]),
+        "8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"
+    );
+
+    let mut env = RuntimeEnvironment::default();
+    let accounts = TestAccounts::new(&mut env);
+    let (alice, bob) = (accounts.alice(), accounts.bob());
+    env.fund(alice, Uint256::_from_eth(10));
+    let sent = Tx::call(bob.address.clone())
+        .value(Uint256::_from_eth(1))
+        .send(&mut env, alice.address.clone());
+    // ArbOS closes the block once a message from a later L1 block arrives
+    env._advance_time(Uint256::one(), None, true);
+
+    let mut machine = load_from_file_and_env(Path::new("arb_os/arbos.mexe"), env);
+    machine.start_at_zero(false);
+    let _ = machine.run(None);
+
+    let blocks = machine.runtime_env.blocks();
+    assert!(!blocks.is_empty());
+    let receipts = machine.runtime_env.get_all_receipt_logs();
+    let grouped: usize = blocks.iter().map(|block| block.receipts.len()).sum();
+    assert_eq!(grouped, receipts.len());
+
+    let block = blocks
+        .iter()
+        .find(|block| block.receipts.iter().any(|r| r.get_request_id() == sent))
+        .expect("no block has the transfer");
+    let gas: Uint256 = block.receipts.iter().fold(Uint256::zero(), |sum, receipt| {
+        sum.add(&receipt.get_gas_used())
+    });
+    assert_eq!(block.gas_used, gas);
+    assert_ne!(block.tx_root, ordered_trie_root(&[]));
+    for window in blocks.windows(2) {
+        assert_eq!(window[1].parent_number, window[0].number);
+    }
+
+    let json = block.to_rpc_json();
+    let hash = format!("0x{}", hex::encode(sent.to_bytes_be()));
+    assert!(json["transactions"]
+        .as_array()
+        .unwrap()
+        .contains(&hash.into()));
+    assert_eq!(json["logsBloom"].as_str().unwrap().len(), 2 + 2 * 256);
+}
+
+#[test]
+fn test_log_filter() {
+    use crate::evm::Tx;
+    use crate::run::{load_from_file_and_env, TestAccounts};
+    use std::path::Path;
+
+    // the log of Ethereum's reference bloom test, which the bloom must also match when built
+    // bit by bit from the yellow paper's definition
+    let word = |hex: &str| Uint256::from_bytes(&hex::decode(hex).unwrap());
+    let log = EvmLog {
+        addr: word("ef2d6d194084c2de36e0dabfce45d046b37d1106"),
+        data: vec![],
+        vals: vec![word(
+            "02c69be41d0b7e40352fc85be1cd65eb03d40ef8427a0ca4596b1ead9a00e9fc",
+        )],
+    };
+    let bloom = log_bloom(std::slice::from_ref(&log));
+    let expected = concat!(
+        "00000000000000000000000000000000",
+        "00000000100000000000000000000000",
+        "00000000000000000000000000000000",
+        "00000000000000000000000000000000",
+        "00000000000000000000000000000000",
+        "00000000000000000000000000000000",
+        "00000002020000000000000000000000",
+        "00000000000000000000000800000000",
+        "10000000000000000000000000000000",
+        "00000000000000000000001000000000",
+        "00000000000000000000000000000000",
+        "00000000000000000000000000000000",
+        "00000000000000000000000000000000",
+        "00000000000000000000000000000000",
+        "00000000000000000000000000000000",
+        "00000000000000000000000000000000",
+    );
+    assert_eq!(hex::encode(bloom.as_bytes()), expected);
+
+    let mut by_definition = [0u8; 256];
+    let inputs = vec![
+        log.addr.to_h160().as_bytes().to_vec(),
+        log.vals[0].to_bytes_be(),
+    ];
+    for input in inputs {
+        let hash = keccak256(&input);
+        for pair in hash[..6].chunks(2) {
+            let bit = ((pair[0] as usize) << 8 | pair[1] as usize) & 2047;
+            by_definition[255 - bit / 8] |= 1 << (bit % 8);
+        }
+    }
+    assert_eq!(bloom.as_bytes(), &by_definition[..]);
+
+    let mut env = RuntimeEnvironment::default();
+    let accounts = TestAccounts::new(&mut env);
+    let (alice, bob) = (accounts.alice(), accounts.bob());
+    env.fund(alice, Uint256::_from_eth(10));
+    // withdrawing to L1 has ArbSys log the withdrawal
+    let arbsys = Uint256::from_u64(100);
+    let mut calldata = vec![0x25, 0xe1, 0x60, 0x63];
+    calldata.extend(bob.address.to_bytes_be());
+    let sent = Tx::call(arbsys.clone())
+        .calldata(calldata)
+        .value(Uint256::_from_eth(1))
+        .send(&mut env, alice.address.clone());
+    env._advance_time(Uint256::one(), None, true);
+
+    let mut machine = load_from_file_and_env(Path::new("arb_os/arbos.mexe"), env);
+    machine.start_at_zero(false);
+    let _ = machine.run(None);
+    let env = &machine.runtime_env;
+
+    let by_arbsys = LogFilter {
+        addresses: vec![arbsys.clone()],
+        ..LogFilter::default()
+    };
+    let matched = env.get_logs(&by_arbsys);
+    let withdrawal = matched
+        .iter()
+        .find(|matched| matched.request_id == sent)
+        .expect("the withdrawal wasn't logged");
+    assert_eq!(withdrawal.log.addr, arbsys);
+
+    let blocks = env.blocks();
+    let block = blocks
+        .iter()
+        .find(|block| block.number == withdrawal.block_number)
+        .unwrap();
+    let index = block
+        .receipts
+        .iter()
+        .position(|receipt| receipt.get_request_id() == sent)
+        .unwrap();
+    assert_eq!(
+        block.receipt_blooms[index],
+        log_bloom(&block.receipts[index]._get_evm_logs())
+    );
+
+    // every log matched by the bloom is matched exactly too, and each filter narrows the last
+    let all = LogFilter::default().apply(&blocks);
+    assert!(all.len() >= matched.len());
+    let by_topic = LogFilter {
+        topics: vec![Some(vec![withdrawal.log.vals[0].clone()])],
+        ..by_arbsys.clone()
+    };
+    assert!(by_topic
+        .apply(&blocks)
+        .iter()
+        .any(|matched| matched.request_id == sent));
+    let in_block = LogFilter {
+        from_block: Some(withdrawal.block_number.clone()),
+        to_block: Some(withdrawal.block_number.clone()),
+        ..by_topic.clone()
+    };
+    let found = in_block.apply(&blocks);
+    assert!(found
+        .iter()
+        .all(|matched| matched.block_number == withdrawal.block_number));
+    assert!(found
+        .iter()
+        .any(|matched| matched.log_index == withdrawal.log_index));
+
+    // filters that rule out the log, by its emitter, its topics, or its block
+    let by_bob = LogFilter {
+        addresses: vec![bob.address.clone()],
+        ..LogFilter::default()
+    };
+    assert!(!by_bob.may_match(&block.log_bloom));
+    assert!(by_bob.apply(&blocks).is_empty());
+    let wrong_topic = LogFilter {
+        topics: vec![None, None, None, None, Some(vec![Uint256::one()])],
+        ..by_arbsys.clone()
+    };
+    assert!(wrong_topic.apply(&blocks).is_empty());
+    let later = LogFilter {
+        from_block: Some(withdrawal.block_number.add(&Uint256::one())),
+        ..by_arbsys
+    };
+    assert!(later
+        .apply(&blocks)
+        .iter()
+        .all(|matched| matched.request_id != sent));
+}
@@ -0,0 +1,425 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides an interactive browser for the globals a machine holds in its register.
+
+use crate::compile::{FileInfo, GlobalVar, Type, TypeTree};
+use crate::link::TupleTree;
+use crate::mavm::Value;
+use crate::run::validate::tuple_path;
+use crate::uint256::Uint256;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+/// The most matches a search lists.
+const MAX_MATCHES: usize = 100;
+
+/// The longest a value is printed, in characters, before it's cut short.
+const MAX_SHOWN: usize = 2000;
+
+const HELP: &str = "\
+commands:
+  ls [<path>]       lists what a node holds, by field name where its type is known
+  cd <path>         moves to a node, where .. goes up and / goes back to the globals
+  show [<path>]     prints a node's value
+  find <value>      lists the nodes holding an int, given in decimal or 0x hex
+  def [<path>]      shows where the global owning a node is declared
+  quit              leaves the browser
+paths are dot separated, like someGlobal.field.0, and are relative unless they start with /";
+
+/// A value in the register, along with what it's known to be.
+struct Node<'a> {
+    label: String,
+    value: &'a Value,
+    tipe: Type,
+}
+
+/// A snapshot of a machine's globals, browsed by name.
+pub struct StateBrowser {
+    register: Value,
+    globals: Vec<GlobalVar>,
+    type_tree: TypeTree,
+    file_info_chart: BTreeMap<u64, FileInfo>,
+    /// The labels leading from the globals to the node being looked at
+    cwd: Vec<String>,
+}
+
+impl StateBrowser {
+    pub fn new(
+        register: Value,
+        globals: Vec<GlobalVar>,
+        type_tree: TypeTree,
+        file_info_chart: BTreeMap<u64, FileInfo>,
+    ) -> Self {
+        StateBrowser {
+            register,
+            globals,
+            type_tree,
+            file_info_chart,
+            cwd: vec![],
+        }
+    }
+
+    /// Runs commands from input until it ends or the user quits, writing what they show to output.
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        writeln!(
+            output,
+            "{} globals, enter a command or help for a list of them",
+            self.globals.len()
+        )?;
+        for line in input.lines() {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let path = words.get(1).copied().unwrap_or("");
+            match words.first().copied() {
+                None => {}
+                Some("quit") | Some("q") => return Ok(()),
+                Some("help") | Some("h") => writeln!(output, "{}", HELP)?,
+                Some("ls") => self.list(&mut output, path)?,
+                Some("cd") => match self.resolve(path) {
+                    Some(labels) => self.cwd = labels,
+                    None => writeln!(output, "There's no node {}", path)?,
+                },
+                Some("show") => self.show(&mut output, path)?,
+                Some("find") => match parse_int(path) {
+                    Some(int) => self.find(&mut output, &int)?,
+                    None => writeln!(output, "{} isn't an int", path)?,
+                },
+                Some("def") => self.show_definition(&mut output, path)?,
+                Some(command) => writeln!(output, "Unrecognized command {}, try help", command)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// The globals, each labeled by name.
+    fn roots(&self) -> Vec<Node<'_>> {
+        let shape = TupleTree::new(self.globals.len(), false);
+        self.globals
+            .iter()
+            .enumerate()
+            .filter_map(|(index, global)| {
+                let path = shape.slot_path(index)?;
+                Some(Node {
+                    label: global.name.clone(),
+                    value: tuple_path(&self.register, &path)?,
+                    tipe: global.tipe.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// The nodes within a node. Fields are labeled by name when the node's type says what they
+    /// are, and tuple slots are labeled by number otherwise.
+    fn children<'a>(&self, node: &Node<'a>) -> Vec<Node<'a>> {
+        let tipe = node.tipe.rep(&self.type_tree).unwrap_or(Type::Any);
+        let fields: Vec<(String, Type)> = match tipe {
+            Type::Struct(fields) => fields
+                .into_iter()
+                .map(|field| (field.name, field.tipe))
+                .collect(),
+            Type::Tuple(types) => types
+                .into_iter()
+                .enumerate()
+                .map(|(index, tipe)| (index.to_string(), tipe))
+                .collect(),
+            Type::Option(inner) => {
+                return match node.value {
+                    Value::Tuple(tup) if tup.len() == 2 => vec![Node {
+                        label: "some".to_string(),
+                        value: &tup[1],
+                        tipe: *inner,
+                    }],
+                    _ => vec![],
+                }
+            }
+            Type::Enum(variants) => {
+                return match node.value {
+                    Value::Tuple(tup) if tup.len() == 2 => {
+                        let variant = match &tup[0] {
+                            Value::Int(tag) => tag.to_usize().and_then(|tag| variants.get(tag)),
+                            _ => None,
+                        };
+                        variant
+                            .map(|variant| Node {
+                                label: variant.name.clone(),
+                                value: &tup[1],
+                                tipe: Type::Tuple(variant.fields.clone()),
+                            })
+                            .into_iter()
+                            .collect()
+                    }
+                    _ => vec![],
+                }
+            }
+            _ => vec![],
+        };
+
+        if fields.len() == 1 {
+            // single field values are unwrapped when written, but may start out wrapped
+            let (label, tipe) = fields.into_iter().next().unwrap();
+            let value = match node.value {
+                Value::Tuple(tup) if tup.len() == 1 => &tup[0],
+                value => value,
+            };
+            return vec![Node { label, value, tipe }];
+        }
+        if !fields.is_empty() {
+            let shape = TupleTree::new(fields.len(), false);
+            let decoded: Option<Vec<_>> = fields
+                .into_iter()
+                .enumerate()
+                .map(|(index, (label, tipe))| {
+                    let value = tuple_path(node.value, &shape.slot_path(index)?)?;
+                    Some(Node { label, value, tipe })
+                })
+                .collect();
+            if let Some(decoded) = decoded {
+                return decoded;
+            }
+        }
+
+        match node.value {
+            Value::Tuple(tup) => tup
+                .iter()
+                .enumerate()
+                .map(|(index, value)| Node {
+                    label: format!("[{}]", index),
+                    value,
+                    tipe: Type::Any,
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The int at a path from the globals, or `None` if there's no such node or it isn't an int.
+    pub fn int_at(&self, path: &str) -> Option<&Uint256> {
+        let labels: Vec<String> = path
+            .trim_start_matches('/')
+            .split('.')
+            .map(|label| label.to_string())
+            .collect();
+        match self.node(&labels)?.value {
+            Value::Int(int) => Some(int),
+            _ => None,
+        }
+    }
+
+    /// Finds the labels leading to a path, relative to the current node unless it starts with /.
+    fn resolve(&self, path: &str) -> Option<Vec<String>> {
+        let mut labels = match path.starts_with('/') {
+            true => vec![],
+            false => self.cwd.clone(),
+        };
+        match path {
+            ".." => drop(labels.pop()),
+            _ => labels.extend(
+                path.trim_start_matches('/')
+                    .split('.')
+                    .filter(|label| !label.is_empty())
+                    .map(|label| label.to_string()),
+            ),
+        }
+        if !labels.is_empty() {
+            self.node(&labels)?;
+        }
+        Some(labels)
+    }
+
+    /// Gets the node labels lead to from the globals, or `None` for the globals themselves or a
+    /// node that doesn't exist.
+    fn node(&self, labels: &[String]) -> Option<Node<'_>> {
+        let (first, rest) = labels.split_first()?;
+        let mut node = self.roots().into_iter().find(|node| &node.label == first)?;
+        for label in rest {
+            node = self
+                .children(&node)
+                .into_iter()
+                .find(|node| &node.label == label)?;
+        }
+        Some(node)
+    }
+
+    fn list(&self, output: &mut impl Write, path: &str) -> io::Result<()> {
+        let labels = match self.resolve(path) {
+            Some(labels) => labels,
+            None => return writeln!(output, "There's no node {}", path),
+        };
+        let children = match self.node(&labels) {
+            Some(node) => self.children(&node),
+            None => self.roots(),
+        };
+        if children.is_empty() {
+            writeln!(output, "{} holds nothing further", labels.join("."))?;
+        }
+        for child in children {
+            writeln!(
+                output,
+                "{:<24} {:<24} {}",
+                child.label,
+                one_line(&child.tipe.print(&self.type_tree)),
+                summarize(child.value)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn show(&self, output: &mut impl Write, path: &str) -> io::Result<()> {
+        let labels = self.resolve(path);
+        match labels.as_deref().and_then(|labels| self.node(labels)) {
+            Some(node) => {
+                let text = node.value.to_string();
+                match text.len() > MAX_SHOWN {
+                    true => writeln!(
+                        output,
+                        "{}... ({} more characters)",
+                        &text[..MAX_SHOWN],
+                        text.len() - MAX_SHOWN
+                    ),
+                    false => writeln!(output, "{}", text),
+                }
+            }
+            None => writeln!(output, "There's no node {}", path),
+        }
+    }
+
+    /// Lists the paths of every node holding int, searching the globals depth first.
+    fn find(&self, output: &mut impl Write, int: &Uint256) -> io::Result<()> {
+        let mut matches = vec![];
+        let mut queue: Vec<(String, Node)> = self
+            .roots()
+            .into_iter()
+            .rev()
+            .map(|node| (node.label.clone(), node))
+            .collect();
+        while let Some((path, node)) = queue.pop() {
+            if let Value::Int(value) = node.value {
+                if value == int {
+                    matches.push(path.clone());
+                }
+            }
+            let children = self.children(&node);
+            for child in children.into_iter().rev() {
+                queue.push((format!("{}.{}", path, child.label), child));
+            }
+        }
+        for path in matches.iter().take(MAX_MATCHES) {
+            writeln!(output, "/{}", path)?;
+        }
+        match matches.len() {
+            0 => writeln!(output, "No node holds {}", int),
+            count if count > MAX_MATCHES => {
+                writeln!(output, "... and {} more", count - MAX_MATCHES)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Shows the declaration of the global a path leads through, along with its source line when
+    /// the file can be read.
+    fn show_definition(&self, output: &mut impl Write, path: &str) -> io::Result<()> {
+        let global = self
+            .resolve(path)
+            .and_then(|labels| labels.first().cloned())
+            .and_then(|name| self.globals.iter().find(|global| global.name == name));
+        let global = match global {
+            Some(global) => global,
+            None => return writeln!(output, "{} isn't within a global", path),
+        };
+        let location = match global.debug_info.location {
+            Some(location) => location,
+            None => return writeln!(output, "{} has no recorded declaration", global.name),
+        };
+        let file = self.file_info_chart.get(&location.file_id);
+        let filename = file.map(|file| file.name.clone()).unwrap_or_default();
+        writeln!(
+            output,
+            "{} is declared in {} line {}",
+            global.name, filename, location.line
+        )?;
+        let source = std::fs::read_to_string(&filename).ok();
+        let line = source
+            .as_ref()
+            .and_then(|source| source.lines().nth(location.line() as usize));
+        match line {
+            Some(line) => writeln!(output, "    {}", line.trim()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Describes a value in a few words, so that listings stay short no matter how large it is.
+fn summarize(value: &Value) -> String {
+    match value {
+        Value::Int(int) => int.to_string(),
+        Value::Tuple(tup) if tup.is_empty() => "empty tuple".to_string(),
+        Value::Tuple(tup) => format!("tuple of size {}", tup.len()),
+        Value::CodePoint(pc) => format!("codepoint {}", pc),
+        Value::Label(label) => format!("label {}", label),
+        Value::Buffer(_) => "buffer".to_string(),
+    }
+}
+
+/// Puts text that may span several lines, like the display of a struct type, on one.
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Parses an int given in decimal, or in hex with a leading 0x.
+pub(super) fn parse_int(s: &str) -> Option<Uint256> {
+    match s.strip_prefix("0x") {
+        Some(hex) => Uint256::from_string_hex(hex),
+        None => Uint256::from_string(s),
+    }
+}
+
+#[test]
+fn test_state_browser() {
+    use crate::compile::{DebugInfo, StructField};
+
+    let point = Type::Struct(vec![
+        StructField::new("x".to_string(), Type::Uint),
+        StructField::new("y".to_string(), Type::Option(Box::new(Type::Uint))),
+    ]);
+    let globals = vec![
+        GlobalVar::new(0, "count".to_string(), Type::Uint, DebugInfo::default()),
+        GlobalVar::new(1, "origin".to_string(), point, DebugInfo::default()),
+    ];
+    let int = |i| Value::Int(Uint256::from_u64(i));
+    let register = Value::new_tuple(vec![
+        int(7),
+        Value::new_tuple(vec![int(3), Value::new_tuple(vec![int(1), int(7)])]),
+    ]);
+    let mut browser = StateBrowser::new(register, globals, TypeTree::new(), BTreeMap::new());
+
+    let commands =
+        "ls\ncd origin\nls\nls y\nshow y.some\ncd ..\nfind 0x7\ncd nowhere\ndef origin.x\n";
+    let mut output = vec![];
+    browser.run(commands.as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<String> = output.lines().map(one_line).collect();
+
+    assert_eq!(
+        lines,
+        vec![
+            "2 globals, enter a command or help for a list of them",
+            "count uint 7",
+            "origin struct { x: uint, y: option<uint> , } tuple of size 2",
+            "x uint 3",
+            "y option<uint> tuple of size 2",
+            "some uint 7",
+            "7",
+            "/count",
+            "/origin.y.some",
+            "There's no node nowhere",
+            "origin has no recorded declaration",
+        ]
+    );
+
+    assert_eq!(browser.int_at("origin.y.some"), Some(&Uint256::from_u64(7)));
+    assert_eq!(browser.int_at("/origin.x"), Some(&Uint256::from_u64(3)));
+    assert_eq!(browser.int_at("origin"), None);
+    assert_eq!(browser.int_at("origin.z"), None);
+}
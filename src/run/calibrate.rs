@@ -0,0 +1,474 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Measures what each opcode costs to run on the host, so that the gas table can be checked
+//! against the time the emulator actually spends. Each opcode is timed across a microbenchmark
+//! that runs it many times on operands set up by untimed code around it.
+
+use super::runtime_env::RtEnvRecorder;
+use super::{Machine, RuntimeEnvironment};
+use crate::compile::DebugInfo;
+use crate::link::{LinkedProgram, SerializableTypeTree, FORMAT_VERSION};
+use crate::mavm::{AVMOpcode, CodePt, Instruction, Opcode, Value};
+use crate::uint256::Uint256;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// A microbenchmark of an opcode: the code that sets up its operands before each run, and the code
+/// that cleans up after it.
+struct Bench {
+    opcode: AVMOpcode,
+    setup: Vec<Instruction<AVMOpcode>>,
+    teardown: Vec<Instruction<AVMOpcode>>,
+}
+
+/// The opcodes that aren't benchmarked, since they jump, block, leave the machine, or need operands
+/// too particular to generate.
+pub const UNBENCHMARKED: [AVMOpcode; 21] = [
+    AVMOpcode::Zero,
+    AVMOpcode::Jump,
+    AVMOpcode::Cjump,
+    AVMOpcode::Halt,
+    AVMOpcode::Error,
+    AVMOpcode::ErrSet,
+    AVMOpcode::ErrCodePoint,
+    AVMOpcode::PushInsn,
+    AVMOpcode::PushInsnImm,
+    AVMOpcode::OpenInsn,
+    AVMOpcode::Breakpoint,
+    AVMOpcode::Log,
+    AVMOpcode::Send,
+    AVMOpcode::Inbox,
+    AVMOpcode::InboxPeek,
+    AVMOpcode::SetGas,
+    AVMOpcode::Sideload,
+    AVMOpcode::Blake2f,
+    AVMOpcode::EcRecover,
+    AVMOpcode::EcPairing,
+    AVMOpcode::DebugPrint,
+];
+
+fn insn(opcode: AVMOpcode) -> Instruction<AVMOpcode> {
+    Instruction::new(opcode, None, DebugInfo::default())
+}
+
+fn push(value: Value) -> Instruction<AVMOpcode> {
+    Instruction::new(AVMOpcode::Noop, Some(value), DebugInfo::default())
+}
+
+impl Bench {
+    /// Benchmarks an opcode on operands pushed in order, popping the outputs it leaves.
+    fn on(opcode: AVMOpcode, operands: Vec<Value>, outputs: usize) -> Self {
+        Bench {
+            opcode,
+            setup: operands.into_iter().map(push).collect(),
+            teardown: vec![insn(AVMOpcode::Pop); outputs],
+        }
+    }
+
+    fn with(
+        opcode: AVMOpcode,
+        setup: Vec<Instruction<AVMOpcode>>,
+        teardown: Vec<Instruction<AVMOpcode>>,
+    ) -> Self {
+        Bench {
+            opcode,
+            setup,
+            teardown,
+        }
+    }
+
+    /// A program running the opcode reps times, along with the codepoints it's run at.
+    fn program(&self, reps: usize) -> (LinkedProgram, Vec<bool>) {
+        let mut code = vec![];
+        let mut timed = vec![];
+        for _ in 0..reps {
+            code.extend(self.setup.iter().cloned());
+            timed.resize(code.len(), false);
+            code.push(insn(self.opcode));
+            timed.push(true);
+            code.extend(self.teardown.iter().cloned());
+        }
+        code.push(insn(AVMOpcode::Halt));
+        timed.resize(code.len(), false);
+
+        let program = LinkedProgram {
+            format_version: FORMAT_VERSION,
+            arbos_version: 0,
+            code,
+            static_val: Value::none(),
+            globals: vec![],
+            file_info_chart: BTreeMap::new(),
+            type_tree: SerializableTypeTree::default(),
+            interface: BTreeMap::new(),
+            symbols: BTreeMap::new(),
+            defines: BTreeMap::new(),
+        };
+        (program, timed)
+    }
+}
+
+fn benches() -> Vec<Bench> {
+    let int = |hex: &str| Value::Int(Uint256::from_string_hex(hex).unwrap());
+    let wide = int("f0e1d2c3b4a5968778695a4b3c2d1e0ff0e1d2c3b4a5968778695a4b3c2d1e0f");
+    let half = int("1234567890abcdef1234567890abcdef");
+    let small = Value::from(7usize);
+    let tuple = |len: usize| Value::new_tuple(vec![wide.clone(); len]);
+    let buffer = Value::new_buffer(vec![0xab; 64]);
+    let one = Value::from(1usize);
+    let two = Value::from(2usize);
+
+    let mut benches = vec![];
+    for opcode in [
+        AVMOpcode::Add,
+        AVMOpcode::Mul,
+        AVMOpcode::Sub,
+        AVMOpcode::Div,
+        AVMOpcode::Sdiv,
+        AVMOpcode::Mod,
+        AVMOpcode::Smod,
+        AVMOpcode::Exp,
+        AVMOpcode::LessThan,
+        AVMOpcode::GreaterThan,
+        AVMOpcode::SLessThan,
+        AVMOpcode::SGreaterThan,
+        AVMOpcode::Equal,
+        AVMOpcode::BitwiseAnd,
+        AVMOpcode::BitwiseOr,
+        AVMOpcode::BitwiseXor,
+        AVMOpcode::EthHash2,
+    ] {
+        benches.push(Bench::on(opcode, vec![half.clone(), wide.clone()], 1));
+    }
+    for opcode in [
+        AVMOpcode::Byte,
+        AVMOpcode::ShiftLeft,
+        AVMOpcode::ShiftRight,
+        AVMOpcode::ShiftArith,
+        AVMOpcode::SignExtend,
+    ] {
+        benches.push(Bench::on(opcode, vec![wide.clone(), small.clone()], 1));
+    }
+    for opcode in [AVMOpcode::AddMod, AVMOpcode::MulMod] {
+        let operands = vec![half.clone(), wide.clone(), wide.clone()];
+        benches.push(Bench::on(opcode, operands, 1));
+    }
+    for opcode in [AVMOpcode::IsZero, AVMOpcode::BitwiseNeg, AVMOpcode::Type] {
+        benches.push(Bench::on(opcode, vec![wide.clone()], 1));
+    }
+    benches.push(Bench::on(AVMOpcode::Hash, vec![tuple(3)], 1));
+    benches.push(Bench::on(AVMOpcode::Tlen, vec![tuple(8)], 1));
+
+    benches.push(Bench::on(AVMOpcode::Noop, vec![], 0));
+    benches.push(Bench::on(AVMOpcode::Pop, vec![wide.clone()], 0));
+    benches.push(Bench::on(AVMOpcode::Dup0, vec![wide.clone()], 2));
+    benches.push(Bench::on(
+        AVMOpcode::Dup1,
+        vec![wide.clone(), half.clone()],
+        3,
+    ));
+    let three = vec![wide.clone(), half.clone(), wide.clone()];
+    benches.push(Bench::on(AVMOpcode::Dup2, three.clone(), 4));
+    benches.push(Bench::on(
+        AVMOpcode::Swap1,
+        vec![wide.clone(), half.clone()],
+        2,
+    ));
+    benches.push(Bench::on(AVMOpcode::Swap2, three.clone(), 3));
+    for opcode in [
+        AVMOpcode::Spush,
+        AVMOpcode::Rpush,
+        AVMOpcode::PushGas,
+        AVMOpcode::PCpush,
+        AVMOpcode::ErrPush,
+        AVMOpcode::StackEmpty,
+        AVMOpcode::AuxStackEmpty,
+        AVMOpcode::NewBuffer,
+    ] {
+        benches.push(Bench::on(opcode, vec![], 1));
+    }
+    benches.push(Bench::on(AVMOpcode::Rset, vec![wide.clone()], 0));
+
+    benches.push(Bench::on(AVMOpcode::Tget, vec![tuple(8), small.clone()], 1));
+    let operands = vec![wide.clone(), tuple(8), small.clone()];
+    benches.push(Bench::on(AVMOpcode::Tset, operands, 1));
+    benches.push(Bench::with(
+        AVMOpcode::AuxPush,
+        vec![push(wide.clone())],
+        vec![insn(AVMOpcode::AuxPop), insn(AVMOpcode::Pop)],
+    ));
+    benches.push(Bench::with(
+        AVMOpcode::AuxPop,
+        vec![push(wide.clone()), insn(AVMOpcode::AuxPush)],
+        vec![insn(AVMOpcode::Pop)],
+    ));
+    benches.push(Bench::with(
+        AVMOpcode::Xget,
+        vec![
+            push(tuple(8)),
+            insn(AVMOpcode::AuxPush),
+            push(small.clone()),
+        ],
+        vec![
+            insn(AVMOpcode::Pop),
+            insn(AVMOpcode::AuxPop),
+            insn(AVMOpcode::Pop),
+        ],
+    ));
+    benches.push(Bench::with(
+        AVMOpcode::Xset,
+        vec![
+            push(tuple(8)),
+            insn(AVMOpcode::AuxPush),
+            push(wide.clone()),
+            push(small.clone()),
+        ],
+        vec![insn(AVMOpcode::AuxPop), insn(AVMOpcode::Pop)],
+    ));
+
+    benches.push(Bench::on(AVMOpcode::Keccakf, vec![tuple(7)], 1));
+    for opcode in [AVMOpcode::Sha256f, AVMOpcode::Ripemd160f] {
+        benches.push(Bench::on(opcode, three.clone(), 1));
+    }
+    let points = vec![two.clone(), one.clone(), two.clone(), one.clone()];
+    benches.push(Bench::on(AVMOpcode::EcAdd, points, 2));
+    let operands = vec![half.clone(), two.clone(), one.clone()];
+    benches.push(Bench::on(AVMOpcode::EcMul, operands, 2));
+
+    for opcode in [
+        AVMOpcode::GetBuffer8,
+        AVMOpcode::GetBuffer64,
+        AVMOpcode::GetBuffer256,
+    ] {
+        benches.push(Bench::on(opcode, vec![buffer.clone(), small.clone()], 1));
+    }
+    for opcode in [
+        AVMOpcode::SetBuffer8,
+        AVMOpcode::SetBuffer64,
+        AVMOpcode::SetBuffer256,
+    ] {
+        let operands = vec![buffer.clone(), wide.clone(), small.clone()];
+        benches.push(Bench::on(opcode, operands, 1));
+    }
+    benches
+}
+
+/// What an opcode costs in gas and on the host.
+#[derive(Clone, Debug)]
+pub struct OpcodeCost {
+    pub opcode: AVMOpcode,
+    pub gas: u64,
+    /// The mean time a run of the opcode took, in nanoseconds
+    pub nanos: f64,
+    /// How many times the opcode ran in the workload the report was weighed by, if any
+    pub frequency: Option<u64>,
+}
+
+impl OpcodeCost {
+    fn nanos_per_gas(&self) -> f64 {
+        self.nanos / self.gas.max(1) as f64
+    }
+}
+
+/// The cost of each benchmarked opcode, compared against the time a unit of gas typically buys.
+#[derive(Clone, Debug)]
+pub struct CalibrationReport {
+    pub costs: Vec<OpcodeCost>,
+    /// The median time per unit of gas across every opcode, which each is measured against
+    pub nanos_per_gas: f64,
+    /// How far from the median an opcode's time per gas can be before it's mispriced
+    pub threshold: f64,
+}
+
+impl CalibrationReport {
+    /// How many times over the median time per gas the opcode takes. Those above 1 are
+    /// underpriced, and those below 1 overpriced.
+    pub fn ratio(&self, cost: &OpcodeCost) -> f64 {
+        cost.nanos_per_gas() / self.nanos_per_gas
+    }
+
+    /// The opcodes whose time per gas strays past the threshold, most underpriced first.
+    pub fn mispriced(&self) -> Vec<&OpcodeCost> {
+        self.costs
+            .iter()
+            .filter(|cost| {
+                let ratio = self.ratio(cost);
+                ratio > self.threshold || ratio * self.threshold < 1.0
+            })
+            .collect()
+    }
+
+    /// The gas the opcode's mispricing skews the workload by, as a share of the workload's total.
+    /// Opcodes that run often matter more to the schedule than those that are far off but rare.
+    pub fn skew(&self, cost: &OpcodeCost) -> Option<f64> {
+        let total: u64 = self.costs.iter().filter_map(|cost| cost.frequency).sum();
+        let frequency = cost.frequency?;
+        let fair_gas = cost.nanos / self.nanos_per_gas;
+        let charged = cost.gas as f64 * frequency as f64;
+        let owed = fair_gas * frequency as f64;
+        Some((owed - charged) / total.max(1) as f64)
+    }
+
+    /// Counts how often each opcode runs when ArbOS handles the messages of a test log.
+    pub fn weigh_by_testlog(&mut self, filename: &str) -> Result<(), String> {
+        let recorder =
+            RtEnvRecorder::from_file(filename).map_err(|e| format!("{}: {}", filename, e))?;
+        let mut machine = recorder.load_machine();
+        let frequencies = opcode_frequencies(&mut machine);
+        for cost in &mut self.costs {
+            cost.frequency = Some(frequencies.get(&cost.opcode).copied().unwrap_or(0));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CalibrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<14} {:>6} {:>10} {:>10} {:>8} {:>12}  pricing",
+            "opcode", "gas", "ns/op", "ns/gas", "ratio", "skew"
+        )?;
+        for cost in &self.costs {
+            let ratio = self.ratio(cost);
+            let skew = match self.skew(cost) {
+                Some(skew) => format!("{:+.4}", skew),
+                None => String::from("-"),
+            };
+            writeln!(
+                f,
+                "{:<14} {:>6} {:>10.1} {:>10.2} {:>8.2} {:>12}  {}",
+                Opcode::AVMOpcode(cost.opcode).to_name(),
+                cost.gas,
+                cost.nanos,
+                cost.nanos_per_gas(),
+                ratio,
+                skew,
+                match ratio {
+                    _ if ratio > self.threshold => "underpriced",
+                    _ if ratio * self.threshold < 1.0 => "overpriced",
+                    _ => "ok",
+                }
+            )?;
+        }
+        writeln!(
+            f,
+            "median {:.2} ns/gas, {} of {} opcodes mispriced by more than {}x",
+            self.nanos_per_gas,
+            self.mispriced().len(),
+            self.costs.len(),
+            self.threshold
+        )?;
+        let unbenchmarked: Vec<_> = UNBENCHMARKED
+            .iter()
+            .map(|opcode| Opcode::AVMOpcode(*opcode).to_name().to_string())
+            .collect();
+        writeln!(f, "not benchmarked: {}", unbenchmarked.join(", "))
+    }
+}
+
+/// How long it takes to read the clock twice, which is subtracted from every timing.
+fn clock_overhead() -> Duration {
+    const SAMPLES: u32 = 10_000;
+    let start = Instant::now();
+    for _ in 0..SAMPLES {
+        let _ = Instant::now().elapsed();
+    }
+    start.elapsed() / SAMPLES
+}
+
+/// Times a bench, returning the gas its opcode is charged and the mean time it took.
+fn time_bench(bench: &Bench, reps: usize, overhead: Duration) -> Result<OpcodeCost, String> {
+    let name = Opcode::AVMOpcode(bench.opcode).to_name().to_string();
+    let (program, timed) = bench.program(reps);
+    let mut machine = Machine::new(program, RuntimeEnvironment::default());
+    machine.start_at_zero(false);
+
+    let mut gas = 0;
+    let mut elapsed = Duration::default();
+    while machine.state.is_running() {
+        let is_timed = match machine.get_pc() {
+            Ok(CodePt::Internal(pc)) => timed[pc],
+            _ => false,
+        };
+        if !is_timed {
+            machine
+                .run_one(false)
+                .map_err(|e| format!("{}: {}", name, e))?;
+            continue;
+        }
+        gas = machine.next_op_gas().unwrap_or(0);
+        let start = Instant::now();
+        let result = machine.run_one(false);
+        elapsed += start.elapsed().saturating_sub(overhead);
+        result.map_err(|e| format!("{}: {}", name, e))?;
+    }
+    if let super::MachineState::Error(error) = &machine.state {
+        return Err(format!("{}: {}", name, error));
+    }
+    Ok(OpcodeCost {
+        opcode: bench.opcode,
+        gas,
+        nanos: elapsed.as_nanos() as f64 / reps.max(1) as f64,
+        frequency: None,
+    })
+}
+
+/// Times every benchmarked opcode over reps runs each, flagging those whose time per gas strays
+/// from the median by more than threshold times.
+pub fn calibrate(reps: usize, threshold: f64) -> Result<CalibrationReport, String> {
+    let overhead = clock_overhead();
+    let mut costs = benches()
+        .iter()
+        .map(|bench| time_bench(bench, reps, overhead))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut rates: Vec<f64> = costs.iter().map(OpcodeCost::nanos_per_gas).collect();
+    rates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let nanos_per_gas = rates
+        .get(rates.len() / 2)
+        .copied()
+        .unwrap_or(1.0)
+        .max(f64::EPSILON);
+
+    costs.sort_by(|a, b| {
+        let (a, b) = (a.nanos_per_gas(), b.nanos_per_gas());
+        b.partial_cmp(&a).unwrap()
+    });
+    Ok(CalibrationReport {
+        costs,
+        nanos_per_gas,
+        threshold,
+    })
+}
+
+/// Runs the machine until it blocks or stops, counting the opcodes it runs.
+fn opcode_frequencies(machine: &mut Machine) -> HashMap<AVMOpcode, u64> {
+    machine.start_at_zero(false);
+    let mut frequencies = HashMap::new();
+    while let Some(insn) = machine.next_opcode() {
+        match machine.run_one(false) {
+            Ok(true) => *frequencies.entry(insn.opcode).or_insert(0) += 1,
+            _ => break,
+        }
+    }
+    frequencies
+}
+
+#[test]
+fn test_calibrate() {
+    let report = calibrate(8, 2.0).unwrap();
+    assert_eq!(report.costs.len(), benches().len());
+    for cost in &report.costs {
+        assert!(cost.gas > 0, "{:?} has no gas cost", cost.opcode);
+        assert!(!UNBENCHMARKED.contains(&cost.opcode));
+    }
+
+    // the costliest time per gas comes first
+    let ratios: Vec<f64> = report.costs.iter().map(|cost| report.ratio(cost)).collect();
+    assert!(ratios.windows(2).all(|pair| pair[0] >= pair[1]));
+    let text = report.to_string();
+    assert_eq!(text.lines().count(), report.costs.len() + 3);
+}
@@ -4,19 +4,31 @@
 
 //! Provides utilities for emulation of AVM bytecode.
 
-use super::RuntimeEnvironment;
-use crate::compile::{CompileError, DebugInfo, FileInfo};
+use super::session::DebugSession;
+use super::{RuntimeEnvironment, StateBrowser};
+use crate::compile::{CompileError, DebugInfo, FileInfo, GlobalVar};
 use crate::console::Color;
-use crate::link::LinkedProgram;
+#[cfg(test)]
+use crate::link::TupleTree;
+use crate::link::{JumpProfile, LinkedProgram, SerializableTypeTree, SymbolMap};
 use crate::mavm::{AVMOpcode, Buffer, CodePt, Instruction, Value};
-use crate::pos::{try_display_location, Location};
+use crate::pos::{try_display_location, BytePos, Location};
 use crate::run::blake2b::blake2bf_instruction;
+use crate::run::inbox;
 use crate::run::ripemd160port;
+use crate::run::sparse::SparseGlobal;
+#[cfg(test)]
+use crate::run::sparse::SparseTuple;
+use crate::run::tracefilter::TraceFilter;
+#[cfg(test)]
+use crate::run::validate::tuple_path;
+use crate::run::validate::GlobalValidator;
+use crate::run::warmboot::BootSnapshot;
 use crate::uint256::Uint256;
 use clap::Clap;
 use ethers_core::types::{Signature, H256};
 use std::cmp::{max, Ordering};
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::TryInto;
 use std::fmt;
 use std::fs::File;
@@ -266,7 +278,7 @@ impl MachineState {
 }
 
 /// Holds AVM bytecode in a list of segments, the runtime is held on segment 0.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CodeStore {
     pub segments: Vec<Vec<Instruction<AVMOpcode>>>,
 }
@@ -291,6 +303,14 @@ impl CodeStore {
         self.segments[0].len()
     }
 
+    /// Gets the location of the first instruction of the first code segment that has one. The
+    /// prologue a program begins with has no location, so this is where its entry point begins.
+    fn entry_location(&self) -> Option<Location> {
+        self.segments[0]
+            .iter()
+            .find_map(|insn| insn.debug_info.location)
+    }
+
     /// Returns the `Instruction` that codept points to, or None if codept points to an invalid
     /// location.
     ///
@@ -371,6 +391,25 @@ pub struct ProfilerData {
     stack_tree: HashMap<CodePt, (Vec<ProfilerEvent>, Option<Location>)>,
     unknown_gas: u64,
     file_info_chart: BTreeMap<u64, FileInfo>,
+    /// The names of the funcs whose code begins at each location
+    symbols: HashMap<Location, String>,
+    /// How many times each codepoint was jumped to
+    jumps: HashMap<CodePt, u64>,
+    /// Where the last run added to the profile left off
+    cursor: Option<ProfileCursor>,
+}
+
+/// Where a profile left off, so that it can be picked up again once the machine has more to do.
+#[derive(Debug, Clone)]
+struct ProfileCursor {
+    total_gas: u64,
+    /// The depth of the call stack when the last instruction was profiled
+    stack_len: usize,
+    stack: Vec<CodePt>,
+    /// Where the func being run begins
+    codepoint: CodePt,
+    /// Whether instructions are being profiled, which they aren't before booting in some modes
+    enabled: bool,
 }
 
 impl ProfilerData {
@@ -433,27 +472,7 @@ impl ProfilerData {
     pub fn profiler_session(&self) {
         let mut formatted_data = BTreeMap::new();
         for (func, (events, location)) in &self.stack_tree {
-            let mut callers: BTreeMap<CodePt, (u64, Option<Location>)> = BTreeMap::new();
-            let mut in_func = false;
-            let mut in_callstack = false;
-            let mut in_func_gas = 0;
-            let mut current_call: Option<(CodePt, (u64, Option<Location>))> = None;
-            let mut called: BTreeMap<CodePt, (u64, Option<Location>)> = BTreeMap::new();
-            let mut start_point = 0;
-            let mut call_start = 0;
-            for event in events {
-                self.handle_event(
-                    event,
-                    &mut in_func,
-                    &mut start_point,
-                    &mut in_callstack,
-                    &mut call_start,
-                    &mut current_call,
-                    &mut called,
-                    &mut in_func_gas,
-                    &mut callers,
-                )
-            }
+            let (in_func_gas, called, callers) = self.summarize(events, self.end_gas());
             formatted_data.insert(in_func_gas, (called, callers, func, location));
         }
         for (in_func_gas, (called, callers, func, location)) in formatted_data.iter().rev() {
@@ -554,6 +573,101 @@ impl ProfilerData {
         }
     }
 
+    /// Computes the gas used within each func, excluding that of the funcs it calls. Funcs are
+    /// named by their module path and name from the program's symbol table, as the func declared
+    /// last at or before their first instruction, so that a func keeps its name across revisions
+    /// that move its code. Without symbols, funcs are named by the location of their first
+    /// instruction instead.
+    pub fn func_costs(&self) -> BTreeMap<String, u64> {
+        let decls: BTreeMap<(u64, BytePos), &String> = self
+            .symbols
+            .iter()
+            .map(|(loc, name)| ((loc.file_id, loc.absolute), name))
+            .collect();
+        let mut costs = BTreeMap::new();
+        for (func, (events, location)) in &self.stack_tree {
+            let enclosing = location.and_then(|loc| {
+                decls
+                    .range((loc.file_id, BytePos::from(0))..=(loc.file_id, loc.absolute))
+                    .next_back()
+                    .map(|(_, name)| *name)
+            });
+            let name = match (location, enclosing) {
+                (Some(loc), _) if self.symbols.contains_key(loc) => self.symbols[loc].clone(),
+                (_, Some(name)) => name.clone(),
+                (Some(loc), None) => format!(
+                    "{}:{}:{}",
+                    match self.file_info_chart.get(&loc.file_id) {
+                        None => "unknown file",
+                        Some(info) => &info.name,
+                    },
+                    loc.line,
+                    loc.column
+                ),
+                (None, None) => format!("unknown func at {}", func),
+            };
+            *costs.entry(name).or_insert(0) += self.summarize(events, self.end_gas()).0;
+        }
+        costs
+    }
+
+    /// Gets the total gas used in the run, including gas without an associated location.
+    pub fn total_gas(&self) -> u64 {
+        let located: u64 = self.data.values().flat_map(|tree| tree.values()).sum();
+        located + self.unknown_gas
+    }
+
+    /// Gets how many times each entry of the jump table was jumped to, named by the symbol map of
+    /// the program run, which compiling with `--jump-profile` lays out the jump table by.
+    pub fn jump_profile(&self, symbols: &SymbolMap) -> JumpProfile {
+        JumpProfile::new(&self.jumps, symbols)
+    }
+
+    /// Gets the gas used by the end of the run, when any funcs yet to return stopped running.
+    fn end_gas(&self) -> u64 {
+        self.cursor.as_ref().map_or(0, |cursor| cursor.total_gas)
+    }
+
+    /// Replays the events of a func, returning the gas used within it along with the gas of the
+    /// funcs it calls and of the callers it was called by. A func still running when the run
+    /// ended, like the entry point of a program that never returns from it, is charged the gas
+    /// used up to end.
+    fn summarize(
+        &self,
+        events: &[ProfilerEvent],
+        end: u64,
+    ) -> (
+        u64,
+        BTreeMap<CodePt, (u64, Option<Location>)>,
+        BTreeMap<CodePt, (u64, Option<Location>)>,
+    ) {
+        let mut callers: BTreeMap<CodePt, (u64, Option<Location>)> = BTreeMap::new();
+        let mut in_func = false;
+        let mut in_callstack = false;
+        let mut in_func_gas = 0;
+        let mut current_call: Option<(CodePt, (u64, Option<Location>))> = None;
+        let mut called: BTreeMap<CodePt, (u64, Option<Location>)> = BTreeMap::new();
+        let mut start_point = 0;
+        let mut call_start = 0;
+        for event in events {
+            self.handle_event(
+                event,
+                &mut in_func,
+                &mut start_point,
+                &mut in_callstack,
+                &mut call_start,
+                &mut current_call,
+                &mut called,
+                &mut in_func_gas,
+                &mut callers,
+            )
+        }
+        if in_func {
+            in_func_gas += end - start_point;
+        }
+        (in_func_gas, called, callers)
+    }
+
     fn handle_event(
         &self,
         event: &ProfilerEvent,
@@ -697,7 +811,7 @@ pub enum ProfilerAction {
     Change,
 }
 
-#[derive(PartialEq, Debug, Clap)]
+#[derive(Clone, Copy, PartialEq, Debug, Clap)]
 pub enum ProfilerMode {
     Never,
     PostBoot,
@@ -731,12 +845,23 @@ pub struct Machine {
     static_val: Value,
     pub register: Value,
     pub err_codepoint: CodePt,
+    /// The error the error handler was last sent to handle, since the machine was last started
+    caught_error: Option<ExecutionError>,
     arb_gas_remaining: Uint256,
     pub runtime_env: RuntimeEnvironment,
     file_info_chart: BTreeMap<u64, FileInfo>,
+    /// The names of the funcs whose code begins at each location
+    symbols: HashMap<Location, String>,
+    type_tree: SerializableTypeTree,
+    globals: Vec<GlobalVar>,
+    global_validator: Option<GlobalValidator>,
+    /// The globals held as sparse trees, which are installed along with the rest of the globals
+    sparse_globals: Vec<SparseGlobal>,
     total_gas_usage: Uint256,
     trace_writer: Option<BufWriter<File>>,
+    trace_filter: Option<TraceFilter>,
     coverage: Option<HashSet<usize>>,
+    debug_session: DebugSession,
 }
 
 impl Machine {
@@ -749,12 +874,24 @@ impl Machine {
             static_val: program.static_val,
             register: Value::none(),
             err_codepoint: CodePt::Null,
+            caught_error: None,
             arb_gas_remaining: Uint256::zero().bitwise_neg(),
             runtime_env: env,
             file_info_chart: program.file_info_chart,
+            symbols: program
+                .symbols
+                .into_iter()
+                .map(|(name, location)| (location, name))
+                .collect(),
+            type_tree: program.type_tree,
+            globals: program.globals,
+            global_validator: None,
+            sparse_globals: vec![],
             total_gas_usage: Uint256::zero(),
             trace_writer: None,
+            trace_filter: None,
             coverage: None,
+            debug_session: DebugSession::default(),
         }
     }
 
@@ -763,15 +900,167 @@ impl Machine {
         self.stack.contents.last()
     }
 
+    /// The machine's aux stack, which holds the frames and return addresses of the calls in
+    /// progress.
+    #[cfg(test)]
+    pub fn aux_stack(&self) -> &ValueStack {
+        &self.aux_stack
+    }
+
+    /// Where the machine jumps when an instruction fails, if an error handler has been installed.
+    pub fn error_handler(&self) -> Option<CodePt> {
+        match self.err_codepoint {
+            CodePt::Null => None,
+            codept => Some(codept),
+        }
+    }
+
+    /// The error the error handler was last sent to handle, if any has been since the machine was
+    /// last started. It's kept after the handler resumes, so it needn't still be being handled.
+    pub fn last_error(&self) -> Option<&ExecutionError> {
+        self.caught_error.as_ref()
+    }
+
     /// Pushes 0 to the stack and sets the program counter to the first instruction. Used by the EVM
     /// compiler.
     pub fn start_at_zero(&mut self, start_coverage: bool) {
         self.state = MachineState::Running(CodePt::Internal(0));
+        self.caught_error = None;
         if start_coverage {
             self.start_coverage();
         }
     }
 
+    /// Checks that every value written to a global conforms to the shape of its declared type,
+    /// stopping the machine at the first write that doesn't.
+    pub fn validate_globals(&mut self) {
+        self.global_validator = Some(GlobalValidator::new(
+            self.globals.clone(),
+            self.type_tree.clone().into_type_tree(),
+        ));
+    }
+
+    /// Has the named global hold a sparse tree, like the state of a forked chain, once the machine
+    /// installs its globals. The tree can be read back with `sparse_global` after running.
+    #[cfg(test)]
+    pub fn back_global(&mut self, name: &str, tree: SparseTuple) -> Result<(), String> {
+        let path = self.global_path(name)?;
+        self.sparse_globals.push(SparseGlobal {
+            path,
+            tree,
+            installed: false,
+        });
+        Ok(())
+    }
+
+    /// The tree backing the named global, holding whatever the machine has written to it.
+    #[cfg(test)]
+    pub fn sparse_global(&mut self, name: &str) -> Result<&mut SparseTuple, String> {
+        let path = self.global_path(name)?;
+        let global = self
+            .sparse_globals
+            .iter_mut()
+            .find(|global| global.path == path)
+            .ok_or_else(|| format!("global {} isn't backed by a sparse tree", name))?;
+        if global.installed {
+            let value = tuple_path(&self.register, &path)
+                .ok_or_else(|| format!("register does not hold global {}", name))?;
+            global.tree.absorb(value)?;
+        }
+        Ok(&mut global.tree)
+    }
+
+    /// The slots leading to the named global through the globals tuple.
+    #[cfg(test)]
+    fn global_path(&self, name: &str) -> Result<Vec<usize>, String> {
+        let index = self
+            .globals
+            .iter()
+            .position(|global| global.name == name)
+            .ok_or_else(|| format!("there's no global named {}", name))?;
+        Ok(TupleTree::new(self.globals.len(), false)
+            .slot_path(index)
+            .unwrap_or_default())
+    }
+
+    /// Copies the machine as it stands, sharing the values it holds with the original. Tracing
+    /// isn't copied, since the two can't write to the same trace.
+    pub fn _fork(&self) -> Machine {
+        Machine {
+            stack: self.stack.clone(),
+            aux_stack: self.aux_stack.clone(),
+            state: self.state.clone(),
+            code: self.code.clone(),
+            static_val: self.static_val.clone(),
+            register: self.register.clone(),
+            err_codepoint: self.err_codepoint,
+            caught_error: self.caught_error.clone(),
+            arb_gas_remaining: self.arb_gas_remaining.clone(),
+            runtime_env: self.runtime_env.clone(),
+            file_info_chart: self.file_info_chart.clone(),
+            symbols: self.symbols.clone(),
+            type_tree: self.type_tree.clone(),
+            globals: self.globals.clone(),
+            global_validator: self.global_validator.clone(),
+            sparse_globals: self.sparse_globals.clone(),
+            total_gas_usage: self.total_gas_usage.clone(),
+            trace_writer: None,
+            trace_filter: None,
+            coverage: self.coverage.clone(),
+            debug_session: DebugSession::default(),
+        }
+    }
+
+    /// Captures what booting changed about the machine, which has read consumed messages since it
+    /// started and is now waiting on its inbox.
+    pub fn boot_snapshot(&self, consumed: usize) -> BootSnapshot {
+        let recorder = &self.runtime_env.recorder;
+        BootSnapshot {
+            stack: self.stack.contents.iter().cloned().collect(),
+            aux_stack: self.aux_stack.contents.iter().cloned().collect(),
+            register: self.register.clone(),
+            pc: self.get_pc().unwrap_or(CodePt::Null),
+            segments: self.code.segments[1..].to_vec(),
+            arb_gas_remaining: self.arb_gas_remaining.clone(),
+            total_gas_usage: self.total_gas_usage.clone(),
+            consumed,
+            logs: recorder.logs().to_vec(),
+            sends: recorder.sends().to_vec(),
+        }
+    }
+
+    /// Puts the machine, freshly loaded with the program and inbox it was booted on, in the state
+    /// booting left it in.
+    pub fn restore_boot(&mut self, snapshot: BootSnapshot) {
+        self.stack.contents = snapshot.stack.into();
+        self.aux_stack.contents = snapshot.aux_stack.into();
+        self.register = snapshot.register;
+        self.state = MachineState::Running(snapshot.pc);
+        self.code.segments.truncate(1);
+        self.code.segments.extend(snapshot.segments);
+        self.arb_gas_remaining = snapshot.arb_gas_remaining;
+        self.total_gas_usage = snapshot.total_gas_usage;
+        for _ in 0..snapshot.consumed {
+            self.runtime_env.l1_inbox.pop();
+        }
+        for log in snapshot.logs {
+            self.runtime_env.push_log(log);
+        }
+        for send in snapshot.sends {
+            self.runtime_env.push_send_contents(send);
+        }
+    }
+
+    /// Starts browsing the globals the machine holds right now.
+    pub fn browse_state(&self) -> StateBrowser {
+        StateBrowser::new(
+            self.register.clone(),
+            self.globals.clone(),
+            self.type_tree.clone().into_type_tree(),
+            self.file_info_chart.clone(),
+        )
+    }
+
     /// Returns a stack trace of the current state of the machine.
     pub fn get_stack_trace(&self) -> StackTrace {
         StackTrace {
@@ -784,6 +1073,22 @@ impl Machine {
         self.trace_writer = Some(BufWriter::new(File::create(Path::new(filename)).unwrap()));
     }
 
+    /// Has the trace writer keep only the trace of txs that touch one of addresses, whether as a
+    /// caller, a callee, or the owner of the storage a call uses.
+    pub fn filter_trace(&mut self, addresses: BTreeSet<Uint256>) {
+        self.trace_filter = Some(TraceFilter::new(addresses));
+    }
+
+    /// Has the debugger run the commands of the script at path before reading any from stdin.
+    pub fn replay_debug_script(&mut self, path: &Path) -> std::io::Result<()> {
+        self.debug_session.replay(path)
+    }
+
+    /// Has the debugger write the commands it reads to a script at path.
+    pub fn record_debug_session(&mut self, path: &Path) -> std::io::Result<()> {
+        self.debug_session.record(path)
+    }
+
     /// Returns the value of the ArbGasRemaining register
     pub fn get_total_gas_usage(&self) -> Uint256 {
         self.total_gas_usage.clone()
@@ -799,6 +1104,7 @@ impl Machine {
         }
         self.stack.push(Value::CodePoint(stop_pc));
         self.state = MachineState::Running(func_addr);
+        self.caught_error = None;
         stop_pc
     }
 
@@ -879,13 +1185,46 @@ impl Machine {
         self.coverage = Some(HashSet::new());
     }
 
+    /// Prints the memory layout of the type with the qualified name `type_name`, as used by the
+    /// debugger. If `type_name` instead names a module, the types declared there are listed.
+    fn print_type_layout(&self, type_name: &str) {
+        let path: Vec<String> = type_name.split("::").map(|s| s.to_string()).collect();
+        if self.type_tree.modules().contains(&path) {
+            for entry in self.type_tree.types_in_module(&path) {
+                println!("  {}", entry.name);
+            }
+            return;
+        }
+        let entry = match self.type_tree.lookup(type_name) {
+            Some(entry) => entry,
+            None => {
+                println!("No type named {}", Color::red(type_name));
+                return;
+            }
+        };
+        match self.type_tree.layout(&entry.tipe) {
+            Ok(layout) => {
+                let type_tree = self.type_tree.clone().into_type_tree();
+                println!("{} has size {}", Color::pink(type_name), layout.size());
+                for (name, offsets, tipe) in layout.leaves() {
+                    println!("  {:?}\t{}\t{}", offsets, name, tipe.print(&type_tree));
+                }
+            }
+            Err(err) => println!("Could not compute layout: {}", err),
+        }
+    }
+
     /// Starts the debugger, execution will end when the program counter of self reaches stop_pc, or
     /// an error state is reached.
     ///
     /// Returns the total gas used by the machine.
     pub fn debug(&mut self, stop_pc: Option<CodePt>) -> u64 {
         println!("Blank line or \"step\" to run one opcode, \"set break\" followed by a \
-         line number to resume program until that line, \"show static\" to show the static contents.");
+         line number to resume program until that line, \"show static\" to show the static contents, \
+         \"show type\" followed by a qualified type name to show its layout, \"show errors\" to show \
+         the error handler and the error it last caught, and \"expect top\" or \"expect line\" \
+         followed by a value or line number to stop with an error unless the stack top or the next \
+         opcode's line is that.");
         let mut breakpoint = true;
         let mut break_line = 0;
         let mut break_gas_amount = 0u64;
@@ -956,47 +1295,92 @@ impl Machine {
                 println!();
                 let mut exit = false;
                 loop {
-                    let mut debugger_state = String::new();
-                    std::io::stdin().read_line(&mut debugger_state).unwrap();
-                    match debugger_state.as_str() {
-                        "\n" | "step\n" => exit = true,
-                        "set break\n" => {
+                    // once there's nothing left to read, the program runs to the end
+                    let command = match self.debug_session.next_command() {
+                        Some(command) => command,
+                        None => String::from("run"),
+                    };
+                    match command.as_str() {
+                        "" | "step" => exit = true,
+                        "set break" => {
                             breakpoint = false;
-                            loop {
-                                let mut debugger_state = String::new();
-                                std::io::stdin().read_line(&mut debugger_state).unwrap();
-                                if let Ok(val) = str::parse(&debugger_state.trim()) {
-                                    break_line = val;
-                                    exit = true;
-                                    break;
-                                } else {
-                                    println!("Could not parse input as number");
-                                }
+                            exit = true;
+                            if let Some(val) = self.read_debug_number() {
+                                break_line = val;
                             }
                         }
-                        "break at gas\n" => {
+                        "break at gas" => {
                             breakpoint = false;
-                            loop {
-                                let mut debugger_state = String::new();
-                                std::io::stdin().read_line(&mut debugger_state).unwrap();
-                                if let Ok(val) = str::parse(&debugger_state.trim()) {
-                                    break_gas_amount = val;
-                                    exit = true;
-                                    break;
-                                } else {
-                                    println!("Could not parse input as number");
-                                }
+                            exit = true;
+                            if let Some(val) = self.read_debug_number() {
+                                break_gas_amount = val;
+                            }
+                        }
+                        "show static" => println!("Static contents: {}", self.static_val),
+                        "show errors" => {
+                            match self.error_handler() {
+                                Some(codept) => println!("Error handler: {}", codept),
+                                None => println!("No error handler installed"),
+                            }
+                            match self.last_error() {
+                                Some(error) => print!("Last error: {}", error),
+                                None => println!("No error caught"),
                             }
                         }
-                        "show static\n" => println!("Static contents: {}", self.static_val),
-                        "r\n" | "run\n" => {
+                        "show type" => {
+                            let type_name = self.debug_session.next_command().unwrap_or_default();
+                            self.print_type_layout(&type_name);
+                        }
+                        "expect top" => {
+                            let expected = self.debug_session.next_command().unwrap_or_default();
+                            let top = self.stack.top();
+                            let found = top.as_ref().map(|top| top.to_string());
+                            if found.as_deref() != Some(expected.as_str()) {
+                                println!(
+                                    "Expected stack top {}, found {}",
+                                    Color::red(&expected),
+                                    Color::red(found.unwrap_or_else(|| String::from("nothing"))),
+                                );
+                                self.state = MachineState::Error(ExecutionError::new(
+                                    "debugger expectation failed",
+                                    &self.state,
+                                    top,
+                                ));
+                                return gas_cost;
+                            }
+                        }
+                        "expect line" => {
+                            let expected = self.read_debug_number();
+                            let line = self
+                                .next_opcode()
+                                .and_then(|insn| insn.debug_info.location)
+                                .map(|location| location.line.to_usize());
+                            if expected.is_none() || line != expected {
+                                println!(
+                                    "Expected to be at line {}, but at {}",
+                                    Color::red(
+                                        expected.map_or(String::from("?"), |l| l.to_string())
+                                    ),
+                                    Color::red(
+                                        line.map_or(String::from("no line"), |l| l.to_string())
+                                    ),
+                                );
+                                self.state = MachineState::Error(ExecutionError::new(
+                                    "debugger expectation failed",
+                                    &self.state,
+                                    None,
+                                ));
+                                return gas_cost;
+                            }
+                        }
+                        "r" | "run" => {
                             breakpoint = false;
                             exit = true;
                         }
-                        "toggle aux\n" => {
+                        "toggle aux" => {
                             show_aux = !show_aux;
                         }
-                        "toggle reg\n" => {
+                        "toggle reg" => {
                             show_reg = !show_reg;
                         }
                         _ => println!("invalid input"),
@@ -1027,6 +1411,18 @@ impl Machine {
         gas_cost
     }
 
+    /// Reads the number a debugger command is followed by, asking again until one's given.
+    /// Returns `None` if the debugger runs out of commands first.
+    fn read_debug_number<T: FromStr>(&mut self) -> Option<T> {
+        loop {
+            let input = self.debug_session.next_command()?;
+            match input.parse() {
+                Ok(val) => return Some(val),
+                Err(_) => println!("Could not parse input as number"),
+            }
+        }
+    }
+
     /// Runs self until the program counter reaches stop_pc, an error state is encountered or the
     /// machine reaches a stopped state for any other reason.  Returns the total gas used by self.
     pub fn run(&mut self, stop_pc: Option<CodePt>) -> u64 {
@@ -1073,21 +1469,26 @@ impl Machine {
                         _ => None,
                     };
                     if let Some((seg_num, pc, opcode)) = res {
-                        write!(trace_writer, "{} {} {}", seg_num, pc, opcode)
-                            .expect("failed to write PC trace file");
+                        let mut line = format!("{} {} {}", seg_num, pc, opcode);
                         if !self.stack.is_empty() {
                             let val = self.stack.top().unwrap();
                             if let Value::Int(ui) = val {
-                                write!(trace_writer, " {}", ui.avm_hash()).unwrap();
+                                line.push_str(&format!(" {}", ui.avm_hash()));
                             }
                             if self.stack.num_items() > 1 {
                                 let val = self.stack.nth(1).unwrap();
                                 if let Value::Int(ui) = val {
-                                    write!(trace_writer, " {}", ui.avm_hash()).unwrap();
+                                    line.push_str(&format!(" {}", ui.avm_hash()));
                                 }
                             }
                         }
-                        write!(trace_writer, "\n").unwrap();
+                        line.push('\n');
+                        match &mut self.trace_filter {
+                            Some(filter) => filter.hold(&line),
+                            None => trace_writer
+                                .write_all(line.as_bytes())
+                                .expect("failed to write PC trace file"),
+                        }
                     }
                 }
             }
@@ -1113,51 +1514,81 @@ impl Machine {
 
     /// Generates a `ProfilerData` from a run of self with args from address 0.
     pub fn profile_gen(&mut self, args: Vec<Value>, mode: ProfilerMode) -> ProfilerData {
-        assert_ne!(mode, ProfilerMode::Never);
         self.call_state(CodePt::new_internal(0), args);
-        let mut loc_map = ProfilerData::default();
-        loc_map.file_info_chart = self.file_info_chart.clone();
-        loc_map.stack_tree.insert(
-            CodePt::new_internal(0),
-            (
-                vec![ProfilerEvent::EnterFunc(0)],
-                self.code
-                    .get_insn(CodePt::new_internal(0))
-                    .map(|insn| insn.debug_info.location)
-                    .unwrap_or(None),
-            ),
-        );
-        let mut stack_len = 0;
-        let mut current_codepoint = CodePt::new_internal(0);
-        let mut total_gas = 0;
-        let mut stack = vec![];
-        let mut profile_enabled = mode == ProfilerMode::Always;
+        let mut profile = ProfilerData::default();
+        self.profile_more(&mut profile, mode);
+        profile
+    }
+
+    /// Runs self from where it stands until it blocks on its inbox, stops, or fails, adding what
+    /// it spends to profile. A profile can be added to over several runs, so that a machine given
+    /// its messages a few at a time is profiled as one run.
+    pub fn profile_more(&mut self, profile: &mut ProfilerData, mode: ProfilerMode) {
+        assert_ne!(mode, ProfilerMode::Never);
+        let mut cursor = match profile.cursor.take() {
+            Some(cursor) => cursor,
+            None => {
+                profile.file_info_chart = self.file_info_chart.clone();
+                profile.symbols = self.symbols.clone();
+                profile.stack_tree.insert(
+                    CodePt::new_internal(0),
+                    (
+                        vec![ProfilerEvent::EnterFunc(0)],
+                        self.code.entry_location(),
+                    ),
+                );
+                ProfileCursor {
+                    total_gas: 0,
+                    stack_len: self.get_stack_trace().trace.len(),
+                    stack: vec![],
+                    codepoint: CodePt::new_internal(0),
+                    enabled: mode == ProfilerMode::Always,
+                }
+            }
+        };
         while let Some(insn) = self.next_opcode() {
             if insn.opcode == AVMOpcode::Inbox {
-                profile_enabled = true;
+                cursor.enabled = true;
             }
-            if profile_enabled {
+            if cursor.enabled {
+                self.count_jump(&insn, profile);
                 self.gen_step(
                     insn,
-                    &mut loc_map,
-                    &mut total_gas,
-                    &mut stack_len,
-                    &mut stack,
-                    &mut current_codepoint,
+                    profile,
+                    &mut cursor.total_gas,
+                    &mut cursor.stack_len,
+                    &mut cursor.stack,
+                    &mut cursor.codepoint,
                 );
             }
             match self.run_one(false) {
-                Ok(false) => {
-                    return loc_map;
-                }
+                Ok(false) => break,
                 Err(e) => {
                     self.state = MachineState::Error(e);
-                    return loc_map;
+                    break;
                 }
                 _ => {}
             }
         }
-        loc_map
+        profile.cursor = Some(cursor);
+    }
+
+    /// Counts the jump insn is about to take, if it's a jump and it's taken, against the codepoint
+    /// it lands on. An immediate is pushed before the instruction runs, so it's the destination of
+    /// a jump given one, and the destination is otherwise on top of the stack.
+    fn count_jump(&self, insn: &Instruction<AVMOpcode>, profile: &mut ProfilerData) {
+        let taken =
+            |cond: Option<Value>| matches!(cond, Some(Value::Int(cond)) if cond != Uint256::zero());
+        let destination = match (insn.opcode, &insn.immediate) {
+            (AVMOpcode::Jump, Some(dest)) => Some(dest.clone()),
+            (AVMOpcode::Jump, None) => self.stack.top(),
+            (AVMOpcode::Cjump, Some(dest)) if taken(self.stack.top()) => Some(dest.clone()),
+            (AVMOpcode::Cjump, None) if taken(self.stack.nth(1)) => self.stack.top(),
+            _ => None,
+        };
+        if let Some(Value::CodePoint(codepoint)) = destination {
+            *profile.jumps.entry(codepoint).or_insert(0) += 1;
+        }
     }
 
     fn gen_step(
@@ -1394,6 +1825,7 @@ impl Machine {
                     Err(e)
                 } else {
                     self.state = MachineState::Running(self.err_codepoint);
+                    self.caught_error = Some(e);
                     Ok(true)
                 }
             }
@@ -1455,6 +1887,38 @@ impl Machine {
                     AVMOpcode::Rset => {
                         let val = self.stack.pop(&self.state)?;
                         self.register = val;
+                        let pending = self.sparse_globals.iter_mut();
+                        for global in pending.filter(|global| !global.installed) {
+                            match global.install(&self.register) {
+                                Ok(register) => self.register = register,
+                                Err(reason) => {
+                                    println!("{} {}", Color::red("Invalid sparse global:"), reason);
+                                    return Err(ExecutionError::new(
+                                        "sparse global could not be installed",
+                                        &self.state,
+                                        None,
+                                    ));
+                                }
+                            }
+                        }
+                        if let Some(validator) = &mut self.global_validator {
+                            if let Err(reason) = validator.check(&self.register) {
+                                println!("{} {}", Color::red("Invalid global:"), reason);
+                                println!(
+                                    "{}",
+                                    Color::grey(try_display_location(
+                                        insn.debug_info.location,
+                                        &self.file_info_chart,
+                                        true
+                                    ))
+                                );
+                                return Err(ExecutionError::new(
+                                    "global does not conform to its declared type",
+                                    &self.state,
+                                    None,
+                                ));
+                            }
+                        }
                         self.incr_pc();
                         Ok(true)
                     }
@@ -1941,7 +2405,7 @@ impl Machine {
                         Ok(true)
                     }
                     AVMOpcode::Inbox => {
-                        match self.runtime_env.get_from_inbox() {
+                        match self.runtime_env.l1_inbox.pop() {
                             Some(msg) => {
                                 self.stack.push(msg);
                                 self.incr_pc();
@@ -1958,28 +2422,19 @@ impl Machine {
                     }
                     AVMOpcode::InboxPeek => {
                         let bn = self.stack.pop_uint(&self.state)?;
-                        match self.runtime_env.peek_at_inbox_head() {
-                            Some(msg) => {
-                                if let Value::Tuple(tup) = msg {
-                                    if let Value::Int(msg_bn) = &tup[1] {
-                                        self.stack.push_bool(bn == msg_bn.clone());
-                                        self.incr_pc();
-                                        Ok(true)
-                                    } else {
-                                        Err(ExecutionError::new(
-                                            "inbox contents not a tuple",
-                                            &self.state,
-                                            None,
-                                        ))
-                                    }
-                                } else {
-                                    Err(ExecutionError::new(
-                                        "blocknum not an integer",
-                                        &self.state,
-                                        None,
-                                    ))
+                        match self.runtime_env.l1_inbox.peek() {
+                            Some(msg) => match inbox::block_number(msg) {
+                                Some(msg_bn) => {
+                                    self.stack.push_bool(bn == *msg_bn);
+                                    self.incr_pc();
+                                    Ok(true)
                                 }
-                            }
+                                None => Err(ExecutionError::new(
+                                    "inbox message has no block number",
+                                    &self.state,
+                                    None,
+                                )),
+                            },
                             None => {
                                 // machine is blocked, waiting for nonempty inbox
                                 self.arb_gas_remaining = gas_remaining_before;
@@ -2086,6 +2541,15 @@ impl Machine {
                             Color::blue(&self.arb_gas_remaining),
                         );
                         check_debugprint_for_malformed_trace_info(&r1);
+                        if let (Some(writer), Some(filter)) =
+                            (&mut self.trace_writer, &mut self.trace_filter)
+                        {
+                            if let Some(trace) = filter.end_tx(&r1) {
+                                writer
+                                    .write_all(trace.as_bytes())
+                                    .expect("failed to write PC trace file");
+                            }
+                        }
                         self.incr_pc();
                         Ok(true)
                     }
@@ -2269,6 +2733,9 @@ impl Machine {
         }
     }
 
+    /// Writes which lines of source the machine has reached since coverage started to
+    /// `coverage/<name>.cov`. Programs compiled with coverage probes are reported by their probes,
+    /// giving one entry per statement, and others by every instruction.
     pub fn write_coverage(&self, name: String) {
         let data = match &self.coverage {
             Some(coverage) => coverage,
@@ -2279,7 +2746,13 @@ impl Machine {
             File::create(PathBuf::from("coverage/").join(name.clone() + ".cov"))
                 .expect(&format!("Could not create coverage for {}", name));
 
-        for (index, insn) in self.code.segments.iter().flatten().enumerate() {
+        let code = || self.code.segments.iter().flatten();
+        let probed = code().any(|insn| insn.debug_info.attributes.probe);
+
+        for (index, insn) in code().enumerate() {
+            if probed && !insn.debug_info.attributes.probe {
+                continue;
+            }
             if let Some(loc) = insn.debug_info.location {
                 if let Some(info) = self.file_info_chart.get(&loc.file_id) {
                     match data.contains(&index) {
@@ -0,0 +1,204 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Finds the stretches of a workload that cost the most and saves each as a benchmark fixture, so
+//! that optimizations can be measured on the traffic dominating a real workload without replaying
+//! all of it.
+//!
+//! A fixture is a checkpoint of the machine as it stood just before the stretch, and a test log
+//! ending with the stretch's last message. Profiling the program with the fixture's
+//! `--checkpoint` and `--testlog` restores the checkpoint and runs only the stretch's messages.
+
+use super::runtime_env::RtEnvRecorder;
+use super::Machine;
+use crate::mavm::Value;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The name of the manifest listing the fixtures written to a folder.
+pub const FIXTURES_MANIFEST: &str = "fixtures.json";
+
+/// A stretch of consecutive messages of a workload, saved as a fixture.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HotPath {
+    /// The name of the fixture, which is ranked by the gas its stretch takes
+    pub name: String,
+    /// The index of the stretch's first message
+    pub start: usize,
+    /// The index just past the stretch's last message
+    pub end: usize,
+    /// The gas the stretch's messages took in the workload
+    pub gas: u64,
+    /// The file holding the checkpoint the fixture starts from
+    pub checkpoint: String,
+    /// The file holding the test log the fixture runs on
+    pub testlog: String,
+}
+
+/// Profiles the program at mexe on the messages of the test log at testlog, and writes fixtures
+/// for the count stretches of window messages that cost the most, none of them overlapping, to
+/// the folder output, along with a manifest listing them. Returns the fixtures written, hottest
+/// first.
+pub fn extract_hot_paths(
+    mexe: &Path,
+    testlog: &Path,
+    window: usize,
+    count: usize,
+    output: &Path,
+) -> io::Result<Vec<HotPath>> {
+    if window == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a hot path takes in at least one message",
+        ));
+    }
+    let recorder = RtEnvRecorder::from_file(&testlog.display().to_string())?;
+    let messages = recorder.messages();
+    let load = || {
+        let mut machine = recorder.load_machine_from(mexe);
+        machine.runtime_env.insert_full_inbox_contents(vec![]);
+        machine.start_at_zero(false);
+        machine
+    };
+
+    let costs = message_costs(&mut load(), messages);
+    let paths: Vec<_> = hottest_windows(&costs, window, count)
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (start, end, gas))| {
+            let name = format!("hot{}", rank + 1);
+            HotPath {
+                checkpoint: format!("{}.boot", name),
+                testlog: format!("{}.aoslog", name),
+                name,
+                start,
+                end,
+                gas,
+            }
+        })
+        .collect();
+
+    // the machine is run again, stopping to check in at the start of each path
+    fs::create_dir_all(output)?;
+    let mut by_start: Vec<&HotPath> = paths.iter().collect();
+    by_start.sort_by_key(|path| path.start);
+    let mut machine = load();
+    let mut delivered = 0;
+    for path in by_start {
+        while delivered < path.start {
+            deliver(&mut machine, &messages[delivered]);
+            delivered += 1;
+        }
+        let consumed = machine.runtime_env.l1_inbox.consumed();
+        machine
+            .boot_snapshot(consumed)
+            .write(&output.join(&path.checkpoint))?;
+        let gas = costs[..path.end].iter().sum();
+        recorder
+            .truncated(path.end)
+            .to_file(&output.join(&path.testlog), gas)?;
+    }
+
+    let manifest = serde_json::to_string_pretty(&paths)?;
+    fs::write(output.join(FIXTURES_MANIFEST), manifest)?;
+    Ok(paths)
+}
+
+/// Delivers messages to machine one at a time, as a scenario's `after` steps do, returning the gas
+/// each took to run until the machine waited for the next. Once the machine stops or fails, the
+/// rest cost nothing.
+fn message_costs(machine: &mut Machine, messages: &[Value]) -> Vec<u64> {
+    messages
+        .iter()
+        .map(|msg| match machine.state.is_running() {
+            true => deliver(machine, msg),
+            false => 0,
+        })
+        .collect()
+}
+
+fn deliver(machine: &mut Machine, msg: &Value) -> u64 {
+    machine.runtime_env.l1_inbox.push(msg.clone());
+    machine.run(None)
+}
+
+/// Finds up to count runs of window consecutive messages, none overlapping, that cost the most,
+/// as the start, end, and gas of each, costliest first. Runs costing nothing are left out.
+fn hottest_windows(costs: &[u64], window: usize, count: usize) -> Vec<(usize, usize, u64)> {
+    let window = window.min(costs.len());
+    if window == 0 {
+        return vec![];
+    }
+    let mut candidates: Vec<_> = costs
+        .windows(window)
+        .enumerate()
+        .map(|(start, costs)| (start, start + window, costs.iter().sum::<u64>()))
+        .filter(|(_, _, gas)| *gas > 0)
+        .collect();
+    candidates.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+
+    let mut chosen: Vec<(usize, usize, u64)> = vec![];
+    for candidate in candidates {
+        if chosen.len() == count {
+            break;
+        }
+        let (start, end, _) = candidate;
+        if chosen
+            .iter()
+            .all(|(other_start, other_end, _)| end <= *other_start || *other_end <= start)
+        {
+            chosen.push(candidate);
+        }
+    }
+    chosen
+}
+
+#[test]
+fn test_hottest_windows() {
+    let costs = [5, 1, 9, 9, 0, 2, 7, 0];
+    assert_eq!(
+        hottest_windows(&costs, 2, 3),
+        vec![(2, 4, 18), (5, 7, 9), (0, 2, 6)]
+    );
+    assert_eq!(hottest_windows(&costs, 1, 2), vec![(2, 3, 9), (3, 4, 9)]);
+    assert_eq!(hottest_windows(&costs, 20, 2), vec![(0, 8, 33)]);
+    assert!(hottest_windows(&[0, 0], 1, 2).is_empty());
+}
+
+#[test]
+fn test_extract_hot_paths() {
+    use super::warmboot::BootSnapshot;
+    use super::{load_from_file_and_env, RuntimeEnvironment};
+
+    let folder = std::env::temp_dir().join(format!("mini-hot-paths-{}", std::process::id()));
+    drop(fs::remove_dir_all(&folder));
+    let mexe = Path::new("arb_os/arbos.mexe");
+    let testlog = Path::new("replayTests/evm_direct_deploy_add.aoslog");
+    let paths = extract_hot_paths(mexe, testlog, 1, 1, &folder).unwrap();
+    assert_eq!(paths.len(), 1);
+    let path = &paths[0];
+    assert_eq!(path.end, path.start + 1);
+
+    // the manifest lists the fixtures written
+    let manifest = fs::read_to_string(folder.join(FIXTURES_MANIFEST)).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Vec<HotPath>>(&manifest).unwrap(),
+        paths
+    );
+
+    // restoring the checkpoint and running the rest of the log runs just the hot path
+    let recorder =
+        RtEnvRecorder::from_file(&folder.join(&path.testlog).display().to_string()).unwrap();
+    assert_eq!(recorder.messages().len(), path.end);
+    let mut env = RuntimeEnvironment::default();
+    env.insert_full_inbox_contents(recorder.messages().to_vec());
+    let mut machine = load_from_file_and_env(mexe, env);
+    machine.restore_boot(BootSnapshot::read(&folder.join(&path.checkpoint)).unwrap());
+    assert_eq!(machine.run(None), path.gas);
+    assert!(machine.state.is_running());
+
+    fs::remove_dir_all(&folder).unwrap();
+}
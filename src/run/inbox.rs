@@ -0,0 +1,234 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the queue of L1 messages a machine reads with the inbox opcodes.
+
+use crate::mavm::Value;
+use crate::uint256::Uint256;
+use std::collections::VecDeque;
+
+/// The messages waiting to be read, in the order they arrived.
+///
+/// ArbOS reads a message at a time with `inbox`, using `inboxpeek` beforehand to ask whether the
+/// next one is in the block it's working on, so messages are consumed from the front without
+/// the rest having to move.
+#[derive(Debug, Clone, Default)]
+pub struct Inbox {
+    messages: VecDeque<Value>,
+    /// How many messages have been read
+    consumed: usize,
+}
+
+impl Inbox {
+    /// Adds a message to the back of the queue.
+    pub fn push(&mut self, msg: Value) {
+        self.messages.push_back(msg);
+    }
+
+    /// Replaces the unread messages, as when replaying a recorded inbox.
+    pub fn replace(&mut self, messages: Vec<Value>) {
+        self.messages = messages.into();
+    }
+
+    /// Gets the next message without consuming it.
+    pub fn peek(&self) -> Option<&Value> {
+        self.messages.front()
+    }
+
+    /// Consumes the next message.
+    pub fn pop(&mut self) -> Option<Value> {
+        let msg = self.messages.pop_front()?;
+        self.consumed += 1;
+        Some(msg)
+    }
+
+    /// Drops all but the first len unread messages, as when the rest are reorged away on L1.
+    #[cfg(test)]
+    pub fn truncate(&mut self, len: usize) {
+        self.messages.truncate(len);
+    }
+
+    /// How many messages are waiting to be read.
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// How many messages have been read so far.
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Iterates over the unread messages, next one first.
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        self.messages.iter()
+    }
+}
+
+/// Gets the number of the L1 block a message was sent in, which `inboxpeek` compares against.
+pub fn block_number(msg: &Value) -> Option<&Uint256> {
+    match msg {
+        Value::Tuple(fields) => match fields.get(1) {
+            Some(Value::Int(block)) => Some(block),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Gets the inbox sequence number of a message, from which its request id is made.
+#[cfg(test)]
+pub fn seq_num(msg: &Value) -> Option<&Uint256> {
+    match msg {
+        Value::Tuple(fields) => match fields.get(4) {
+            Some(Value::Int(seq_num)) => Some(seq_num),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[test]
+fn test_inbox_queue() {
+    use crate::run::RuntimeEnvironment;
+
+    let mut env = RuntimeEnvironment::default();
+    let queued = env.l1_inbox.len();
+    let sender = Uint256::from_u64(1025);
+    let block = |num: u64| Some(Uint256::from_u64(num));
+    env.insert_l1_message(3, sender.clone(), &[1], block(7), None);
+    env.insert_l1_message(3, sender.clone(), &[2], block(7), None);
+    env.insert_l1_message(3, sender, &[3], block(8), None);
+    assert_eq!(env.l1_inbox.len(), queued + 3);
+
+    // drain what the environment queued on creation, so the messages above are next
+    for _ in 0..queued {
+        env.l1_inbox.pop();
+    }
+    assert_eq!(env.l1_inbox.consumed(), queued);
+
+    let inbox = &mut env.l1_inbox;
+    assert_eq!(inbox.peek().and_then(block_number), block(7).as_ref());
+    assert_eq!(inbox.len(), 3);
+    let read: Vec<_> = (0..3).filter_map(|_| inbox.pop()).collect();
+    let blocks: Vec<_> = read.iter().map(block_number).collect();
+    assert_eq!(
+        blocks,
+        vec![block(7).as_ref(), block(7).as_ref(), block(8).as_ref()]
+    );
+    assert!(inbox.pop().is_none());
+    assert_eq!(inbox.consumed(), queued + 3);
+
+    // messages that aren't from L1 blocks have no block number to peek at
+    inbox.replace(vec![Value::Int(Uint256::one()), Value::Int(Uint256::one())]);
+    assert!(inbox.peek().and_then(block_number).is_none());
+    assert_eq!(inbox.iter().count(), 2);
+}
+
+#[cfg(test)]
+mod reorg_tests {
+    use crate::evm::Tx;
+    use crate::run::{Machine, RuntimeEnvironment};
+    use crate::uint256::Uint256;
+
+    /// Delivers deposits of each amount, from a contract to a fixed payee.
+    fn deposit(env: &mut RuntimeEnvironment, amounts: &[u64]) -> Vec<Uint256> {
+        amounts
+            .iter()
+            .map(|amount| {
+                Tx::call(Uint256::from_u64(1025))
+                    .value(Uint256::from_u64(*amount))
+                    .with_deposit()
+                    .send_from_contract(env, Uint256::from_u64(1024))
+            })
+            .collect()
+    }
+
+    /// Runs ArbOS on every message the environment has delivered, returning the inbox hash
+    /// accumulator it ends with along with the request ids of its receipts.
+    fn replay(env: &RuntimeEnvironment) -> (String, Vec<Uint256>) {
+        let mut machine: Machine = env.recorder.load_machine();
+        machine.start_at_zero(false);
+        let _ = machine.run(None);
+        let mut shown = vec![];
+        machine
+            .browse_state()
+            .run(
+                "show globalInbox.inboxHashAccumulator\n".as_bytes(),
+                &mut shown,
+            )
+            .unwrap();
+        let shown = String::from_utf8(shown).unwrap();
+        let ids = machine
+            .runtime_env
+            .get_all_receipt_logs()
+            .iter()
+            .map(|receipt| receipt.get_request_id())
+            .collect();
+        (shown.lines().last().unwrap().to_string(), ids)
+    }
+
+    #[test]
+    fn test_reorged_suffix_is_redelivered() {
+        let mut env = RuntimeEnvironment::default();
+        let prefix = deposit(&mut env, &[1, 2]);
+        let fork = env.recorder.messages().len();
+        let (fork_accumulator, fork_ids) = replay(&env);
+        let reorged = deposit(&mut env, &[3, 4]);
+        let (reorged_accumulator, _) = replay(&env);
+
+        // the messages after the fork go away, and their replacements take their request ids
+        assert_eq!(env._reorg_inbox(fork).len(), reorged.len());
+        assert_eq!(env.l1_inbox.len(), fork);
+        assert_eq!(replay(&env), (fork_accumulator.clone(), fork_ids.clone()));
+        let redelivered = deposit(&mut env, &[5, 6, 7]);
+        assert_eq!(redelivered[..2], reorged[..]);
+
+        // the accumulator commits to everything delivered, so it forks with the inbox, while
+        // what ArbOS made of the messages before the fork stays put
+        let (accumulator, ids) = replay(&env);
+        assert_ne!(accumulator, fork_accumulator);
+        assert_ne!(accumulator, reorged_accumulator);
+        assert_eq!(ids[..fork_ids.len()], fork_ids[..]);
+        assert!(prefix.iter().all(|id| fork_ids.contains(id)));
+        assert!(redelivered.iter().all(|id| ids.contains(id)));
+
+        // and ArbOS can't tell the reorged inbox from one that only ever held the new messages
+        let mut fresh = RuntimeEnvironment::default();
+        deposit(&mut fresh, &[1, 2]);
+        deposit(&mut fresh, &[5, 6, 7]);
+        assert_eq!(fresh.recorder.messages(), env.recorder.messages());
+        assert_eq!(replay(&fresh), (accumulator, ids));
+    }
+
+    #[test]
+    fn test_reorg_of_readmessages() {
+        let mut env = RuntimeEnvironment::default();
+        let start = env.recorder.messages().len();
+        deposit(&mut env, &[1, 2, 3]);
+        for _ in 0..start + 1 {
+            env.l1_inbox.pop();
+        }
+
+        // only the unread messages after the fork leave the queue
+        let removed = env._reorg_inbox(start + 2);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(env.l1_inbox.len(), 1);
+        assert_eq!(env.recorder.messages().len(), start + 2);
+        assert_eq!(env._reorg_inbox(start).len(), 2);
+        assert!(env.l1_inbox.is_empty());
+        assert_eq!(
+            env.next_inbox_seq_num,
+            super::seq_num(&removed[0])
+                .unwrap()
+                .sub(&Uint256::from_u64(2))
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,291 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides patterns that the logs of a replayed testlog can be matched against instead of the
+//! logs recorded, so that a change that legitimately shifts gas doesn't mean regenerating every
+//! testlog. A pattern reads like the value it matches, with `*` matching anything, and an int
+//! followed by `~` and a bound, or a bound and `%`, matching any int that close to it, as in
+//! `Tuple(0, *, 21000~5%)`.
+
+use crate::mavm::Value;
+use crate::uint256::Uint256;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// How far an int may be from the one expected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tolerance {
+    Exact,
+    /// Within this much either way
+    Absolute(Uint256),
+    /// Within this percent of the int expected either way
+    Percent(Uint256),
+}
+
+impl Tolerance {
+    fn allows(&self, expected: &Uint256, seen: &Uint256) -> bool {
+        let diff = match seen > expected {
+            true => seen.unchecked_sub(expected),
+            false => expected.unchecked_sub(seen),
+        };
+        match self {
+            Tolerance::Exact => diff.is_zero(),
+            Tolerance::Absolute(bound) => diff <= *bound,
+            Tolerance::Percent(percent) => {
+                diff.mul(&Uint256::from_u64(100)) <= expected.mul(percent)
+            }
+        }
+    }
+}
+
+/// A pattern a log is expected to match, written as text in testlogs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum LogPattern {
+    Any,
+    Int(Uint256, Tolerance),
+    /// Matches a buffer with the same contents, given in hex
+    Buffer(String),
+    Tuple(Vec<LogPattern>),
+}
+
+impl LogPattern {
+    /// Describes where value first departs from the pattern, or returns `None` if it matches.
+    pub fn mismatch(&self, value: &Value) -> Option<String> {
+        let (mut path, why) = self.find_mismatch(value)?;
+        if path.is_empty() {
+            return Some(why);
+        }
+        path.reverse();
+        let path: Vec<_> = path.iter().map(|index| index.to_string()).collect();
+        Some(format!("at field {}, {}", path.join("."), why))
+    }
+
+    /// Finds the first part of value that doesn't match, along with the indices of the tuples
+    /// leading to it, innermost first.
+    fn find_mismatch(&self, value: &Value) -> Option<(Vec<usize>, String)> {
+        let matched = match (self, value) {
+            (LogPattern::Any, _) => true,
+            (LogPattern::Int(expected, tolerance), Value::Int(seen)) => {
+                tolerance.allows(expected, seen)
+            }
+            (LogPattern::Buffer(expected), Value::Buffer(buf)) => *expected == buf.hex_encode(),
+            (LogPattern::Tuple(fields), Value::Tuple(tup)) if fields.len() == tup.len() => {
+                return fields.iter().zip(tup.iter()).enumerate().find_map(
+                    |(index, (field, value))| {
+                        let (mut path, why) = field.find_mismatch(value)?;
+                        path.push(index);
+                        Some((path, why))
+                    },
+                );
+            }
+            _ => false,
+        };
+        match matched {
+            true => None,
+            false => Some((vec![], format!("expected {}, found {}", self, value))),
+        }
+    }
+}
+
+/// Matches each log against the pattern in the same place, describing the first that doesn't
+/// match if any.
+pub fn match_logs(patterns: &[LogPattern], logs: &[Value]) -> Result<(), String> {
+    if patterns.len() != logs.len() {
+        return Err(format!(
+            "expected {} logs, got {}",
+            patterns.len(),
+            logs.len()
+        ));
+    }
+    for (index, (pattern, log)) in patterns.iter().zip(logs).enumerate() {
+        if let Some(why) = pattern.mismatch(log) {
+            return Err(format!("log {} {}", index, why));
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for LogPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogPattern::Any => write!(f, "*"),
+            LogPattern::Int(value, Tolerance::Exact) => write!(f, "{}", value),
+            LogPattern::Int(value, Tolerance::Absolute(bound)) => write!(f, "{}~{}", value, bound),
+            LogPattern::Int(value, Tolerance::Percent(percent)) => {
+                write!(f, "{}~{}%", value, percent)
+            }
+            LogPattern::Buffer(hex) => write!(f, "Buffer({})", hex),
+            LogPattern::Tuple(fields) if fields.is_empty() => write!(f, "_"),
+            LogPattern::Tuple(fields) => {
+                let fields: Vec<_> = fields.iter().map(|field| field.to_string()).collect();
+                write!(f, "Tuple({})", fields.join(", "))
+            }
+        }
+    }
+}
+
+impl FromStr for LogPattern {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { text, pos: 0 };
+        let pattern = parser.pattern()?;
+        parser.skip_space();
+        match parser.pos == text.len() {
+            true => Ok(pattern),
+            false => Err(format!(
+                "unexpected {:?} in pattern {:?}",
+                parser.rest(),
+                text
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for LogPattern {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        text.parse()
+    }
+}
+
+impl From<LogPattern> for String {
+    fn from(pattern: LogPattern) -> String {
+        pattern.to_string()
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_space(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes token if it's next, returning whether it was.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_space();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(format!("expected {:?} before {:?}", token, self.rest())),
+        }
+    }
+
+    /// Consumes the letters and digits that come next.
+    fn word(&mut self) -> &'a str {
+        self.skip_space();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or_else(|| rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn int(&mut self) -> Result<Uint256, String> {
+        let word = self.word();
+        let value = match word.strip_prefix("0x") {
+            Some(hex) => Uint256::from_string_hex(hex),
+            None => Uint256::from_string(word),
+        };
+        value.ok_or_else(|| format!("expected an int or pattern, found {:?}", word))
+    }
+
+    fn pattern(&mut self) -> Result<LogPattern, String> {
+        if self.eat("*") {
+            return Ok(LogPattern::Any);
+        }
+        if self.eat("_") {
+            return Ok(LogPattern::Tuple(vec![]));
+        }
+        if self.eat("Buffer(") {
+            let hex = self.word().to_lowercase();
+            hex::decode(&hex).map_err(|e| format!("bad buffer {:?}: {}", hex, e))?;
+            self.expect(")")?;
+            return Ok(LogPattern::Buffer(hex));
+        }
+        if self.eat("Tuple(") || self.eat("(") {
+            let mut fields = vec![];
+            if !self.eat(")") {
+                loop {
+                    fields.push(self.pattern()?);
+                    if self.eat(")") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            return Ok(LogPattern::Tuple(fields));
+        }
+
+        let value = self.int()?;
+        let tolerance = match self.eat("~") {
+            true => {
+                let bound = self.int()?;
+                match self.eat("%") {
+                    true => Tolerance::Percent(bound),
+                    false => Tolerance::Absolute(bound),
+                }
+            }
+            false => Tolerance::Exact,
+        };
+        Ok(LogPattern::Int(value, tolerance))
+    }
+}
+
+#[test]
+fn test_log_patterns() {
+    let log = Value::new_tuple(vec![
+        Value::from(0),
+        Value::new_tuple(vec![Value::from(7), Value::from(0x1234_5678_9abc_u64)]),
+        Value::from(21400),
+        Value::new_tuple(vec![]),
+    ]);
+    let matches = |pattern: &str| {
+        let pattern: LogPattern = pattern.parse().unwrap();
+        pattern.mismatch(&log)
+    };
+
+    // a log's own text is a pattern matching it exactly
+    assert_eq!(matches(&log.to_string()), None);
+    assert_eq!(matches("(0, *, 21000~500, _)"), None);
+    assert_eq!(matches("(0, (7, 0x123456789abc), 21000~2%, *)"), None);
+    assert_eq!(
+        matches("(0, (8, *), *, *)"),
+        Some(String::from("at field 1.0, expected 8, found 7"))
+    );
+    assert_eq!(
+        matches("(0, *, 21000~1%, *)"),
+        Some(String::from("at field 2, expected 21000~1%, found 21400"))
+    );
+    assert!(matches("(0, *, *)").is_some());
+
+    // patterns are kept in testlogs as text, which reads back as the same pattern
+    let pattern: LogPattern = "Tuple(1, *, 5~2, 0x1234567890~10%, _)".parse().unwrap();
+    let json = serde_json::to_string(&pattern).unwrap();
+    assert_eq!(json, "\"Tuple(1, *, 5~2, 0x1234567890~10%, _)\"");
+    assert_eq!(serde_json::from_str::<LogPattern>(&json).unwrap(), pattern);
+    assert!("(1, 2".parse::<LogPattern>().is_err());
+    assert!("(1, two)".parse::<LogPattern>().is_err());
+}
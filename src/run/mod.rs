@@ -4,23 +4,60 @@
 
 //! Provides functionality for running mavm executables.
 
-use crate::link::LinkedProgram;
+use crate::link::{sidecar_path, symbol_map_path, DebugSidecar, LinkedProgram, SymbolMap};
 use crate::mavm::{CodePt, Value};
-use emulator::{ExecutionError, StackTrace};
-use std::{fs::File, io::Read, path::Path};
+use emulator::{ExecutionError, ProfilerData, StackTrace};
+use once_cell::sync::OnceCell;
+use runtime_env::RtEnvRecorder;
+use scenario::Scenario;
+use std::io;
+use std::{fs::File, io::Read, path::Path, path::PathBuf};
+use warmboot::BootSnapshot;
 
 use crate::compile::FileInfo;
+pub use accounts::{TestAccount, TestAccounts};
+pub use browse::StateBrowser;
+pub use calibrate::calibrate;
 pub use emulator::{Machine, MachineState, ProfilerMode};
+pub use hotpaths::extract_hot_paths;
+pub use inbox::Inbox;
+pub use outcome::TestOutcome;
+pub use perfdb::{compare_costs, compare_revs, load_records, PerfRecord, DEFAULT_PERF_DB};
+pub use retention::RetentionPolicy;
 pub use runtime_env::{
-    _bytes_from_bytestack, _bytestack_from_bytes, generic_compress_token_amount,
-    replay_from_testlog_file, ArbosReceipt, RuntimeEnvironment,
+    _bytes_from_bytestack, _bytestack_from_bytes, gas_diff_from_testlog_file,
+    generic_compress_token_amount, replay_from_testlog_file, ArbosReceipt, EvmLog,
+    RuntimeEnvironment,
 };
+pub use scenario::run_scenario_file;
+#[cfg(test)]
+pub use sparse::{SparseSource, SparseTuple};
 use std::collections::BTreeMap;
+pub use unittest::run_unit_tests;
+pub use warmboot::BootCache;
 
+mod accounts;
 mod blake2b;
+#[cfg(test)]
+mod blocks;
+mod browse;
+mod calibrate;
 mod emulator;
+mod hotpaths;
+mod inbox;
+mod logmatch;
+mod outcome;
+mod perfdb;
+mod retention;
 mod ripemd160port;
 pub mod runtime_env;
+mod scenario;
+mod session;
+mod sparse;
+mod tracefilter;
+mod unittest;
+mod validate;
+mod warmboot;
 
 pub fn run_from_file(
     path: &Path,
@@ -62,6 +99,38 @@ pub fn load_from_file(path: &Path) -> Machine {
     load_from_file_and_env(path, RuntimeEnvironment::default())
 }
 
+/// Boots machine, freshly loaded from the program at path, by running it from zero until it
+/// waits on its inbox. The boot is restored from the cache next to the program when it's been
+/// booted on the same inbox before.
+pub fn boot(machine: &mut Machine, path: &Path) {
+    let folder = path.parent().unwrap_or_else(|| Path::new(""));
+    match (BootCache::new(folder), std::fs::read(path)) {
+        (Some(cache), Ok(mexe)) => {
+            cache.boot(machine, &mexe);
+        }
+        _ => {
+            machine.start_at_zero(false);
+            let _ = machine.run(None);
+        }
+    }
+}
+
+/// Gets a copy of ArbOS as it stands once it's booted from genesis in the default environment,
+/// waiting on its next message. It's only loaded and booted the first time it's asked for, so
+/// tests that start this way share the cost, and each copy shares the values it holds with the
+/// others until it changes them.
+pub fn _booted_arbos() -> Machine {
+    static GENESIS: OnceCell<Machine> = OnceCell::new();
+    GENESIS
+        .get_or_init(|| {
+            let path = Path::new("arb_os/arbos.mexe");
+            let mut machine = load_from_file(path);
+            boot(&mut machine, path);
+            machine
+        })
+        ._fork()
+}
+
 /// Generates a `Machine` from the given path and `RuntimeEnvironment`. See `RuntimeEnvironment` for
 /// more details.
 ///
@@ -82,30 +151,39 @@ pub fn load_from_file_and_env_ret_file_info_table(
         Ok(file) => file,
     };
 
-    let mut s = String::new();
-    s = match file.read_to_string(&mut s) {
+    let mut bytes = vec![];
+    bytes = match file.read_to_end(&mut bytes) {
         Err(why) => panic!("couldn't read {}: {:?}", display, why),
-        Ok(_) => s,
+        Ok(_) => bytes,
     };
 
-    load_from_string(s, env)
+    let mut program = load_from_bytes(&bytes);
+
+    // debug info split out of the mexe is loaded back from its sidecar, if it was kept
+    let sidecar = sidecar_path(path);
+    if sidecar.exists() {
+        let attached = DebugSidecar::read(&sidecar)
+            .and_then(|debug_info| program.attach_debug_info(debug_info));
+        if let Err(e) = attached {
+            println!("Ignoring {}: {}", sidecar.display(), e);
+        }
+    }
+
+    let fic = program.file_info_chart.clone();
+    (Machine::new(program, env), fic)
 }
 
-/// Interprets s as a mini executable and generates a `Machine` with the specified
-/// `RuntimeEnvironment`.
+/// Interprets bytes as a mini executable, in any of the formats it can be written in.
 ///
-/// Will panic if s cannot be interpreted as a mini executable.
-fn load_from_string(s: String, env: RuntimeEnvironment) -> (Machine, BTreeMap<u64, FileInfo>) {
-    let parse_result: Result<LinkedProgram, serde_json::Error> = serde_json::from_str(&s);
-    let program = match parse_result {
+/// Will panic if bytes cannot be interpreted as a mini executable.
+fn load_from_bytes(bytes: &[u8]) -> LinkedProgram {
+    match LinkedProgram::from_bytes(bytes) {
         Ok(prog) => prog,
         Err(e) => {
-            println!("json parsing error: {:?}", e);
+            println!("mexe loading error: {}", e);
             panic!();
         }
-    };
-    let fic = program.file_info_chart.clone();
-    (Machine::new(program, env), fic)
+    }
 }
 
 /// Runs the specified `Machine` from its first codepoint.
@@ -130,17 +208,143 @@ pub fn run(
     }
 }
 
-/// Interprets path as a mini executable and starts a profiler session with executable arguments args
-/// and `RuntimeEnvironment` env.  See `profiler_session` for more details.
+/// The workloads a program can be profiled on.
+#[derive(Debug)]
+pub enum ProfileInput {
+    /// Calls the program from its first codepoint with no args
+    Call,
+    /// Runs the program on the messages of a test log
+    Testlog(PathBuf),
+    /// Runs the program through a scenario, which delivers the messages of a test log a few at a
+    /// time
+    Scenario { script: PathBuf, testlog: PathBuf },
+    /// Restores the program from a checkpoint, like the boots kept in a `.mini-cache`, then runs
+    /// it on the messages of a test log the checkpoint hasn't already read
+    Checkpoint {
+        checkpoint: PathBuf,
+        testlog: PathBuf,
+    },
+}
+
+impl ProfileInput {
+    /// Names a profile of the program at mexe on self, as it's recorded in the perf database.
+    fn label(&self, mexe: &Path) -> String {
+        match self {
+            ProfileInput::Call => mexe.display().to_string(),
+            ProfileInput::Testlog(testlog) => {
+                format!("{} on {}", mexe.display(), testlog.display())
+            }
+            ProfileInput::Scenario { script, testlog } => format!(
+                "{} through {} on {}",
+                mexe.display(),
+                script.display(),
+                testlog.display()
+            ),
+            ProfileInput::Checkpoint {
+                checkpoint,
+                testlog,
+            } => format!(
+                "{} from {} on {}",
+                mexe.display(),
+                checkpoint.display(),
+                testlog.display()
+            ),
+        }
+    }
+}
+
+/// Interprets path as a mini executable and starts a profiler session on a run of it over input,
+/// in `RuntimeEnvironment` env. See `profiler_session` for more details.
+///
+/// If perf_db is given, the per-func costs are instead appended to that database for later comparison.
+/// If jump_profile is given, how often each entry of the jump table was jumped to is instead written
+/// there, for compiling with `--jump-profile`. This needs the symbol map written beside the program.
 pub fn profile_gen_from_file(
     path: &Path,
-    args: Vec<Value>,
-    env: RuntimeEnvironment,
+    input: &ProfileInput,
+    mut env: RuntimeEnvironment,
     mode: ProfilerMode,
-) {
-    let mut machine = load_from_file_and_env(path, env);
-    let profile = machine.profile_gen(args, mode);
-    profile.profiler_session();
+    perf_db: Option<&Path>,
+    jump_profile: Option<&Path>,
+) -> io::Result<()> {
+    // errors are named after the file they're about
+    let within = |path: &Path| {
+        let path = path.display().to_string();
+        move |e: io::Error| io::Error::new(e.kind(), format!("\"{}\": {}", path, e))
+    };
+    let read_testlog = |testlog: &Path| {
+        RtEnvRecorder::from_file(&testlog.display().to_string()).map_err(within(testlog))
+    };
+    let profile = match input {
+        ProfileInput::Call => load_from_file_and_env(path, env).profile_gen(vec![], mode),
+        ProfileInput::Testlog(testlog) => {
+            let recorder = read_testlog(testlog)?;
+            env.insert_full_inbox_contents(recorder.messages().to_vec());
+            load_from_file_and_env(path, env).profile_gen(vec![], mode)
+        }
+        ProfileInput::Scenario { script, testlog } => {
+            let recorder = read_testlog(testlog)?;
+            let text = std::fs::read_to_string(script).map_err(within(script))?;
+            let scenario = Scenario::parse(&text)
+                .map_err(|e| within(script)(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+            let mut machine = load_from_file_and_env(path, env);
+            scenario.profile(&recorder, &mut machine, mode)
+        }
+        ProfileInput::Checkpoint {
+            checkpoint,
+            testlog,
+        } => {
+            let recorder = read_testlog(testlog)?;
+            let snapshot = BootSnapshot::read(checkpoint).map_err(within(checkpoint))?;
+            env.insert_full_inbox_contents(recorder.messages().to_vec());
+            let mut machine = load_from_file_and_env(path, env);
+            machine.restore_boot(snapshot);
+            let mut profile = ProfilerData::default();
+            machine.profile_more(&mut profile, mode);
+            profile
+        }
+    };
+    if let Some(jump_profile) = jump_profile {
+        // jumps are named by the func and entry they lead to, which only the symbol map records
+        let symbols_path = symbol_map_path(path);
+        let symbols = SymbolMap::read(&symbols_path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "\"{}\": {}, so compile the program with --symbol-map to profile its jumps",
+                    symbols_path.display(),
+                    e
+                ),
+            )
+        })?;
+        let jumps = profile.jump_profile(&symbols);
+        jumps.write(jump_profile).map_err(within(jump_profile))?;
+        println!(
+            "Recorded {} jumps to {} jump table entries at {}",
+            jumps.total(),
+            jumps.targets.len(),
+            jump_profile.display()
+        );
+    }
+    match perf_db {
+        Some(perf_db) => {
+            let input = input.label(path);
+            let record = PerfRecord::new(
+                "profiler",
+                &input,
+                profile.total_gas(),
+                profile.func_costs(),
+            );
+            record.append_to(perf_db).map_err(within(perf_db))?;
+            println!(
+                "Recorded {} ArbGas for {} at {}",
+                record.total, input, record.rev
+            );
+        }
+        None if jump_profile.is_none() => profile.profiler_session(),
+        None => {}
+    }
+    Ok(())
 }
 
 /*
@@ -193,3 +397,51 @@ mod tests {
     }
 }
  */
+
+#[test]
+fn test_booted_arbos_copies_are_independent() {
+    use crate::uint256::Uint256;
+
+    let mut first = _booted_arbos();
+    let second = _booted_arbos();
+    assert_eq!(first.register, second.register);
+
+    let payee = Uint256::from_u64(1025);
+    first.runtime_env.insert_eth_deposit_message(
+        payee.clone(),
+        payee,
+        Uint256::from_u64(1000),
+        false,
+    );
+    let _ = first.run(None);
+    assert_eq!(first.runtime_env.get_all_receipt_logs().len(), 1);
+    assert_ne!(first.register, second.register);
+    assert!(second.runtime_env.get_all_receipt_logs().is_empty());
+    assert!(_booted_arbos().runtime_env.l1_inbox.is_empty());
+}
+
+#[test]
+fn test_booted_arbos_error_handler() {
+    use crate::compile::DebugInfo;
+    use crate::mavm::{AVMOpcode, Instruction};
+
+    // booting installs ArbOS's error handler, which nothing has needed yet
+    let mut machine = _booted_arbos();
+    let handler = machine
+        .error_handler()
+        .expect("ArbOS didn't install an error handler");
+    assert!(machine.last_error().is_none());
+
+    // a failing instruction sends the machine to the handler, leaving the aux stack as it was
+    let frames = machine.aux_stack().num_items();
+    let segment = machine.code.segments.len();
+    machine.code.segments.push(vec![Instruction::from_opcode(
+        AVMOpcode::Error,
+        DebugInfo::default(),
+    )]);
+    machine.state = MachineState::Running(CodePt::InSegment(segment, 0));
+    assert!(matches!(machine.run_one(false), Ok(true)));
+    assert!(matches!(machine.get_pc(), Ok(pc) if pc == handler));
+    assert!(machine.last_error().is_some());
+    assert_eq!(machine.aux_stack().num_items(), frames);
+}
@@ -0,0 +1,127 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the convention by which test programs report whether they passed.
+//!
+//! A test program's last log is its outcome. The functions of `std::testing` log a tuple of
+//! `TEST_OUTCOME_TAG`, a status, and a message, which can say a test passed, failed, or was
+//! skipped. Older test programs instead log a buffer, which is empty when they pass and otherwise
+//! says what failed, or an integer, which is zero when they pass and otherwise numbers the check
+//! that failed, and these are understood as well.
+
+use crate::mavm::{Buffer, Value};
+use crate::uint256::Uint256;
+use std::fmt;
+
+/// Marks a log as reporting a test's outcome, and spells "minitest" in ascii.
+pub const TEST_OUTCOME_TAG: u64 = 0x6d696e6974657374;
+
+/// How a test program went.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TestOutcome {
+    Pass,
+    Fail(String),
+    Skip(String),
+}
+
+impl TestOutcome {
+    /// Reads the outcome a test program reported in its logs.
+    pub fn from_logs(logs: &[Value]) -> Self {
+        match logs.last() {
+            Some(log) => Self::from_log(log),
+            None => TestOutcome::Fail("the test didn't report an outcome".to_string()),
+        }
+    }
+
+    fn from_log(log: &Value) -> Self {
+        match log {
+            Value::Buffer(buffer) => match buffer.max_size() {
+                0 => TestOutcome::Pass,
+                size => TestOutcome::Fail(text(buffer, size as usize)),
+            },
+            Value::Int(code) => match code.is_zero() {
+                true => TestOutcome::Pass,
+                false => TestOutcome::Fail(format!("the test failed with code {}", code)),
+            },
+            Value::Tuple(fields) if Self::is_tagged(fields) => {
+                let message = match &fields[2] {
+                    Value::Tuple(string) if string.len() == 2 => match (&string[0], &string[1]) {
+                        (Value::Int(len), Value::Buffer(buffer)) => {
+                            len.to_usize().map(|len| text(buffer, len))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                match (&fields[1], message) {
+                    (Value::Int(status), Some(message)) => match status.to_usize() {
+                        Some(0) => TestOutcome::Pass,
+                        Some(1) => TestOutcome::Fail(message),
+                        Some(2) => TestOutcome::Skip(message),
+                        _ => TestOutcome::Fail(format!("the test reported status {}", status)),
+                    },
+                    _ => TestOutcome::Fail("the test's outcome is malformed".to_string()),
+                }
+            }
+            _ => TestOutcome::Fail(format!("the test logged {} last", log)),
+        }
+    }
+
+    fn is_tagged(fields: &[Value]) -> bool {
+        fields.len() == 3 && fields[0] == Value::Int(Uint256::from_u64(TEST_OUTCOME_TAG))
+    }
+
+    pub fn passed(&self) -> bool {
+        !matches!(self, TestOutcome::Fail(_))
+    }
+}
+
+impl fmt::Display for TestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestOutcome::Pass => write!(f, "PASS"),
+            TestOutcome::Fail(message) => write!(f, "FAIL: {}", message),
+            TestOutcome::Skip(reason) => write!(f, "SKIP: {}", reason),
+        }
+    }
+}
+
+fn text(buffer: &Buffer, len: usize) -> String {
+    String::from_utf8_lossy(&buffer.as_bytes(len)).to_string()
+}
+
+#[test]
+fn test_outcome_conventions() {
+    let string = |text: &str| {
+        Value::new_tuple(vec![
+            Value::from(text.len()),
+            Value::new_buffer(text.as_bytes().to_vec()),
+        ])
+    };
+    let tagged = |status: usize, message: &str| {
+        Value::new_tuple(vec![
+            Value::Int(Uint256::from_u64(TEST_OUTCOME_TAG)),
+            Value::from(status),
+            string(message),
+        ])
+    };
+
+    let outcome = |log: Value| TestOutcome::from_logs(&[Value::from(7), log]);
+    assert_eq!(outcome(tagged(0, "")), TestOutcome::Pass);
+    assert_eq!(outcome(tagged(1, "bad")), TestOutcome::Fail("bad".into()));
+    assert_eq!(
+        outcome(tagged(2, "later")),
+        TestOutcome::Skip("later".into())
+    );
+    assert!(!outcome(tagged(3, "")).passed());
+    assert_eq!(outcome(Value::new_buffer(vec![])), TestOutcome::Pass);
+    assert_eq!(
+        outcome(Value::new_buffer(b"wrong".to_vec())),
+        TestOutcome::Fail("wrong".into())
+    );
+    assert_eq!(outcome(Value::from(0)), TestOutcome::Pass);
+    assert!(!outcome(Value::from(4)).passed());
+    assert!(!outcome(string("untagged")).passed());
+    assert!(!TestOutcome::from_logs(&[]).passed());
+}
@@ -0,0 +1,274 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides a history of profiler and benchmark results that can be compared across revisions.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where results are stored when no other database is given.
+pub const DEFAULT_PERF_DB: &str = "perf/history.jsonl";
+
+/// The gas costs measured by a single profiler or benchmark run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PerfRecord {
+    /// Git hash of the tree the run was made from
+    pub rev: String,
+    /// Whether the tree had uncommitted changes
+    pub dirty: bool,
+    /// Seconds since the unix epoch
+    pub timestamp: u64,
+    /// What produced the results, like "profiler" or "benchmark"
+    pub kind: String,
+    /// The program or benchmark suite that was run
+    pub input: String,
+    /// The command line arguments of the run
+    pub flags: Vec<String>,
+    /// Total gas used
+    pub total: u64,
+    /// Gas used by each func, named by its module path and name, or by each benchmark
+    pub costs: BTreeMap<String, u64>,
+}
+
+impl PerfRecord {
+    /// Creates a record of the current revision and command line.
+    pub fn new(kind: &str, input: &str, total: u64, costs: BTreeMap<String, u64>) -> Self {
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(args)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+        PerfRecord {
+            rev: git(&["rev-parse", "HEAD"]).unwrap_or_else(|| "unknown".to_string()),
+            dirty: git(&["status", "--porcelain", "--untracked-files=no"])
+                .map(|changes| !changes.is_empty())
+                .unwrap_or(false),
+            timestamp,
+            kind: kind.to_string(),
+            input: input.to_string(),
+            flags: std::env::args().skip(1).collect(),
+            total,
+            costs,
+        }
+    }
+
+    /// Appends self as a line of json to the database at path, creating it if needed.
+    pub fn append_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let line = serde_json::to_string(self).map_err(io::Error::from)?;
+        writeln!(file, "{}", line)
+    }
+}
+
+/// Reads every record in the database at path, skipping lines that can't be parsed.
+pub fn load_records(path: &Path) -> io::Result<Vec<PerfRecord>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = vec![];
+    for line in reader.lines() {
+        if let Ok(record) = serde_json::from_str(&line?) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+/// Resolves a revision to the git hash it names, falling back to treating it as a hash prefix.
+fn resolve_rev(rev: &str) -> String {
+    Command::new("git")
+        .args([
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{}^{{commit}}", rev),
+        ])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| rev.to_string())
+}
+
+fn percent(old: u64, new: u64) -> String {
+    match old {
+        0 => "new".to_string(),
+        _ => format!("{:+.2}%", 100.0 * (new as f64 - old as f64) / old as f64),
+    }
+}
+
+/// Compares the latest results recorded for two revisions, writing the funcs and benchmarks whose
+/// cost changed by more than threshold percent to output.
+pub fn compare_revs(
+    records: &[PerfRecord],
+    old_rev: &str,
+    new_rev: &str,
+    threshold: f64,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    let latest = |rev: &str| {
+        let rev = resolve_rev(rev);
+        let mut latest: BTreeMap<(String, String), &PerfRecord> = BTreeMap::new();
+        for record in records.iter().filter(|record| record.rev.starts_with(&rev)) {
+            let key = (record.kind.clone(), record.input.clone());
+            match latest.get(&key) {
+                Some(prior) if prior.timestamp > record.timestamp => {}
+                _ => drop(latest.insert(key, record)),
+            }
+        }
+        latest
+    };
+
+    let old = latest(old_rev);
+    let new = latest(new_rev);
+
+    if old.is_empty() || new.is_empty() {
+        let missing = if old.is_empty() { old_rev } else { new_rev };
+        return writeln!(output, "No results recorded for {}", missing);
+    }
+
+    let mut compared = false;
+    for ((kind, input), new_record) in &new {
+        let old_record = match old.get(&(kind.clone(), input.clone())) {
+            Some(record) => record,
+            None => continue,
+        };
+        compared = true;
+
+        compare_costs(
+            &format!("{} {}", kind, input),
+            (old_record.total, &old_record.costs),
+            (new_record.total, &new_record.costs),
+            threshold,
+            output,
+        )?;
+    }
+
+    if !compared {
+        writeln!(
+            output,
+            "No runs of the same program were recorded for both {} and {}",
+            old_rev, new_rev
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes how the total cost under heading changed, followed by the costs that changed by more
+/// than threshold percent, largest changes first. Each side is a total and the costs making it up,
+/// keyed by name.
+pub fn compare_costs(
+    heading: &str,
+    old: (u64, &BTreeMap<String, u64>),
+    new: (u64, &BTreeMap<String, u64>),
+    threshold: f64,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    writeln!(
+        output,
+        "{}: {} -> {} ({})",
+        heading,
+        old.0,
+        new.0,
+        percent(old.0, new.0)
+    )?;
+
+    let mut changes = vec![];
+    let names = old.1.keys().chain(new.1.keys());
+    for name in names.collect::<std::collections::BTreeSet<_>>() {
+        let before = old.1.get(name).cloned().unwrap_or(0);
+        let after = new.1.get(name).cloned().unwrap_or(0);
+        let delta = after as i128 - before as i128;
+        let significant = match before {
+            0 => delta != 0,
+            _ => 100.0 * (delta.abs() as f64) / before as f64 > threshold,
+        };
+        if delta != 0 && significant {
+            changes.push((delta, name, before, after));
+        }
+    }
+
+    changes.sort_by(|a, b| b.0.abs().cmp(&a.0.abs()).then(a.1.cmp(b.1)));
+    let (regressions, improvements): (Vec<_>, Vec<_>) =
+        changes.into_iter().partition(|change| change.0 > 0);
+
+    for (title, changes) in [("Regressions", regressions), ("Improvements", improvements)] {
+        if changes.is_empty() {
+            continue;
+        }
+        writeln!(output, "  {}:", title)?;
+        for (delta, name, before, after) in changes {
+            writeln!(
+                output,
+                "    {}: {} -> {} ({:+}, {})",
+                name,
+                before,
+                after,
+                delta,
+                percent(before, after)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_perf_compare() {
+    let record = |rev: &str, timestamp, costs: Vec<(&str, u64)>| PerfRecord {
+        rev: rev.to_string(),
+        dirty: false,
+        timestamp,
+        kind: "profiler".to_string(),
+        input: "arbos.mexe".to_string(),
+        flags: vec![],
+        total: costs.iter().map(|cost| cost.1).sum(),
+        costs: costs
+            .into_iter()
+            .map(|(name, gas)| (name.to_string(), gas))
+            .collect(),
+    };
+
+    let records = vec![
+        record("aaaa1111", 1, vec![("inbox::read", 500), ("gas::pay", 100)]),
+        record("aaaa1111", 2, vec![("inbox::read", 400), ("gas::pay", 100)]),
+        record(
+            "bbbb2222",
+            3,
+            vec![("inbox::read", 300), ("gas::pay", 150), ("new::f", 7)],
+        ),
+    ];
+
+    let mut output = vec![];
+    compare_revs(&records, "aaaa1111", "bbbb2222", 0.0, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("profiler arbos.mexe: 500 -> 457 (-8.60%)"));
+    assert!(output.contains("Regressions:\n    gas::pay: 100 -> 150 (+50, +50.00%)"));
+    assert!(output.contains("    new::f: 0 -> 7 (+7, new)"));
+    assert!(output.contains("Improvements:\n    inbox::read: 400 -> 300 (-100, -25.00%)"));
+
+    let mut output = vec![];
+    compare_revs(&records, "aaaa1111", "bbbb2222", 30.0, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(!output.contains("Improvements"));
+
+    let mut output = vec![];
+    compare_revs(&records, "aaaa1111", "cccc3333", 0.0, &mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "No results recorded for cccc3333\n"
+    );
+}
@@ -0,0 +1,226 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides the policies bounding how many of its logs and sends a runtime environment keeps in
+//! memory, so that soak tests running ArbOS for days don't exhaust the host. What an environment
+//! drops can be spilled to a file, from which it can be read back later.
+
+use crate::mavm::Value;
+use crate::uint256::Uint256;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// How many logs and sends an environment keeps. Unless limited, it keeps all of them.
+#[derive(Clone, Debug, Default)]
+pub struct RetentionPolicy {
+    /// How many of the most recent logs are kept, and how many of the most recent sends
+    pub max_items: Option<usize>,
+    /// How many of the most recent blocks the logs and sends of are kept, along with those of the
+    /// block being built
+    pub max_blocks: Option<usize>,
+    /// A file the logs and sends dropped are appended to, so that they're out of memory but not
+    /// lost
+    pub spill: Option<PathBuf>,
+    /// Keeps the recorder from keeping copies of the logs and sends, which it otherwise does
+    /// whatever the policy, as test logs need all of them
+    pub unrecorded: bool,
+}
+
+/// A log or send dropped from memory, as written to a spill file, one to a line.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Spilled {
+    Log(Value),
+    Send(Vec<u8>),
+}
+
+/// Reads back the logs and sends spilled to the file at path, in the order they were dropped.
+pub fn _read_spill(path: &Path) -> io::Result<Vec<Spilled>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .map(|line| {
+            serde_json::from_str(&line?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+        .collect()
+}
+
+/// Keeps the logs and sends of an environment within its policy. Clones of an environment share
+/// its spill file.
+#[derive(Clone, Debug)]
+pub(super) struct Retention {
+    policy: RetentionPolicy,
+    /// How many of the logs and sends kept belong to each block, oldest first, with those of the
+    /// block being built last
+    blocks: VecDeque<(usize, usize)>,
+    spill: Option<SpillFile>,
+}
+
+#[derive(Clone)]
+struct SpillFile(Arc<Mutex<BufWriter<File>>>);
+
+impl fmt::Debug for SpillFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "spill file")
+    }
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            policy: RetentionPolicy::default(),
+            blocks: VecDeque::from(vec![(0, 0)]),
+            spill: None,
+        }
+    }
+}
+
+impl Retention {
+    /// Switches to policy, opening its spill file for appending if it has one.
+    pub(super) fn set_policy(&mut self, policy: RetentionPolicy) -> io::Result<()> {
+        self.spill = match &policy.spill {
+            Some(path) => {
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                Some(SpillFile(Arc::new(Mutex::new(BufWriter::new(file)))))
+            }
+            None => None,
+        };
+        self.policy = policy;
+        Ok(())
+    }
+
+    pub(super) fn recording(&self) -> bool {
+        !self.policy.unrecorded
+    }
+
+    /// Notes a log just kept, which ends the block being built if it's the block's summary.
+    pub(super) fn log_kept(&mut self, log: &Value) {
+        self.blocks.back_mut().unwrap().0 += 1;
+        let summary = match log {
+            Value::Tuple(fields) => fields.first() == Some(&Value::Int(Uint256::one())),
+            _ => false,
+        };
+        if summary {
+            self.blocks.push_back((0, 0));
+        }
+    }
+
+    pub(super) fn send_kept(&mut self) {
+        self.blocks.back_mut().unwrap().1 += 1;
+    }
+
+    /// Drops the oldest logs and sends until those left are within the policy.
+    pub(super) fn trim(&mut self, logs: &mut VecDeque<Value>, sends: &mut VecDeque<Vec<u8>>) {
+        if let Some(capacity) = self.policy.max_items {
+            while logs.len() > capacity {
+                self.drop_log(logs);
+            }
+            while sends.len() > capacity {
+                self.drop_send(sends);
+            }
+        }
+        if let Some(max_blocks) = self.policy.max_blocks {
+            while self.blocks.len() > max_blocks + 1 {
+                let (block_logs, block_sends) = self.blocks.pop_front().unwrap();
+                for log in logs.drain(..block_logs.min(logs.len())) {
+                    self.spill(Spilled::Log(log));
+                }
+                for send in sends.drain(..block_sends.min(sends.len())) {
+                    self.spill(Spilled::Send(send));
+                }
+            }
+        }
+        if let Some(spill) = &self.spill {
+            // a spill that can't be written loses what's dropped, but the run goes on
+            drop(spill.0.lock().unwrap().flush());
+        }
+    }
+
+    fn drop_log(&mut self, logs: &mut VecDeque<Value>) {
+        if let Some(log) = logs.pop_front() {
+            if let Some(block) = self.blocks.iter_mut().find(|block| block.0 > 0) {
+                block.0 -= 1;
+            }
+            self.spill(Spilled::Log(log));
+        }
+        self.forget_empty_blocks();
+    }
+
+    fn drop_send(&mut self, sends: &mut VecDeque<Vec<u8>>) {
+        if let Some(send) = sends.pop_front() {
+            if let Some(block) = self.blocks.iter_mut().find(|block| block.1 > 0) {
+                block.1 -= 1;
+            }
+            self.spill(Spilled::Send(send));
+        }
+        self.forget_empty_blocks();
+    }
+
+    /// Stops counting the oldest blocks once nothing of theirs is kept.
+    fn forget_empty_blocks(&mut self) {
+        while self.blocks.len() > 1 && self.blocks[0] == (0, 0) {
+            self.blocks.pop_front();
+        }
+    }
+
+    fn spill(&self, item: Spilled) {
+        if let Some(spill) = &self.spill {
+            let mut file = spill.0.lock().unwrap();
+            if let Ok(line) = serde_json::to_string(&item) {
+                drop(writeln!(file, "{}", line));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_retention_policy() {
+    use crate::mavm::Buffer;
+    use crate::run::RuntimeEnvironment;
+
+    let summary = |block: u64| Value::new_tuple(vec![Value::from(1), Value::from(block)]);
+    let receipt = |n: u64| Value::new_tuple(vec![Value::from(0), Value::from(n)]);
+    let path = std::env::temp_dir().join(format!("test-{}.spill", std::process::id()));
+    drop(std::fs::remove_file(&path));
+
+    let mut env = RuntimeEnvironment::default();
+    env.set_retention_policy(RetentionPolicy {
+        max_blocks: Some(2),
+        spill: Some(path.clone()),
+        unrecorded: true,
+        ..RetentionPolicy::default()
+    })
+    .unwrap();
+    let mut logs = vec![];
+    for block in 0..4 {
+        logs.push(receipt(block));
+        env.push_log(receipt(block));
+        env.push_send(Uint256::from_u64(1), Buffer::from_bytes(vec![block as u8]));
+        logs.push(summary(block));
+        env.push_log(summary(block));
+    }
+    logs.push(receipt(4));
+    env.push_log(receipt(4));
+
+    // the last two blocks are kept along with the one being built, and the rest spilled
+    assert_eq!(env.get_all_raw_logs(), logs[4..].to_vec());
+    assert_eq!(env.sends, vec![vec![2], vec![3]]);
+    assert!(env.recorder.logs().is_empty());
+    assert_eq!(
+        _read_spill(&path).unwrap(),
+        vec![
+            Spilled::Log(receipt(0)),
+            Spilled::Log(summary(0)),
+            Spilled::Send(vec![0]),
+            Spilled::Log(receipt(1)),
+            Spilled::Log(summary(1)),
+            Spilled::Send(vec![1]),
+        ]
+    );
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -2,33 +2,59 @@
  * Copyright 2020, Offchain Labs, Inc. All rights reserved.
  */
 
+use crate::evm::Tx;
 use crate::mavm::{Buffer, Value};
-use crate::run::{load_from_file_and_env, ProfilerMode};
+use crate::run::logmatch::{match_logs, LogPattern};
+use crate::run::retention::{Retention, RetentionPolicy};
+use crate::run::{compare_costs, load_from_file_and_env, Inbox, Machine, ProfilerMode};
 use crate::uint256::Uint256;
 use ethers_core::rand::rngs::StdRng;
 use ethers_core::rand::SeedableRng;
-use ethers_core::types::TransactionRequest;
 use ethers_core::utils::keccak256;
-use ethers_signers::{Signer, Wallet};
+use ethers_signers::Wallet;
 use serde::{Deserialize, Serialize};
-use std::convert::TryInto;
-use std::io::Read;
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
 use std::{collections::HashMap, fs::File, io, path::Path};
 
+type LogCallback = dyn FnMut(&Value) + Send;
+type SendCallback = dyn FnMut(&[u8]) + Send;
+
+/// Callbacks run on each log or send as it's emitted. Clones of an environment share them.
+struct Subscribers<F: ?Sized>(Vec<Arc<Mutex<F>>>);
+
+impl<F: ?Sized> Clone for Subscribers<F> {
+    fn clone(&self) -> Self {
+        Subscribers(self.0.clone())
+    }
+}
+
+impl<F: ?Sized> fmt::Debug for Subscribers<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} subscribers", self.0.len())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RuntimeEnvironment {
     pub chain_id: u64,
-    pub l1_inbox: Vec<Value>,
+    pub l1_inbox: Inbox,
     pub current_block_num: Uint256,
     pub current_timestamp: Uint256,
-    pub logs: Vec<Value>,
-    pub sends: Vec<Vec<u8>>,
+    /// The most recent logs, as many as the retention policy keeps
+    pub logs: VecDeque<Value>,
+    /// The most recent sends, as many as the retention policy keeps
+    pub sends: VecDeque<Vec<u8>>,
+    retention: Retention,
+    log_subscribers: Subscribers<LogCallback>,
+    send_subscribers: Subscribers<SendCallback>,
     pub next_inbox_seq_num: Uint256,
     pub caller_seq_nums: HashMap<Uint256, Uint256>,
     next_id: Uint256, // used to assign unique (but artificial) txids to messages
     pub recorder: RtEnvRecorder,
-    compressor: TxCompressor,
+    pub compressor: TxCompressor,
     charging_policy: Option<(Uint256, Uint256, Uint256)>,
     num_wallets: u64,
     chain_init_message: Vec<u8>,
@@ -63,20 +89,46 @@ impl RuntimeEnvironment {
         )
     }
 
+    /// Makes an environment whose chain has sequencer as its default aggregator, the only poster
+    /// whose batches may end L2 blocks.
+    pub fn _new_with_sequencer(sequencer: Uint256) -> Self {
+        RuntimeEnvironment::new_with_params(
+            Uint256::from_u64(100_000),
+            Uint256::from_u64(10_000_000),
+            None,
+            None,
+            &[("DefaultAggregator", sequencer)],
+        )
+    }
+
     pub fn new_with_blocknum_timestamp(
         blocknum: Uint256,
         timestamp: Uint256,
         charging_policy: Option<(Uint256, Uint256, Uint256)>,
         owner: Option<Uint256>,
+    ) -> Self {
+        RuntimeEnvironment::new_with_params(blocknum, timestamp, charging_policy, owner, &[])
+    }
+
+    /// Makes an environment whose chain init message sets params, besides the owner and chain id.
+    fn new_with_params(
+        blocknum: Uint256,
+        timestamp: Uint256,
+        charging_policy: Option<(Uint256, Uint256, Uint256)>,
+        owner: Option<Uint256>,
+        params: &[(&str, Uint256)],
     ) -> Self {
         let chain_id = 42161;
         let mut ret = RuntimeEnvironment {
             chain_id,
-            l1_inbox: vec![],
+            l1_inbox: Inbox::default(),
             current_block_num: blocknum,
             current_timestamp: timestamp,
-            logs: Vec::new(),
-            sends: Vec::new(),
+            logs: VecDeque::new(),
+            sends: VecDeque::new(),
+            retention: Retention::default(),
+            log_subscribers: Subscribers(vec![]),
+            send_subscribers: Subscribers(vec![]),
             next_inbox_seq_num: Uint256::zero(),
             caller_seq_nums: HashMap::new(),
             next_id: Uint256::zero(),
@@ -84,7 +136,7 @@ impl RuntimeEnvironment {
             compressor: TxCompressor::new(),
             charging_policy: charging_policy.clone(),
             num_wallets: 0,
-            chain_init_message: RuntimeEnvironment::get_params_bytes(owner, chain_id),
+            chain_init_message: RuntimeEnvironment::get_params_bytes(owner, chain_id, params),
             force_zero_gas_price: false,
         };
 
@@ -102,9 +154,13 @@ impl RuntimeEnvironment {
         );
     }
 
-    fn get_params_bytes(owner: Option<Uint256>, chain_id: u64) -> Vec<u8> {
+    fn get_params_bytes(
+        owner: Option<Uint256>,
+        chain_id: u64,
+        params: &[(&str, Uint256)],
+    ) -> Vec<u8> {
         let mut buf = Vec::new();
-        let params_to_set = if let Some(the_owner) = owner {
+        let mut params_to_set = if let Some(the_owner) = owner {
             vec![
                 ("ChainOwner", the_owner),
                 ("ChainID", Uint256::from_u64(chain_id)),
@@ -112,6 +168,7 @@ impl RuntimeEnvironment {
         } else {
             vec![("ChainID", Uint256::from_u64(chain_id))]
         };
+        params_to_set.extend(params.iter().cloned());
         for (name, val) in params_to_set {
             buf.extend(&keccak256(name.as_bytes()));
             buf.extend(val.to_bytes_be());
@@ -145,8 +202,26 @@ impl RuntimeEnvironment {
         self.chain_id
     }
 
+    /// Replaces the unread messages of the inbox.
     pub fn insert_full_inbox_contents(&mut self, contents: Vec<Value>) {
-        self.l1_inbox = contents;
+        self.l1_inbox.replace(contents);
+    }
+
+    /// Marks the messages delivered after the first keep as reorged away on L1, returning them.
+    /// Those still unread are dropped from the inbox, and the sequence numbers they took are
+    /// handed out again to whatever's delivered in their place. A machine that's read any of them
+    /// has to be rerun on what's left from the start, as a node rolls back to a checkpoint before
+    /// the reorg, which `self.recorder.load_machine()` does.
+    #[cfg(test)]
+    pub fn _reorg_inbox(&mut self, keep: usize) -> Vec<Value> {
+        let history = &mut self.recorder.inbox;
+        let read = history.len().saturating_sub(self.l1_inbox.len());
+        let removed = history.split_off(keep.min(history.len()));
+        self.l1_inbox.truncate(keep.saturating_sub(read));
+        if let Some(seq_num) = removed.first().and_then(super::inbox::seq_num) {
+            self.next_inbox_seq_num = seq_num.clone();
+        }
+        removed
     }
 
     pub fn insert_l1_message(
@@ -263,85 +338,6 @@ impl RuntimeEnvironment {
         }
     }
 
-    pub fn insert_tx_message(
-        &mut self,
-        sender_addr: Uint256,
-        max_gas: Uint256,
-        gas_price_bid: Option<Uint256>,
-        to_addr: Uint256,
-        value: Uint256,
-        data: &[u8],
-        with_deposit: bool,
-    ) -> Uint256 {
-        let mut buf = vec![0u8];
-        let seq_num = self.get_seq_num(&sender_addr.clone(), true);
-        buf.extend(max_gas.to_bytes_be());
-        buf.extend(gas_price_bid.unwrap_or(self.get_gas_price()).to_bytes_be());
-        buf.extend(seq_num.to_bytes_be());
-        buf.extend(to_addr.to_bytes_be());
-        buf.extend(value.to_bytes_be());
-        buf.extend_from_slice(data);
-
-        if with_deposit {
-            self.insert_l2_message_with_deposit(sender_addr.clone(), &buf)
-        } else {
-            self.insert_l2_message(sender_addr.clone(), &buf)
-        }
-    }
-
-    #[cfg(test)]
-    pub fn insert_gas_estimation_message(
-        &mut self,
-        max_gas: Uint256,
-        gas_price_bid: Option<Uint256>,
-        to_addr: Uint256,
-        value: Uint256,
-        data: &[u8],
-        aggregator: Uint256,
-        wallet: &Wallet,
-    ) {
-        let mut buf = vec![3u8];
-        buf.extend(aggregator.to_bytes_be());
-        buf.extend(max_gas.clone().to_bytes_be());
-
-        let _ = self._append_compressed_and_signed_tx_message_to_batch(
-            &mut buf,
-            max_gas,
-            gas_price_bid,
-            to_addr,
-            value,
-            Vec::from(data),
-            wallet,
-            true,
-        );
-
-        self.insert_l2_message_for_gas_estimation(aggregator, &buf)
-    }
-
-    pub fn insert_tx_message_from_contract(
-        &mut self,
-        sender_addr: Uint256,
-        max_gas: Uint256,
-        gas_price_bid: Option<Uint256>,
-        to_addr: Uint256,
-        value: Uint256,
-        data: &[u8],
-        with_deposit: bool,
-    ) -> Uint256 {
-        let mut buf = vec![1u8];
-        buf.extend(max_gas.to_bytes_be());
-        buf.extend(gas_price_bid.unwrap_or(self.get_gas_price()).to_bytes_be());
-        buf.extend(to_addr.to_bytes_be());
-        buf.extend(value.to_bytes_be());
-        buf.extend_from_slice(data);
-
-        if with_deposit {
-            self.insert_l2_message_with_deposit(sender_addr.clone(), &buf)
-        } else {
-            self.insert_l2_message(sender_addr.clone(), &buf)
-        }
-    }
-
     pub fn new_batch(&self) -> Vec<u8> {
         vec![3u8]
     }
@@ -371,44 +367,6 @@ impl RuntimeEnvironment {
         ret
     }
 
-    pub fn make_compressed_and_signed_l2_message(
-        &mut self,
-        gas_price: Option<Uint256>,
-        gas_limit: Uint256,
-        to_addr: Uint256,
-        value: Uint256,
-        calldata: &[u8],
-        wallet: &Wallet,
-        is_gas_estimation: bool,
-    ) -> (Vec<u8>, Vec<u8>) {
-        let sender = Uint256::from_bytes(wallet.address().as_bytes());
-        let mut result = vec![7u8, 0xffu8];
-        let seq_num = self.get_seq_num(&sender, !is_gas_estimation);
-        let gas_price = gas_price.unwrap_or(self.get_gas_price());
-        result.extend(seq_num.rlp_encode());
-        result.extend(gas_price.rlp_encode());
-        result.extend(gas_limit.rlp_encode());
-        result.extend(self.compressor.compress_address(to_addr.clone()));
-        result.extend(self.compressor.compress_token_amount(value.clone()));
-        result.extend(calldata);
-
-        let tx_for_signing = TransactionRequest::new()
-            .from(sender.to_h160())
-            .to(to_addr.to_h160())
-            .gas(gas_limit.to_u256())
-            .gas_price(gas_price.to_u256())
-            .value(value.to_u256())
-            .data(calldata.to_vec())
-            .nonce(seq_num.to_u256());
-        let tx = wallet.sign_transaction(tx_for_signing).unwrap();
-
-        result.extend(Uint256::from_u256(&tx.r).to_bytes_be());
-        result.extend(Uint256::from_u256(&tx.s).to_bytes_be());
-        result.extend(vec![(tx.v.as_u64() % 2) as u8]);
-
-        (result, keccak256(tx.rlp().as_ref()).to_vec())
-    }
-
     /*
     pub fn _make_compressed_tx_for_bls(
         &mut self,
@@ -472,33 +430,6 @@ impl RuntimeEnvironment {
         self.insert_l2_message(batch_sender.clone(), &buf);
     }
 
-    pub fn _append_compressed_and_signed_tx_message_to_batch(
-        &mut self,
-        batch: &mut Vec<u8>,
-        max_gas: Uint256,
-        gas_price_bid: Option<Uint256>,
-        to_addr: Uint256,
-        value: Uint256,
-        calldata: Vec<u8>,
-        wallet: &Wallet,
-        is_gas_estimation: bool,
-    ) -> Vec<u8> {
-        let (msg, tx_id_bytes) = self.make_compressed_and_signed_l2_message(
-            gas_price_bid,
-            max_gas,
-            to_addr,
-            value,
-            &calldata,
-            wallet,
-            is_gas_estimation,
-        );
-        let msg_size: u64 = msg.len().try_into().unwrap();
-        let rlp_encoded_len = Uint256::from_u64(msg_size).rlp_encode();
-        batch.extend(rlp_encoded_len.clone());
-        batch.extend(msg);
-        tx_id_bytes
-    }
-
     pub fn insert_batch_message(&mut self, sender_addr: Uint256, batch: &[u8]) {
         self.insert_l2_message(sender_addr, batch);
     }
@@ -526,23 +457,16 @@ impl RuntimeEnvironment {
         amount: Uint256,
         adjust_payee_address: bool,
     ) {
-        self.insert_tx_message_from_contract(
-            sender_addr,
-            Uint256::from_u64(100_000_000),
-            None,
-            {
-                let x = if adjust_payee_address {
-                    remap_l1_sender_address(payee)
-                } else {
-                    payee
-                };
-                println!("eth deposit to {}", x);
-                x
-            },
-            amount,
-            &[],
-            true,
-        );
+        let payee = if adjust_payee_address {
+            remap_l1_sender_address(payee)
+        } else {
+            payee
+        };
+        println!("eth deposit to {}", payee);
+        Tx::call(payee)
+            .value(amount)
+            .with_deposit()
+            .send_from_contract(self, sender_addr);
     }
 
     pub fn get_seq_num(&mut self, addr: &Uint256, do_increment: bool) -> Uint256 {
@@ -557,29 +481,45 @@ impl RuntimeEnvironment {
         cur_seq_num
     }
 
-    pub fn get_from_inbox(&mut self) -> Option<Value> {
-        if self.l1_inbox.is_empty() {
-            None
-        } else {
-            Some(self.l1_inbox.remove(0))
-        }
+    /// Runs callback on each log as it's emitted.
+    pub fn on_log(&mut self, callback: impl FnMut(&Value) + Send + 'static) {
+        self.log_subscribers.0.push(Arc::new(Mutex::new(callback)));
     }
 
-    pub fn peek_at_inbox_head(&mut self) -> Option<Value> {
-        if self.l1_inbox.is_empty() {
-            None
-        } else {
-            Some(self.l1_inbox[0].clone())
-        }
+    /// Runs callback on the contents of each send as it's emitted.
+    pub fn on_send(&mut self, callback: impl FnMut(&[u8]) + Send + 'static) {
+        self.send_subscribers.0.push(Arc::new(Mutex::new(callback)));
+    }
+
+    /// Keeps only the logs and sends policy allows, such as the most recent few or those of the
+    /// last few blocks, spilling the rest to its file if it has one. Long runs should subscribe to
+    /// what they need instead of relying on what's kept. The recorder keeps its own copies, since
+    /// test logs need all of them. Fails if the spill file can't be opened, in which case the
+    /// policy is left as it was.
+    pub fn set_retention_policy(&mut self, policy: RetentionPolicy) -> io::Result<()> {
+        self.retention.set_policy(policy)?;
+        self.trim();
+        Ok(())
+    }
+
+    fn trim(&mut self) {
+        self.retention.trim(&mut self.logs, &mut self.sends);
     }
 
     pub fn push_log(&mut self, log_item: Value) {
-        self.logs.push(log_item.clone());
-        self.recorder.add_log(log_item);
+        for subscriber in &self.log_subscribers.0 {
+            (subscriber.lock().unwrap())(&log_item);
+        }
+        self.retention.log_kept(&log_item);
+        self.logs.push_back(log_item.clone());
+        self.trim();
+        if self.retention.recording() {
+            self.recorder.add_log(log_item);
+        }
     }
 
     pub fn get_all_raw_logs(&self) -> Vec<Value> {
-        self.logs.clone()
+        self.logs.iter().cloned().collect()
     }
 
     pub fn get_all_receipt_logs(&self) -> Vec<ArbosReceipt> {
@@ -603,9 +543,20 @@ impl RuntimeEnvironment {
     }
 
     pub fn push_send(&mut self, size: Uint256, buf: Buffer) {
-        let contents = buf.as_bytes(size.to_usize().unwrap());
-        self.sends.push(contents.clone());
-        self.recorder.add_send(contents);
+        self.push_send_contents(buf.as_bytes(size.to_usize().unwrap()));
+    }
+
+    /// Emits a send with the given contents.
+    pub fn push_send_contents(&mut self, contents: Vec<u8>) {
+        for subscriber in &self.send_subscribers.0 {
+            (subscriber.lock().unwrap())(&contents);
+        }
+        self.retention.send_kept();
+        self.sends.push_back(contents.clone());
+        self.trim();
+        if self.retention.recording() {
+            self.recorder.add_send(contents);
+        }
     }
 
     pub fn get_all_sends(&self) -> Vec<Vec<u8>> {
@@ -1244,6 +1195,41 @@ fn test_hash_bytestack() {
     );
 }
 
+#[test]
+fn test_log_subscribers_and_retention() {
+    let mut env = RuntimeEnvironment::default();
+    let seen = Arc::new(Mutex::new(vec![]));
+    let sent = Arc::new(Mutex::new(vec![]));
+    let seen_by_callback = seen.clone();
+    let sent_by_callback = sent.clone();
+    env.on_log(move |log| seen_by_callback.lock().unwrap().push(log.clone()));
+    env.on_send(move |send| sent_by_callback.lock().unwrap().push(send.to_vec()));
+    env.set_retention_policy(RetentionPolicy {
+        max_items: Some(2),
+        ..RetentionPolicy::default()
+    })
+    .unwrap();
+
+    for i in 0..5 {
+        env.push_log(Value::from(i));
+    }
+    env.push_send(Uint256::from_u64(2), Buffer::from_bytes(vec![7, 8]));
+
+    // subscribers see everything, while only the most recent are kept
+    let all: Vec<_> = (0..5).map(Value::from).collect();
+    assert_eq!(*seen.lock().unwrap(), all);
+    assert_eq!(env.get_all_raw_logs(), all[3..].to_vec());
+    assert_eq!(*sent.lock().unwrap(), vec![vec![7, 8]]);
+    assert_eq!(env.sends, vec![vec![7, 8]]);
+
+    env.set_retention_policy(RetentionPolicy {
+        max_items: Some(1),
+        ..RetentionPolicy::default()
+    })
+    .unwrap();
+    assert_eq!(env.get_all_raw_logs(), all[4..].to_vec());
+}
+
 pub fn _bytes_from_bytestack(bs: Value) -> Option<Vec<u8>> {
     if let Value::Tuple(tup) = bs {
         if let Value::Int(ui) = &tup[0] {
@@ -1313,6 +1299,10 @@ pub struct RtEnvRecorder {
     logs: Vec<Value>,
     sends: Vec<Vec<u8>>,
     total_gas: u64,
+    /// Patterns the logs of a replay are matched against in place of the logs recorded, so that
+    /// gas and other fields that may legitimately vary can be allowed for field by field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expected_logs: Option<Vec<LogPattern>>,
 }
 
 impl RtEnvRecorder {
@@ -1323,6 +1313,7 @@ impl RtEnvRecorder {
             logs: Vec::new(),
             sends: Vec::new(),
             total_gas: 0,
+            expected_logs: None,
         }
     }
 
@@ -1330,6 +1321,31 @@ impl RtEnvRecorder {
         self.inbox.push(msg);
     }
 
+    /// The logs recorded so far, in order.
+    pub fn logs(&self) -> &[Value] {
+        &self.logs
+    }
+
+    /// The sends recorded so far, in order.
+    pub fn sends(&self) -> &[Vec<u8>] {
+        &self.sends
+    }
+
+    /// The messages recorded as delivered, in order.
+    pub fn messages(&self) -> &[Value] {
+        &self.inbox
+    }
+
+    /// A log of only the first count messages, for a run stopping after them. What the run of
+    /// every message logged and sent is left out, as most of it belongs to the messages dropped.
+    pub fn truncated(&self, count: usize) -> Self {
+        RtEnvRecorder {
+            format_version: self.format_version,
+            inbox: self.inbox.iter().take(count).cloned().collect(),
+            ..RtEnvRecorder::new()
+        }
+    }
+
     fn add_log(&mut self, log_item: Value) {
         self.logs.push(log_item);
     }
@@ -1348,21 +1364,50 @@ impl RtEnvRecorder {
         writeln!(file, "{}", self.to_json_string()?)
     }
 
+    /// Reads a test log, as written by `to_file`.
+    pub fn from_file(filename: &str) -> io::Result<Self> {
+        let mut file = File::open(filename)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        // need to be tricky about how we deserialize, to work around serde_json's recursion limit
+        let mut deserializer = serde_json::Deserializer::from_str(&contents);
+        deserializer.disable_recursion_limit();
+        let deserializer = serde_stacker::Deserializer::new(&mut deserializer);
+        let json_value = serde_json::Value::deserialize(deserializer)?;
+        Ok(serde_json::from_value(json_value)?)
+    }
+
+    /// Loads ArbOS with the recorded inbox, ready to replay it.
+    pub fn load_machine(&self) -> Machine {
+        self.load_machine_from(Path::new("arb_os/arbos.mexe"))
+    }
+
+    /// Loads the build of ArbOS at mexe with the recorded inbox, ready to replay it.
+    pub fn load_machine_from(&self, mexe: &Path) -> Machine {
+        let mut rt_env = RuntimeEnvironment::default();
+        rt_env.insert_full_inbox_contents(self.inbox.clone());
+        load_from_file_and_env(mexe, rt_env)
+    }
+
     pub fn replay_and_compare(
         &self,
         require_same_gas: bool,
         debug: bool,
         profiler_mode: ProfilerMode,
         trace_file: Option<&str>,
+        trace_addresses: &[Uint256],
+        coverage_filename: Option<String>,
     ) -> bool {
         // returns true iff result matches
-        let mut rt_env = RuntimeEnvironment::default();
-        rt_env.insert_full_inbox_contents(self.inbox.clone());
-        let mut machine = load_from_file_and_env(Path::new("arb_os/arbos.mexe"), rt_env);
+        let mut machine = self.load_machine();
         if let Some(trace_file_name) = trace_file {
             machine.add_trace_writer(trace_file_name);
+            if !trace_addresses.is_empty() {
+                machine.filter_trace(trace_addresses.iter().cloned().collect());
+            }
         }
-        machine.start_at_zero(false);
+        machine.start_at_zero(coverage_filename.is_some());
         if debug {
             let _ = machine.debug(None);
         } else if (profiler_mode != ProfilerMode::Never) {
@@ -1371,30 +1416,44 @@ impl RtEnvRecorder {
         } else {
             let _ = machine.run(None);
         }
-        let logs_expected = if require_same_gas {
-            self.logs.clone()
-        } else {
-            self.logs
-                .clone()
-                .into_iter()
-                .filter_map(strip_var_from_log)
-                .collect()
-        };
-        let logs_seen = if require_same_gas {
-            machine.runtime_env.recorder.logs.clone()
+        if let Some(name) = coverage_filename {
+            machine.write_coverage(name);
+        }
+        if let Some(patterns) = &self.expected_logs {
+            if let Err(why) = match_logs(patterns, &machine.runtime_env.recorder.logs) {
+                println!("log mismatch: {}", why);
+                return false;
+            }
         } else {
-            machine
-                .runtime_env
-                .recorder
-                .logs
-                .clone()
-                .into_iter()
-                .filter_map(strip_var_from_log)
-                .collect()
-        };
-        if !(logs_expected == logs_seen) {
-            print_output_differences("log", machine.runtime_env.recorder.logs, self.logs.clone());
-            return false;
+            let logs_expected = if require_same_gas {
+                self.logs.clone()
+            } else {
+                self.logs
+                    .clone()
+                    .into_iter()
+                    .filter_map(strip_var_from_log)
+                    .collect()
+            };
+            let logs_seen = if require_same_gas {
+                machine.runtime_env.recorder.logs.clone()
+            } else {
+                machine
+                    .runtime_env
+                    .recorder
+                    .logs
+                    .clone()
+                    .into_iter()
+                    .filter_map(strip_var_from_log)
+                    .collect()
+            };
+            if !(logs_expected == logs_seen) {
+                print_output_differences(
+                    "log",
+                    machine.runtime_env.recorder.logs,
+                    self.logs.clone(),
+                );
+                return false;
+            }
         }
         if !(self.sends == machine.runtime_env.recorder.sends) {
             print_output_differences_bytevec(
@@ -1515,27 +1574,48 @@ pub fn replay_from_testlog_file(
     debug: bool,
     profiler_mode: ProfilerMode,
     trace_file: Option<&str>,
+    trace_addresses: &[Uint256],
+    coverage_filename: Option<String>,
 ) -> std::io::Result<bool> {
-    let mut file = File::open(filename)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-
-    // need to be tricky about how we deserialize, to work around serde_json's recursion limit
-    let mut deserializer = serde_json::Deserializer::from_str(&contents);
-    deserializer.disable_recursion_limit();
-    let deserializer = serde_stacker::Deserializer::new(&mut deserializer);
-    let json_value = serde_json::Value::deserialize(deserializer).unwrap();
-    let res: Result<RtEnvRecorder, serde_json::error::Error> = serde_json::from_value(json_value);
-
-    match res {
-        Ok(recorder) => {
-            let success =
-                recorder.replay_and_compare(require_same_gas, debug, profiler_mode, trace_file);
-            println!("{}", if success { "success" } else { "mismatch " });
-            Ok(success)
-        }
-        Err(e) => panic!("json parsing failed: {}", e),
-    }
+    let recorder = RtEnvRecorder::from_file(filename)?;
+    let success = recorder.replay_and_compare(
+        require_same_gas,
+        debug,
+        profiler_mode,
+        trace_file,
+        trace_addresses,
+        coverage_filename,
+    );
+    println!("{}", if success { "success" } else { "mismatch " });
+    Ok(success)
+}
+
+/// Replays the testlog in filename on two builds of ArbOS, writing how the gas used within each
+/// func changed from the old build to the new one, largest changes first. Funcs are matched up by
+/// the names in each build's symbol table, so moving one within its file doesn't show as a change.
+pub fn gas_diff_from_testlog_file(
+    filename: &str,
+    old: &Path,
+    new: &Path,
+    threshold: f64,
+    output: &mut dyn Write,
+) -> std::io::Result<()> {
+    let recorder = RtEnvRecorder::from_file(filename)?;
+    let profile = |mexe: &Path| {
+        let mut machine = recorder.load_machine_from(mexe);
+        machine.start_at_zero(false);
+        let profile = machine.profile_gen(vec![], ProfilerMode::Always);
+        (profile.total_gas(), profile.func_costs())
+    };
+    let (old_total, old_costs) = profile(old);
+    let (new_total, new_costs) = profile(new);
+    compare_costs(
+        filename,
+        (old_total, &old_costs),
+        (new_total, &new_costs),
+        threshold,
+        output,
+    )
 }
 
 #[test]
@@ -0,0 +1,230 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides scenario scripts, which replay a test log a message at a time and assert what ArbOS's
+//! globals hold along the way, so that a subsystem can be checked without bespoke Rust.
+//!
+//! Each line of a script is blank, a `#` comment, or one of:
+//!
+//! ```text
+//! after <n>                   delivers the first n messages of the log, then runs until ArbOS
+//!                             blocks waiting for more
+//! assert <path> <op> <int>    compares the int at a path through the globals, as paths are
+//!                             written in the state browser, where op is one of == != < <= > >=
+//! ```
+//!
+//! Paths only lead through the fields and slots of values, so a collection's length is asserted
+//! through whichever field keeps count of it.
+
+use super::emulator::ProfilerData;
+use super::runtime_env::RtEnvRecorder;
+use super::{Machine, MachineState, ProfilerMode};
+use crate::mavm::Value;
+use crate::uint256::Uint256;
+use std::cmp::Ordering;
+use std::io::{self, Write};
+
+/// A step of a scenario, along with the line it's written on.
+#[derive(Debug, PartialEq)]
+enum Step {
+    After(usize),
+    Assert {
+        line: usize,
+        path: String,
+        op: String,
+        expected: Uint256,
+    },
+}
+
+/// A parsed scenario script.
+#[derive(Debug)]
+pub struct Scenario {
+    steps: Vec<Step>,
+}
+
+const OPS: [&str; 6] = ["==", "!=", "<", "<=", ">", ">="];
+
+impl Scenario {
+    /// Parses a script, failing with the first line that isn't a step.
+    pub fn parse(script: &str) -> Result<Self, String> {
+        let mut steps = vec![];
+        for (index, line) in script.lines().enumerate() {
+            let line_error = |why: &str| format!("line {}: {}", index + 1, why);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                [first, ..] if first.starts_with('#') => {}
+                ["after", count] => match count.parse() {
+                    Ok(count) => steps.push(Step::After(count)),
+                    Err(_) => return Err(line_error("after takes a number of messages")),
+                },
+                ["assert", path, op, expected] => {
+                    if !OPS.contains(op) {
+                        return Err(line_error(&format!("unknown comparison {}", op)));
+                    }
+                    let expected = super::browse::parse_int(expected)
+                        .ok_or_else(|| line_error(&format!("{} isn't an int", expected)))?;
+                    steps.push(Step::Assert {
+                        line: index + 1,
+                        path: path.to_string(),
+                        op: op.to_string(),
+                        expected,
+                    });
+                }
+                _ => return Err(line_error("expected after or assert")),
+            }
+        }
+        Ok(Scenario { steps })
+    }
+
+    /// Runs the scenario against the messages of a test log, writing the result of each assertion
+    /// to output. Returns whether every assertion held.
+    pub fn run(
+        &self,
+        recorder: &RtEnvRecorder,
+        machine: &mut Machine,
+        output: &mut dyn Write,
+    ) -> io::Result<bool> {
+        let messages = recorder.messages();
+        machine.runtime_env.insert_full_inbox_contents(vec![]);
+        machine.start_at_zero(false);
+
+        let mut delivered = 0;
+        let mut passed = true;
+        for step in &self.steps {
+            match step {
+                Step::After(count) => {
+                    delivered = deliver(machine, messages, delivered, *count);
+                    machine.run(None);
+                    if let MachineState::Error(error) = &machine.state {
+                        writeln!(output, "ArbOS failed after {} messages: {}", count, error)?;
+                        return Ok(false);
+                    }
+                }
+                Step::Assert {
+                    line,
+                    path,
+                    op,
+                    expected,
+                } => {
+                    let browser = machine.browse_state();
+                    let found = browser.int_at(path);
+                    let held = found.map_or(false, |found| compare(found.cmp(expected), op));
+                    passed &= held;
+                    let status = if held { "ok" } else { "FAILED" };
+                    let found = match found {
+                        Some(found) => found.to_string(),
+                        None => String::from("nothing"),
+                    };
+                    writeln!(
+                        output,
+                        "line {}: {}: {} {} {}, found {}",
+                        line, status, path, op, expected, found
+                    )?;
+                }
+            }
+        }
+        Ok(passed)
+    }
+
+    /// Profiles a run of the scenario, which delivers its messages as usual but leaves its
+    /// assertions unchecked.
+    pub fn profile(
+        &self,
+        recorder: &RtEnvRecorder,
+        machine: &mut Machine,
+        mode: ProfilerMode,
+    ) -> ProfilerData {
+        let messages = recorder.messages();
+        machine.runtime_env.insert_full_inbox_contents(vec![]);
+        machine.start_at_zero(false);
+
+        let mut profile = ProfilerData::default();
+        let mut delivered = 0;
+        for step in &self.steps {
+            if let Step::After(count) = step {
+                delivered = deliver(machine, messages, delivered, *count);
+                machine.profile_more(&mut profile, mode);
+            }
+        }
+        profile
+    }
+}
+
+/// Delivers the messages up to count that haven't been yet, returning how many have been.
+fn deliver(machine: &mut Machine, messages: &[Value], delivered: usize, count: usize) -> usize {
+    for msg in messages.iter().take(count).skip(delivered) {
+        machine.runtime_env.l1_inbox.push(msg.clone());
+    }
+    delivered.max(count.min(messages.len()))
+}
+
+fn compare(ordering: Ordering, op: &str) -> bool {
+    match op {
+        "==" => ordering == Ordering::Equal,
+        "!=" => ordering != Ordering::Equal,
+        "<" => ordering == Ordering::Less,
+        "<=" => ordering != Ordering::Greater,
+        ">" => ordering == Ordering::Greater,
+        _ => ordering != Ordering::Less,
+    }
+}
+
+/// Runs the scenario in script against the test log in filename, on the ArbOS the log was
+/// recorded with. Returns whether every assertion held.
+pub fn run_scenario_file(
+    script: &str,
+    filename: &str,
+    output: &mut dyn Write,
+) -> Result<bool, String> {
+    let text = std::fs::read_to_string(script).map_err(|e| format!("{}: {}", script, e))?;
+    let scenario = Scenario::parse(&text).map_err(|e| format!("{}: {}", script, e))?;
+    let recorder =
+        RtEnvRecorder::from_file(filename).map_err(|e| format!("{}: {}", filename, e))?;
+    let mut machine = recorder.load_machine();
+    scenario
+        .run(&recorder, &mut machine, output)
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_scenario_parse() {
+    let scenario =
+        Scenario::parse("# deliver the init message\nafter 1\n\nassert globalInbox.count >= 0x1\n")
+            .unwrap();
+    assert_eq!(
+        scenario.steps,
+        vec![
+            Step::After(1),
+            Step::Assert {
+                line: 4,
+                path: String::from("globalInbox.count"),
+                op: String::from(">="),
+                expected: Uint256::one(),
+            },
+        ]
+    );
+
+    let error = Scenario::parse("after 1\nassert x ~= 3\n").unwrap_err();
+    assert!(error.starts_with("line 2"));
+    assert!(Scenario::parse("assert x == three").is_err());
+    assert!(Scenario::parse("run 5").is_err());
+}
+
+#[test]
+fn test_scenario_profile() {
+    let recorder = RtEnvRecorder::from_file("replayTests/evm_direct_deploy_add.aoslog").unwrap();
+    assert_eq!(recorder.messages().len(), 2);
+    let profile = |script: &str| {
+        let mut machine = recorder.load_machine();
+        let scenario = Scenario::parse(script).unwrap();
+        let profile = scenario.profile(&recorder, &mut machine, ProfilerMode::Always);
+        assert!(machine.state.is_running());
+        profile.total_gas()
+    };
+
+    // each delivery adds to the same profile
+    let boot = profile("after 1\n");
+    assert!(profile("after 1\nafter 2\n") > boot);
+}
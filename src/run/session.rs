@@ -0,0 +1,65 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides debugger sessions, whose commands can be recorded to a script and replayed from one,
+//! so that a walkthrough can be repeated, or a path checked automatically with `expect` commands.
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, stdin, BufWriter, Write};
+use std::path::Path;
+
+/// Where the debugger reads its commands from.
+#[derive(Debug, Default)]
+pub struct DebugSession {
+    /// The commands of the script left to replay, after which commands are read from stdin
+    script: VecDeque<String>,
+    recording: Option<BufWriter<File>>,
+}
+
+impl DebugSession {
+    /// Queues the commands of a script, one per line, to be run before any typed. Blank lines are
+    /// kept, as they step, while lines starting with `#` are comments.
+    pub fn replay(&mut self, path: &Path) -> io::Result<()> {
+        let script = fs::read_to_string(path)?;
+        self.script.extend(
+            script
+                .lines()
+                .filter(|line| !line.trim_start().starts_with('#'))
+                .map(|line| line.trim().to_string()),
+        );
+        Ok(())
+    }
+
+    /// Writes each command the debugger reads from now on to a script at path, whether it's
+    /// typed or replayed, so that the session can be replayed later.
+    pub fn record(&mut self, path: &Path) -> io::Result<()> {
+        self.recording = Some(BufWriter::new(File::create(path)?));
+        Ok(())
+    }
+
+    /// Reads the next command, without its newline. Replayed commands are echoed, so that a
+    /// replay reads like the session recorded. Returns `None` once the script is done and stdin
+    /// is closed.
+    pub fn next_command(&mut self) -> Option<String> {
+        let command = match self.script.pop_front() {
+            Some(command) => {
+                println!("> {}", command);
+                command
+            }
+            None => {
+                let mut line = String::new();
+                match stdin().read_line(&mut line) {
+                    Ok(0) | Err(_) => return None,
+                    Ok(_) => line.trim().to_string(),
+                }
+            }
+        };
+        if let Some(recording) = &mut self.recording {
+            // a session that can't be recorded can still be debugged
+            drop(writeln!(recording, "{}", command).and_then(|_| recording.flush()));
+        }
+        Some(command)
+    }
+}
@@ -0,0 +1,326 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides sparse tuple trees, for tests whose states are too large to build in full, like those
+//! forking a chain or holding a hundred thousand accounts. A machine can hold one as a global, see
+//! `Machine::back_global`.
+
+use crate::mavm::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// A state kept elsewhere, whose leaves a tree loads as they're first read.
+pub trait SparseSource: Send + Sync {
+    /// The leaf at a path, if the state holds one other than the default.
+    fn load(&self, path: &[usize]) -> Option<Value>;
+
+    /// The paths of every leaf the state holds, which a machine must see even if no test has read
+    /// them yet.
+    fn paths(&self) -> Vec<Vec<usize>>;
+}
+
+#[cfg(test)]
+impl SparseSource for HashMap<Vec<usize>, Value> {
+    fn load(&self, path: &[usize]) -> Option<Value> {
+        self.get(path).cloned()
+    }
+
+    fn paths(&self) -> Vec<Vec<usize>> {
+        self.keys().cloned().collect()
+    }
+}
+
+/// A tree of tuples, each as wide as the others, whose leaves hold a default value unless they've
+/// been set. Only the leaves that have been set are stored, keyed by their paths from the root, so
+/// memory scales with the state that's touched rather than the size of the tree.
+///
+/// A source can populate the tree on demand, supplying the leaves of a state kept elsewhere as
+/// they're first read. Reading the tree out as a value builds just the tuples leading to the
+/// leaves stored or supplied, sharing a single copy of each untouched subtree, and reading it back
+/// in after a machine has run skips those same shared subtrees, which the machine can't have
+/// changed. Leaves the machine wrote back to the default are kept, so the source's stale value
+/// isn't loaded in their place.
+#[derive(Clone)]
+pub struct SparseTuple {
+    width: usize,
+    depth: usize,
+    /// The subtree of each height that holds only default leaves, starting with the leaf itself
+    defaults: Vec<Value>,
+    entries: HashMap<Vec<usize>, Value>,
+    source: Option<Arc<dyn SparseSource>>,
+}
+
+impl fmt::Debug for SparseTuple {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SparseTuple({} wide, {} deep, {} stored)",
+            self.width,
+            self.depth,
+            self.entries.len()
+        )
+    }
+}
+
+impl SparseTuple {
+    /// Creates a tree of tuples of the given width, with depth tuples between the root and each
+    /// leaf, whose leaves all hold the default.
+    #[cfg(test)]
+    pub fn new(width: usize, depth: usize, default: Value) -> Self {
+        let mut defaults = vec![default];
+        for height in 0..depth {
+            let child = defaults[height].clone();
+            defaults.push(Value::new_tuple(vec![child; width]));
+        }
+        SparseTuple {
+            width,
+            depth,
+            defaults,
+            entries: HashMap::new(),
+            source: None,
+        }
+    }
+
+    /// Populates the leaves that haven't been set from the source, as they're read.
+    #[cfg(test)]
+    pub fn with_source(mut self, source: Arc<dyn SparseSource>) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// The number of leaves stored, whether they were set, loaded, or written back to the default.
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no leaves are stored.
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Reads the leaf at a path, loading it first if it hasn't been set.
+    #[cfg(test)]
+    pub fn get(&mut self, path: &[usize]) -> Result<Value, String> {
+        self.check_path(path)?;
+        if let Some(value) = self.entries.get(path) {
+            return Ok(value.clone());
+        }
+        let loaded = self.source.as_ref().and_then(|source| source.load(path));
+        match loaded {
+            Some(value) => {
+                self.entries.insert(path.to_vec(), value.clone());
+                Ok(value)
+            }
+            None => Ok(self.defaults[0].clone()),
+        }
+    }
+
+    /// Sets the leaf at a path.
+    #[cfg(test)]
+    pub fn set(&mut self, path: &[usize], value: Value) -> Result<(), String> {
+        self.check_path(path)?;
+        self.entries.insert(path.to_vec(), value);
+        Ok(())
+    }
+
+    fn check_path(&self, path: &[usize]) -> Result<(), String> {
+        match path.len() == self.depth && path.iter().all(|index| *index < self.width) {
+            true => Ok(()),
+            false => Err(format!(
+                "path {:?} isn't a leaf of a tree {} wide and {} deep",
+                path, self.width, self.depth
+            )),
+        }
+    }
+
+    /// The paths the source supplies leaves for, each checked to be a leaf of the tree.
+    fn source_paths(&self) -> Result<Vec<Vec<usize>>, String> {
+        let paths = match &self.source {
+            Some(source) => source.paths(),
+            None => vec![],
+        };
+        for path in &paths {
+            self.check_path(path)?;
+        }
+        Ok(paths)
+    }
+
+    /// Builds the tree as a value, as a machine would hold it, loading every leaf the source
+    /// supplies that isn't already stored.
+    pub fn build_value(&mut self) -> Result<Value, String> {
+        for path in self.source_paths()? {
+            if !self.entries.contains_key(&path) {
+                if let Some(value) = self.source.as_ref().and_then(|source| source.load(&path)) {
+                    self.entries.insert(path, value);
+                }
+            }
+        }
+        let entries: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(path, value)| (path.as_slice(), value))
+            .collect();
+        Ok(self.build_subtree(self.depth, entries))
+    }
+
+    fn build_subtree(&self, height: usize, entries: Vec<(&[usize], &Value)>) -> Value {
+        if entries.is_empty() {
+            return self.defaults[height].clone();
+        }
+        if height == 0 {
+            return entries[0].1.clone();
+        }
+        let mut children = vec![vec![]; self.width];
+        for (path, value) in entries {
+            children[path[0]].push((&path[1..], value));
+        }
+        let children = children
+            .into_iter()
+            .map(|entries| self.build_subtree(height - 1, entries))
+            .collect();
+        Value::new_tuple(children)
+    }
+
+    /// Replaces the tree's leaves with those of a value built by `build_value`, as a machine might
+    /// have changed it. Leaves holding the default are only stored where the source supplies
+    /// another value, so that it isn't loaded again.
+    #[cfg(test)]
+    pub fn absorb(&mut self, value: &Value) -> Result<(), String> {
+        let mut entries = HashMap::new();
+        self.collect(value, self.depth, &mut vec![], &mut entries)?;
+        for path in self.source_paths()? {
+            entries
+                .entry(path)
+                .or_insert_with(|| self.defaults[0].clone());
+        }
+        self.entries = entries;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn collect(
+        &self,
+        value: &Value,
+        height: usize,
+        path: &mut Vec<usize>,
+        entries: &mut HashMap<Vec<usize>, Value>,
+    ) -> Result<(), String> {
+        let default = &self.defaults[height];
+        match (value, default) {
+            // untouched subtrees are still the shared default, which is never worth walking
+            (Value::Tuple(tup), Value::Tuple(untouched)) if Arc::ptr_eq(tup, untouched) => {}
+            (Value::Tuple(tup), _) if height > 0 && tup.len() == self.width => {
+                for (index, child) in tup.iter().enumerate() {
+                    path.push(index);
+                    self.collect(child, height - 1, path, entries)?;
+                    path.pop();
+                }
+            }
+            _ if height > 0 => {
+                return Err(format!(
+                    "{} at {:?} isn't shaped like a tree {} wide",
+                    value, path, self.width
+                ))
+            }
+            _ if value == default => {}
+            _ => drop(entries.insert(path.clone(), value.clone())),
+        }
+        Ok(())
+    }
+}
+
+/// A global held as a sparse tree, along with the slots leading to it through the globals tuple.
+#[derive(Debug, Clone)]
+pub(crate) struct SparseGlobal {
+    pub path: Vec<usize>,
+    pub tree: SparseTuple,
+    /// Whether the machine has installed its globals, so the register holds the tree
+    pub installed: bool,
+}
+
+impl SparseGlobal {
+    /// Writes the tree into the globals tuple held in register, once the prologue has set it.
+    pub fn install(&mut self, register: &Value) -> Result<Value, String> {
+        let value = self.tree.build_value()?;
+        let register = replace_path(register, &self.path, value)
+            .ok_or_else(|| "register does not hold the globals tuple".to_string())?;
+        self.installed = true;
+        Ok(register)
+    }
+}
+
+/// Replaces the value at a sequence of tuple slots, rebuilding only the tuples along the way.
+fn replace_path(value: &Value, path: &[usize], leaf: Value) -> Option<Value> {
+    match path.split_first() {
+        None => Some(leaf),
+        Some((slot, rest)) => match value {
+            Value::Tuple(tup) => {
+                let child = replace_path(tup.get(*slot)?, rest, leaf)?;
+                let mut children = (**tup).clone();
+                children[*slot] = child;
+                Some(Value::new_tuple(children))
+            }
+            _ => None,
+        },
+    }
+}
+
+#[test]
+fn test_sparse_tuple() {
+    // a tree this size would hold over 16 million leaves if it were built in full
+    let mut state = HashMap::new();
+    state.insert(vec![7, 0, 0, 0, 0, 0, 0, 5], Value::from(5));
+    state.insert(vec![7, 7, 7, 7, 7, 7, 7, 7], Value::from(77));
+    let mut tree = SparseTuple::new(8, 8, Value::from(0)).with_source(Arc::new(state));
+    assert!(tree.is_empty());
+    tree.set(&[1, 2, 3, 4, 5, 6, 7, 0], Value::from(10))
+        .unwrap();
+    tree.set(&[1, 2, 3, 4, 5, 6, 7, 1], Value::from(11))
+        .unwrap();
+    assert_eq!(tree.get(&[1, 2, 3, 4, 5, 6, 7, 1]), Ok(Value::from(11)));
+    assert_eq!(tree.get(&[0, 0, 0, 0, 0, 0, 0, 0]), Ok(Value::from(0)));
+    assert_eq!(tree.len(), 2);
+    assert_eq!(tree.get(&[7, 0, 0, 0, 0, 0, 0, 5]), Ok(Value::from(5)));
+    assert_eq!(tree.len(), 3);
+    assert!(tree.get(&[8, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    assert!(tree.set(&[0, 0], Value::from(1)).is_err());
+
+    // the value holds every leaf the source supplies, including those not yet read
+    let leaf =
+        |value: &Value, path: &[usize]| crate::run::validate::tuple_path(value, path).cloned();
+    let value = tree.build_value().unwrap();
+    assert_eq!(
+        leaf(&value, &[1, 2, 3, 4, 5, 6, 7, 0]),
+        Some(Value::from(10))
+    );
+    assert_eq!(
+        leaf(&value, &[7, 0, 0, 0, 0, 0, 0, 5]),
+        Some(Value::from(5))
+    );
+    assert_eq!(
+        leaf(&value, &[7, 7, 7, 7, 7, 7, 7, 7]),
+        Some(Value::from(77))
+    );
+    assert_eq!(
+        leaf(&value, &[6, 6, 6, 6, 6, 6, 6, 6]),
+        Some(Value::from(0))
+    );
+
+    // change the value as a machine would, writing a supplied leaf back to the default
+    let value = replace_path(&value, &[1, 2, 3, 4, 5, 6, 7, 0], Value::from(0)).unwrap();
+    let value = replace_path(&value, &[3, 3, 3, 3, 3, 3, 3, 3], Value::from(33)).unwrap();
+    let value = replace_path(&value, &[7, 0, 0, 0, 0, 0, 0, 5], Value::from(0)).unwrap();
+    tree.absorb(&value).unwrap();
+    assert_eq!(tree.len(), 4);
+    assert_eq!(tree.get(&[1, 2, 3, 4, 5, 6, 7, 0]), Ok(Value::from(0)));
+    assert_eq!(tree.get(&[3, 3, 3, 3, 3, 3, 3, 3]), Ok(Value::from(33)));
+    assert_eq!(tree.get(&[7, 0, 0, 0, 0, 0, 0, 5]), Ok(Value::from(0)));
+    assert_eq!(tree.build_value(), Ok(value));
+
+    let malformed = replace_path(&tree.build_value().unwrap(), &[2, 2], Value::from(2)).unwrap();
+    assert!(tree.absorb(&malformed).is_err());
+}
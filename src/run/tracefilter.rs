@@ -0,0 +1,147 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides a filter that narrows a PC trace down to the txs touching a few addresses, so that
+//! one contract can be followed through a replay whose full trace would be far too large.
+//!
+//! ArbOS reports the calls and creations of each tx in the trace info it prints as the tx ends.
+//! The filter holds back the trace of each tx until then, writing it out only if one of those
+//! calls was made by or to an address filtered for, or one of those creations made or created
+//! one. A call's storage belongs to its callee, or to its caller if it's a delegate call, so a
+//! tx touching an address's storage is caught too.
+
+use crate::mavm::Value;
+use crate::uint256::Uint256;
+use std::collections::BTreeSet;
+
+/// The code ArbOS tags its trace info with when it prints it.
+pub const TRACE_INFO_CODE: u64 = 20000;
+
+const TRACE_EVENT_CALL: u64 = 0;
+const TRACE_EVENT_CREATE: u64 = 2;
+const TRACE_EVENT_CREATE2: u64 = 3;
+
+/// Keeps only the parts of a trace that belong to txs touching a set of addresses.
+#[derive(Clone, Debug, Default)]
+pub struct TraceFilter {
+    addresses: BTreeSet<Uint256>,
+    /// The trace of the tx being run, held until its trace info shows whether it's kept
+    pending: String,
+}
+
+impl TraceFilter {
+    pub fn new(addresses: BTreeSet<Uint256>) -> Self {
+        TraceFilter {
+            addresses,
+            pending: String::new(),
+        }
+    }
+
+    /// Holds a line of trace until the tx it belongs to ends.
+    pub(super) fn hold(&mut self, line: &str) {
+        self.pending.push_str(line);
+    }
+
+    /// Ends the tx being run if printed is the trace info of one, returning its trace if it
+    /// touched any of the addresses. Everything traced since the last tx ended belongs to this
+    /// one, so traces between txs are only kept along with the tx after them.
+    pub(super) fn end_tx(&mut self, printed: &Value) -> Option<String> {
+        let touched = trace_info(printed).map(touched_addresses)?;
+        let trace = std::mem::take(&mut self.pending);
+        match touched
+            .iter()
+            .any(|address| self.addresses.contains(address))
+        {
+            true => Some(trace),
+            false => None,
+        }
+    }
+}
+
+/// The trace info in what a program printed, if it's ArbOS's trace info.
+fn trace_info(printed: &Value) -> Option<&Value> {
+    match printed {
+        Value::Tuple(tup) if tup.len() == 2 && tup[0] == Value::from(TRACE_INFO_CODE) => {
+            Some(&tup[1])
+        }
+        _ => None,
+    }
+}
+
+/// The addresses that made calls and creations in trace info, or were called or created. Trace
+/// info is a list of events, each a typecode and a tuple of fields.
+pub fn touched_addresses(trace_info: &Value) -> BTreeSet<Uint256> {
+    let mut touched = BTreeSet::new();
+    let mut list = trace_info;
+    while let Value::Tuple(cell) = list {
+        if cell.len() != 2 {
+            break;
+        }
+        if let Value::Tuple(event) = &cell[0] {
+            if let (Some(Value::Int(typecode)), Some(Value::Tuple(fields))) =
+                (event.get(0), event.get(1))
+            {
+                let indices: &[usize] = match typecode.to_u64() {
+                    Some(TRACE_EVENT_CALL) => &[3, 4],
+                    Some(TRACE_EVENT_CREATE) => &[1],
+                    Some(TRACE_EVENT_CREATE2) => &[1, 3],
+                    _ => &[],
+                };
+                for index in indices {
+                    if let Some(Value::Int(address)) = fields.get(*index) {
+                        touched.insert(address.clone());
+                    }
+                }
+            }
+        }
+        list = &cell[1];
+    }
+    touched
+}
+
+#[test]
+fn test_trace_filter() {
+    let address = |n: u64| Value::from(n);
+    let event = |typecode: u64, fields: Vec<Value>| {
+        Value::new_tuple(vec![Value::from(typecode), Value::new_tuple(fields)])
+    };
+    let list = |events: Vec<Value>| {
+        events.into_iter().rev().fold(Value::none(), |rest, event| {
+            Value::new_tuple(vec![event, rest])
+        })
+    };
+    let printed = |info: Value| Value::new_tuple(vec![Value::from(TRACE_INFO_CODE), info]);
+
+    let zero = || Value::from(0);
+    let call =
+        |from: u64, to: u64| event(0, vec![zero(), zero(), zero(), address(from), address(to)]);
+
+    // a call from 1 to 2 that creates 3, and a call from 4 to 5 that create2s 6 from 5
+    let first = list(vec![
+        call(1, 2),
+        event(2, vec![zero(), address(3)]),
+        event(1, vec![zero(), zero(), zero()]),
+        event(1, vec![zero(), zero(), zero()]),
+    ]);
+    let second = list(vec![
+        call(4, 5),
+        event(3, vec![zero(), address(5), zero(), address(6)]),
+    ]);
+    let expected: BTreeSet<_> = (1..=3).map(Uint256::from_u64).collect();
+    assert_eq!(touched_addresses(&first), expected);
+
+    let mut filter = TraceFilter::new(vec![Uint256::from_u64(3)].into_iter().collect());
+    filter.hold("0 1 2\n");
+    assert_eq!(filter.end_tx(&Value::from(7)), None);
+    filter.hold("0 3 4\n");
+    assert_eq!(
+        filter.end_tx(&printed(first)),
+        Some(String::from("0 1 2\n0 3 4\n"))
+    );
+    filter.hold("0 5 6\n");
+    assert_eq!(filter.end_tx(&printed(second)), None);
+    filter.hold("0 7 8\n");
+    assert_eq!(filter.end_tx(&printed(Value::none())), None);
+    assert_eq!(filter.pending, "");
+}
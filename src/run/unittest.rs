@@ -0,0 +1,99 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Runs the unit tests of a mini program, the funcs marked `#[test]`, each on a machine of its own
+//! so that no test sees the globals another has set.
+
+use super::{ExecutionError, Machine, MachineState, RuntimeEnvironment};
+use crate::compile::UnitTest;
+use crate::console::Color;
+use crate::mavm::CodePt;
+use crate::pos::Location;
+use std::io::{self, Write};
+
+/// Runs each test whose name contains the filter, writing whether it passed to output along with
+/// where it failed for those that didn't. Returns whether every test run passed.
+pub fn run_unit_tests(
+    tests: Vec<UnitTest>,
+    filter: Option<&str>,
+    output: &mut dyn Write,
+) -> io::Result<bool> {
+    let tests: Vec<_> = tests
+        .into_iter()
+        .filter(|test| filter.map_or(true, |filter| test.name.contains(filter)))
+        .collect();
+    writeln!(output, "running {} tests", tests.len())?;
+
+    let mut failed = vec![];
+    for test in tests {
+        let locations: Vec<Option<Location>> = test
+            .program
+            .code
+            .iter()
+            .map(|insn| insn.debug_info.location)
+            .collect();
+        let file_info_chart = test.program.file_info_chart.clone();
+
+        // main's entry ABI is given to the test, so it's called just as main would be
+        let mut machine = Machine::new(test.program, RuntimeEnvironment::new(None));
+        let stop_pc = machine.call_state(CodePt::new_internal(1), vec![]);
+        machine.run(Some(stop_pc));
+
+        let failure = match &machine.state {
+            MachineState::Running(_) => None,
+            MachineState::Stopped => Some(String::from("the machine stopped")),
+            MachineState::Error(error) => {
+                let location = match error {
+                    ExecutionError::RunningErr(_, CodePt::Internal(pc), _) => {
+                        locations.get(*pc).copied().flatten()
+                    }
+                    _ => None,
+                };
+                Some(match location {
+                    Some(location) => format!(
+                        "{} at {}",
+                        error,
+                        location.display_with_file(&file_info_chart, false)
+                    ),
+                    None => error.to_string(),
+                })
+            }
+        };
+
+        match failure {
+            None => writeln!(output, "test {} ... {}", test.name, Color::mint("ok"))?,
+            Some(why) => {
+                writeln!(output, "test {} ... {}", test.name, Color::red("FAILED"))?;
+                writeln!(output, "    {}", why.trim_end())?;
+                if let Some(location) = test.location {
+                    writeln!(
+                        output,
+                        "    declared at {}",
+                        location.display_with_file(&file_info_chart, false)
+                    )?;
+                }
+                failed.push(test.name);
+            }
+        }
+    }
+
+    writeln!(output)?;
+    if !failed.is_empty() {
+        writeln!(output, "failures:")?;
+        for name in &failed {
+            writeln!(output, "    {}", name)?;
+        }
+        writeln!(output)?;
+    }
+    writeln!(
+        output,
+        "test result: {}. {} failed",
+        match failed.is_empty() {
+            true => Color::mint("ok"),
+            false => Color::red("FAILED"),
+        },
+        failed.len(),
+    )?;
+    Ok(failed.is_empty())
+}
@@ -0,0 +1,218 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides runtime checks that values held in globals conform to their declared types.
+
+use crate::compile::{GlobalVar, Type, TypeTree};
+use crate::link::TupleTree;
+use crate::mavm::Value;
+
+/// Checks the globals tuple held in the register against the types recorded for each global.
+///
+/// Only the shape of values is checked, so this catches unchecked casts that produce a value of the
+/// wrong structure, but not those that produce a well formed value of a different type.
+#[derive(Debug, Clone)]
+pub struct GlobalValidator {
+    globals: Vec<GlobalVar>,
+    type_tree: TypeTree,
+    shape: TupleTree,
+    last_checked: Vec<Value>,
+}
+
+impl GlobalValidator {
+    pub fn new(globals: Vec<GlobalVar>, type_tree: TypeTree) -> Self {
+        let shape = TupleTree::new(globals.len(), false);
+        GlobalValidator {
+            globals,
+            type_tree,
+            shape,
+            last_checked: vec![],
+        }
+    }
+
+    /// Validates every global in register whose value has changed since the last call, returning
+    /// a description of the first mismatch found.
+    pub fn check(&mut self, register: &Value) -> Result<(), String> {
+        let mut values = vec![];
+        for index in 0..self.globals.len() {
+            let path = self.shape.slot_path(index).unwrap_or_default();
+            match tuple_path(register, &path) {
+                Some(value) => values.push(value.clone()),
+                None => {
+                    return Err(format!(
+                        "register does not hold a globals tuple with {} slots",
+                        self.globals.len()
+                    ))
+                }
+            }
+        }
+
+        for (index, (global, value)) in self.globals.iter().zip(values.iter()).enumerate() {
+            if global.id == usize::MAX || self.last_checked.get(index) == Some(value) {
+                continue;
+            }
+            check_shape(value, &global.tipe, &self.type_tree, &global.name)?;
+        }
+        self.last_checked = values;
+        Ok(())
+    }
+}
+
+/// Follows a sequence of tuple slots into value.
+pub(crate) fn tuple_path<'a>(value: &'a Value, path: &[usize]) -> Option<&'a Value> {
+    let mut value = value;
+    for slot in path {
+        value = match value {
+            Value::Tuple(tup) => tup.get(*slot)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Int(_) => "an int".to_string(),
+        Value::Tuple(tup) => format!("a tuple of size {}", tup.len()),
+        Value::CodePoint(_) => "a codepoint".to_string(),
+        Value::Label(_) => "a label".to_string(),
+        Value::Buffer(_) => "a buffer".to_string(),
+    }
+}
+
+/// Checks that value has the structure of a value of type tipe, where name describes where value
+/// was found for error reporting.
+pub fn check_shape(
+    value: &Value,
+    tipe: &Type,
+    type_tree: &TypeTree,
+    name: &str,
+) -> Result<(), String> {
+    let tipe = tipe
+        .rep(type_tree)
+        .map_err(|err| format!("could not resolve type of {}: {}", name, err.description))?;
+
+    let mismatch = || {
+        Err(format!(
+            "{} should be {} but is {}",
+            name,
+            tipe.display(),
+            describe(value)
+        ))
+    };
+
+    // checks that value is the wide-tuple encoding of the given fields
+    let check_fields = |fields: Vec<(String, &Type)>| {
+        if fields.len() == 1 {
+            // single field values are unwrapped when written, but may start out wrapped
+            return match value {
+                Value::Tuple(tup) if tup.len() == 1 => {
+                    check_shape(&tup[0], fields[0].1, type_tree, &fields[0].0)
+                        .or_else(|_| check_shape(value, fields[0].1, type_tree, &fields[0].0))
+                }
+                _ => check_shape(value, fields[0].1, type_tree, &fields[0].0),
+            };
+        }
+        let shape = TupleTree::new(fields.len(), false);
+        for (index, (field_name, field_type)) in fields.iter().enumerate() {
+            let path = shape.slot_path(index).unwrap_or_default();
+            match tuple_path(value, &path) {
+                Some(field) => check_shape(field, field_type, type_tree, field_name)?,
+                None => return mismatch(),
+            }
+        }
+        Ok(())
+    };
+
+    match tipe {
+        Type::Any | Type::Every | Type::Union(_) | Type::GenericSlot(_) | Type::Generic(_) => {
+            Ok(())
+        }
+        Type::Uint | Type::Int | Type::Bool | Type::Bytes32 | Type::EthAddress => match value {
+            Value::Int(_) => Ok(()),
+            _ => mismatch(),
+        },
+        Type::Buffer => match value {
+            Value::Buffer(_) => Ok(()),
+            _ => mismatch(),
+        },
+        Type::String => check_shape(value, &Type::Tuple(Type::string_fields()), type_tree, name),
+        Type::Func(..) => match value {
+            Value::CodePoint(_) | Value::Tuple(_) => Ok(()),
+            _ => mismatch(),
+        },
+        Type::FixedArray(ref inner, size) => {
+            // newfixedarray nests tuples of 8, and each level is indexed by the next base 8 digit
+            // of an index, least significant first
+            let mut depth = 1;
+            let mut capacity = 8;
+            while capacity < size {
+                depth += 1;
+                capacity *= 8;
+            }
+            for index in 0..size {
+                let path: Vec<usize> = (0..depth)
+                    .map(|level| match level + 1 == depth {
+                        true => index >> (3 * level),
+                        false => (index >> (3 * level)) % 8,
+                    })
+                    .collect();
+                match tuple_path(value, &path) {
+                    Some(elem) => {
+                        check_shape(elem, inner, type_tree, &format!("{}[{}]", name, index))?
+                    }
+                    None => return mismatch(),
+                }
+            }
+            Ok(())
+        }
+        Type::Array(_) | Type::Map(..) => match value {
+            Value::Tuple(_) => Ok(()),
+            _ => mismatch(),
+        },
+        Type::Option(ref inner) => match value {
+            Value::Tuple(tup) => match (tup.get(0), tup.len()) {
+                (Some(Value::Int(tag)), 1) if tag.is_zero() => Ok(()),
+                (Some(Value::Int(_)), 2) => {
+                    check_shape(&tup[1], inner, type_tree, &format!("{}.some", name))
+                }
+                _ => mismatch(),
+            },
+            _ => mismatch(),
+        },
+        Type::Tuple(ref types) => check_fields(
+            types
+                .iter()
+                .enumerate()
+                .map(|(index, field)| (format!("{}.{}", name, index), field))
+                .collect(),
+        ),
+        Type::Struct(ref fields) => check_fields(
+            fields
+                .iter()
+                .map(|field| (format!("{}.{}", name, field.name), &field.tipe))
+                .collect(),
+        ),
+        Type::Enum(ref variants) => match value {
+            Value::Tuple(tup) if tup.len() == 2 => match &tup[0] {
+                Value::Int(tag) => match tag.to_usize().and_then(|tag| variants.get(tag)) {
+                    Some(variant) => check_shape(
+                        &tup[1],
+                        &Type::Tuple(variant.fields.clone()),
+                        type_tree,
+                        &format!("{}.{}", name, variant.name),
+                    ),
+                    None => mismatch(),
+                },
+                _ => mismatch(),
+            },
+            _ => mismatch(),
+        },
+        Type::Void => match value {
+            Value::Tuple(tup) if tup.is_empty() => Ok(()),
+            _ => mismatch(),
+        },
+        Type::Nominal(..) => mismatch(),
+    }
+}
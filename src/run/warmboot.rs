@@ -0,0 +1,191 @@
+/*
+ * Copyright 2021, Offchain Labs, Inc. All rights reserved.
+ */
+
+//! Provides an on-disk cache of machines as they stand once they've booted, so that a program
+//! booted on the same inbox as before picks up where that boot left off instead of redoing it.
+//!
+//! Booting runs a machine from zero until it waits on its inbox, which for ArbOS means reading
+//! the chain's init message and setting up every subsystem. What the machine ends up holding
+//! depends only on its program and the messages it read, so those, along with the emulator that
+//! ran it, are what a boot is cached under.
+
+use crate::compile::CACHE_DIR;
+use crate::mavm::{AVMOpcode, CodePt, Instruction, StableHasher, Value};
+use crate::run::Machine;
+use crate::uint256::Uint256;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Changes whenever the layout of cache entries does, so that older entries are ignored.
+const CACHE_FORMAT: u64 = 2;
+
+/// What booting changed about a machine and its environment.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BootSnapshot {
+    pub(super) stack: Vec<Value>,
+    pub(super) aux_stack: Vec<Value>,
+    pub(super) register: Value,
+    /// Where the machine waits on its inbox
+    pub(super) pc: CodePt,
+    /// The code segments made while booting, after the runtime
+    pub(super) segments: Vec<Vec<Instruction<AVMOpcode>>>,
+    pub(super) arb_gas_remaining: Uint256,
+    pub(super) total_gas_usage: Uint256,
+    /// How many messages were read from the inbox
+    pub(super) consumed: usize,
+    pub(super) logs: Vec<Value>,
+    pub(super) sends: Vec<Vec<u8>>,
+}
+
+impl BootSnapshot {
+    /// Reads a snapshot written by the cache, so that a machine can be restored from it as a
+    /// checkpoint.
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        let bytes = fs::read(path)?;
+        bincode::deserialize(&bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes the snapshot where `read` can read it back.
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let bytes = bincode::serialize(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        fs::write(path, bytes)
+    }
+}
+
+/// The boots of earlier runs of programs in a folder.
+pub struct BootCache {
+    dir: PathBuf,
+    /// Covers the emulator, whose gas costs and opcodes a boot depends on
+    salt: u64,
+}
+
+impl BootCache {
+    /// Opens the cache kept in folder. Since a cached boot is only valid for the emulator that
+    /// produced it, there's no cache if the running one can't be identified.
+    pub fn new(folder: &Path) -> Option<Self> {
+        let exe = std::env::current_exe().ok()?;
+        let meta = fs::metadata(&exe).ok()?;
+
+        let mut hasher = StableHasher::default();
+        CACHE_FORMAT.hash(&mut hasher);
+        exe.hash(&mut hasher);
+        meta.len().hash(&mut hasher);
+        meta.modified().ok()?.hash(&mut hasher);
+
+        Some(BootCache {
+            dir: folder.join(CACHE_DIR),
+            salt: hasher.finish(),
+        })
+    }
+
+    /// Gets the key a boot of the serialized program mexe on inbox is cached under.
+    fn key(&self, mexe: &[u8], inbox: &[Value]) -> Option<u64> {
+        let mut hasher = StableHasher::default();
+        self.salt.hash(&mut hasher);
+        mexe.hash(&mut hasher);
+        bincode::serialize(inbox).ok()?.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+
+    fn load(&self, key: u64) -> Option<BootSnapshot> {
+        let bytes = fs::read(self.entry_path(key)).ok()?;
+        bincode::deserialize(&bytes).ok()
+    }
+
+    /// Caches a boot under key. Failing to is harmless, since the program will just be booted
+    /// again next time.
+    fn store(&self, key: u64, snapshot: &BootSnapshot) {
+        let bytes = match bincode::serialize(snapshot) {
+            Ok(bytes) => bytes,
+            Err(_) => return,
+        };
+        // written elsewhere first so that readers never see part of an entry
+        let temp = self
+            .dir
+            .join(format!("{:016x}.{}.boot.tmp", key, std::process::id()));
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&temp, bytes))
+            .and_then(|_| fs::rename(&temp, self.entry_path(key)));
+        if written.is_err() {
+            drop(fs::remove_file(&temp));
+        }
+    }
+
+    fn entry_path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.boot", key))
+    }
+
+    /// Boots machine, freshly loaded from the serialized program mexe, by restoring an earlier
+    /// boot on the same inbox if there's one cached, and by running it and caching the result
+    /// otherwise. Returns whether the boot was restored.
+    ///
+    /// Boots that end with the machine stopped or in error aren't cached, so running one again
+    /// fails the same way it did the first time.
+    pub fn boot(&self, machine: &mut Machine, mexe: &[u8]) -> bool {
+        machine.start_at_zero(false);
+        let inbox: Vec<Value> = machine.runtime_env.l1_inbox.iter().cloned().collect();
+        let key = self.key(mexe, &inbox);
+        if let Some(snapshot) = key.and_then(|key| self.load(key)) {
+            machine.restore_boot(snapshot);
+            return true;
+        }
+        let consumed = machine.runtime_env.l1_inbox.consumed();
+        let _ = machine.run(None);
+        if let (Some(key), true) = (key, machine.state.is_running()) {
+            let consumed = machine.runtime_env.l1_inbox.consumed() - consumed;
+            self.store(key, &machine.boot_snapshot(consumed));
+        }
+        false
+    }
+}
+
+#[test]
+fn test_warm_boot_matches_cold_boot() {
+    use crate::run::load_from_file;
+
+    let dir = std::env::temp_dir().join(format!("mini-warm-boot-{}", std::process::id()));
+    let cache = BootCache::new(&dir).unwrap();
+    let mexe = fs::read("arb_os/arbos.mexe").unwrap();
+
+    let mut cold = load_from_file(Path::new("arb_os/arbos.mexe"));
+    assert!(!cache.boot(&mut cold, &mexe));
+    let mut warm = load_from_file(Path::new("arb_os/arbos.mexe"));
+    assert!(cache.boot(&mut warm, &mexe));
+
+    assert_eq!(warm.register, cold.register);
+    assert_eq!(warm.get_total_gas_usage(), cold.get_total_gas_usage());
+    assert_eq!(
+        warm.runtime_env.get_all_raw_logs(),
+        cold.runtime_env.get_all_raw_logs()
+    );
+    assert!(warm.runtime_env.l1_inbox.is_empty());
+
+    // both carry on the same from there
+    for machine in [&mut cold, &mut warm] {
+        let payee = Uint256::from_u64(1025);
+        machine.runtime_env.insert_eth_deposit_message(
+            payee.clone(),
+            payee,
+            Uint256::from_u64(1000),
+            false,
+        );
+        let _ = machine.run(None);
+    }
+    assert_eq!(warm.register, cold.register);
+    assert_eq!(
+        warm.runtime_env.get_all_receipt_logs().len(),
+        cold.runtime_env.get_all_receipt_logs().len()
+    );
+
+    // a different inbox boots afresh
+    let mut other = load_from_file(Path::new("arb_os/arbos.mexe"));
+    other.runtime_env.insert_l2_message(Uint256::zero(), &[6u8]);
+    assert!(!cache.boot(&mut other, &mexe));
+
+    drop(fs::remove_dir_all(&dir));
+}
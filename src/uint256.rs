@@ -26,6 +26,12 @@ impl Uint256 {
         }
     }
 
+    pub fn from_u128(x: u128) -> Self {
+        Uint256 {
+            val: BigUint::from(x),
+        }
+    }
+
     pub fn from_usize(x: usize) -> Self {
         Uint256 {
             val: BigUint::from(x),
@@ -199,8 +205,8 @@ impl Uint256 {
         }
     }
 
-    pub fn _max_neg_int() -> Self {
-        Uint256::one().exp(&Uint256::from_usize(255))
+    pub fn max_neg_int() -> Self {
+        Uint256::one().shift_left(255)
     }
 
     pub fn is_zero(&self) -> bool {
@@ -41,20 +41,20 @@ impl CodeUploader {
             Ok(_) => s,
         };
 
-        let parse_result: Result<LinkedProgram, serde_json::Error> = serde_json::from_str(&s);
-        match parse_result {
-            Ok(prog) => {
-                let code_len = prog.code.len();
-                let mut ret = CodeUploader::_new(code_len);
-                for i in 0..prog.code.len() {
-                    ret._serialize_one(&prog.code[code_len - 1 - i]);
-                }
-                ret
-            }
-            Err(_) => {
-                panic!();
-            }
+        match LinkedProgram::from_json(&s) {
+            Ok(prog) => CodeUploader::_new_from_program(&prog),
+            Err(e) => panic!("couldn't load {}: {}", path.display(), e),
+        }
+    }
+
+    /// Serializes the code of a program, last instruction first, as the code builder expects.
+    pub fn _new_from_program(prog: &LinkedProgram) -> Self {
+        let code_len = prog.code.len();
+        let mut ret = CodeUploader::_new(code_len);
+        for i in 0..prog.code.len() {
+            ret._serialize_one(&prog.code[code_len - 1 - i]);
         }
+        ret
     }
 
     pub fn push_byte(&mut self, b: u8) {